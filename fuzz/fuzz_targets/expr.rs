@@ -0,0 +1,21 @@
+//! Parsing of arbitrary expressions: the parser must not panic, and what it parses must read
+//! back the same from its translation to the KS syntax.
+//!
+//! Run with `cargo fuzz run expr` from the root of the repository.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use kaitai_struct_testgen::ast::parser::parse_expr;
+use kaitai_struct_testgen::translator::translate;
+
+fuzz_target!(|text: &str| {
+    if let Ok(expr) = parse_expr(text) {
+        let translated = translate(&expr);
+        match parse_expr(&translated) {
+            Ok(reparsed) => assert_eq!(reparsed, expr, "`{}` translated to `{}`", text, translated),
+            Err(error) => panic!("`{}` translated to `{}`, which fails: {}", text, translated, error),
+        }
+    }
+});
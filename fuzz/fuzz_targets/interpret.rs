@@ -0,0 +1,19 @@
+//! Arbitrary data read with a generated spec: the interpreter must fail with an error, not a
+//! panic, whatever the bytes.
+//!
+//! Run with `cargo fuzz run interpret` from the root of the repository.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use kaitai_struct_testgen::datagen::interpret::read;
+use kaitai_struct_testgen::gen::profile::GenProfile;
+use kaitai_struct_testgen::gen::suite::generate_case;
+
+fuzz_target!(|input: (u64, Vec<u8>)| {
+    let (seed, data) = input;
+    if let Some(case) = generate_case(seed, &GenProfile::default()) {
+        let _ = read(&case.spec, &data);
+    }
+});
@@ -0,0 +1,37 @@
+//! End-to-end generation from a random seed: the case must be the same when generated again and
+//! internally consistent (see `gen::check`), and writing it out as a suite and as native tests
+//! must not panic.
+//!
+//! Run with `cargo fuzz run pipeline` from the root of the repository.
+
+#![no_main]
+
+use std::sync::OnceLock;
+
+use libfuzzer_sys::fuzz_target;
+
+use kaitai_struct_testgen::gen::check::check_case;
+use kaitai_struct_testgen::gen::profile::GenProfile;
+use kaitai_struct_testgen::gen::suite::generate_case;
+use kaitai_struct_testgen::harness::{case_tests, emitter};
+use kaitai_struct_testgen::layout::case_files;
+use kaitai_struct_testgen::schema::Schema;
+use kaitai_struct_testgen::target::Target;
+
+static SCHEMA: OnceLock<Schema> = OnceLock::new();
+
+fuzz_target!(|seed: u64| {
+    let profile = GenProfile::default();
+    let Some(case) = generate_case(seed, &profile) else {
+        return;
+    };
+    assert_eq!(generate_case(seed, &profile).as_ref(), Some(&case));
+    let schema = SCHEMA.get_or_init(Schema::bundled);
+    if let Err(error) = check_case(&case, &profile, schema) {
+        panic!("case `{}` is inconsistent: {}", case.id, error);
+    }
+    let _ = case_files(&case);
+    for target in Target::ALL {
+        let _ = case_tests(&case, emitter(target).as_ref());
+    }
+});
@@ -1,5 +1,8 @@
+use std::fmt;
+
 use utils::PositiveFiniteF64;
 
+pub mod parser;
 pub mod utils;
 
 #[derive(Clone, Debug, Eq, PartialEq, Hash)]
@@ -43,10 +46,130 @@ pub enum Expr {
         value: Box<Expr>,
         idx: Box<Expr>,
     },
+
+    /// `value.as<type_name>`
+    CastTo {
+        value: Box<Expr>,
+        type_name: TypeName,
+    },
+    /// `sizeof<type_name>`, or `bitsizeof<type_name>` if `bits` is set
+    SizeOf {
+        type_name: TypeName,
+        bits: bool,
+    },
+}
+
+/// Reference to a type in casts and `sizeof`, e.g. `u4`, `header::entry` or `str[]`.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct TypeName {
+    pub path: Vec<String>,
+    pub is_array: bool,
+}
+
+impl TypeName {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            path: vec![name.into()],
+            is_array: false,
+        }
+    }
+
+    /// Name of a single-component non-array type, e.g. of a built-in type like `u4` or `str`.
+    pub fn simple_name(&self) -> Option<&str> {
+        match self.path.as_slice() {
+            [name] if !self.is_array => Some(name),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for TypeName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.path.join("::"))?;
+        if self.is_array {
+            f.write_str("[]")?;
+        }
+        Ok(())
+    }
+}
+
+impl Expr {
+    /// Direct subexpressions of this node, in the order in which they appear in the source.
+    pub fn children(&self) -> Vec<&Expr> {
+        match self {
+            Expr::Int(_)
+            | Expr::Float(_)
+            | Expr::Str(_)
+            | Expr::Bool(_)
+            | Expr::EnumMember { .. }
+            | Expr::Name(_)
+            | Expr::SizeOf { .. } => vec![],
+            Expr::List(items) => items.iter().collect(),
+            Expr::Attribute { value, .. } => vec![value],
+            Expr::MethodCall { value, args, .. } => {
+                let mut children = vec![value.as_ref()];
+                children.extend(args);
+                children
+            }
+            Expr::UnaryOp { value, .. } => vec![value],
+            Expr::BinaryOp { l, r, .. } => vec![l, r],
+            Expr::CondOp {
+                cond,
+                if_true,
+                if_false,
+            } => vec![cond, if_true, if_false],
+            Expr::Subscript { value, idx } => vec![value, idx],
+            Expr::CastTo { value, .. } => vec![value],
+        }
+    }
+
+    /// Descendant of this node reached by following the given indices into [`Expr::children`].
+    pub fn node_at(&self, path: &[usize]) -> Option<&Expr> {
+        match path.split_first() {
+            None => Some(self),
+            Some((&idx, rest)) => self.children().get(idx)?.node_at(rest),
+        }
+    }
+
+    /// Same as [`Expr::children`], for modifying the children in place.
+    pub fn children_mut(&mut self) -> Vec<&mut Expr> {
+        match self {
+            Expr::Int(_)
+            | Expr::Float(_)
+            | Expr::Str(_)
+            | Expr::Bool(_)
+            | Expr::EnumMember { .. }
+            | Expr::Name(_)
+            | Expr::SizeOf { .. } => vec![],
+            Expr::List(items) => items.iter_mut().collect(),
+            Expr::Attribute { value, .. } => vec![value],
+            Expr::MethodCall { value, args, .. } => {
+                let mut children = vec![value.as_mut()];
+                children.extend(args);
+                children
+            }
+            Expr::UnaryOp { value, .. } => vec![value],
+            Expr::BinaryOp { l, r, .. } => vec![l, r],
+            Expr::CondOp {
+                cond,
+                if_true,
+                if_false,
+            } => vec![cond, if_true, if_false],
+            Expr::Subscript { value, idx } => vec![value, idx],
+            Expr::CastTo { value, .. } => vec![value],
+        }
+    }
+
+    pub fn node_at_mut(&mut self, path: &[usize]) -> Option<&mut Expr> {
+        match path.split_first() {
+            None => Some(self),
+            Some((&idx, rest)) => self.children_mut().into_iter().nth(idx)?.node_at_mut(rest),
+        }
+    }
 }
 
 /// https://github.com/Mingun/ksc-rs/blob/7e6a82f/src/parser/expressions.rs#L274-L281
-#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
 pub enum UnaryOp {
     /// `-`: Negation
     Neg,
@@ -57,7 +180,7 @@ pub enum UnaryOp {
 }
 
 /// https://github.com/Mingun/ksc-rs/blob/7e6a82f/src/parser/expressions.rs#L285-L326
-#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
 pub enum BinaryOp {
     /// `+`: Addition or concatenation
     Add,
@@ -100,3 +223,40 @@ pub enum BinaryOp {
     /// `>>`: Bitwise right shift
     Shr,
 }
+
+impl UnaryOp {
+    /// Operator as written in the KS expression language.
+    pub fn symbol(self) -> &'static str {
+        match self {
+            UnaryOp::Neg => "-",
+            UnaryOp::Not => "not",
+            UnaryOp::Inv => "~",
+        }
+    }
+}
+
+impl BinaryOp {
+    /// Operator as written in the KS expression language.
+    pub fn symbol(self) -> &'static str {
+        match self {
+            BinaryOp::Add => "+",
+            BinaryOp::Sub => "-",
+            BinaryOp::Mul => "*",
+            BinaryOp::Div => "/",
+            BinaryOp::Rem => "%",
+            BinaryOp::Eq => "==",
+            BinaryOp::Ne => "!=",
+            BinaryOp::Lt => "<",
+            BinaryOp::Le => "<=",
+            BinaryOp::Gt => ">",
+            BinaryOp::Ge => ">=",
+            BinaryOp::And => "and",
+            BinaryOp::Or => "or",
+            BinaryOp::BitOr => "|",
+            BinaryOp::BitXor => "^",
+            BinaryOp::BitAnd => "&",
+            BinaryOp::Shl => "<<",
+            BinaryOp::Shr => ">>",
+        }
+    }
+}
@@ -0,0 +1,613 @@
+//! Parser of the KS expression language, for expressions written by hand (in ksy and KST files)
+//! rather than generated. Precedence and associativity follow KSC, see
+//! https://github.com/kaitai-io/kaitai_struct_compiler/blob/master/shared/src/main/scala/io/kaitai/struct/exprlang/Expressions.scala
+
+use thiserror::Error;
+
+use super::utils::PositiveFiniteF64;
+use super::{BinaryOp, Expr, TypeName, UnaryOp};
+
+#[derive(Clone, Debug, Error, PartialEq)]
+#[error("at offset {pos}: {message}")]
+pub struct SyntaxError {
+    /// Byte offset in the source
+    pub pos: usize,
+    pub message: String,
+}
+
+/// Parses a whole expression.
+pub fn parse_expr(source: &str) -> Result<Expr, SyntaxError> {
+    let tokens = tokenize(source)?;
+    let mut parser = Parser {
+        tokens,
+        next: 0,
+        end: source.len(),
+    };
+    let expr = parser.test()?;
+    match parser.peek() {
+        None => Ok(expr),
+        Some(_) => Err(parser.error("expected the end of the expression")),
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Int(u64),
+    Float(f64),
+    Str(String),
+    Name(String),
+    Symbol(&'static str),
+}
+
+/// Symbols, longest first so that `<<` isn't read as two `<`.
+const SYMBOLS: [&str; 26] = [
+    "::", "==", "!=", "<=", ">=", "<<", ">>", "(", ")", "[", "]", ",", ".", "?", ":", "+", "-",
+    "*", "/", "%", "<", ">", "|", "^", "&", "~",
+];
+
+fn tokenize(source: &str) -> Result<Vec<(usize, Token)>, SyntaxError> {
+    let error = |pos, message: &str| SyntaxError {
+        pos,
+        message: message.to_string(),
+    };
+    let bytes = source.as_bytes();
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+    while pos < bytes.len() {
+        let start = pos;
+        let ch = bytes[pos];
+        if ch.is_ascii_whitespace() {
+            pos += 1;
+            continue;
+        }
+        if ch.is_ascii_digit() {
+            let (token, len) = number(&source[pos..]).ok_or_else(|| error(pos, "bad number"))?;
+            tokens.push((start, token));
+            pos += len;
+        } else if ch.is_ascii_alphabetic() || ch == b'_' {
+            while pos < bytes.len() && (bytes[pos].is_ascii_alphanumeric() || bytes[pos] == b'_') {
+                pos += 1;
+            }
+            tokens.push((start, Token::Name(source[start..pos].to_string())));
+        } else if ch == b'\'' {
+            let len = source[pos + 1..]
+                .find('\'')
+                .ok_or_else(|| error(pos, "unterminated string"))?;
+            tokens.push((
+                start,
+                Token::Str(source[pos + 1..pos + 1 + len].to_string()),
+            ));
+            pos += len + 2;
+        } else if ch == b'"' {
+            let (value, len) = escaped_str(&source[pos + 1..])
+                .map_err(|(offset, message)| error(pos + 1 + offset, message))?;
+            tokens.push((start, Token::Str(value)));
+            pos += len + 2;
+        } else {
+            let symbol = SYMBOLS
+                .into_iter()
+                .find(|symbol| source[pos..].starts_with(symbol))
+                .ok_or_else(|| error(pos, "unexpected character"))?;
+            tokens.push((start, Token::Symbol(symbol)));
+            pos += symbol.len();
+        }
+    }
+    Ok(tokens)
+}
+
+/// Number at the start of `s`, with the length of its literal.
+fn number(s: &str) -> Option<(Token, usize)> {
+    let radix_digits = |radix, prefix_len| {
+        let digits: String = s[prefix_len..]
+            .chars()
+            .take_while(|ch| ch.is_digit(radix) || *ch == '_')
+            .collect();
+        let value = u64::from_str_radix(&digits.replace('_', ""), radix).ok()?;
+        Some((Token::Int(value), prefix_len + digits.len()))
+    };
+    match s.get(..2) {
+        Some("0x" | "0X") => return radix_digits(16, 2),
+        Some("0b" | "0B") => return radix_digits(2, 2),
+        Some("0o" | "0O") => return radix_digits(8, 2),
+        _ => {}
+    }
+    let bytes = s.as_bytes();
+    let digits = |from: usize| {
+        from + bytes[from..]
+            .iter()
+            .take_while(|b| b.is_ascii_digit() || **b == b'_')
+            .count()
+    };
+    let mut len = digits(0);
+    let mut is_float = false;
+    // `1.to_s` is a method call on an integer
+    if bytes.get(len) == Some(&b'.') && bytes.get(len + 1).is_some_and(u8::is_ascii_digit) {
+        len = digits(len + 1);
+        is_float = true;
+    }
+    if matches!(bytes.get(len), Some(b'e' | b'E')) {
+        let mut exp = len + 1;
+        if matches!(bytes.get(exp), Some(b'+' | b'-')) {
+            exp += 1;
+        }
+        if bytes.get(exp).is_some_and(u8::is_ascii_digit) {
+            len = digits(exp);
+            is_float = true;
+        }
+    }
+    let literal = s[..len].replace('_', "");
+    let token = if is_float {
+        Token::Float(literal.parse().ok()?)
+    } else {
+        Token::Int(literal.parse().ok()?)
+    };
+    Some((token, len))
+}
+
+/// Value of a double-quoted string starting after the opening quote, with the length of its
+/// contents (without the quotes).
+fn escaped_str(s: &str) -> Result<(String, usize), (usize, &'static str)> {
+    let mut value = String::new();
+    let mut chars = s.char_indices().peekable();
+    while let Some((i, ch)) = chars.next() {
+        match ch {
+            '"' => return Ok((value, i)),
+            '\\' => {
+                let Some((_, escape)) = chars.next() else {
+                    break;
+                };
+                let simple = match escape {
+                    'a' => Some('\x07'),
+                    'b' => Some('\x08'),
+                    't' => Some('\t'),
+                    'n' => Some('\n'),
+                    'v' => Some('\x0b'),
+                    'f' => Some('\x0c'),
+                    'r' => Some('\r'),
+                    'e' => Some('\x1b'),
+                    '"' | '\'' | '\\' => Some(escape),
+                    _ => None,
+                };
+                if let Some(ch) = simple {
+                    value.push(ch);
+                } else if escape == 'u' {
+                    let hex: String = (0..4)
+                        .filter_map(|_| chars.next())
+                        .map(|(_, c)| c)
+                        .collect();
+                    let ch = u32::from_str_radix(&hex, 16)
+                        .ok()
+                        .filter(|_| hex.len() == 4)
+                        .and_then(char::from_u32)
+                        .ok_or((i, "bad \\u escape"))?;
+                    value.push(ch);
+                } else if let Some(first) = escape.to_digit(8) {
+                    let mut code = first;
+                    for _ in 0..2 {
+                        match chars.peek().and_then(|(_, c)| c.to_digit(8)) {
+                            Some(digit) => {
+                                code = code * 8 + digit;
+                                chars.next();
+                            }
+                            None => break,
+                        }
+                    }
+                    value.push(char::from_u32(code).ok_or((i, "bad octal escape"))?);
+                } else {
+                    return Err((i, "unknown escape"));
+                }
+            }
+            ch => value.push(ch),
+        }
+    }
+    Err((s.len(), "unterminated string"))
+}
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    next: usize,
+    /// Length of the source, the position of errors at its end
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.next).map(|(_, token)| token)
+    }
+
+    fn error(&self, message: &str) -> SyntaxError {
+        SyntaxError {
+            pos: self.tokens.get(self.next).map_or(self.end, |(pos, _)| *pos),
+            message: message.to_string(),
+        }
+    }
+
+    fn eat_symbol(&mut self, symbol: &str) -> bool {
+        if matches!(self.peek(), Some(Token::Symbol(s)) if *s == symbol) {
+            self.next += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        if matches!(self.peek(), Some(Token::Name(name)) if name == keyword) {
+            self.next += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect_symbol(&mut self, symbol: &str) -> Result<(), SyntaxError> {
+        if self.eat_symbol(symbol) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected `{}`", symbol)))
+        }
+    }
+
+    fn name(&mut self) -> Result<String, SyntaxError> {
+        match self.peek() {
+            Some(Token::Name(name)) => {
+                let name = name.clone();
+                self.next += 1;
+                Ok(name)
+            }
+            _ => Err(self.error("expected a name")),
+        }
+    }
+
+    /// `cond ? if_true : if_false`, or a lower-precedence expression
+    fn test(&mut self) -> Result<Expr, SyntaxError> {
+        let cond = self.binary_chain(0)?;
+        if !self.eat_symbol("?") {
+            return Ok(cond);
+        }
+        let if_true = self.test()?;
+        self.expect_symbol(":")?;
+        let if_false = self.test()?;
+        Ok(Expr::CondOp {
+            cond: Box::new(cond),
+            if_true: Box::new(if_true),
+            if_false: Box::new(if_false),
+        })
+    }
+
+    /// Left-associative chain of the binary operators of the level in [`LEVELS`], with `not`
+    /// binding looser than comparisons.
+    fn binary_chain(&mut self, level: usize) -> Result<Expr, SyntaxError> {
+        let Some(ops) = LEVELS.get(level) else {
+            return self.factor();
+        };
+        let operand = |parser: &mut Self| {
+            if level + 1 == NOT_LEVEL {
+                parser.not_test()
+            } else {
+                parser.binary_chain(level + 1)
+            }
+        };
+        let mut l = operand(self)?;
+        while let Some(op) = self.binary_op(ops) {
+            let r = operand(self)?;
+            l = Expr::BinaryOp {
+                l: Box::new(l),
+                op,
+                r: Box::new(r),
+            };
+        }
+        Ok(l)
+    }
+
+    /// Comparison, possibly negated by `not`
+    fn not_test(&mut self) -> Result<Expr, SyntaxError> {
+        if !self.eat_keyword("not") {
+            return self.binary_chain(NOT_LEVEL);
+        }
+        let value = self.not_test()?;
+        Ok(Expr::UnaryOp {
+            op: UnaryOp::Not,
+            value: Box::new(value),
+        })
+    }
+
+    fn binary_op(&mut self, ops: &[BinaryOp]) -> Option<BinaryOp> {
+        let op = *ops.iter().find(|op| match self.peek() {
+            Some(Token::Symbol(symbol)) => *symbol == op.symbol(),
+            Some(Token::Name(name)) => name == op.symbol(),
+            _ => false,
+        })?;
+        self.next += 1;
+        Some(op)
+    }
+
+    /// Unary operators and what they apply to
+    fn factor(&mut self) -> Result<Expr, SyntaxError> {
+        let op = if self.eat_symbol("-") {
+            UnaryOp::Neg
+        } else if self.eat_symbol("~") {
+            UnaryOp::Inv
+        } else if self.eat_symbol("+") {
+            return self.factor();
+        } else {
+            return self.power();
+        };
+        let value = self.factor()?;
+        Ok(Expr::UnaryOp {
+            op,
+            value: Box::new(value),
+        })
+    }
+
+    /// Atom with its trailers: attributes, method calls, subscripts and casts
+    fn power(&mut self) -> Result<Expr, SyntaxError> {
+        let mut expr = self.atom()?;
+        loop {
+            if self.eat_symbol("[") {
+                let idx = self.test()?;
+                self.expect_symbol("]")?;
+                expr = Expr::Subscript {
+                    value: Box::new(expr),
+                    idx: Box::new(idx),
+                };
+            } else if self.eat_symbol(".") {
+                let name = self.name()?;
+                if name == "as" && self.eat_symbol("<") {
+                    let type_name = self.type_name()?;
+                    expr = Expr::CastTo {
+                        value: Box::new(expr),
+                        type_name,
+                    };
+                } else if self.eat_symbol("(") {
+                    let args = self.separated(")")?;
+                    expr = Expr::MethodCall {
+                        value: Box::new(expr),
+                        method_name: name,
+                        args,
+                    };
+                } else {
+                    expr = Expr::Attribute {
+                        value: Box::new(expr),
+                        attr_name: name,
+                    };
+                }
+            } else {
+                return Ok(expr);
+            }
+        }
+    }
+
+    fn atom(&mut self) -> Result<Expr, SyntaxError> {
+        let Some(token) = self.peek().cloned() else {
+            return Err(self.error("expected an expression"));
+        };
+        self.next += 1;
+        Ok(match token {
+            Token::Int(value) => Expr::Int(value),
+            Token::Float(value) => Expr::Float(
+                PositiveFiniteF64::try_from(value).map_err(|_| self.error("float out of range"))?,
+            ),
+            Token::Str(value) => Expr::Str(value),
+            Token::Symbol("(") => {
+                let expr = self.test()?;
+                self.expect_symbol(")")?;
+                expr
+            }
+            Token::Symbol("[") => Expr::List(self.separated("]")?),
+            Token::Symbol(_) => {
+                self.next -= 1;
+                return Err(self.error("expected an expression"));
+            }
+            Token::Name(name) => match name.as_str() {
+                "true" => Expr::Bool(true),
+                "false" => Expr::Bool(false),
+                "sizeof" | "bitsizeof" if self.eat_symbol("<") => Expr::SizeOf {
+                    type_name: self.type_name()?,
+                    bits: name == "bitsizeof",
+                },
+                _ => {
+                    let mut path = vec![name];
+                    while self.eat_symbol("::") {
+                        path.push(self.name()?);
+                    }
+                    match path.pop() {
+                        Some(label) if !path.is_empty() => Expr::EnumMember {
+                            enum_path: path,
+                            label,
+                        },
+                        Some(name) => Expr::Name(name),
+                        None => unreachable!("paths have a name"),
+                    }
+                }
+            },
+        })
+    }
+
+    /// Type name in `as<...>` and `sizeof<...>`, after the `<`.
+    fn type_name(&mut self) -> Result<TypeName, SyntaxError> {
+        let mut path = vec![self.name()?];
+        while self.eat_symbol("::") {
+            path.push(self.name()?);
+        }
+        let is_array = self.eat_symbol("[");
+        if is_array {
+            self.expect_symbol("]")?;
+        }
+        // `a.as<u4>>b` closes the type with the first half of `>>`
+        if self.peek() == Some(&Token::Symbol(">>")) {
+            self.tokens[self.next].1 = Token::Symbol(">");
+        } else {
+            self.expect_symbol(">")?;
+        }
+        Ok(TypeName { path, is_array })
+    }
+
+    /// Comma-separated expressions up to the closing symbol.
+    fn separated(&mut self, close: &str) -> Result<Vec<Expr>, SyntaxError> {
+        let mut items = Vec::new();
+        while !self.eat_symbol(close) {
+            if !items.is_empty() {
+                self.expect_symbol(",")?;
+                // trailing commas are allowed
+                if self.eat_symbol(close) {
+                    break;
+                }
+            }
+            items.push(self.test()?);
+        }
+        Ok(items)
+    }
+}
+
+/// Binary operators by precedence, loosest first.
+const LEVELS: [&[BinaryOp]; 9] = [
+    &[BinaryOp::Or],
+    &[BinaryOp::And],
+    &[
+        BinaryOp::Eq,
+        BinaryOp::Ne,
+        BinaryOp::Le,
+        BinaryOp::Ge,
+        BinaryOp::Lt,
+        BinaryOp::Gt,
+    ],
+    &[BinaryOp::BitOr],
+    &[BinaryOp::BitXor],
+    &[BinaryOp::BitAnd],
+    &[BinaryOp::Shl, BinaryOp::Shr],
+    &[BinaryOp::Add, BinaryOp::Sub],
+    &[BinaryOp::Mul, BinaryOp::Div, BinaryOp::Rem],
+];
+
+/// Level in [`LEVELS`] of the comparisons, which `not` negates
+const NOT_LEVEL: usize = 2;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gen::expr::ExprGenerator;
+    use crate::translator::translate;
+    use crate::typing::{KsType, TypeEnv};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn name(name: &str) -> Box<Expr> {
+        Box::new(Expr::Name(name.to_string()))
+    }
+
+    #[test]
+    fn precedence() {
+        let parsed = parse_expr("a + b * 2 == c and not d or e").unwrap();
+        assert_eq!(
+            translate(&parsed),
+            "((((a + (b * 2)) == c) and (not d)) or e)"
+        );
+        assert_eq!(translate(&parse_expr("-a.b[1]").unwrap()), "(-a.b[1])");
+        assert_eq!(
+            translate(&parse_expr("x ? 1 : y ? 2 : 3").unwrap()),
+            "(x ? 1 : (y ? 2 : 3))"
+        );
+        assert_eq!(
+            translate(&parse_expr("1 - 2 - 3").unwrap()),
+            "((1 - 2) - 3)"
+        );
+    }
+
+    #[test]
+    fn literals() {
+        let parse = |s| parse_expr(s).unwrap();
+        assert_eq!(parse("0x1_F"), Expr::Int(31));
+        assert_eq!(parse("0b101"), Expr::Int(5));
+        assert_eq!(parse("2.5e-3"), parse("0.0025"));
+        assert_eq!(
+            parse("\"a\\\"\\u00e9\\0\""),
+            Expr::Str("a\"é\0".to_string())
+        );
+        assert_eq!(parse("'a\\n'"), Expr::Str("a\\n".to_string()));
+        assert_eq!(
+            parse("animal::cat"),
+            Expr::EnumMember {
+                enum_path: vec!["animal".to_string()],
+                label: "cat".to_string()
+            }
+        );
+        assert_eq!(
+            parse("[0x50, 0x41,]"),
+            Expr::List(vec![Expr::Int(0x50), Expr::Int(0x41)])
+        );
+        assert_eq!(
+            parse("1.to_s"),
+            Expr::Attribute {
+                value: Box::new(Expr::Int(1)),
+                attr_name: "to_s".to_string()
+            }
+        );
+        assert_eq!(
+            parse("a.as<u4>>b"),
+            Expr::BinaryOp {
+                l: Box::new(Expr::CastTo {
+                    value: name("a"),
+                    type_name: TypeName::new("u4")
+                }),
+                op: BinaryOp::Gt,
+                r: name("b"),
+            }
+        );
+        assert_eq!(
+            parse("bitsizeof<hdr::entry[]>"),
+            Expr::SizeOf {
+                type_name: TypeName {
+                    path: vec!["hdr".to_string(), "entry".to_string()],
+                    is_array: true
+                },
+                bits: true
+            }
+        );
+    }
+
+    #[test]
+    fn syntax_errors() {
+        assert_eq!(parse_expr("a +").unwrap_err().pos, 3);
+        assert_eq!(parse_expr("(a").unwrap_err().message, "expected `)`");
+        assert_eq!(parse_expr("a b").unwrap_err().pos, 2);
+        assert!(parse_expr("'open").is_err());
+        assert!(parse_expr("\"\\q\"").is_err());
+        assert!(parse_expr("a $ b").is_err());
+    }
+
+    #[test]
+    fn generated_round_trip() {
+        let mut env = TypeEnv::new();
+        env.set("hdr", KsType::User(vec!["header".to_string()]));
+        env.set("items", KsType::Array(Box::new(KsType::Int)));
+        env.set("_io", KsType::Stream);
+        env.define_type(
+            vec!["header".to_string()],
+            [
+                ("num_items", KsType::Int),
+                ("kind", KsType::Enum(vec!["kind".to_string()])),
+            ],
+        );
+        env.define_enum(vec!["kind".to_string()], ["a", "b"]);
+        let generator = ExprGenerator::new(&env, 4);
+        let mut rng = StdRng::seed_from_u64(0);
+        let types = [
+            KsType::Int,
+            KsType::Float,
+            KsType::Str,
+            KsType::Bool,
+            KsType::Bytes,
+            KsType::Enum(vec!["kind".to_string()]),
+        ];
+        for _ in 0..50 {
+            for ty in &types {
+                let Some(expr) = generator.generate(&mut rng, ty) else {
+                    continue;
+                };
+                let source = translate(&expr);
+                assert_eq!(parse_expr(&source), Ok(expr), "{}", source);
+            }
+        }
+    }
+}
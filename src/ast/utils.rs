@@ -2,6 +2,8 @@ use std::cmp::Ordering;
 use std::hash::{Hash, Hasher};
 use thiserror::Error;
 
+use crate::ast::{BinaryOp, Expr};
+
 #[derive(Copy, Clone, Debug, PartialEq, PartialOrd)]
 pub struct PositiveFiniteF64 {
     value: f64,
@@ -58,6 +60,27 @@ impl PositiveFiniteF64 {
     }
 }
 
+/// Reference to the field (or another name in scope) `name`.
+pub fn name(name: &str) -> Expr {
+    Expr::Name(name.to_string())
+}
+
+/// `value.attr_name`
+pub fn attr(value: Expr, attr_name: &str) -> Expr {
+    Expr::Attribute {
+        value: Box::new(value),
+        attr_name: attr_name.to_string(),
+    }
+}
+
+pub fn binary(l: Expr, op: BinaryOp, r: Expr) -> Expr {
+    Expr::BinaryOp {
+        l: Box::new(l),
+        op,
+        r: Box::new(r),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::{InvalidFloatError, PositiveFiniteF64};
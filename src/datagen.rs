@@ -0,0 +1,2564 @@
+//! Synthesis of binary data for a spec. The generator walks the spec the way a parser would,
+//! chooses a value for every attribute and writes its bytes, so the values that parsing the data
+//! yields are known in advance.
+//!
+//! Integers that sizes or repeat counts may refer to (by their name) are kept below the maximal
+//! length, so that the data stays small. Repetitions until a condition and byte arrays or
+//! strings until a terminator are generated so that they end exactly where intended.
+//!
+//! With a size budget, an attribute that would exceed it sends the generator back to the
+//! attribute before, which it chooses again with smaller free lengths and counts.
+//!
+//! The data is then read back with the [`interpret`]er, which must yield the intended values.
+
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet};
+
+use rand::seq::SliceRandom;
+use rand::Rng;
+use thiserror::Error;
+
+use crate::ast::{BinaryOp, Expr, UnaryOp};
+use crate::eval::{eval, Env, EvalError, Value};
+use crate::gen::string::Encoding;
+use crate::ksy::{
+    Attribute, Endian, KsySpec, MetaEndian, Repeat, TypeRef, TypeSpec, Valid, ValidChecks,
+};
+use crate::numeric::IntType;
+use crate::tolerance::non_finite_assertion;
+use crate::translator::translate;
+use constraints::{solve_int, solve_value};
+use edges::{float_edges, int_edges, narrow_f4, widen_f4, EdgeBias};
+use interpret::ReadError;
+use process::Process;
+use trace::TraceSpan;
+use writer::Writer;
+
+pub mod constraints;
+pub mod edges;
+pub mod interpret;
+pub mod process;
+pub mod trace;
+pub mod writer;
+
+/// Attempts at synthesizing data before giving up on a spec whose data didn't fit
+const ATTEMPTS: usize = 100;
+/// Largest size or repeat count that the generator accepts
+const MAX_SIZE: i128 = 1 << 16;
+/// Times that the generator goes back to an earlier attribute when the data exceeds the size
+/// budget, in each attempt
+const MAX_BACKTRACKS: usize = 32;
+/// Levels of user types nested in each other, at most, which recursive types stop at
+const MAX_NESTING: usize = 32;
+/// Random data that [`synthesize_variants`] chooses each variant from
+const CANDIDATES_PER_VARIANT: usize = 4;
+/// Checks that [`violations`] tries to violate, at most
+pub const MAX_VIOLATIONS: usize = 64;
+/// Key of the default case of a switch
+const DEFAULT_CASE: &str = "_";
+
+#[derive(Clone, Debug, Error, PartialEq)]
+pub enum DataGenError {
+    #[error("{0} is not supported by the data generator")]
+    Unsupported(String),
+    #[error("unknown type `{0}`")]
+    UnknownType(String),
+    #[error("unknown enum `{0}`")]
+    UnknownEnum(String),
+    #[error("`{0}` needs `meta/endian`")]
+    MissingEndian(String),
+    #[error("`meta/endian` of `{0}` matches no case")]
+    UndecidedEndianness(String),
+    #[error("can't evaluate `{expr}`: {error}")]
+    Eval { expr: String, error: EvalError },
+    #[error("`{expr}` is {found}, expected {expected}")]
+    UnexpectedValue {
+        expr: String,
+        expected: &'static str,
+        found: &'static str,
+    },
+    #[error("size {0} is out of range")]
+    SizeOutOfRange(i128),
+    #[error("can't check `valid` of `{id}`: {error}")]
+    Valid { id: String, error: EvalError },
+    #[error("no value of `{0}` passes its `valid` checks")]
+    Unsatisfiable(String),
+    #[error("`{0}` doesn't fit into the rest of its stream")]
+    DoesNotFit(String),
+    #[error("`{0}` follows an attribute reaching the end of the stream")]
+    AfterEos(String),
+    #[error("`{0}` doesn't fit into the size budget")]
+    OverBudget(String),
+    #[error("`{0}` nests user types too deep")]
+    TooDeep(String),
+    #[error("synthesized data doesn't read back as intended: {0}")]
+    SelfCheck(String),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct DataOptions {
+    /// Length of byte arrays and strings read until the end of the stream, and the largest value
+    /// of the integers that sizes refer to
+    pub max_len: usize,
+    /// Fewest items of attributes repeated until the end of the stream or until a condition
+    /// (which always have at least one)
+    pub min_items: usize,
+    /// Most items of attributes repeated until the end of the stream or until a condition, and
+    /// the largest value of the integers that repeat counts refer to
+    pub max_items: usize,
+    /// How often fields get boundary values
+    pub edges: EdgeBias,
+    /// Largest data in bytes. Lengths and counts exceeding it are chosen again, smaller.
+    pub max_size: Option<usize>,
+    /// Whether to record which field each byte of the data comes from
+    pub trace: bool,
+    /// Whether to read the data back with the [`interpret`]er, failing with
+    /// [`DataGenError::SelfCheck`] if it doesn't give the intended values
+    pub self_check: bool,
+}
+
+impl Default for DataOptions {
+    fn default() -> Self {
+        DataOptions {
+            max_len: 16,
+            min_items: 0,
+            max_items: 8,
+            edges: EdgeBias::default(),
+            max_size: None,
+            trace: false,
+            self_check: true,
+        }
+    }
+}
+
+/// Data with the values of the attributes of the top-level type that parsing it yields.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Synthesized {
+    pub data: Vec<u8>,
+    pub values: BTreeMap<String, Value>,
+    /// Offsets in the data where the attributes of the top-level type (and of the types read
+    /// inline, without a substream) and the items of repeated ones end, in order
+    pub boundaries: Vec<usize>,
+    /// Offset of the first attribute reading up to the end of the data (or tolerating a missing
+    /// terminator), from where cutting the data short doesn't make parsing fail
+    pub tolerant_from: Option<usize>,
+    /// Branches that the data takes, like `if x: true`, `switch body: chunk` or `repeat
+    /// items: 3`
+    pub coverage: BTreeSet<String>,
+    /// Fields that the bytes of the data come from, in order, if [`DataOptions::trace`] is set
+    pub trace: Vec<TraceSpan>,
+}
+
+impl Synthesized {
+    /// Expected values as assertions on the top-level type, with the attributes of nested types
+    /// reached through `.` and subscripts of arrays of them.
+    ///
+    /// Non-finite floats, which have no literal, are checked by an isnan-style (or range)
+    /// condition expected to be true instead.
+    pub fn assertions(&self) -> Vec<(Expr, Value)> {
+        field_assertions(&self.values)
+    }
+
+    /// Bit patterns (of the values as doubles) of the floats that the [assertions] don't pin
+    /// down: NaNs with their payloads and signed zeros. Only targets that can reinterpret a
+    /// float as an integer check them.
+    ///
+    /// [assertions]: Synthesized::assertions
+    pub fn float_bits(&self) -> Vec<(Expr, u64)> {
+        let mut values = Vec::new();
+        flatten(None, &self.values, &mut values);
+        values
+            .into_iter()
+            .filter_map(|(expr, value)| match value {
+                Value::Float(x) if x.is_nan() || x == 0.0 => Some((expr, x.to_bits())),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// The data cut at every boundary and in the middle of every field, shortest first: each
+    /// fails to parse with an end-of-stream error.
+    pub fn truncations(&self) -> Vec<Vec<u8>> {
+        let end = self.tolerant_from.unwrap_or(self.data.len());
+        let mut cuts = BTreeSet::new();
+        let mut start = 0;
+        for boundary in self.boundaries.iter().copied().chain([self.data.len()]) {
+            cuts.insert(start);
+            cuts.insert(start + (boundary.saturating_sub(start)) / 2);
+            start = boundary;
+        }
+        cuts.into_iter()
+            .filter(|cut| *cut < end)
+            .map(|cut| self.data[..cut].to_vec())
+            .collect()
+    }
+}
+
+/// Assertions of the values of the fields of the top-level type, as in
+/// [`Synthesized::assertions`].
+pub fn field_assertions(fields: &BTreeMap<String, Value>) -> Vec<(Expr, Value)> {
+    let mut assertions = Vec::new();
+    flatten(None, fields, &mut assertions);
+    checked(assertions)
+}
+
+/// Assertions that `expr` has the value, taken apart like the fields in
+/// [`Synthesized::assertions`].
+pub fn value_assertions(expr: Expr, value: &Value) -> Vec<(Expr, Value)> {
+    let mut assertions = Vec::new();
+    flatten_value(expr, value, &mut assertions);
+    checked(assertions)
+}
+
+/// Replaces the assertions of non-finite floats with conditions expected to be true.
+fn checked(mut assertions: Vec<(Expr, Value)>) -> Vec<(Expr, Value)> {
+    for (expr, value) in &mut assertions {
+        if let Value::Float(x) = *value {
+            if let Some(check) = non_finite_assertion(expr.clone(), x) {
+                *expr = check;
+                *value = Value::Bool(true);
+            }
+        }
+    }
+    assertions
+}
+
+fn flatten(prefix: Option<&Expr>, fields: &BTreeMap<String, Value>, out: &mut Vec<(Expr, Value)>) {
+    for (name, value) in fields {
+        let expr = match prefix {
+            None => Expr::Name(name.clone()),
+            Some(prefix) => Expr::Attribute {
+                value: Box::new(prefix.clone()),
+                attr_name: name.clone(),
+            },
+        };
+        flatten_value(expr, value, out);
+    }
+}
+
+fn flatten_value(expr: Expr, value: &Value, out: &mut Vec<(Expr, Value)>) {
+    match value {
+        Value::Struct(fields) => flatten(Some(&expr), fields, out),
+        Value::Array(items) if items.iter().any(needs_subscript) => {
+            for (i, item) in items.iter().enumerate() {
+                let item_expr = Expr::Subscript {
+                    value: Box::new(expr.clone()),
+                    idx: Box::new(Expr::Int(i as u64)),
+                };
+                flatten_value(item_expr, item, out);
+            }
+        }
+        _ => out.push((expr, value.clone())),
+    }
+}
+
+/// Whether the items of an array holding the value get assertions of their own: structs, and
+/// floats whose value a literal doesn't determine.
+fn needs_subscript(item: &Value) -> bool {
+    match item {
+        Value::Struct(_) => true,
+        Value::Float(x) => !x.is_finite() || *x == 0.0,
+        _ => false,
+    }
+}
+
+/// Data for the spec with random values. Retries a few times if the data happens not to fit
+/// into a fixed-size substream, or the attributes before a `valid` check leave no value passing
+/// it.
+pub fn synthesize<R: Rng + ?Sized>(
+    rng: &mut R,
+    spec: &KsySpec,
+    options: &DataOptions,
+) -> Result<Synthesized, DataGenError> {
+    synthesize_run(rng, spec, options, None).map(|(synthesized, _)| synthesized)
+}
+
+/// Up to `count` different data for the spec, chosen among more random ones to take as many
+/// different branches (see [`Synthesized::coverage`]) as possible. The candidates are made with
+/// the random numbers from `rng_for` their index, which is returned with each chosen one:
+/// [`synthesize`] with the same random numbers regenerates it.
+pub fn synthesize_variants<R, F>(
+    spec: &KsySpec,
+    options: &DataOptions,
+    count: usize,
+    mut rng_for: F,
+) -> Result<Vec<(usize, Synthesized)>, DataGenError>
+where
+    R: Rng,
+    F: FnMut(usize) -> R,
+{
+    let mut candidates: Vec<(usize, Synthesized)> = Vec::new();
+    for i in 0..count * CANDIDATES_PER_VARIANT {
+        let synthesized = synthesize(&mut rng_for(i), spec, options)?;
+        if candidates.iter().all(|(_, c)| c.data != synthesized.data) {
+            candidates.push((i, synthesized));
+        }
+    }
+    let mut covered = BTreeSet::new();
+    let mut variants = Vec::new();
+    while variants.len() < count && !candidates.is_empty() {
+        // the first of the candidates adding the most
+        let new = |c: &Synthesized| c.coverage.difference(&covered).count();
+        let (best, _) = candidates
+            .iter()
+            .enumerate()
+            .rev()
+            .max_by_key(|(_, (_, c))| new(c))
+            .expect("candidates must not be empty");
+        let variant = candidates.remove(best);
+        covered.extend(variant.1.coverage.iter().cloned());
+        variants.push(variant);
+    }
+    Ok(variants)
+}
+
+/// Data failing a `contents` or `valid` check, one for each check that can fail (in the order
+/// parsing reaches them, as far as the random data reaches them). Each is valid up to the failing
+/// attribute and ends right after it. Checks of items repeated until a condition are left alone,
+/// as failing them could end the repetition instead.
+pub fn violations<R: Rng + ?Sized>(
+    rng: &mut R,
+    spec: &KsySpec,
+    options: &DataOptions,
+) -> Result<Vec<Vec<u8>>, DataGenError> {
+    let mut violations = Vec::new();
+    for check in 0..MAX_VIOLATIONS {
+        match violation(rng, spec, options, check) {
+            Ok(Some(data)) => violations.push(data),
+            Ok(None) => break,
+            // no value fails the check
+            Err(DataGenError::Unsatisfiable(_)) => {}
+            Err(error) => return Err(error),
+        }
+    }
+    Ok(violations)
+}
+
+/// Data failing the check with the index `check` (counting the checks in the order parsing
+/// reaches them), or `None` if parsing doesn't get that far. Fails with
+/// [`DataGenError::Unsatisfiable`] if no value fails the check.
+pub fn violation<R: Rng + ?Sized>(
+    rng: &mut R,
+    spec: &KsySpec,
+    options: &DataOptions,
+    check: usize,
+) -> Result<Option<Vec<u8>>, DataGenError> {
+    let (synthesized, violated) = synthesize_run(rng, spec, options, Some(check))?;
+    Ok(violated.then_some(synthesized.data))
+}
+
+/// Synthesized data, violating the check with the index `violate` (counting from the start of
+/// the data) if given, and whether it did.
+fn synthesize_run<R: Rng + ?Sized>(
+    rng: &mut R,
+    spec: &KsySpec,
+    options: &DataOptions,
+    violate: Option<usize>,
+) -> Result<(Synthesized, bool), DataGenError> {
+    let mut length_names = BTreeSet::new();
+    collect_length_names(spec, &mut length_names);
+    let mut result = Err(DataGenError::DoesNotFit(String::new()));
+    for _ in 0..ATTEMPTS {
+        let mut synth = Synth {
+            rng: &mut *rng,
+            options,
+            length_names: &length_names,
+            violate,
+            checks: 0,
+            in_until: false,
+            stopped: false,
+            coverage: BTreeSet::new(),
+            backtracks: 0,
+            shrink: 0,
+            nesting: 0,
+        };
+        let mut stream = Stream {
+            budget: options.max_size,
+            ..Stream::new(None)
+        };
+        result = synth
+            .gen_type(vec![spec], &[], None, &mut stream)
+            .map(|values| {
+                let synthesized = Synthesized {
+                    data: stream.writer.into_bytes(),
+                    values,
+                    boundaries: stream.boundaries,
+                    tolerant_from: stream.tolerant_from,
+                    coverage: synth.coverage,
+                    trace: stream.trace,
+                };
+                (synthesized, synth.stopped)
+            });
+        if !matches!(
+            result,
+            Err(DataGenError::DoesNotFit(_)
+                | DataGenError::Unsatisfiable(_)
+                | DataGenError::OverBudget(_)
+                | DataGenError::TooDeep(_))
+        ) {
+            break;
+        }
+    }
+    let (synthesized, violated) = result?;
+    if options.self_check {
+        read_back(spec, &synthesized, violated)?;
+    }
+    Ok((synthesized, violated))
+}
+
+/// Checks that the data reads back as the values it was synthesized for, or fails a check if
+/// it `violated` one.
+fn read_back(
+    spec: &KsySpec,
+    synthesized: &Synthesized,
+    violated: bool,
+) -> Result<(), DataGenError> {
+    let read = interpret::read(spec, &synthesized.data);
+    let mismatch = match (read, violated) {
+        (Ok(values), false) if interpret::same_fields(&values, &synthesized.values) => {
+            return Ok(())
+        }
+        (Err(ReadError::Validation(..)), true) => return Ok(()),
+        (Ok(values), false) => format!("read {:?}, expected {:?}", values, synthesized.values),
+        (Ok(_), true) => "no check fails".to_string(),
+        (Err(error), _) => error.to_string(),
+    };
+    Err(DataGenError::SelfCheck(mismatch))
+}
+
+/// Names that `size` and `repeat-expr` of any attribute refer to.
+fn collect_length_names(ty: &TypeSpec, names: &mut BTreeSet<String>) {
+    fn collect(expr: &Expr, names: &mut BTreeSet<String>) {
+        match expr {
+            Expr::Name(name) => {
+                names.insert(name.clone());
+            }
+            Expr::Attribute { attr_name, .. } => {
+                names.insert(attr_name.clone());
+            }
+            _ => {}
+        }
+        for child in expr.children() {
+            collect(child, names);
+        }
+    }
+    for attr in ty.seq.iter().chain(ty.instances.values()) {
+        for expr in [&attr.size, &attr.repeat_expr].into_iter().flatten() {
+            collect(expr, names);
+        }
+    }
+    for sub in ty.types.values() {
+        collect_length_names(sub, names);
+    }
+}
+
+#[derive(Clone)]
+struct Stream {
+    writer: Writer,
+    /// Size of a substream
+    limit: Option<usize>,
+    /// Whether an attribute read everything up to the end
+    eos: bool,
+    /// Offsets where attributes and items end
+    boundaries: Vec<usize>,
+    /// Offset of the first attribute that parses even if the stream is cut short
+    tolerant_from: Option<usize>,
+    /// Bytes that the stream may take before the data exceeds the size budget
+    budget: Option<usize>,
+    /// Fields of the bytes, with paths relative to the attribute being generated
+    trace: Vec<TraceSpan>,
+}
+
+impl Stream {
+    fn new(limit: Option<usize>) -> Self {
+        Stream {
+            writer: Writer::new(),
+            limit,
+            eos: false,
+            boundaries: Vec::new(),
+            tolerant_from: None,
+            budget: None,
+            trace: Vec::new(),
+        }
+    }
+
+    /// Stream whose bytes end up in this one, sharing its budget.
+    fn substream(&self, limit: Option<usize>) -> Self {
+        Stream {
+            budget: self.budget_left(),
+            ..Stream::new(limit)
+        }
+    }
+
+    fn budget_left(&self) -> Option<usize> {
+        self.budget
+            .map(|budget| budget.saturating_sub(self.writer.len()))
+    }
+
+    /// Records the end of an attribute or an item.
+    fn mark(&mut self) {
+        self.boundaries.push(self.writer.len());
+    }
+
+    fn remaining(&self) -> Option<usize> {
+        self.limit.map(|limit| limit - self.writer.len())
+    }
+
+    /// Checks that `id` can still write `len` more bytes.
+    fn check(&self, id: &str, len: usize) -> Result<(), DataGenError> {
+        if self.eos {
+            return Err(DataGenError::AfterEos(id.to_string()));
+        }
+        match self.remaining() {
+            Some(remaining) if len > remaining => Err(DataGenError::DoesNotFit(id.to_string())),
+            _ if self.budget_left().is_some_and(|budget| len > budget) => {
+                Err(DataGenError::OverBudget(id.to_string()))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Byte order and encoding in effect in a type, inherited from the types it's nested in.
+#[derive(Clone, Debug, Default)]
+struct Defaults {
+    endian: Option<Endian>,
+    bit_endian: Option<Endian>,
+    encoding: Option<String>,
+}
+
+struct Synth<'a, R: ?Sized> {
+    rng: &'a mut R,
+    options: &'a DataOptions,
+    length_names: &'a BTreeSet<String>,
+    /// Index of the check to violate
+    violate: Option<usize>,
+    /// Checks met so far
+    checks: usize,
+    /// Whether items repeated until a condition are being generated
+    in_until: bool,
+    /// Whether the check was violated, after which parsing fails and nothing else is generated
+    stopped: bool,
+    coverage: BTreeSet<String>,
+    /// Times the generator went back to an earlier attribute to stay within the size budget
+    backtracks: usize,
+    /// Halvings of the free lengths and counts, one for each backtrack
+    shrink: usize,
+    /// Levels of user types that the attribute being generated is nested in
+    nesting: usize,
+}
+
+impl<'a, R: Rng + ?Sized> Synth<'a, R> {
+    /// Values of the attributes of the last type in `scopes` (the types it's nested in come
+    /// before it), whose instances of the types above are `parents`.
+    fn gen_type(
+        &mut self,
+        scopes: Vec<&'a TypeSpec>,
+        parents: &[Value],
+        inherited: Option<&Defaults>,
+        stream: &mut Stream,
+    ) -> Result<BTreeMap<String, Value>, DataGenError> {
+        let ty = *scopes.last().expect("scopes must not be empty");
+        let mut env = Env::new();
+        for scope in &scopes {
+            for (name, members) in &scope.enums {
+                let members = members.iter().map(|(value, label)| (label.clone(), *value));
+                env.define_enum(vec![name.clone()], members);
+            }
+        }
+        if let Some(parent) = parents.last() {
+            env.set("_parent", parent.clone());
+        }
+        let defaults = self.defaults(ty, inherited, &env)?;
+
+        let mut fields = Fields {
+            values: BTreeMap::new(),
+            env,
+        };
+        let budgeted = self.options.max_size.is_some();
+        // states before each attribute, to go back to when the data exceeds the size budget
+        let mut checkpoints = Vec::new();
+        let mut i = 0;
+        while i < ty.seq.len() && !self.stopped {
+            if budgeted {
+                checkpoints.truncate(i);
+                checkpoints.push(self.checkpoint(stream, &fields));
+            }
+            let attr = &ty.seq[i];
+            let result = self.gen_seq_attr(attr, &scopes, parents, &defaults, &mut fields, stream);
+            match result {
+                Err(DataGenError::OverBudget(_))
+                    if budgeted && self.backtracks < MAX_BACKTRACKS =>
+                {
+                    // choose the previous attribute again, with smaller lengths and counts
+                    self.backtracks += 1;
+                    self.shrink += 1;
+                    i = i.saturating_sub(1);
+                    self.restore(checkpoints[i].clone(), stream, &mut fields);
+                }
+                result => {
+                    result?;
+                    i += 1;
+                }
+            }
+        }
+
+        let Fields {
+            mut values,
+            mut env,
+        } = fields;
+        for (name, instance) in &ty.instances {
+            let Some(expr) = &instance.value else {
+                return Err(DataGenError::Unsupported(format!(
+                    "parse instance `{}`",
+                    name
+                )));
+            };
+            // instances failing to evaluate throw in the runtimes too, they aren't expected values
+            if let Ok(value) = eval(expr, &env) {
+                env.set(name.clone(), value.clone());
+                values.insert(name.clone(), value);
+            }
+        }
+        Ok(values)
+    }
+
+    /// Generates a seq attribute of a type, adding its value to the `fields`.
+    fn gen_seq_attr(
+        &mut self,
+        attr: &Attribute,
+        scopes: &[&'a TypeSpec],
+        parents: &[Value],
+        defaults: &Defaults,
+        fields: &mut Fields,
+        stream: &mut Stream,
+    ) -> Result<(), DataGenError> {
+        let Fields { values, env } = fields;
+        let id = attr
+            .id
+            .as_deref()
+            .ok_or_else(|| DataGenError::Unsupported("a seq attribute without id".into()))?;
+        check_supported(attr)?;
+        let root = parents
+            .first()
+            .cloned()
+            .unwrap_or_else(|| Value::Struct(values.clone()));
+        env.set("_root", root);
+        if let Some(cond) = &attr.if_expr {
+            let taken = eval_bool(cond, env)?;
+            self.cover(format!("if {}: {}", id, taken));
+            if !taken {
+                return Ok(());
+            }
+        }
+        let ctx = Ctx {
+            scopes,
+            parents,
+            values,
+            env,
+            defaults,
+        };
+        let (start, was_eos) = (stream.writer.len(), stream.eos);
+        let (first_span, start_byte) = (stream.trace.len(), stream.writer.bit_len() / 8);
+        let value = match attr.repeat {
+            None => {
+                let value = self.gen_attr(attr, id, &ctx, stream)?;
+                if let Some(value) = &value {
+                    self.record(start_byte, value, stream);
+                }
+                value
+            }
+            Some(Repeat::Expr) => {
+                let count = eval_size(attr.repeat_expr.as_ref().unwrap_or(&Expr::Int(0)), env)?;
+                let mut items = Vec::new();
+                for i in 0..count {
+                    if self.stopped {
+                        break;
+                    }
+                    items.push(self.gen_item(attr, id, i, &ctx, stream)?);
+                }
+                Some(Value::Array(items))
+            }
+            Some(Repeat::Eos) => {
+                let mut items = Vec::new();
+                match stream.limit {
+                    Some(_) => {
+                        while stream.remaining() != Some(0) && !self.stopped {
+                            let before = stream.writer.len();
+                            items.push(self.gen_item(attr, id, items.len(), &ctx, stream)?);
+                            if stream.writer.len() == before {
+                                return Err(DataGenError::Unsupported(format!(
+                                    "`repeat: eos` of `{}` reading nothing",
+                                    id
+                                )));
+                            }
+                        }
+                    }
+                    None => {
+                        for i in 0..self.item_count(0) {
+                            if self.stopped {
+                                break;
+                            }
+                            items.push(self.gen_item(attr, id, i, &ctx, stream)?);
+                        }
+                    }
+                }
+                stream.eos = true;
+                Some(Value::Array(items))
+            }
+            Some(Repeat::Until) => {
+                if attr.repeat_until.is_none() {
+                    return Err(DataGenError::Unsupported(format!(
+                        "`{}` without `repeat-until`",
+                        id
+                    )));
+                }
+                let count = self.item_count(1);
+                let mut items = Vec::new();
+                for i in 0..count {
+                    let mut item_env = env.clone();
+                    item_env.set("_index", Value::Int(i as i128));
+                    let item_ctx = Ctx {
+                        env: &item_env,
+                        ..ctx
+                    };
+                    let last = i + 1 == count;
+                    items.push(self.gen_until_item(attr, id, i, last, &item_ctx, stream)?);
+                }
+                Some(Value::Array(items))
+            }
+        };
+        if let (Some(_), Some(Value::Array(items))) = (attr.repeat, &value) {
+            self.cover(format!("repeat {}: {}", id, items.len()));
+        }
+        trace::prefix(&mut stream.trace[first_span..], id);
+        stream.mark();
+        if (stream.eos && !was_eos) || attr.eos_error == Some(false) {
+            stream.tolerant_from = Some(stream.tolerant_from.map_or(start, |t| t.min(start)));
+        }
+        if let Some(value) = value {
+            env.set(id, value.clone());
+            values.insert(id.to_string(), value);
+        }
+        Ok(())
+    }
+
+    fn checkpoint(&self, stream: &Stream, fields: &Fields) -> Checkpoint {
+        Checkpoint {
+            stream: stream.clone(),
+            fields: fields.clone(),
+            checks: self.checks,
+            coverage: self.coverage.clone(),
+        }
+    }
+
+    fn restore(&mut self, checkpoint: Checkpoint, stream: &mut Stream, fields: &mut Fields) {
+        *stream = checkpoint.stream;
+        *fields = checkpoint.fields;
+        self.checks = checkpoint.checks;
+        self.coverage = checkpoint.coverage;
+    }
+
+    fn defaults(
+        &mut self,
+        ty: &TypeSpec,
+        inherited: Option<&Defaults>,
+        env: &Env,
+    ) -> Result<Defaults, DataGenError> {
+        let defaults = type_defaults(ty, inherited, env)?;
+        if let Some(meta) = &ty.meta {
+            if let (Some(MetaEndian::Switch { .. }), Some(endian)) = (&meta.endian, defaults.endian)
+            {
+                let id = meta.id.clone().unwrap_or_default();
+                self.cover(format!("endian {}: {:?}", id, endian));
+            }
+        }
+        Ok(defaults)
+    }
+
+    /// Length of byte arrays and strings of a free length, shrinking with backtracks.
+    fn max_len(&self) -> usize {
+        self.options
+            .max_len
+            .checked_shr(self.shrink as u32)
+            .unwrap_or(0)
+    }
+
+    /// Number of items of repetitions of a free count, shrinking with backtracks.
+    fn max_items(&self) -> usize {
+        self.options
+            .max_items
+            .checked_shr(self.shrink as u32)
+            .unwrap_or(0)
+    }
+
+    /// Number of items of an attribute repeated until the end of the stream or until a
+    /// condition, at least `min`.
+    fn item_count(&mut self, min: usize) -> usize {
+        let min = self.options.min_items.max(min);
+        if self.biased(self.options.edges.empty_arrays) {
+            return min;
+        }
+        self.rng.gen_range(min..=self.max_items().max(min))
+    }
+
+    /// Item of a `repeat: until` attribute for which its condition holds only if it's the
+    /// `last` one. The condition is added to the `valid` checks of the item, which takes care of
+    /// simple types; others are sampled until one fits.
+    fn gen_until_item(
+        &mut self,
+        attr: &Attribute,
+        id: &str,
+        index: usize,
+        last: bool,
+        ctx: &Ctx<'_, 'a>,
+        stream: &mut Stream,
+    ) -> Result<Value, DataGenError> {
+        let cond = attr
+            .repeat_until
+            .as_ref()
+            .expect("`repeat: until` attributes must have a condition");
+        let check = if last {
+            cond.clone()
+        } else {
+            Expr::UnaryOp {
+                op: UnaryOp::Not,
+                value: Box::new(cond.clone()),
+            }
+        };
+        let item_attr = Attribute {
+            valid: Some(with_check(attr.valid.as_ref(), check)),
+            ..attr.clone()
+        };
+        for _ in 0..ATTEMPTS {
+            let mut attempt = stream.clone();
+            let coverage = self.coverage.clone();
+            let in_until = std::mem::replace(&mut self.in_until, true);
+            let value = self.gen_item(&item_attr, id, index, ctx, &mut attempt);
+            self.in_until = in_until;
+            let value = value?;
+            let mut env = ctx.env.clone();
+            env.set("_", value.clone());
+            let ends = match eval(cond, &env) {
+                // the runtimes throw too, no item can end up there
+                Err(error) if error.is_runtime() => !last,
+                _ => eval_bool(cond, &env)?,
+            };
+            if ends == last {
+                *stream = attempt;
+                return Ok(value);
+            }
+            self.coverage = coverage;
+        }
+        Err(DataGenError::Unsatisfiable(id.to_string()))
+    }
+
+    fn gen_item(
+        &mut self,
+        attr: &Attribute,
+        id: &str,
+        index: usize,
+        ctx: &Ctx<'_, 'a>,
+        stream: &mut Stream,
+    ) -> Result<Value, DataGenError> {
+        let (first_span, start_byte) = (stream.trace.len(), stream.writer.bit_len() / 8);
+        let value = self.gen_attr(attr, id, ctx, stream)?.ok_or_else(|| {
+            DataGenError::Unsupported(format!("repeated switch `{}` without a matching case", id))
+        })?;
+        self.record(start_byte, &value, stream);
+        trace::prefix(&mut stream.trace[first_span..], &format!("[{}]", index));
+        stream.mark();
+        Ok(value)
+    }
+
+    /// Records the bytes from `start` as the ones of the value, unless it's a struct (whose
+    /// fields record their own).
+    fn record(&self, start: usize, value: &Value, stream: &mut Stream) {
+        if self.options.trace && !matches!(value, Value::Struct(_)) {
+            stream.trace.push(TraceSpan {
+                bytes: start..stream.writer.len(),
+                field: String::new(),
+                value: value.clone(),
+            });
+        }
+    }
+
+    /// Value of one item of the attribute, `None` if it's a switch matching no case.
+    fn gen_attr(
+        &mut self,
+        attr: &Attribute,
+        id: &str,
+        ctx: &Ctx<'_, 'a>,
+        stream: &mut Stream,
+    ) -> Result<Option<Value>, DataGenError> {
+        if let Some(contents) = &attr.contents {
+            let mut bytes = contents.to_bytes();
+            if !bytes.is_empty() && self.violating() {
+                let at = self.rng.gen_range(0..bytes.len());
+                bytes[at] ^= self.rng.gen_range(1..=u8::MAX);
+            }
+            stream.check(id, bytes.len())?;
+            stream.writer.write_bytes(&bytes);
+            return Ok(Some(Value::Bytes(bytes)));
+        }
+        if let Some(process) = &attr.process {
+            return self.gen_processed(attr, id, process, ctx, stream);
+        }
+        let type_name = match &attr.type_ref {
+            None => {
+                return self
+                    .gen_sized(attr, id, None, attr.terminator, ctx, stream)
+                    .map(Some)
+            }
+            Some(TypeRef::Named(type_name)) => type_name,
+            Some(TypeRef::Switch { switch_on, cases }) => {
+                let selector = eval_expr(switch_on, ctx.env)?;
+                let mut chosen = None;
+                for (key, type_name) in cases {
+                    if *key == Expr::Name(DEFAULT_CASE.to_string()) {
+                        chosen = chosen.or(Some(type_name));
+                    } else if eval_expr(key, ctx.env)? == selector {
+                        chosen = Some(type_name);
+                        break;
+                    }
+                }
+                let case = chosen.map_or("none", String::as_str);
+                self.cover(format!("switch {}: {}", id, case));
+                match chosen {
+                    Some(type_name) => type_name,
+                    None => return Ok(None),
+                }
+            }
+        };
+        self.gen_typed(attr, id, type_name, ctx, stream).map(Some)
+    }
+
+    /// Attribute with a `process`: its payload is generated like the attribute without the
+    /// process, then written through the inverse process.
+    fn gen_processed(
+        &mut self,
+        attr: &Attribute,
+        id: &str,
+        process: &str,
+        ctx: &Ctx<'_, 'a>,
+        stream: &mut Stream,
+    ) -> Result<Option<Value>, DataGenError> {
+        if attr.terminator.is_some() || attr.pad_right.is_some() {
+            return Err(DataGenError::Unsupported(format!(
+                "`process` of `{}` with a terminator or padding",
+                id
+            )));
+        }
+        let process = Process::parse(process, ctx.env)?;
+        let len = match &attr.size {
+            Some(size) => Some(eval_size(size, ctx.env)?),
+            None if attr.size_eos == Some(true) => stream.remaining(),
+            None => {
+                return Err(DataGenError::Unsupported(format!(
+                    "`process` of `{}` without `size` or `size-eos`",
+                    id
+                )))
+            }
+        };
+        let payload_len = match len {
+            Some(len) => Some(
+                process
+                    .payload_len(len)
+                    .ok_or_else(|| DataGenError::Unsatisfiable(id.to_string()))?,
+            ),
+            None => None,
+        };
+        let payload_attr = Attribute {
+            process: None,
+            size: payload_len.map(|len| Expr::Int(len as u64)),
+            size_eos: payload_len.is_none().then_some(true),
+            ..attr.clone()
+        };
+        let mut payload = stream.substream(None);
+        let Some(value) = self.gen_attr(&payload_attr, id, ctx, &mut payload)? else {
+            return Ok(None);
+        };
+        let raw = process.unapply(&payload.writer.into_bytes(), len.is_some());
+        self.write_sized(attr, id, &raw, stream)?;
+        Ok(Some(value))
+    }
+
+    fn gen_typed(
+        &mut self,
+        attr: &Attribute,
+        id: &str,
+        type_name: &str,
+        ctx: &Ctx<'_, 'a>,
+        stream: &mut Stream,
+    ) -> Result<Value, DataGenError> {
+        if type_name.contains('(') {
+            return Err(DataGenError::Unsupported(format!(
+                "parametric type `{}`",
+                type_name
+            )));
+        }
+        let (base, suffix) = match type_name.strip_suffix("le") {
+            Some(base) => (base, Some(Endian::Le)),
+            None => match type_name.strip_suffix("be") {
+                Some(base) => (base, Some(Endian::Be)),
+                None => (type_name, None),
+            },
+        };
+        let endian = || {
+            suffix
+                .or(ctx.defaults.endian)
+                .ok_or_else(|| DataGenError::MissingEndian(type_name.to_string()))
+        };
+
+        if let Some(int_type) = IntType::from_name(base) {
+            let endian = if int_type.width > 1 {
+                endian()?
+            } else {
+                Endian::Le
+            };
+            let (min, max) = (int_type.min_value(), int_type.max_value());
+            let value = self.choose_int(attr, id, min, max, false, ctx)?;
+            stream.check(id, usize::from(int_type.width))?;
+            stream
+                .writer
+                .write_int(value, usize::from(int_type.width), endian);
+            return Ok(int_to_value(attr, value, false));
+        }
+        match base {
+            "f4" | "f8" => {
+                let endian = endian()?;
+                let width = if base == "f4" { 4 } else { 8 };
+                stream.check(id, width)?;
+                let edges = float_edges(width, self.options.edges.nan);
+                let bias = self.options.edges.floats.clamp(0.0, 1.0);
+                let sample = |rng: &mut R| {
+                    if rng.gen_bool(bias) {
+                        Value::Float(*edges.choose(rng).unwrap())
+                    } else if width == 4 {
+                        Value::Float(rng.gen_range(-1e6f32..1e6).into())
+                    } else {
+                        Value::Float(rng.gen_range(-1e12f64..1e12))
+                    }
+                };
+                let value = match &attr.valid {
+                    None => sample(self.rng),
+                    Some(valid) => {
+                        let valid = self.valid_for(valid);
+                        // the value must survive the round trip through `f4`
+                        let accept = |value: &Value| matches!(value, Value::Float(x) if width == 8 || widen_f4(narrow_f4(*x)).to_bits() == x.to_bits());
+                        self.solve(id, &valid, ctx, accept, sample)?
+                    }
+                };
+                let Value::Float(value) = value else {
+                    return Err(DataGenError::Unsatisfiable(id.to_string()));
+                };
+                let mut bytes = if width == 4 {
+                    narrow_f4(value).to_le_bytes().to_vec()
+                } else {
+                    value.to_le_bytes().to_vec()
+                };
+                if endian == Endian::Be {
+                    bytes.reverse();
+                }
+                stream.writer.write_bytes(&bytes);
+                return Ok(Value::Float(value));
+            }
+            "str" | "strz" => {
+                let encoding = attr
+                    .encoding
+                    .as_ref()
+                    .or(ctx.defaults.encoding.as_ref())
+                    .ok_or_else(|| {
+                        DataGenError::Unsupported(format!("`{}` without an encoding", id))
+                    })?;
+                let encoding = Encoding::from_name(encoding)
+                    .ok_or_else(|| DataGenError::Unsupported(format!("encoding `{}`", encoding)))?;
+                let terminator = match base {
+                    "strz" => attr.terminator.or(Some(0)),
+                    _ => attr.terminator,
+                };
+                if terminator.is_some() && !encoding.supports_byte_terminator() {
+                    return Err(DataGenError::Unsupported(format!(
+                        "terminator of `{}` in {}",
+                        id,
+                        encoding.name()
+                    )));
+                }
+                return self.gen_sized(attr, id, Some(encoding), terminator, ctx, stream);
+            }
+            _ => {}
+        }
+        if let Some(bits) = type_name
+            .strip_prefix('b')
+            .and_then(|bits| bits.parse::<u32>().ok())
+            .filter(|bits| (1..=64).contains(bits))
+        {
+            let max = if bits == 64 {
+                u64::MAX.into()
+            } else {
+                (1i128 << bits) - 1
+            };
+            let is_bool = bits == 1 && attr.enum_name.is_none();
+            let value = self.choose_int(attr, id, 0, max, is_bool, ctx)?;
+            let bytes_needed = (stream.writer.bit_len() + bits as usize).div_ceil(8);
+            stream.check(id, bytes_needed - stream.writer.len())?;
+            let order = ctx.defaults.bit_endian.unwrap_or(Endian::Be);
+            stream.writer.write_bits(value as u64, bits, order);
+            return Ok(int_to_value(attr, value, is_bool));
+        }
+        self.gen_user_type(attr, id, type_name, ctx, stream)
+    }
+
+    fn gen_user_type(
+        &mut self,
+        attr: &Attribute,
+        id: &str,
+        type_name: &str,
+        ctx: &Ctx<'_, 'a>,
+        stream: &mut Stream,
+    ) -> Result<Value, DataGenError> {
+        let scopes = resolve_type(ctx.scopes, type_name)
+            .ok_or_else(|| DataGenError::UnknownType(type_name.to_string()))?;
+        if self.nesting == MAX_NESTING {
+            return Err(DataGenError::TooDeep(id.to_string()));
+        }
+        self.nesting += 1;
+        let value = self.gen_nested(attr, id, scopes, ctx, stream);
+        self.nesting -= 1;
+        value
+    }
+
+    fn gen_nested(
+        &mut self,
+        attr: &Attribute,
+        id: &str,
+        scopes: Vec<&'a TypeSpec>,
+        ctx: &Ctx<'_, 'a>,
+        stream: &mut Stream,
+    ) -> Result<Value, DataGenError> {
+        let mut parents = ctx.parents.to_vec();
+        parents.push(Value::Struct(ctx.values.clone()));
+        if attr.terminator.is_some() || attr.pad_right.is_some() {
+            return Err(DataGenError::Unsupported(format!(
+                "substream of `{}` with a terminator or padding",
+                id
+            )));
+        }
+        if attr.size.is_none() && attr.size_eos != Some(true) {
+            let values = self.gen_type(scopes, &parents, Some(ctx.defaults), stream)?;
+            return Ok(Value::Struct(values));
+        }
+
+        let limit = match &attr.size {
+            Some(size) => Some(eval_size(size, ctx.env)?),
+            None => stream.remaining(),
+        };
+        let mut sub = stream.substream(limit);
+        let values = self.gen_type(scopes, &parents, Some(ctx.defaults), &mut sub)?;
+        if let Some(remaining) = sub.remaining().filter(|_| !sub.eos) {
+            // bytes of the substream that the type doesn't read
+            let unread: Vec<u8> = (0..remaining).map(|_| self.rng.gen()).collect();
+            sub.writer.write_bytes(&unread);
+        }
+        let bytes = sub.writer.into_bytes();
+        stream.check(id, bytes.len())?;
+        let offset = stream.writer.len();
+        stream
+            .trace
+            .extend(sub.trace.into_iter().map(|span| TraceSpan {
+                bytes: span.bytes.start + offset..span.bytes.end + offset,
+                ..span
+            }));
+        stream.writer.write_bytes(&bytes);
+        if attr.size.is_none() {
+            stream.eos = true;
+        }
+        Ok(Value::Struct(values))
+    }
+
+    fn cover(&mut self, branch: String) {
+        self.coverage.insert(branch);
+    }
+
+    /// Counts a check, and whether it's the one to violate.
+    fn violating(&mut self) -> bool {
+        if self.in_until {
+            return false;
+        }
+        let violating = self.violate == Some(self.checks);
+        self.checks += 1;
+        self.stopped |= violating;
+        violating
+    }
+
+    /// Checks that the value of an attribute with `valid` must pass: `valid` itself, or its
+    /// negation if it's the check to violate.
+    fn valid_for<'v>(&mut self, valid: &'v Valid) -> Cow<'v, Valid> {
+        if self.violating() {
+            Cow::Owned(negated(valid))
+        } else {
+            Cow::Borrowed(valid)
+        }
+    }
+
+    /// Whether to go for an edge value, with the probability `bias`.
+    fn biased(&mut self, bias: f64) -> bool {
+        self.rng.gen_bool(bias.clamp(0.0, 1.0))
+    }
+
+    /// Random integer in the range, small if it may be a size or a count, a member of the enum
+    /// of the attribute most of the time, and an edge value some of the time.
+    fn int_value(
+        &mut self,
+        attr: &Attribute,
+        id: &str,
+        min: i128,
+        max: i128,
+        ctx: &Ctx<'_, 'a>,
+    ) -> Result<i128, DataGenError> {
+        if let Some(enum_name) = &attr.enum_name {
+            let members = resolve_enum(ctx.scopes, enum_name)
+                .ok_or_else(|| DataGenError::UnknownEnum(enum_name.clone()))?;
+            let members: Vec<i128> = members
+                .filter(|value| (min..=max).contains(value))
+                .collect();
+            if let Some(value) = members.choose(self.rng).filter(|_| self.rng.gen_bool(0.75)) {
+                return Ok(*value);
+            }
+        }
+        let (min, max) = if self.length_names.contains(id) {
+            let limit = self.max_len().max(self.max_items()) as i128;
+            (min.max(0), max.min(limit))
+        } else {
+            (min, max)
+        };
+        if self.biased(self.options.edges.ints) {
+            if let Some(edge) = int_edges(min, max).choose(self.rng) {
+                return Ok(*edge);
+            }
+        }
+        Ok(self.rng.gen_range(min..=max))
+    }
+
+    /// Integer in the range for the attribute, passing its `valid` checks if it has any.
+    fn choose_int(
+        &mut self,
+        attr: &Attribute,
+        id: &str,
+        min: i128,
+        max: i128,
+        is_bool: bool,
+        ctx: &Ctx<'_, 'a>,
+    ) -> Result<i128, DataGenError> {
+        if let Some(enum_name) = &attr.enum_name {
+            if resolve_enum(ctx.scopes, enum_name).is_none() {
+                return Err(DataGenError::UnknownEnum(enum_name.clone()));
+            }
+        }
+        let Some(valid) = &attr.valid else {
+            return self.int_value(attr, id, min, max, ctx);
+        };
+        let valid = self.valid_for(valid);
+        let to_value = |value| int_to_value(attr, value, is_bool);
+        solve_int(self.rng, &valid, ctx.env, min, max, to_value)
+            .map_err(|error| DataGenError::Valid {
+                id: id.to_string(),
+                error,
+            })?
+            .ok_or_else(|| DataGenError::Unsatisfiable(id.to_string()))
+    }
+
+    fn solve<A, S>(
+        &mut self,
+        id: &str,
+        valid: &Valid,
+        ctx: &Ctx<'_, 'a>,
+        accept: A,
+        sample: S,
+    ) -> Result<Value, DataGenError>
+    where
+        A: Fn(&Value) -> bool,
+        S: FnMut(&mut R) -> Value,
+    {
+        solve_value(self.rng, valid, ctx.env, accept, sample)
+            .map_err(|error| DataGenError::Valid {
+                id: id.to_string(),
+                error,
+            })?
+            .ok_or_else(|| DataGenError::Unsatisfiable(id.to_string()))
+    }
+
+    /// Byte array (without an encoding) or string with a `size` or `size-eos`, or ending at a
+    /// terminator.
+    fn gen_sized(
+        &mut self,
+        attr: &Attribute,
+        id: &str,
+        encoding: Option<Encoding>,
+        terminator: Option<u8>,
+        ctx: &Ctx<'_, 'a>,
+        stream: &mut Stream,
+    ) -> Result<Value, DataGenError> {
+        let ending = Ending {
+            terminator,
+            include: attr.include == Some(true),
+            pad: attr.pad_right,
+        };
+        if terminator.is_some() && attr.consume == Some(false) {
+            return Err(DataGenError::Unsupported("`consume: false`".into()));
+        }
+        if encoding.is_some() && ending.include && terminator.is_some_and(|t| !t.is_ascii()) {
+            return Err(DataGenError::Unsupported(format!(
+                "non-ASCII terminator included in `{}`",
+                id
+            )));
+        }
+        // `None` if the bytes only end at the terminator, or at the end of the data
+        let size = match &attr.size {
+            Some(size) => Some(eval_size(size, ctx.env)?),
+            None if attr.size_eos == Some(true) => stream.remaining(),
+            None if terminator.is_some() => None,
+            None => {
+                return Err(DataGenError::Unsupported(format!(
+                    "`{}` without `size`, `size-eos` or `terminator`",
+                    id
+                )))
+            }
+        };
+        if size.is_none() && attr.pad_right.is_some() {
+            return Err(DataGenError::Unsupported(format!(
+                "`pad-right` of `{}` without `size`",
+                id
+            )));
+        }
+
+        // characters of strings with their encoded lengths, none containing a special byte
+        let chars: Vec<(char, usize)> = encoding.map_or_else(Vec::new, |encoding| {
+            let boundary = encoding.boundary_chars().iter().copied();
+            encoding
+                .alphabet()
+                .into_iter()
+                .chain(boundary)
+                .filter_map(|c| {
+                    let bytes = encoding.encode(&c.to_string())?;
+                    let plain = !bytes.iter().any(|b| ending.is_special(*b));
+                    plain.then_some((c, bytes.len()))
+                })
+                .collect()
+        });
+        let max_len = self
+            .max_len()
+            .min(stream.budget_left().unwrap_or(usize::MAX));
+        let empty_bias = self.options.edges.empty_strings.clamp(0.0, 1.0);
+        let sample = |rng: &mut R| {
+            let len = match size {
+                Some(size) if terminator.is_none() && ending.pad.is_none() => size,
+                _ if rng.gen_bool(empty_bias) => 0,
+                Some(size) => rng.gen_range(0..=size),
+                None => rng.gen_range(0..=max_len),
+            };
+            let terminated = size.is_none_or(|size| len < size);
+            let included = terminator.filter(|_| ending.include && terminated);
+            if encoding.is_none() {
+                let mut bytes: Vec<u8> = (0..len)
+                    .map(|_| loop {
+                        let byte = rng.gen();
+                        if !ending.is_special(byte) {
+                            break byte;
+                        }
+                    })
+                    .collect();
+                bytes.extend(included);
+                return Value::Bytes(bytes);
+            }
+            // characters fitting into the rest of the length, which falls short if none does
+            let mut s = String::new();
+            let mut s_len = 0;
+            loop {
+                let fitting: Vec<&(char, usize)> =
+                    chars.iter().filter(|(_, n)| s_len + n <= len).collect();
+                let Some((c, n)) = fitting.choose(rng) else {
+                    break;
+                };
+                s.push(*c);
+                s_len += n;
+            }
+            s.extend(included.map(char::from));
+            Value::Str(s)
+        };
+        let value = match &attr.valid {
+            None => sample(self.rng),
+            Some(valid) => {
+                let valid = self.valid_for(valid);
+                let accept = |value: &Value| {
+                    encode(value, encoding)
+                        .is_some_and(|bytes| ending.layout(&bytes, size).is_some())
+                };
+                self.solve(id, &valid, ctx, accept, sample)?
+            }
+        };
+        let bytes = encode(&value, encoding).expect("values must be encodable");
+        // e.g. an odd size in UTF-16
+        let (mut raw, fill) = ending
+            .layout(&bytes, size)
+            .ok_or_else(|| DataGenError::Unsatisfiable(id.to_string()))?;
+        for _ in 0..fill {
+            raw.push(ending.pad.unwrap_or_else(|| self.rng.gen()));
+        }
+        self.write_sized(attr, id, &raw, stream)?;
+        Ok(value)
+    }
+
+    /// Writes the bytes of a byte array or string of the attribute.
+    fn write_sized(
+        &mut self,
+        attr: &Attribute,
+        id: &str,
+        bytes: &[u8],
+        stream: &mut Stream,
+    ) -> Result<(), DataGenError> {
+        stream.check(id, bytes.len())?;
+        stream.writer.write_bytes(bytes);
+        if attr.size_eos == Some(true) {
+            stream.eos = true;
+        }
+        Ok(())
+    }
+}
+
+/// How the value of a byte array or string ends within the bytes read for it: at the
+/// terminator, or before the padding at the end.
+#[derive(Clone, Copy, Debug)]
+struct Ending {
+    terminator: Option<u8>,
+    include: bool,
+    pad: Option<u8>,
+}
+
+impl Ending {
+    /// Whether the byte would end the value if it were part of it.
+    fn is_special(&self, byte: u8) -> bool {
+        self.terminator == Some(byte) || self.pad == Some(byte)
+    }
+
+    /// Value read from `bytes`, with the padding stripped and cut at the terminator, like the
+    /// runtimes do.
+    fn parse<'b>(&self, bytes: &'b [u8]) -> &'b [u8] {
+        let len = match self.pad {
+            Some(pad) => bytes.iter().rposition(|b| *b != pad).map_or(0, |i| i + 1),
+            None => bytes.len(),
+        };
+        let stripped = &bytes[..len];
+        match self
+            .terminator
+            .and_then(|t| stripped.iter().position(|b| *b == t))
+        {
+            Some(i) if self.include => &stripped[..=i],
+            Some(i) => &stripped[..i],
+            None => stripped,
+        }
+    }
+
+    /// Bytes to write for `value` followed by the number of fill bytes (padding, or anything
+    /// after a terminator) up to `size`, `None` if reading them back doesn't give `value`.
+    fn layout(&self, value: &[u8], size: Option<usize>) -> Option<(Vec<u8>, usize)> {
+        let mut raw = value.to_vec();
+        if let Some(t) = self.terminator {
+            let terminated = self.include && value.last() == Some(&t);
+            if !terminated && size.is_none_or(|size| raw.len() < size) {
+                raw.push(t);
+            }
+        }
+        let fill = match size {
+            Some(size) => size.checked_sub(raw.len())?,
+            None => 0,
+        };
+        let mut read = raw.clone();
+        read.resize(raw.len() + fill, self.pad.unwrap_or(0));
+        (self.parse(&read) == value).then_some((raw, fill))
+    }
+}
+
+/// Byte order and encoding in effect in `ty`, with the `meta/endian` switch evaluated in `env`.
+fn type_defaults(
+    ty: &TypeSpec,
+    inherited: Option<&Defaults>,
+    env: &Env,
+) -> Result<Defaults, DataGenError> {
+    let mut defaults = inherited.cloned().unwrap_or_default();
+    let Some(meta) = &ty.meta else {
+        return Ok(defaults);
+    };
+    match &meta.endian {
+        Some(MetaEndian::Fixed(endian)) => defaults.endian = Some(*endian),
+        Some(MetaEndian::Switch { switch_on, cases }) => {
+            let selector = eval_expr(switch_on, env)?;
+            let mut endian = None;
+            for (key, case_endian) in cases {
+                if *key == Expr::Name(DEFAULT_CASE.to_string()) {
+                    endian = endian.or(Some(*case_endian));
+                } else if eval_expr(key, env)? == selector {
+                    endian = Some(*case_endian);
+                    break;
+                }
+            }
+            let id = meta.id.clone().unwrap_or_default();
+            defaults.endian = Some(endian.ok_or(DataGenError::UndecidedEndianness(id))?);
+        }
+        None => {}
+    }
+    if meta.bit_endian.is_some() {
+        defaults.bit_endian = meta.bit_endian;
+    }
+    if meta.encoding.is_some() {
+        defaults.encoding = meta.encoding.clone();
+    }
+    Ok(defaults)
+}
+
+/// Value of an integer attribute, which is an enum if the attribute has one.
+fn int_to_value(attr: &Attribute, value: i128, is_bool: bool) -> Value {
+    match &attr.enum_name {
+        Some(enum_name) => Value::Enum {
+            enum_path: enum_name.split("::").map(String::from).collect(),
+            value,
+        },
+        None if is_bool => Value::Bool(value == 1),
+        None => Value::Int(value),
+    }
+}
+
+/// Bytes of a byte array, or of a string in the encoding, `None` if the value isn't one or
+/// can't be encoded.
+fn encode(value: &Value, encoding: Option<Encoding>) -> Option<Vec<u8>> {
+    match (value, encoding) {
+        (Value::Bytes(bytes), None) => Some(bytes.clone()),
+        (Value::Str(s), Some(encoding)) => encoding.encode(s),
+        _ => None,
+    }
+}
+
+/// Checks that exactly the values failing `valid` pass.
+fn negated(valid: &Valid) -> Valid {
+    let cmp = |op, r: &Expr| Expr::BinaryOp {
+        l: Box::new(Expr::Name("_".to_string())),
+        op,
+        r: Box::new(r.clone()),
+    };
+    let join = |op| {
+        move |l: Expr, r: Expr| Expr::BinaryOp {
+            l: Box::new(l),
+            op,
+            r: Box::new(r),
+        }
+    };
+    let mut conds = Vec::new();
+    match valid {
+        Valid::Eq(eq) => conds.push(cmp(BinaryOp::Eq, eq)),
+        Valid::Checks(checks) => {
+            conds.extend(checks.eq.iter().map(|eq| cmp(BinaryOp::Eq, eq)));
+            conds.extend(checks.min.iter().map(|min| cmp(BinaryOp::Ge, min)));
+            conds.extend(checks.max.iter().map(|max| cmp(BinaryOp::Le, max)));
+            let any_of = checks.any_of.iter().map(|value| cmp(BinaryOp::Eq, value));
+            conds.extend(any_of.reduce(join(BinaryOp::Or)));
+            conds.extend(checks.expr.clone());
+        }
+    }
+    let all = conds
+        .into_iter()
+        .reduce(join(BinaryOp::And))
+        .unwrap_or(Expr::Bool(true));
+    Valid::Checks(ValidChecks {
+        expr: Some(Expr::UnaryOp {
+            op: UnaryOp::Not,
+            value: Box::new(all),
+        }),
+        ..Default::default()
+    })
+}
+
+/// `valid` checks with `expr` added to them.
+fn with_check(valid: Option<&Valid>, expr: Expr) -> Valid {
+    let mut checks = match valid {
+        None => ValidChecks::default(),
+        Some(Valid::Eq(eq)) => ValidChecks {
+            eq: Some(eq.clone()),
+            ..Default::default()
+        },
+        Some(Valid::Checks(checks)) => checks.clone(),
+    };
+    checks.expr = Some(match checks.expr.take() {
+        Some(prev) => Expr::BinaryOp {
+            l: Box::new(prev),
+            op: BinaryOp::And,
+            r: Box::new(expr),
+        },
+        None => expr,
+    });
+    Valid::Checks(checks)
+}
+
+/// Values of the attributes of a type generated so far, and the environment that the
+/// expressions in the type see.
+#[derive(Clone)]
+struct Fields {
+    values: BTreeMap<String, Value>,
+    env: Env,
+}
+
+/// State of the generator before an attribute.
+#[derive(Clone)]
+struct Checkpoint {
+    stream: Stream,
+    fields: Fields,
+    checks: usize,
+    coverage: BTreeSet<String>,
+}
+
+/// What an attribute of a type being generated can refer to.
+#[derive(Clone, Copy)]
+struct Ctx<'c, 'a> {
+    scopes: &'c [&'a TypeSpec],
+    parents: &'c [Value],
+    values: &'c BTreeMap<String, Value>,
+    env: &'c Env,
+    defaults: &'c Defaults,
+}
+
+fn check_supported(attr: &Attribute) -> Result<(), DataGenError> {
+    let unsupported = [("pos", attr.pos.is_some()), ("io", attr.io.is_some())];
+    match unsupported.into_iter().find(|(_, present)| *present) {
+        Some((key, _)) => Err(DataGenError::Unsupported(format!("`{}`", key))),
+        None => Ok(()),
+    }
+}
+
+/// Scopes of the type named `type_name` as seen from the last of `scopes`: the types it's
+/// looked up in from the innermost to the outermost, then the types it's nested in.
+fn resolve_type<'a>(scopes: &[&'a TypeSpec], type_name: &str) -> Option<Vec<&'a TypeSpec>> {
+    let path: Vec<&str> = type_name.split("::").collect();
+    (0..scopes.len()).rev().find_map(|i| {
+        let mut resolved = scopes[..=i].to_vec();
+        for name in &path {
+            let ty = resolved.last()?.types.get(*name)?;
+            resolved.push(ty);
+        }
+        Some(resolved)
+    })
+}
+
+/// Values of the members of the enum named `enum_name` as seen from the last of `scopes`.
+fn resolve_enum<'a>(
+    scopes: &[&'a TypeSpec],
+    enum_name: &str,
+) -> Option<impl Iterator<Item = i128> + 'a> {
+    let (type_path, name) = match enum_name.rsplit_once("::") {
+        Some((type_path, name)) => (Some(type_path), name),
+        None => (None, enum_name),
+    };
+    let owner = match type_path {
+        Some(type_path) => *resolve_type(scopes, type_path)?.last()?,
+        None => scopes.iter().rev().find(|ty| ty.enums.contains_key(name))?,
+    };
+    Some(owner.enums.get(name)?.keys().copied())
+}
+
+fn eval_expr(expr: &Expr, env: &Env) -> Result<Value, DataGenError> {
+    eval(expr, env).map_err(|error| DataGenError::Eval {
+        expr: translate(expr),
+        error,
+    })
+}
+
+fn eval_bool(expr: &Expr, env: &Env) -> Result<bool, DataGenError> {
+    match eval_expr(expr, env)? {
+        Value::Bool(b) => Ok(b),
+        value => Err(DataGenError::UnexpectedValue {
+            expr: translate(expr),
+            expected: "boolean",
+            found: value.type_name(),
+        }),
+    }
+}
+
+fn eval_size(expr: &Expr, env: &Env) -> Result<usize, DataGenError> {
+    match eval_expr(expr, env)? {
+        Value::Int(n) if (0..=MAX_SIZE).contains(&n) => Ok(n as usize),
+        Value::Int(n) => Err(DataGenError::SizeOutOfRange(n)),
+        value => Err(DataGenError::UnexpectedValue {
+            expr: translate(expr),
+            expected: "integer",
+            found: value.type_name(),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gen::pad::strip;
+    use crate::gen::primitive::{primitive_spec, Primitive};
+    use crate::gen::process::{apply, ProcessKind};
+    use crate::gen::switch::{switch_case, SwitchOn};
+    use crate::gen::valid::{is_valid, valid_case, ValidForm};
+    use crate::ksy::Contents;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use std::ops::Range;
+
+    fn sized(id: &str, type_name: Option<&str>, size: Expr) -> Attribute {
+        Attribute {
+            id: Some(id.to_string()),
+            type_ref: type_name.map(|name| TypeRef::Named(name.to_string())),
+            size: Some(size),
+            ..Default::default()
+        }
+    }
+
+    fn name(name: &str) -> Expr {
+        Expr::Name(name.to_string())
+    }
+
+    #[test]
+    fn primitives() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for endian in [Endian::Le, Endian::Be] {
+            let spec = primitive_spec("primitives", endian);
+            let synthesized = synthesize(&mut rng, &spec, &DataOptions::default()).unwrap();
+            let mut pos = 0;
+            for primitive in Primitive::all() {
+                let size = primitive.kind.byte_size() as usize;
+                let mut bytes = synthesized.data[pos..pos + size].to_vec();
+                pos += size;
+                if primitive.effective_endian(endian) == Endian::Be {
+                    bytes.reverse();
+                }
+                let value = &synthesized.values[&primitive.attr_id()];
+                match value {
+                    Value::Int(value) => assert_eq!(bytes, value.to_le_bytes()[..size]),
+                    Value::Float(value) if size == 4 => {
+                        assert_eq!(bytes, (*value as f32).to_le_bytes())
+                    }
+                    Value::Float(value) => assert_eq!(bytes, value.to_le_bytes()),
+                    _ => panic!("{:?}", value),
+                }
+            }
+            assert_eq!(pos, synthesized.data.len());
+        }
+    }
+
+    #[test]
+    fn sizes_and_substreams() {
+        let mut spec = KsySpec::top_level("sizes");
+        spec.meta.as_mut().unwrap().endian = Some(Endian::Le.into());
+        spec.seq = vec![
+            Attribute::new("len", "u4"),
+            sized("body", None, name("len")),
+            Attribute::new("n", "u1"),
+            Attribute {
+                repeat: Some(Repeat::Expr),
+                repeat_expr: Some(name("n")),
+                ..Attribute::new("items", "u2")
+            },
+            sized("hdr", Some("header"), Expr::Int(4)),
+            Attribute {
+                size_eos: Some(true),
+                encoding: Some("UTF-8".to_string()),
+                ..Attribute::new("rest", "str")
+            },
+        ];
+        spec.types.insert(
+            "header".to_string(),
+            TypeSpec {
+                seq: vec![
+                    Attribute::new("a", "u1"),
+                    Attribute {
+                        id: Some("tail".to_string()),
+                        size_eos: Some(true),
+                        ..Default::default()
+                    },
+                ],
+                ..Default::default()
+            },
+        );
+
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..20 {
+            let synthesized = synthesize(&mut rng, &spec, &DataOptions::default()).unwrap();
+            let data = &synthesized.data;
+            let values = &synthesized.values;
+            let len = usize::from(data[0]);
+            assert!(len <= 16);
+            assert_eq!(values["len"], Value::Int(len as i128));
+            assert_eq!(values["body"], Value::Bytes(data[4..4 + len].to_vec()));
+            let n = usize::from(data[4 + len]);
+            let Value::Array(items) = &values["items"] else {
+                panic!()
+            };
+            assert_eq!(items.len(), n);
+            let hdr_pos = 5 + len + 2 * n;
+            let assertions = synthesized.assertions();
+            let hdr_a = Expr::Attribute {
+                value: Box::new(name("hdr")),
+                attr_name: "a".to_string(),
+            };
+            let a = assertions.iter().find(|(expr, _)| *expr == hdr_a).unwrap();
+            assert_eq!(a.1, Value::Int(data[hdr_pos].into()));
+            let Value::Str(rest) = &values["rest"] else {
+                panic!()
+            };
+            assert_eq!(data.len(), hdr_pos + 4 + rest.len());
+        }
+    }
+
+    #[test]
+    fn switches() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for on in [SwitchOn::Int, SwitchOn::Enum] {
+            let spec = switch_case(&mut rng, "switch", on, false).spec;
+            let mut seen = [false, false];
+            for _ in 0..200 {
+                let synthesized = synthesize(&mut rng, &spec, &DataOptions::default()).unwrap();
+                let body = synthesized.values.get("body");
+                if let Some(body) = body {
+                    assert!(matches!(body, Value::Struct(fields) if fields.contains_key("value")));
+                }
+                seen[usize::from(body.is_some())] = true;
+            }
+            if on == SwitchOn::Enum {
+                assert_eq!(seen, [true, true]);
+            }
+        }
+    }
+
+    #[test]
+    fn bit_fields() {
+        let mut spec = KsySpec::top_level("bits");
+        spec.seq = vec![
+            Attribute::new("a", "b3"),
+            Attribute::new("b", "b1"),
+            Attribute::new("c", "b11"),
+            Attribute::new("d", "u1"),
+        ];
+        let mut rng = StdRng::seed_from_u64(0);
+        let synthesized = synthesize(&mut rng, &spec, &DataOptions::default()).unwrap();
+        let int = |name: &str| match synthesized.values[name] {
+            Value::Int(value) => value as u32,
+            Value::Bool(value) => value.into(),
+            _ => panic!(),
+        };
+        // 15 bits, aligned to 2 bytes before `d`
+        let packed = (int("a") << 12 | int("b") << 11 | int("c")) << 1;
+        let mut expected = (packed as u16).to_be_bytes().to_vec();
+        expected.push(int("d") as u8);
+        assert_eq!(synthesized.data, expected);
+
+        spec.meta.as_mut().unwrap().bit_endian = Some(Endian::Le);
+        let synthesized = synthesize(&mut rng, &spec, &DataOptions::default()).unwrap();
+        let int = |name: &str| match synthesized.values[name] {
+            Value::Int(value) => value as u32,
+            Value::Bool(value) => value.into(),
+            _ => panic!(),
+        };
+        // least significant bits first, `c` spanning both bytes
+        let packed = int("a") | int("b") << 3 | int("c") << 4;
+        let mut expected = (packed as u16).to_le_bytes().to_vec();
+        expected.push(int("d") as u8);
+        assert_eq!(synthesized.data, expected);
+    }
+
+    #[test]
+    fn terminators() {
+        let mut spec = KsySpec::top_level("terminators");
+        spec.meta.as_mut().unwrap().encoding = Some("ASCII".to_string());
+        spec.seq = vec![
+            Attribute::new("name", "strz"),
+            Attribute {
+                id: Some("tag".to_string()),
+                terminator: Some(b','),
+                include: Some(true),
+                ..Default::default()
+            },
+            Attribute {
+                pad_right: Some(b' '),
+                ..sized("padded", None, Expr::Int(6))
+            },
+            Attribute {
+                terminator: Some(b';'),
+                pad_right: Some(b'.'),
+                ..sized("both", Some("str"), Expr::Int(5))
+            },
+        ];
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..50 {
+            let synthesized = synthesize(&mut rng, &spec, &DataOptions::default()).unwrap();
+            let (data, values) = (&synthesized.data, &synthesized.values);
+            let name_end = data.iter().position(|b| *b == 0).unwrap();
+            let name = String::from_utf8(data[..name_end].to_vec()).unwrap();
+            assert_eq!(values["name"], Value::Str(name));
+            let tag_start = name_end + 1;
+            let tag_end = tag_start + data[tag_start..].iter().position(|b| *b == b',').unwrap();
+            assert_eq!(
+                values["tag"],
+                Value::Bytes(data[tag_start..=tag_end].to_vec())
+            );
+            let padded = &data[tag_end + 1..tag_end + 7];
+            assert_eq!(
+                values["padded"],
+                Value::Bytes(strip(padded, b' ', None, false))
+            );
+            let both = strip(&data[tag_end + 7..], b'.', Some(b';'), false);
+            assert_eq!(values["both"], Value::Str(String::from_utf8(both).unwrap()));
+            assert_eq!(data.len(), tag_end + 12);
+        }
+
+        spec.seq = vec![Attribute {
+            consume: Some(false),
+            ..Attribute::new("name", "strz")
+        }];
+        assert_eq!(
+            synthesize(&mut rng, &spec, &DataOptions::default()),
+            Err(DataGenError::Unsupported("`consume: false`".into()))
+        );
+    }
+
+    #[test]
+    fn encodings() {
+        let decode = |encoding: Encoding, bytes: &[u8]| -> String {
+            match encoding {
+                Encoding::Utf16Le | Encoding::Utf16Be => {
+                    let units: Vec<u16> = bytes
+                        .chunks(2)
+                        .map(|unit| match encoding {
+                            Encoding::Utf16Le => u16::from_le_bytes([unit[0], unit[1]]),
+                            _ => u16::from_be_bytes([unit[0], unit[1]]),
+                        })
+                        .collect();
+                    String::from_utf16(&units).unwrap()
+                }
+                Encoding::Iso8859_1 => bytes.iter().copied().map(char::from).collect(),
+                _ => encoding_rs::Encoding::for_label(encoding.name().as_bytes())
+                    .unwrap()
+                    .decode_without_bom_handling_and_without_replacement(bytes)
+                    .unwrap()
+                    .into_owned(),
+            }
+        };
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut astral = false;
+        for encoding in Encoding::ALL {
+            let mut spec = KsySpec::top_level("encodings");
+            spec.meta.as_mut().unwrap().encoding = Some(encoding.name().to_lowercase());
+            spec.seq = vec![sized("fixed", Some("str"), Expr::Int(12))];
+            if encoding.supports_byte_terminator() {
+                spec.seq.push(Attribute::new("z", "strz"));
+            }
+            for _ in 0..50 {
+                let synthesized = synthesize(&mut rng, &spec, &DataOptions::default()).unwrap();
+                let (data, values) = (&synthesized.data, &synthesized.values);
+                let fixed = decode(encoding, &data[..12]);
+                astral |= fixed.chars().any(|c| c.len_utf16() == 2);
+                assert_eq!(values["fixed"], Value::Str(fixed), "{:?}", encoding);
+                if encoding.supports_byte_terminator() {
+                    assert_eq!(data.last(), Some(&0));
+                    let z = decode(encoding, &data[12..data.len() - 1]);
+                    assert_eq!(values["z"], Value::Str(z), "{:?}", encoding);
+                }
+            }
+        }
+        assert!(astral);
+
+        let mut spec = KsySpec::top_level("odd");
+        spec.meta.as_mut().unwrap().encoding = Some("UTF-16LE".to_string());
+        spec.seq = vec![sized("odd", Some("str"), Expr::Int(3))];
+        assert_eq!(
+            synthesize(&mut rng, &spec, &DataOptions::default()),
+            Err(DataGenError::Unsatisfiable("odd".into()))
+        );
+    }
+
+    #[test]
+    fn repeat_until() {
+        let mut spec = KsySpec::top_level("until");
+        let is_ff = Expr::BinaryOp {
+            l: Box::new(name("_")),
+            op: BinaryOp::Eq,
+            r: Box::new(Expr::Int(0xff)),
+        };
+        let is_last = Expr::BinaryOp {
+            l: Box::new(Expr::Attribute {
+                value: Box::new(name("_")),
+                attr_name: "kind".to_string(),
+            }),
+            op: BinaryOp::Lt,
+            r: Box::new(Expr::Int(0x80)),
+        };
+        spec.seq = vec![
+            Attribute {
+                repeat: Some(Repeat::Until),
+                repeat_until: Some(is_ff),
+                ..Attribute::new("bytes", "u1")
+            },
+            Attribute {
+                repeat: Some(Repeat::Until),
+                repeat_until: Some(is_last),
+                ..Attribute::new("records", "record")
+            },
+        ];
+        spec.types.insert(
+            "record".to_string(),
+            TypeSpec {
+                seq: vec![Attribute::new("kind", "u1"), Attribute::new("x", "u1")],
+                ..Default::default()
+            },
+        );
+        let mut rng = StdRng::seed_from_u64(0);
+        let options = DataOptions {
+            min_items: 3,
+            max_items: 5,
+            ..Default::default()
+        };
+        for _ in 0..20 {
+            let synthesized = synthesize(&mut rng, &spec, &options).unwrap();
+            let data = &synthesized.data;
+            let Value::Array(bytes) = &synthesized.values["bytes"] else {
+                panic!()
+            };
+            assert!((3..=5).contains(&bytes.len()));
+            let n = data.iter().position(|b| *b == 0xff).unwrap() + 1;
+            assert_eq!(n, bytes.len());
+            let Value::Array(records) = &synthesized.values["records"] else {
+                panic!()
+            };
+            assert!((3..=5).contains(&records.len()));
+            assert_eq!(data.len(), n + 2 * records.len());
+            let kinds: Vec<u8> = data[n..].iter().step_by(2).copied().collect();
+            let (last, rest) = kinds.split_last().unwrap();
+            assert!(*last < 0x80 && rest.iter().all(|kind| *kind >= 0x80));
+        }
+    }
+
+    #[test]
+    fn processed() {
+        let processed = |attr: Attribute, process: &str| Attribute {
+            process: Some(process.to_string()),
+            ..attr
+        };
+        let mut spec = KsySpec::top_level("processed");
+        spec.meta.as_mut().unwrap().endian = Some(Endian::Le.into());
+        spec.seq = vec![
+            Attribute::new("key", "u1"),
+            processed(sized("xored", None, Expr::Int(8)), "xor(key)"),
+            processed(sized("rotated", None, Expr::Int(4)), "rol(3)"),
+            processed(sized("packed", Some("inner"), Expr::Int(20)), "zlib"),
+            Attribute {
+                size_eos: Some(true),
+                ..processed(Attribute::new("rest", "inner"), "zlib")
+            },
+        ];
+        spec.types.insert(
+            "inner".to_string(),
+            TypeSpec {
+                seq: vec![
+                    Attribute::new("a", "u4"),
+                    Attribute {
+                        id: Some("tail".to_string()),
+                        size_eos: Some(true),
+                        ..Default::default()
+                    },
+                ],
+                ..Default::default()
+            },
+        );
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..20 {
+            let synthesized = synthesize(&mut rng, &spec, &DataOptions::default()).unwrap();
+            let (data, values) = (&synthesized.data, &synthesized.values);
+            let key = [data[0]];
+            let xored = apply(ProcessKind::Xor, &key, &data[1..9]);
+            assert_eq!(values["xored"], Value::Bytes(xored));
+            let rotated = apply(ProcessKind::Rol, &[3], &data[9..13]);
+            assert_eq!(values["rotated"], Value::Bytes(rotated));
+            for (name, raw) in [("packed", &data[13..33]), ("rest", &data[33..])] {
+                let inner = apply(ProcessKind::Zlib, &[], raw);
+                let Value::Struct(fields) = &values[name] else {
+                    panic!()
+                };
+                let a = u32::from_le_bytes(inner[..4].try_into().unwrap());
+                assert_eq!(fields["a"], Value::Int(a.into()));
+                assert_eq!(fields["tail"], Value::Bytes(inner[4..].to_vec()));
+            }
+        }
+    }
+
+    #[test]
+    fn edge_values() {
+        let mut spec = KsySpec::top_level("edges");
+        spec.meta.as_mut().unwrap().endian = Some(Endian::Be.into());
+        spec.meta.as_mut().unwrap().encoding = Some("UTF-8".to_string());
+        spec.seq = vec![
+            Attribute::new("i", "s4"),
+            Attribute::new("f", "f4"),
+            Attribute::new("s", "strz"),
+            Attribute {
+                repeat: Some(Repeat::Eos),
+                ..Attribute::new("items", "u1")
+            },
+        ];
+        let options = DataOptions {
+            edges: EdgeBias {
+                ints: 1.0,
+                floats: 1.0,
+                empty_strings: 1.0,
+                empty_arrays: 1.0,
+                nan: true,
+            },
+            ..Default::default()
+        };
+        let i_edges = int_edges(i32::MIN.into(), i32::MAX.into());
+        let mut seen_nan = false;
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..50 {
+            let synthesized = synthesize(&mut rng, &spec, &options).unwrap();
+            let values = &synthesized.values;
+            let Value::Int(i) = values["i"] else { panic!() };
+            assert!(i_edges.contains(&i), "{}", i);
+            let Value::Float(f) = values["f"] else {
+                panic!()
+            };
+            seen_nan |= f.is_nan();
+            assert!(f.is_nan() || float_edges(4, false).contains(&f), "{}", f);
+            assert_eq!(values["s"], Value::Str(String::new()));
+            assert_eq!(values["items"], Value::Array(vec![]));
+            assert_eq!(synthesized.data.len(), 9);
+        }
+        assert!(seen_nan);
+    }
+
+    #[test]
+    fn float_bit_patterns() {
+        let mut spec = KsySpec::top_level("floats");
+        spec.meta.as_mut().unwrap().endian = Some(Endian::Le.into());
+        spec.seq = vec![
+            Attribute::new("single", "f4"),
+            Attribute::new("double", "f8"),
+            Attribute {
+                repeat: Some(Repeat::Expr),
+                repeat_expr: Some(Expr::Int(2)),
+                ..Attribute::new("singles", "f4")
+            },
+        ];
+        let options = DataOptions {
+            edges: EdgeBias {
+                floats: 1.0,
+                nan: true,
+                ..EdgeBias::NONE
+            },
+            ..Default::default()
+        };
+        let mut rng = StdRng::seed_from_u64(0);
+        let (mut seen_nan, mut seen_zero) = (false, false);
+        for _ in 0..50 {
+            let synthesized = synthesize(&mut rng, &spec, &options).unwrap();
+            let data = &synthesized.data;
+            let Value::Float(single) = synthesized.values["single"] else {
+                panic!()
+            };
+            let Value::Float(double) = synthesized.values["double"] else {
+                panic!()
+            };
+            assert_eq!(data[..4], narrow_f4(single).to_le_bytes());
+            assert_eq!(data[4..12], double.to_bits().to_le_bytes());
+
+            let bits = synthesized.float_bits();
+            for (expr, value) in synthesized.assertions() {
+                let x = match value {
+                    Value::Float(x) => x,
+                    Value::Array(items) => {
+                        assert!(!items.iter().any(needs_subscript));
+                        continue;
+                    }
+                    value => {
+                        assert_eq!(value, Value::Bool(true));
+                        continue;
+                    }
+                };
+                assert!(x.is_finite());
+                if x == 0.0 {
+                    seen_zero = true;
+                    assert!(bits.contains(&(expr, x.to_bits())));
+                }
+            }
+            for (_, bits) in bits {
+                seen_nan |= f64::from_bits(bits).is_nan();
+            }
+        }
+        assert!(seen_nan && seen_zero);
+    }
+
+    #[test]
+    fn size_budget() {
+        let mut spec = KsySpec::top_level("budget");
+        spec.meta.as_mut().unwrap().endian = Some(Endian::Le.into());
+        spec.seq = vec![
+            Attribute::new("len", "u2"),
+            sized("body", None, name("len")),
+            Attribute {
+                repeat: Some(Repeat::Eos),
+                ..Attribute::new("items", "u4")
+            },
+        ];
+        let options = DataOptions {
+            max_len: 64,
+            max_items: 32,
+            max_size: Some(20),
+            ..Default::default()
+        };
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut longest = 0;
+        for _ in 0..50 {
+            let synthesized = synthesize(&mut rng, &spec, &options).unwrap();
+            assert!(synthesized.data.len() <= 20);
+            longest = longest.max(synthesized.data.len());
+        }
+        assert!(longest > 10);
+
+        spec.seq = vec![
+            Attribute::new("a", "u8"),
+            Attribute::new("b", "u8"),
+            Attribute::new("c", "u8"),
+        ];
+        assert_eq!(
+            synthesize(&mut rng, &spec, &options),
+            Err(DataGenError::OverBudget("c".to_string()))
+        );
+    }
+
+    #[test]
+    fn endless_recursion() {
+        let mut spec = KsySpec::top_level("endless");
+        spec.seq = vec![Attribute::new("node", "node")];
+        spec.types.insert(
+            "node".to_string(),
+            TypeSpec {
+                seq: vec![Attribute::new("a", "u1"), Attribute::new("next", "node")],
+                ..Default::default()
+            },
+        );
+        let mut rng = StdRng::seed_from_u64(0);
+        assert_eq!(
+            synthesize(&mut rng, &spec, &DataOptions::default()),
+            Err(DataGenError::TooDeep("next".to_string()))
+        );
+    }
+
+    #[test]
+    fn traced_bytes() {
+        let mut spec = KsySpec::top_level("traced");
+        spec.seq = vec![
+            Attribute::new("flags", "b4"),
+            Attribute::new("kind", "b4"),
+            Attribute {
+                repeat: Some(Repeat::Expr),
+                repeat_expr: Some(Expr::Int(2)),
+                ..sized("entries", Some("entry"), Expr::Int(3))
+            },
+        ];
+        spec.types.insert(
+            "entry".to_string(),
+            TypeSpec {
+                seq: vec![
+                    Attribute::new("tag", "u1"),
+                    Attribute {
+                        repeat: Some(Repeat::Eos),
+                        ..Attribute::new("ids", "u1")
+                    },
+                ],
+                ..Default::default()
+            },
+        );
+        let options = DataOptions {
+            trace: true,
+            ..Default::default()
+        };
+        let mut rng = StdRng::seed_from_u64(0);
+        let synthesized = synthesize(&mut rng, &spec, &options).unwrap();
+        let spans: Vec<(Range<usize>, &str)> = synthesized
+            .trace
+            .iter()
+            .map(|span| (span.bytes.clone(), span.field.as_str()))
+            .collect();
+        assert_eq!(
+            spans,
+            [
+                (0..1, "flags"),
+                (0..1, "kind"),
+                (1..2, "entries[0].tag"),
+                (2..3, "entries[0].ids[0]"),
+                (3..4, "entries[0].ids[1]"),
+                (4..5, "entries[1].tag"),
+                (5..6, "entries[1].ids[0]"),
+                (6..7, "entries[1].ids[1]"),
+            ]
+        );
+        for span in &synthesized.trace {
+            let Value::Int(value) = span.value else {
+                panic!()
+            };
+            if span.bytes.start > 0 {
+                assert_eq!(i128::from(synthesized.data[span.bytes.start]), value);
+            }
+        }
+        let report = trace::report(&synthesized.trace);
+        assert!(report.starts_with("0x0000..0x0001 flags = "), "{}", report);
+
+        let untraced = synthesize(&mut rng, &spec, &DataOptions::default()).unwrap();
+        assert!(untraced.trace.is_empty());
+    }
+
+    #[test]
+    fn read_back_checks() {
+        let mut spec = KsySpec::top_level("read_back");
+        spec.meta.as_mut().unwrap().endian = Some(Endian::Le.into());
+        spec.meta.as_mut().unwrap().bit_endian = Some(Endian::Le);
+        spec.seq = vec![
+            Attribute::new("bits", "b5"),
+            Attribute::new("f", "f4"),
+            Attribute {
+                valid: Some(Valid::Checks(ValidChecks {
+                    max: Some(Expr::Int(9)),
+                    ..Default::default()
+                })),
+                ..Attribute::new("small", "u2")
+            },
+            Attribute {
+                repeat: Some(Repeat::Until),
+                repeat_until: Some(Expr::BinaryOp {
+                    l: Box::new(name("_")),
+                    op: BinaryOp::Eq,
+                    r: Box::new(Expr::Int(0)),
+                }),
+                ..Attribute::new("items", "u1")
+            },
+        ];
+        let options = DataOptions {
+            edges: EdgeBias {
+                nan: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..20 {
+            let synthesized = synthesize(&mut rng, &spec, &options).unwrap();
+            assert_eq!(read_back(&spec, &synthesized, false), Ok(()));
+            assert!(read_back(&spec, &synthesized, true).is_err());
+
+            let mut wrong = synthesized.clone();
+            wrong.values.insert("small".to_string(), Value::Int(10));
+            assert!(matches!(
+                read_back(&spec, &wrong, false),
+                Err(DataGenError::SelfCheck(_))
+            ));
+            wrong.data.pop();
+            assert!(read_back(&spec, &wrong, false).is_err());
+        }
+        for data in violations(&mut rng, &spec, &options).unwrap() {
+            let read = interpret::read(&spec, &data);
+            assert!(
+                matches!(&read, Err(ReadError::Validation(id, _)) if id == "small"),
+                "{:?}",
+                read
+            );
+        }
+    }
+
+    #[test]
+    fn variants() {
+        let mut spec = KsySpec::top_level("variants");
+        spec.seq = vec![
+            Attribute::new("flag", "u1"),
+            Attribute {
+                if_expr: Some(Expr::BinaryOp {
+                    l: Box::new(name("flag")),
+                    op: BinaryOp::Gt,
+                    r: Box::new(Expr::Int(127)),
+                }),
+                ..Attribute::new("x", "u1")
+            },
+        ];
+        let options = DataOptions::default();
+        let rng_for = |i| StdRng::seed_from_u64(i as u64);
+        let variants = synthesize_variants(&spec, &options, 2, rng_for).unwrap();
+        assert_eq!(variants.len(), 2);
+        let covered: BTreeSet<&str> = variants
+            .iter()
+            .flat_map(|(_, variant)| variant.coverage.iter().map(String::as_str))
+            .collect();
+        assert_eq!(covered, BTreeSet::from(["if x: false", "if x: true"]));
+        assert_ne!(variants[0].1.data.len(), variants[1].1.data.len());
+        for (i, variant) in variants {
+            assert_eq!(synthesize(&mut rng_for(i), &spec, &options), Ok(variant));
+        }
+    }
+
+    #[test]
+    fn truncations() {
+        let mut spec = KsySpec::top_level("truncated");
+        spec.meta.as_mut().unwrap().endian = Some(Endian::Le.into());
+        spec.meta.as_mut().unwrap().encoding = Some("ASCII".to_string());
+        spec.seq = vec![
+            Attribute::new("a", "u4"),
+            Attribute {
+                repeat: Some(Repeat::Expr),
+                repeat_expr: Some(Expr::Int(2)),
+                ..Attribute::new("pair", "u2")
+            },
+            Attribute {
+                valid: Some(Valid::Eq(Expr::Str("xyz".to_string()))),
+                ..Attribute::new("name", "strz")
+            },
+            Attribute {
+                size_eos: Some(true),
+                ..Attribute::new("rest", "str")
+            },
+        ];
+        let mut rng = StdRng::seed_from_u64(0);
+        let synthesized = synthesize(&mut rng, &spec, &DataOptions::default()).unwrap();
+        assert_eq!(synthesized.boundaries[..4], [4, 6, 8, 8]);
+        assert_eq!(synthesized.boundaries[4], 12);
+        assert_eq!(synthesized.tolerant_from, Some(12));
+        let lens: Vec<usize> = synthesized
+            .truncations()
+            .iter()
+            .map(|data| data.len())
+            .collect();
+        assert_eq!(lens, [0, 2, 4, 5, 6, 7, 8, 10]);
+    }
+
+    #[test]
+    fn constraints() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let options = DataOptions::default();
+        for form in ValidForm::ALL {
+            let Some(case) = valid_case(&mut rng, "valid", form) else {
+                continue;
+            };
+            let mut spec = case.spec;
+            spec.seq.insert(
+                0,
+                Attribute {
+                    id: Some("magic".to_string()),
+                    contents: Some(Contents::Str("KS".to_string())),
+                    ..Default::default()
+                },
+            );
+            let valid = spec.seq[2].valid.clone().unwrap();
+            for _ in 0..20 {
+                let synthesized = synthesize(&mut rng, &spec, &options).unwrap();
+                assert_eq!(&synthesized.data[..2], b"KS");
+                assert_eq!(synthesized.values["magic"], Value::Bytes(b"KS".to_vec()));
+                let mut env = Env::new();
+                env.set("base", synthesized.values["base"].clone());
+                let value = &synthesized.values["value"];
+                assert_eq!(is_valid(&valid, value, &env), Ok(true), "{:?}", form);
+            }
+        }
+
+        let mut spec = KsySpec::top_level("valid_str");
+        spec.meta.as_mut().unwrap().encoding = Some("ASCII".to_string());
+        spec.seq = vec![Attribute {
+            valid: Some(Valid::Eq(Expr::Str("KS".to_string()))),
+            ..sized("magic", Some("str"), Expr::Int(2))
+        }];
+        let synthesized = synthesize(&mut rng, &spec, &options).unwrap();
+        assert_eq!(synthesized.data, b"KS");
+        spec.seq[0].size = Some(Expr::Int(3));
+        assert_eq!(
+            synthesize(&mut rng, &spec, &options),
+            Err(DataGenError::Unsatisfiable("magic".into()))
+        );
+    }
+
+    #[test]
+    fn violated_checks() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let options = DataOptions::default();
+        for form in ValidForm::ALL {
+            let Some(case) = valid_case(&mut rng, "violated", form) else {
+                continue;
+            };
+            let mut spec = case.spec;
+            spec.seq.insert(
+                0,
+                Attribute {
+                    id: Some("magic".to_string()),
+                    contents: Some(Contents::Str("KS".to_string())),
+                    ..Default::default()
+                },
+            );
+            let valid = spec.seq[2].valid.clone().unwrap();
+            let violations = violations(&mut rng, &spec, &options).unwrap();
+            let [bad_magic, bad_value] = &violations[..] else {
+                panic!("{:?}: {:?}", form, violations)
+            };
+            assert_eq!(bad_magic.len(), 2);
+            assert_ne!(bad_magic, b"KS");
+            // `base` and `value`, both `u1`
+            assert_eq!(bad_value[..2], *b"KS");
+            assert_eq!(bad_value.len(), 4);
+            let mut env = Env::new();
+            env.set("base", Value::Int(bad_value[2].into()));
+            let value = Value::Int(bad_value[3].into());
+            assert_eq!(is_valid(&valid, &value, &env), Ok(false), "{:?}", form);
+        }
+
+        // `u1` between 0 and 255 can't fail
+        let mut spec = KsySpec::top_level("always_valid");
+        spec.seq = vec![Attribute {
+            valid: Some(Valid::Checks(ValidChecks {
+                min: Some(Expr::Int(0)),
+                ..Default::default()
+            })),
+            ..Attribute::new("x", "u1")
+        }];
+        assert_eq!(violations(&mut rng, &spec, &options), Ok(vec![]));
+    }
+
+    #[test]
+    fn errors() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let options = DataOptions::default();
+        let mut spec = KsySpec::top_level("errors");
+        spec.seq = vec![Attribute {
+            process: Some("my_custom(1)".to_string()),
+            ..sized("packed", None, Expr::Int(4))
+        }];
+        assert_eq!(
+            synthesize(&mut rng, &spec, &options),
+            Err(DataGenError::Unsupported("process `my_custom(1)`".into()))
+        );
+        spec.seq = vec![Attribute::new("x", "u2")];
+        assert_eq!(
+            synthesize(&mut rng, &spec, &options),
+            Err(DataGenError::MissingEndian("u2".into()))
+        );
+        spec.seq = vec![
+            Attribute {
+                size_eos: Some(true),
+                ..Default::default()
+            },
+            Attribute::new("after", "u1"),
+        ];
+        spec.seq[0].id = Some("all".to_string());
+        assert_eq!(
+            synthesize(&mut rng, &spec, &options),
+            Err(DataGenError::AfterEos("after".into()))
+        );
+        spec.seq = vec![Attribute::new("x", "unknown")];
+        assert_eq!(
+            synthesize(&mut rng, &spec, &options),
+            Err(DataGenError::UnknownType("unknown".into()))
+        );
+    }
+}
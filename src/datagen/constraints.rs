@@ -0,0 +1,214 @@
+//! Choice of values passing the `valid` checks of attributes, which may refer to the attributes
+//! read before them. Values listed by `eq` or `any-of` are tried first, integer ranges are
+//! narrowed by `min` and `max`, and everything else (like `expr`) is left to sampling.
+
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+use crate::ast::Expr;
+use crate::eval::{eval, Env, EvalError, Value};
+use crate::gen::valid::is_valid;
+use crate::ksy::Valid;
+
+/// Ranges of integers up to this size are searched exhaustively
+const ENUMERATE_LIMIT: i128 = 4096;
+/// Random values tried before giving up
+const SAMPLES: usize = 1000;
+
+/// Whether `value` passes, where failing to evaluate a check (e.g. dividing by zero) counts as
+/// not passing, like in the runtimes.
+fn passes(valid: &Valid, value: &Value, env: &Env) -> Result<bool, EvalError> {
+    match is_valid(valid, value, env) {
+        Err(err) if err.is_runtime() => Ok(false),
+        result => result,
+    }
+}
+
+/// Expressions of the values that `eq` or `any-of` allow, empty if any value may pass.
+fn listed(valid: &Valid) -> Vec<&Expr> {
+    match valid {
+        Valid::Eq(expr) => vec![expr],
+        Valid::Checks(checks) => match &checks.eq {
+            Some(eq) => vec![eq],
+            None => checks.any_of.iter().collect(),
+        },
+    }
+}
+
+fn as_int(value: &Value) -> Option<i128> {
+    match value {
+        Value::Int(value) | Value::Enum { value, .. } => Some(*value),
+        _ => None,
+    }
+}
+
+/// Integer in `min..=max` whose value (made by `to_value`, e.g. an enum) passes `valid`, or
+/// `None` if there's no such integer or none was found.
+pub fn solve_int<R, F>(
+    rng: &mut R,
+    valid: &Valid,
+    env: &Env,
+    min: i128,
+    max: i128,
+    to_value: F,
+) -> Result<Option<i128>, EvalError>
+where
+    R: Rng + ?Sized,
+    F: Fn(i128) -> Value,
+{
+    let listed = listed(valid);
+    if !listed.is_empty() {
+        let mut options = Vec::new();
+        for expr in listed {
+            if let Some(value) = as_int(&eval(expr, env)?).filter(|v| (min..=max).contains(v)) {
+                options.push(value);
+            }
+        }
+        options.shuffle(rng);
+        for value in options {
+            if passes(valid, &to_value(value), env)? {
+                return Ok(Some(value));
+            }
+        }
+        return Ok(None);
+    }
+
+    let (mut lo, mut hi) = (min, max);
+    if let Valid::Checks(checks) = valid {
+        if let Some(min) = &checks.min {
+            lo = as_int(&eval(min, env)?).map_or(lo, |min| lo.max(min));
+        }
+        if let Some(max) = &checks.max {
+            hi = as_int(&eval(max, env)?).map_or(hi, |max| hi.min(max));
+        }
+    }
+    if lo > hi {
+        return Ok(None);
+    }
+    if hi - lo < ENUMERATE_LIMIT {
+        let mut passing = Vec::new();
+        for value in lo..=hi {
+            if passes(valid, &to_value(value), env)? {
+                passing.push(value);
+            }
+        }
+        return Ok(passing.choose(rng).copied());
+    }
+    for _ in 0..SAMPLES {
+        let value = rng.gen_range(lo..=hi);
+        if passes(valid, &to_value(value), env)? {
+            return Ok(Some(value));
+        }
+    }
+    Ok(None)
+}
+
+/// Value passing `valid` and `accept` (e.g. of the right length): one of the listed values if
+/// `valid` lists them, otherwise one of the values made by `sample`.
+pub fn solve_value<R, A, S>(
+    rng: &mut R,
+    valid: &Valid,
+    env: &Env,
+    accept: A,
+    mut sample: S,
+) -> Result<Option<Value>, EvalError>
+where
+    R: Rng + ?Sized,
+    A: Fn(&Value) -> bool,
+    S: FnMut(&mut R) -> Value,
+{
+    let listed = listed(valid);
+    if !listed.is_empty() {
+        let mut options = Vec::new();
+        for expr in listed {
+            options.push(eval(expr, env)?);
+        }
+        options.shuffle(rng);
+        for value in options {
+            if accept(&value) && passes(valid, &value, env)? {
+                return Ok(Some(value));
+            }
+        }
+        return Ok(None);
+    }
+    for _ in 0..SAMPLES {
+        let value = sample(rng);
+        if accept(&value) && passes(valid, &value, env)? {
+            return Ok(Some(value));
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::BinaryOp;
+    use crate::ksy::ValidChecks;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn name(name: &str) -> Expr {
+        Expr::Name(name.to_string())
+    }
+
+    #[test]
+    fn integers() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut env = Env::new();
+        env.set("base", Value::Int(200));
+        // `_ > base` over `u4`: too many values to enumerate
+        let above_base = Valid::Checks(ValidChecks {
+            expr: Some(Expr::BinaryOp {
+                l: Box::new(name("_")),
+                op: BinaryOp::Gt,
+                r: Box::new(name("base")),
+            }),
+            max: Some(Expr::Int(100_000)),
+            ..Default::default()
+        });
+        for _ in 0..20 {
+            let value = solve_int(&mut rng, &above_base, &env, 0, u32::MAX.into(), Value::Int);
+            let value = value.unwrap().unwrap();
+            assert!((201..=100_000).contains(&value), "{}", value);
+        }
+        let any_of = Valid::Checks(ValidChecks {
+            any_of: vec![Expr::Int(3), Expr::Int(300), name("base")],
+            ..Default::default()
+        });
+        let value = solve_int(&mut rng, &any_of, &env, 0, 255, Value::Int).unwrap();
+        assert!(matches!(value, Some(3 | 200)));
+        let none = Valid::Eq(Expr::Int(300));
+        assert_eq!(
+            solve_int(&mut rng, &none, &env, 0, 255, Value::Int),
+            Ok(None)
+        );
+        let div = Valid::Checks(ValidChecks {
+            expr: Some(Expr::BinaryOp {
+                l: Box::new(Expr::BinaryOp {
+                    l: Box::new(Expr::Int(10)),
+                    op: BinaryOp::Div,
+                    r: Box::new(name("_")),
+                }),
+                op: BinaryOp::Eq,
+                r: Box::new(Expr::Int(0)),
+            }),
+            ..Default::default()
+        });
+        let value = solve_int(&mut rng, &div, &env, 0, 255, Value::Int).unwrap();
+        assert!(value.unwrap() > 10);
+    }
+
+    #[test]
+    fn strings() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let env = Env::new();
+        let valid = Valid::Eq(Expr::Str("KS".to_string()));
+        let short = |value: &Value| matches!(value, Value::Str(s) if s.len() == 2);
+        let sample = |_: &mut StdRng| Value::Str("xx".to_string());
+        let value = solve_value(&mut rng, &valid, &env, short, sample).unwrap();
+        assert_eq!(value, Some(Value::Str("KS".to_string())));
+        let long = |value: &Value| matches!(value, Value::Str(s) if s.len() == 3);
+        assert_eq!(solve_value(&mut rng, &valid, &env, long, sample), Ok(None));
+    }
+}
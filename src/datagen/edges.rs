@@ -0,0 +1,182 @@
+//! Boundary values that the data generator injects more often than uniform sampling would,
+//! since runtimes and generated parsers tend to get them wrong: sign and width boundaries of
+//! integers, the limits of exact integers in doubles, and the special floats.
+//!
+//! Float values are doubles with the exact bits of the data: an `f4` NaN is widened with its
+//! payload in the high payload bits, the way hardware converts it (see [`widen_f4`]).
+
+/// How often each kind of field gets an edge value instead of a random one, from 0 (never) to 1
+/// (whenever the field allows one).
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct EdgeBias {
+    /// Integers (and bit fields) get one of [`int_edges`]
+    pub ints: f64,
+    /// Floats get one of [`float_edges`]
+    pub floats: f64,
+    /// Strings and byte arrays of a free length are empty
+    pub empty_strings: f64,
+    /// Arrays of a free number of items have as few as allowed
+    pub empty_arrays: f64,
+    /// Whether the float edges include NaN, which never equals itself: consumers comparing
+    /// expected values have to treat it specially
+    pub nan: bool,
+}
+
+impl EdgeBias {
+    /// Uniform sampling, without edge values.
+    pub const NONE: EdgeBias = EdgeBias {
+        ints: 0.0,
+        floats: 0.0,
+        empty_strings: 0.0,
+        empty_arrays: 0.0,
+        nan: false,
+    };
+}
+
+impl Default for EdgeBias {
+    fn default() -> Self {
+        EdgeBias {
+            ints: 0.25,
+            floats: 0.25,
+            empty_strings: 0.1,
+            empty_arrays: 0.1,
+            nan: false,
+        }
+    }
+}
+
+/// Edge values in `min..=max`: zero and ±1, the limits of the range and their neighbours, and
+/// the neighbours of 2^31 and 2^53 (and their negations).
+pub fn int_edges(min: i128, max: i128) -> Vec<i128> {
+    let mut edges = vec![0, 1, -1, min, min + 1, max - 1, max];
+    for power in [31, 32, 53] {
+        let p = 1i128 << power;
+        edges.extend([p - 1, p, p + 1, -p - 1, -p, -p + 1]);
+    }
+    edges.retain(|edge| (min..=max).contains(edge));
+    edges.sort();
+    edges.dedup();
+    edges
+}
+
+/// Edge values of a float of `width` bytes (4 or 8), exactly representable in it: signed zeros
+/// and ones, the extremes, the smallest normal and subnormal values, infinities and (with
+/// `nan`) the [`nan_payloads`].
+pub fn float_edges(width: usize, nan: bool) -> Vec<f64> {
+    let mut edges = if width == 4 {
+        vec![
+            f32::MAX,
+            f32::MIN,
+            f32::MIN_POSITIVE,
+            -f32::MIN_POSITIVE,
+            f32::from_bits(1),
+            -f32::from_bits(1),
+            f32::EPSILON,
+        ]
+        .into_iter()
+        .map(f64::from)
+        .collect()
+    } else {
+        vec![
+            f64::MAX,
+            f64::MIN,
+            f64::MIN_POSITIVE,
+            -f64::MIN_POSITIVE,
+            f64::from_bits(1),
+            -f64::from_bits(1),
+            f64::EPSILON,
+            // the largest integer with all smaller ones exact
+            9_007_199_254_740_992.0,
+        ]
+    };
+    edges.extend([0.0, -0.0, 1.0, -1.0, f64::INFINITY, f64::NEG_INFINITY]);
+    if nan {
+        edges.extend(nan_payloads(width));
+    }
+    edges
+}
+
+/// NaNs of a float of `width` bytes: the default one, its negation, and ones with the lowest
+/// and all payload bits set. All are quiet, since widening a signaling NaN (as runtimes of
+/// languages without single-precision floats do) quiets it.
+pub fn nan_payloads(width: usize) -> Vec<f64> {
+    if width == 4 {
+        [0x7fc0_0000, 0xffc0_0000, 0x7fc0_0001, 0x7fff_ffff]
+            .into_iter()
+            .map(widen_f4)
+            .collect()
+    } else {
+        [
+            0x7ff8 << 48,
+            0xfff8 << 48,
+            (0x7ff8 << 48) | 1,
+            0x7fff_ffff_ffff_ffff,
+        ]
+        .into_iter()
+        .map(f64::from_bits)
+        .collect()
+    }
+}
+
+/// Double with the value of the `f4` bits, keeping the payload of a NaN.
+pub fn widen_f4(bits: u32) -> f64 {
+    let value = f32::from_bits(bits);
+    if !value.is_nan() {
+        return value.into();
+    }
+    let sign = u64::from(bits >> 31) << 63;
+    let payload = u64::from(bits & 0x7f_ffff) << 29;
+    f64::from_bits(sign | (0x7ff << 52) | payload)
+}
+
+/// `f4` bits of a double representable in it, the inverse of [`widen_f4`].
+pub fn narrow_f4(value: f64) -> u32 {
+    if !value.is_nan() {
+        return (value as f32).to_bits();
+    }
+    let bits = value.to_bits();
+    let sign = ((bits >> 63) as u32) << 31;
+    sign | 0x7f80_0000 | ((bits >> 29) as u32 & 0x7f_ffff)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn edges_in_range() {
+        assert_eq!(int_edges(0, 255), [0, 1, 254, 255]);
+        assert_eq!(int_edges(-128, 127), [-128, -127, -1, 0, 1, 126, 127]);
+        let u4 = int_edges(0, u32::MAX.into());
+        assert!(u4.contains(&(1 << 31)) && u4.contains(&(u32::MAX as i128)));
+        assert!(!u4.contains(&(1 << 32)));
+        let s8 = int_edges(i64::MIN.into(), i64::MAX.into());
+        assert!(s8.contains(&((1 << 53) + 1)) && s8.contains(&-(1 << 53)));
+
+        for width in [4, 8] {
+            for edge in float_edges(width, false) {
+                assert!(!edge.is_nan());
+                if width == 4 {
+                    assert_eq!(f64::from(edge as f32).to_bits(), edge.to_bits());
+                }
+            }
+        }
+        assert!(float_edges(8, true).iter().any(|edge| edge.is_nan()));
+    }
+
+    #[test]
+    fn nan_payloads_survive() {
+        for bits in [0x7fc0_0000, 0xffc0_0001, 0x7fff_ffff, 0x8000_0000, 1] {
+            assert_eq!(narrow_f4(widen_f4(bits)), bits);
+        }
+        let payloads = nan_payloads(4);
+        assert!(payloads.iter().all(|nan| nan.is_nan()));
+        let bits: Vec<u32> = payloads.into_iter().map(narrow_f4).collect();
+        assert_eq!(bits, [0x7fc0_0000, 0xffc0_0000, 0x7fc0_0001, 0x7fff_ffff]);
+        // the hardware conversion agrees with the widening
+        assert_eq!(
+            f64::from(f32::from_bits(0x7fc0_0001)).to_bits(),
+            widen_f4(0x7fc0_0001).to_bits()
+        );
+    }
+}
@@ -0,0 +1,596 @@
+//! Interpreter parsing data with a spec the way the generated parsers do, supporting what the
+//! data generator does. Synthesized data is read back with it, so that generator bugs show up
+//! as errors instead of as tests expecting wrong values.
+
+use std::collections::BTreeMap;
+use std::io::Read;
+
+use flate2::read::ZlibDecoder;
+use thiserror::Error;
+
+use crate::ast::Expr;
+use crate::eval::{eval, Env, Value};
+use crate::gen::process::{apply, ProcessKind};
+use crate::gen::string::Encoding;
+use crate::gen::valid::{failed_check, ValidCheck};
+use crate::ksy::{Attribute, Endian, KsySpec, Repeat, TypeRef, TypeSpec};
+use crate::numeric::IntType;
+
+use super::edges::widen_f4;
+use super::process::Process;
+use super::{
+    check_supported, eval_bool, eval_expr, eval_size, int_to_value, resolve_type, type_defaults,
+    Ctx, DataGenError, Defaults, Ending, DEFAULT_CASE, MAX_SIZE,
+};
+
+#[derive(Clone, Debug, Error, PartialEq)]
+pub enum ReadError {
+    #[error("`{0}` reads past the end of the stream")]
+    EndOfStream(String),
+    /// The attribute and the exception that runtimes throw, like `ValidationNotEqualError<u1>`
+    #[error("`{0}` fails its `contents` or `valid` check with {1}")]
+    Validation(String, String),
+    #[error("`meta/endian` of `{0}` matches no case")]
+    UndecidedEndianness(String),
+    #[error("`{0}` is not valid {1}")]
+    Decoding(String, &'static str),
+    #[error("`{0}` is not valid zlib data")]
+    Zlib(String),
+    #[error("can't interpret the spec: {0}")]
+    Spec(DataGenError),
+}
+
+impl From<DataGenError> for ReadError {
+    fn from(error: DataGenError) -> Self {
+        match error {
+            DataGenError::UndecidedEndianness(id) => ReadError::UndecidedEndianness(id),
+            error => ReadError::Spec(error),
+        }
+    }
+}
+
+/// Values of the attributes (and value instances) of the top-level type read from `data`.
+pub fn read(spec: &KsySpec, data: &[u8]) -> Result<BTreeMap<String, Value>, ReadError> {
+    read_type(vec![spec], &[], None, &mut Reader::new(data))
+}
+
+/// Whether the values are the same, with floats compared by their bits (so that NaNs are).
+pub fn same_value(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Float(a), Value::Float(b)) => a.to_bits() == b.to_bits(),
+        (Value::Array(a), Value::Array(b)) => {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| same_value(a, b))
+        }
+        (Value::Struct(a), Value::Struct(b)) => same_fields(a, b),
+        _ => a == b,
+    }
+}
+
+pub fn same_fields(a: &BTreeMap<String, Value>, b: &BTreeMap<String, Value>) -> bool {
+    a.len() == b.len()
+        && a.iter()
+            .zip(b)
+            .all(|((a_name, a), (b_name, b))| a_name == b_name && same_value(a, b))
+}
+
+/// Stream of a runtime: bytes, and the bits left over from a byte partially read by bit fields.
+struct Reader<'d> {
+    data: &'d [u8],
+    pos: usize,
+    /// Unread bits of the last byte, in the low `bit_count` bits
+    bits: u8,
+    bit_count: u32,
+    bit_order: Endian,
+}
+
+impl<'d> Reader<'d> {
+    fn new(data: &'d [u8]) -> Self {
+        Reader {
+            data,
+            pos: 0,
+            bits: 0,
+            bit_count: 0,
+            bit_order: Endian::Be,
+        }
+    }
+
+    fn is_eof(&self) -> bool {
+        self.bit_count == 0 && self.pos >= self.data.len()
+    }
+
+    /// Skips the bits left of a partially read byte.
+    fn align(&mut self) {
+        self.bits = 0;
+        self.bit_count = 0;
+    }
+
+    fn read_bytes(&mut self, len: usize, id: &str) -> Result<&'d [u8], ReadError> {
+        self.align();
+        if len > self.data.len() - self.pos {
+            return Err(ReadError::EndOfStream(id.to_string()));
+        }
+        self.pos += len;
+        Ok(&self.data[self.pos - len..self.pos])
+    }
+
+    fn read_rest(&mut self) -> &'d [u8] {
+        self.align();
+        let rest = &self.data[self.pos..];
+        self.pos = self.data.len();
+        rest
+    }
+
+    /// Bytes up to the terminator, like `read_bytes_term` of the runtimes.
+    fn read_until(
+        &mut self,
+        terminator: u8,
+        include: bool,
+        consume: bool,
+        eos_error: bool,
+        id: &str,
+    ) -> Result<&'d [u8], ReadError> {
+        self.align();
+        let rest = &self.data[self.pos..];
+        let Some(end) = rest.iter().position(|b| *b == terminator) else {
+            if eos_error {
+                return Err(ReadError::EndOfStream(id.to_string()));
+            }
+            self.pos = self.data.len();
+            return Ok(rest);
+        };
+        self.pos += end + usize::from(consume);
+        Ok(&rest[..end + usize::from(include)])
+    }
+
+    /// Reads `n` bits in the bit order, the inverse of [`super::writer::Writer::write_bits`].
+    fn read_bits(&mut self, n: u32, order: Endian, id: &str) -> Result<u64, ReadError> {
+        if order != self.bit_order {
+            self.align();
+            self.bit_order = order;
+        }
+        let mut value = 0u64;
+        for i in 0..n {
+            if self.bit_count == 0 {
+                let byte = *self
+                    .data
+                    .get(self.pos)
+                    .ok_or_else(|| ReadError::EndOfStream(id.to_string()))?;
+                self.pos += 1;
+                self.bits = byte;
+                self.bit_count = 8;
+            }
+            self.bit_count -= 1;
+            match order {
+                Endian::Be => {
+                    let bit = (self.bits >> self.bit_count) & 1;
+                    value = (value << 1) | u64::from(bit);
+                }
+                Endian::Le => {
+                    value |= u64::from(self.bits & 1) << i;
+                    self.bits >>= 1;
+                }
+            }
+        }
+        Ok(value)
+    }
+}
+
+fn read_type(
+    scopes: Vec<&TypeSpec>,
+    parents: &[Value],
+    inherited: Option<&Defaults>,
+    reader: &mut Reader<'_>,
+) -> Result<BTreeMap<String, Value>, ReadError> {
+    let ty = *scopes.last().expect("scopes must not be empty");
+    let mut env = Env::new();
+    for scope in &scopes {
+        for (name, members) in &scope.enums {
+            let members = members.iter().map(|(value, label)| (label.clone(), *value));
+            env.define_enum(vec![name.clone()], members);
+        }
+    }
+    if let Some(parent) = parents.last() {
+        env.set("_parent", parent.clone());
+    }
+    let defaults = type_defaults(ty, inherited, &env)?;
+
+    let mut values = BTreeMap::new();
+    for attr in &ty.seq {
+        let id = attr
+            .id
+            .as_deref()
+            .ok_or_else(|| DataGenError::Unsupported("a seq attribute without id".into()))?;
+        check_supported(attr)?;
+        let root = parents
+            .first()
+            .cloned()
+            .unwrap_or_else(|| Value::Struct(values.clone()));
+        env.set("_root", root);
+        if let Some(cond) = &attr.if_expr {
+            if !eval_bool(cond, &env)? {
+                continue;
+            }
+        }
+        let ctx = Ctx {
+            scopes: &scopes,
+            parents,
+            values: &values,
+            env: &env,
+            defaults: &defaults,
+        };
+        let value = match attr.repeat {
+            None => read_attr(attr, id, &ctx, reader)?,
+            Some(Repeat::Expr) => {
+                let count = eval_size(attr.repeat_expr.as_ref().unwrap_or(&Expr::Int(0)), &env)?;
+                let items = (0..count)
+                    .map(|_| read_item(attr, id, &ctx, reader))
+                    .collect::<Result<_, _>>()?;
+                Some(Value::Array(items))
+            }
+            Some(Repeat::Eos) => {
+                let mut items = Vec::new();
+                while !reader.is_eof() {
+                    items.push(read_item(attr, id, &ctx, reader)?);
+                }
+                Some(Value::Array(items))
+            }
+            Some(Repeat::Until) => {
+                let cond = attr.repeat_until.as_ref().ok_or_else(|| {
+                    DataGenError::Unsupported(format!("`{}` without `repeat-until`", id))
+                })?;
+                let mut items = Vec::new();
+                loop {
+                    if items.len() as i128 > MAX_SIZE {
+                        return Err(DataGenError::SizeOutOfRange(items.len() as i128).into());
+                    }
+                    let mut item_env = env.clone();
+                    item_env.set("_index", Value::Int(items.len() as i128));
+                    let item_ctx = Ctx {
+                        env: &item_env,
+                        ..ctx
+                    };
+                    let item = read_item(attr, id, &item_ctx, reader)?;
+                    item_env.set("_", item.clone());
+                    items.push(item);
+                    if eval_bool(cond, &item_env)? {
+                        break;
+                    }
+                }
+                Some(Value::Array(items))
+            }
+        };
+        if let Some(value) = value {
+            env.set(id, value.clone());
+            values.insert(id.to_string(), value);
+        }
+    }
+
+    for (name, instance) in &ty.instances {
+        let Some(expr) = &instance.value else {
+            return Err(DataGenError::Unsupported(format!("parse instance `{}`", name)).into());
+        };
+        if let Ok(value) = eval(expr, &env) {
+            env.set(name.clone(), value.clone());
+            values.insert(name.clone(), value);
+        }
+    }
+    Ok(values)
+}
+
+fn read_item(
+    attr: &Attribute,
+    id: &str,
+    ctx: &Ctx<'_, '_>,
+    reader: &mut Reader<'_>,
+) -> Result<Value, ReadError> {
+    let value = read_attr(attr, id, ctx, reader)?.ok_or_else(|| {
+        DataGenError::Unsupported(format!("repeated switch `{}` without a matching case", id))
+    })?;
+    Ok(value)
+}
+
+/// Value of one item of the attribute, `None` if it's a switch matching no case.
+fn read_attr(
+    attr: &Attribute,
+    id: &str,
+    ctx: &Ctx<'_, '_>,
+    reader: &mut Reader<'_>,
+) -> Result<Option<Value>, ReadError> {
+    if let Some(contents) = &attr.contents {
+        let expected = contents.to_bytes();
+        let bytes = reader.read_bytes(expected.len(), id)?;
+        if bytes != expected {
+            let exception = format!("{}<bytes>", ValidCheck::Eq.exception_name());
+            return Err(ReadError::Validation(id.to_string(), exception));
+        }
+        return Ok(Some(Value::Bytes(expected)));
+    }
+    if let Some(process) = &attr.process {
+        let process = Process::parse(process, ctx.env)?;
+        let raw = match &attr.size {
+            Some(size) => reader.read_bytes(eval_size(size, ctx.env)?, id)?,
+            None => reader.read_rest(),
+        };
+        let payload = match process.kind {
+            ProcessKind::Zlib => {
+                let mut payload = Vec::new();
+                ZlibDecoder::new(raw)
+                    .read_to_end(&mut payload)
+                    .map_err(|_| ReadError::Zlib(id.to_string()))?;
+                payload
+            }
+            kind => apply(kind, &process.key, raw),
+        };
+        let payload_attr = Attribute {
+            process: None,
+            size: None,
+            size_eos: Some(true),
+            ..attr.clone()
+        };
+        return read_attr(&payload_attr, id, ctx, &mut Reader::new(&payload));
+    }
+    let value = match &attr.type_ref {
+        None => read_sized(attr, id, None, attr.terminator, ctx, reader)?,
+        Some(TypeRef::Named(type_name)) => read_typed(attr, id, type_name, ctx, reader)?,
+        Some(TypeRef::Switch { switch_on, cases }) => {
+            let selector = eval_expr(switch_on, ctx.env)?;
+            let mut chosen = None;
+            for (key, type_name) in cases {
+                if *key == Expr::Name(DEFAULT_CASE.to_string()) {
+                    chosen = chosen.or(Some(type_name));
+                } else if eval_expr(key, ctx.env)? == selector {
+                    chosen = Some(type_name);
+                    break;
+                }
+            }
+            match chosen {
+                Some(type_name) => read_typed(attr, id, type_name, ctx, reader)?,
+                None => return Ok(None),
+            }
+        }
+    };
+    if let (Some(valid), false) = (&attr.valid, matches!(value, Value::Struct(_))) {
+        let failed = failed_check(valid, &value, ctx.env).map_err(|error| DataGenError::Valid {
+            id: id.to_string(),
+            error,
+        })?;
+        if let Some(check) = failed {
+            let exception = format!("{}<{}>", check.exception_name(), checked_type(attr));
+            return Err(ReadError::Validation(id.to_string(), exception));
+        }
+    }
+    Ok(Some(value))
+}
+
+/// Type of a validated attribute as exceptions name it: without an endianness, and `bytes` if
+/// it has none.
+fn checked_type(attr: &Attribute) -> &str {
+    match &attr.type_ref {
+        Some(TypeRef::Named(type_name)) => {
+            let base = type_name
+                .strip_suffix("le")
+                .or_else(|| type_name.strip_suffix("be"))
+                .unwrap_or(type_name);
+            match base {
+                "strz" => "str",
+                base => base,
+            }
+        }
+        _ => "bytes",
+    }
+}
+
+fn read_typed(
+    attr: &Attribute,
+    id: &str,
+    type_name: &str,
+    ctx: &Ctx<'_, '_>,
+    reader: &mut Reader<'_>,
+) -> Result<Value, ReadError> {
+    if type_name.contains('(') {
+        return Err(DataGenError::Unsupported(format!("parametric type `{}`", type_name)).into());
+    }
+    let (base, suffix) = match type_name.strip_suffix("le") {
+        Some(base) => (base, Some(Endian::Le)),
+        None => match type_name.strip_suffix("be") {
+            Some(base) => (base, Some(Endian::Be)),
+            None => (type_name, None),
+        },
+    };
+    let endian = || {
+        suffix
+            .or(ctx.defaults.endian)
+            .ok_or_else(|| DataGenError::MissingEndian(type_name.to_string()))
+    };
+
+    if let Some(int_type) = IntType::from_name(base) {
+        let endian = if int_type.width > 1 {
+            endian()?
+        } else {
+            Endian::Le
+        };
+        let mut bytes = reader.read_bytes(usize::from(int_type.width), id)?.to_vec();
+        if endian == Endian::Be {
+            bytes.reverse();
+        }
+        let fill = if int_type.signed && bytes.last().is_some_and(|b| b & 0x80 != 0) {
+            0xff
+        } else {
+            0
+        };
+        bytes.resize(16, fill);
+        let value = i128::from_le_bytes(bytes.try_into().unwrap());
+        return Ok(int_to_value(attr, value, false));
+    }
+    match base {
+        "f4" => {
+            let bytes = reader.read_bytes(4, id)?.try_into().unwrap();
+            let bits = match endian()? {
+                Endian::Le => u32::from_le_bytes(bytes),
+                Endian::Be => u32::from_be_bytes(bytes),
+            };
+            return Ok(Value::Float(widen_f4(bits)));
+        }
+        "f8" => {
+            let bytes = reader.read_bytes(8, id)?.try_into().unwrap();
+            let bits = match endian()? {
+                Endian::Le => u64::from_le_bytes(bytes),
+                Endian::Be => u64::from_be_bytes(bytes),
+            };
+            return Ok(Value::Float(f64::from_bits(bits)));
+        }
+        "str" | "strz" => {
+            let encoding = attr
+                .encoding
+                .as_ref()
+                .or(ctx.defaults.encoding.as_ref())
+                .ok_or_else(|| {
+                    DataGenError::Unsupported(format!("`{}` without an encoding", id))
+                })?;
+            let encoding = Encoding::from_name(encoding)
+                .ok_or_else(|| DataGenError::Unsupported(format!("encoding `{}`", encoding)))?;
+            let terminator = match base {
+                "strz" => attr.terminator.or(Some(0)),
+                _ => attr.terminator,
+            };
+            return read_sized(attr, id, Some(encoding), terminator, ctx, reader);
+        }
+        _ => {}
+    }
+    if let Some(bits) = type_name
+        .strip_prefix('b')
+        .and_then(|bits| bits.parse::<u32>().ok())
+        .filter(|bits| (1..=64).contains(bits))
+    {
+        let order = ctx.defaults.bit_endian.unwrap_or(Endian::Be);
+        let value = reader.read_bits(bits, order, id)?;
+        let is_bool = bits == 1 && attr.enum_name.is_none();
+        return Ok(int_to_value(attr, value.into(), is_bool));
+    }
+    read_user_type(attr, id, type_name, ctx, reader)
+}
+
+/// Byte array (without an encoding) or string with a `size` or `size-eos`, or ending at a
+/// terminator.
+fn read_sized(
+    attr: &Attribute,
+    id: &str,
+    encoding: Option<Encoding>,
+    terminator: Option<u8>,
+    ctx: &Ctx<'_, '_>,
+    reader: &mut Reader<'_>,
+) -> Result<Value, ReadError> {
+    let ending = Ending {
+        terminator,
+        include: attr.include == Some(true),
+        pad: attr.pad_right,
+    };
+    let bytes = match (&attr.size, terminator) {
+        (Some(size), _) => ending.parse(reader.read_bytes(eval_size(size, ctx.env)?, id)?),
+        (None, _) if attr.size_eos == Some(true) => ending.parse(reader.read_rest()),
+        (None, Some(terminator)) => reader.read_until(
+            terminator,
+            ending.include,
+            attr.consume != Some(false),
+            attr.eos_error != Some(false),
+            id,
+        )?,
+        (None, None) => {
+            return Err(DataGenError::Unsupported(format!(
+                "`{}` without `size`, `size-eos` or `terminator`",
+                id
+            ))
+            .into())
+        }
+    };
+    match encoding {
+        None => Ok(Value::Bytes(bytes.to_vec())),
+        Some(encoding) => encoding
+            .decode(bytes)
+            .map(Value::Str)
+            .ok_or_else(|| ReadError::Decoding(id.to_string(), encoding.name())),
+    }
+}
+
+fn read_user_type(
+    attr: &Attribute,
+    id: &str,
+    type_name: &str,
+    ctx: &Ctx<'_, '_>,
+    reader: &mut Reader<'_>,
+) -> Result<Value, ReadError> {
+    let scopes = resolve_type(ctx.scopes, type_name)
+        .ok_or_else(|| DataGenError::UnknownType(type_name.to_string()))?;
+    let mut parents = ctx.parents.to_vec();
+    parents.push(Value::Struct(ctx.values.clone()));
+    let bytes = match &attr.size {
+        Some(size) => reader.read_bytes(eval_size(size, ctx.env)?, id)?,
+        None if attr.size_eos == Some(true) => reader.read_rest(),
+        None => {
+            let values = read_type(scopes, &parents, Some(ctx.defaults), reader)?;
+            return Ok(Value::Struct(values));
+        }
+    };
+    let mut sub = Reader::new(bytes);
+    let values = read_type(scopes, &parents, Some(ctx.defaults), &mut sub)?;
+    Ok(Value::Struct(values))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ksy::Valid;
+
+    #[test]
+    fn bits_and_terminators() {
+        let mut reader = Reader::new(&[0b1011_0110, 0xab, 0xcd, b'x', 0, b'y']);
+        assert_eq!(reader.read_bits(3, Endian::Be, "a").unwrap(), 0b101);
+        assert_eq!(reader.read_bits(5, Endian::Be, "b").unwrap(), 0b1_0110);
+        assert_eq!(reader.read_bits(4, Endian::Le, "c").unwrap(), 0xb);
+        assert_eq!(reader.read_bits(8, Endian::Le, "d").unwrap(), 0xda);
+        assert_eq!(reader.read_until(0, false, true, true, "e"), Ok(&b"x"[..]));
+        assert_eq!(reader.read_until(0, false, true, false, "f"), Ok(&b"y"[..]));
+        assert!(reader.is_eof());
+        assert_eq!(
+            reader.read_bits(1, Endian::Le, "g"),
+            Err(ReadError::EndOfStream("g".to_string()))
+        );
+    }
+
+    #[test]
+    fn read_values() {
+        let mut spec = KsySpec::top_level("read");
+        spec.meta.as_mut().unwrap().endian = Some(Endian::Be.into());
+        spec.seq = vec![
+            Attribute::new("len", "u1"),
+            Attribute {
+                size: Some(Expr::Name("len".to_string())),
+                encoding: Some("UTF-8".to_string()),
+                ..Attribute::new("name", "str")
+            },
+            Attribute {
+                valid: Some(Valid::Eq(Expr::Int(0x7fff))),
+                ..Attribute::new("magic", "s2")
+            },
+            Attribute::new("neg", "s2"),
+        ];
+        let values = read(&spec, b"\x03a\xc3\xa9\x7f\xff\xff\xfe").unwrap();
+        assert_eq!(values["name"], Value::Str("aé".to_string()));
+        assert_eq!(values["neg"], Value::Int(-2));
+        assert_eq!(
+            read(&spec, b"\x02ab\x7f\xfe\xff\xfe"),
+            Err(ReadError::Validation(
+                "magic".to_string(),
+                "ValidationNotEqualError<s2>".to_string()
+            ))
+        );
+        assert_eq!(
+            read(&spec, b"\x02ab\x7f\xff\xff"),
+            Err(ReadError::EndOfStream("neg".to_string()))
+        );
+        assert_eq!(
+            read(&spec, b"\x01\xc3\x7f\xff\xff\xfe"),
+            Err(ReadError::Decoding("name".to_string(), "UTF-8"))
+        );
+    }
+}
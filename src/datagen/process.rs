@@ -0,0 +1,210 @@
+//! `process` of attributes as the data generator sees it. The bytes in the data are the payload
+//! (the value, or the substream of a user type) transformed by the inverse of the process.
+//!
+//! When the length of the bytes is fixed, `zlib` data is made of stored (uncompressed) deflate
+//! blocks, so that the payload length giving exactly that many bytes is known.
+
+use crate::ast::Expr;
+use crate::eval::{Env, Value};
+use crate::gen::process::{unapply, ProcessKind};
+
+use super::{eval_expr, DataGenError};
+
+/// Largest length of a stored deflate block
+const MAX_STORED_BLOCK: usize = 0xffff;
+/// Zlib header and Adler-32 checksum
+const ZLIB_OVERHEAD: usize = 6;
+/// Header, length and its complement of a stored deflate block
+const BLOCK_OVERHEAD: usize = 5;
+
+/// Process with its key: the xor key, a single byte with the rotation amount, or empty for
+/// `zlib`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Process {
+    pub kind: ProcessKind,
+    pub key: Vec<u8>,
+}
+
+impl Process {
+    /// Process of a `process` key, with its argument evaluated in `env`. Arguments are integer
+    /// literals, lists of them, or names of attributes.
+    pub fn parse(process: &str, env: &Env) -> Result<Self, DataGenError> {
+        let unsupported = || DataGenError::Unsupported(format!("process `{}`", process));
+        let (name, arg) = match process.split_once('(') {
+            Some((name, rest)) => {
+                let arg = rest.strip_suffix(')').ok_or_else(unsupported)?;
+                (name.trim(), Some(arg.trim()))
+            }
+            None => (process.trim(), None),
+        };
+        let kind = match name {
+            "xor" => ProcessKind::Xor,
+            "rol" => ProcessKind::Rol,
+            "ror" => ProcessKind::Ror,
+            "zlib" => ProcessKind::Zlib,
+            _ => return Err(unsupported()),
+        };
+        let key = match (kind, arg) {
+            (ProcessKind::Zlib, None) => Vec::new(),
+            (ProcessKind::Zlib, Some(_)) | (_, None) => return Err(unsupported()),
+            (_, Some(arg)) => {
+                let expr = parse_arg(arg).ok_or_else(unsupported)?;
+                key_bytes(&eval_expr(&expr, env)?).ok_or_else(unsupported)?
+            }
+        };
+        if key.is_empty() && kind != ProcessKind::Zlib {
+            return Err(unsupported());
+        }
+        let key = match kind {
+            // runtimes rotate by the amount modulo 8
+            ProcessKind::Rol | ProcessKind::Ror => vec![key[0] % 8],
+            _ => key,
+        };
+        Ok(Process { kind, key })
+    }
+
+    /// Length of the payload whose bytes are exactly `len` long, if there's one.
+    pub fn payload_len(&self, len: usize) -> Option<usize> {
+        if self.kind != ProcessKind::Zlib {
+            return Some(len);
+        }
+        let mut blocks = 1;
+        loop {
+            let payload_len = len.checked_sub(ZLIB_OVERHEAD + BLOCK_OVERHEAD * blocks)?;
+            if payload_len.div_ceil(MAX_STORED_BLOCK).max(1) == blocks {
+                return Some(payload_len);
+            }
+            blocks += 1;
+        }
+    }
+
+    /// Bytes reading as `payload`. With `stored`, `zlib` data has the length that
+    /// [`Process::payload_len`] expects.
+    pub fn unapply(&self, payload: &[u8], stored: bool) -> Vec<u8> {
+        match self.kind {
+            ProcessKind::Zlib if stored => zlib_stored(payload),
+            kind => unapply(kind, &self.key, payload),
+        }
+    }
+}
+
+/// Argument of a process: an integer, a list of integers, or a (possibly dotted) name.
+fn parse_arg(arg: &str) -> Option<Expr> {
+    if let Some(items) = arg.strip_prefix('[').and_then(|arg| arg.strip_suffix(']')) {
+        let items: Option<Vec<Expr>> = items
+            .split(',')
+            .map(|item| parse_int(item.trim()))
+            .collect();
+        return items.map(Expr::List);
+    }
+    if let Some(int) = parse_int(arg) {
+        return Some(int);
+    }
+    let mut expr: Option<Expr> = None;
+    for part in arg.split('.') {
+        let part = part.trim();
+        let is_name = part.starts_with(|c: char| c.is_ascii_lowercase() || c == '_')
+            && part
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+        if !is_name {
+            return None;
+        }
+        expr = Some(match expr {
+            None => Expr::Name(part.to_string()),
+            Some(value) => Expr::Attribute {
+                value: Box::new(value),
+                attr_name: part.to_string(),
+            },
+        });
+    }
+    expr
+}
+
+fn parse_int(s: &str) -> Option<Expr> {
+    let value = match s.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16).ok()?,
+        None => s.parse().ok()?,
+    };
+    Some(Expr::Int(value))
+}
+
+fn key_bytes(value: &Value) -> Option<Vec<u8>> {
+    match value {
+        Value::Int(n) => u8::try_from(*n).ok().map(|b| vec![b]),
+        Value::Bytes(bytes) => Some(bytes.clone()),
+        Value::Array(items) => items.iter().map(|item| key_bytes(item)?.pop()).collect(),
+        _ => None,
+    }
+}
+
+/// Zlib stream of stored deflate blocks, `ZLIB_OVERHEAD + BLOCK_OVERHEAD * blocks` bytes longer
+/// than the payload.
+fn zlib_stored(payload: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+    let mut blocks = payload.chunks(MAX_STORED_BLOCK).peekable();
+    if blocks.peek().is_none() {
+        out.extend([1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next() {
+        out.push(u8::from(blocks.peek().is_none()));
+        let len = block.len() as u16;
+        out.extend(len.to_le_bytes());
+        out.extend((!len).to_le_bytes());
+        out.extend(block);
+    }
+    out.extend(adler32(payload).to_be_bytes());
+    out
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    const MOD: u32 = 65521;
+    let (mut a, mut b) = (1u32, 0u32);
+    for byte in data {
+        a = (a + u32::from(*byte)) % MOD;
+        b = (b + a) % MOD;
+    }
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gen::process::apply;
+
+    #[test]
+    fn parsed() {
+        let mut env = Env::new();
+        env.set("key", Value::Bytes(vec![1, 2]));
+        let parse = |process| Process::parse(process, &env).map(|p| (p.kind, p.key));
+        assert_eq!(parse("xor(0x5a)"), Ok((ProcessKind::Xor, vec![0x5a])));
+        assert_eq!(parse("xor([1, 255])"), Ok((ProcessKind::Xor, vec![1, 255])));
+        assert_eq!(parse("xor(key)"), Ok((ProcessKind::Xor, vec![1, 2])));
+        assert_eq!(parse("rol(11)"), Ok((ProcessKind::Rol, vec![3])));
+        assert_eq!(parse("zlib"), Ok((ProcessKind::Zlib, vec![])));
+        for unsupported in ["zlib(1)", "xor", "xor(256)", "my_custom.process(1)"] {
+            assert!(
+                matches!(parse(unsupported), Err(DataGenError::Unsupported(_))),
+                "{}",
+                unsupported
+            );
+        }
+    }
+
+    #[test]
+    fn stored_zlib() {
+        let process = Process {
+            kind: ProcessKind::Zlib,
+            key: vec![],
+        };
+        for len in [0, 1, 100, MAX_STORED_BLOCK, MAX_STORED_BLOCK + 1] {
+            let payload: Vec<u8> = (0..len).map(|i| i as u8).collect();
+            let raw = process.unapply(&payload, true);
+            assert_eq!(process.payload_len(raw.len()), Some(len));
+            assert_eq!(apply(ProcessKind::Zlib, &[], &raw), payload);
+        }
+        assert_eq!(process.payload_len(10), None);
+        // a full block gives 65546 bytes, the shortest two blocks 65552
+        assert_eq!(process.payload_len(MAX_STORED_BLOCK + 16), None);
+    }
+}
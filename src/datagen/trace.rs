@@ -0,0 +1,81 @@
+//! Byte provenance of synthesized data: which field (and which value chosen for it) each range
+//! of bytes comes from, for finding out why a parser reads something other than intended.
+
+use std::fmt::Write;
+use std::ops::Range;
+
+use crate::eval::{Env, Value};
+use crate::kst::literal;
+
+/// Bytes of a field of a primitive type (or an item of one), with the value chosen for it.
+/// Bit fields cover the bytes they have bits in.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TraceSpan {
+    pub bytes: Range<usize>,
+    /// Path of the field from the top-level type, like `header.entries[2].len`
+    pub field: String,
+    pub value: Value,
+}
+
+/// Prefixes the paths of the spans with the attribute (or item subscript, like `[2]`) that they
+/// are part of.
+pub(super) fn prefix(spans: &mut [TraceSpan], prefix: &str) {
+    for span in spans {
+        span.field = if span.field.is_empty() {
+            prefix.to_string()
+        } else if span.field.starts_with('[') {
+            format!("{}{}", prefix, span.field)
+        } else {
+            format!("{}.{}", prefix, span.field)
+        };
+    }
+}
+
+/// Sidecar report of the spans, one line each: the range of bytes in hex, the field and the
+/// value as a KS literal.
+pub fn report(spans: &[TraceSpan]) -> String {
+    let mut out = String::new();
+    for span in spans {
+        let value = literal(&span.value, &Env::new()).unwrap_or_else(|_| match &span.value {
+            Value::Enum { enum_path, value } => format!("{}::({})", enum_path.join("::"), value),
+            value => format!("{:?}", value),
+        });
+        writeln!(
+            out,
+            "{:#06x}..{:#06x} {} = {}",
+            span.bytes.start, span.bytes.end, span.field, value
+        )
+        .unwrap();
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn span(bytes: Range<usize>, field: &str, value: Value) -> TraceSpan {
+        TraceSpan {
+            bytes,
+            field: field.to_string(),
+            value,
+        }
+    }
+
+    #[test]
+    fn prefixed_report() {
+        let mut spans = vec![
+            span(0..1, "", Value::Int(3)),
+            span(1..2, "[0]", Value::Float(f64::NAN)),
+            span(2..4, "[1].name", Value::Str("ab".to_string())),
+        ];
+        prefix(&mut spans[1..], "items");
+        prefix(&mut spans, "header");
+        assert_eq!(
+            report(&spans),
+            "0x0000..0x0001 header = 3\n\
+             0x0001..0x0002 header.items[0] = Float(NaN)\n\
+             0x0002..0x0004 header.items[1].name = \"ab\"\n"
+        );
+    }
+}
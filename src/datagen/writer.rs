@@ -0,0 +1,140 @@
+//! Output stream of the data generator, the mirror image of a KS runtime stream.
+
+use crate::ksy::Endian;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Writer {
+    bytes: Vec<u8>,
+    /// Bits written since the last full byte, in the low `bit_count` bits
+    bits: u64,
+    bit_count: u32,
+    /// Bit order of the partially written byte
+    bit_order: Endian,
+}
+
+impl Default for Writer {
+    fn default() -> Self {
+        Writer {
+            bytes: Vec::new(),
+            bits: 0,
+            bit_count: 0,
+            bit_order: Endian::Be,
+        }
+    }
+}
+
+impl Writer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of bytes written, counting a partially written byte.
+    pub fn len(&self) -> usize {
+        self.bytes.len() + usize::from(self.bit_count > 0)
+    }
+
+    /// Number of bits written.
+    pub fn bit_len(&self) -> usize {
+        self.bytes.len() * 8 + self.bit_count as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Fills the rest of a partially written byte with zero bits, as runtimes skip them before
+    /// reading anything byte-aligned.
+    pub fn align(&mut self) {
+        if self.bit_count > 0 {
+            let byte = match self.bit_order {
+                Endian::Be => self.bits << (8 - self.bit_count),
+                Endian::Le => self.bits,
+            };
+            self.bytes.push(byte as u8);
+            self.bits = 0;
+            self.bit_count = 0;
+        }
+    }
+
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.align();
+        self.bytes.extend_from_slice(bytes);
+    }
+
+    /// Writes the low `width` bytes of `value` (in two's complement for negative values).
+    pub fn write_int(&mut self, value: i128, width: usize, endian: Endian) {
+        let mut bytes = value.to_le_bytes()[..width].to_vec();
+        if endian == Endian::Be {
+            bytes.reverse();
+        }
+        self.write_bytes(&bytes);
+    }
+
+    /// Writes the low `n` bits of `value` in the bit order: big-endian fills bytes from their
+    /// most significant bit with the most significant bits of the value first, little-endian
+    /// fills them from their least significant bit with the least significant bits first.
+    /// Switching the order aligns to a byte first.
+    pub fn write_bits(&mut self, value: u64, n: u32, order: Endian) {
+        if order != self.bit_order {
+            self.align();
+            self.bit_order = order;
+        }
+        for i in 0..n {
+            match order {
+                Endian::Be => {
+                    let bit = (value >> (n - 1 - i)) & 1;
+                    self.bits = (self.bits << 1) | bit;
+                }
+                Endian::Le => self.bits |= ((value >> i) & 1) << self.bit_count,
+            }
+            self.bit_count += 1;
+            if self.bit_count == 8 {
+                self.bytes.push(self.bits as u8);
+                self.bits = 0;
+                self.bit_count = 0;
+            }
+        }
+    }
+
+    pub fn into_bytes(mut self) -> Vec<u8> {
+        self.align();
+        self.bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bytes_and_bits() {
+        let mut writer = Writer::new();
+        writer.write_int(-2, 2, Endian::Le);
+        writer.write_int(0x0102_0304, 4, Endian::Be);
+        writer.write_bits(0b101, 3, Endian::Be);
+        assert_eq!(writer.len(), 7);
+        writer.write_bits(0b1_1110, 5, Endian::Be);
+        writer.write_bits(0xfff, 12, Endian::Be);
+        writer.write_bytes(&[0x55]);
+        assert_eq!(
+            writer.into_bytes(),
+            [0xfe, 0xff, 1, 2, 3, 4, 0b1011_1110, 0xff, 0xf0, 0x55]
+        );
+    }
+
+    #[test]
+    fn little_endian_bits() {
+        let mut writer = Writer::new();
+        writer.write_bits(0b101, 3, Endian::Le);
+        writer.write_bits(0b1_1110, 5, Endian::Le);
+        // spans three bytes, the most significant 4 bits starting the last one
+        writer.write_bits(0xabcde, 20, Endian::Le);
+        assert_eq!(writer.bit_len(), 28);
+        writer.write_bits(0b11, 2, Endian::Be);
+        writer.write_bits(1, 1, Endian::Le);
+        assert_eq!(
+            writer.into_bytes(),
+            [0b1111_0101, 0xde, 0xbc, 0x0a, 0b1100_0000, 1]
+        );
+    }
+}
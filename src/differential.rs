@@ -0,0 +1,313 @@
+//! Differential testing across targets: a generated case goes through the pipeline (compiling
+//! the spec, building and running the parser) of each configured target, and every pair of
+//! targets whose observations disagree is reported as a finding.
+//!
+//! Running the pipelines is left to implementations of [`Pipeline`], which know where the
+//! compiler and the runtimes are. Findings carry what it takes to reproduce them: the seed and
+//! id of the case, the input and how its data was made.
+
+use std::fmt::Write;
+
+use crate::ast::Expr;
+use crate::eval::Value;
+use crate::gen::suite::{DataSource, GenCase, GenInput, Outcome, ParseError};
+use crate::target::Target;
+use crate::translator::translate;
+
+/// Relative difference of floats that still agree, for rounding in a different order
+const FLOAT_TOLERANCE: f64 = 1e-12;
+
+/// What a target made of an input.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Observation {
+    /// Parsing succeeded, with the values of the expressions of the expected outcome
+    Values(Vec<(Expr, Value)>),
+    /// Parsing failed with an error of the runtime
+    Error(ParseError),
+    /// The pipeline itself failed (compiling the spec, building or crashing), with its message
+    Failed(String),
+}
+
+/// Compiler, build and runtime of a target.
+pub trait Pipeline {
+    fn target(&self) -> Target;
+
+    /// Parses the data of the input with the spec of the case.
+    fn run(&self, case: &GenCase, input: &GenInput) -> Observation;
+}
+
+/// Where a finding comes from, to reproduce it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Repro {
+    pub case_id: String,
+    /// Seed that the case regenerates from, with the profile it was generated with
+    pub seed: u64,
+    pub feature: &'static str,
+    /// Index of the input in the case
+    pub input: usize,
+    /// How synthesized data was made, `None` for data made together with the spec
+    pub source: Option<DataSource>,
+    pub data: Vec<u8>,
+}
+
+/// Two targets disagreeing on an input.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Finding {
+    pub repro: Repro,
+    pub targets: (Target, Target),
+    /// First expression whose values differ, `None` if the outcomes differ altogether
+    pub expr: Option<Expr>,
+    pub observations: (Observation, Observation),
+    /// Outcome that the generator expects
+    pub expected: Outcome,
+}
+
+impl Finding {
+    /// Summary of the finding, to paste into a bug report.
+    pub fn report(&self) -> String {
+        let (a, b) = self.targets;
+        let mut out = format!(
+            "{} and {} disagree on input {} of `{}` (feature {}, seed {:#x})\n",
+            a.ksc_name(),
+            b.ksc_name(),
+            self.repro.input,
+            self.repro.case_id,
+            self.repro.feature,
+            self.repro.seed,
+        );
+        if let Some(source) = &self.repro.source {
+            writeln!(out, "data: {:?}", source).unwrap();
+        }
+        let data: Vec<String> = self
+            .repro
+            .data
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        writeln!(out, "bytes: {}", data.join(" ")).unwrap();
+        let observed = |observation: &Observation| match (observation, &self.expr) {
+            (Observation::Values(values), Some(expr)) => values
+                .iter()
+                .find(|(e, _)| e == expr)
+                .map(|(_, value)| format!("{:?}", value))
+                .unwrap_or_else(|| "nothing".to_string()),
+            (observation, _) => format!("{:?}", observation),
+        };
+        if let Some(expr) = &self.expr {
+            writeln!(out, "expression: {}", translate(expr)).unwrap();
+        }
+        writeln!(out, "{}: {}", a.ksc_name(), observed(&self.observations.0)).unwrap();
+        writeln!(out, "{}: {}", b.ksc_name(), observed(&self.observations.1)).unwrap();
+        out
+    }
+}
+
+#[derive(Default)]
+pub struct Driver {
+    pipelines: Vec<Box<dyn Pipeline>>,
+}
+
+impl Driver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_pipeline(mut self, pipeline: impl Pipeline + 'static) -> Self {
+        self.pipelines.push(Box::new(pipeline));
+        self
+    }
+
+    pub fn targets(&self) -> Vec<Target> {
+        self.pipelines.iter().map(|p| p.target()).collect()
+    }
+
+    /// Runs every input of the case through all the pipelines and reports each pair of targets
+    /// that disagree on an input, in the order of the inputs and of the pipelines.
+    pub fn run(&self, case: &GenCase) -> Vec<Finding> {
+        let mut findings = Vec::new();
+        for (index, input) in case.inputs.iter().enumerate() {
+            let observations: Vec<(Target, Observation)> = self
+                .pipelines
+                .iter()
+                .map(|pipeline| (pipeline.target(), pipeline.run(case, input)))
+                .collect();
+            for (i, (a, observed_a)) in observations.iter().enumerate() {
+                for (b, observed_b) in &observations[i + 1..] {
+                    let Err(expr) = compare(observed_a, observed_b) else {
+                        continue;
+                    };
+                    findings.push(Finding {
+                        repro: Repro {
+                            case_id: case.id.clone(),
+                            seed: case.seed,
+                            feature: case.feature.name(),
+                            input: index,
+                            source: input.source,
+                            data: input.data.clone(),
+                        },
+                        targets: (*a, *b),
+                        expr,
+                        observations: (observed_a.clone(), observed_b.clone()),
+                        expected: input.outcome.clone(),
+                    });
+                }
+            }
+        }
+        findings
+    }
+}
+
+/// Whether the observations agree, or else the first expression whose values differ (`None` if
+/// they differ otherwise). Failed pipelines agree with each other whatever their messages, which
+/// are specific to the targets.
+fn compare(a: &Observation, b: &Observation) -> Result<(), Option<Expr>> {
+    match (a, b) {
+        (Observation::Values(a), Observation::Values(b)) => {
+            for (expr, value) in a {
+                match b.iter().find(|(other, _)| other == expr) {
+                    Some((_, other)) if agree(value, other) => {}
+                    _ => return Err(Some(expr.clone())),
+                }
+            }
+            match b
+                .iter()
+                .find(|(expr, _)| a.iter().all(|(other, _)| other != expr))
+            {
+                Some((expr, _)) => Err(Some(expr.clone())),
+                None => Ok(()),
+            }
+        }
+        (Observation::Error(a), Observation::Error(b)) if a == b => Ok(()),
+        (Observation::Failed(_), Observation::Failed(_)) => Ok(()),
+        _ => Err(None),
+    }
+}
+
+fn agree(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Float(a), Value::Float(b)) => {
+            a == b
+                || (a.is_nan() && b.is_nan())
+                || (a - b).abs() <= FLOAT_TOLERANCE * a.abs().max(b.abs())
+        }
+        (Value::Array(a), Value::Array(b)) => {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| agree(a, b))
+        }
+        (Value::Struct(a), Value::Struct(b)) => {
+            a.len() == b.len()
+                && a.iter()
+                    .zip(b)
+                    .all(|((ka, a), (kb, b))| ka == kb && agree(a, b))
+        }
+        _ => a == b,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gen::profile::GenProfile;
+    use crate::gen::suite::generate_case;
+
+    /// Stand-in for a target, observing the expected outcome with the values of integers
+    /// changed by `skew`.
+    struct Fake {
+        target: Target,
+        skew: i128,
+    }
+
+    impl Pipeline for Fake {
+        fn target(&self) -> Target {
+            self.target
+        }
+
+        fn run(&self, _case: &GenCase, input: &GenInput) -> Observation {
+            match &input.outcome {
+                Outcome::Values(values) => Observation::Values(
+                    values
+                        .iter()
+                        .map(|(expr, value)| match value {
+                            Value::Int(x) => (expr.clone(), Value::Int(x + self.skew)),
+                            _ => (expr.clone(), value.clone()),
+                        })
+                        .collect(),
+                ),
+                Outcome::Error(error) => Observation::Error(*error),
+            }
+        }
+    }
+
+    fn case_with_ints() -> GenCase {
+        (0..)
+            .filter_map(|seed| generate_case(seed, &GenProfile::default()))
+            .find(|case| {
+                case.inputs.iter().any(|input| {
+                    matches!(&input.outcome, Outcome::Values(values)
+                        if values.iter().any(|(_, value)| matches!(value, Value::Int(_))))
+                })
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn disagreements() {
+        let case = case_with_ints();
+        let driver = Driver::new()
+            .with_pipeline(Fake {
+                target: Target::Python,
+                skew: 0,
+            })
+            .with_pipeline(Fake {
+                target: Target::Java,
+                skew: 0,
+            })
+            .with_pipeline(Fake {
+                target: Target::Go,
+                skew: 1,
+            });
+        assert_eq!(driver.targets(), [Target::Python, Target::Java, Target::Go]);
+        let findings = driver.run(&case);
+        assert!(!findings.is_empty());
+        assert!(findings
+            .iter()
+            .all(|finding| finding.targets.1 == Target::Go));
+        let finding = &findings[0];
+        assert_eq!(finding.repro.seed, case.seed);
+        assert_eq!(finding.repro.case_id, case.id);
+        assert_eq!(finding.repro.data, case.inputs[finding.repro.input].data);
+        let expr = finding.expr.as_ref().unwrap();
+        let report = finding.report();
+        assert!(report.starts_with("python and go disagree on input "));
+        assert!(report.contains(&format!("expression: {}\n", translate(expr))));
+
+        let agreeing = Driver::new()
+            .with_pipeline(Fake {
+                target: Target::Ruby,
+                skew: 0,
+            })
+            .with_pipeline(Fake {
+                target: Target::Rust,
+                skew: 0,
+            });
+        assert_eq!(agreeing.run(&case), []);
+    }
+
+    #[test]
+    fn comparisons() {
+        let x = || Expr::Name("x".to_string());
+        let values = |value: f64| Observation::Values(vec![(x(), Value::Float(value))]);
+        assert_eq!(compare(&values(0.1 + 0.2), &values(0.3)), Ok(()));
+        assert_eq!(compare(&values(f64::NAN), &values(f64::NAN)), Ok(()));
+        assert_eq!(compare(&values(0.3), &values(0.31)), Err(Some(x())));
+        assert_eq!(
+            compare(&values(0.3), &Observation::Values(vec![])),
+            Err(Some(x()))
+        );
+        let eos = Observation::Error(ParseError::EndOfStream);
+        assert_eq!(compare(&eos, &eos), Ok(()));
+        assert_eq!(compare(&eos, &values(0.3)), Err(None));
+        let failed = |message: &str| Observation::Failed(message.to_string());
+        assert_eq!(compare(&failed("no such file"), &failed("panic")), Ok(()));
+        assert_eq!(compare(&failed("panic"), &eos), Err(None));
+    }
+}
@@ -0,0 +1,365 @@
+//! Knowledge base of known differences between the semantics of the KS expression language and
+//! the way it ends up being evaluated in individual target languages.
+
+use std::collections::BTreeSet;
+
+use crate::ast::{BinaryOp, Expr, UnaryOp};
+use crate::eval::{eval, Env, Value};
+use crate::numeric::IntType;
+use crate::target::Target;
+
+/// Largest integer `n` such that all integers in `-n..=n` are exactly representable in an IEEE
+/// 754 double.
+const MAX_SAFE_INTEGER: i128 = (1 << 53) - 1;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum Quirk {
+    /// JavaScript only has double-precision floats, so integers beyond 2^53 lose precision
+    JsFloatOnlyInts,
+    /// JavaScript bitwise operators convert their operands to 32-bit signed integers
+    JsBitwise32,
+    /// PHP silently converts an integer that overflows 64 bits to a float
+    PhpIntOverflowToFloat,
+    /// Languages with fixed-width integers wrap around (or trap) when the result doesn't fit
+    /// into 64 bits
+    Int64Overflow,
+    /// Native integer division rounds towards zero instead of towards negative infinity
+    TruncatingDivision,
+    /// `KaitaiStream.mod` of several runtimes rejects a non-positive divisor
+    NonPositiveModDivisor,
+    /// Shifting by 64 or more bits is undefined or masked in languages with fixed-width integers
+    OversizedShift,
+    /// Lua tables are 1-based, so every index must be adjusted by the translator
+    LuaOneBasedIndexing,
+    /// Strings are byte strings, so `length`, `reverse` and `substring` work on bytes instead of
+    /// code points
+    ByteStringOps,
+    /// Strings are sequences of UTF-16 code units, so characters outside the BMP count twice
+    Utf16StringOps,
+    /// Division of floats by zero yields an infinity instead of raising an error
+    FloatDivByZeroInfinity,
+    /// Casting an integer to a narrower fixed-width type truncates it in statically typed
+    /// languages, while dynamically typed ones keep the value as is
+    NarrowingCast,
+}
+
+impl Quirk {
+    pub const ALL: [Quirk; 12] = [
+        Quirk::JsFloatOnlyInts,
+        Quirk::JsBitwise32,
+        Quirk::PhpIntOverflowToFloat,
+        Quirk::Int64Overflow,
+        Quirk::TruncatingDivision,
+        Quirk::NonPositiveModDivisor,
+        Quirk::OversizedShift,
+        Quirk::LuaOneBasedIndexing,
+        Quirk::ByteStringOps,
+        Quirk::Utf16StringOps,
+        Quirk::FloatDivByZeroInfinity,
+        Quirk::NarrowingCast,
+    ];
+
+    pub fn targets(self) -> &'static [Target] {
+        use Target::*;
+        match self {
+            Quirk::JsFloatOnlyInts | Quirk::JsBitwise32 => &[JavaScript],
+            Quirk::PhpIntOverflowToFloat => &[Php],
+            Quirk::Int64Overflow => &[Cpp, CSharp, Go, Java, Lua, Nim, Perl, Rust, Swift],
+            Quirk::TruncatingDivision => &[Cpp, CSharp, Go, Java, Nim, Perl, Php, Rust, Swift],
+            Quirk::NonPositiveModDivisor => &[Cpp, CSharp, Go, Java, JavaScript, Php, Rust],
+            Quirk::OversizedShift => &[Cpp, CSharp, Go, Java, Nim, Perl, Php, Rust, Swift],
+            Quirk::LuaOneBasedIndexing => &[Lua],
+            Quirk::ByteStringOps => &[Cpp, Go, Lua, Php],
+            Quirk::Utf16StringOps => &[CSharp, Java, JavaScript],
+            Quirk::FloatDivByZeroInfinity => &[
+                Cpp, CSharp, Go, Java, JavaScript, Lua, Nim, Perl, Php, Ruby, Rust, Swift,
+            ],
+            Quirk::NarrowingCast => &[Cpp, CSharp, Go, Java, Nim, Rust, Swift],
+        }
+    }
+}
+
+/// Subexpression that may evaluate differently in some target languages than the KS semantics
+/// prescribe.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Divergence {
+    pub quirk: Quirk,
+    pub expr: Expr,
+}
+
+impl Divergence {
+    pub fn targets(&self) -> &'static [Target] {
+        self.quirk.targets()
+    }
+}
+
+/// Predicts which subexpressions of `expr` may diverge in which target languages. Each
+/// [`Divergence`] is a candidate for a differential test focused on the affected targets.
+pub fn predict(expr: &Expr, env: &Env) -> Vec<Divergence> {
+    let mut divergences = Vec::new();
+    collect(expr, env, &mut divergences);
+    divergences
+}
+
+/// All targets that may diverge on at least one subexpression of `expr`.
+pub fn diverging_targets(expr: &Expr, env: &Env) -> BTreeSet<Target> {
+    predict(expr, env)
+        .iter()
+        .flat_map(|divergence| divergence.targets().iter().copied())
+        .collect()
+}
+
+fn collect(expr: &Expr, env: &Env, divergences: &mut Vec<Divergence>) {
+    for child in expr.children() {
+        collect(child, env, divergences);
+    }
+    let mut add = |quirk| {
+        divergences.push(Divergence {
+            quirk,
+            expr: expr.clone(),
+        })
+    };
+
+    let value = eval(expr, env).ok();
+    if let Some(Value::Int(x)) = value {
+        if !(-MAX_SAFE_INTEGER..=MAX_SAFE_INTEGER).contains(&x) {
+            add(Quirk::JsFloatOnlyInts);
+        }
+    }
+
+    match expr {
+        Expr::BinaryOp { l, op, r } => {
+            let l = eval(l, env).ok();
+            let r = eval(r, env).ok();
+            let (l_int, r_int) = match (&l, &r) {
+                (Some(Value::Int(a)), Some(Value::Int(b))) => (Some(*a), Some(*b)),
+                _ => (None, None),
+            };
+            let result_int = match value {
+                Some(Value::Int(x)) => Some(x),
+                _ => None,
+            };
+            match op {
+                BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul
+                    if result_int.is_some_and(|x| i64::try_from(x).is_err()) =>
+                {
+                    add(Quirk::Int64Overflow);
+                    add(Quirk::PhpIntOverflowToFloat);
+                }
+                BinaryOp::Div => {
+                    if let (Some(a), Some(b)) = (l_int, r_int) {
+                        if b != 0 && a % b != 0 && (a < 0) != (b < 0) {
+                            add(Quirk::TruncatingDivision);
+                        }
+                    }
+                    if is_float_div_by_zero(&l, &r) {
+                        add(Quirk::FloatDivByZeroInfinity);
+                    }
+                }
+                BinaryOp::Rem => {
+                    if r_int.is_some_and(|b| b < 0) {
+                        add(Quirk::NonPositiveModDivisor);
+                    }
+                    if is_float_div_by_zero(&l, &r) {
+                        add(Quirk::FloatDivByZeroInfinity);
+                    }
+                }
+                BinaryOp::BitOr | BinaryOp::BitXor | BinaryOp::BitAnd => {
+                    let operands_or_result = [l_int, r_int, result_int];
+                    if operands_or_result
+                        .iter()
+                        .flatten()
+                        .any(|&x| i32::try_from(x).is_err())
+                    {
+                        add(Quirk::JsBitwise32);
+                    }
+                }
+                BinaryOp::Shl | BinaryOp::Shr => {
+                    if let (Some(a), Some(b)) = (l_int, r_int) {
+                        if b >= 64 {
+                            add(Quirk::OversizedShift);
+                        }
+                        let fits_i32 =
+                            |x: Option<i128>| x.is_some_and(|x| i32::try_from(x).is_ok());
+                        if b >= 32 || !fits_i32(Some(a)) || !fits_i32(result_int) {
+                            add(Quirk::JsBitwise32);
+                        }
+                        let fits_i64 = result_int.is_some_and(|x| i64::try_from(x).is_ok());
+                        if *op == BinaryOp::Shl && !fits_i64 {
+                            add(Quirk::Int64Overflow);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        Expr::UnaryOp {
+            op: UnaryOp::Inv,
+            value: operand,
+        } => {
+            if let Ok(Value::Int(x)) = eval(operand, env) {
+                if i32::try_from(x).is_err() {
+                    add(Quirk::JsBitwise32);
+                }
+            }
+        }
+        Expr::Subscript { .. } => add(Quirk::LuaOneBasedIndexing),
+        Expr::Attribute { value, attr_name } => match (eval(value, env), attr_name.as_str()) {
+            (Ok(Value::Array(_) | Value::Bytes(_)), "first" | "last") => {
+                add(Quirk::LuaOneBasedIndexing)
+            }
+            (Ok(Value::Str(s)), "length" | "reverse") => add_string_quirks(&s, &mut add),
+            _ => {}
+        },
+        Expr::MethodCall {
+            value, method_name, ..
+        } if method_name == "substring" => {
+            if let Ok(Value::Str(s)) = eval(value, env) {
+                add_string_quirks(&s, &mut add);
+            }
+        }
+        Expr::CastTo { value, type_name } => {
+            let int_type = type_name.simple_name().and_then(IntType::from_name);
+            if let (Some(int_type), Ok(Value::Int(x))) = (int_type, eval(value, env)) {
+                if !int_type.contains(x) {
+                    add(Quirk::NarrowingCast);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+fn is_float_div_by_zero(l: &Option<Value>, r: &Option<Value>) -> bool {
+    let is_float = |v: &Option<Value>| matches!(v, Some(Value::Float(_)));
+    let is_zero = match r {
+        Some(Value::Int(0)) => true,
+        Some(Value::Float(x)) => *x == 0.0,
+        _ => false,
+    };
+    (is_float(l) || is_float(r)) && is_zero
+}
+
+fn add_string_quirks(s: &str, add: &mut impl FnMut(Quirk)) {
+    if !s.is_ascii() {
+        add(Quirk::ByteStringOps);
+    }
+    if s.chars().any(|ch| ch.len_utf16() > 1) {
+        add(Quirk::Utf16StringOps);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::TypeName;
+
+    fn binary(l: Expr, op: BinaryOp, r: Expr) -> Expr {
+        Expr::BinaryOp {
+            l: Box::new(l),
+            op,
+            r: Box::new(r),
+        }
+    }
+
+    fn neg(value: Expr) -> Expr {
+        Expr::UnaryOp {
+            op: UnaryOp::Neg,
+            value: Box::new(value),
+        }
+    }
+
+    fn quirks(expr: &Expr) -> Vec<Quirk> {
+        predict(expr, &Env::new())
+            .into_iter()
+            .map(|divergence| divergence.quirk)
+            .collect()
+    }
+
+    #[test]
+    fn large_int_in_js() {
+        let expr = binary(Expr::Int((1 << 53) - 1), BinaryOp::Add, Expr::Int(1));
+        assert_eq!(quirks(&expr), vec![Quirk::JsFloatOnlyInts]);
+        assert_eq!(
+            diverging_targets(&expr, &Env::new()),
+            BTreeSet::from([Target::JavaScript])
+        );
+    }
+
+    #[test]
+    fn int64_overflow() {
+        let expr = binary(Expr::Int(i64::MAX as u64), BinaryOp::Add, Expr::Int(1));
+        let quirks = quirks(&expr);
+        assert!(quirks.contains(&Quirk::Int64Overflow));
+        assert!(quirks.contains(&Quirk::PhpIntOverflowToFloat));
+    }
+
+    #[test]
+    fn truncating_division() {
+        let expr = binary(neg(Expr::Int(7)), BinaryOp::Div, Expr::Int(2));
+        assert_eq!(quirks(&expr), vec![Quirk::TruncatingDivision]);
+        let exact = binary(neg(Expr::Int(8)), BinaryOp::Div, Expr::Int(2));
+        assert_eq!(quirks(&exact), vec![]);
+    }
+
+    #[test]
+    fn negative_mod_divisor() {
+        let expr = binary(Expr::Int(7), BinaryOp::Rem, neg(Expr::Int(2)));
+        assert_eq!(quirks(&expr), vec![Quirk::NonPositiveModDivisor]);
+    }
+
+    #[test]
+    fn bitwise_beyond_32_bits() {
+        let expr = binary(Expr::Int(1 << 40), BinaryOp::BitOr, Expr::Int(1));
+        assert_eq!(quirks(&expr), vec![Quirk::JsBitwise32]);
+        let small = binary(Expr::Int(1 << 20), BinaryOp::BitOr, Expr::Int(1));
+        assert_eq!(quirks(&small), vec![]);
+    }
+
+    #[test]
+    fn oversized_shift() {
+        let expr = binary(Expr::Int(1), BinaryOp::Shl, Expr::Int(64));
+        let quirks = quirks(&expr);
+        assert!(quirks.contains(&Quirk::OversizedShift));
+        assert!(quirks.contains(&Quirk::Int64Overflow));
+        assert!(quirks.contains(&Quirk::JsBitwise32));
+    }
+
+    #[test]
+    fn subscript_in_lua() {
+        let expr = Expr::Subscript {
+            value: Box::new(Expr::List(vec![Expr::Int(1), Expr::Int(2)])),
+            idx: Box::new(Expr::Int(0)),
+        };
+        assert_eq!(quirks(&expr), vec![Quirk::LuaOneBasedIndexing]);
+    }
+
+    #[test]
+    fn astral_string_length() {
+        let expr = Expr::Attribute {
+            value: Box::new(Expr::Str("a\u{1F600}".to_string())),
+            attr_name: "length".to_string(),
+        };
+        assert_eq!(
+            quirks(&expr),
+            vec![Quirk::ByteStringOps, Quirk::Utf16StringOps]
+        );
+    }
+
+    #[test]
+    fn every_quirk_affects_some_target() {
+        for quirk in Quirk::ALL {
+            assert!(!quirk.targets().is_empty(), "{:?}", quirk);
+        }
+    }
+
+    #[test]
+    fn narrowing_cast() {
+        let cast = |value| Expr::CastTo {
+            value: Box::new(Expr::Int(value)),
+            type_name: TypeName::new("u1"),
+        };
+        assert_eq!(quirks(&cast(256)), vec![Quirk::NarrowingCast]);
+        assert_eq!(quirks(&cast(255)), vec![]);
+    }
+}
@@ -0,0 +1,1206 @@
+use std::collections::BTreeMap;
+
+use thiserror::Error;
+
+use crate::ast::{BinaryOp, Expr, UnaryOp};
+use crate::numeric::{builtin_byte_size, numeric_op, NumKind};
+use trace::{Feature, Trace};
+
+pub mod trace;
+
+/// Result of evaluating an expression.
+///
+/// Integers are kept as `i128` so that every value of both `s8` and `u8` is representable and
+/// intermediate results don't silently wrap around - whether a result fits into the integer type
+/// of a particular target language is a separate question.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Int(i128),
+    Float(f64),
+    Str(String),
+    Bool(bool),
+    Bytes(Vec<u8>),
+    Array(Vec<Value>),
+    Enum {
+        enum_path: Vec<String>,
+        value: i128,
+    },
+    /// Instance of a user type (or a stream object like `_io`) with named fields
+    Struct(BTreeMap<String, Value>),
+}
+
+impl Value {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Int(_) => "integer",
+            Value::Float(_) => "float",
+            Value::Str(_) => "string",
+            Value::Bool(_) => "boolean",
+            Value::Bytes(_) => "byte array",
+            Value::Array(_) => "array",
+            Value::Enum { .. } => "enum",
+            Value::Struct(_) => "struct",
+        }
+    }
+
+    pub fn num_kind(&self) -> Option<NumKind> {
+        match self {
+            Value::Int(_) => Some(NumKind::Int),
+            Value::Float(_) => Some(NumKind::Float),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Env {
+    names: BTreeMap<String, Value>,
+    enums: BTreeMap<Vec<String>, BTreeMap<String, i128>>,
+}
+
+impl Env {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set(&mut self, name: impl Into<String>, value: Value) {
+        self.names.insert(name.into(), value);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.names.get(name)
+    }
+
+    pub fn define_enum<I, S>(&mut self, enum_path: Vec<String>, members: I)
+    where
+        I: IntoIterator<Item = (S, i128)>,
+        S: Into<String>,
+    {
+        self.enums.insert(
+            enum_path,
+            members
+                .into_iter()
+                .map(|(label, value)| (label.into(), value))
+                .collect(),
+        );
+    }
+
+    pub fn enum_value(&self, enum_path: &[String], label: &str) -> Option<i128> {
+        self.enums.get(enum_path)?.get(label).copied()
+    }
+
+    pub fn enum_members(&self, enum_path: &[String]) -> Option<&BTreeMap<String, i128>> {
+        self.enums.get(enum_path)
+    }
+
+    pub fn has_enum(&self, enum_path: &[String]) -> bool {
+        self.enums.contains_key(enum_path)
+    }
+
+    pub fn enum_label(&self, enum_path: &[String], value: i128) -> Option<&str> {
+        self.enums
+            .get(enum_path)?
+            .iter()
+            .find(|(_, v)| **v == value)
+            .map(|(label, _)| label.as_str())
+    }
+}
+
+/// Errors that a correct KS runtime would also run into when evaluating the expression. An
+/// expression yielding one of these is not a candidate for a value assertion, but it's perfectly
+/// usable for a test expecting an error.
+#[derive(Clone, Debug, Error, PartialEq)]
+pub enum RuntimeError {
+    #[error("division by zero")]
+    DivisionByZero,
+    #[error("negative shift amount ({0})")]
+    NegativeShift(i128),
+    #[error("integer overflow")]
+    IntegerOverflow,
+    #[error("substring indices {from}..{to} out of range for a string of length {len}")]
+    SubstringOutOfRange { from: i128, to: i128, len: usize },
+    #[error("index {idx} out of range for a collection of size {len}")]
+    IndexOutOfRange { idx: i128, len: usize },
+    #[error("`{method}` called on an empty collection")]
+    EmptyCollection { method: &'static str },
+    #[error("cannot convert {0:?} to an integer")]
+    StrToIntConversion(String),
+}
+
+/// Errors caused by the expression itself being invalid (e.g. ill-typed or referencing something
+/// that doesn't exist), as opposed to [`RuntimeError`]s.
+#[derive(Clone, Debug, Error, PartialEq)]
+pub enum EvalError {
+    #[error(transparent)]
+    Runtime(#[from] RuntimeError),
+    #[error("unknown name `{0}`")]
+    UnknownName(String),
+    #[error("unknown enum member `{}::{label}`", enum_path.join("::"))]
+    UnknownEnumMember {
+        enum_path: Vec<String>,
+        label: String,
+    },
+    #[error("operator `{op}` cannot be applied to {operands}")]
+    UnsupportedOperands { op: &'static str, operands: String },
+    #[error("{value_type} has no attribute or method `{name}`")]
+    UnknownMember {
+        value_type: &'static str,
+        name: String,
+    },
+    #[error("size of type `{0}` is unknown")]
+    UnknownSize(String),
+    #[error("`{method}` expects {expected} argument(s), but got {actual}")]
+    ArgCount {
+        method: String,
+        expected: usize,
+        actual: usize,
+    },
+}
+
+impl EvalError {
+    pub fn is_runtime(&self) -> bool {
+        matches!(self, EvalError::Runtime(_))
+    }
+}
+
+/// Operand of `and`, `or` or `?:` that was not evaluated because the result was already decided
+/// by the operands before it, and which would have failed if it had been evaluated eagerly.
+///
+/// A target language that doesn't short-circuit the operator correctly would fail on such an
+/// expression, so these expressions make good tests of the short-circuit behavior.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SkippedOperand {
+    pub expr: Expr,
+    pub error: EvalError,
+}
+
+pub fn eval(expr: &Expr, env: &Env) -> Result<Value, EvalError> {
+    Evaluator::new(env).eval(expr)
+}
+
+/// Evaluates the expression like [`eval`] and additionally returns all short-circuited operands
+/// that would have failed if they had been evaluated.
+pub fn eval_with_skipped(
+    expr: &Expr,
+    env: &Env,
+) -> (Result<Value, EvalError>, Vec<SkippedOperand>) {
+    let mut evaluator = Evaluator::new(env);
+    evaluator.probe_skipped = true;
+    let result = evaluator.eval(expr);
+    (result, evaluator.skipped)
+}
+
+/// Evaluates the expression like [`eval`] and additionally returns the semantic features that
+/// were exercised along the way. Subexpressions that were not evaluated (the branch of `?:` not
+/// taken, short-circuited operands) don't contribute to the trace.
+pub fn eval_traced(expr: &Expr, env: &Env) -> (Result<Value, EvalError>, Trace) {
+    let mut evaluator = Evaluator::new(env);
+    evaluator.trace = Some(Trace::new());
+    let result = evaluator.eval(expr);
+    (result, evaluator.trace.unwrap_or_default())
+}
+
+struct Evaluator<'a> {
+    env: &'a Env,
+    probe_skipped: bool,
+    skipped: Vec<SkippedOperand>,
+    trace: Option<Trace>,
+}
+
+impl<'a> Evaluator<'a> {
+    fn new(env: &'a Env) -> Self {
+        Self {
+            env,
+            probe_skipped: false,
+            skipped: Vec::new(),
+            trace: None,
+        }
+    }
+
+    fn record(&mut self, feature: impl FnOnce() -> Feature) {
+        if let Some(trace) = &mut self.trace {
+            trace.record(feature());
+        }
+    }
+
+    fn record_binary_op(&mut self, op: BinaryOp, l: &Value, r: &Value) {
+        self.record(|| Feature::BinaryOp {
+            op,
+            l: l.type_name(),
+            r: r.type_name(),
+        });
+        if let (Some(l), Some(r)) = (l.num_kind(), r.num_kind()) {
+            if numeric_op(op, l, r).is_some_and(|rule| rule.promotes(l, r)) {
+                self.record(|| Feature::IntToFloat);
+            }
+        }
+    }
+
+    /// Records the operand as skipped if evaluating it would fail at runtime. Other errors (unknown
+    /// names, mismatched types) make the whole expression invalid, so they are returned.
+    fn skip(&mut self, expr: &Expr) -> Result<(), EvalError> {
+        if !self.probe_skipped {
+            return Ok(());
+        }
+        // The operand is evaluated by a separate evaluator so that operands nested in it don't
+        // get reported - they would not have been reached in the actual evaluation either
+        match eval(expr, self.env) {
+            Err(error) if error.is_runtime() => self.skipped.push(SkippedOperand {
+                expr: expr.clone(),
+                error,
+            }),
+            Err(error) => return Err(error),
+            Ok(_) => {}
+        }
+        Ok(())
+    }
+
+    fn eval(&mut self, expr: &Expr) -> Result<Value, EvalError> {
+        match expr {
+            Expr::Int(x) => Ok(Value::Int(i128::from(*x))),
+            Expr::Float(x) => Ok(Value::Float(x.value())),
+            Expr::Str(x) => Ok(Value::Str(x.clone())),
+            Expr::Bool(x) => Ok(Value::Bool(*x)),
+            Expr::EnumMember { enum_path, label } => match self.env.enum_value(enum_path, label) {
+                Some(value) => Ok(Value::Enum {
+                    enum_path: enum_path.clone(),
+                    value,
+                }),
+                None => Err(EvalError::UnknownEnumMember {
+                    enum_path: enum_path.clone(),
+                    label: label.clone(),
+                }),
+            },
+            Expr::List(items) => {
+                if is_byte_array_literal(items) {
+                    // KSC types a list consisting only of integer literals in the range 0..=255
+                    // as a byte array, not as an array of integers
+                    return Ok(Value::Bytes(
+                        items
+                            .iter()
+                            .map(|item| match item {
+                                Expr::Int(x) => *x as u8,
+                                _ => unreachable!(),
+                            })
+                            .collect(),
+                    ));
+                }
+                let values = items
+                    .iter()
+                    .map(|item| self.eval(item))
+                    .collect::<Result<_, _>>()?;
+                Ok(Value::Array(values))
+            }
+
+            Expr::Name(name) => self
+                .env
+                .get(name)
+                .cloned()
+                .ok_or_else(|| EvalError::UnknownName(name.clone())),
+            Expr::Attribute { value, attr_name } => {
+                let value = self.eval(value)?;
+                if !matches!(value, Value::Struct(_)) {
+                    self.record(|| Feature::Builtin {
+                        receiver: value.type_name(),
+                        name: attr_name.clone(),
+                    });
+                }
+                self.eval_attribute(value, attr_name)
+            }
+            Expr::MethodCall {
+                value,
+                method_name,
+                args,
+            } => {
+                let value = self.eval(value)?;
+                let args = args
+                    .iter()
+                    .map(|arg| self.eval(arg))
+                    .collect::<Result<Vec<_>, _>>()?;
+                self.record(|| Feature::Builtin {
+                    receiver: value.type_name(),
+                    name: method_name.clone(),
+                });
+                self.eval_method_call(value, method_name, args)
+            }
+
+            Expr::UnaryOp { op, value } => {
+                let value = self.eval(value)?;
+                self.record(|| Feature::UnaryOp {
+                    op: *op,
+                    operand: value.type_name(),
+                });
+                eval_unary_op(*op, value)
+            }
+            Expr::BinaryOp {
+                l,
+                op: op @ (BinaryOp::And | BinaryOp::Or),
+                r,
+            } => {
+                // `and` and `or` are evaluated lazily in all target languages, so the right
+                // operand must not be evaluated at all if the left one decides the result
+                let l = self.eval(l)?;
+                match (op, &l) {
+                    (BinaryOp::And, Value::Bool(false)) | (BinaryOp::Or, Value::Bool(true)) => {
+                        self.record(|| Feature::ShortCircuit(*op));
+                        self.skip(r)?;
+                        Ok(l)
+                    }
+                    _ => {
+                        let r = self.eval(r)?;
+                        self.record_binary_op(*op, &l, &r);
+                        eval_binary_op(*op, l, r)
+                    }
+                }
+            }
+            Expr::BinaryOp { l, op, r } => {
+                let l = self.eval(l)?;
+                let r = self.eval(r)?;
+                self.record_binary_op(*op, &l, &r);
+                eval_binary_op(*op, l, r)
+            }
+            Expr::CondOp {
+                cond,
+                if_true,
+                if_false,
+            } => match self.eval(cond)? {
+                Value::Bool(true) => {
+                    self.record(|| Feature::CondBranch(true));
+                    self.skip(if_false)?;
+                    self.eval(if_true)
+                }
+                Value::Bool(false) => {
+                    self.record(|| Feature::CondBranch(false));
+                    self.skip(if_true)?;
+                    self.eval(if_false)
+                }
+                other => Err(EvalError::UnsupportedOperands {
+                    op: "?:",
+                    operands: other.type_name().to_string(),
+                }),
+            },
+            Expr::Subscript { value, idx } => {
+                let value = self.eval(value)?;
+                let idx = self.eval(idx)?;
+                self.record(|| Feature::Subscript {
+                    receiver: value.type_name(),
+                });
+                eval_subscript(value, idx)
+            }
+            Expr::CastTo { value, type_name } => match self.eval(value)? {
+                Value::Int(x) if !type_name.is_array && self.env.has_enum(&type_name.path) => {
+                    self.record(|| Feature::IntToEnum);
+                    Ok(Value::Enum {
+                        enum_path: type_name.path.clone(),
+                        value: x,
+                    })
+                }
+                // otherwise `.as<>` only changes the static type, the value is passed through
+                value => Ok(value),
+            },
+            Expr::SizeOf { type_name, bits } => {
+                let size = type_name
+                    .simple_name()
+                    .and_then(builtin_byte_size)
+                    .ok_or_else(|| EvalError::UnknownSize(type_name.to_string()))?;
+                Ok(Value::Int(i128::from(if *bits { size * 8 } else { size })))
+            }
+        }
+    }
+
+    fn eval_attribute(&mut self, value: Value, attr_name: &str) -> Result<Value, EvalError> {
+        let unknown = |value: &Value| EvalError::UnknownMember {
+            value_type: value.type_name(),
+            name: attr_name.to_string(),
+        };
+        match (&value, attr_name) {
+            (Value::Int(x), "to_s") => Ok(Value::Str(x.to_string())),
+            (Value::Float(x), "to_i") => {
+                let truncated = x.trunc();
+                if truncated.abs() >= 2f64.powi(127) {
+                    return Err(RuntimeError::IntegerOverflow.into());
+                }
+                Ok(Value::Int(truncated as i128))
+            }
+            (Value::Bool(x), "to_i") => Ok(Value::Int(i128::from(*x))),
+            (Value::Enum { value, .. }, "to_i") => Ok(Value::Int(*value)),
+
+            (Value::Str(x), "length") => Ok(Value::Int(x.chars().count() as i128)),
+            (Value::Str(x), "reverse") => Ok(Value::Str(x.chars().rev().collect())),
+            (Value::Str(x), "to_i") => str_to_i(x, 10),
+
+            (Value::Bytes(x), "length" | "size") => Ok(Value::Int(x.len() as i128)),
+            (Value::Bytes(x), "first" | "last" | "min" | "max") => {
+                let method = collection_method_name(attr_name);
+                let byte = match attr_name {
+                    "first" => x.first().copied(),
+                    "last" => x.last().copied(),
+                    "min" => x.iter().min().copied(),
+                    _ => x.iter().max().copied(),
+                };
+                byte.map(|b| Value::Int(i128::from(b)))
+                    .ok_or_else(|| RuntimeError::EmptyCollection { method }.into())
+            }
+
+            (Value::Array(x), "size") => Ok(Value::Int(x.len() as i128)),
+            (Value::Array(x), "first" | "last") => {
+                let method = collection_method_name(attr_name);
+                let item = if attr_name == "first" {
+                    x.first()
+                } else {
+                    x.last()
+                };
+                item.cloned()
+                    .ok_or_else(|| RuntimeError::EmptyCollection { method }.into())
+            }
+            (Value::Array(x), "min" | "max") => {
+                let method = collection_method_name(attr_name);
+                let mut items = x.iter();
+                let mut best = items
+                    .next()
+                    .ok_or(RuntimeError::EmptyCollection { method })?;
+                for item in items {
+                    let ordering = compare(method, best, item)?;
+                    let replace = if method == "min" {
+                        ordering.is_gt()
+                    } else {
+                        ordering.is_lt()
+                    };
+                    if replace {
+                        best = item;
+                    }
+                }
+                Ok(best.clone())
+            }
+
+            (Value::Struct(fields), _) => fields
+                .get(attr_name)
+                .cloned()
+                .ok_or_else(|| unknown(&value)),
+            _ => Err(unknown(&value)),
+        }
+    }
+
+    fn eval_method_call(
+        &mut self,
+        value: Value,
+        method_name: &str,
+        args: Vec<Value>,
+    ) -> Result<Value, EvalError> {
+        let expect_args = |expected: usize| {
+            if args.len() == expected {
+                Ok(())
+            } else {
+                Err(EvalError::ArgCount {
+                    method: method_name.to_string(),
+                    expected,
+                    actual: args.len(),
+                })
+            }
+        };
+        match (&value, method_name) {
+            (Value::Str(x), "substring") => {
+                expect_args(2)?;
+                match (&args[0], &args[1]) {
+                    (Value::Int(from), Value::Int(to)) => substring(x, *from, *to),
+                    _ => Err(EvalError::UnsupportedOperands {
+                        op: "substring",
+                        operands: describe_args(&args),
+                    }),
+                }
+            }
+            (Value::Str(x), "to_i") => {
+                expect_args(1)?;
+                match &args[0] {
+                    Value::Int(radix @ 2..=36) => str_to_i(x, *radix as u32),
+                    _ => Err(EvalError::UnsupportedOperands {
+                        op: "to_i",
+                        operands: describe_args(&args),
+                    }),
+                }
+            }
+            (Value::Bytes(x), "to_s") => {
+                expect_args(1)?;
+                match &args[0] {
+                    Value::Str(encoding) => decode_bytes(x, encoding),
+                    _ => Err(EvalError::UnsupportedOperands {
+                        op: "to_s",
+                        operands: describe_args(&args),
+                    }),
+                }
+            }
+            _ => Err(EvalError::UnknownMember {
+                value_type: value.type_name(),
+                name: method_name.to_string(),
+            }),
+        }
+    }
+}
+
+fn is_byte_array_literal(items: &[Expr]) -> bool {
+    !items.is_empty()
+        && items
+            .iter()
+            .all(|item| matches!(item, Expr::Int(x) if *x <= u64::from(u8::MAX)))
+}
+
+fn collection_method_name(attr_name: &str) -> &'static str {
+    match attr_name {
+        "first" => "first",
+        "last" => "last",
+        "min" => "min",
+        _ => "max",
+    }
+}
+
+fn describe_args(args: &[Value]) -> String {
+    args.iter()
+        .map(|arg| arg.type_name())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn describe_operands(l: &Value, r: &Value) -> String {
+    format!("{} and {}", l.type_name(), r.type_name())
+}
+
+fn str_to_i(s: &str, radix: u32) -> Result<Value, EvalError> {
+    i128::from_str_radix(s, radix)
+        .map(Value::Int)
+        .map_err(|_| RuntimeError::StrToIntConversion(s.to_string()).into())
+}
+
+fn substring(s: &str, from: i128, to: i128) -> Result<Value, EvalError> {
+    let len = s.chars().count();
+    if from < 0 || to < from || to > len as i128 {
+        return Err(RuntimeError::SubstringOutOfRange { from, to, len }.into());
+    }
+    Ok(Value::Str(
+        s.chars()
+            .skip(from as usize)
+            .take((to - from) as usize)
+            .collect(),
+    ))
+}
+
+fn decode_bytes(bytes: &[u8], encoding: &str) -> Result<Value, EvalError> {
+    match encoding.to_ascii_uppercase().as_str() {
+        "ASCII" if bytes.is_ascii() => Ok(Value::Str(bytes.iter().map(|&b| b as char).collect())),
+        "UTF-8" => match std::str::from_utf8(bytes) {
+            Ok(s) => Ok(Value::Str(s.to_string())),
+            Err(_) => Err(EvalError::UnsupportedOperands {
+                op: "to_s",
+                operands: "invalid UTF-8 byte array".to_string(),
+            }),
+        },
+        _ => Err(EvalError::UnsupportedOperands {
+            op: "to_s",
+            operands: format!("byte array and encoding {:?}", encoding),
+        }),
+    }
+}
+
+fn eval_subscript(value: Value, idx: Value) -> Result<Value, EvalError> {
+    let idx = match idx {
+        Value::Int(idx) => idx,
+        other => {
+            return Err(EvalError::UnsupportedOperands {
+                op: "[]",
+                operands: describe_operands(&value, &other),
+            })
+        }
+    };
+    let len = match &value {
+        Value::Array(items) => items.len(),
+        Value::Bytes(bytes) => bytes.len(),
+        other => {
+            return Err(EvalError::UnsupportedOperands {
+                op: "[]",
+                operands: describe_operands(other, &Value::Int(idx)),
+            })
+        }
+    };
+    if idx < 0 || idx >= len as i128 {
+        return Err(RuntimeError::IndexOutOfRange { idx, len }.into());
+    }
+    Ok(match value {
+        Value::Array(mut items) => items.swap_remove(idx as usize),
+        Value::Bytes(bytes) => Value::Int(i128::from(bytes[idx as usize])),
+        _ => unreachable!(),
+    })
+}
+
+fn eval_unary_op(op: UnaryOp, value: Value) -> Result<Value, EvalError> {
+    match (op, value) {
+        (UnaryOp::Neg, Value::Int(x)) => Ok(Value::Int(
+            x.checked_neg().ok_or(RuntimeError::IntegerOverflow)?,
+        )),
+        (UnaryOp::Neg, Value::Float(x)) => Ok(Value::Float(-x)),
+        (UnaryOp::Not, Value::Bool(x)) => Ok(Value::Bool(!x)),
+        (UnaryOp::Inv, Value::Int(x)) => Ok(Value::Int(!x)),
+        (op, value) => Err(EvalError::UnsupportedOperands {
+            op: op.symbol(),
+            operands: value.type_name().to_string(),
+        }),
+    }
+}
+
+fn eval_binary_op(op: BinaryOp, l: Value, r: Value) -> Result<Value, EvalError> {
+    let unsupported = |l: &Value, r: &Value| EvalError::UnsupportedOperands {
+        op: op.symbol(),
+        operands: describe_operands(l, r),
+    };
+    match op {
+        BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div | BinaryOp::Rem => {
+            match (&l, &r) {
+                _ if numeric_domain(op, &l, &r) == Some(NumKind::Int) => {
+                    Ok(Value::Int(int_arith(op, as_i128(&l), as_i128(&r))?))
+                }
+                _ if numeric_domain(op, &l, &r) == Some(NumKind::Float) => {
+                    Ok(Value::Float(float_arith(op, as_f64(&l), as_f64(&r))?))
+                }
+                (Value::Str(a), Value::Str(b)) if op == BinaryOp::Add => {
+                    Ok(Value::Str(format!("{}{}", a, b)))
+                }
+                _ => Err(unsupported(&l, &r)),
+            }
+        }
+
+        BinaryOp::Eq | BinaryOp::Ne => {
+            let eq = values_equal(op.symbol(), &l, &r)?;
+            Ok(Value::Bool(if op == BinaryOp::Eq { eq } else { !eq }))
+        }
+        BinaryOp::Lt | BinaryOp::Le | BinaryOp::Gt | BinaryOp::Ge => {
+            let ordering = compare(op.symbol(), &l, &r)?;
+            Ok(Value::Bool(match op {
+                BinaryOp::Lt => ordering.is_lt(),
+                BinaryOp::Le => ordering.is_le(),
+                BinaryOp::Gt => ordering.is_gt(),
+                _ => ordering.is_ge(),
+            }))
+        }
+
+        BinaryOp::And | BinaryOp::Or => match (&l, &r) {
+            (Value::Bool(a), Value::Bool(b)) => Ok(Value::Bool(if op == BinaryOp::And {
+                *a && *b
+            } else {
+                *a || *b
+            })),
+            _ => Err(unsupported(&l, &r)),
+        },
+
+        BinaryOp::BitOr | BinaryOp::BitXor | BinaryOp::BitAnd | BinaryOp::Shl | BinaryOp::Shr => {
+            match (&l, &r) {
+                (Value::Int(a), Value::Int(b)) => Ok(Value::Int(int_bitwise(op, *a, *b)?)),
+                _ => Err(unsupported(&l, &r)),
+            }
+        }
+    }
+}
+
+/// Kind in which `l op r` is carried out if both operands are numbers.
+fn numeric_domain(op: BinaryOp, l: &Value, r: &Value) -> Option<NumKind> {
+    Some(numeric_op(op, l.num_kind()?, r.num_kind()?)?.domain)
+}
+
+fn as_i128(value: &Value) -> i128 {
+    match value {
+        Value::Int(x) => *x,
+        _ => unreachable!(),
+    }
+}
+
+fn as_f64(value: &Value) -> f64 {
+    match value {
+        Value::Int(x) => *x as f64,
+        Value::Float(x) => *x,
+        _ => unreachable!(),
+    }
+}
+
+/// Integer arithmetic following the KS semantics: `/` rounds towards negative infinity and the
+/// result of `%` has the same sign as the divisor (see
+/// https://doc.kaitai.io/user_guide.html#_operators).
+fn int_arith(op: BinaryOp, a: i128, b: i128) -> Result<i128, RuntimeError> {
+    let result = match op {
+        BinaryOp::Add => a.checked_add(b),
+        BinaryOp::Sub => a.checked_sub(b),
+        BinaryOp::Mul => a.checked_mul(b),
+        BinaryOp::Div | BinaryOp::Rem => {
+            if b == 0 {
+                return Err(RuntimeError::DivisionByZero);
+            }
+            let (q, r) = (
+                a.checked_div(b).ok_or(RuntimeError::IntegerOverflow)?,
+                a.checked_rem(b).ok_or(RuntimeError::IntegerOverflow)?,
+            );
+            let needs_adjustment = r != 0 && ((r < 0) != (b < 0));
+            Some(match (op, needs_adjustment) {
+                (BinaryOp::Div, false) => q,
+                (BinaryOp::Div, true) => q - 1,
+                (_, false) => r,
+                (_, true) => r + b,
+            })
+        }
+        _ => unreachable!(),
+    };
+    result.ok_or(RuntimeError::IntegerOverflow)
+}
+
+fn float_arith(op: BinaryOp, a: f64, b: f64) -> Result<f64, RuntimeError> {
+    match op {
+        BinaryOp::Add => Ok(a + b),
+        BinaryOp::Sub => Ok(a - b),
+        BinaryOp::Mul => Ok(a * b),
+        BinaryOp::Div | BinaryOp::Rem if b == 0.0 => Err(RuntimeError::DivisionByZero),
+        BinaryOp::Div => Ok(a / b),
+        BinaryOp::Rem => Ok(a - b * (a / b).floor()),
+        _ => unreachable!(),
+    }
+}
+
+fn int_bitwise(op: BinaryOp, a: i128, b: i128) -> Result<i128, RuntimeError> {
+    match op {
+        BinaryOp::BitOr => Ok(a | b),
+        BinaryOp::BitXor => Ok(a ^ b),
+        BinaryOp::BitAnd => Ok(a & b),
+        BinaryOp::Shl | BinaryOp::Shr if b < 0 => Err(RuntimeError::NegativeShift(b)),
+        BinaryOp::Shl => {
+            if a == 0 {
+                return Ok(0);
+            }
+            if b >= i128::from(i128::BITS) {
+                return Err(RuntimeError::IntegerOverflow);
+            }
+            let result = a << b;
+            if result >> b != a {
+                return Err(RuntimeError::IntegerOverflow);
+            }
+            Ok(result)
+        }
+        BinaryOp::Shr => Ok(a >> b.min(i128::from(i128::BITS - 1))),
+        _ => unreachable!(),
+    }
+}
+
+fn values_equal(op: &'static str, l: &Value, r: &Value) -> Result<bool, EvalError> {
+    match (l, r) {
+        _ if numeric_domain(BinaryOp::Eq, l, r) == Some(NumKind::Int) => {
+            Ok(as_i128(l) == as_i128(r))
+        }
+        _ if numeric_domain(BinaryOp::Eq, l, r) == Some(NumKind::Float) => {
+            Ok(as_f64(l) == as_f64(r))
+        }
+        (Value::Str(a), Value::Str(b)) => Ok(a == b),
+        (Value::Bool(a), Value::Bool(b)) => Ok(a == b),
+        (Value::Bytes(a), Value::Bytes(b)) => Ok(a == b),
+        (
+            Value::Enum {
+                enum_path: path_a,
+                value: a,
+            },
+            Value::Enum {
+                enum_path: path_b,
+                value: b,
+            },
+        ) if path_a == path_b => Ok(a == b),
+        (Value::Array(a), Value::Array(b)) => {
+            if a.len() != b.len() {
+                return Ok(false);
+            }
+            for (x, y) in a.iter().zip(b) {
+                if !values_equal(op, x, y)? {
+                    return Ok(false);
+                }
+            }
+            Ok(true)
+        }
+        _ => Err(EvalError::UnsupportedOperands {
+            op,
+            operands: describe_operands(l, r),
+        }),
+    }
+}
+
+fn compare(op: &'static str, l: &Value, r: &Value) -> Result<std::cmp::Ordering, EvalError> {
+    let ordering = match (l, r) {
+        _ if numeric_domain(BinaryOp::Lt, l, r) == Some(NumKind::Int) => {
+            Some(as_i128(l).cmp(&as_i128(r)))
+        }
+        _ if numeric_domain(BinaryOp::Lt, l, r) == Some(NumKind::Float) => {
+            as_f64(l).partial_cmp(&as_f64(r))
+        }
+        (Value::Str(a), Value::Str(b)) => Some(a.cmp(b)),
+        (Value::Bytes(a), Value::Bytes(b)) => Some(a.cmp(b)),
+        _ => None,
+    };
+    ordering.ok_or_else(|| EvalError::UnsupportedOperands {
+        op,
+        operands: describe_operands(l, r),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::utils::PositiveFiniteF64;
+    use crate::ast::TypeName;
+
+    fn int(x: u64) -> Box<Expr> {
+        Box::new(Expr::Int(x))
+    }
+
+    fn neg(value: Box<Expr>) -> Box<Expr> {
+        Box::new(Expr::UnaryOp {
+            op: UnaryOp::Neg,
+            value,
+        })
+    }
+
+    fn binary(l: Box<Expr>, op: BinaryOp, r: Box<Expr>) -> Expr {
+        Expr::BinaryOp { l, op, r }
+    }
+
+    fn eval_empty(expr: &Expr) -> Result<Value, EvalError> {
+        eval(expr, &Env::new())
+    }
+
+    #[test]
+    fn int_div_rounds_towards_neg_infinity() {
+        let expr = binary(neg(int(7)), BinaryOp::Div, int(2));
+        assert_eq!(eval_empty(&expr), Ok(Value::Int(-4)));
+    }
+
+    #[test]
+    fn int_rem_has_sign_of_divisor() {
+        let expr = binary(neg(int(7)), BinaryOp::Rem, int(2));
+        assert_eq!(eval_empty(&expr), Ok(Value::Int(1)));
+        let expr = binary(int(7), BinaryOp::Rem, neg(int(2)));
+        assert_eq!(eval_empty(&expr), Ok(Value::Int(-1)));
+    }
+
+    #[test]
+    fn int_div_by_zero() {
+        let expr = binary(int(1), BinaryOp::Div, int(0));
+        let error = eval_empty(&expr).unwrap_err();
+        assert_eq!(error, EvalError::Runtime(RuntimeError::DivisionByZero));
+        assert!(error.is_runtime());
+    }
+
+    #[test]
+    fn float_rem_by_zero() {
+        let expr = binary(
+            Box::new(Expr::Float(PositiveFiniteF64::try_from(1.5).unwrap())),
+            BinaryOp::Rem,
+            int(0),
+        );
+        assert_eq!(
+            eval_empty(&expr),
+            Err(EvalError::Runtime(RuntimeError::DivisionByZero))
+        );
+    }
+
+    #[test]
+    fn negative_shift() {
+        let expr = binary(int(1), BinaryOp::Shl, neg(int(3)));
+        assert_eq!(
+            eval_empty(&expr),
+            Err(EvalError::Runtime(RuntimeError::NegativeShift(-3)))
+        );
+    }
+
+    #[test]
+    fn shl_beyond_u64() {
+        let expr = binary(int(1), BinaryOp::Shl, int(64));
+        assert_eq!(eval_empty(&expr), Ok(Value::Int(1 << 64)));
+    }
+
+    #[test]
+    fn substring_in_range() {
+        let expr = Expr::MethodCall {
+            value: Box::new(Expr::Str("0123456789".to_string())),
+            method_name: "substring".to_string(),
+            args: vec![Expr::Int(2), Expr::Int(7)],
+        };
+        assert_eq!(eval_empty(&expr), Ok(Value::Str("23456".to_string())));
+    }
+
+    #[test]
+    fn substring_out_of_range() {
+        let expr = Expr::MethodCall {
+            value: Box::new(Expr::Str("abc".to_string())),
+            method_name: "substring".to_string(),
+            args: vec![Expr::Int(1), Expr::Int(4)],
+        };
+        assert_eq!(
+            eval_empty(&expr),
+            Err(EvalError::Runtime(RuntimeError::SubstringOutOfRange {
+                from: 1,
+                to: 4,
+                len: 3
+            }))
+        );
+    }
+
+    #[test]
+    fn empty_array_max() {
+        let mut env = Env::new();
+        env.set("items", Value::Array(vec![]));
+        let expr = Expr::Attribute {
+            value: Box::new(Expr::Name("items".to_string())),
+            attr_name: "max".to_string(),
+        };
+        assert_eq!(
+            eval(&expr, &env),
+            Err(EvalError::Runtime(RuntimeError::EmptyCollection {
+                method: "max"
+            }))
+        );
+    }
+
+    #[test]
+    fn array_min() {
+        let expr = Expr::Attribute {
+            value: Box::new(Expr::List(vec![
+                Expr::Int(300),
+                Expr::UnaryOp {
+                    op: UnaryOp::Neg,
+                    value: Box::new(Expr::Int(5)),
+                },
+                Expr::Int(7),
+            ])),
+            attr_name: "min".to_string(),
+        };
+        assert_eq!(eval_empty(&expr), Ok(Value::Int(-5)));
+    }
+
+    #[test]
+    fn byte_array_literal() {
+        let expr = Expr::List(vec![Expr::Int(0x41), Expr::Int(0xff)]);
+        assert_eq!(eval_empty(&expr), Ok(Value::Bytes(vec![0x41, 0xff])));
+    }
+
+    #[test]
+    fn subscript_out_of_range() {
+        let expr = Expr::Subscript {
+            value: Box::new(Expr::List(vec![Expr::Int(1), Expr::Int(300)])),
+            idx: int(2),
+        };
+        assert_eq!(
+            eval_empty(&expr),
+            Err(EvalError::Runtime(RuntimeError::IndexOutOfRange {
+                idx: 2,
+                len: 2
+            }))
+        );
+    }
+
+    #[test]
+    fn enum_member_to_i() {
+        let mut env = Env::new();
+        env.define_enum(vec!["port".to_string()], [("http", 80)]);
+        let expr = Expr::Attribute {
+            value: Box::new(Expr::EnumMember {
+                enum_path: vec!["port".to_string()],
+                label: "http".to_string(),
+            }),
+            attr_name: "to_i".to_string(),
+        };
+        assert_eq!(eval(&expr, &env), Ok(Value::Int(80)));
+    }
+
+    #[test]
+    fn unknown_name_is_not_runtime_error() {
+        let error = eval_empty(&Expr::Name("foo".to_string())).unwrap_err();
+        assert_eq!(error, EvalError::UnknownName("foo".to_string()));
+        assert!(!error.is_runtime());
+    }
+
+    fn div_by_zero() -> Box<Expr> {
+        Box::new(binary(int(1), BinaryOp::Div, int(0)))
+    }
+
+    #[test]
+    fn and_short_circuits() {
+        let expr = binary(
+            Box::new(Expr::Bool(false)),
+            BinaryOp::And,
+            Box::new(binary(div_by_zero(), BinaryOp::Eq, int(1))),
+        );
+        let (result, skipped) = eval_with_skipped(&expr, &Env::new());
+        assert_eq!(result, Ok(Value::Bool(false)));
+        assert_eq!(skipped.len(), 1);
+        assert_eq!(
+            skipped[0].error,
+            EvalError::Runtime(RuntimeError::DivisionByZero)
+        );
+    }
+
+    #[test]
+    fn or_evaluates_right_operand() {
+        let expr = binary(
+            Box::new(Expr::Bool(false)),
+            BinaryOp::Or,
+            Box::new(binary(div_by_zero(), BinaryOp::Eq, int(1))),
+        );
+        let (result, skipped) = eval_with_skipped(&expr, &Env::new());
+        assert_eq!(
+            result,
+            Err(EvalError::Runtime(RuntimeError::DivisionByZero))
+        );
+        assert!(skipped.is_empty());
+    }
+
+    #[test]
+    fn cond_op_skips_other_branch() {
+        let expr = Expr::CondOp {
+            cond: Box::new(Expr::Bool(true)),
+            if_true: int(5),
+            if_false: div_by_zero(),
+        };
+        let (result, skipped) = eval_with_skipped(&expr, &Env::new());
+        assert_eq!(result, Ok(Value::Int(5)));
+        assert_eq!(skipped.len(), 1);
+        assert_eq!(skipped[0].expr, *div_by_zero());
+    }
+
+    #[test]
+    fn invalid_skipped_operand_is_an_error() {
+        let expr = binary(
+            Box::new(Expr::Bool(true)),
+            BinaryOp::Or,
+            Box::new(Expr::Name("unknown".to_string())),
+        );
+        let (result, skipped) = eval_with_skipped(&expr, &Env::new());
+        assert_eq!(result, Err(EvalError::UnknownName("unknown".to_string())));
+        assert!(skipped.is_empty());
+        assert_eq!(eval(&expr, &Env::new()), Ok(Value::Bool(true)));
+    }
+
+    #[test]
+    fn skipped_operand_that_succeeds_not_reported() {
+        let expr = binary(
+            Box::new(Expr::Bool(true)),
+            BinaryOp::Or,
+            Box::new(Expr::Bool(false)),
+        );
+        let (result, skipped) = eval_with_skipped(&expr, &Env::new());
+        assert_eq!(result, Ok(Value::Bool(true)));
+        assert!(skipped.is_empty());
+    }
+
+    #[test]
+    fn trace_records_taken_branch_only() {
+        let expr = Expr::CondOp {
+            cond: Box::new(binary(int(1), BinaryOp::Lt, int(2))),
+            if_true: Box::new(binary(
+                int(1),
+                BinaryOp::Add,
+                Box::new(Expr::Float(PositiveFiniteF64::try_from(0.5).unwrap())),
+            )),
+            if_false: Box::new(Expr::Attribute {
+                value: Box::new(Expr::Str("abc".to_string())),
+                attr_name: "length".to_string(),
+            }),
+        };
+        let (result, trace) = eval_traced(&expr, &Env::new());
+        assert_eq!(result, Ok(Value::Float(1.5)));
+        assert_eq!(trace.hits(&Feature::CondBranch(true)), 1);
+        assert_eq!(trace.hits(&Feature::CondBranch(false)), 0);
+        assert_eq!(trace.hits(&Feature::IntToFloat), 1);
+        assert_eq!(
+            trace.hits(&Feature::BinaryOp {
+                op: BinaryOp::Lt,
+                l: "integer",
+                r: "integer"
+            }),
+            1
+        );
+        assert_eq!(
+            trace.hits(&Feature::Builtin {
+                receiver: "string",
+                name: "length".to_string()
+            }),
+            0
+        );
+    }
+
+    #[test]
+    fn trace_records_short_circuit() {
+        let expr = binary(
+            Box::new(Expr::Bool(true)),
+            BinaryOp::Or,
+            Box::new(Expr::Bool(false)),
+        );
+        let (_, trace) = eval_traced(&expr, &Env::new());
+        assert_eq!(trace.hits(&Feature::ShortCircuit(BinaryOp::Or)), 1);
+        assert_eq!(trace.features().count(), 1);
+    }
+
+    #[test]
+    fn trace_uncovered_features() {
+        let (_, covered) = eval_traced(&binary(int(1), BinaryOp::Add, int(2)), &Env::new());
+        let (_, trace) = eval_traced(
+            &binary(
+                Box::new(binary(int(1), BinaryOp::Add, int(2))),
+                BinaryOp::Mul,
+                int(3),
+            ),
+            &Env::new(),
+        );
+        let uncovered: Vec<_> = trace.uncovered_by(&covered).collect();
+        assert_eq!(
+            uncovered,
+            vec![&Feature::BinaryOp {
+                op: BinaryOp::Mul,
+                l: "integer",
+                r: "integer"
+            }]
+        );
+    }
+
+    #[test]
+    fn str_concat_with_int() {
+        let expr = binary(Box::new(Expr::Str("a".to_string())), BinaryOp::Add, int(3));
+        assert_eq!(
+            eval_empty(&expr),
+            Err(EvalError::UnsupportedOperands {
+                op: "+",
+                operands: "string and integer".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn sizeof_builtin() {
+        let size_of = |name: &str, bits| Expr::SizeOf {
+            type_name: TypeName::new(name),
+            bits,
+        };
+        assert_eq!(eval_empty(&size_of("u4", false)), Ok(Value::Int(4)));
+        assert_eq!(eval_empty(&size_of("f8", true)), Ok(Value::Int(64)));
+        assert_eq!(
+            eval_empty(&size_of("header", false)),
+            Err(EvalError::UnknownSize("header".to_string()))
+        );
+    }
+
+    #[test]
+    fn int_to_enum_cast() {
+        let mut env = Env::new();
+        env.define_enum(vec!["animal".to_string()], [("cat", 7), ("dog", 4)]);
+        let expr = Expr::CastTo {
+            value: int(4),
+            type_name: TypeName::new("animal"),
+        };
+        let (result, trace) = eval_traced(&expr, &env);
+        assert_eq!(
+            result,
+            Ok(Value::Enum {
+                enum_path: vec!["animal".to_string()],
+                value: 4,
+            })
+        );
+        assert_eq!(trace.hits(&Feature::IntToEnum), 1);
+    }
+}
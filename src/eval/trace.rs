@@ -0,0 +1,74 @@
+use std::collections::BTreeMap;
+
+use crate::ast::{BinaryOp, UnaryOp};
+
+/// Semantic feature of the expression language exercised during an evaluation.
+///
+/// Unlike the syntactic structure of an expression, features take the runtime types of the
+/// operands into account: `1 + 2`, `1 + 2.5` and `'a' + 'b'` all use the same operator, but
+/// exercise different code paths in the translators and runtimes.
+#[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum Feature {
+    UnaryOp {
+        op: UnaryOp,
+        operand: &'static str,
+    },
+    BinaryOp {
+        op: BinaryOp,
+        l: &'static str,
+        r: &'static str,
+    },
+    /// Implicit conversion of an integer operand to a float
+    IntToFloat,
+    /// Conversion of an integer to an enum with `.as<>`
+    IntToEnum,
+    /// The right operand of `and`/`or` was not evaluated
+    ShortCircuit(BinaryOp),
+    /// Branch of the `?:` operator that was taken
+    CondBranch(bool),
+    /// Built-in attribute or method
+    Builtin {
+        receiver: &'static str,
+        name: String,
+    },
+    Subscript {
+        receiver: &'static str,
+    },
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Trace {
+    hits: BTreeMap<Feature, usize>,
+}
+
+impl Trace {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, feature: Feature) {
+        *self.hits.entry(feature).or_insert(0) += 1;
+    }
+
+    pub fn hits(&self, feature: &Feature) -> usize {
+        self.hits.get(feature).copied().unwrap_or(0)
+    }
+
+    pub fn features(&self) -> impl Iterator<Item = &Feature> {
+        self.hits.keys()
+    }
+
+    /// Adds all hits of `other` to this trace, e.g. to accumulate the coverage of a whole
+    /// generation session.
+    pub fn merge(&mut self, other: &Trace) {
+        for (feature, count) in &other.hits {
+            *self.hits.entry(feature.clone()).or_insert(0) += count;
+        }
+    }
+
+    /// Features of `self` that `covered` doesn't contain.
+    pub fn uncovered_by<'a>(&'a self, covered: &'a Trace) -> impl Iterator<Item = &'a Feature> {
+        self.features()
+            .filter(move |feature| covered.hits(feature) == 0)
+    }
+}
@@ -0,0 +1,40 @@
+//! Generation of the building blocks of test cases.
+
+pub mod bytes;
+pub mod case;
+pub mod cast;
+pub mod check;
+pub mod cond;
+pub mod contents;
+pub mod corpus;
+pub mod coverage;
+pub mod endian;
+pub mod enums;
+pub mod eos;
+pub mod exhaustive;
+pub mod expr;
+pub mod idents;
+pub mod imports;
+pub mod instances;
+pub mod mutate;
+pub mod naming;
+pub mod nested;
+pub mod opaque;
+pub mod pad;
+pub mod pairwise;
+pub mod params;
+pub mod pos;
+pub mod primitive;
+pub mod process;
+pub mod profile;
+pub mod recursive;
+pub mod repeat;
+pub mod shrink;
+pub mod spec;
+pub mod string;
+pub mod substream;
+pub mod suite;
+pub mod switch;
+pub mod terminator;
+pub mod valid;
+pub mod version;
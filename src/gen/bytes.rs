@@ -0,0 +1,132 @@
+//! Raw byte array attributes (no `type`), sized in the ways that make the runtimes slice the
+//! stream differently: a literal `size`, a `size` computed from an earlier attribute and
+//! `size-eos`.
+
+use rand::Rng;
+
+use crate::ast::{BinaryOp, Expr};
+use crate::ksy::Attribute;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum BytesForm {
+    /// `size: <literal>`
+    Size,
+    /// `size` computed from a preceding `u1` length attribute
+    SizeExpr,
+    /// `size-eos: true`, which only makes sense for the last attribute of a stream
+    SizeEos,
+}
+
+impl BytesForm {
+    pub const ALL: [BytesForm; 3] = [BytesForm::Size, BytesForm::SizeExpr, BytesForm::SizeEos];
+}
+
+/// Byte array attribute (preceded by the attributes its size depends on), with the bytes they all
+/// read and the expected content of the byte array.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BytesField {
+    pub attrs: Vec<Attribute>,
+    pub bytes: Vec<u8>,
+    pub expected: Vec<u8>,
+}
+
+/// Byte array attribute `id` of up to `max_len` (at most 255) random bytes.
+pub fn bytes_field<R: Rng + ?Sized>(
+    rng: &mut R,
+    id: &str,
+    form: BytesForm,
+    max_len: u8,
+) -> BytesField {
+    let len = rng.gen_range(0..=max_len);
+    let expected: Vec<u8> = (0..len).map(|_| rng.gen()).collect();
+    let mut attrs = Vec::new();
+    let mut bytes = Vec::new();
+    let attr = match form {
+        BytesForm::Size => Attribute {
+            size: Some(Expr::Int(len.into())),
+            ..Default::default()
+        },
+        BytesForm::SizeExpr => {
+            // the length attribute is off by a random amount, corrected in the expression
+            let offset = rng.gen_range(0..=u8::MAX - len);
+            let len_id = format!("{}_len", id);
+            attrs.push(Attribute::new(&len_id, "u1"));
+            bytes.push(len + offset);
+            let len_ref = Expr::Name(len_id);
+            let size = if offset == 0 {
+                len_ref
+            } else {
+                Expr::BinaryOp {
+                    l: Box::new(len_ref),
+                    op: BinaryOp::Sub,
+                    r: Box::new(Expr::Int(offset.into())),
+                }
+            };
+            Attribute {
+                size: Some(size),
+                ..Default::default()
+            }
+        }
+        BytesForm::SizeEos => Attribute {
+            size_eos: Some(true),
+            ..Default::default()
+        },
+    };
+    attrs.push(Attribute {
+        id: Some(id.to_string()),
+        ..attr
+    });
+    bytes.extend(&expected);
+    BytesField {
+        attrs,
+        bytes,
+        expected,
+    }
+}
+
+/// One field of each [`BytesForm`], named after the form, with the `size-eos` one last so that
+/// the fields can share a stream.
+pub fn bytes_fields<R: Rng + ?Sized>(rng: &mut R, max_len: u8) -> Vec<BytesField> {
+    BytesForm::ALL
+        .into_iter()
+        .map(|form| {
+            let id = match form {
+                BytesForm::Size => "buf_size",
+                BytesForm::SizeExpr => "buf_size_expr",
+                BytesForm::SizeEos => "buf_size_eos",
+            };
+            bytes_field(rng, id, form, max_len)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::{eval, Env, Value};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn size_matches_content() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..20 {
+            let fields = bytes_fields(&mut rng, 16);
+            assert_eq!(fields.last().unwrap().attrs[0].size_eos, Some(true));
+            for field in fields {
+                let mut env = Env::new();
+                let (header, data) = field.bytes.split_at(field.attrs.len() - 1);
+                for (attr, byte) in field.attrs.iter().zip(header) {
+                    env.set(attr.id.clone().unwrap(), Value::Int((*byte).into()));
+                }
+                assert_eq!(data, field.expected);
+                if let Some(size) = &field.attrs.last().unwrap().size {
+                    assert_eq!(
+                        eval(size, &env),
+                        Ok(Value::Int(field.expected.len() as i128))
+                    );
+                }
+            }
+        }
+    }
+}
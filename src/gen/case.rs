@@ -0,0 +1,13 @@
+//! Case shared by the generators that make a single input for their spec.
+
+use crate::ast::Expr;
+use crate::eval::Value;
+use crate::ksy::KsySpec;
+
+/// Spec with data for it and the expected values of expressions over the parsed data.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpecCase {
+    pub spec: KsySpec,
+    pub data: Vec<u8>,
+    pub assertions: Vec<(Expr, Value)>,
+}
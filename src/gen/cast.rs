@@ -0,0 +1,227 @@
+//! Value instances full of `.as<>` casts: between integer widths, from integers to enums (and
+//! back with `.to_i`), and from a switch of user types to one of its cases. Every generated
+//! expression is checked with [`infer`] before it makes it into the spec.
+
+use indexmap::IndexMap;
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+use crate::ast::utils::{attr, name};
+use crate::ast::{BinaryOp, Expr, TypeName};
+use crate::eval::{eval, Env, Value};
+use crate::gen::case::SpecCase;
+use crate::ksy::{Attribute, KsySpec, TypeRef, TypeSpec};
+use crate::numeric::IntType;
+use crate::typing::{infer, TypeEnv};
+
+/// Integer attributes at the start of the data
+const INT_ATTRS: [(&str, &str); 3] = [("a", "u1"), ("b", "s2le"), ("c", "u4le")];
+const ENUM_NAME: &str = "variant";
+/// Labels of [`ENUM_NAME`], with the values 0 and 1; `kind` selects the type of `body` by them
+const LABELS: [&str; 2] = ["first", "second"];
+/// Types of `body` for each value of `kind`
+const BODY_TYPES: [&str; 2] = ["rec_first", "rec_second"];
+
+fn cast(value: Expr, type_name: &str) -> Expr {
+    Expr::CastTo {
+        value: Box::new(value),
+        type_name: TypeName::new(type_name),
+    }
+}
+
+/// The seq reads the [`INT_ATTRS`], `kind: u1`, `kind_e: u1` with the enum [`ENUM_NAME`] and
+/// `body`, which switches on `kind` between the [`BODY_TYPES`].
+fn cast_spec(id: &str) -> KsySpec {
+    let mut spec = KsySpec::top_level(id);
+    for (attr_name, type_name) in INT_ATTRS {
+        spec.seq.push(Attribute::new(attr_name, type_name));
+    }
+    spec.seq.push(Attribute::new("kind", "u1"));
+    spec.seq.push(Attribute {
+        enum_name: Some(ENUM_NAME.to_string()),
+        ..Attribute::new("kind_e", "u1")
+    });
+    let cases: IndexMap<Expr, String> = BODY_TYPES
+        .iter()
+        .enumerate()
+        .map(|(i, type_name)| (Expr::Int(i as u64), type_name.to_string()))
+        .collect();
+    spec.seq.push(Attribute {
+        id: Some("body".to_string()),
+        type_ref: Some(TypeRef::Switch {
+            switch_on: name("kind"),
+            cases,
+        }),
+        ..Default::default()
+    });
+    spec.types.insert(
+        BODY_TYPES[0].to_string(),
+        TypeSpec {
+            seq: vec![Attribute::new("x", "u1")],
+            ..Default::default()
+        },
+    );
+    spec.types.insert(
+        BODY_TYPES[1].to_string(),
+        TypeSpec {
+            seq: vec![Attribute::new("x", "u2le"), Attribute::new("y", "u1")],
+            ..Default::default()
+        },
+    );
+    spec.enums.insert(
+        ENUM_NAME.to_string(),
+        LABELS
+            .iter()
+            .enumerate()
+            .map(|(i, label)| (i as i128, label.to_string()))
+            .collect(),
+    );
+    spec
+}
+
+fn int_type(type_name: &str) -> IntType {
+    IntType::from_name(type_name.trim_end_matches("le")).expect("must be an integer type")
+}
+
+/// Integer expression with up to `depth` levels of casts and arithmetic. `kind` is the type of
+/// `body` that casts to a user type go to.
+fn int_expr<R: Rng + ?Sized>(rng: &mut R, depth: usize, kind: usize) -> Expr {
+    if depth == 0 {
+        return match rng.gen_range(0..4) {
+            0 => attr(cast(name("body"), BODY_TYPES[kind]), "x"),
+            1 => attr(name("kind_e"), "to_i"),
+            _ => name(INT_ATTRS.choose(rng).unwrap().0),
+        };
+    }
+    match rng.gen_range(0..3) {
+        0 => {
+            let ty = IntType::ALL.choose(rng).unwrap();
+            cast(int_expr(rng, depth - 1, kind), &ty.name())
+        }
+        // `kind` holds a member of the enum, so the conversion round-trips
+        1 => attr(cast(name("kind"), ENUM_NAME), "to_i"),
+        _ => Expr::BinaryOp {
+            l: Box::new(int_expr(rng, depth - 1, kind)),
+            op: *[BinaryOp::Add, BinaryOp::Sub, BinaryOp::Mul]
+                .choose(rng)
+                .unwrap(),
+            r: Box::new(int_expr(rng, depth - 1, kind)),
+        },
+    }
+}
+
+/// Case with `count` cast-heavy value instances `cast_<i>` of at most `max_depth` levels.
+/// Expressions failing to type-check or to evaluate are generated again, up to 100 times each.
+pub fn cast_case<R: Rng + ?Sized>(
+    rng: &mut R,
+    id: &str,
+    count: usize,
+    max_depth: usize,
+) -> SpecCase {
+    let kind = rng.gen_range(0..BODY_TYPES.len());
+    let kind_e = rng.gen_range(0..LABELS.len());
+    let ints: Vec<i128> = INT_ATTRS
+        .iter()
+        .map(|(_, type_name)| {
+            let ty = int_type(type_name);
+            rng.gen_range(ty.min_value()..=ty.max_value())
+        })
+        .collect();
+    let mut data = Vec::new();
+    let mut env = Env::new();
+    for ((attr_name, type_name), value) in INT_ATTRS.iter().zip(&ints) {
+        let width = usize::from(int_type(type_name).width);
+        data.extend(&(*value as u128).to_le_bytes()[..width]);
+        env.set(*attr_name, Value::Int(*value));
+    }
+    data.extend([kind as u8, kind_e as u8]);
+    env.set("kind", Value::Int(kind as i128));
+    env.set(
+        "kind_e",
+        Value::Enum {
+            enum_path: vec![ENUM_NAME.to_string()],
+            value: kind_e as i128,
+        },
+    );
+    env.define_enum(
+        vec![ENUM_NAME.to_string()],
+        LABELS.iter().enumerate().map(|(i, l)| (*l, i as i128)),
+    );
+    let x: u16 = match kind {
+        0 => rng.gen::<u8>().into(),
+        _ => rng.gen(),
+    };
+    let mut body = vec![("x".to_string(), Value::Int(x.into()))];
+    match kind {
+        0 => data.push(x as u8),
+        _ => {
+            let y: u8 = rng.gen();
+            data.extend(x.to_le_bytes());
+            data.push(y);
+            body.push(("y".to_string(), Value::Int(y.into())));
+        }
+    }
+    env.set("body", Value::Struct(body.into_iter().collect()));
+
+    let mut spec = cast_spec(id);
+    let type_env = TypeEnv::from_spec(&spec, &[]).expect("spec must be well-formed");
+    let mut assertions = Vec::new();
+    for i in 0..count {
+        for _ in 0..100 {
+            let depth = rng.gen_range(1..=max_depth.max(1));
+            let expr = int_expr(rng, depth, kind);
+            if infer(&expr, &type_env).is_err() {
+                continue;
+            }
+            let Ok(value) = eval(&expr, &env) else {
+                continue;
+            };
+            let instance_name = format!("cast_{}", i);
+            spec.instances
+                .insert(instance_name.clone(), Attribute::value_instance(expr));
+            assertions.push((name(&instance_name), value));
+            break;
+        }
+    }
+    SpecCase {
+        spec,
+        data,
+        assertions,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::typing::KsType;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn user_type_needs_cast() {
+        let spec = cast_spec("cast");
+        let env = TypeEnv::from_spec(&spec, &[]).unwrap();
+        assert_eq!(env.get("body"), Some(&KsType::AnyStruct));
+        assert!(infer(&attr(name("body"), "x"), &env).is_err());
+        let x = attr(cast(name("body"), "rec_second"), "x");
+        assert_eq!(infer(&x, &env).map(|ty| ty.widened()), Ok(KsType::Int));
+        let to_enum = cast(name("a"), ENUM_NAME);
+        let expected = KsType::Enum(vec![ENUM_NAME.to_string()]);
+        assert_eq!(infer(&to_enum, &env), Ok(expected));
+    }
+
+    #[test]
+    fn instances_type_check() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..10 {
+            let case = cast_case(&mut rng, "cast", 8, 3);
+            assert_eq!(case.assertions.len(), 8);
+            let body_len = if case.data[7] == 0 { 1 } else { 3 };
+            assert_eq!(case.data.len(), 9 + body_len);
+            let env = TypeEnv::from_spec(&case.spec, &[]).unwrap();
+            for instance in case.spec.instances.values() {
+                assert!(infer(instance.value.as_ref().unwrap(), &env).is_ok());
+            }
+        }
+    }
+}
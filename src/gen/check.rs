@@ -0,0 +1,144 @@
+//! Internal consistency of generated cases, for fuzzing the generator: the specs match the
+//! schema, every input reads back with the interpreter as its expected outcome, and synthesized
+//! data regenerates from the seed of its case.
+//!
+//! Inputs of specs that the interpreter doesn't support, and expressions that the reference
+//! evaluator can't evaluate on the fields that were read, are left unchecked.
+
+use thiserror::Error;
+
+use crate::datagen::interpret::{read, same_value, ReadError};
+use crate::eval::{eval, Env};
+use crate::gen::profile::GenProfile;
+use crate::gen::suite::{regenerate_input, GenCase, Outcome, ParseError};
+use crate::schema::{InvalidSpec, Schema};
+use crate::translator::translate;
+
+#[derive(Clone, Debug, Error, PartialEq)]
+pub enum Inconsistency {
+    #[error(transparent)]
+    Schema(#[from] InvalidSpec),
+    #[error("input {input} reads as {read}, but {expected} is expected")]
+    Outcome {
+        input: usize,
+        read: String,
+        expected: String,
+    },
+    #[error("input {input}: `{expr}` is {actual} instead of {expected}")]
+    Value {
+        input: usize,
+        expr: String,
+        actual: String,
+        expected: String,
+    },
+    #[error("input {0} doesn't regenerate from the seed of the case")]
+    Regeneration(usize),
+}
+
+/// Checks the case, generated with the profile.
+pub fn check_case(
+    case: &GenCase,
+    profile: &GenProfile,
+    schema: &Schema,
+) -> Result<(), Inconsistency> {
+    schema.check_spec(&case.spec)?;
+    for spec in &case.extra_specs {
+        schema.check_spec(spec)?;
+    }
+    for (index, input) in case.inputs.iter().enumerate() {
+        if let Some(source) = input.source {
+            if regenerate_input(case, source, profile).as_ref() != Some(&input.data) {
+                return Err(Inconsistency::Regeneration(index));
+            }
+        }
+        let outcome_error = |read: String| Inconsistency::Outcome {
+            input: index,
+            read,
+            expected: match &input.outcome {
+                Outcome::Values(_) => "success".to_string(),
+                Outcome::Error(error) => format!("{:?}", error),
+            },
+        };
+        match (read(&case.spec, &input.data), &input.outcome) {
+            (Err(ReadError::Spec(_)), _) => {}
+            (Ok(fields), Outcome::Values(values)) => {
+                let mut env = Env::new();
+                for (name, value) in fields {
+                    env.set(name, value);
+                }
+                for (expr, expected) in values {
+                    match eval(expr, &env) {
+                        Ok(actual) if !same_value(&actual, expected) => {
+                            return Err(Inconsistency::Value {
+                                input: index,
+                                expr: translate(expr),
+                                actual: format!("{:?}", actual),
+                                expected: format!("{:?}", expected),
+                            })
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            (Ok(_), Outcome::Error(_)) => return Err(outcome_error("success".to_string())),
+            (Err(error), Outcome::Error(expected)) => {
+                let matches = matches!(
+                    (&error, expected),
+                    (ReadError::EndOfStream(_), ParseError::EndOfStream)
+                        | (ReadError::Validation(..), ParseError::Validation)
+                        | (
+                            ReadError::UndecidedEndianness(_),
+                            ParseError::UndecidedEndianness
+                        )
+                );
+                if !matches {
+                    return Err(outcome_error(error.to_string()));
+                }
+            }
+            (Err(error), Outcome::Values(_)) => return Err(outcome_error(error.to_string())),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gen::suite::generate_suite;
+
+    #[test]
+    fn generated_cases_are_consistent() {
+        let profile = GenProfile::default();
+        let schema = Schema::bundled();
+        for case in generate_suite(0, 100, &profile) {
+            assert_eq!(check_case(&case, &profile, &schema), Ok(()), "{}", case.id);
+        }
+    }
+
+    #[test]
+    fn inconsistencies() {
+        let profile = GenProfile::default();
+        let schema = Schema::bundled();
+        let readable = |case: &GenCase| {
+            case.inputs.iter().position(|input| {
+                matches!(input.outcome, Outcome::Values(_)) && read(&case.spec, &input.data).is_ok()
+            })
+        };
+        let mut case = generate_suite(1, 20, &profile)
+            .into_iter()
+            .find(|case| readable(case).is_some())
+            .unwrap();
+        let index = readable(&case).unwrap();
+        let input = &mut case.inputs[index];
+        input.source = None;
+        input.outcome = Outcome::Error(ParseError::EndOfStream);
+        assert_eq!(
+            check_case(&case, &profile, &schema),
+            Err(Inconsistency::Outcome {
+                input: index,
+                read: "success".to_string(),
+                expected: "EndOfStream".to_string(),
+            })
+        );
+    }
+}
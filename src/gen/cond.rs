@@ -0,0 +1,147 @@
+//! Attributes guarded by `if` conditions of varying complexity, with data for which the attribute
+//! is present and data for which it's absent.
+
+use rand::Rng;
+
+use crate::ast::utils::name;
+use crate::ast::Expr;
+use crate::divergence::diverging_targets;
+use crate::eval::{eval, Env, Value};
+use crate::gen::expr::ExprGenerator;
+use crate::ksy::{Attribute, KsySpec};
+use crate::typing::{KsType, TypeEnv};
+
+/// `u1` attributes read before the conditional one, which the condition can refer to
+const HEADER: [&str; 3] = ["a", "b", "c"];
+
+/// Spec with a `u2le` attribute `opt` guarded by an `if` condition over the `u1` attributes
+/// before it and followed by a `u1` attribute `tail`, with data for both outcomes.
+#[derive(Clone, Debug, PartialEq)]
+pub struct IfCase {
+    pub spec: KsySpec,
+    pub variants: Vec<IfData>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct IfData {
+    pub data: Vec<u8>,
+    /// Whether the condition holds, i.e. `opt` is read
+    pub present: bool,
+    /// Expected values of `opt` (if it's read) and `tail`
+    pub assertions: Vec<(Expr, Value)>,
+}
+
+/// Case with a condition of at most `max_depth` levels of operators, or `None` if no condition
+/// taking both branches was found.
+pub fn if_case<R: Rng + ?Sized>(rng: &mut R, id: &str, max_depth: usize) -> Option<IfCase> {
+    let mut type_env = TypeEnv::new();
+    for name in HEADER {
+        type_env.set(name, KsType::Int);
+    }
+    let generator = ExprGenerator::new(&type_env, rng.gen_range(1..=max_depth.max(1)));
+    for _ in 0..1000 {
+        let cond = generator.generate(rng, &KsType::Bool)?;
+        if !refers_to_header(&cond) {
+            continue;
+        }
+        let (mut if_true, mut if_false) = (None, None);
+        for _ in 0..64 {
+            let header: Vec<u8> = HEADER.iter().map(|_| rng.gen()).collect();
+            let mut env = Env::new();
+            for (name, byte) in HEADER.iter().zip(&header) {
+                env.set(*name, Value::Int((*byte).into()));
+            }
+            // data that some target takes the other branch for is no use
+            if !diverging_targets(&cond, &env).is_empty() {
+                continue;
+            }
+            match eval(&cond, &env) {
+                Ok(Value::Bool(true)) => if_true = Some(header),
+                Ok(Value::Bool(false)) => if_false = Some(header),
+                _ => {}
+            }
+            if if_true.is_some() && if_false.is_some() {
+                break;
+            }
+        }
+        let (Some(if_true), Some(if_false)) = (if_true, if_false) else {
+            continue;
+        };
+
+        let mut spec = KsySpec::top_level(id);
+        spec.seq = HEADER
+            .iter()
+            .map(|name| Attribute::new(*name, "u1"))
+            .collect();
+        spec.seq.push(Attribute {
+            if_expr: Some(cond),
+            ..Attribute::new("opt", "u2le")
+        });
+        spec.seq.push(Attribute::new("tail", "u1"));
+        let variants = [(if_true, true), (if_false, false)]
+            .into_iter()
+            .map(|(mut data, present)| {
+                let mut assertions = Vec::new();
+                if present {
+                    let opt: u16 = rng.gen();
+                    data.extend(opt.to_le_bytes());
+                    assertions.push((name("opt"), Value::Int(opt.into())));
+                }
+                let tail: u8 = rng.gen();
+                data.push(tail);
+                assertions.push((name("tail"), Value::Int(tail.into())));
+                IfData {
+                    data,
+                    present,
+                    assertions,
+                }
+            })
+            .collect();
+        return Some(IfCase { spec, variants });
+    }
+    None
+}
+
+fn refers_to_header(expr: &Expr) -> bool {
+    matches!(expr, Expr::Name(name) if HEADER.contains(&name.as_str()))
+        || expr.children().into_iter().any(refers_to_header)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn both_branches() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..20 {
+            let case = if_case(&mut rng, "cond", 3).unwrap();
+            let cond = case.spec.seq[HEADER.len()].if_expr.as_ref().unwrap();
+            for variant in &case.variants {
+                let mut env = Env::new();
+                for (name, byte) in HEADER.iter().zip(&variant.data) {
+                    env.set(*name, Value::Int((*byte).into()));
+                }
+                assert_eq!(eval(cond, &env), Ok(Value::Bool(variant.present)));
+                assert!(diverging_targets(cond, &env).is_empty());
+                let opt_len = if variant.present { 2 } else { 0 };
+                assert_eq!(variant.data.len(), HEADER.len() + opt_len + 1);
+                let names: Vec<&Expr> = variant.assertions.iter().map(|(expr, _)| expr).collect();
+                if variant.present {
+                    let opt = u16::from_le_bytes([variant.data[3], variant.data[4]]);
+                    assert_eq!(names, [&name("opt"), &name("tail")]);
+                    assert_eq!(variant.assertions[0].1, Value::Int(opt.into()));
+                } else {
+                    assert_eq!(names, [&name("tail")]);
+                }
+                let tail = *variant.data.last().unwrap();
+                assert_eq!(
+                    variant.assertions.last().unwrap().1,
+                    Value::Int(tail.into())
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,134 @@
+//! `contents` attributes in each of the accepted YAML forms, with data that matches them or (for
+//! tests expecting a validation error) differs in one byte.
+
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+use crate::ksy::{Attribute, Contents, ContentsItem};
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum ContentsForm {
+    /// `contents: ABC`
+    Str,
+    /// `contents: [0x41, 0x42, 0x43]`
+    Bytes,
+    /// `contents: [0x41, BC]`
+    Mixed,
+}
+
+impl ContentsForm {
+    pub const ALL: [ContentsForm; 3] =
+        [ContentsForm::Str, ContentsForm::Bytes, ContentsForm::Mixed];
+}
+
+/// `contents` attribute with the bytes to put in the stream for it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ContentsField {
+    pub attr: Attribute,
+    pub bytes: Vec<u8>,
+    /// Index of the byte that differs from the expected contents, if any
+    pub mismatch_at: Option<usize>,
+}
+
+/// Characters of the string parts. Letters only, so that their UTF-8 encoding is obvious and they
+/// can't be mistaken for numbers in the YAML.
+const STR_ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+
+fn sample_str<R: Rng + ?Sized>(rng: &mut R, len: usize) -> String {
+    (0..len)
+        .map(|_| char::from(*STR_ALPHABET.choose(rng).unwrap()))
+        .collect()
+}
+
+/// Random contents of 1 to `max_len` (at least 2) bytes in the given form.
+pub fn sample_contents<R: Rng + ?Sized>(
+    rng: &mut R,
+    form: ContentsForm,
+    max_len: usize,
+) -> Contents {
+    match form {
+        ContentsForm::Str => {
+            let len = rng.gen_range(1..=max_len);
+            Contents::Str(sample_str(rng, len))
+        }
+        ContentsForm::Bytes => {
+            Contents::Bytes((0..rng.gen_range(1..=max_len)).map(|_| rng.gen()).collect())
+        }
+        ContentsForm::Mixed => {
+            // at least one item of each kind
+            let str_len = rng.gen_range(1..max_len);
+            let mut items = vec![ContentsItem::Str(sample_str(rng, str_len))];
+            items.extend(
+                (0..rng.gen_range(1..=max_len - str_len)).map(|_| ContentsItem::Byte(rng.gen())),
+            );
+            items.shuffle(rng);
+            Contents::Mixed(items)
+        }
+    }
+}
+
+/// `contents` attribute `id`. If `mismatch` is set, one byte of the data differs from the
+/// contents.
+pub fn contents_field<R: Rng + ?Sized>(
+    rng: &mut R,
+    id: &str,
+    form: ContentsForm,
+    max_len: usize,
+    mismatch: bool,
+) -> ContentsField {
+    let contents = sample_contents(rng, form, max_len);
+    let mut bytes = contents.to_bytes();
+    let mismatch_at = mismatch.then(|| {
+        let i = rng.gen_range(0..bytes.len());
+        bytes[i] ^= rng.gen_range(1..=u8::MAX);
+        i
+    });
+    ContentsField {
+        attr: Attribute {
+            id: Some(id.to_string()),
+            contents: Some(contents),
+            ..Default::default()
+        },
+        bytes,
+        mismatch_at,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn yaml_forms() {
+        let attr = |contents| Attribute {
+            id: Some("magic".to_string()),
+            contents: Some(contents),
+            ..Default::default()
+        };
+        let mixed = Contents::Mixed(vec![
+            ContentsItem::Byte(0xca),
+            ContentsItem::Str("fe".to_string()),
+        ]);
+        assert_eq!(mixed.to_bytes(), b"\xcafe");
+        let yaml = serde_yaml::to_string(&attr(mixed)).unwrap();
+        assert_eq!(yaml, "id: magic\ncontents:\n- 202\n- fe\n");
+    }
+
+    #[test]
+    fn data_matches_unless_mismatched() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for form in ContentsForm::ALL {
+            for mismatch in [false, true] {
+                let field = contents_field(&mut rng, "magic", form, 6, mismatch);
+                let expected = field.attr.contents.as_ref().unwrap().to_bytes();
+                assert_eq!(field.bytes.len(), expected.len());
+                let differing: Vec<usize> = (0..expected.len())
+                    .filter(|&i| field.bytes[i] != expected[i])
+                    .collect();
+                assert_eq!(differing, Vec::from_iter(field.mismatch_at));
+            }
+        }
+    }
+}
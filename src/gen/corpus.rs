@@ -0,0 +1,287 @@
+//! Corpus of generated expressions and specs, keyed by a hash of their normalized form so that
+//! variants differing only in things that don't change what they test (the order of the operands
+//! of `==`, the id or the docs of a spec) count as duplicates.
+//!
+//! Corpora persist as YAML files that keep the text of the first variant of each entry. The
+//! hash is FNV-1a, which unlike the hasher of the standard library is stable across builds, so
+//! keys stay valid on disk.
+
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use serde_yaml::Value;
+use thiserror::Error;
+
+use crate::ast::{BinaryOp, Expr};
+use crate::ksy::KsySpec;
+use crate::translator::translate;
+
+/// Keys of specs that are dropped when normalizing, as they don't change what a spec parses
+const IGNORED_KEYS: [&str; 4] = ["doc", "doc-ref", "title", "-orig-id"];
+
+#[derive(Debug, Error)]
+pub enum CorpusError {
+    #[error("can't access the corpus: {0}")]
+    Io(#[from] io::Error),
+    #[error("invalid corpus file: {0}")]
+    Parse(#[from] serde_yaml::Error),
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EntryKind {
+    Expr,
+    Spec,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CorpusEntry {
+    pub kind: EntryKind,
+    /// Expression in the KS syntax or spec in YAML, as first inserted
+    pub text: String,
+}
+
+/// What a session (the insertions since loading the corpus or starting the session) added.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct SessionStats {
+    pub offered: usize,
+    pub novel: usize,
+    /// Duplicates of entries of earlier sessions
+    pub known: usize,
+    /// Duplicates of entries that the session added
+    pub repeated: usize,
+}
+
+impl SessionStats {
+    /// Fraction of the offered entries that were novel, 1 if nothing was offered.
+    pub fn novelty(&self) -> f64 {
+        if self.offered == 0 {
+            1.0
+        } else {
+            self.novel as f64 / self.offered as f64
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Corpus {
+    /// Entries by the hex hash of their normalized form
+    pub entries: BTreeMap<String, CorpusEntry>,
+    #[serde(skip)]
+    session: SessionStats,
+    #[serde(skip)]
+    session_keys: HashSet<String>,
+}
+
+impl Corpus {
+    /// Corpus of the file, or an empty one if it doesn't exist yet.
+    pub fn load(path: &Path) -> Result<Self, CorpusError> {
+        match fs::read_to_string(path) {
+            Ok(text) => Ok(serde_yaml::from_str(&text)?),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Corpus::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), CorpusError> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, serde_yaml::to_string(self)?)?;
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Adds the expression unless the corpus has one with the same normalized form. Returns
+    /// whether it was added.
+    pub fn insert_expr(&mut self, expr: &Expr) -> bool {
+        self.insert(expr_key(expr), EntryKind::Expr, translate(expr))
+    }
+
+    /// Adds the spec unless the corpus has one with the same normalized form. Returns whether it
+    /// was added.
+    pub fn insert_spec(&mut self, spec: &KsySpec) -> bool {
+        self.insert(spec_key(spec), EntryKind::Spec, spec.to_yaml())
+    }
+
+    pub fn contains_expr(&self, expr: &Expr) -> bool {
+        self.entries.contains_key(&expr_key(expr))
+    }
+
+    pub fn contains_spec(&self, spec: &KsySpec) -> bool {
+        self.entries.contains_key(&spec_key(spec))
+    }
+
+    fn insert(&mut self, key: String, kind: EntryKind, text: String) -> bool {
+        self.session.offered += 1;
+        if self.session_keys.contains(&key) {
+            self.session.repeated += 1;
+            false
+        } else if self.entries.contains_key(&key) {
+            self.session.known += 1;
+            false
+        } else {
+            self.session.novel += 1;
+            self.session_keys.insert(key.clone());
+            self.entries.insert(key, CorpusEntry { kind, text });
+            true
+        }
+    }
+
+    pub fn session(&self) -> SessionStats {
+        self.session
+    }
+
+    /// Starts a new session, whose duplicates of the entries added so far count as known.
+    pub fn start_session(&mut self) -> SessionStats {
+        self.session_keys.clear();
+        std::mem::take(&mut self.session)
+    }
+}
+
+/// Key of the expression: the hash of its text with the operands of commutative operators
+/// sorted.
+pub fn expr_key(expr: &Expr) -> String {
+    key("expr", &translate(&normalized_expr(expr)))
+}
+
+/// Key of the spec: the hash of its YAML without the id and the keys that are only
+/// documentation.
+pub fn spec_key(spec: &KsySpec) -> String {
+    let mut doc = serde_yaml::to_value(spec).expect("spec model must be serializable to YAML");
+    if let Some(meta) = doc.get_mut("meta").and_then(Value::as_mapping_mut) {
+        meta.remove("id");
+    }
+    strip_docs(&mut doc);
+    let yaml = serde_yaml::to_string(&doc).expect("spec model must be serializable to YAML");
+    key("spec", &yaml)
+}
+
+fn normalized_expr(expr: &Expr) -> Expr {
+    let mut expr = expr.clone();
+    for child in expr.children_mut() {
+        *child = normalized_expr(child);
+    }
+    // `+` concatenates strings, so only the operators that commute for every type qualify
+    if let Expr::BinaryOp { l, op, r } = &mut expr {
+        let commutes = matches!(
+            op,
+            BinaryOp::Mul
+                | BinaryOp::Eq
+                | BinaryOp::Ne
+                | BinaryOp::BitAnd
+                | BinaryOp::BitOr
+                | BinaryOp::BitXor
+                | BinaryOp::And
+                | BinaryOp::Or
+        );
+        if commutes && translate(l) > translate(r) {
+            std::mem::swap(l, r);
+        }
+    }
+    expr
+}
+
+fn strip_docs(value: &mut Value) {
+    match value {
+        Value::Mapping(mapping) => {
+            for key in IGNORED_KEYS {
+                mapping.remove(key);
+            }
+            for (_, value) in mapping.iter_mut() {
+                strip_docs(value);
+            }
+        }
+        Value::Sequence(items) => items.iter_mut().for_each(strip_docs),
+        _ => {}
+    }
+}
+
+/// Hex FNV-1a hash of the text, with the kind so that an expression and a spec of the same text
+/// get different keys.
+fn key(kind: &str, text: &str) -> String {
+    let hash = [kind.as_bytes(), &[0], text.as_bytes()]
+        .concat()
+        .iter()
+        .fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+            (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+        });
+    format!("{:016x}", hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::parser::parse_expr;
+    use crate::ksy::Attribute;
+
+    fn parse(text: &str) -> Expr {
+        parse_expr(text).unwrap()
+    }
+
+    #[test]
+    fn normalized_duplicates() {
+        let mut corpus = Corpus::default();
+        assert!(corpus.insert_expr(&parse("a == b * 2")));
+        assert!(!corpus.insert_expr(&parse("2 * b == a")));
+        assert!(corpus.insert_expr(&parse("\"x\" + s")));
+        assert!(corpus.insert_expr(&parse("s + \"x\"")));
+        assert!(corpus.contains_expr(&parse("(b * 2) == a")));
+
+        let mut spec = KsySpec::top_level("first");
+        spec.seq.push(Attribute::new("len", "u1"));
+        assert!(corpus.insert_spec(&spec));
+        let mut renamed = spec.clone();
+        renamed.meta.as_mut().unwrap().id = Some("second".to_string());
+        renamed.seq[0].doc = Some("Length".to_string());
+        assert!(!corpus.insert_spec(&renamed));
+        renamed.seq[0] = Attribute::new("len", "u2");
+        assert!(corpus.insert_spec(&renamed));
+
+        assert_eq!(corpus.len(), 5);
+        assert_eq!(
+            corpus.session(),
+            SessionStats {
+                offered: 7,
+                novel: 5,
+                known: 0,
+                repeated: 2,
+            }
+        );
+    }
+
+    #[test]
+    fn sessions_and_persistence() {
+        let path = std::env::temp_dir()
+            .join(format!("ks_corpus_{}", std::process::id()))
+            .join("corpus.yaml");
+        let mut corpus = Corpus::load(&path).unwrap();
+        assert!(corpus.is_empty());
+        corpus.insert_expr(&parse("x + 1"));
+        corpus.insert_expr(&parse("x - 1"));
+        corpus.save(&path).unwrap();
+
+        let mut loaded = Corpus::load(&path).unwrap();
+        assert_eq!(loaded.entries, corpus.entries);
+        assert_eq!(loaded.entries[&expr_key(&parse("x+1"))].text, "(x + 1)");
+        assert!(!loaded.insert_expr(&parse("x + 1")));
+        assert!(loaded.insert_expr(&parse("x * 1")));
+        let stats = loaded.start_session();
+        assert_eq!((stats.known, stats.novel), (1, 1));
+        assert_eq!(stats.novelty(), 0.5);
+        assert!(!loaded.insert_expr(&parse("x * 1")));
+        assert_eq!(loaded.session().known, 1);
+        assert_eq!(SessionStats::default().novelty(), 1.0);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
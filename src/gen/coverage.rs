@@ -0,0 +1,473 @@
+//! Coverage feedback: coverage data of instrumented KSC runs biases the weights of a profile
+//! towards the features, operators and kinds of expressions whose compiler code hasn't been hit.
+//!
+//! Three formats are read, told apart by their contents:
+//!
+//! - JaCoCo CSV reports, with the lines missed and covered per class;
+//! - scoverage XML reports, with the invocations of each statement;
+//! - line-hit files, with one `<source file>:<line> <hits>` per line and `#` comments.
+//!
+//! A [`CoverageMap`] says which parts of the compiler each feature, operator and kind of expression
+//! exercises. Its rules match the units of the coverage data (source files or classes) by
+//! substring, and optionally a range of lines, which the class-level JaCoCo CSV reports can't
+//! match. The built-in map only knows the units that implement a single feature; finer maps,
+//! like the lines of the translators that handle each operator, are written in TOML:
+//!
+//! ```toml
+//! [[rules]]
+//! unit = "translators/BaseTranslator.scala"
+//! lines = [120, 134]
+//! operators = ["<<", ">>"]
+//! variants = ["binary"]
+//! ```
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::hash::Hash;
+use std::io;
+use std::path::Path;
+
+use indexmap::IndexMap;
+use regex::Regex;
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::gen::profile::{operator_symbols, Feature, GenProfile, EXPR_VARIANTS};
+
+/// Most that a weight grows by, as a multiple of itself, for code that is all missed
+const BOOST: f64 = 4.0;
+
+const BUILTIN_MAP: &str = r#"
+[[rules]]
+unit = "format/RepeatSpec"
+features = ["repeat"]
+
+[[rules]]
+unit = "format/ValidationSpec"
+features = ["valid"]
+
+[[rules]]
+unit = "format/InstanceSpec"
+features = ["instances", "pos"]
+
+[[rules]]
+unit = "format/ParamDefSpec"
+features = ["params"]
+
+[[rules]]
+unit = "format/EnumSpec"
+features = ["enums"]
+
+[[rules]]
+unit = "format/ProcessExpr"
+features = ["process"]
+
+[[rules]]
+unit = "datatype/Endianness"
+features = ["endian"]
+
+[[rules]]
+unit = "precompile/LoadImports"
+features = ["imports", "opaque"]
+
+[[rules]]
+unit = "components/SwitchOps"
+features = ["switch"]
+
+[[rules]]
+unit = "components/FixedContents"
+features = ["contents"]
+
+[[rules]]
+unit = "translators/TypeDetector"
+variants = ["cast", "attribute", "method-call"]
+"#;
+
+#[derive(Debug, Error)]
+pub enum CoverageError {
+    #[error("can't read the coverage data: {0}")]
+    Io(#[from] io::Error),
+    #[error("invalid coverage data at line {line}: {message}")]
+    Data { line: usize, message: String },
+    #[error("invalid coverage map: {0}")]
+    Map(#[from] toml::de::Error),
+    #[error("unknown operator `{0}` in the coverage map")]
+    UnknownOperator(String),
+    #[error("unknown kind of expression `{0}` in the coverage map")]
+    UnknownVariant(String),
+}
+
+/// Coverage of a source file or a class.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct UnitCoverage {
+    pub covered: u64,
+    pub missed: u64,
+    /// Hits by line, if the data has lines
+    pub lines: BTreeMap<u32, u64>,
+}
+
+impl UnitCoverage {
+    /// Counts the hits of a line, which may have been seen before (for another statement of it,
+    /// or in another run).
+    fn record(&mut self, line: u32, hits: u64) {
+        match self.lines.get_mut(&line) {
+            Some(total) => {
+                if *total == 0 && hits > 0 {
+                    self.missed -= 1;
+                    self.covered += 1;
+                }
+                *total += hits;
+            }
+            None => {
+                self.lines.insert(line, hits);
+                if hits > 0 {
+                    self.covered += 1;
+                } else {
+                    self.missed += 1;
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Coverage {
+    pub units: BTreeMap<String, UnitCoverage>,
+}
+
+impl Coverage {
+    pub fn load(path: &Path) -> Result<Self, CoverageError> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    /// Coverage data in any of the supported formats.
+    pub fn parse(text: &str) -> Result<Self, CoverageError> {
+        if text.trim_start().starts_with("GROUP,") {
+            Self::parse_jacoco_csv(text)
+        } else if text.contains("<statement") {
+            Ok(Self::parse_scoverage(text))
+        } else {
+            Self::parse_line_hits(text)
+        }
+    }
+
+    /// Adds the coverage of another run.
+    pub fn merge(&mut self, other: &Coverage) {
+        for (name, unit) in &other.units {
+            let merged = self.units.entry(name.clone()).or_default();
+            if unit.lines.is_empty() {
+                merged.covered += unit.covered;
+                merged.missed += unit.missed;
+            }
+            for (line, hits) in &unit.lines {
+                merged.record(*line, *hits);
+            }
+        }
+    }
+
+    fn parse_jacoco_csv(text: &str) -> Result<Self, CoverageError> {
+        let mut lines = text.lines().enumerate();
+        let header: Vec<&str> = lines.next().map(|(_, h)| h.split(',').collect()).unwrap();
+        let column = |name: &str| {
+            header
+                .iter()
+                .position(|h| h.trim() == name)
+                .ok_or_else(|| CoverageError::Data {
+                    line: 1,
+                    message: format!("no `{}` column", name),
+                })
+        };
+        let (package, class) = (column("PACKAGE")?, column("CLASS")?);
+        let (missed, covered) = (column("LINE_MISSED")?, column("LINE_COVERED")?);
+        let mut coverage = Coverage::default();
+        for (i, line) in lines.filter(|(_, line)| !line.trim().is_empty()) {
+            let fields: Vec<&str> = line.split(',').collect();
+            let field = |index: usize| {
+                fields
+                    .get(index)
+                    .copied()
+                    .ok_or_else(|| CoverageError::Data {
+                        line: i + 1,
+                        message: format!("{} fields instead of {}", fields.len(), header.len()),
+                    })
+            };
+            let count = |index: usize| {
+                field(index)?
+                    .trim()
+                    .parse::<u64>()
+                    .map_err(|_| CoverageError::Data {
+                        line: i + 1,
+                        message: format!("`{}` is not a count", fields[index]),
+                    })
+            };
+            let name = format!("{}/{}", field(package)?.replace('.', "/"), field(class)?);
+            let unit = coverage.units.entry(name).or_default();
+            unit.missed += count(missed)?;
+            unit.covered += count(covered)?;
+        }
+        Ok(coverage)
+    }
+
+    fn parse_scoverage(text: &str) -> Self {
+        let statement = Regex::new(r"<statement\b[^>]*>").unwrap();
+        let attribute_regex = |name: &str| Regex::new(&format!(r#"\s{}="([^"]*)""#, name)).unwrap();
+        let (source_attr, line_attr, hits_attr) = (
+            attribute_regex("source"),
+            attribute_regex("line"),
+            attribute_regex("invocation-count"),
+        );
+        let attribute = |tag: &str, regex: &Regex| regex.captures(tag).map(|c| c[1].to_string());
+        let mut coverage = Coverage::default();
+        for tag in statement.find_iter(text).map(|m| m.as_str()) {
+            let (Some(source), Some(line)) = (
+                attribute(tag, &source_attr),
+                attribute(tag, &line_attr).and_then(|l| l.parse().ok()),
+            ) else {
+                continue;
+            };
+            let hits = attribute(tag, &hits_attr)
+                .and_then(|h| h.parse().ok())
+                .unwrap_or(0);
+            coverage.units.entry(source).or_default().record(line, hits);
+        }
+        coverage
+    }
+
+    fn parse_line_hits(text: &str) -> Result<Self, CoverageError> {
+        let mut coverage = Coverage::default();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = || CoverageError::Data {
+                line: i + 1,
+                message: format!("`{}` is not `<source file>:<line> <hits>`", line),
+            };
+            let (location, hits) = line.rsplit_once(char::is_whitespace).ok_or_else(invalid)?;
+            let (source, number) = location.trim().rsplit_once(':').ok_or_else(invalid)?;
+            let number = number.parse().map_err(|_| invalid())?;
+            let hits = hits.parse().map_err(|_| invalid())?;
+            coverage
+                .units
+                .entry(source.to_string())
+                .or_default()
+                .record(number, hits);
+        }
+        Ok(coverage)
+    }
+
+    /// Fraction of the code that the rule matches that was missed, or `None` if the data has
+    /// none of it.
+    pub fn gap(&self, rule: &CoverageRule) -> Option<f64> {
+        let (mut covered, mut missed) = (0, 0);
+        for (name, unit) in &self.units {
+            if !name.contains(&rule.unit) {
+                continue;
+            }
+            match rule.lines {
+                Some((first, last)) => {
+                    for hits in unit.lines.range(first..=last).map(|(_, hits)| *hits) {
+                        if hits > 0 {
+                            covered += 1;
+                        } else {
+                            missed += 1;
+                        }
+                    }
+                }
+                None => {
+                    covered += unit.covered;
+                    missed += unit.missed;
+                }
+            }
+        }
+        let total = covered + missed;
+        (total > 0).then(|| missed as f64 / total as f64)
+    }
+}
+
+/// Part of the compiler and what exercises it.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CoverageRule {
+    /// Substring of the names of the units, like `format/RepeatSpec`
+    pub unit: String,
+    /// First and last line, inclusive
+    pub lines: Option<(u32, u32)>,
+    pub features: Vec<Feature>,
+    pub operators: Vec<String>,
+    pub variants: Vec<String>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CoverageMap {
+    pub rules: Vec<CoverageRule>,
+}
+
+impl CoverageMap {
+    /// Map of the units of KSC that implement a single feature.
+    pub fn builtin() -> Self {
+        Self::from_toml_str(BUILTIN_MAP).expect("built-in coverage map must be valid")
+    }
+
+    pub fn from_toml_str(s: &str) -> Result<Self, CoverageError> {
+        let map: Self = toml::from_str(s)?;
+        let symbols = operator_symbols();
+        for rule in &map.rules {
+            if let Some(symbol) = rule
+                .operators
+                .iter()
+                .find(|symbol| !symbols.contains(&symbol.as_str()))
+            {
+                return Err(CoverageError::UnknownOperator(symbol.clone()));
+            }
+            if let Some(name) = rule
+                .variants
+                .iter()
+                .find(|name| !EXPR_VARIANTS.contains(&name.as_str()))
+            {
+                return Err(CoverageError::UnknownVariant(name.clone()));
+            }
+        }
+        Ok(map)
+    }
+}
+
+/// The profile with the weights of what the map relates to missed code increased, by up to
+/// [`BOOST`] times for code that was all missed. What the profile leaves out stays out.
+pub fn biased(profile: &GenProfile, coverage: &Coverage, map: &CoverageMap) -> GenProfile {
+    let mut feature_gaps: IndexMap<Feature, f64> = IndexMap::new();
+    let mut operator_gaps: IndexMap<String, f64> = IndexMap::new();
+    let mut variant_gaps: IndexMap<String, f64> = IndexMap::new();
+    for rule in &map.rules {
+        let Some(gap) = coverage.gap(rule) else {
+            continue;
+        };
+        for feature in &rule.features {
+            widen(&mut feature_gaps, *feature, gap);
+        }
+        for symbol in &rule.operators {
+            widen(&mut operator_gaps, symbol.clone(), gap);
+        }
+        for name in &rule.variants {
+            widen(&mut variant_gaps, name.clone(), gap);
+        }
+    }
+
+    let boosted = |weight: u32, gap: Option<&f64>| {
+        let gap = gap.copied().unwrap_or(0.0);
+        weight.saturating_add((f64::from(weight) * BOOST * gap).ceil() as u32)
+    };
+    let mut profile = profile.clone();
+    for (feature, weight) in profile.features.iter_mut() {
+        *weight = boosted(*weight, feature_gaps.get(feature));
+    }
+    // empty maps allow everything with the weight 1, which becomes explicit to be increased
+    if profile.operators.is_empty() && !operator_gaps.is_empty() {
+        profile.operators = operator_symbols()
+            .into_iter()
+            .map(|symbol| (symbol.to_string(), 1))
+            .collect();
+    }
+    for (symbol, weight) in profile.operators.iter_mut() {
+        *weight = boosted(*weight, operator_gaps.get(symbol));
+    }
+    let variants = &mut profile.expressions.variants;
+    if variants.is_empty() && !variant_gaps.is_empty() {
+        *variants = EXPR_VARIANTS
+            .into_iter()
+            .map(|name| (name.to_string(), 1))
+            .collect();
+    }
+    for (name, weight) in variants.iter_mut() {
+        *weight = boosted(*weight, variant_gaps.get(name));
+    }
+    profile
+}
+
+/// Keeps the largest gap of the things that exercise several parts of the compiler.
+fn widen<K: Hash + Eq>(gaps: &mut IndexMap<K, f64>, key: K, gap: f64) {
+    let entry = gaps.entry(key).or_insert(0.0);
+    *entry = entry.max(gap);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const JACOCO: &str = "\
+GROUP,PACKAGE,CLASS,INSTRUCTION_MISSED,INSTRUCTION_COVERED,LINE_MISSED,LINE_COVERED
+ksc,io.kaitai.struct.format,RepeatSpec,40,0,10,0
+ksc,io.kaitai.struct.format,EnumSpec,1,39,1,9
+ksc,io.kaitai.struct.format,ValidationSpec,0,20,0,5
+";
+
+    const SCOVERAGE: &str = r#"<scoverage>
+<statement source="/ksc/translators/BaseTranslator.scala" line="120" invocation-count="0" ignored="false">
+</statement>
+<statement source="/ksc/translators/BaseTranslator.scala" line="121" invocation-count="3">
+</statement>
+<statement source="/ksc/translators/BaseTranslator.scala" line="121" invocation-count="0">
+</statement>
+<statement source="/ksc/translators/BaseTranslator.scala" line="200" invocation-count="5">
+</statement>
+</scoverage>"#;
+
+    #[test]
+    fn formats() {
+        let jacoco = Coverage::parse(JACOCO).unwrap();
+        let repeat = &jacoco.units["io/kaitai/struct/format/RepeatSpec"];
+        assert_eq!((repeat.covered, repeat.missed), (0, 10));
+
+        let scoverage = Coverage::parse(SCOVERAGE).unwrap();
+        let unit = &scoverage.units["/ksc/translators/BaseTranslator.scala"];
+        assert_eq!((unit.covered, unit.missed), (2, 1));
+        assert_eq!(unit.lines, BTreeMap::from([(120, 0), (121, 3), (200, 5)]));
+
+        let hits = Coverage::parse("# run 1\nsrc/Foo.scala:3 0\nsrc/Foo.scala:4 2\n").unwrap();
+        assert_eq!(hits.units["src/Foo.scala"].lines.len(), 2);
+        let err = Coverage::parse("src/Foo.scala 3\n").unwrap_err();
+        assert!(matches!(err, CoverageError::Data { line: 1, .. }));
+
+        let mut merged = hits.clone();
+        merged.merge(&Coverage::parse("src/Foo.scala:3 1\n").unwrap());
+        assert_eq!(merged.units["src/Foo.scala"].missed, 0);
+    }
+
+    #[test]
+    fn bias() {
+        let mut coverage = Coverage::parse(JACOCO).unwrap();
+        coverage.merge(&Coverage::parse(SCOVERAGE).unwrap());
+        let map = CoverageMap::from_toml_str(
+            "[[rules]]\n\
+             unit = \"BaseTranslator\"\n\
+             lines = [120, 121]\n\
+             operators = [\"<<\", \">>\"]\n",
+        )
+        .unwrap();
+        let mut rules = CoverageMap::builtin().rules;
+        rules.extend(map.rules);
+        let map = CoverageMap { rules };
+        assert_eq!(coverage.gap(&map.rules[0]), Some(1.0));
+
+        let mut profile = GenProfile::default();
+        profile.features.insert(Feature::Enums, 10);
+        profile.features.insert(Feature::Params, 0);
+        let biased = biased(&profile, &coverage, &map);
+        assert_eq!(biased.feature_weight(Feature::Repeat), 5);
+        assert_eq!(biased.feature_weight(Feature::Enums), 14);
+        assert_eq!(biased.feature_weight(Feature::Valid), 1);
+        assert_eq!(biased.feature_weight(Feature::Params), 0);
+        // no data on them
+        assert_eq!(biased.feature_weight(Feature::Endian), 1);
+        assert_eq!(biased.operator_weight("<<"), 3);
+        assert_eq!(biased.operator_weight("+"), 1);
+        assert_eq!(biased.variant_weight("cast"), 1);
+
+        let err = CoverageMap::from_toml_str("[[rules]]\nunit = \"x\"\noperators = [\"@\"]\n");
+        assert!(matches!(err, Err(CoverageError::UnknownOperator(_))));
+        let err = CoverageMap::from_toml_str("[[rules]]\nunit = \"x\"\nfeatures = [\"nope\"]\n");
+        assert!(matches!(err, Err(CoverageError::Map(_))));
+    }
+}
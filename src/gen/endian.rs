@@ -0,0 +1,239 @@
+//! Calculated default endianness (`meta/endian` with `switch-on` and `cases`), with data for each
+//! endianness and data matching no case.
+
+use indexmap::IndexMap;
+use rand::seq::index::sample;
+use rand::Rng;
+
+use crate::ast::utils::{attr, name};
+use crate::ast::Expr;
+use crate::eval::Value;
+use crate::ksy::{Attribute, Endian, KsySpec, MetaEndian, TypeSpec};
+use crate::numeric::IntType;
+
+/// Key of the default case
+const DEFAULT_CASE: &str = "_";
+/// Attributes of `body` read in the calculated endianness
+const FIELDS: [&str; 3] = ["u2", "s4", "u8"];
+/// Byte-order marks of TIFF and similar formats
+const BOM_LE: [u8; 2] = *b"II";
+const BOM_BE: [u8; 2] = *b"MM";
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum EndianOn {
+    /// `u1` indicator
+    Int,
+    /// 2-byte indicator, compared to byte array literals
+    Bytes,
+}
+
+impl EndianOn {
+    pub const ALL: [EndianOn; 2] = [EndianOn::Int, EndianOn::Bytes];
+
+    fn name(self) -> &'static str {
+        match self {
+            EndianOn::Int => "int",
+            EndianOn::Bytes => "bytes",
+        }
+    }
+}
+
+/// Spec whose `body` type calculates its endianness from the `indicator` attribute read before
+/// it, with data for each endianness.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EndianCase {
+    pub spec: KsySpec,
+    pub variants: Vec<EndianData>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct EndianData {
+    pub data: Vec<u8>,
+    /// Endianness that `body` gets parsed in, `None` if no case matches and there's no default
+    /// case (which is an error)
+    pub endian: Option<Endian>,
+    /// Expected values of the attributes of `body` if it gets parsed
+    pub assertions: Vec<(Expr, Value)>,
+}
+
+/// `value` (in the range of `ty`) in `ty.width` bytes of the given endianness.
+fn encode(value: i128, ty: IntType, endian: Endian) -> Vec<u8> {
+    let le = &(value as u128).to_le_bytes()[..usize::from(ty.width)];
+    match endian {
+        Endian::Le => le.to_vec(),
+        Endian::Be => le.iter().rev().copied().collect(),
+    }
+}
+
+fn random_int<R: Rng + ?Sized>(rng: &mut R, ty: IntType) -> i128 {
+    rng.gen_range(ty.min_value()..=ty.max_value())
+}
+
+/// The `body` type reads [`FIELDS`] as `f_<type>`, a `u2be` (unaffected by the calculated
+/// endianness) and a nested type `inner` reading a `u4`, which inherits the endianness.
+fn body_type(switch_on: Expr, cases: IndexMap<Expr, Endian>) -> TypeSpec {
+    let mut body = TypeSpec {
+        meta: Some(Default::default()),
+        ..Default::default()
+    };
+    body.meta.as_mut().unwrap().endian = Some(MetaEndian::Switch { switch_on, cases });
+    for type_name in FIELDS {
+        body.seq
+            .push(Attribute::new(format!("f_{}", type_name), type_name));
+    }
+    body.seq.push(Attribute::new("fixed", "u2be"));
+    body.seq.push(Attribute::new("inner", "inner"));
+    body.types.insert(
+        "inner".to_string(),
+        TypeSpec {
+            seq: vec![Attribute::new("x", "u4")],
+            ..Default::default()
+        },
+    );
+    body
+}
+
+/// Data of `body` in the given endianness, with the expected values. If the endianness is
+/// undecided, the data is little-endian and there are no expected values.
+fn body_data<R: Rng + ?Sized>(
+    rng: &mut R,
+    endian: Option<Endian>,
+) -> (Vec<u8>, Vec<(Expr, Value)>) {
+    let body = name("body");
+    let mut data = Vec::new();
+    let mut assertions = Vec::new();
+    let fields = FIELDS
+        .iter()
+        .map(|type_name| (format!("f_{}", type_name), *type_name, None))
+        .chain([
+            ("fixed".to_string(), "u2", Some(Endian::Be)),
+            ("x".to_string(), "u4", None),
+        ]);
+    for (field, type_name, fixed) in fields {
+        let ty = IntType::from_name(type_name).unwrap();
+        let value = random_int(rng, ty);
+        let field_endian = fixed.or(endian).unwrap_or(Endian::Le);
+        data.extend(encode(value, ty, field_endian));
+        let path = match field.as_str() {
+            "x" => attr(attr(body.clone(), "inner"), "x"),
+            _ => attr(body.clone(), &field),
+        };
+        assertions.push((path, Value::Int(value)));
+    }
+    if endian.is_none() {
+        assertions.clear();
+    }
+    (data, assertions)
+}
+
+/// Spec switching on an indicator of the given kind, with an `le` and a `be` case and optionally
+/// a default case.
+pub fn endian_case<R: Rng + ?Sized>(
+    rng: &mut R,
+    id: &str,
+    on: EndianOn,
+    with_default: bool,
+) -> EndianCase {
+    let mut spec = KsySpec::top_level(id);
+    let byte_list = |bytes: [u8; 2]| Expr::List(bytes.map(|b| Expr::Int(b.into())).to_vec());
+    // indicators for `le`, `be` and none of the cases
+    let indicators: [(Vec<u8>, Expr); 3] = match on {
+        EndianOn::Int => {
+            spec.seq.push(Attribute::new("indicator", "u1"));
+            let values = sample(rng, 256, 3);
+            [0, 1, 2].map(|i| {
+                let value = values.index(i) as u8;
+                (vec![value], Expr::Int(value.into()))
+            })
+        }
+        EndianOn::Bytes => {
+            spec.seq.push(Attribute {
+                id: Some("indicator".to_string()),
+                size: Some(Expr::Int(2)),
+                ..Default::default()
+            });
+            // the unmatched one is a mix of both marks
+            let mixed = [BOM_LE[0], BOM_BE[1]];
+            [BOM_LE, BOM_BE, mixed].map(|bom| (bom.to_vec(), byte_list(bom)))
+        }
+    };
+    let [(le_data, le_key), (be_data, be_key), (other_data, _)] = indicators;
+
+    let mut cases = IndexMap::from([(le_key, Endian::Le), (be_key, Endian::Be)]);
+    let default = with_default.then(|| if rng.gen() { Endian::Le } else { Endian::Be });
+    if let Some(default) = default {
+        cases.insert(name(DEFAULT_CASE), default);
+    }
+    let switch_on = attr(name("_parent"), "indicator");
+    spec.seq.push(Attribute::new("body", "body"));
+    spec.types
+        .insert("body".to_string(), body_type(switch_on, cases));
+
+    let variants = [
+        (le_data, Some(Endian::Le)),
+        (be_data, Some(Endian::Be)),
+        (other_data, default),
+    ]
+    .into_iter()
+    .map(|(mut data, endian)| {
+        let (body, assertions) = body_data(rng, endian);
+        data.extend(body);
+        EndianData {
+            data,
+            endian,
+            assertions,
+        }
+    })
+    .collect();
+    EndianCase { spec, variants }
+}
+
+/// Cases for every [`EndianOn`], with and without a default case, with ids like
+/// `endian_bytes_default`.
+pub fn endian_cases<R: Rng + ?Sized>(rng: &mut R) -> Vec<EndianCase> {
+    let mut cases = Vec::new();
+    for on in EndianOn::ALL {
+        for with_default in [false, true] {
+            let suffix = if with_default { "_default" } else { "" };
+            let id = format!("endian_{}{}", on.name(), suffix);
+            cases.push(endian_case(rng, &id, on, with_default));
+        }
+    }
+    cases
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn encoding() {
+        let u2 = IntType::from_name("u2").unwrap();
+        let s4 = IntType::from_name("s4").unwrap();
+        assert_eq!(encode(0x1234, u2, Endian::Le), [0x34, 0x12]);
+        assert_eq!(encode(0x1234, u2, Endian::Be), [0x12, 0x34]);
+        assert_eq!(encode(-2, s4, Endian::Be), [0xff, 0xff, 0xff, 0xfe]);
+    }
+
+    #[test]
+    fn variants_cover_each_endianness() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for case in endian_cases(&mut rng) {
+            let id = case.spec.id().unwrap();
+            let endians: Vec<_> = case.variants.iter().map(|v| v.endian).collect();
+            assert_eq!(endians[..2], [Some(Endian::Le), Some(Endian::Be)], "{}", id);
+            assert_eq!(endians[2].is_some(), id.ends_with("_default"), "{}", id);
+            let indicator_len = if id.contains("_bytes") { 2 } else { 1 };
+            for variant in &case.variants {
+                // u2, s4, u8, u2be and u4
+                assert_eq!(variant.data.len(), indicator_len + 20);
+                let expected = if variant.endian.is_some() { 5 } else { 0 };
+                assert_eq!(variant.assertions.len(), expected);
+            }
+            let yaml = case.spec.to_yaml();
+            assert!(yaml.contains("      endian:\n        switch-on: _parent.indicator\n"));
+        }
+    }
+}
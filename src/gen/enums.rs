@@ -0,0 +1,111 @@
+//! Enums with members at the edges of the integer range, read into `s8` attributes and checked
+//! both against the enum members and through `.to_i`.
+
+use indexmap::IndexMap;
+
+use crate::ast::{BinaryOp, Expr};
+use crate::eval::Value;
+use crate::gen::case::SpecCase;
+use crate::gen::expr::int_literal;
+use crate::ksy::{Attribute, Endian, KsySpec};
+
+/// Edge values with their labels. The largest magnitudes fill all 64 bits, so they also test that
+/// enum values aren't truncated to 32 bits (or parsed as floats) anywhere.
+pub const EDGE_VALUES: [(&str, i128); 7] = [
+    ("zero", 0),
+    ("one", 1),
+    ("minus_one", -1),
+    ("s4_min", i32::MIN as i128),
+    ("u4_max", u32::MAX as i128),
+    ("s8_min", i64::MIN as i128),
+    ("s8_max", i64::MAX as i128),
+];
+
+const ENUM_NAME: &str = "edge";
+
+/// Spec with an enum of all [`EDGE_VALUES`] and one `s8le` attribute `f_<label>` holding each of
+/// them.
+pub fn edge_enum_case(id: &str) -> SpecCase {
+    let mut spec = KsySpec::top_level(id);
+    spec.meta.as_mut().unwrap().endian = Some(Endian::Le.into());
+    spec.enums.insert(
+        ENUM_NAME.to_string(),
+        EDGE_VALUES
+            .iter()
+            .map(|(label, value)| (*value, label.to_string()))
+            .collect::<IndexMap<_, _>>(),
+    );
+    let mut data = Vec::new();
+    let mut assertions = Vec::new();
+    for (label, value) in EDGE_VALUES {
+        let id = format!("f_{}", label);
+        spec.seq.push(Attribute {
+            enum_name: Some(ENUM_NAME.to_string()),
+            ..Attribute::new(&id, "s8")
+        });
+        data.extend((value as i64).to_le_bytes());
+
+        let attr = Expr::Name(id);
+        assertions.push((
+            Expr::BinaryOp {
+                l: Box::new(attr.clone()),
+                op: BinaryOp::Eq,
+                r: Box::new(Expr::EnumMember {
+                    enum_path: vec![ENUM_NAME.to_string()],
+                    label: label.to_string(),
+                }),
+            },
+            Value::Bool(true),
+        ));
+        let to_i = Expr::Attribute {
+            value: Box::new(attr),
+            attr_name: "to_i".to_string(),
+        };
+        assertions.push((to_i.clone(), Value::Int(value)));
+        assertions.push((
+            Expr::BinaryOp {
+                l: Box::new(to_i),
+                op: BinaryOp::Eq,
+                r: Box::new(int_literal(value)),
+            },
+            Value::Bool(true),
+        ));
+    }
+    SpecCase {
+        spec,
+        data,
+        assertions,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::{eval, Env};
+
+    #[test]
+    fn assertions_hold() {
+        let case = edge_enum_case("enum_edges");
+        assert_eq!(case.data.len(), 8 * EDGE_VALUES.len());
+
+        let mut env = Env::new();
+        env.define_enum(vec![ENUM_NAME.to_string()], EDGE_VALUES);
+        for (attr, chunk) in case.spec.seq.iter().zip(case.data.chunks(8)) {
+            let value = i64::from_le_bytes(chunk.try_into().unwrap());
+            env.set(
+                attr.id.clone().unwrap(),
+                Value::Enum {
+                    enum_path: vec![ENUM_NAME.to_string()],
+                    value: value.into(),
+                },
+            );
+        }
+        for (expr, expected) in &case.assertions {
+            assert_eq!(eval(expr, &env).as_ref(), Ok(expected), "{:?}", expr);
+        }
+        assert!(case
+            .spec
+            .to_yaml()
+            .contains("    -9223372036854775808: s8_min\n"));
+    }
+}
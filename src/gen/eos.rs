@@ -0,0 +1,191 @@
+//! Specs whose last attribute reads up to the end of the stream (`size-eos`, `repeat: eos`) or
+//! exactly to it, with data ending exactly at the boundary of that attribute, one byte before it
+//! and one byte after it.
+
+use rand::Rng;
+
+use crate::ast::Expr;
+use crate::eval::Value;
+use crate::ksy::{Attribute, KsySpec, Repeat};
+
+/// Size of the items of `repeat: eos`
+const ITEM_SIZE: usize = 2;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum TrailingForm {
+    /// Byte array with `size-eos: true`
+    SizeEos,
+    /// `u2le` items with `repeat: eos`
+    RepeatEos,
+    /// Byte array with a size read from the attribute before it
+    ExactFit,
+}
+
+impl TrailingForm {
+    pub const ALL: [TrailingForm; 3] = [
+        TrailingForm::SizeEos,
+        TrailingForm::RepeatEos,
+        TrailingForm::ExactFit,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            TrailingForm::SizeEos => "size_eos",
+            TrailingForm::RepeatEos => "repeat_eos",
+            TrailingForm::ExactFit => "exact_fit",
+        }
+    }
+}
+
+/// Where the data ends relative to the end of the last attribute.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Boundary {
+    Exact,
+    /// One byte before
+    Short,
+    /// One byte after
+    Long,
+}
+
+impl Boundary {
+    pub const ALL: [Boundary; 3] = [Boundary::Exact, Boundary::Short, Boundary::Long];
+}
+
+/// Spec reading `head: u1` and then the trailing attribute `tail`, with data for each
+/// [`Boundary`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct EosCase {
+    pub spec: KsySpec,
+    pub variants: Vec<EosData>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct EosData {
+    pub data: Vec<u8>,
+    pub boundary: Boundary,
+    /// Expected value of `tail`, `None` if reading it fails at the end of the stream
+    pub expected: Option<Value>,
+}
+
+fn tail_attr(form: TrailingForm) -> Attribute {
+    let tail = Attribute {
+        id: Some("tail".to_string()),
+        ..Default::default()
+    };
+    match form {
+        TrailingForm::SizeEos => Attribute {
+            size_eos: Some(true),
+            ..tail
+        },
+        TrailingForm::RepeatEos => Attribute {
+            repeat: Some(Repeat::Eos),
+            ..Attribute::new("tail", "u2le")
+        },
+        TrailingForm::ExactFit => Attribute {
+            size: Some(Expr::Name("head".to_string())),
+            ..tail
+        },
+    }
+}
+
+/// Expected value of `tail` read from `bytes` (the data after `head`), where `len` is the length
+/// the data is built around.
+fn expected(form: TrailingForm, bytes: &[u8], len: usize) -> Option<Value> {
+    match form {
+        TrailingForm::SizeEos => Some(Value::Bytes(bytes.to_vec())),
+        TrailingForm::RepeatEos => {
+            // a partial item at the end fails to be read
+            let items = bytes.chunks(ITEM_SIZE).map(|chunk| {
+                let item: [u8; ITEM_SIZE] = chunk.try_into().ok()?;
+                Some(Value::Int(u16::from_le_bytes(item).into()))
+            });
+            items.collect::<Option<_>>().map(Value::Array)
+        }
+        TrailingForm::ExactFit => bytes.get(..len).map(|tail| Value::Bytes(tail.to_vec())),
+    }
+}
+
+/// Case with a `tail` of 1 to `max_items` items (bytes, except for `repeat: eos`). For
+/// [`TrailingForm::ExactFit`], `head` holds the size of `tail`, otherwise it's random.
+pub fn eos_case<R: Rng + ?Sized>(
+    rng: &mut R,
+    id: &str,
+    form: TrailingForm,
+    max_items: usize,
+) -> EosCase {
+    let items = rng.gen_range(1..=max_items.min(255));
+    let len = match form {
+        TrailingForm::RepeatEos => items * ITEM_SIZE,
+        _ => items,
+    };
+    let head = match form {
+        TrailingForm::ExactFit => items as u8,
+        _ => rng.gen(),
+    };
+    let mut spec = KsySpec::top_level(id);
+    spec.seq.push(Attribute::new("head", "u1"));
+    spec.seq.push(tail_attr(form));
+
+    let variants = Boundary::ALL
+        .into_iter()
+        .map(|boundary| {
+            let tail_len = match boundary {
+                Boundary::Exact => len,
+                Boundary::Short => len - 1,
+                Boundary::Long => len + 1,
+            };
+            let bytes: Vec<u8> = (0..tail_len).map(|_| rng.gen()).collect();
+            let expected = expected(form, &bytes, len);
+            let mut data = vec![head];
+            data.extend(bytes);
+            EosData {
+                data,
+                boundary,
+                expected,
+            }
+        })
+        .collect();
+    EosCase { spec, variants }
+}
+
+/// Cases for every [`TrailingForm`], with ids like `eos_repeat_eos`.
+pub fn eos_cases<R: Rng + ?Sized>(rng: &mut R, max_items: usize) -> Vec<EosCase> {
+    TrailingForm::ALL
+        .into_iter()
+        .map(|form| eos_case(rng, &format!("eos_{}", form.name()), form, max_items))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn failing_boundaries() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for case in eos_cases(&mut rng, 8) {
+            let failing: Vec<Boundary> = case
+                .variants
+                .iter()
+                .filter(|variant| variant.expected.is_none())
+                .map(|variant| variant.boundary)
+                .collect();
+            let expected = match case.spec.id().unwrap() {
+                "eos_size_eos" => vec![],
+                "eos_repeat_eos" => vec![Boundary::Short, Boundary::Long],
+                "eos_exact_fit" => vec![Boundary::Short],
+                _ => unreachable!(),
+            };
+            assert_eq!(failing, expected);
+        }
+    }
+
+    #[test]
+    fn repeated_items() {
+        let expected = expected(TrailingForm::RepeatEos, &[1, 0, 0, 1], 4);
+        let items = vec![Value::Int(1), Value::Int(256)];
+        assert_eq!(expected, Some(Value::Array(items)));
+    }
+}
@@ -0,0 +1,297 @@
+//! Bounded exhaustive enumeration of small specs: every layout of up to `max_fields` integer
+//! fields, combined with every value instance of up to `max_ops` operators over the fields and a
+//! few literals. Unlike the random generators, this guarantees that all small cases are covered.
+//!
+//! Expressions alone are enumerated lazily over an [`Alphabet`] by [`all_exprs`], smallest first,
+//! so that even enumerations too large to hold in memory can be consumed to the end.
+
+use std::rc::Rc;
+
+use crate::ast::{BinaryOp, Expr, UnaryOp};
+use crate::eval::{eval, Env, Value};
+use crate::ksy::{Attribute, Endian, KsySpec};
+use crate::numeric::IntType;
+
+/// Types of the fields, which are named `f0`, `f1`, ...
+pub const FIELD_TYPES: [&str; 3] = ["u1", "s1", "u2"];
+const LITERALS: [u64; 2] = [1, 2];
+const OPS: [BinaryOp; 10] = [
+    BinaryOp::Add,
+    BinaryOp::Sub,
+    BinaryOp::Mul,
+    BinaryOp::Div,
+    BinaryOp::Rem,
+    BinaryOp::BitAnd,
+    BinaryOp::BitOr,
+    BinaryOp::BitXor,
+    BinaryOp::Shl,
+    BinaryOp::Shr,
+];
+/// Bytes the fields are read from, in order (over again if there are more fields)
+const DATA: [u8; 8] = [0x81, 0x07, 0xfe, 0x02, 0x10, 0xff, 0x00, 0x80];
+const INSTANCE_NAME: &str = "result";
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Bounds {
+    pub max_fields: usize,
+    pub max_ops: usize,
+}
+
+/// Spec with the fields and the value instance `result`, with data and the value of `result`,
+/// or `None` if evaluating it fails (e.g. on a division by zero).
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExhaustiveCase {
+    pub spec: KsySpec,
+    pub data: Vec<u8>,
+    pub expected: Option<Value>,
+}
+
+/// Every sequence of up to `max_fields` [`FIELD_TYPES`], shortest first.
+pub fn layouts(max_fields: usize) -> Vec<Vec<&'static str>> {
+    let mut layouts = vec![Vec::new()];
+    let mut last = vec![Vec::new()];
+    for _ in 0..max_fields {
+        last = last
+            .iter()
+            .flat_map(|layout: &Vec<&str>| {
+                FIELD_TYPES.iter().map(move |type_name| {
+                    let mut layout = layout.clone();
+                    layout.push(*type_name);
+                    layout
+                })
+            })
+            .collect();
+        layouts.extend(last.iter().cloned());
+    }
+    layouts
+}
+
+/// Every expression with exactly `ops` binary operators over the leaves.
+pub fn exprs(leaves: &[Expr], ops: usize) -> Vec<Expr> {
+    if ops == 0 {
+        return leaves.to_vec();
+    }
+    let mut all = Vec::new();
+    for l_ops in 0..ops {
+        let rights = exprs(leaves, ops - 1 - l_ops);
+        for l in exprs(leaves, l_ops) {
+            for r in &rights {
+                for op in OPS {
+                    all.push(Expr::BinaryOp {
+                        l: Box::new(l.clone()),
+                        op,
+                        r: Box::new(r.clone()),
+                    });
+                }
+            }
+        }
+    }
+    all
+}
+
+/// Leaves and operators that expressions are built from.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Alphabet {
+    pub leaves: Vec<Expr>,
+    pub unary_ops: Vec<UnaryOp>,
+    pub binary_ops: Vec<BinaryOp>,
+}
+
+/// Every expression over the alphabet with at most `max_nodes` nodes (leaves and operators), in
+/// the order of their number of nodes, each exactly once.
+pub fn all_exprs(alphabet: &Alphabet, max_nodes: usize) -> impl Iterator<Item = Expr> {
+    let alphabet = Rc::new(alphabet.clone());
+    (1..=max_nodes).flat_map(move |nodes| sized_exprs(Rc::clone(&alphabet), nodes))
+}
+
+/// Number of expressions that [`all_exprs`] yields.
+pub fn expr_count(alphabet: &Alphabet, max_nodes: usize) -> u128 {
+    // counts[n]: expressions of exactly n nodes
+    let mut counts = vec![0u128; max_nodes + 1];
+    for nodes in 1..=max_nodes {
+        counts[nodes] = if nodes == 1 {
+            alphabet.leaves.len() as u128
+        } else {
+            let unary = alphabet.unary_ops.len() as u128 * counts[nodes - 1];
+            let binary: u128 = (1..nodes - 1)
+                .map(|l_nodes| counts[l_nodes] * counts[nodes - 1 - l_nodes])
+                .sum();
+            unary + alphabet.binary_ops.len() as u128 * binary
+        };
+    }
+    counts.iter().sum()
+}
+
+/// Expressions of exactly `nodes` nodes, made as they are consumed.
+fn sized_exprs(alphabet: Rc<Alphabet>, nodes: usize) -> Box<dyn Iterator<Item = Expr>> {
+    if nodes <= 1 {
+        let leaves = if nodes == 1 {
+            alphabet.leaves.clone()
+        } else {
+            Vec::new()
+        };
+        return Box::new(leaves.into_iter());
+    }
+    let unary = {
+        let alphabet = Rc::clone(&alphabet);
+        (0..alphabet.unary_ops.len()).flat_map(move |i| {
+            let op = alphabet.unary_ops[i];
+            sized_exprs(Rc::clone(&alphabet), nodes - 1).map(move |value| Expr::UnaryOp {
+                op,
+                value: Box::new(value),
+            })
+        })
+    };
+    let binary = (1..nodes - 1).flat_map(move |l_nodes| {
+        let alphabet = Rc::clone(&alphabet);
+        sized_exprs(Rc::clone(&alphabet), l_nodes).flat_map(move |l| {
+            let alphabet = Rc::clone(&alphabet);
+            sized_exprs(Rc::clone(&alphabet), nodes - 1 - l_nodes).flat_map(move |r| {
+                let l = l.clone();
+                let ops = alphabet.binary_ops.clone();
+                ops.into_iter().map(move |op| Expr::BinaryOp {
+                    l: Box::new(l.clone()),
+                    op,
+                    r: Box::new(r.clone()),
+                })
+            })
+        })
+    });
+    Box::new(unary.chain(binary))
+}
+
+fn field_name(i: usize) -> String {
+    format!("f{}", i)
+}
+
+/// All cases within the bounds, with ids `exh_<n>` numbered in the order of enumeration.
+pub fn exhaustive_cases(bounds: Bounds) -> Vec<ExhaustiveCase> {
+    let mut cases = Vec::new();
+    // the expressions only depend on the number of fields
+    let mut exprs_by_fields = Vec::new();
+    for fields in 0..=bounds.max_fields {
+        let leaves: Vec<Expr> = (0..fields)
+            .map(|i| Expr::Name(field_name(i)))
+            .chain(LITERALS.map(Expr::Int))
+            .collect();
+        let exprs: Vec<Expr> = (0..=bounds.max_ops)
+            .flat_map(|ops| exprs(&leaves, ops))
+            .collect();
+        exprs_by_fields.push(exprs);
+    }
+    for layout in layouts(bounds.max_fields) {
+        let mut seq = Vec::new();
+        let mut env = Env::new();
+        let mut data = Vec::new();
+        for (i, type_name) in layout.iter().enumerate() {
+            let int_type = IntType::from_name(type_name).expect("must be an integer type");
+            let width = usize::from(int_type.width);
+            let mut bytes = [0; 16];
+            for byte in &mut bytes[..width] {
+                *byte = DATA[data.len() % DATA.len()];
+                data.push(*byte);
+            }
+            let mut value = u128::from_le_bytes(bytes) as i128;
+            if int_type.signed && value > int_type.max_value() {
+                value -= 1 << (8 * width);
+            }
+            seq.push(Attribute::new(field_name(i), *type_name));
+            env.set(field_name(i), Value::Int(value));
+        }
+        for expr in &exprs_by_fields[layout.len()] {
+            let mut spec = KsySpec::top_level(format!("exh_{}", cases.len()));
+            spec.meta.as_mut().unwrap().endian = Some(Endian::Le.into());
+            spec.seq = seq.clone();
+            spec.instances.insert(
+                INSTANCE_NAME.to_string(),
+                Attribute::value_instance(expr.clone()),
+            );
+            cases.push(ExhaustiveCase {
+                spec,
+                data: data.clone(),
+                expected: eval(expr, &env).ok(),
+            });
+        }
+    }
+    cases
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ksy::TypeRef;
+    use crate::typing::{infer, KsType, TypeEnv};
+    use std::collections::HashSet;
+
+    #[test]
+    fn enumeration() {
+        assert_eq!(layouts(2).len(), 1 + 3 + 9);
+        assert_eq!(layouts(2)[4], ["u1", "u1"]);
+        let leaves = [Expr::Int(1), Expr::Int(2)];
+        // 1 and 2 operators: 10 ops for each pair of leaves, twice as many shapes for 2
+        assert_eq!(exprs(&leaves, 1).len(), 10 * 2 * 2);
+        assert_eq!(exprs(&leaves, 2).len(), 2 * 100 * 2 * 2 * 2);
+    }
+
+    #[test]
+    fn lazy_enumeration() {
+        let alphabet = Alphabet {
+            leaves: vec![Expr::Int(1), Expr::Name("a".to_string())],
+            unary_ops: vec![UnaryOp::Neg],
+            binary_ops: vec![BinaryOp::Add, BinaryOp::Shl],
+        };
+        for max_nodes in 0..=5 {
+            let all: Vec<Expr> = all_exprs(&alphabet, max_nodes).collect();
+            assert_eq!(all.len() as u128, expr_count(&alphabet, max_nodes));
+            let distinct: HashSet<String> = all.iter().map(|expr| format!("{:?}", expr)).collect();
+            assert_eq!(distinct.len(), all.len());
+        }
+        // 2 leaves, 2 negations, 2 * 2 * 2 sums and shifts
+        assert_eq!(expr_count(&alphabet, 3), 2 + 2 + (2 + 8));
+        assert_eq!(
+            all_exprs(&alphabet, 3).nth(2),
+            Some(Expr::UnaryOp {
+                op: UnaryOp::Neg,
+                value: Box::new(Expr::Int(1)),
+            })
+        );
+
+        // far too many to collect, but the first ones come right away
+        assert!(expr_count(&alphabet, 40) > u64::MAX.into());
+        assert_eq!(all_exprs(&alphabet, 40).take(1000).count(), 1000);
+    }
+
+    #[test]
+    fn cases_are_distinct_and_type_check() {
+        let cases = exhaustive_cases(Bounds {
+            max_fields: 1,
+            max_ops: 1,
+        });
+        // no fields: 2 leaves, 1 field: 3 leaves, with 3 types
+        assert_eq!(cases.len(), (2 + 10 * 4) + 3 * (3 + 10 * 9));
+        let contents: HashSet<String> = cases
+            .iter()
+            .map(|case| format!("{:?} {:?}", case.spec.seq, case.spec.instances))
+            .collect();
+        assert_eq!(contents.len(), cases.len());
+        for case in &cases {
+            let env = TypeEnv::from_spec(&case.spec, &[]).unwrap();
+            let instance = &case.spec.instances[INSTANCE_NAME];
+            let ty = infer(instance.value.as_ref().unwrap(), &env).unwrap();
+            assert_eq!(ty.widened(), KsType::Int);
+        }
+        let s1 = TypeRef::Named("s1".to_string());
+        let s1 = cases
+            .iter()
+            .find(|case| {
+                case.spec
+                    .seq
+                    .iter()
+                    .any(|f| f.type_ref.as_ref() == Some(&s1))
+            })
+            .unwrap();
+        assert_eq!(s1.data, [0x81]);
+        assert_eq!(s1.expected, Some(Value::Int(-127)));
+        assert!(cases.iter().any(|case| case.expected.is_none()));
+    }
+}
@@ -0,0 +1,788 @@
+//! Type-directed generation of random KS expressions.
+//!
+//! Instead of generating arbitrary expressions and throwing away those that don't type check, the
+//! generator is asked for an expression of a particular type (e.g. `bool` for an `if` condition or
+//! `int` for a `repeat-expr` count) and only picks productions that yield that type.
+
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+use crate::ast::utils::PositiveFiniteF64;
+use crate::ast::{BinaryOp, Expr, TypeName, UnaryOp};
+use crate::gen::profile::{GenProfile, COND_OP};
+use crate::numeric::IntType;
+use crate::tolerance::float_literal;
+use crate::typing::{is_valid_switch_case, KsType, TypeEnv};
+
+/// Probability that a node above the depth limit is a compound expression rather than a leaf
+const COMPOUND_PROBABILITY: f64 = 0.7;
+
+const ARITH_OPS: [BinaryOp; 5] = [
+    BinaryOp::Add,
+    BinaryOp::Sub,
+    BinaryOp::Mul,
+    BinaryOp::Div,
+    BinaryOp::Rem,
+];
+
+const BITWISE_OPS: [BinaryOp; 5] = [
+    BinaryOp::BitAnd,
+    BinaryOp::BitOr,
+    BinaryOp::BitXor,
+    BinaryOp::Shl,
+    BinaryOp::Shr,
+];
+
+const ORDER_OPS: [BinaryOp; 4] = [BinaryOp::Lt, BinaryOp::Le, BinaryOp::Gt, BinaryOp::Ge];
+
+const ENCODINGS: [&str; 2] = ["ASCII", "UTF-8"];
+
+/// Integers at the limits of the integer types of the targets, and of the integers that doubles
+/// hold exactly
+const BOUNDARY_INTS: [i128; 12] = [
+    0,
+    1,
+    -1,
+    i32::MAX as i128,
+    i32::MAX as i128 + 1,
+    i32::MIN as i128,
+    u32::MAX as i128,
+    1 << 53,
+    i64::MAX as i128,
+    i64::MIN as i128,
+    1 << 63,
+    u64::MAX as i128,
+];
+
+/// Floats at the limits of doubles, and the largest integer up to which they're all exact
+const BOUNDARY_FLOATS: [f64; 7] = [
+    0.0,
+    1.0,
+    -1.0,
+    f64::MAX,
+    f64::MIN_POSITIVE,
+    // the smallest subnormal
+    5e-324,
+    9007199254740992.0,
+];
+
+const BOUNDARY_STRS: [&str; 2] = ["", "a"];
+
+/// Ways of building a compound expression of a requested type.
+#[derive(Clone, Debug)]
+enum Production {
+    Unary(UnaryOp, KsType),
+    Binary(BinaryOp, KsType, KsType),
+    Cond,
+    /// `?:` with branches of different types that KSC unifies to the requested type
+    CondMixed(KsType, KsType),
+    Attribute(KsType, &'static str),
+    MethodCall(KsType, &'static str),
+    Subscript(KsType),
+    SizeOf,
+    /// Conversion of an integer to the requested enum type
+    IntToEnum,
+}
+
+impl Production {
+    /// Name of the kind of expression in the weights of variants of profiles
+    fn variant(&self) -> &'static str {
+        match self {
+            Production::Unary(_, _) => "unary",
+            Production::Binary(_, _, _) => "binary",
+            Production::Cond | Production::CondMixed(_, _) => "cond",
+            Production::Attribute(_, _) => "attribute",
+            Production::MethodCall(_, _) => "method-call",
+            Production::Subscript(_) => "subscript",
+            Production::SizeOf => "sizeof",
+            Production::IntToEnum => "cast",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ExprGenerator<'a> {
+    env: &'a TypeEnv,
+    max_depth: usize,
+    profile: Option<&'a GenProfile>,
+}
+
+impl<'a> ExprGenerator<'a> {
+    /// Generator of expressions referring to the names in `env`, with at most `max_depth` levels
+    /// of operators above the leaves.
+    pub fn new(env: &'a TypeEnv, max_depth: usize) -> Self {
+        Self {
+            env,
+            max_depth,
+            profile: None,
+        }
+    }
+
+    /// Generator with the depth limit, operators and kinds of expressions of the profile.
+    pub fn from_profile(env: &'a TypeEnv, profile: &'a GenProfile) -> Self {
+        Self::new(env, profile.sizes.max_depth).with_profile(profile)
+    }
+
+    /// Generator using only the operators and kinds of expressions that `profile` allows, picked
+    /// by their weights, and literals in its ranges.
+    pub fn with_profile(self, profile: &'a GenProfile) -> Self {
+        Self {
+            profile: Some(profile),
+            ..self
+        }
+    }
+
+    /// Random expression of type `ty`, or `None` if no such expression can be built in the
+    /// environment (e.g. a user type that no name refers to).
+    pub fn generate<R: Rng + ?Sized>(&self, rng: &mut R, ty: &KsType) -> Option<Expr> {
+        self.generate_at(rng, ty, self.max_depth)
+    }
+
+    /// Up to `count` distinct constant keys for the `cases` of a `switch-on` over a value of type
+    /// `on`.
+    pub fn switch_cases<R: Rng + ?Sized>(
+        &self,
+        rng: &mut R,
+        on: &KsType,
+        count: usize,
+    ) -> Vec<Expr> {
+        let mut cases = Vec::new();
+        // types like `bool` have only a few values, so don't insist on reaching `count`
+        for _ in 0..count * 4 {
+            if cases.len() == count {
+                break;
+            }
+            let Some(case) = self.case_literal(rng, on) else {
+                break;
+            };
+            if !cases.contains(&case) {
+                cases.push(case);
+            }
+        }
+        cases
+    }
+
+    /// Case key that KSC rejects in a `switch-on` over a value of type `on`, while looking
+    /// plausible (e.g. an integer in a switch over an enum), for negative compiler tests.
+    pub fn near_miss_case<R: Rng + ?Sized>(&self, rng: &mut R, on: &KsType) -> Option<Expr> {
+        let mut case_types = vec![KsType::Int, KsType::Str, KsType::Bool, KsType::Bytes];
+        case_types.extend(self.env.enums().map(|(path, _)| KsType::Enum(path.clone())));
+        case_types.retain(|case_type| !is_valid_switch_case(on, case_type));
+        let case_type = case_types.choose(rng)?;
+        self.literal(rng, case_type)
+    }
+
+    fn case_literal<R: Rng + ?Sized>(&self, rng: &mut R, on: &KsType) -> Option<Expr> {
+        match on {
+            // the case keys are compared with the value read from the stream, so they should be
+            // in its range
+            KsType::SizedInt(int_type) => Some(int_literal(
+                rng.gen_range(int_type.min_value()..=int_type.max_value()),
+            )),
+            KsType::Int | KsType::Str | KsType::Bool | KsType::Bytes | KsType::Enum(_) => {
+                self.literal(rng, on)
+            }
+            _ => None,
+        }
+    }
+
+    fn generate_at<R: Rng + ?Sized>(&self, rng: &mut R, ty: &KsType, depth: usize) -> Option<Expr> {
+        if depth == 0 {
+            return self.leaf(rng, ty);
+        }
+        // some types (e.g. the common supertype of user types) only have compound expressions
+        let compound = self
+            .profile
+            .and_then(|profile| profile.expressions.compound)
+            .unwrap_or(COMPOUND_PROBABILITY);
+        if rng.gen_bool(compound) {
+            self.compound(rng, ty, depth - 1)
+                .or_else(|| self.leaf(rng, ty))
+        } else {
+            self.leaf(rng, ty)
+                .or_else(|| self.compound(rng, ty, depth - 1))
+        }
+    }
+
+    fn compound<R: Rng + ?Sized>(&self, rng: &mut R, ty: &KsType, depth: usize) -> Option<Expr> {
+        let mut productions = self.productions(ty);
+        if self.profile.is_some() {
+            productions.retain(|production| self.weight(production) > 0);
+            productions = productions
+                .choose_multiple_weighted(rng, productions.len(), |production| {
+                    self.weight(production)
+                })
+                .ok()?
+                .cloned()
+                .collect();
+        } else {
+            productions.shuffle(rng);
+        }
+        productions
+            .into_iter()
+            .find_map(|production| self.apply(rng, production, ty, depth))
+    }
+
+    /// Weight of the production in the profile: the one of its kind, times the one of its
+    /// operator if it has one.
+    fn weight(&self, production: &Production) -> u32 {
+        let Some(profile) = self.profile else {
+            return 1;
+        };
+        let operator = match production {
+            Production::Unary(op, _) => profile.operator_weight(op.symbol()),
+            Production::Binary(op, _, _) => profile.operator_weight(op.symbol()),
+            Production::Cond | Production::CondMixed(_, _) => profile.operator_weight(COND_OP),
+            _ => 1,
+        };
+        operator * profile.variant_weight(production.variant())
+    }
+
+    fn leaf<R: Rng + ?Sized>(&self, rng: &mut R, ty: &KsType) -> Option<Expr> {
+        let refs = self.refs(ty);
+        if !refs.is_empty() && rng.gen_bool(0.5) {
+            return refs.choose(rng).cloned();
+        }
+        self.literal(rng, ty).or_else(|| refs.choose(rng).cloned())
+    }
+
+    /// Names and fields of user types accessible through them that have type `ty`.
+    fn refs(&self, ty: &KsType) -> Vec<Expr> {
+        let mut refs = Vec::new();
+        for (name, name_ty) in self.env.names() {
+            if name_ty == ty {
+                refs.push(Expr::Name(name.clone()));
+            }
+            if let KsType::User(type_path) = name_ty {
+                let fields = self.env.type_fields(type_path).into_iter().flatten();
+                for (field, field_ty) in fields {
+                    if field_ty == ty {
+                        refs.push(Expr::Attribute {
+                            value: Box::new(Expr::Name(name.clone())),
+                            attr_name: field.clone(),
+                        });
+                    }
+                }
+            }
+        }
+        refs
+    }
+
+    fn productions(&self, ty: &KsType) -> Vec<Production> {
+        use Production::*;
+
+        let mut productions = vec![Cond];
+        match ty {
+            KsType::Int => {
+                for op in ARITH_OPS.into_iter().chain(BITWISE_OPS) {
+                    productions.push(Binary(op, KsType::Int, KsType::Int));
+                }
+                productions.push(Unary(UnaryOp::Neg, KsType::Int));
+                productions.push(Unary(UnaryOp::Inv, KsType::Int));
+                productions.push(Attribute(KsType::Str, "length"));
+                productions.push(Attribute(KsType::Str, "to_i"));
+                productions.push(Attribute(KsType::Float, "to_i"));
+                productions.push(Attribute(KsType::Bool, "to_i"));
+                for attr in ["size", "first", "last", "min", "max"] {
+                    productions.push(Attribute(KsType::Bytes, attr));
+                }
+                productions.push(Attribute(KsType::Array(Box::new(KsType::Int)), "size"));
+                productions.push(Attribute(KsType::Array(Box::new(KsType::Str)), "size"));
+                productions.push(Attribute(KsType::Stream, "pos"));
+                productions.push(Attribute(KsType::Stream, "size"));
+                for (enum_path, _) in self.env.enums() {
+                    productions.push(Attribute(KsType::Enum(enum_path.clone()), "to_i"));
+                }
+                productions.push(MethodCall(KsType::Str, "to_i"));
+                // mixing fixed-width integers into computations is where overflows happen
+                for int_type in IntType::ALL {
+                    productions.push(Binary(
+                        BinaryOp::Add,
+                        KsType::SizedInt(int_type),
+                        KsType::Int,
+                    ));
+                }
+                productions.push(SizeOf);
+                let (u1, s8) = (IntType::ALL[0], IntType::ALL[7]);
+                productions.push(CondMixed(KsType::SizedInt(u1), KsType::Int));
+                productions.push(CondMixed(KsType::SizedInt(u1), KsType::SizedInt(s8)));
+                productions.push(Subscript(KsType::Bytes));
+                productions.push(Subscript(KsType::Array(Box::new(KsType::Int))));
+            }
+            KsType::Float => {
+                for op in ARITH_OPS {
+                    productions.push(Binary(op, KsType::Float, KsType::Float));
+                    productions.push(Binary(op, KsType::Int, KsType::Float));
+                    productions.push(Binary(op, KsType::Float, KsType::Int));
+                }
+                productions.push(Unary(UnaryOp::Neg, KsType::Float));
+                productions.push(CondMixed(KsType::Int, KsType::Float));
+                productions.push(CondMixed(KsType::Float, KsType::Int));
+                productions.push(Subscript(KsType::Array(Box::new(KsType::Float))));
+            }
+            KsType::Str => {
+                productions.push(Binary(BinaryOp::Add, KsType::Str, KsType::Str));
+                productions.push(Attribute(KsType::Int, "to_s"));
+                productions.push(Attribute(KsType::Str, "reverse"));
+                productions.push(MethodCall(KsType::Str, "substring"));
+                productions.push(MethodCall(KsType::Bytes, "to_s"));
+                productions.push(Subscript(KsType::Array(Box::new(KsType::Str))));
+            }
+            KsType::Bool => {
+                let mut comparable = vec![
+                    KsType::Int,
+                    KsType::Float,
+                    KsType::Str,
+                    KsType::Bool,
+                    KsType::Bytes,
+                ];
+                comparable.extend(self.env.enums().map(|(path, _)| KsType::Enum(path.clone())));
+                for operand in comparable {
+                    productions.push(Binary(BinaryOp::Eq, operand.clone(), operand.clone()));
+                    productions.push(Binary(BinaryOp::Ne, operand.clone(), operand));
+                }
+                for operand in [KsType::Int, KsType::Float, KsType::Str, KsType::Bytes] {
+                    for op in ORDER_OPS {
+                        productions.push(Binary(op, operand.clone(), operand.clone()));
+                    }
+                }
+                productions.push(Binary(BinaryOp::Lt, KsType::Int, KsType::Float));
+                productions.push(Binary(BinaryOp::And, KsType::Bool, KsType::Bool));
+                productions.push(Binary(BinaryOp::Or, KsType::Bool, KsType::Bool));
+                productions.push(Unary(UnaryOp::Not, KsType::Bool));
+                productions.push(Attribute(KsType::Stream, "eof"));
+                productions.push(Subscript(KsType::Array(Box::new(KsType::Bool))));
+            }
+            KsType::Enum(_) => productions.push(IntToEnum),
+            KsType::AnyStruct => {
+                for a in self.env.user_types() {
+                    for b in self.env.user_types().filter(|b| *b != a) {
+                        productions
+                            .push(CondMixed(KsType::User(a.clone()), KsType::User(b.clone())));
+                    }
+                }
+            }
+            KsType::SizedInt(_)
+            | KsType::Bytes
+            | KsType::Array(_)
+            | KsType::User(_)
+            | KsType::Stream => {}
+        }
+        productions
+    }
+
+    fn apply<R: Rng + ?Sized>(
+        &self,
+        rng: &mut R,
+        production: Production,
+        ty: &KsType,
+        depth: usize,
+    ) -> Option<Expr> {
+        Some(match production {
+            Production::Unary(op, operand) => Expr::UnaryOp {
+                op,
+                value: Box::new(self.generate_at(rng, &operand, depth)?),
+            },
+            Production::Binary(op, l, r) => Expr::BinaryOp {
+                l: Box::new(self.generate_at(rng, &l, depth)?),
+                op,
+                r: Box::new(self.generate_at(rng, &r, depth)?),
+            },
+            Production::Cond => Expr::CondOp {
+                cond: Box::new(self.generate_at(rng, &KsType::Bool, depth)?),
+                if_true: Box::new(self.generate_at(rng, ty, depth)?),
+                if_false: Box::new(self.generate_at(rng, ty, depth)?),
+            },
+            Production::CondMixed(if_true, if_false) => Expr::CondOp {
+                cond: Box::new(self.generate_at(rng, &KsType::Bool, depth)?),
+                if_true: Box::new(self.generate_at(rng, &if_true, depth)?),
+                if_false: Box::new(self.generate_at(rng, &if_false, depth)?),
+            },
+            Production::Attribute(receiver, attr_name) => Expr::Attribute {
+                value: Box::new(self.generate_at(rng, &receiver, depth)?),
+                attr_name: attr_name.to_string(),
+            },
+            Production::MethodCall(receiver, method_name) => {
+                let value = Box::new(self.generate_at(rng, &receiver, depth)?);
+                let args = match method_name {
+                    "substring" => vec![
+                        self.generate_at(rng, &KsType::Int, depth)?,
+                        self.generate_at(rng, &KsType::Int, depth)?,
+                    ],
+                    // only a handful of radixes and encodings are supported by all targets, so
+                    // these arguments are always literals
+                    "to_i" => vec![Expr::Int(*[2, 8, 10, 16].choose(rng)?)],
+                    "to_s" => vec![Expr::Str(ENCODINGS.choose(rng)?.to_string())],
+                    _ => unreachable!("no production for method `{method_name}`"),
+                };
+                Expr::MethodCall {
+                    value,
+                    method_name: method_name.to_string(),
+                    args,
+                }
+            }
+            Production::Subscript(container) => Expr::Subscript {
+                value: Box::new(self.generate_at(rng, &container, depth)?),
+                idx: Box::new(self.generate_at(rng, &KsType::Int, depth)?),
+            },
+            Production::IntToEnum => {
+                let KsType::Enum(enum_path) = ty else {
+                    unreachable!("int to enum conversion producing {:?}", ty);
+                };
+                Expr::CastTo {
+                    value: Box::new(self.generate_at(rng, &KsType::Int, depth)?),
+                    type_name: TypeName {
+                        path: enum_path.clone(),
+                        is_array: false,
+                    },
+                }
+            }
+            Production::SizeOf => {
+                let int_type = IntType::ALL.choose(rng)?;
+                Expr::SizeOf {
+                    type_name: TypeName::new(int_type.name()),
+                    bits: rng.gen(),
+                }
+            }
+        })
+    }
+
+    /// Random literal of type `ty`, if the type has literals.
+    fn literal<R: Rng + ?Sized>(&self, rng: &mut R, ty: &KsType) -> Option<Expr> {
+        let expressions = self.profile.map(|profile| &profile.expressions);
+        let boundary = expressions.and_then(|e| e.boundary).unwrap_or(0.0);
+        if boundary > 0.0 && rng.gen_bool(boundary) {
+            if let Some(literal) = boundary_literal(rng, ty) {
+                return Some(literal);
+            }
+        }
+        Some(match ty {
+            KsType::Int => match expressions.and_then(|e| e.int_range) {
+                Some((min, max)) => int_literal(rng.gen_range(min..=max).into()),
+                None => Expr::Int(int_value(rng)),
+            },
+            KsType::SizedInt(int_type) => {
+                // values at the edges of the range are the most likely to expose overflows
+                let value = match rng.gen_range(0..4) {
+                    0 => int_type.min_value(),
+                    1 => int_type.max_value(),
+                    2 => 0,
+                    _ => rng.gen_range(int_type.min_value()..=int_type.max_value()),
+                };
+                Expr::CastTo {
+                    value: Box::new(int_literal(value)),
+                    type_name: TypeName::new(int_type.name()),
+                }
+            }
+            KsType::Float => match expressions.and_then(|e| e.float_range) {
+                Some((min, max)) => float_literal(rng.gen_range(min..=max))?,
+                None => {
+                    // mostly "nice" values that are exactly representable in binary
+                    let value = if rng.gen_bool(0.8) {
+                        f64::from(rng.gen_range(0..64)) / 4.0
+                    } else {
+                        rng.gen_range(0.0..1e6)
+                    };
+                    Expr::Float(PositiveFiniteF64::try_from(value).ok()?)
+                }
+            },
+            KsType::Str => {
+                let max_len = expressions.and_then(|e| e.max_str_len).unwrap_or(5);
+                let len = rng.gen_range(0..max_len + 1);
+                Expr::Str(
+                    (0..len)
+                        .map(|_| char::from(rng.gen_range(b'a'..=b'z')))
+                        .collect(),
+                )
+            }
+            KsType::Bool => Expr::Bool(rng.gen()),
+            KsType::Bytes => {
+                let len = rng.gen_range(1..5);
+                Expr::List(
+                    (0..len)
+                        .map(|_| Expr::Int(rng.gen_range(0..=255)))
+                        .collect(),
+                )
+            }
+            KsType::Array(item) => {
+                let len = rng.gen_range(1..4);
+                let mut items = (0..len)
+                    .map(|_| self.literal(rng, item))
+                    .collect::<Option<Vec<_>>>()?;
+                // a list of byte-sized integer literals would be a byte array instead
+                if **item == KsType::Int {
+                    items[0] = Expr::Int(rng.gen_range(256..=65535));
+                }
+                Expr::List(items)
+            }
+            KsType::Enum(enum_path) => {
+                let labels = self.env.enum_labels(enum_path)?;
+                let label = labels.iter().nth(rng.gen_range(0..labels.len()))?;
+                Expr::EnumMember {
+                    enum_path: enum_path.clone(),
+                    label: label.clone(),
+                }
+            }
+            KsType::User(_) | KsType::AnyStruct | KsType::Stream => return None,
+        })
+    }
+}
+
+/// Integer literal, wrapped in a negation if it's negative. The magnitude of the value must fit
+/// in a `u64`, like that of every integer of the expression language.
+pub fn int_literal(value: i128) -> Expr {
+    let magnitude = u64::try_from(value.unsigned_abs())
+        .unwrap_or_else(|_| panic!("integer literal {} is out of the range of u64", value));
+    let lit = Expr::Int(magnitude);
+    if value < 0 {
+        Expr::UnaryOp {
+            op: UnaryOp::Neg,
+            value: Box::new(lit),
+        }
+    } else {
+        lit
+    }
+}
+
+/// Random boundary value of type `ty`, if it has any.
+fn boundary_literal<R: Rng + ?Sized>(rng: &mut R, ty: &KsType) -> Option<Expr> {
+    match ty {
+        KsType::Int => Some(int_literal(*BOUNDARY_INTS.choose(rng)?)),
+        KsType::Float => float_literal(*BOUNDARY_FLOATS.choose(rng)?),
+        KsType::Str => Some(Expr::Str(BOUNDARY_STRS.choose(rng)?.to_string())),
+        _ => None,
+    }
+}
+
+fn int_value<R: Rng + ?Sized>(rng: &mut R) -> u64 {
+    match rng.gen_range(0..4) {
+        0 => rng.gen_range(0..=8),
+        1 => rng.gen_range(0..=255),
+        2 => rng.gen_range(0..=65535),
+        _ => rng.gen(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::typing::infer;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn check_types(env: &TypeEnv, ty: &KsType) {
+        let generator = ExprGenerator::new(env, 4);
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..200 {
+            let expr = generator.generate(&mut rng, ty).unwrap();
+            assert_eq!(infer(&expr, env).as_ref(), Ok(ty), "{:?}", expr);
+        }
+    }
+
+    #[test]
+    fn primitive_types() {
+        let env = TypeEnv::new();
+        for ty in [KsType::Int, KsType::Float, KsType::Str, KsType::Bool] {
+            check_types(&env, &ty);
+        }
+    }
+
+    #[test]
+    fn sized_ints() {
+        let env = TypeEnv::new();
+        for int_type in IntType::ALL {
+            check_types(&env, &KsType::SizedInt(int_type));
+        }
+    }
+
+    #[test]
+    fn byte_and_int_arrays() {
+        let env = TypeEnv::new();
+        check_types(&env, &KsType::Bytes);
+        check_types(&env, &KsType::Array(Box::new(KsType::Int)));
+    }
+
+    #[test]
+    fn names_fields_and_enums() {
+        let mut env = TypeEnv::new();
+        env.set("hdr", KsType::User(vec!["header".to_string()]));
+        env.set("_io", KsType::Stream);
+        env.define_type(
+            vec!["header".to_string()],
+            [
+                ("num_items", KsType::Int),
+                ("kind", KsType::Enum(vec!["kind".to_string()])),
+            ],
+        );
+        env.define_enum(vec!["kind".to_string()], ["a", "b"]);
+        check_types(&env, &KsType::Bool);
+        check_types(&env, &KsType::Enum(vec!["kind".to_string()]));
+    }
+
+    #[test]
+    fn enum_literals() {
+        let mut env = TypeEnv::new();
+        env.define_enum(vec!["animal".to_string()], ["cat", "dog"]);
+        check_types(&env, &KsType::Enum(vec!["animal".to_string()]));
+        check_types(&env, &KsType::Int);
+    }
+
+    #[test]
+    fn mixed_user_types() {
+        let mut env = TypeEnv::new();
+        env.define_type(vec!["header".to_string()], [("len", KsType::Int)]);
+        env.define_type(vec!["footer".to_string()], [("crc", KsType::Int)]);
+        env.set("hdr", KsType::User(vec!["header".to_string()]));
+        env.set("ftr", KsType::User(vec!["footer".to_string()]));
+        check_types(&env, &KsType::AnyStruct);
+    }
+
+    #[test]
+    fn switch_cases() {
+        let mut env = TypeEnv::new();
+        env.define_enum(vec!["animal".to_string()], ["cat", "dog", "fox"]);
+        let generator = ExprGenerator::new(&env, 3);
+        let mut rng = StdRng::seed_from_u64(0);
+        let on_types = [
+            KsType::SizedInt(IntType::from_name("s2").unwrap()),
+            KsType::Str,
+            KsType::Bool,
+            KsType::Enum(vec!["animal".to_string()]),
+        ];
+        for on in on_types {
+            let cases = generator.switch_cases(&mut rng, &on, 3);
+            assert!(!cases.is_empty());
+            for (i, case) in cases.iter().enumerate() {
+                assert!(!cases[..i].contains(case));
+                let case_type = infer(case, &env).unwrap();
+                assert!(is_valid_switch_case(&on, &case_type), "{:?}", case);
+            }
+            let near_miss = generator.near_miss_case(&mut rng, &on).unwrap();
+            let case_type = infer(&near_miss, &env).unwrap();
+            assert!(!is_valid_switch_case(&on, &case_type), "{:?}", near_miss);
+        }
+    }
+
+    #[test]
+    fn impossible_type() {
+        let env = TypeEnv::new();
+        let generator = ExprGenerator::new(&env, 3);
+        let mut rng = StdRng::seed_from_u64(0);
+        let ty = KsType::User(vec!["missing".to_string()]);
+        assert_eq!(generator.generate(&mut rng, &ty), None);
+    }
+
+    #[test]
+    fn profile_operators() {
+        fn ops(expr: &Expr, symbols: &mut Vec<&'static str>) {
+            match expr {
+                Expr::UnaryOp { op, .. } => symbols.push(op.symbol()),
+                Expr::BinaryOp { op, .. } => symbols.push(op.symbol()),
+                Expr::CondOp { .. } => symbols.push(COND_OP),
+                _ => {}
+            }
+            for child in expr.children() {
+                ops(child, symbols);
+            }
+        }
+
+        let profile = GenProfile::from_toml_str("[operators]\n\"&\" = 3\n\"<<\" = 1\n").unwrap();
+        let env = TypeEnv::new();
+        let generator = ExprGenerator::new(&env, 3).with_profile(&profile);
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut symbols = Vec::new();
+        for _ in 0..100 {
+            let expr = generator.generate(&mut rng, &KsType::Int).unwrap();
+            assert_eq!(infer(&expr, &env), Ok(KsType::Int), "{:?}", expr);
+            ops(&expr, &mut symbols);
+        }
+        assert!(symbols.contains(&"&"));
+        assert!(symbols.iter().all(|symbol| ["&", "<<"].contains(symbol)));
+    }
+
+    #[test]
+    fn profile_expressions() {
+        let toml = "[sizes]\nmax-depth = 2\n\
+                    [expressions]\n\
+                    compound = 1.0\n\
+                    int-range = [-3, 3]\n\
+                    float-range = [-1.0, 1.0]\n\
+                    [expressions.variants]\n\
+                    binary = 1\n";
+        let profile = GenProfile::from_toml_str(toml).unwrap();
+        let env = TypeEnv::new();
+        let generator = ExprGenerator::from_profile(&env, &profile);
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..100 {
+            let expr = generator.generate(&mut rng, &KsType::Int).unwrap();
+            assert_eq!(infer(&expr, &env), Ok(KsType::Int), "{:?}", expr);
+            assert!(matches!(expr, Expr::BinaryOp { .. }), "{:?}", expr);
+            let mut nodes = vec![&expr];
+            while let Some(node) = nodes.pop() {
+                match node {
+                    Expr::BinaryOp { .. } => nodes.extend(node.children()),
+                    Expr::Int(value) => assert!(*value <= 3, "{:?}", expr),
+                    Expr::UnaryOp { op, value } => {
+                        assert_eq!(*op, UnaryOp::Neg);
+                        assert!(matches!(**value, Expr::Int(1..=3)), "{:?}", expr);
+                    }
+                    // literals of fixed-width integers are in the range of their type
+                    Expr::CastTo { .. } => {}
+                    _ => panic!("{:?}", expr),
+                }
+            }
+        }
+        let expr = generator.generate(&mut rng, &KsType::Float).unwrap();
+        assert_eq!(infer(&expr, &env), Ok(KsType::Float), "{:?}", expr);
+    }
+
+    #[test]
+    fn int_literals() {
+        assert_eq!(int_literal(u64::MAX.into()), Expr::Int(u64::MAX));
+        assert_eq!(
+            int_literal(-i128::from(u64::MAX)),
+            Expr::UnaryOp {
+                op: UnaryOp::Neg,
+                value: Box::new(Expr::Int(u64::MAX)),
+            }
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "integer literal 18446744073709551616 is out of the range of u64")]
+    fn int_literal_out_of_range() {
+        int_literal(i128::from(u64::MAX) + 1);
+    }
+
+    #[test]
+    fn boundary_literals() {
+        let profile = GenProfile::from_toml_str("[expressions]\nboundary = 1.0\n").unwrap();
+        let env = TypeEnv::new();
+        let generator = ExprGenerator::new(&env, 0).with_profile(&profile);
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut ints = Vec::new();
+        for _ in 0..200 {
+            let expr = generator.generate(&mut rng, &KsType::Int).unwrap();
+            let value = match expr {
+                Expr::Int(x) => i128::from(x),
+                Expr::UnaryOp { value, .. } => match *value {
+                    Expr::Int(x) => -i128::from(x),
+                    _ => panic!("{:?}", value),
+                },
+                _ => panic!("{:?}", expr),
+            };
+            assert!(BOUNDARY_INTS.contains(&value), "{}", value);
+            ints.push(value);
+        }
+        assert!(BOUNDARY_INTS.iter().all(|x| ints.contains(x)));
+
+        for ty in [KsType::Float, KsType::Str] {
+            for _ in 0..20 {
+                let expr = generator.generate(&mut rng, &ty).unwrap();
+                assert_eq!(infer(&expr, &env).as_ref(), Ok(&ty), "{:?}", expr);
+            }
+        }
+        let expr = generator.generate(&mut rng, &KsType::Str).unwrap();
+        assert!(matches!(&expr, Expr::Str(s) if s.len() <= 1), "{:?}", expr);
+    }
+}
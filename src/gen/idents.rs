@@ -0,0 +1,181 @@
+//! Specs whose attributes, types, enums and instances are named after keywords of the target
+//! languages and members of their runtimes (see [`Target::reserved_words`]), so that the
+//! escaping of names in generated parsers gets exercised.
+
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+use crate::ast::utils::name;
+use crate::ast::{BinaryOp, Expr};
+use crate::eval::Value;
+use crate::gen::case::SpecCase;
+use crate::ksy::{Attribute, KsySpec, TypeSpec};
+use crate::target::Target;
+use crate::typing::spec::builtin_type;
+
+/// Names that can't be referred to in KS expressions
+const KS_KEYWORDS: [&str; 8] = [
+    "and",
+    "or",
+    "not",
+    "true",
+    "false",
+    "as",
+    "sizeof",
+    "bitsizeof",
+];
+/// Names with a meaning in the `type` key besides the built-in types
+const KS_TYPE_NAMES: [&str; 5] = ["bool", "bytes", "struct", "io", "any"];
+
+/// Reserved words of the targets that can be used as names in a spec, sorted and deduplicated.
+pub fn reserved_words(targets: &[Target]) -> Vec<&'static str> {
+    let mut words: Vec<&str> = targets
+        .iter()
+        .flat_map(|target| target.reserved_words())
+        .copied()
+        .filter(|word| !KS_KEYWORDS.contains(word) && !KS_TYPE_NAMES.contains(word))
+        .filter(|word| builtin_type(word).is_none())
+        .collect();
+    words.sort_unstable();
+    words.dedup();
+    words
+}
+
+/// Case with up to `count` `u1` attributes in the `seq`, plus a user type, an enum and a value
+/// instance, all named after distinct reserved words of the targets. Returns `None` if the
+/// targets don't have enough reserved words.
+pub fn ident_case<R: Rng + ?Sized>(
+    rng: &mut R,
+    id: &str,
+    targets: &[Target],
+    count: usize,
+) -> Option<SpecCase> {
+    let words = reserved_words(targets);
+    // besides the plain attributes: the type, its attribute, the attribute of the type, the enum,
+    // 2 labels, the enum attribute and the instance
+    let extra = 8;
+    let count = count.clamp(2, words.len().checked_sub(extra)?);
+    let mut words: Vec<&str> = words.choose_multiple(rng, count + extra).copied().collect();
+    let mut next_word = || words.pop().expect("enough words were chosen");
+
+    let mut spec = KsySpec::top_level(id);
+    let mut data = Vec::new();
+    let mut assertions = Vec::new();
+    let mut plain = Vec::new();
+    for _ in 0..count {
+        let word = next_word();
+        let value: u8 = rng.gen();
+        spec.seq.push(Attribute::new(word, "u1"));
+        data.push(value);
+        assertions.push((name(word), Value::Int(value.into())));
+        plain.push((word, value));
+    }
+
+    let (type_name, field, attr_name) = (next_word(), next_word(), next_word());
+    let value: u8 = rng.gen();
+    spec.types.insert(
+        type_name.to_string(),
+        TypeSpec {
+            seq: vec![Attribute::new(field, "u1")],
+            ..Default::default()
+        },
+    );
+    spec.seq.push(Attribute::new(attr_name, type_name));
+    data.push(value);
+    let field_expr = Expr::Attribute {
+        value: Box::new(name(attr_name)),
+        attr_name: field.to_string(),
+    };
+    assertions.push((field_expr, Value::Int(value.into())));
+
+    let (enum_name, labels, enum_attr) = (next_word(), [next_word(), next_word()], next_word());
+    spec.enums.insert(
+        enum_name.to_string(),
+        labels
+            .iter()
+            .enumerate()
+            .map(|(i, label)| (i as i128, label.to_string()))
+            .collect(),
+    );
+    spec.seq.push(Attribute {
+        enum_name: Some(enum_name.to_string()),
+        ..Attribute::new(enum_attr, "u1")
+    });
+    let member = rng.gen_range(0..labels.len());
+    data.push(member as u8);
+    let is_first = Expr::BinaryOp {
+        l: Box::new(name(enum_attr)),
+        op: BinaryOp::Eq,
+        r: Box::new(Expr::EnumMember {
+            enum_path: vec![enum_name.to_string()],
+            label: labels[0].to_string(),
+        }),
+    };
+    assertions.push((is_first, Value::Bool(member == 0)));
+
+    let instance = next_word();
+    let [(l, l_value), (r, r_value)] = [plain[0], plain[1]];
+    spec.instances.insert(
+        instance.to_string(),
+        Attribute::value_instance(Expr::BinaryOp {
+            l: Box::new(name(l)),
+            op: BinaryOp::Add,
+            r: Box::new(name(r)),
+        }),
+    );
+    let sum = i128::from(l_value) + i128::from(r_value);
+    assertions.push((name(instance), Value::Int(sum)));
+    Some(SpecCase {
+        spec,
+        data,
+        assertions,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::typing::{infer, TypeEnv};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn words() {
+        let words = reserved_words(&Target::ALL);
+        assert!(words.contains(&"class"));
+        assert!(words.contains(&"end"));
+        assert!(!words.contains(&"as"));
+        assert!(!words.contains(&"bool"));
+        assert!(words.windows(2).all(|pair| pair[0] < pair[1]));
+        for target in Target::ALL {
+            for word in target.reserved_words() {
+                let is_identifier = word.starts_with(|c: char| c.is_ascii_lowercase())
+                    && word
+                        .chars()
+                        .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+                assert!(is_identifier, "{}", word);
+            }
+        }
+    }
+
+    #[test]
+    fn expressions_resolve() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for target in Target::ALL {
+            let case = ident_case(&mut rng, "idents", &[target], 4).unwrap();
+            let env = TypeEnv::from_spec(&case.spec, &[]).unwrap();
+            assert_eq!(case.assertions.len(), 4 + 3);
+            for (expr, _) in &case.assertions {
+                // value instances aren't in the environment, their values are checked instead
+                let expr = match expr {
+                    Expr::Name(name) => match &case.spec.instances.get(name) {
+                        Some(instance) => instance.value.as_ref().unwrap(),
+                        None => expr,
+                    },
+                    _ => expr,
+                };
+                assert!(infer(expr, &env).is_ok(), "{}: {:?}", target, expr);
+            }
+        }
+    }
+}
@@ -0,0 +1,154 @@
+//! Families of specs linked by `meta/imports`, where the main spec uses types and enums of the
+//! imported ones (including one imported indirectly) in attributes and expressions.
+
+use rand::Rng;
+
+use crate::ast::utils::{attr, binary, name};
+use crate::ast::{BinaryOp, Expr};
+use crate::eval::Value;
+use crate::gen::case::SpecCase;
+use crate::ksy::{Attribute, KsySpec, TypeSpec};
+
+/// Specs of the family, with data for the main spec and the expected values of expressions in it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ImportsCase {
+    /// The main spec, with its data
+    pub main: SpecCase,
+    /// Imported specs; each one is meant to be saved in a file named after its `meta/id`
+    pub imports: Vec<KsySpec>,
+}
+
+const COLORS: [&str; 4] = ["black", "red", "green", "blue"];
+
+/// Family of three specs:
+///
+/// - `<id>_colors` defines an enum `color`
+/// - `<id>_geom` imports `<id>_colors` and defines a type `point` with a `color` attribute; its
+///   top-level type reads a `scale`
+/// - `<id>` imports `<id>_geom` and `<id>_colors`, reads an `<id>_geom`, an `<id>_geom::point` and
+///   an `<id>_colors::color`, and has value instances combining them
+pub fn imports_case<R: Rng + ?Sized>(rng: &mut R, id: &str) -> ImportsCase {
+    let colors_id = format!("{}_colors", id);
+    let geom_id = format!("{}_geom", id);
+    let color_enum = format!("{}::color", colors_id);
+
+    // enum values are spread out, so that a value mistaken for an index shows
+    let mut color_values: Vec<i128> = Vec::new();
+    while color_values.len() < COLORS.len() {
+        let value = rng.gen_range(0..=255);
+        if !color_values.contains(&value) {
+            color_values.push(value);
+        }
+    }
+    let mut colors = KsySpec::top_level(&colors_id);
+    colors.enums.insert(
+        "color".to_string(),
+        color_values
+            .iter()
+            .zip(COLORS)
+            .map(|(value, label)| (*value, label.to_string()))
+            .collect(),
+    );
+
+    let mut geom = KsySpec::top_level(&geom_id);
+    geom.meta.as_mut().unwrap().imports = vec![colors_id.clone()];
+    geom.seq.push(Attribute::new("scale", "u1"));
+    geom.types.insert(
+        "point".to_string(),
+        TypeSpec {
+            seq: vec![
+                Attribute::new("x", "u1"),
+                Attribute::new("y", "u1"),
+                Attribute {
+                    enum_name: Some(color_enum.clone()),
+                    ..Attribute::new("color", "u1")
+                },
+            ],
+            ..Default::default()
+        },
+    );
+
+    let mut spec = KsySpec::top_level(id);
+    spec.meta.as_mut().unwrap().imports = vec![geom_id.clone(), colors_id.clone()];
+    spec.seq.push(Attribute::new("g", &geom_id));
+    spec.seq
+        .push(Attribute::new("origin", format!("{}::point", geom_id)));
+    spec.seq.push(Attribute {
+        enum_name: Some(color_enum),
+        ..Attribute::new("tint", "u1")
+    });
+
+    let scale: u8 = rng.gen();
+    let (x, y): (u8, u8) = (rng.gen(), rng.gen());
+    let point_color = color_values[rng.gen_range(0..COLORS.len())];
+    let tint = color_values[rng.gen_range(0..COLORS.len())];
+    let data = vec![scale, x, y, point_color as u8, tint as u8];
+
+    let origin = || name("origin");
+    let red = Expr::EnumMember {
+        enum_path: vec![colors_id, "color".to_string()],
+        label: "red".to_string(),
+    };
+    let instances = [
+        (
+            "sum",
+            binary(attr(origin(), "x"), BinaryOp::Add, attr(origin(), "y")),
+            Value::Int(i128::from(x) + i128::from(y)),
+        ),
+        (
+            "scaled",
+            binary(attr(name("g"), "scale"), BinaryOp::Mul, attr(origin(), "x")),
+            Value::Int(i128::from(scale) * i128::from(x)),
+        ),
+        (
+            "same_color",
+            binary(attr(origin(), "color"), BinaryOp::Eq, name("tint")),
+            Value::Bool(point_color == tint),
+        ),
+        (
+            "is_red",
+            binary(name("tint"), BinaryOp::Eq, red),
+            Value::Bool(tint == color_values[1]),
+        ),
+    ];
+    let mut assertions = Vec::new();
+    for (instance_name, value, expected) in instances {
+        spec.instances
+            .insert(instance_name.to_string(), Attribute::value_instance(value));
+        assertions.push((name(instance_name), expected));
+    }
+    ImportsCase {
+        main: SpecCase {
+            spec,
+            data,
+            assertions,
+        },
+        imports: vec![geom, colors],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::typing::{infer, KsType, TypeEnv};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn resolves_across_specs() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let case = imports_case(&mut rng, "family");
+        let env = TypeEnv::from_spec_with_imports(&case.main.spec, &case.imports, &[]).unwrap();
+        let color = KsType::Enum(vec!["family_colors".to_string(), "color".to_string()]);
+        let origin_color = attr(name("origin"), "color");
+        assert_eq!(infer(&origin_color, &env), Ok(color));
+        for (instance_name, instance) in &case.main.spec.instances {
+            let value = instance.value.as_ref().unwrap();
+            assert!(infer(value, &env).is_ok(), "{}", instance_name);
+        }
+
+        // without the imports, the types are unknown
+        let env = TypeEnv::from_spec(&case.main.spec, &[]).unwrap();
+        assert_eq!(env.get("origin"), None);
+    }
+}
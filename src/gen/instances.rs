@@ -0,0 +1,168 @@
+//! Parse instances at positions in the root stream and in substreams, and value instances built
+//! on top of them, so that the lazy evaluation of instances referring to other instances gets
+//! exercised.
+
+use rand::Rng;
+
+use crate::ast::utils::{binary, name};
+use crate::ast::{BinaryOp, Expr};
+use crate::eval::Value;
+use crate::gen::case::SpecCase;
+use crate::ksy::{Attribute, KsySpec, TypeRef, TypeSpec};
+
+/// Length of the data; every position in it fits in a `u1`
+const DATA_LEN: usize = 32;
+/// Size of the `blob` substream
+const BLOB_LEN: usize = 8;
+/// Offset of `blob` in the data, after `ofs` and `len`
+const BLOB_START: usize = 2;
+
+fn u1() -> Attribute {
+    Attribute {
+        type_ref: Some(TypeRef::Named("u1".to_string())),
+        ..Default::default()
+    }
+}
+
+/// The seq reads `ofs: u1`, `len: u1` and an 8-byte substream `blob`. The instances are:
+///
+/// - `at_ofs`: `u1` at `pos: ofs`
+/// - `slice`: `len` bytes at `pos: ofs`
+/// - `in_blob`: `u1` at a fixed position in `blob._io`
+/// - `chained`: `u1` at `pos: at_ofs`, i.e. positioned by another instance
+/// - `sum`: value `at_ofs + in_blob`, and `twice`: value `sum * 2`
+///
+/// They're declared in an order in which each one comes before the instances it depends on.
+pub fn instances_case<R: Rng + ?Sized>(rng: &mut R, id: &str) -> SpecCase {
+    let len = rng.gen_range(1..=4);
+    let ofs = rng.gen_range(0..=DATA_LEN - len);
+    let blob_pos = rng.gen_range(0..BLOB_LEN);
+    let mut data: Vec<u8> = (0..DATA_LEN).map(|_| rng.gen()).collect();
+    data[0] = ofs as u8;
+    data[1] = len as u8;
+    if ofs >= BLOB_START {
+        // `at_ofs` is used as a position too
+        data[ofs] = rng.gen_range(0..DATA_LEN) as u8;
+    }
+
+    let mut spec = KsySpec::top_level(id);
+    spec.seq.push(Attribute::new("ofs", "u1"));
+    spec.seq.push(Attribute::new("len", "u1"));
+    spec.seq.push(Attribute {
+        size: Some(Expr::Int(BLOB_LEN as u64)),
+        ..Attribute::new("blob", "block")
+    });
+    spec.types.insert(
+        "block".to_string(),
+        TypeSpec {
+            seq: vec![Attribute {
+                id: Some("raw".to_string()),
+                size_eos: Some(true),
+                ..Default::default()
+            }],
+            ..Default::default()
+        },
+    );
+
+    let at_ofs = i128::from(data[ofs]);
+    let in_blob = i128::from(data[BLOB_START + blob_pos]);
+    let instances = [
+        (
+            "twice",
+            Attribute::value_instance(binary(name("sum"), BinaryOp::Mul, Expr::Int(2))),
+            Value::Int((at_ofs + in_blob) * 2),
+        ),
+        (
+            "sum",
+            Attribute::value_instance(binary(name("at_ofs"), BinaryOp::Add, name("in_blob"))),
+            Value::Int(at_ofs + in_blob),
+        ),
+        (
+            "chained",
+            Attribute {
+                pos: Some(name("at_ofs")),
+                ..u1()
+            },
+            Value::Int(data[at_ofs as usize].into()),
+        ),
+        (
+            "in_blob",
+            Attribute {
+                io: Some(Expr::Attribute {
+                    value: Box::new(name("blob")),
+                    attr_name: "_io".to_string(),
+                }),
+                pos: Some(Expr::Int(blob_pos as u64)),
+                ..u1()
+            },
+            Value::Int(in_blob),
+        ),
+        (
+            "slice",
+            Attribute {
+                pos: Some(name("ofs")),
+                size: Some(name("len")),
+                ..Default::default()
+            },
+            Value::Bytes(data[ofs..ofs + len].to_vec()),
+        ),
+        (
+            "at_ofs",
+            Attribute {
+                pos: Some(name("ofs")),
+                ..u1()
+            },
+            Value::Int(at_ofs),
+        ),
+    ];
+    let mut assertions = Vec::new();
+    for (instance_name, attr, expected) in instances {
+        spec.instances.insert(instance_name.to_string(), attr);
+        assertions.push((name(instance_name), expected));
+    }
+    SpecCase {
+        spec,
+        data,
+        assertions,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::{eval, Env};
+    use crate::typing::{infer, KsType, TypeEnv};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn value_instances_agree_with_parse_instances() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..20 {
+            let case = instances_case(&mut rng, "instances");
+            let mut env = Env::new();
+            for (expr, value) in &case.assertions {
+                let Expr::Name(name) = expr else {
+                    unreachable!()
+                };
+                env.set(name, value.clone());
+            }
+            for (instance_name, attr) in &case.spec.instances {
+                if let Some(value) = &attr.value {
+                    assert_eq!(eval(value, &env).ok().as_ref(), env.get(instance_name));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn parse_instances_are_typed() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let case = instances_case(&mut rng, "instances");
+        let env = TypeEnv::from_spec(&case.spec, &[]).unwrap();
+        assert_eq!(env.get("slice"), Some(&KsType::Bytes));
+        let sum = case.spec.instances["sum"].value.as_ref().unwrap();
+        assert_eq!(infer(sum, &env), Ok(KsType::Int));
+        assert!(case.spec.to_yaml().contains("    io: blob._io\n"));
+    }
+}
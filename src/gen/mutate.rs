@@ -0,0 +1,426 @@
+//! Mutation of expressions: small changes to seed expressions, typically harvested from real
+//! specs with [`harvest`], which keep them looking like what people write while making them new.
+//!
+//! Harvested expressions refer to the fields of their specs, which the type environment of the
+//! mutator usually doesn't know. Mutants of expressions that type check in it have the same type;
+//! others are only changed in ways that keep their shape plausible.
+
+use rand::seq::{IteratorRandom, SliceRandom};
+use rand::Rng;
+use serde_yaml::Value;
+
+use crate::ast::parser::parse_expr;
+use crate::ast::utils::PositiveFiniteF64;
+use crate::ast::{BinaryOp, Expr, TypeName};
+use crate::gen::expr::ExprGenerator;
+use crate::numeric::IntType;
+use crate::typing::{infer, KsType, TypeEnv};
+
+pub mod spec;
+
+/// Keys of specs whose values are expressions, including the ones of `valid` checks
+pub const EXPR_KEYS: [&str; 11] = [
+    "if",
+    "size",
+    "repeat-expr",
+    "repeat-until",
+    "value",
+    "pos",
+    "switch-on",
+    "eq",
+    "min",
+    "max",
+    "expr",
+];
+
+/// Attempts at a mutant with the type of the seed before giving up
+const ATTEMPTS: usize = 16;
+
+/// Levels of operators in generated replacements of subtrees
+const REPLACEMENT_DEPTH: usize = 2;
+
+/// Binary operators that take and give the same types as the others of their class
+const OP_CLASSES: [&[BinaryOp]; 4] = [
+    &[
+        BinaryOp::Add,
+        BinaryOp::Sub,
+        BinaryOp::Mul,
+        BinaryOp::Div,
+        BinaryOp::Rem,
+    ],
+    &[
+        BinaryOp::BitAnd,
+        BinaryOp::BitOr,
+        BinaryOp::BitXor,
+        BinaryOp::Shl,
+        BinaryOp::Shr,
+    ],
+    &[
+        BinaryOp::Eq,
+        BinaryOp::Ne,
+        BinaryOp::Lt,
+        BinaryOp::Le,
+        BinaryOp::Gt,
+        BinaryOp::Ge,
+    ],
+    &[BinaryOp::And, BinaryOp::Or],
+];
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Mutation {
+    /// Another binary operator of the same class, like `-` for `+`
+    SwapOperator,
+    /// A literal changed a little, like `3` into `4`
+    NudgeLiteral,
+    /// A subexpression replaced by a generated one of its type, or by a subexpression of another
+    /// seed if its type isn't known
+    ReplaceSubtree,
+    /// The operands of a binary operator, or the branches of `?:`, swapped
+    SwapOperands,
+    /// A subexpression converted to an integer type with `.as<>`
+    WrapInCast,
+}
+
+impl Mutation {
+    pub const ALL: [Mutation; 5] = [
+        Mutation::SwapOperator,
+        Mutation::NudgeLiteral,
+        Mutation::ReplaceSubtree,
+        Mutation::SwapOperands,
+        Mutation::WrapInCast,
+    ];
+}
+
+/// Expressions of a ksy document, in the order they appear in it. Values that don't parse as
+/// expressions are left out.
+pub fn harvest(ksy: &str) -> Result<Vec<Expr>, serde_yaml::Error> {
+    let doc: Value = serde_yaml::from_str(ksy)?;
+    let mut exprs = Vec::new();
+    harvest_value(&doc, &mut exprs);
+    Ok(exprs)
+}
+
+fn harvest_value(value: &Value, exprs: &mut Vec<Expr>) {
+    match value {
+        Value::Mapping(mapping) => {
+            for (key, value) in mapping {
+                let key = key.as_str().unwrap_or_default();
+                if EXPR_KEYS.contains(&key) {
+                    exprs.extend(scalar_text(value).and_then(|text| parse_expr(&text).ok()));
+                }
+                // the keys of the cases of a switch are expressions too
+                if let (true, Value::Mapping(cases)) = (key == "cases", value) {
+                    exprs.extend(
+                        cases
+                            .keys()
+                            .filter_map(scalar_text)
+                            .filter_map(|text| parse_expr(&text).ok()),
+                    );
+                }
+                harvest_value(value, exprs);
+            }
+        }
+        Value::Sequence(items) => {
+            for item in items {
+                harvest_value(item, exprs);
+            }
+        }
+        Value::Tagged(tagged) => harvest_value(&tagged.value, exprs),
+        _ => {}
+    }
+}
+
+fn scalar_text(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(x) => Some(x.to_string()),
+        Value::Bool(x) => Some(x.to_string()),
+        _ => None,
+    }
+}
+
+/// Paths (as [`Expr::node_at`] takes them) of all the nodes of the expression, in pre-order.
+fn node_paths(expr: &Expr) -> Vec<Vec<usize>> {
+    let mut paths = vec![Vec::new()];
+    for (i, child) in expr.children().into_iter().enumerate() {
+        for mut path in node_paths(child) {
+            path.insert(0, i);
+            paths.push(path);
+        }
+    }
+    paths
+}
+
+#[derive(Clone, Debug)]
+pub struct Mutator<'a> {
+    env: &'a TypeEnv,
+    /// Expressions whose subexpressions replace the ones of unknown type
+    donors: &'a [Expr],
+}
+
+impl<'a> Mutator<'a> {
+    pub fn new(env: &'a TypeEnv, donors: &'a [Expr]) -> Self {
+        Self { env, donors }
+    }
+
+    /// Mutant of `expr` differing from it, with the mutation that made it, or `None` if no
+    /// mutation applies. If `expr` type checks in the environment, the mutant has its type (up to
+    /// the width of integers).
+    pub fn mutate<R: Rng + ?Sized>(&self, rng: &mut R, expr: &Expr) -> Option<(Mutation, Expr)> {
+        let ty = infer(expr, self.env).ok().map(|ty| ty.widened());
+        for _ in 0..ATTEMPTS {
+            let mutation = *Mutation::ALL.choose(rng)?;
+            let Some(mutant) = self.apply(rng, mutation, expr) else {
+                continue;
+            };
+            if mutant == *expr {
+                continue;
+            }
+            if let Some(ty) = &ty {
+                if infer(&mutant, self.env).map(|ty| ty.widened()).as_ref() != Ok(ty) {
+                    continue;
+                }
+            }
+            return Some((mutation, mutant));
+        }
+        None
+    }
+
+    /// Mutant of `expr` by the mutation, or `None` if it has nothing to apply it to. The mutant
+    /// may not type check.
+    pub fn apply<R: Rng + ?Sized>(
+        &self,
+        rng: &mut R,
+        mutation: Mutation,
+        expr: &Expr,
+    ) -> Option<Expr> {
+        let paths = node_paths(expr);
+        let mut pick = |matches: &dyn Fn(&Expr) -> bool| {
+            paths
+                .iter()
+                .filter(|path| expr.node_at(path).is_some_and(matches))
+                .choose(rng)
+                .cloned()
+        };
+        let path = match mutation {
+            Mutation::SwapOperator => pick(&|node| matches!(node, Expr::BinaryOp { .. }))?,
+            Mutation::NudgeLiteral => pick(&|node| {
+                matches!(
+                    node,
+                    Expr::Int(_) | Expr::Float(_) | Expr::Str(_) | Expr::Bool(_)
+                )
+            })?,
+            Mutation::SwapOperands => {
+                pick(&|node| matches!(node, Expr::BinaryOp { .. } | Expr::CondOp { .. }))?
+            }
+            // the whole expression replaced would be nothing like the seed
+            Mutation::ReplaceSubtree if paths.len() > 1 => pick(&|node| node != expr)?,
+            Mutation::ReplaceSubtree | Mutation::WrapInCast => pick(&|_| true)?,
+        };
+        let mut mutant = expr.clone();
+        let node = mutant.node_at_mut(&path)?;
+        match mutation {
+            Mutation::SwapOperator => {
+                let Expr::BinaryOp { op, .. } = node else {
+                    return None;
+                };
+                let class = OP_CLASSES.iter().find(|class| class.contains(op))?;
+                *op = *class.iter().filter(|other| *other != op).choose(rng)?;
+            }
+            Mutation::NudgeLiteral => *node = nudge(rng, node)?,
+            Mutation::ReplaceSubtree => *node = self.replacement(rng, node)?,
+            Mutation::SwapOperands => match node {
+                Expr::BinaryOp { l, r, .. } => std::mem::swap(l, r),
+                Expr::CondOp {
+                    if_true, if_false, ..
+                } => std::mem::swap(if_true, if_false),
+                _ => return None,
+            },
+            Mutation::WrapInCast => {
+                match infer(node, self.env).map(|ty| ty.widened()) {
+                    Ok(KsType::Int) | Err(_) => {}
+                    Ok(_) => return None,
+                }
+                let int_type = IntType::ALL.choose(rng)?;
+                *node = Expr::CastTo {
+                    value: Box::new(node.clone()),
+                    type_name: TypeName::new(int_type.name()),
+                };
+            }
+        }
+        Some(mutant)
+    }
+
+    /// Expression of the type of `node`, or a subexpression of a donor if the type isn't known.
+    fn replacement<R: Rng + ?Sized>(&self, rng: &mut R, node: &Expr) -> Option<Expr> {
+        match infer(node, self.env) {
+            Ok(ty) => ExprGenerator::new(self.env, REPLACEMENT_DEPTH).generate(rng, &ty),
+            Err(_) => {
+                let donor = self.donors.choose(rng)?;
+                let path = node_paths(donor).choose(rng)?.clone();
+                donor.node_at(&path).cloned()
+            }
+        }
+    }
+}
+
+/// Literal close to `literal`, like the next integer or the string with a character more.
+fn nudge<R: Rng + ?Sized>(rng: &mut R, literal: &Expr) -> Option<Expr> {
+    Some(match literal {
+        Expr::Int(x) => {
+            let candidates = [x.checked_add(1), x.checked_sub(1), x.checked_mul(2)];
+            Expr::Int(candidates.into_iter().flatten().choose(rng)?)
+        }
+        Expr::Float(x) => {
+            let x = x.value();
+            let candidates = [x + 1.0, x * 2.0, x / 2.0, f64::from_bits(x.to_bits() + 1)];
+            let x = candidates.choose(rng)?;
+            Expr::Float(PositiveFiniteF64::try_from(*x).ok()?)
+        }
+        Expr::Str(s) => {
+            let mut s = s.clone();
+            if s.is_empty() || rng.gen_bool(0.5) {
+                s.push(char::from(rng.gen_range(b'a'..=b'z')));
+            } else {
+                s.pop();
+            }
+            Expr::Str(s)
+        }
+        Expr::Bool(x) => Expr::Bool(!x),
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    const KSY: &str = "\
+meta:
+  id: sample
+seq:
+  - id: len
+    type: u2
+  - id: body
+    size: len - 2
+    if: len > 2
+  - id: kind
+    type: u1
+    valid:
+      max: 7
+  - id: items
+    type:
+      switch-on: kind
+      cases:
+        1: u4
+        '_': u1
+    repeat: expr
+    repeat-expr: 3
+instances:
+  max:
+    value: len * 2
+";
+
+    fn parse(text: &str) -> Expr {
+        parse_expr(text).unwrap()
+    }
+
+    #[test]
+    fn harvested() {
+        let exprs: Vec<String> = harvest(KSY)
+            .unwrap()
+            .iter()
+            .map(|expr| format!("{:?}", expr))
+            .collect();
+        let expected: Vec<String> = ["len - 2", "len > 2", "7", "kind", "1", "_", "3", "len * 2"]
+            .iter()
+            .map(|text| format!("{:?}", parse(text)))
+            .collect();
+        assert_eq!(exprs, expected);
+    }
+
+    #[test]
+    fn mutations() {
+        let env = TypeEnv::new();
+        let donors = [parse("a.b[3]")];
+        let mutator = Mutator::new(&env, &donors);
+        let mut rng = StdRng::seed_from_u64(0);
+        let seed = parse("x - 2 < y");
+        for _ in 0..20 {
+            let swapped = mutator
+                .apply(&mut rng, Mutation::SwapOperator, &seed)
+                .unwrap();
+            match swapped {
+                Expr::BinaryOp {
+                    l,
+                    op: BinaryOp::Lt,
+                    ..
+                } => {
+                    let Expr::BinaryOp { op, .. } = *l else {
+                        panic!("{:?}", l);
+                    };
+                    assert!(
+                        [BinaryOp::Add, BinaryOp::Mul, BinaryOp::Div, BinaryOp::Rem].contains(&op)
+                    );
+                }
+                Expr::BinaryOp { op, .. } => assert!(OP_CLASSES[2].contains(&op)),
+                _ => panic!("{:?}", swapped),
+            }
+        }
+        assert_eq!(
+            mutator.apply(&mut rng, Mutation::SwapOperands, &parse("x - 2")),
+            Some(parse("2 - x"))
+        );
+        let nudged = mutator.apply(&mut rng, Mutation::NudgeLiteral, &parse("x - 2"));
+        assert!(matches!(
+            nudged,
+            Some(Expr::BinaryOp { r, .. }) if matches!(*r, Expr::Int(1 | 3 | 4))
+        ));
+        let cast = mutator.apply(&mut rng, Mutation::WrapInCast, &parse("x"));
+        assert!(matches!(cast, Some(Expr::CastTo { value, .. }) if *value == parse("x")));
+        // names of unknown type are replaced by parts of the donors
+        let replaced = mutator
+            .apply(&mut rng, Mutation::ReplaceSubtree, &parse("-x"))
+            .unwrap();
+        let Expr::UnaryOp { value, .. } = replaced else {
+            panic!("{:?}", replaced);
+        };
+        assert!(node_paths(&donors[0])
+            .iter()
+            .any(|path| donors[0].node_at(path) == Some(&value)));
+        assert_eq!(
+            mutator.apply(&mut rng, Mutation::SwapOperator, &parse("x")),
+            None
+        );
+    }
+
+    #[test]
+    fn typed_mutants() {
+        let mut env = TypeEnv::new();
+        env.set("len", KsType::Int);
+        env.set("name", KsType::Str);
+        env.set("ok", KsType::Bool);
+        let seeds = [
+            parse("len * 2 + 1"),
+            parse("name + \"a\" == \"b\" and ok"),
+            parse("ok ? len : 7"),
+        ];
+        let mutator = Mutator::new(&env, &seeds);
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut seen = Vec::new();
+        for seed in &seeds {
+            let ty = infer(seed, &env).unwrap();
+            for _ in 0..50 {
+                let (mutation, mutant) = mutator.mutate(&mut rng, seed).unwrap();
+                assert_ne!(&mutant, seed);
+                assert_eq!(infer(&mutant, &env).unwrap().widened(), ty, "{:?}", mutant);
+                if !seen.contains(&mutation) {
+                    seen.push(mutation);
+                }
+            }
+        }
+        assert_eq!(seen.len(), Mutation::ALL.len());
+    }
+}
@@ -0,0 +1,411 @@
+//! Mutation of whole ksy documents, to turn real-world formats into seeds for fuzzing the
+//! compiler and the runtimes.
+//!
+//! Documents are mutated as YAML values instead of through the spec model, which only holds the
+//! keys that the generators emit. Each mutant differs from the original by one change and has no
+//! schema violations that the original doesn't have, so hand-written keys that the schema doesn't
+//! know don't keep their specs from being mutated.
+
+use rand::seq::{IteratorRandom, SliceRandom};
+use rand::Rng;
+use serde_yaml::Value;
+
+use crate::ast::parser::parse_expr;
+use crate::ast::Expr;
+use crate::schema::Schema;
+use crate::translator::translate;
+use crate::typing::TypeEnv;
+
+use super::{harvest_value, nudge, Mutator, ATTEMPTS, EXPR_KEYS};
+
+/// Widths of the byte-aligned numeric types that a width changes into, which all take an endian
+const BYTE_WIDTHS: [u32; 3] = [2, 4, 8];
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum SpecMutation {
+    /// `le` and `be` swapped, in `meta/endian` or in the name of a type like `u4le`
+    FlipEndian,
+    /// Another width of a numeric type, like `u2be` for `u4be`, or another integer `size`
+    TweakSize,
+    /// Another `repeat` mode, with the key that the mode needs
+    ChangeRepeat,
+    /// An expression mutated like the ones of [`Mutator`]
+    MutateExpr,
+}
+
+impl SpecMutation {
+    pub const ALL: [SpecMutation; 4] = [
+        SpecMutation::FlipEndian,
+        SpecMutation::TweakSize,
+        SpecMutation::ChangeRepeat,
+        SpecMutation::MutateExpr,
+    ];
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpecMutant {
+    pub mutation: SpecMutation,
+    /// JSON pointer to the changed mapping, like `/seq/0`
+    pub path: String,
+    pub ksy: String,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Step {
+    Key(Value),
+    Index(usize),
+}
+
+/// Up to `count` distinct mutants of the ksy document. There are fewer if the document has little
+/// to mutate.
+pub fn mutate_spec<R: Rng + ?Sized>(
+    rng: &mut R,
+    schema: &Schema,
+    ksy: &str,
+    count: usize,
+) -> Result<Vec<SpecMutant>, serde_yaml::Error> {
+    let doc: Value = serde_yaml::from_str(ksy)?;
+    let violations = schema.validate_value(&doc);
+    let mut donors = Vec::new();
+    harvest_value(&doc, &mut donors);
+    let env = TypeEnv::new();
+    let mutator = Mutator::new(&env, &donors);
+    let mut paths = Vec::new();
+    mapping_paths(&doc, &mut Vec::new(), &mut paths);
+
+    let mut mutants: Vec<SpecMutant> = Vec::new();
+    for _ in 0..count * ATTEMPTS {
+        if mutants.len() == count {
+            break;
+        }
+        let mutation = *SpecMutation::ALL.choose(rng).unwrap();
+        let Some(path) = paths
+            .iter()
+            .filter(|path| node_at(&doc, path).is_some_and(|node| applies(mutation, node)))
+            .choose(rng)
+        else {
+            continue;
+        };
+        let mut mutant = doc.clone();
+        let node = node_at_mut(&mut mutant, path).expect("paths point at mappings");
+        if apply(rng, &mutator, mutation, node).is_none() || mutant == doc {
+            continue;
+        }
+        let valid = schema
+            .validate_value(&mutant)
+            .iter()
+            .all(|violation| violations.contains(violation));
+        let ksy = serde_yaml::to_string(&mutant)?;
+        if valid && mutants.iter().all(|other| other.ksy != ksy) {
+            mutants.push(SpecMutant {
+                mutation,
+                path: pointer(path),
+                ksy,
+            });
+        }
+    }
+    Ok(mutants)
+}
+
+/// Paths of all the mappings of the document, in pre-order.
+fn mapping_paths(value: &Value, path: &mut Vec<Step>, out: &mut Vec<Vec<Step>>) {
+    match value {
+        Value::Mapping(mapping) => {
+            out.push(path.clone());
+            // JSON pointers can't point past keys that aren't scalars
+            for (key, value) in mapping.iter().filter(|(key, _)| scalar(key).is_some()) {
+                path.push(Step::Key(key.clone()));
+                mapping_paths(value, path, out);
+                path.pop();
+            }
+        }
+        Value::Sequence(items) => {
+            for (i, item) in items.iter().enumerate() {
+                path.push(Step::Index(i));
+                mapping_paths(item, path, out);
+                path.pop();
+            }
+        }
+        _ => {}
+    }
+}
+
+fn node_at<'a>(value: &'a Value, path: &[Step]) -> Option<&'a Value> {
+    path.iter().try_fold(value, |node, step| match step {
+        Step::Key(key) => node.as_mapping()?.get(key),
+        Step::Index(i) => node.as_sequence()?.get(*i),
+    })
+}
+
+fn node_at_mut<'a>(value: &'a mut Value, path: &[Step]) -> Option<&'a mut Value> {
+    path.iter().try_fold(value, |node, step| match step {
+        Step::Key(key) => node.as_mapping_mut()?.get_mut(key),
+        Step::Index(i) => node.as_sequence_mut()?.get_mut(*i),
+    })
+}
+
+/// JSON pointer of the path, with `~` and `/` in keys escaped as RFC 6901 has it.
+fn pointer(path: &[Step]) -> String {
+    path.iter()
+        .map(|step| match step {
+            Step::Key(key) => {
+                let key = scalar(key).expect("paths only go through scalar keys");
+                format!("/{}", key.replace('~', "~0").replace('/', "~1"))
+            }
+            Step::Index(i) => format!("/{}", i),
+        })
+        .collect()
+}
+
+fn scalar(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(x) => Some(x.to_string()),
+        Value::Bool(x) => Some(x.to_string()),
+        _ => None,
+    }
+}
+
+/// Kind (`u`, `s`, `f` or `b`), width and endian suffix of a numeric type like `u4le`.
+fn split_numeric_type(name: &str) -> Option<(char, u32, &str)> {
+    let kind = name.chars().next().filter(|c| "usfb".contains(*c))?;
+    let rest = &name[1..];
+    let digits = rest
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(rest.len());
+    let width = rest[..digits].parse().ok()?;
+    let endian = &rest[digits..];
+    ["", "le", "be"]
+        .contains(&endian)
+        .then_some((kind, width, endian))
+}
+
+fn flipped(endian: &str) -> Option<&'static str> {
+    match endian {
+        "le" => Some("be"),
+        "be" => Some("le"),
+        _ => None,
+    }
+}
+
+fn get<'a>(node: &'a Value, key: &str) -> Option<&'a Value> {
+    node.as_mapping()?.get(key)
+}
+
+fn get_str<'a>(node: &'a Value, key: &str) -> Option<&'a str> {
+    get(node, key)?.as_str()
+}
+
+/// Whether the mutation has something to change in the mapping itself (not in nested ones).
+fn applies(mutation: SpecMutation, node: &Value) -> bool {
+    let numeric_type = get_str(node, "type").and_then(split_numeric_type);
+    match mutation {
+        SpecMutation::FlipEndian => {
+            ["endian", "bit-endian"]
+                .iter()
+                .any(|key| get_str(node, key).and_then(flipped).is_some())
+                || numeric_type.is_some_and(|(_, _, endian)| flipped(endian).is_some())
+        }
+        SpecMutation::TweakSize => {
+            get(node, "size").is_some_and(Value::is_u64)
+                || numeric_type
+                    .is_some_and(|(kind, width, _)| kind == 'b' || BYTE_WIDTHS.contains(&width))
+        }
+        SpecMutation::ChangeRepeat => get(node, "repeat").is_some(),
+        SpecMutation::MutateExpr => EXPR_KEYS.iter().any(|key| {
+            get(node, key)
+                .and_then(scalar)
+                .is_some_and(|text| parse_expr(&text).is_ok())
+        }),
+    }
+}
+
+/// Applies the mutation to the mapping, or returns `None` if it didn't find anything to change.
+fn apply<R: Rng + ?Sized>(
+    rng: &mut R,
+    mutator: &Mutator,
+    mutation: SpecMutation,
+    node: &mut Value,
+) -> Option<()> {
+    let mapping = node.as_mapping_mut()?;
+    match mutation {
+        SpecMutation::FlipEndian => {
+            let key = ["endian", "bit-endian", "type"]
+                .into_iter()
+                .filter(|key| applies(mutation, &mapping_with(mapping, key)))
+                .choose(rng)?;
+            let value = mapping.get_mut(key)?;
+            let text = value.as_str()?;
+            *value = Value::String(match split_numeric_type(text) {
+                Some((kind, width, endian)) if key == "type" => {
+                    format!("{}{}{}", kind, width, flipped(endian)?)
+                }
+                _ => flipped(text)?.to_string(),
+            });
+        }
+        SpecMutation::TweakSize => {
+            let key = ["size", "type"]
+                .into_iter()
+                .filter(|key| applies(mutation, &mapping_with(mapping, key)))
+                .choose(rng)?;
+            let value = mapping.get_mut(key)?;
+            if key == "size" {
+                let Expr::Int(size) = nudge(rng, &Expr::Int(value.as_u64()?))? else {
+                    return None;
+                };
+                *value = Value::from(size);
+            } else {
+                let (kind, width, endian) = split_numeric_type(value.as_str()?)?;
+                let width = if kind == 'b' {
+                    let Expr::Int(width) = nudge(rng, &Expr::Int(width.into()))? else {
+                        return None;
+                    };
+                    u32::try_from(width).ok().filter(|w| (1..=64).contains(w))?
+                } else {
+                    // floats only come in 4 and 8 bytes
+                    let widths = if kind == 'f' {
+                        &[4, 8][..]
+                    } else {
+                        &BYTE_WIDTHS
+                    };
+                    *widths.iter().filter(|w| **w != width).choose(rng)?
+                };
+                *value = Value::String(format!("{}{}{}", kind, width, endian));
+            }
+        }
+        SpecMutation::ChangeRepeat => {
+            let mode = mapping.get("repeat")?.as_str()?;
+            let new_mode = *["eos", "expr", "until"]
+                .iter()
+                .filter(|other| **other != mode)
+                .choose(rng)?;
+            mapping.remove("repeat-expr");
+            mapping.remove("repeat-until");
+            mapping.insert("repeat".into(), new_mode.into());
+            match new_mode {
+                "expr" => mapping.insert("repeat-expr".into(), rng.gen_range(1..5u64).into()),
+                "until" => mapping.insert("repeat-until".into(), "_io.eof".into()),
+                _ => None,
+            };
+        }
+        SpecMutation::MutateExpr => {
+            let (key, expr) = EXPR_KEYS
+                .iter()
+                .filter_map(|key| {
+                    let text = scalar(mapping.get(*key)?)?;
+                    Some((*key, parse_expr(&text).ok()?))
+                })
+                .choose(rng)?;
+            let (_, mutant) = mutator.mutate(rng, &expr)?;
+            // integer literals stay YAML numbers, like in hand-written specs
+            let value = match mutant {
+                Expr::Int(x) if mapping.get(key)?.is_number() => Value::from(x),
+                _ => Value::String(translate(&mutant)),
+            };
+            mapping.insert(key.into(), value);
+        }
+    }
+    Some(())
+}
+
+/// Mapping of only the key of `mapping` (if it has it), to check what a mutation applies to.
+fn mapping_with(mapping: &serde_yaml::Mapping, key: &str) -> Value {
+    let mut single = serde_yaml::Mapping::new();
+    if let Some(value) = mapping.get(key) {
+        single.insert(key.into(), value.clone());
+    }
+    Value::Mapping(single)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    const KSY: &str = "\
+meta:
+  id: sample
+  endian: le
+seq:
+  - id: len
+    type: u2
+  - id: body
+    size: len - 2
+  - id: flags
+    type: b3
+  - id: crc
+    type: u4be
+    doc: checksum of the body
+  - id: items
+    size: 4
+    repeat: eos
+";
+
+    fn mutants(count: usize) -> Vec<SpecMutant> {
+        let mut rng = StdRng::seed_from_u64(0);
+        mutate_spec(&mut rng, &Schema::bundled(), KSY, count).unwrap()
+    }
+
+    #[test]
+    fn mutants_are_valid_and_distinct() {
+        let schema = Schema::bundled();
+        let original: Value = serde_yaml::from_str(KSY).unwrap();
+        assert_eq!(schema.validate_value(&original), []);
+        let mutants = mutants(30);
+        assert_eq!(mutants.len(), 30);
+        for (i, mutant) in mutants.iter().enumerate() {
+            let doc: Value = serde_yaml::from_str(&mutant.ksy).unwrap();
+            assert_ne!(doc, original);
+            assert_eq!(schema.validate_value(&doc), [], "{}", mutant.ksy);
+            assert!(mutants[..i].iter().all(|other| other.ksy != mutant.ksy));
+        }
+        for mutation in SpecMutation::ALL {
+            assert!(mutants.iter().any(|mutant| mutant.mutation == mutation));
+        }
+    }
+
+    #[test]
+    fn single_changes() {
+        for mutant in mutants(30) {
+            let doc: Value = serde_yaml::from_str(&mutant.ksy).unwrap();
+            let items = &doc["seq"][4];
+            match mutant.mutation {
+                SpecMutation::FlipEndian => assert!(
+                    doc["meta"]["endian"] == "be" || doc["seq"][3]["type"] == "u4le",
+                    "{}",
+                    mutant.ksy
+                ),
+                SpecMutation::ChangeRepeat => {
+                    assert_eq!(mutant.path, "/seq/4");
+                    match items["repeat"].as_str().unwrap() {
+                        "expr" => assert!(items["repeat-expr"].is_u64()),
+                        "until" => assert_eq!(items["repeat-until"], "_io.eof"),
+                        mode => panic!("{}", mode),
+                    }
+                }
+                SpecMutation::TweakSize | SpecMutation::MutateExpr => {
+                    assert!(mutant.path.starts_with("/seq/"), "{}", mutant.path);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn pointers() {
+        let doc: Value = serde_yaml::from_str("{a/b~c: [{x: 1}], [1]: {y: 2}, 3: {z: 3}}").unwrap();
+        let mut paths = Vec::new();
+        mapping_paths(&doc, &mut Vec::new(), &mut paths);
+        let pointers: Vec<String> = paths.iter().map(|path| pointer(path)).collect();
+        assert_eq!(pointers, ["", "/a~1b~0c/0", "/3"]);
+    }
+
+    #[test]
+    fn numeric_types() {
+        assert_eq!(split_numeric_type("u4le"), Some(('u', 4, "le")));
+        assert_eq!(split_numeric_type("b12"), Some(('b', 12, "")));
+        assert_eq!(split_numeric_type("str"), None);
+        assert_eq!(split_numeric_type("u4x"), None);
+        assert_eq!(split_numeric_type("s"), None);
+    }
+}
@@ -0,0 +1,218 @@
+//! Ids of the specs of a generated suite, which also become file names (`<id>.ksy`) and class
+//! or module names in every target language.
+//!
+//! Two ids collide when they only differ in underscores: `foo_bar` and `foobar` both give the
+//! classes `FooBar`/`Foobar`, whose files clash on case-insensitive file systems. Ids that are
+//! reserved words of a target collide with the language itself.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use thiserror::Error;
+
+use crate::target::Target;
+
+/// Ids are cut to this length (before a disambiguating suffix) to keep the generated file names
+/// manageable
+pub const MAX_ID_LEN: usize = 48;
+/// Appended to ids that are reserved words
+const RESERVED_SUFFIX: &str = "_spec";
+
+#[derive(Debug, Error)]
+pub enum NameError {
+    #[error("can't read the corpus: {0}")]
+    Io(#[from] io::Error),
+    #[error("`{0}` is not a valid id")]
+    Invalid(String),
+    #[error("`{0}` is a reserved word of a target language")]
+    Reserved(String),
+    #[error("`{id}` collides with the existing `{existing}`")]
+    Taken { id: String, existing: String },
+}
+
+/// Names taken so far, by the corpus and by the specs generated before. The ids it assigns only
+/// depend on the taken names and on the order of the calls.
+#[derive(Clone, Debug, Default)]
+pub struct Namer {
+    /// Taken ids by their [`collision_key`]
+    taken: HashMap<String, String>,
+}
+
+impl Namer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Namer with the ids of the specs in `dir` (like the `formats` directory of the test
+    /// corpus) taken.
+    pub fn from_corpus(dir: &Path) -> Result<Self, NameError> {
+        let mut namer = Self::new();
+        let mut ids = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "ksy") {
+                if let Some(stem) = path.file_stem().and_then(|stem| stem.to_str()) {
+                    ids.push(stem.to_string());
+                }
+            }
+        }
+        // the order of directory entries is unspecified
+        ids.sort();
+        for id in ids {
+            namer.reserve(&id);
+        }
+        Ok(namer)
+    }
+
+    /// Marks an existing id as taken, without any checks.
+    pub fn reserve(&mut self, id: &str) {
+        self.taken
+            .entry(collision_key(id))
+            .or_insert_with(|| id.to_string());
+    }
+
+    /// Existing id that `id` collides with, if any.
+    pub fn collision(&self, id: &str) -> Option<&str> {
+        self.taken.get(&collision_key(id)).map(String::as_str)
+    }
+
+    /// Takes exactly `id`, which must be valid and free.
+    pub fn claim(&mut self, id: &str) -> Result<(), NameError> {
+        if !is_valid_id(id) {
+            return Err(NameError::Invalid(id.to_string()));
+        }
+        if is_reserved(id) {
+            return Err(NameError::Reserved(id.to_string()));
+        }
+        if let Some(existing) = self.collision(id) {
+            return Err(NameError::Taken {
+                id: id.to_string(),
+                existing: existing.to_string(),
+            });
+        }
+        self.reserve(id);
+        Ok(())
+    }
+
+    /// Takes a free id derived from `base`: sanitized, moved off reserved words and with a
+    /// numeric suffix (`_2`, `_3`, ...) if needed.
+    pub fn assign(&mut self, base: &str) -> String {
+        let mut base = sanitize(base);
+        if is_reserved(&base) {
+            base.push_str(RESERVED_SUFFIX);
+        }
+        let mut id = base.clone();
+        let mut n = 1;
+        while self.collision(&id).is_some() {
+            n += 1;
+            id = format!("{}_{}", base, n);
+        }
+        self.reserve(&id);
+        id
+    }
+}
+
+/// File name of the spec with the id.
+pub fn file_name(id: &str) -> String {
+    format!("{}.ksy", id)
+}
+
+/// Valid KS identifier of at most [`MAX_ID_LEN`] characters, with runs of other characters
+/// replaced by `_`.
+pub fn sanitize(base: &str) -> String {
+    let mut id = String::new();
+    for c in base.chars().map(|c| c.to_ascii_lowercase()) {
+        if c.is_ascii_lowercase() || c.is_ascii_digit() {
+            id.push(c);
+        } else if !id.is_empty() && !id.ends_with('_') {
+            id.push('_');
+        }
+    }
+    if !id.starts_with(|c: char| c.is_ascii_lowercase()) {
+        id.insert_str(0, "t_");
+    }
+    id.truncate(MAX_ID_LEN);
+    while id.ends_with('_') {
+        id.pop();
+    }
+    id
+}
+
+fn is_valid_id(id: &str) -> bool {
+    id.starts_with(|c: char| c.is_ascii_lowercase())
+        && id
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+}
+
+fn is_reserved(id: &str) -> bool {
+    let key = collision_key(id);
+    Target::ALL
+        .iter()
+        .flat_map(|target| target.reserved_words())
+        .any(|word| collision_key(word) == key)
+}
+
+/// Ids with the same key are written the same in some target.
+fn collision_key(id: &str) -> String {
+    id.chars()
+        .filter(|c| *c != '_')
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sanitized() {
+        assert_eq!(sanitize("valid_any_of"), "valid_any_of");
+        assert_eq!(sanitize("Switch On-Enum!"), "switch_on_enum");
+        assert_eq!(sanitize("__x__y__"), "x_y");
+        assert_eq!(sanitize("2bytes"), "t_2bytes");
+        assert_eq!(sanitize(""), "t");
+        assert_eq!(sanitize(&"ab_".repeat(40)).len(), MAX_ID_LEN - 1);
+        for base in ["x", "Über", "a.b.c", "0", &"z".repeat(100)] {
+            assert!(is_valid_id(&sanitize(base)), "{}", base);
+        }
+    }
+
+    #[test]
+    fn unique_ids() {
+        let mut namer = Namer::new();
+        assert_eq!(namer.assign("foo_bar"), "foo_bar");
+        assert_eq!(namer.assign("foobar"), "foobar_2");
+        assert_eq!(namer.assign("foo_bar"), "foo_bar_3");
+        assert_eq!(namer.assign("class"), "class_spec");
+        assert_eq!(namer.assign("Class"), "class_spec_2");
+        assert_eq!(file_name("foo_bar"), "foo_bar.ksy");
+
+        assert!(matches!(
+            namer.claim("foo_b_ar"),
+            Err(NameError::Taken { existing, .. }) if existing == "foo_bar"
+        ));
+        assert!(matches!(namer.claim("end"), Err(NameError::Reserved(_))));
+        assert!(matches!(namer.claim("Foo"), Err(NameError::Invalid(_))));
+        namer.claim("baz").unwrap();
+        assert_eq!(namer.assign("baz"), "baz_2");
+    }
+
+    #[test]
+    fn corpus() {
+        let dir = std::env::temp_dir().join(format!("ksy_corpus_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for name in ["bits_simple.ksy", "enum_0.ksy", "README.md"] {
+            fs::write(dir.join(name), "").unwrap();
+        }
+        let namer = Namer::from_corpus(&dir);
+        fs::remove_dir_all(&dir).unwrap();
+        let mut namer = namer.unwrap();
+        assert_eq!(namer.collision("bitssimple"), Some("bits_simple"));
+        assert_eq!(namer.collision("readme"), None);
+        assert_eq!(namer.assign("enum_0"), "enum_0_2");
+        assert!(Namer::from_corpus(Path::new("/nonexistent/formats")).is_err());
+    }
+}
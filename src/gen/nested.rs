@@ -0,0 +1,170 @@
+//! Multi-level hierarchies of nested types whose expressions reach up through `_parent` and
+//! `_root`, a historically fragile part of the typing in KSC.
+
+use rand::Rng;
+
+use crate::ast::utils::{attr, binary, name};
+use crate::ast::{BinaryOp, Expr};
+use crate::eval::Value;
+use crate::gen::case::SpecCase;
+use crate::ksy::{Attribute, KsySpec, Repeat, TypeSpec};
+
+/// Name of the `u1` attribute read at each level (the top-level type being level 0)
+fn level_attr(level: usize) -> String {
+    format!("v{}", level)
+}
+
+/// Hierarchy of `depth` (at least 1) types below the top-level type. The type at level `i` is
+/// called `level_<i>`; each one reads `v<i>` and has instances over `_parent` (and
+/// `_parent._parent`) and `_root`. The deepest type repeats an attribute `_root.count` times.
+pub fn nested_case<R: Rng + ?Sized>(rng: &mut R, id: &str, depth: usize) -> SpecCase {
+    let count: u8 = rng.gen_range(0..=3);
+    let values: Vec<u8> = (0..=depth).map(|_| rng.gen()).collect();
+    let items: Vec<u8> = (0..count).map(|_| rng.gen()).collect();
+
+    let mut assertions = Vec::new();
+    let mut inner: Option<TypeSpec> = None;
+    for level in (1..=depth).rev() {
+        let mut level_type = TypeSpec::default();
+        level_type.seq.push(Attribute::new(level_attr(level), "u1"));
+        match inner.take() {
+            Some(child) => {
+                let child_name = format!("level_{}", level + 1);
+                level_type.seq.push(Attribute::new("child", &child_name));
+                level_type.types.insert(child_name, child);
+            }
+            None => level_type.seq.push(Attribute {
+                repeat: Some(Repeat::Expr),
+                repeat_expr: Some(attr(name("_root"), "count")),
+                ..Attribute::new("items", "u1")
+            }),
+        }
+
+        // path from the top-level type to this level
+        let path = (0..level).fold(None, |path: Option<Expr>, _| {
+            Some(path.map_or(name("child"), |path| attr(path, "child")))
+        });
+        let path = path.unwrap();
+        let v = |level: usize| i128::from(values[level]);
+        let mut instance = |instance_name: &str, value: Expr, expected: i128| {
+            level_type
+                .instances
+                .insert(instance_name.to_string(), Attribute::value_instance(value));
+            assertions.push((attr(path.clone(), instance_name), Value::Int(expected)));
+        };
+        instance(
+            "parent_sum",
+            binary(
+                attr(name("_parent"), &level_attr(level - 1)),
+                BinaryOp::Add,
+                name(&level_attr(level)),
+            ),
+            v(level - 1) + v(level),
+        );
+        instance(
+            "root_diff",
+            binary(
+                attr(name("_root"), &level_attr(0)),
+                BinaryOp::Sub,
+                name(&level_attr(level)),
+            ),
+            v(0) - v(level),
+        );
+        if level >= 2 {
+            instance(
+                "grandparent",
+                attr(attr(name("_parent"), "_parent"), &level_attr(level - 2)),
+                v(level - 2),
+            );
+        }
+        inner = Some(level_type);
+    }
+
+    let mut spec = KsySpec::top_level(id);
+    spec.seq.push(Attribute::new("count", "u1"));
+    spec.seq.push(Attribute::new(level_attr(0), "u1"));
+    spec.seq.push(Attribute::new("child", "level_1"));
+    spec.types.insert(
+        "level_1".to_string(),
+        inner.expect("depth must be at least 1"),
+    );
+
+    let mut data = vec![count];
+    data.extend(&values);
+    data.extend(&items);
+    SpecCase {
+        spec,
+        data,
+        assertions,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::{eval, Env};
+    use crate::typing::{infer, KsType, TypeEnv};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn instances_type_check_in_their_level() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let case = nested_case(&mut rng, "nested", 3);
+        let mut type_path = Vec::new();
+        let mut level_type = &case.spec.types["level_1"];
+        for level in 1..=3 {
+            type_path.push(format!("level_{}", level));
+            let env = TypeEnv::from_spec(&case.spec, &type_path).unwrap();
+            for instance in level_type.instances.values() {
+                let value = instance.value.as_ref().unwrap();
+                let ty = infer(value, &env).map(|ty| ty.widened());
+                assert_eq!(ty, Ok(KsType::Int), "{:?}", value);
+            }
+            if level < 3 {
+                level_type = &level_type.types[&format!("level_{}", level + 1)];
+            }
+        }
+    }
+
+    #[test]
+    fn assertions_match_data() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let case = nested_case(&mut rng, "nested", 2);
+        let [count, v0, v1, v2, ..] = case.data[..] else {
+            unreachable!()
+        };
+        let int = |x: u8| Value::Int(x.into());
+        // the instances are evaluated by hand into the structs
+        let level_2 = Value::Struct(BTreeMap::from([
+            (
+                "parent_sum".to_string(),
+                Value::Int(i128::from(v1) + i128::from(v2)),
+            ),
+            (
+                "root_diff".to_string(),
+                Value::Int(i128::from(v0) - i128::from(v2)),
+            ),
+            ("grandparent".to_string(), int(v0)),
+        ]));
+        let level_1 = Value::Struct(BTreeMap::from([
+            ("child".to_string(), level_2),
+            (
+                "parent_sum".to_string(),
+                Value::Int(i128::from(v0) + i128::from(v1)),
+            ),
+            (
+                "root_diff".to_string(),
+                Value::Int(i128::from(v0) - i128::from(v1)),
+            ),
+        ]));
+        let mut env = Env::new();
+        env.set("child", level_1);
+        assert_eq!(case.data.len(), 4 + usize::from(count));
+        assert_eq!(case.assertions.len(), 5);
+        for (expr, expected) in &case.assertions {
+            assert_eq!(eval(expr, &env).as_ref(), Ok(expected), "{:?}", expr);
+        }
+    }
+}
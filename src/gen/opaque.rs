@@ -0,0 +1,187 @@
+//! Specs with `ks-opaque-types: true` reading types that they don't define, with stub
+//! implementations of those types.
+//!
+//! Every stub is available as a spec of its own, to be compiled by KSC for the target at hand
+//! (which is how the opaque types of the official test suite are implemented). For targets with a
+//! stable convention for opaque types, a hand-written stub is available too, to check that
+//! generated parsers only rely on the documented interface.
+
+use rand::Rng;
+
+use crate::ast::Expr;
+use crate::eval::Value;
+use crate::gen::case::SpecCase;
+use crate::ksy::{Attribute, KsySpec};
+use crate::target::Target;
+
+/// External type read by the main spec. The stub reads `size` bytes into `raw`.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct OpaqueStub {
+    pub type_name: String,
+    pub size: usize,
+}
+
+impl OpaqueStub {
+    /// The stub as a spec.
+    pub fn spec(&self) -> KsySpec {
+        let mut spec = KsySpec::top_level(&self.type_name);
+        spec.seq.push(Attribute {
+            id: Some("raw".to_string()),
+            size: Some(Expr::Int(self.size as u64)),
+            ..Default::default()
+        });
+        spec
+    }
+
+    /// Name of the class that parsers of the main spec instantiate.
+    fn class_name(&self) -> String {
+        self.type_name
+            .split('_')
+            .map(|part| {
+                let mut chars = part.chars();
+                chars.next().map_or(String::new(), |first| {
+                    first.to_ascii_uppercase().to_string() + chars.as_str()
+                })
+            })
+            .collect()
+    }
+
+    /// Hand-written stub for the target: the file name and its source, or `None` if the stub
+    /// spec has to be compiled instead.
+    pub fn source(&self, target: Target) -> Option<(String, String)> {
+        let (class, size) = (self.class_name(), self.size);
+        match target {
+            Target::Python => Some((
+                format!("{}.py", self.type_name),
+                format!(
+                    "from kaitaistruct import KaitaiStruct\n\
+                     \n\
+                     \n\
+                     class {class}(KaitaiStruct):\n    \
+                         def __init__(self, _io, _parent=None, _root=None):\n        \
+                             self._io = _io\n        \
+                             self.raw = self._io.read_bytes({size})\n"
+                ),
+            )),
+            Target::Ruby => Some((
+                format!("{}.rb", self.type_name),
+                format!(
+                    "require 'kaitai/struct/struct'\n\
+                     \n\
+                     class {class} < Kaitai::Struct::Struct\n  \
+                         attr_reader :raw\n\
+                     \n  \
+                         def initialize(_io, _parent = nil, _root = self)\n    \
+                             super(_io, _parent, _root)\n    \
+                             @raw = @_io.read_bytes({size})\n  \
+                         end\n\
+                     end\n"
+                ),
+            )),
+            _ => None,
+        }
+    }
+}
+
+/// Main spec reading opaque types between `u1` attributes, with the stubs of the types, data
+/// and the expected values (including the `raw` attributes of the stubs).
+#[derive(Clone, Debug, PartialEq)]
+pub struct OpaqueCase {
+    pub main: SpecCase,
+    pub stubs: Vec<OpaqueStub>,
+}
+
+/// The main spec reads `head: u1`, `ext_0`, `mid: u1`, `ext_1` in a substream larger than what
+/// the stub reads, and `tail: u1`. The opaque types are named `<id>_ext_<i>`.
+pub fn opaque_case<R: Rng + ?Sized>(rng: &mut R, id: &str, max_size: usize) -> OpaqueCase {
+    let mut spec = KsySpec::top_level(id);
+    spec.meta.as_mut().unwrap().ks_opaque_types = Some(true);
+    let mut data = Vec::new();
+    let mut assertions = Vec::new();
+    let mut stubs = Vec::new();
+    let mut read_u1 = |spec: &mut KsySpec, data: &mut Vec<u8>, attr_name: &str, value: u8| {
+        spec.seq.push(Attribute::new(attr_name, "u1"));
+        data.push(value);
+        assertions.push((Expr::Name(attr_name.to_string()), Value::Int(value.into())));
+    };
+    read_u1(&mut spec, &mut data, "head", rng.gen());
+    let mut externals = Vec::new();
+    for i in 0..2 {
+        let stub = OpaqueStub {
+            type_name: format!("{}_ext_{}", id, i),
+            size: rng.gen_range(0..=max_size),
+        };
+        let raw: Vec<u8> = (0..stub.size).map(|_| rng.gen()).collect();
+        let attr_name = format!("ext_{}", i);
+        let mut attr = Attribute::new(&attr_name, &stub.type_name);
+        data.extend(&raw);
+        if i == 1 {
+            // the rest of the substream is left unread
+            let extra = rng.gen_range(1..=4);
+            attr.size = Some(Expr::Int((stub.size + extra) as u64));
+            data.extend((0..extra).map(|_| rng.gen::<u8>()));
+        }
+        spec.seq.push(attr);
+        externals.push((attr_name, raw));
+        stubs.push(stub);
+        if i == 0 {
+            read_u1(&mut spec, &mut data, "mid", rng.gen());
+        }
+    }
+    read_u1(&mut spec, &mut data, "tail", rng.gen());
+    for (attr_name, raw) in externals {
+        let raw_expr = Expr::Attribute {
+            value: Box::new(Expr::Name(attr_name)),
+            attr_name: "raw".to_string(),
+        };
+        assertions.push((raw_expr, Value::Bytes(raw)));
+    }
+    OpaqueCase {
+        main: SpecCase {
+            spec,
+            data,
+            assertions,
+        },
+        stubs,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::typing::{KsType, TypeEnv};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn stubs() {
+        let stub = OpaqueStub {
+            type_name: "opaque_ext_0".to_string(),
+            size: 3,
+        };
+        assert_eq!(stub.class_name(), "OpaqueExt0");
+        let (file_name, source) = stub.source(Target::Python).unwrap();
+        assert_eq!(file_name, "opaque_ext_0.py");
+        assert!(source.contains("\nclass OpaqueExt0(KaitaiStruct):\n    def __init__("));
+        assert!(source.ends_with("        self.raw = self._io.read_bytes(3)\n"));
+        assert!(stub.source(Target::Cpp).is_none());
+        assert_eq!(stub.spec().id(), Some("opaque_ext_0"));
+    }
+
+    #[test]
+    fn opaque_types_are_undefined() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let case = opaque_case(&mut rng, "opaque", 8);
+        assert!(case
+            .main
+            .spec
+            .to_yaml()
+            .contains("  ks-opaque-types: true\n"));
+        assert!(case.main.spec.types.is_empty());
+        let sizes: usize = case.stubs.iter().map(|stub| stub.size).sum();
+        assert!(case.main.data.len() > 3 + sizes);
+        let env = TypeEnv::from_spec(&case.main.spec, &[]).unwrap();
+        assert_eq!(env.get("ext_0"), None);
+        assert_eq!(env.get("tail").map(KsType::widened), Some(KsType::Int));
+    }
+}
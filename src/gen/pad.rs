@@ -0,0 +1,191 @@
+//! Fixed-size byte arrays and strings with `pad-right`, alone and combined with `terminator`,
+//! with data padded accordingly.
+//!
+//! Runtimes strip the padding first and then look for the terminator in what's left, so content
+//! may contain the padding byte anywhere but at its end.
+
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+use crate::ast::Expr;
+use crate::eval::Value;
+use crate::ksy::{Attribute, KsySpec};
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum PadForm {
+    Bytes,
+    /// ASCII `str`
+    Str,
+}
+
+impl PadForm {
+    pub const ALL: [PadForm; 2] = [PadForm::Bytes, PadForm::Str];
+
+    fn name(self) -> &'static str {
+        match self {
+            PadForm::Bytes => "bytes",
+            PadForm::Str => "str",
+        }
+    }
+}
+
+/// Spec reading `value` of a fixed size with `pad-right` (and possibly `terminator`) and then a
+/// `u1` attribute `next`, with data where the content is empty, fills the whole size or is
+/// somewhere in between.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PadCase {
+    pub spec: KsySpec,
+    pub variants: Vec<PadData>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PadData {
+    pub data: Vec<u8>,
+    pub assertions: Vec<(Expr, Value)>,
+}
+
+/// Content of `value` with the bytes that follow it within the size: the terminator (if any and
+/// if there's room) and then the padding.
+fn padded(content: &[u8], size: usize, pad: u8, terminator: Option<u8>) -> Vec<u8> {
+    let mut bytes = content.to_vec();
+    if let Some(terminator) = terminator {
+        if bytes.len() < size {
+            bytes.push(terminator);
+        }
+    }
+    bytes.resize(size, pad);
+    bytes
+}
+
+/// Value of `value` read from `bytes`: with the padding stripped, then cut at the terminator.
+pub fn strip(bytes: &[u8], pad: u8, terminator: Option<u8>, include: bool) -> Vec<u8> {
+    let len = bytes.iter().rposition(|b| *b != pad).map_or(0, |i| i + 1);
+    let stripped = &bytes[..len];
+    match terminator.and_then(|t| stripped.iter().position(|b| *b == t)) {
+        Some(i) if include => stripped[..=i].to_vec(),
+        Some(i) => stripped[..i].to_vec(),
+        None => stripped.to_vec(),
+    }
+}
+
+/// Case with `value` of 1 to `max_size` bytes. With `with_terminator`, `include` is chosen at
+/// random.
+pub fn pad_case<R: Rng + ?Sized>(
+    rng: &mut R,
+    id: &str,
+    form: PadForm,
+    with_terminator: bool,
+    max_size: usize,
+) -> PadCase {
+    let alphabet: Vec<u8> = match form {
+        PadForm::Bytes => (0..=255).collect(),
+        PadForm::Str => (b' '..=b'~').collect(),
+    };
+    let pad = *alphabet.choose(rng).unwrap();
+    let terminator = with_terminator.then(|| loop {
+        let terminator = *alphabet.choose(rng).unwrap();
+        if terminator != pad {
+            break terminator;
+        }
+    });
+    let include = with_terminator && rng.gen();
+    let size = rng.gen_range(1..=max_size);
+
+    let mut value = match form {
+        PadForm::Bytes => Attribute {
+            id: Some("value".to_string()),
+            ..Default::default()
+        },
+        PadForm::Str => Attribute {
+            encoding: Some("ASCII".to_string()),
+            ..Attribute::new("value", "str")
+        },
+    };
+    value.size = Some(Expr::Int(size as u64));
+    value.pad_right = Some(pad);
+    value.terminator = terminator;
+    if with_terminator {
+        value.include = Some(include);
+    }
+    let mut spec = KsySpec::top_level(id);
+    spec.seq.push(value);
+    spec.seq.push(Attribute::new("next", "u1"));
+
+    let content_byte = |rng: &mut R, last: bool| loop {
+        let byte = *alphabet.choose(rng).unwrap();
+        if Some(byte) != terminator && !(last && byte == pad) {
+            break byte;
+        }
+    };
+    let mut variants = Vec::new();
+    for len in [0, size, rng.gen_range(0..=size)] {
+        let content: Vec<u8> = (0..len).map(|i| content_byte(rng, i + 1 == len)).collect();
+        let mut data = padded(&content, size, pad, terminator);
+        let expected = strip(&data, pad, terminator, include);
+        let next: u8 = rng.gen();
+        data.push(next);
+        let value = match form {
+            PadForm::Bytes => Value::Bytes(expected),
+            PadForm::Str => Value::Str(String::from_utf8(expected).unwrap()),
+        };
+        variants.push(PadData {
+            data,
+            assertions: vec![
+                (Expr::Name("value".to_string()), value),
+                (Expr::Name("next".to_string()), Value::Int(next.into())),
+            ],
+        });
+    }
+    PadCase { spec, variants }
+}
+
+/// Cases for every [`PadForm`], with and without a terminator, with ids like `pad_str_term`.
+pub fn pad_cases<R: Rng + ?Sized>(rng: &mut R, max_size: usize) -> Vec<PadCase> {
+    let mut cases = Vec::new();
+    for form in PadForm::ALL {
+        for with_terminator in [false, true] {
+            let suffix = if with_terminator { "_term" } else { "" };
+            let id = format!("pad_{}{}", form.name(), suffix);
+            cases.push(pad_case(rng, &id, form, with_terminator, max_size));
+        }
+    }
+    cases
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn stripping() {
+        assert_eq!(strip(b"a b  ", b' ', None, false), b"a b");
+        assert_eq!(strip(b"     ", b' ', None, false), b"");
+        assert_eq!(strip(b"ab|c|  ", b' ', Some(b'|'), false), b"ab");
+        assert_eq!(strip(b"ab|c|  ", b' ', Some(b'|'), true), b"ab|");
+        // the padding is stripped before looking for the terminator
+        assert_eq!(strip(b"ab  ", b' ', Some(b' '), false), b"ab");
+        assert_eq!(padded(b"ab", 5, 0, Some(b'|')), b"ab|\0\0");
+        assert_eq!(padded(b"abc", 3, 0, Some(b'|')), b"abc");
+    }
+
+    #[test]
+    fn content_survives_padding() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for case in pad_cases(&mut rng, 8) {
+            let Some(Expr::Int(size)) = case.spec.seq[0].size else {
+                unreachable!()
+            };
+            for variant in &case.variants {
+                assert_eq!(variant.data.len(), size as usize + 1);
+            }
+            let empty = &case.variants[0].assertions[0].1;
+            assert!(
+                matches!(empty, Value::Bytes(b) if b.is_empty())
+                    || matches!(empty, Value::Str(s) if s.is_empty())
+                    || case.spec.seq[0].include == Some(true)
+            );
+        }
+    }
+}
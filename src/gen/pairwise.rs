@@ -0,0 +1,383 @@
+//! Specs combining several KS features on one attribute `x`, since compiler bugs tend to hide in
+//! the interactions of features rather than in the features themselves. [`pairwise_cases`]
+//! covers every pair of [`Aspect`]s that can be combined.
+
+use std::collections::BTreeSet;
+
+use rand::Rng;
+
+use crate::ast::utils::{attr, binary, name};
+use crate::ast::{BinaryOp, Expr};
+use crate::eval::Value;
+use crate::gen::case::SpecCase;
+use crate::gen::process::{unapply, ProcessKind};
+use crate::ksy::{Attribute, KsySpec, Repeat, TypeSpec, Valid, ValidChecks};
+
+const ATTR_NAME: &str = "x";
+/// Attribute of the wrapper type holding the item when `x` is read from a substream
+const FIELD_NAME: &str = "v";
+const WRAPPER_NAME: &str = "x_wrap";
+const ENUM_NAME: &str = "x_enum";
+const COUNT_NAME: &str = "n";
+const FLAG_NAME: &str = "has_x";
+const ITEM_NAME: &str = "_";
+
+/// Feature applied to the attribute `x` (or to its items).
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum Aspect {
+    /// Items are `b2`..`b7` bit fields instead of `u1`
+    BitField,
+    /// Items have an `enum` with a label for each value in the data
+    Enum,
+    /// Items have a `valid` key that the data passes
+    Valid,
+    /// `x` has an `if` on a preceding flag
+    If,
+    RepeatExpr,
+    RepeatUntil,
+    RepeatEos,
+    /// Items are read from a substream larger than what they take, by a wrapper type
+    Substream,
+    /// Items are read from a `process: xor(...)` substream, by a wrapper type
+    Process,
+}
+
+impl Aspect {
+    pub const ALL: [Aspect; 9] = [
+        Aspect::BitField,
+        Aspect::Enum,
+        Aspect::Valid,
+        Aspect::If,
+        Aspect::RepeatExpr,
+        Aspect::RepeatUntil,
+        Aspect::RepeatEos,
+        Aspect::Substream,
+        Aspect::Process,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Aspect::BitField => "bits",
+            Aspect::Enum => "enum",
+            Aspect::Valid => "valid",
+            Aspect::If => "if",
+            Aspect::RepeatExpr => "repeat_expr",
+            Aspect::RepeatUntil => "repeat_until",
+            Aspect::RepeatEos => "repeat_eos",
+            Aspect::Substream => "substream",
+            Aspect::Process => "process",
+        }
+    }
+
+    fn is_repeat(self) -> bool {
+        matches!(
+            self,
+            Aspect::RepeatExpr | Aspect::RepeatUntil | Aspect::RepeatEos
+        )
+    }
+}
+
+/// Whether the aspects can be combined on one attribute, i.e. there's at most one repeat mode.
+pub fn compatible(aspects: &[Aspect]) -> bool {
+    aspects.iter().filter(|aspect| aspect.is_repeat()).count() <= 1
+}
+
+/// Every pair of distinct aspects that can be combined.
+pub fn pairs() -> Vec<(Aspect, Aspect)> {
+    let mut pairs = Vec::new();
+    for (i, a) in Aspect::ALL.into_iter().enumerate() {
+        for b in Aspect::ALL.into_iter().skip(i + 1) {
+            if compatible(&[a, b]) {
+                pairs.push((a, b));
+            }
+        }
+    }
+    pairs
+}
+
+/// Spec with the aspects applied to `x`, with data and the expected values of `x` (or of the
+/// items in the wrapper types).
+#[derive(Clone, Debug, PartialEq)]
+pub struct PairwiseCase {
+    pub main: SpecCase,
+    pub aspects: Vec<Aspect>,
+}
+
+fn label(value: u8) -> String {
+    format!("v{}", value)
+}
+
+/// Packs the items of `bits` bits each, most significant bit first (the default `bit-endian`),
+/// padding the last byte with zeros.
+pub fn pack_bits(items: &[u8], bits: u32) -> Vec<u8> {
+    let mut packed = Vec::new();
+    let mut pos = 0;
+    for item in items {
+        for i in (0..bits).rev() {
+            if pos % 8 == 0 {
+                packed.push(0);
+            }
+            let bit = (item >> i) & 1;
+            *packed.last_mut().unwrap() |= bit << (7 - pos % 8);
+            pos += 1;
+        }
+    }
+    packed
+}
+
+/// Case with the aspects applied together, with up to `max_items` items if one of them is a
+/// repeat. Returns `None` if the aspects can't be combined.
+pub fn combined_case<R: Rng + ?Sized>(
+    rng: &mut R,
+    id: &str,
+    aspects: &[Aspect],
+    max_items: usize,
+) -> Option<PairwiseCase> {
+    if !compatible(aspects) {
+        return None;
+    }
+    let has = |aspect| aspects.contains(&aspect);
+    let bits = has(Aspect::BitField).then(|| rng.gen_range(2..=7u32));
+    let max_value = bits.map_or(u8::MAX, |bits| (1u8 << bits) - 1);
+    let wrapped = has(Aspect::Substream) || has(Aspect::Process);
+    let repeat = aspects.iter().copied().find(|aspect| aspect.is_repeat());
+
+    let max_items = max_items.max(1);
+    let count = match repeat {
+        None => 1,
+        // the items must end on a byte boundary, where the end of the stream can be detected
+        Some(Aspect::RepeatEos) if bits.is_some() && !wrapped => {
+            let step = 8 >> bits.unwrap().trailing_zeros().min(3);
+            step * rng.gen_range(1..=(max_items / step).max(1))
+        }
+        Some(_) => rng.gen_range(1..=max_items),
+    };
+    let mut items: Vec<u8> = (0..count).map(|_| rng.gen_range(0..=max_value)).collect();
+    let last = items[count - 1];
+    if repeat == Some(Aspect::RepeatUntil) {
+        // the repetition must only stop at the last item
+        for item in &mut items[..count - 1] {
+            while *item == last {
+                *item = rng.gen_range(0..=max_value);
+            }
+        }
+    }
+    let item_value = |item: u8| {
+        if has(Aspect::Enum) {
+            Value::Enum {
+                enum_path: vec![ENUM_NAME.to_string()],
+                value: item.into(),
+            }
+        } else {
+            Value::Int(item.into())
+        }
+    };
+    let item_literal = |item: u8| {
+        if has(Aspect::Enum) {
+            Expr::EnumMember {
+                enum_path: vec![ENUM_NAME.to_string()],
+                label: label(item),
+            }
+        } else {
+            Expr::Int(item.into())
+        }
+    };
+
+    let mut spec = KsySpec::top_level(id);
+    let mut data = Vec::new();
+    let item_type = bits.map_or("u1".to_string(), |bits| format!("b{}", bits));
+    let mut item_attr = Attribute::new(if wrapped { FIELD_NAME } else { ATTR_NAME }, item_type);
+    let distinct: BTreeSet<u8> = items.iter().copied().collect();
+    if has(Aspect::Enum) {
+        item_attr.enum_name = Some(ENUM_NAME.to_string());
+        spec.enums.insert(
+            ENUM_NAME.to_string(),
+            distinct
+                .iter()
+                .map(|item| (i128::from(*item), label(*item)))
+                .collect(),
+        );
+    }
+    if has(Aspect::Valid) {
+        // enums can't be ordered, so they are checked against the members instead
+        let checks = if has(Aspect::Enum) {
+            ValidChecks {
+                any_of: distinct.iter().map(|item| item_literal(*item)).collect(),
+                ..Default::default()
+            }
+        } else {
+            ValidChecks {
+                min: Some(Expr::Int(distinct.first().copied().unwrap().into())),
+                max: Some(Expr::Int(distinct.last().copied().unwrap().into())),
+                ..Default::default()
+            }
+        };
+        item_attr.valid = Some(Valid::Checks(checks));
+    }
+
+    let mut x = if wrapped {
+        spec.types.insert(
+            WRAPPER_NAME.to_string(),
+            TypeSpec {
+                seq: vec![item_attr],
+                ..Default::default()
+            },
+        );
+        Attribute::new(ATTR_NAME, WRAPPER_NAME)
+    } else {
+        item_attr
+    };
+    let extra = if has(Aspect::Substream) {
+        rng.gen_range(1..=3)
+    } else {
+        0
+    };
+    let key: u8 = rng.gen_range(1..=u8::MAX);
+    if wrapped {
+        x.size = Some(Expr::Int(1 + extra as u64));
+        if has(Aspect::Process) {
+            x.process = Some(format!("xor({})", key));
+        }
+    }
+
+    match repeat {
+        Some(Aspect::RepeatExpr) => {
+            spec.seq.push(Attribute::new(COUNT_NAME, "u1"));
+            data.push(count as u8);
+            x.repeat = Some(Repeat::Expr);
+            x.repeat_expr = Some(name(COUNT_NAME));
+        }
+        Some(Aspect::RepeatUntil) => {
+            let item = if wrapped {
+                attr(name(ITEM_NAME), FIELD_NAME)
+            } else {
+                name(ITEM_NAME)
+            };
+            x.repeat = Some(Repeat::Until);
+            x.repeat_until = Some(binary(item, BinaryOp::Eq, item_literal(last)));
+        }
+        Some(_) => x.repeat = Some(Repeat::Eos),
+        None => {}
+    }
+    if has(Aspect::If) {
+        spec.seq.push(Attribute::new(FLAG_NAME, "u1"));
+        data.push(rng.gen_range(1..=u8::MAX));
+        x.if_expr = Some(binary(name(FLAG_NAME), BinaryOp::Ne, Expr::Int(0)));
+    }
+    spec.seq.push(x);
+
+    let mut assertions = Vec::new();
+    if wrapped {
+        for (i, item) in items.iter().enumerate() {
+            let byte = bits.map_or(*item, |bits| item << (8 - bits));
+            let mut raw = vec![byte];
+            raw.extend((0..extra).map(|_| rng.gen::<u8>()));
+            if has(Aspect::Process) {
+                raw = unapply(ProcessKind::Xor, &[key], &raw);
+            }
+            data.extend(raw);
+            let wrapper = match repeat {
+                Some(_) => Expr::Subscript {
+                    value: Box::new(name(ATTR_NAME)),
+                    idx: Box::new(Expr::Int(i as u64)),
+                },
+                None => name(ATTR_NAME),
+            };
+            assertions.push((attr(wrapper, FIELD_NAME), item_value(*item)));
+        }
+    } else {
+        match bits {
+            Some(bits) => data.extend(pack_bits(&items, bits)),
+            None => data.extend(&items),
+        }
+        let value = match repeat {
+            Some(_) => Value::Array(items.iter().map(|item| item_value(*item)).collect()),
+            None => item_value(items[0]),
+        };
+        assertions.push((name(ATTR_NAME), value));
+    }
+    Some(PairwiseCase {
+        main: SpecCase {
+            spec,
+            data,
+            assertions,
+        },
+        aspects: aspects.to_vec(),
+    })
+}
+
+/// Cases for all [`pairs`], with ids like `pair_bits_enum`.
+pub fn pairwise_cases<R: Rng + ?Sized>(rng: &mut R, max_items: usize) -> Vec<PairwiseCase> {
+    pairs()
+        .into_iter()
+        .filter_map(|(a, b)| {
+            let id = format!("pair_{}_{}", a.name(), b.name());
+            combined_case(rng, &id, &[a, b], max_items)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ksy::TypeRef;
+    use crate::typing::{infer, TypeEnv};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn bit_packing() {
+        assert_eq!(
+            pack_bits(&[0b101, 0b011, 0b110], 3),
+            [0b1010_1111, 0b0000_0000]
+        );
+        assert_eq!(pack_bits(&[0b11, 0b01, 0b10, 0b00], 2), [0b1101_1000]);
+        assert_eq!(pack_bits(&[], 5), []);
+    }
+
+    #[test]
+    fn all_pairs_are_covered() {
+        let mut rng = StdRng::seed_from_u64(0);
+        // 36 pairs, without the 3 pairs of repeat modes
+        assert_eq!(pairs().len(), 33);
+        assert!(
+            combined_case(&mut rng, "x", &[Aspect::RepeatExpr, Aspect::RepeatEos], 4).is_none()
+        );
+        let cases = pairwise_cases(&mut rng, 8);
+        assert_eq!(cases.len(), 33);
+        for case in &cases {
+            let env = TypeEnv::from_spec(&case.main.spec, &[]).unwrap();
+            for (expr, _) in &case.main.assertions {
+                assert!(infer(expr, &env).is_ok(), "{:?}: {:?}", case.aspects, expr);
+            }
+        }
+    }
+
+    #[test]
+    fn combined_keys() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..10 {
+            let aspects = [Aspect::BitField, Aspect::RepeatEos];
+            let case = combined_case(&mut rng, "pair", &aspects, 8).unwrap();
+            let x = &case.main.spec.seq[0];
+            assert_eq!(x.repeat, Some(Repeat::Eos));
+            let Some(TypeRef::Named(type_name)) = &x.type_ref else {
+                panic!("{:?}", x.type_ref);
+            };
+            let bits: usize = type_name[1..].parse().unwrap();
+            let Value::Array(items) = &case.main.assertions[0].1 else {
+                panic!("{:?}", case.main.assertions);
+            };
+            assert_eq!(items.len() * bits, case.main.data.len() * 8);
+        }
+
+        let aspects = [Aspect::Process, Aspect::Substream, Aspect::If, Aspect::Enum];
+        let case = combined_case(&mut rng, "pair", &aspects, 8).unwrap();
+        let yaml = case.main.spec.to_yaml();
+        for key in ["process: xor(", "if: (has_x != 0)", "enum: x_enum"] {
+            assert!(yaml.contains(key), "{}", yaml);
+        }
+        // the flag, the item and 1 to 3 bytes left unread in the substream
+        assert!((3..=5).contains(&case.main.data.len()));
+    }
+}
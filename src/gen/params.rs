@@ -0,0 +1,213 @@
+//! Parametric user types with parameters of each kind, instantiated with arguments computed from
+//! the attributes read before the call site.
+
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+use crate::ast::utils::{attr, binary, name};
+use crate::ast::{BinaryOp, Expr};
+use crate::eval::{eval, Env, Value};
+use crate::gen::case::SpecCase;
+use crate::ksy::{Attribute, KsySpec, Param, TypeRef, TypeSpec};
+
+const ENUM_NAME: &str = "animal";
+const ENUM_LABELS: [&str; 3] = ["cat", "dog", "fox"];
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum ParamKind {
+    Int,
+    Bool,
+    Str,
+    Enum,
+    /// Instance of a user type
+    Type,
+}
+
+impl ParamKind {
+    pub const ALL: [ParamKind; 5] = [
+        ParamKind::Int,
+        ParamKind::Bool,
+        ParamKind::Str,
+        ParamKind::Enum,
+        ParamKind::Type,
+    ];
+}
+
+/// Parameter `p_<kind>` with the argument passed for it.
+fn param<R: Rng + ?Sized>(rng: &mut R, kind: ParamKind) -> (Param, Expr) {
+    let (id, type_name, arg) = match kind {
+        ParamKind::Int => {
+            let type_name = *["u1", "u2", "s4", "u8"].choose(rng).unwrap();
+            let arg = binary(name("n"), BinaryOp::Add, Expr::Int(rng.gen_range(0..=8)));
+            ("p_int", type_name, arg)
+        }
+        ParamKind::Bool => {
+            let arg = binary(name("n"), BinaryOp::Gt, Expr::Int(rng.gen_range(0..=8)));
+            ("p_bool", "bool", arg)
+        }
+        ParamKind::Str => {
+            let arg = if rng.gen() {
+                name("label")
+            } else {
+                binary(name("label"), BinaryOp::Add, Expr::Str("-x".to_string()))
+            };
+            ("p_str", "str", arg)
+        }
+        ParamKind::Enum => ("p_enum", "u1", name("kind")),
+        ParamKind::Type => ("p_hdr", "header", name("hdr")),
+    };
+    let param = Param {
+        id: id.to_string(),
+        type_name: Some(type_name.to_string()),
+        enum_name: (kind == ParamKind::Enum).then(|| ENUM_NAME.to_string()),
+        doc: None,
+    };
+    (param, arg)
+}
+
+/// Parametric type `target` with a random non-empty subset of the [`ParamKind`]s in random order.
+/// An integer parameter also sets the size of a `payload` attribute.
+pub fn params_case<R: Rng + ?Sized>(rng: &mut R, id: &str) -> SpecCase {
+    let count = rng.gen_range(1..=ParamKind::ALL.len());
+    let kinds: Vec<ParamKind> = ParamKind::ALL
+        .choose_multiple(rng, count)
+        .copied()
+        .collect();
+
+    let mut spec = KsySpec::top_level(id);
+    spec.seq.push(Attribute::new("hdr", "header"));
+    spec.seq.push(Attribute::new("n", "u1"));
+    spec.seq.push(Attribute {
+        encoding: Some("ASCII".to_string()),
+        ..Attribute::new("label", "strz")
+    });
+    spec.seq.push(Attribute {
+        enum_name: Some(ENUM_NAME.to_string()),
+        ..Attribute::new("kind", "u1")
+    });
+    spec.types.insert(
+        "header".to_string(),
+        TypeSpec {
+            seq: vec![Attribute::new("x", "u1")],
+            ..Default::default()
+        },
+    );
+    spec.enums.insert(
+        ENUM_NAME.to_string(),
+        ENUM_LABELS
+            .iter()
+            .enumerate()
+            .map(|(i, label)| (i as i128, label.to_string()))
+            .collect(),
+    );
+
+    let x: u8 = rng.gen();
+    let n: u8 = rng.gen_range(0..=8);
+    let label: String = (0..rng.gen_range(0..=4))
+        .map(|_| *['a', 'b', 'c'].choose(rng).unwrap())
+        .collect();
+    let kind = rng.gen_range(0..ENUM_LABELS.len()) as u8;
+    let mut env = Env::new();
+    env.set(
+        "hdr",
+        Value::Struct([("x".to_string(), Value::Int(x.into()))].into()),
+    );
+    env.set("n", Value::Int(n.into()));
+    env.set("label", Value::Str(label.clone()));
+    env.set(
+        "kind",
+        Value::Enum {
+            enum_path: vec![ENUM_NAME.to_string()],
+            value: kind.into(),
+        },
+    );
+    env.define_enum(
+        vec![ENUM_NAME.to_string()],
+        ENUM_LABELS.iter().enumerate().map(|(i, l)| (*l, i as i128)),
+    );
+    let mut data = vec![x, n];
+    data.extend(label.as_bytes());
+    data.extend([0, kind]);
+
+    let body = name("body");
+    let mut target = TypeSpec::default();
+    let mut args = Vec::new();
+    let mut assertions = Vec::new();
+    for kind in kinds {
+        let (param, arg) = param(rng, kind);
+        let value = eval(&arg, &env).expect("arguments are valid in the environment");
+        let param_ref = attr(body.clone(), &param.id);
+        match kind {
+            ParamKind::Type => assertions.push((attr(param_ref, "x"), Value::Int(x.into()))),
+            ParamKind::Int => {
+                let Value::Int(size) = value else {
+                    unreachable!()
+                };
+                let payload: Vec<u8> = (0..size).map(|_| rng.gen()).collect();
+                target.seq.push(Attribute {
+                    id: Some("payload".to_string()),
+                    size: Some(name(&param.id)),
+                    ..Default::default()
+                });
+                data.extend(&payload);
+                assertions.push((attr(body.clone(), "payload"), Value::Bytes(payload)));
+                assertions.push((param_ref, value));
+            }
+            _ => assertions.push((param_ref, value)),
+        }
+        target.params.push(param);
+        args.push(arg);
+    }
+    spec.types.insert("target".to_string(), target);
+    spec.seq.push(Attribute {
+        id: Some("body".to_string()),
+        type_ref: Some(TypeRef::call("target", &args)),
+        ..Default::default()
+    });
+    SpecCase {
+        spec,
+        data,
+        assertions,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::typing::{infer, KsType, TypeEnv};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn call_site() {
+        let args = [
+            binary(name("n"), BinaryOp::Add, Expr::Int(1)),
+            Expr::Bool(true),
+        ];
+        assert_eq!(
+            TypeRef::call("target", &args),
+            TypeRef::Named("target((n + 1), true)".to_string())
+        );
+    }
+
+    #[test]
+    fn params_are_typed_members() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..10 {
+            let case = params_case(&mut rng, "params");
+            let env = TypeEnv::from_spec(&case.spec, &[]).unwrap();
+            for (expr, expected) in &case.assertions {
+                let ty = infer(expr, &env).unwrap().widened();
+                let expected_ty = match expected {
+                    Value::Int(_) => KsType::Int,
+                    Value::Bool(_) => KsType::Bool,
+                    Value::Str(_) => KsType::Str,
+                    Value::Bytes(_) => KsType::Bytes,
+                    Value::Enum { enum_path, .. } => KsType::Enum(enum_path.clone()),
+                    _ => unreachable!(),
+                };
+                assert_eq!(ty, expected_ty, "{:?}", expr);
+            }
+        }
+    }
+}
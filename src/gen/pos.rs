@@ -0,0 +1,212 @@
+//! Parse instances at positions computed by generated expressions over the attributes read before
+//! them, pointing both back into the `seq` and forward past its end.
+
+use rand::Rng;
+
+use crate::ast::Expr;
+use crate::divergence::diverging_targets;
+use crate::eval::{eval, Env, Value};
+use crate::gen::case::SpecCase;
+use crate::gen::expr::ExprGenerator;
+use crate::ksy::{Attribute, KsySpec, TypeRef};
+use crate::typing::{KsType, TypeEnv};
+
+/// `u1` attributes at the start of the data, which the positions can refer to
+const HEADER: [&str; 3] = ["a", "b", "c"];
+/// Size of the `body` attribute read after the header
+const BODY_LEN: usize = 13;
+/// End of the `seq`
+const SEQ_END: usize = HEADER.len() + BODY_LEN;
+const DATA_LEN: usize = 64;
+/// Size of the `u2le` instances
+const TARGET_LEN: usize = 2;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum Direction {
+    /// Into the header or `body`
+    Backward,
+    /// Past the end of the `seq`
+    Forward,
+}
+
+fn refers_to_header(expr: &Expr) -> bool {
+    matches!(expr, Expr::Name(name) if HEADER.contains(&name.as_str()))
+        || expr.children().into_iter().any(refers_to_header)
+}
+
+/// Position expression (and its value) referring to the header, in the given direction if any.
+fn position<R: Rng + ?Sized>(
+    rng: &mut R,
+    generator: &ExprGenerator,
+    env: &Env,
+    direction: Option<Direction>,
+) -> Option<(Expr, usize)> {
+    for _ in 0..1000 {
+        let expr = generator.generate(rng, &KsType::Int)?;
+        if let Some(pos) = fitting_position(&expr, env, direction) {
+            return Some((expr, pos));
+        }
+    }
+    None
+}
+
+/// Value of the position expression, if it refers to the header and leaves room for a target
+/// in the given direction.
+fn fitting_position(expr: &Expr, env: &Env, direction: Option<Direction>) -> Option<usize> {
+    // a position that some target computes differently reads other bytes there
+    if !refers_to_header(expr) || !diverging_targets(expr, env).is_empty() {
+        return None;
+    }
+    let Ok(Value::Int(pos)) = eval(expr, env) else {
+        return None;
+    };
+    let pos = usize::try_from(pos).ok()?;
+    let in_direction = match direction {
+        Some(Direction::Backward) => pos < SEQ_END,
+        Some(Direction::Forward) => pos >= SEQ_END,
+        None => true,
+    };
+    let fits = pos
+        .checked_add(TARGET_LEN)
+        .is_some_and(|end| end <= DATA_LEN);
+    (in_direction && fits).then_some(pos)
+}
+
+/// Case with `count` (at least 2) `u2le` instances `at_<i>`, the first pointing backward and the
+/// second forward, at positions of at most `max_depth` levels of operators, with data holding a
+/// target value at each of them. Returns `None` if no fitting positions were found.
+pub fn pos_case<R: Rng + ?Sized>(
+    rng: &mut R,
+    id: &str,
+    count: usize,
+    max_depth: usize,
+) -> Option<SpecCase> {
+    let mut type_env = TypeEnv::new();
+    for name in HEADER {
+        type_env.set(name, KsType::Int);
+    }
+    let generator = ExprGenerator::new(&type_env, rng.gen_range(1..=max_depth.max(1)));
+    let mut data: Vec<u8> = (0..DATA_LEN).map(|_| rng.gen()).collect();
+    let mut env = Env::new();
+    for (name, byte) in HEADER.iter().zip(&data) {
+        env.set(*name, Value::Int((*byte).into()));
+    }
+
+    let directions = [Some(Direction::Backward), Some(Direction::Forward)];
+    let mut positions = Vec::new();
+    for i in 0..count.max(2) {
+        let direction = directions.get(i).copied().flatten();
+        positions.push(position(rng, &generator, &env, direction)?);
+    }
+    // targets may overlap; the header stays as it is, since the positions depend on it
+    for (_, pos) in &positions {
+        let target: u16 = rng.gen();
+        for (i, byte) in target.to_le_bytes().into_iter().enumerate() {
+            if pos + i >= HEADER.len() {
+                data[pos + i] = byte;
+            }
+        }
+    }
+
+    let mut spec = KsySpec::top_level(id);
+    spec.seq = HEADER
+        .iter()
+        .map(|name| Attribute::new(*name, "u1"))
+        .collect();
+    spec.seq.push(Attribute {
+        id: Some("body".to_string()),
+        size: Some(Expr::Int(BODY_LEN as u64)),
+        ..Default::default()
+    });
+    let mut assertions = Vec::new();
+    for (i, (expr, pos)) in positions.into_iter().enumerate() {
+        let instance_name = format!("at_{}", i);
+        spec.instances.insert(
+            instance_name.clone(),
+            Attribute {
+                pos: Some(expr),
+                type_ref: Some(TypeRef::Named("u2le".to_string())),
+                ..Default::default()
+            },
+        );
+        let target = u16::from_le_bytes([data[pos], data[pos + 1]]);
+        assertions.push((Expr::Name(instance_name), Value::Int(target.into())));
+    }
+    Some(SpecCase {
+        spec,
+        data,
+        assertions,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::parser::parse_expr;
+    use crate::gen::profile::{Feature, GenProfile};
+    use crate::gen::suite::generate_case;
+    use indexmap::IndexMap;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn positions_point_at_targets() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..20 {
+            let case = pos_case(&mut rng, "pos", 4, 3).unwrap();
+            let mut env = Env::new();
+            for (name, byte) in HEADER.iter().zip(&case.data) {
+                env.set(*name, Value::Int((*byte).into()));
+            }
+            let mut positions = Vec::new();
+            for (expr, expected) in &case.assertions {
+                let Expr::Name(instance_name) = expr else {
+                    unreachable!()
+                };
+                let instance = &case.spec.instances[instance_name];
+                let Ok(Value::Int(pos)) = eval(instance.pos.as_ref().unwrap(), &env) else {
+                    unreachable!()
+                };
+                let pos = pos as usize;
+                let target = u16::from_le_bytes([case.data[pos], case.data[pos + 1]]);
+                assert_eq!(expected, &Value::Int(target.into()));
+                positions.push(pos);
+            }
+            assert!(positions[0] < SEQ_END);
+            assert!(positions[1] >= SEQ_END);
+        }
+    }
+
+    #[test]
+    fn huge_positions_are_skipped() {
+        let mut env = Env::new();
+        for name in HEADER {
+            env.set(name, Value::Int(1));
+        }
+        let huge = parse_expr("(a << 64) - 1").unwrap();
+        assert_eq!(eval(&huge, &env), Ok(Value::Int(u64::MAX.into())));
+        assert_eq!(fitting_position(&huge, &env, None), None);
+        assert_eq!(
+            fitting_position(&huge, &env, Some(Direction::Forward)),
+            None
+        );
+        let fitting = parse_expr("a + 20").unwrap();
+        assert_eq!(fitting_position(&fitting, &env, None), Some(21));
+        // -1 / 2 is 0 where division truncates
+        let divergent = parse_expr("30 + -a / 2").unwrap();
+        assert_eq!(eval(&divergent, &env), Ok(Value::Int(29)));
+        assert_eq!(fitting_position(&divergent, &env, None), None);
+    }
+
+    #[test]
+    fn pos_cases_from_seeds() {
+        let profile = GenProfile {
+            features: IndexMap::from([(Feature::Pos, 1)]),
+            ..GenProfile::default()
+        };
+        for seed in 0..500 {
+            let case = generate_case(seed, &profile).unwrap();
+            assert_eq!(case.feature, Feature::Pos);
+        }
+    }
+}
@@ -0,0 +1,168 @@
+//! Seq attributes of the fixed-size primitive types, covering every read path of the runtime
+//! libraries: `u1`..`u8`, `s1`..`s8`, `f4` and `f8`, each with an explicit `le`/`be` suffix and
+//! with the endianness inherited from `meta/endian`.
+
+use crate::ksy::{Attribute, Endian, KsySpec};
+use crate::numeric::IntType;
+use crate::typing::KsType;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum PrimitiveKind {
+    Int(IntType),
+    /// IEEE 754 float of the given width in bytes (4 or 8)
+    Float(u8),
+}
+
+impl PrimitiveKind {
+    pub const ALL: [PrimitiveKind; 10] = [
+        PrimitiveKind::Int(IntType::ALL[0]),
+        PrimitiveKind::Int(IntType::ALL[1]),
+        PrimitiveKind::Int(IntType::ALL[2]),
+        PrimitiveKind::Int(IntType::ALL[3]),
+        PrimitiveKind::Int(IntType::ALL[4]),
+        PrimitiveKind::Int(IntType::ALL[5]),
+        PrimitiveKind::Int(IntType::ALL[6]),
+        PrimitiveKind::Int(IntType::ALL[7]),
+        PrimitiveKind::Float(4),
+        PrimitiveKind::Float(8),
+    ];
+
+    /// Type name without an endianness suffix.
+    pub fn name(self) -> String {
+        match self {
+            PrimitiveKind::Int(int_type) => int_type.name(),
+            PrimitiveKind::Float(width) => format!("f{}", width),
+        }
+    }
+
+    pub fn byte_size(self) -> u32 {
+        match self {
+            PrimitiveKind::Int(int_type) => u32::from(int_type.width),
+            PrimitiveKind::Float(width) => u32::from(width),
+        }
+    }
+
+    /// Single-byte types are read the same way in both byte orders and don't take a suffix.
+    pub fn has_endianness(self) -> bool {
+        self.byte_size() > 1
+    }
+
+    pub fn ks_type(self) -> KsType {
+        match self {
+            PrimitiveKind::Int(int_type) => KsType::SizedInt(int_type),
+            PrimitiveKind::Float(_) => KsType::Float,
+        }
+    }
+}
+
+/// Primitive type as it appears in a `type` key.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Primitive {
+    pub kind: PrimitiveKind,
+    /// Explicit endianness suffix, `None` if it comes from `meta/endian` (or is irrelevant)
+    pub endian: Option<Endian>,
+}
+
+impl Primitive {
+    /// Every distinct way of writing a primitive type: three forms (`le`, `be` and no suffix)
+    /// for multi-byte types and one form for single-byte types.
+    pub fn all() -> Vec<Primitive> {
+        let mut all = Vec::new();
+        for kind in PrimitiveKind::ALL {
+            all.push(Primitive { kind, endian: None });
+            if kind.has_endianness() {
+                for endian in [Endian::Le, Endian::Be] {
+                    all.push(Primitive {
+                        kind,
+                        endian: Some(endian),
+                    });
+                }
+            }
+        }
+        all
+    }
+
+    pub fn type_name(self) -> String {
+        match self.endian {
+            Some(Endian::Le) => format!("{}le", self.kind.name()),
+            Some(Endian::Be) => format!("{}be", self.kind.name()),
+            None => self.kind.name(),
+        }
+    }
+
+    /// Byte order the value is actually read in, given the default endianness of the spec.
+    pub fn effective_endian(self, default: Endian) -> Endian {
+        self.endian.unwrap_or(default)
+    }
+
+    /// Id of the attribute reading this primitive in [`primitive_spec`].
+    pub fn attr_id(self) -> String {
+        match (self.endian, self.kind.has_endianness()) {
+            (Some(Endian::Le), _) => format!("{}_le", self.kind.name()),
+            (Some(Endian::Be), _) => format!("{}_be", self.kind.name()),
+            (None, true) => format!("{}_default", self.kind.name()),
+            (None, false) => self.kind.name(),
+        }
+    }
+}
+
+/// Spec with `meta/endian: default_endian` reading one attribute of every primitive form, in the
+/// order of [`Primitive::all`].
+pub fn primitive_spec(id: &str, default_endian: Endian) -> KsySpec {
+    let mut spec = KsySpec::top_level(id);
+    spec.meta.as_mut().unwrap().endian = Some(default_endian.into());
+    spec.seq = Primitive::all()
+        .into_iter()
+        .map(|primitive| Attribute::new(primitive.attr_id(), primitive.type_name()))
+        .collect();
+    spec
+}
+
+/// Specs covering every primitive read path: both with `meta/endian: le` and `be`, so that
+/// suffix-less types are read in both byte orders too.
+pub fn primitive_specs(id_prefix: &str) -> [KsySpec; 2] {
+    [
+        primitive_spec(&format!("{}_le", id_prefix), Endian::Le),
+        primitive_spec(&format!("{}_be", id_prefix), Endian::Be),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::Expr;
+    use crate::ksy::MetaEndian;
+    use crate::typing::{infer, TypeEnv};
+    use std::collections::HashSet;
+
+    #[test]
+    fn covers_every_read_path() {
+        let specs = primitive_specs("primitives");
+        let mut read_paths = HashSet::new();
+        for spec in &specs {
+            let Some(MetaEndian::Fixed(default)) = spec.meta.as_ref().unwrap().endian else {
+                unreachable!()
+            };
+            for primitive in Primitive::all() {
+                let endian = primitive.kind.has_endianness();
+                read_paths.insert((
+                    primitive.kind,
+                    endian.then(|| primitive.effective_endian(default)),
+                ));
+            }
+            assert_eq!(spec.seq.len(), 2 + 8 * 3);
+        }
+        assert_eq!(read_paths.len(), 2 + 8 * 2);
+    }
+
+    #[test]
+    fn attributes_have_primitive_types() {
+        let spec = primitive_spec("primitives", Endian::Be);
+        let env = TypeEnv::from_spec(&spec, &[]).unwrap();
+        for primitive in Primitive::all() {
+            let attr = Expr::Name(primitive.attr_id());
+            assert_eq!(infer(&attr, &env), Ok(primitive.kind.ks_type()));
+        }
+        assert!(spec.to_yaml().contains("- id: f4_be\n  type: f4be\n"));
+    }
+}
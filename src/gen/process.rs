@@ -0,0 +1,210 @@
+//! Attributes with a `process` key (`xor`, `rol`, `ror` and `zlib`). The data holds the payload
+//! transformed by the inverse of the process, so that reading it yields the payload again.
+
+use std::io::{Read, Write};
+
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use rand::Rng;
+
+use crate::ast::Expr;
+use crate::ksy::{Attribute, KsySpec};
+use crate::translator::translate;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum ProcessKind {
+    Xor,
+    Rol,
+    Ror,
+    Zlib,
+}
+
+impl ProcessKind {
+    pub const ALL: [ProcessKind; 4] = [
+        ProcessKind::Xor,
+        ProcessKind::Rol,
+        ProcessKind::Ror,
+        ProcessKind::Zlib,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            ProcessKind::Xor => "xor",
+            ProcessKind::Rol => "rol",
+            ProcessKind::Ror => "ror",
+            ProcessKind::Zlib => "zlib",
+        }
+    }
+}
+
+/// Spec with a processed byte array `buf` (possibly preceded by the attribute its key comes
+/// from), with data and the expected value of `buf`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProcessCase {
+    pub spec: KsySpec,
+    pub data: Vec<u8>,
+    pub expected: Vec<u8>,
+}
+
+/// Applies the process like a runtime reading the data does. `key` is the xor key (repeated over
+/// the data), a single byte with the rotation amount, or empty for `zlib`.
+pub fn apply(kind: ProcessKind, key: &[u8], raw: &[u8]) -> Vec<u8> {
+    match kind {
+        ProcessKind::Xor => xor(key, raw),
+        ProcessKind::Rol => raw.iter().map(|b| b.rotate_left(key[0].into())).collect(),
+        ProcessKind::Ror => raw.iter().map(|b| b.rotate_right(key[0].into())).collect(),
+        ProcessKind::Zlib => {
+            let mut decoded = Vec::new();
+            ZlibDecoder::new(raw)
+                .read_to_end(&mut decoded)
+                .expect("data must be valid zlib");
+            decoded
+        }
+    }
+}
+
+/// Inverse of [`apply`]: the raw data that reads as `payload`.
+pub fn unapply(kind: ProcessKind, key: &[u8], payload: &[u8]) -> Vec<u8> {
+    match kind {
+        ProcessKind::Xor => xor(key, payload),
+        ProcessKind::Rol => apply(ProcessKind::Ror, key, payload),
+        ProcessKind::Ror => apply(ProcessKind::Rol, key, payload),
+        ProcessKind::Zlib => {
+            let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(payload).unwrap();
+            encoder.finish().unwrap()
+        }
+    }
+}
+
+fn xor(key: &[u8], data: &[u8]) -> Vec<u8> {
+    data.iter()
+        .zip(key.iter().cycle())
+        .map(|(b, k)| b ^ k)
+        .collect()
+}
+
+/// Case for a payload of up to `max_len` bytes. If `key_from_expr` is set, the key is read into
+/// an attribute `key` first and the process refers to it.
+pub fn process_case<R: Rng + ?Sized>(
+    rng: &mut R,
+    id: &str,
+    kind: ProcessKind,
+    key_from_expr: bool,
+    max_len: usize,
+) -> ProcessCase {
+    let mut spec = KsySpec::top_level(id);
+    let mut data = Vec::new();
+    let key: Vec<u8> = match kind {
+        ProcessKind::Xor => {
+            let len = if rng.gen() { 1 } else { rng.gen_range(2..=4) };
+            (0..len).map(|_| rng.gen()).collect()
+        }
+        ProcessKind::Rol | ProcessKind::Ror => vec![rng.gen_range(0..8)],
+        ProcessKind::Zlib => Vec::new(),
+    };
+    let process = match kind {
+        ProcessKind::Zlib => kind.name().to_string(),
+        _ => {
+            let arg = if key_from_expr {
+                // a single-byte key is read as an integer, a longer one as a byte array
+                spec.seq.push(match key.len() {
+                    1 => Attribute::new("key", "u1"),
+                    len => Attribute {
+                        id: Some("key".to_string()),
+                        size: Some(Expr::Int(len as u64)),
+                        ..Default::default()
+                    },
+                });
+                data.extend(&key);
+                Expr::Name("key".to_string())
+            } else if key.len() == 1 {
+                Expr::Int(key[0].into())
+            } else {
+                Expr::List(key.iter().map(|k| Expr::Int((*k).into())).collect())
+            };
+            format!("{}({})", kind.name(), translate(&arg))
+        }
+    };
+
+    let len = rng.gen_range(0..=max_len);
+    let expected: Vec<u8> = (0..len).map(|_| rng.gen()).collect();
+    let raw = unapply(kind, &key, &expected);
+    spec.seq.push(Attribute {
+        id: Some("buf".to_string()),
+        size: Some(Expr::Int(raw.len() as u64)),
+        process: Some(process),
+        ..Default::default()
+    });
+    data.extend(raw);
+    ProcessCase {
+        spec,
+        data,
+        expected,
+    }
+}
+
+/// Cases for every [`ProcessKind`], with literal and (except for `zlib`) expression keys, with
+/// ids like `process_xor_expr`.
+pub fn process_cases<R: Rng + ?Sized>(rng: &mut R, max_len: usize) -> Vec<ProcessCase> {
+    let mut cases = Vec::new();
+    for kind in ProcessKind::ALL {
+        for key_from_expr in [false, true] {
+            if kind == ProcessKind::Zlib && key_from_expr {
+                continue;
+            }
+            let suffix = if key_from_expr { "_expr" } else { "" };
+            let id = format!("process_{}{}", kind.name(), suffix);
+            cases.push(process_case(rng, &id, kind, key_from_expr, max_len));
+        }
+    }
+    cases
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn transformations() {
+        assert_eq!(apply(ProcessKind::Rol, &[1], &[0x81]), [0x03]);
+        assert_eq!(apply(ProcessKind::Ror, &[1], &[0x81]), [0xc0]);
+        assert_eq!(
+            apply(ProcessKind::Xor, &[0xff, 0], &[1, 2, 3]),
+            [0xfe, 2, 0xfc]
+        );
+    }
+
+    #[test]
+    fn data_reads_as_payload() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let cases = process_cases(&mut rng, 16);
+        assert_eq!(cases.len(), 7);
+        for case in cases {
+            let buf = case.spec.seq.last().unwrap();
+            let process = buf.process.as_ref().unwrap();
+            let kind = ProcessKind::ALL
+                .into_iter()
+                .find(|kind| process.starts_with(kind.name()))
+                .unwrap();
+            let Some(Expr::Int(size)) = buf.size else {
+                unreachable!()
+            };
+            let (key_data, raw) = case.data.split_at(case.data.len() - size as usize);
+            let key = match process.split_once('(') {
+                Some((_, "key)")) => key_data.to_vec(),
+                Some((_, arg)) => arg
+                    .trim_end_matches(')')
+                    .trim_matches(['[', ']'])
+                    .split(", ")
+                    .map(|k| k.parse().unwrap())
+                    .collect(),
+                None => Vec::new(),
+            };
+            assert_eq!(apply(kind, &key, raw), case.expected, "{}", process);
+        }
+    }
+}
@@ -0,0 +1,545 @@
+//! Profiles selecting what the generators may use: which kinds of test cases, which operators in
+//! expressions, which primitive types and how large things get, each with a weight. A profile
+//! lets a suite focus on one area (e.g. only bit-sized integers, or only string handling).
+//!
+//! Profiles are written in TOML or YAML, with every section optional:
+//!
+//! ```toml
+//! trace = true
+//!
+//! [features]
+//! primitive = 3
+//! repeat = 1
+//!
+//! [operators]
+//! "&" = 2
+//! "<<" = 1
+//!
+//! [types]
+//! b1 = 1
+//! b5 = 2
+//!
+//! [sizes]
+//! max-len = 4
+//! inputs = 3
+//! max-data = 1024
+//!
+//! [negatives]
+//! truncated = true
+//! violations = true
+//!
+//! [expressions]
+//! compound = 0.9
+//! int-range = [-128, 127]
+//! float-range = [0.0, 1.0]
+//! max-str-len = 3
+//! boundary = 0.5
+//!
+//! [expressions.variants]
+//! binary = 4
+//! cond = 1
+//! ```
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use indexmap::IndexMap;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::ast::{BinaryOp, UnaryOp};
+use crate::typing::spec::builtin_type;
+
+/// Symbol of the `?:` operator in the weights of operators
+pub const COND_OP: &str = "?:";
+
+const BINARY_OPS: [BinaryOp; 18] = [
+    BinaryOp::Add,
+    BinaryOp::Sub,
+    BinaryOp::Mul,
+    BinaryOp::Div,
+    BinaryOp::Rem,
+    BinaryOp::Eq,
+    BinaryOp::Ne,
+    BinaryOp::Lt,
+    BinaryOp::Le,
+    BinaryOp::Gt,
+    BinaryOp::Ge,
+    BinaryOp::And,
+    BinaryOp::Or,
+    BinaryOp::BitOr,
+    BinaryOp::BitXor,
+    BinaryOp::BitAnd,
+    BinaryOp::Shl,
+    BinaryOp::Shr,
+];
+
+const UNARY_OPS: [UnaryOp; 3] = [UnaryOp::Neg, UnaryOp::Not, UnaryOp::Inv];
+
+/// Names of the kinds of compound expressions in the weights of variants
+pub const EXPR_VARIANTS: [&str; 8] = [
+    "unary",
+    "binary",
+    "cond",
+    "attribute",
+    "method-call",
+    "subscript",
+    "sizeof",
+    "cast",
+];
+
+#[derive(Debug, Error)]
+pub enum ProfileError {
+    #[error("can't read the profile: {0}")]
+    Io(#[from] io::Error),
+    #[error("invalid TOML profile: {0}")]
+    Toml(#[from] toml::de::Error),
+    #[error("invalid YAML profile: {0}")]
+    Yaml(#[from] serde_yaml::Error),
+    #[error("profile file `{0}` is neither `.toml` nor `.yaml`")]
+    UnknownFormat(String),
+    #[error("unknown operator `{0}`")]
+    UnknownOperator(String),
+    #[error("`{0}` is not a primitive type")]
+    UnknownType(String),
+    #[error("unknown kind of expression `{0}`")]
+    UnknownVariant(String),
+    #[error("invalid `{0}` in the expression settings")]
+    InvalidSetting(&'static str),
+}
+
+/// Kinds of test cases, one for each generator module.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Feature {
+    Bytes,
+    Cast,
+    Cond,
+    Contents,
+    Endian,
+    Enums,
+    Eos,
+    Idents,
+    Imports,
+    Instances,
+    Nested,
+    Opaque,
+    Pad,
+    Pairwise,
+    Params,
+    Pos,
+    Primitive,
+    Process,
+    Recursive,
+    Repeat,
+    String,
+    Substream,
+    Switch,
+    Terminator,
+    Valid,
+}
+
+impl Feature {
+    pub const ALL: [Feature; 25] = [
+        Feature::Bytes,
+        Feature::Cast,
+        Feature::Cond,
+        Feature::Contents,
+        Feature::Endian,
+        Feature::Enums,
+        Feature::Eos,
+        Feature::Idents,
+        Feature::Imports,
+        Feature::Instances,
+        Feature::Nested,
+        Feature::Opaque,
+        Feature::Pad,
+        Feature::Pairwise,
+        Feature::Params,
+        Feature::Pos,
+        Feature::Primitive,
+        Feature::Process,
+        Feature::Recursive,
+        Feature::Repeat,
+        Feature::String,
+        Feature::Substream,
+        Feature::Switch,
+        Feature::Terminator,
+        Feature::Valid,
+    ];
+
+    /// Name as written in profiles.
+    pub fn name(self) -> &'static str {
+        match self {
+            Feature::Bytes => "bytes",
+            Feature::Cast => "cast",
+            Feature::Cond => "cond",
+            Feature::Contents => "contents",
+            Feature::Endian => "endian",
+            Feature::Enums => "enums",
+            Feature::Eos => "eos",
+            Feature::Idents => "idents",
+            Feature::Imports => "imports",
+            Feature::Instances => "instances",
+            Feature::Nested => "nested",
+            Feature::Opaque => "opaque",
+            Feature::Pad => "pad",
+            Feature::Pairwise => "pairwise",
+            Feature::Params => "params",
+            Feature::Pos => "pos",
+            Feature::Primitive => "primitive",
+            Feature::Process => "process",
+            Feature::Recursive => "recursive",
+            Feature::Repeat => "repeat",
+            Feature::String => "string",
+            Feature::Substream => "substream",
+            Feature::Switch => "switch",
+            Feature::Terminator => "terminator",
+            Feature::Valid => "valid",
+        }
+    }
+}
+
+/// Limits on the size of generated specs and data.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Sizes {
+    /// Length of byte arrays, strings and substreams
+    pub max_len: usize,
+    /// Levels of operators in expressions and of nested or recursive types
+    pub max_depth: usize,
+    /// Items of repeated attributes and attributes of generated types
+    pub max_items: usize,
+    /// Data files for each spec whose data is synthesized, taking different branches where
+    /// possible
+    pub inputs: usize,
+    /// Bytes of each synthesized data file, at most
+    pub max_data: Option<usize>,
+}
+
+impl Default for Sizes {
+    fn default() -> Self {
+        Sizes {
+            max_len: 16,
+            max_depth: 3,
+            max_items: 8,
+            inputs: 1,
+            max_data: None,
+        }
+    }
+}
+
+/// Inputs that parsing must reject, added to the cases of every feature whose spec the data
+/// generator supports.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Negatives {
+    /// The data cut at the boundaries and in the middle of fields, failing with an
+    /// end-of-stream error
+    pub truncated: bool,
+    /// Data failing a `contents` or `valid` check, one for each check
+    pub violations: bool,
+}
+
+/// Shape of random expressions, besides their operators and depth.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Expressions {
+    /// Weights of the kinds of compound expressions by their name (one of [`EXPR_VARIANTS`]),
+    /// following the same rules as the operators
+    pub variants: IndexMap<String, u32>,
+    /// Probability that an expression above the depth limit is compound rather than a leaf
+    pub compound: Option<f64>,
+    /// Smallest and largest `int` literal
+    pub int_range: Option<(i64, i64)>,
+    /// Smallest and largest float literal
+    pub float_range: Option<(f64, f64)>,
+    /// Characters of string literals, at most
+    pub max_str_len: Option<u32>,
+    /// Probability that a literal is one of the boundary values of its type (like `0`, `-1`,
+    /// `2^63 - 1` or `""`), which find most bugs of translators, rather than a random one
+    pub boundary: Option<f64>,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GenProfile {
+    /// Weights of the kinds of test cases; the kinds that aren't listed are not generated
+    pub features: IndexMap<Feature, u32>,
+    /// Weights of the operators by their symbol (with [`COND_OP`] for `?:`); if empty, all
+    /// operators have the same weight, otherwise the ones that aren't listed are not used
+    pub operators: IndexMap<String, u32>,
+    /// Weights of the primitive types of attributes (e.g. `u2` or `b3`), following the same
+    /// rules as the operators
+    pub types: IndexMap<String, u32>,
+    pub sizes: Sizes,
+    pub negatives: Negatives,
+    pub expressions: Expressions,
+    /// Whether synthesized inputs come with a trace of the fields their bytes come from
+    pub trace: bool,
+}
+
+impl Default for GenProfile {
+    /// Profile allowing everything with the same weight.
+    fn default() -> Self {
+        GenProfile {
+            features: Feature::ALL
+                .into_iter()
+                .map(|feature| (feature, 1))
+                .collect(),
+            operators: IndexMap::new(),
+            types: IndexMap::new(),
+            sizes: Sizes::default(),
+            negatives: Negatives::default(),
+            expressions: Expressions::default(),
+            trace: false,
+        }
+    }
+}
+
+impl GenProfile {
+    pub fn from_toml_str(s: &str) -> Result<Self, ProfileError> {
+        toml::from_str::<Self>(s)?.checked()
+    }
+
+    pub fn from_yaml_str(s: &str) -> Result<Self, ProfileError> {
+        serde_yaml::from_str::<Self>(s)?.checked()
+    }
+
+    /// Loads a profile, in the format given by the extension of the file.
+    pub fn load(path: &Path) -> Result<Self, ProfileError> {
+        let s = fs::read_to_string(path)?;
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => Self::from_toml_str(&s),
+            Some("yaml" | "yml") => Self::from_yaml_str(&s),
+            _ => Err(ProfileError::UnknownFormat(path.display().to_string())),
+        }
+    }
+
+    fn checked(self) -> Result<Self, ProfileError> {
+        let symbols = operator_symbols();
+        if let Some(symbol) = self
+            .operators
+            .keys()
+            .find(|symbol| !symbols.contains(&symbol.as_str()))
+        {
+            return Err(ProfileError::UnknownOperator(symbol.clone()));
+        }
+        if let Some(type_name) = self.types.keys().find(|name| builtin_type(name).is_none()) {
+            return Err(ProfileError::UnknownType(type_name.clone()));
+        }
+        let expressions = &self.expressions;
+        if let Some(name) = expressions
+            .variants
+            .keys()
+            .find(|name| !EXPR_VARIANTS.contains(&name.as_str()))
+        {
+            return Err(ProfileError::UnknownVariant(name.clone()));
+        }
+        let probability = |p: Option<f64>| p.is_some_and(|p| !(0.0..=1.0).contains(&p));
+        if probability(expressions.compound) {
+            return Err(ProfileError::InvalidSetting("compound"));
+        }
+        if probability(expressions.boundary) {
+            return Err(ProfileError::InvalidSetting("boundary"));
+        }
+        if expressions.int_range.is_some_and(|(min, max)| min > max) {
+            return Err(ProfileError::InvalidSetting("int-range"));
+        }
+        if expressions
+            .float_range
+            .is_some_and(|(min, max)| !(min.is_finite() && max.is_finite() && min <= max))
+        {
+            return Err(ProfileError::InvalidSetting("float-range"));
+        }
+        Ok(self)
+    }
+
+    pub fn feature_weight(&self, feature: Feature) -> u32 {
+        self.features.get(&feature).copied().unwrap_or(0)
+    }
+
+    /// Weight of the operator with the symbol (`-` stands for both the negation and the
+    /// subtraction).
+    pub fn operator_weight(&self, symbol: &str) -> u32 {
+        weight(&self.operators, symbol)
+    }
+
+    pub fn type_weight(&self, type_name: &str) -> u32 {
+        weight(&self.types, type_name)
+    }
+
+    /// Weight of the kind of compound expression, one of [`EXPR_VARIANTS`].
+    pub fn variant_weight(&self, name: &str) -> u32 {
+        weight(&self.expressions.variants, name)
+    }
+
+    /// Random kind of test case by the weights, or `None` if no kind is allowed.
+    pub fn choose_feature<R: Rng + ?Sized>(&self, rng: &mut R) -> Option<Feature> {
+        choose_weighted(rng, &self.features).copied()
+    }
+
+    /// Random type by the weights out of `candidates` (which an empty `types` map allows all of).
+    pub fn choose_type<'a, R: Rng + ?Sized>(
+        &self,
+        rng: &mut R,
+        candidates: &[&'a str],
+    ) -> Option<&'a str> {
+        let weights: IndexMap<&str, u32> = candidates
+            .iter()
+            .map(|type_name| (*type_name, self.type_weight(type_name)))
+            .collect();
+        choose_weighted(rng, &weights).copied()
+    }
+}
+
+/// Symbols of all the operators that profiles weight, each once (`-` is both the negation and the
+/// subtraction).
+pub fn operator_symbols() -> Vec<&'static str> {
+    let mut symbols: Vec<&str> = BINARY_OPS.iter().map(|op| op.symbol()).collect();
+    for op in UNARY_OPS {
+        if !symbols.contains(&op.symbol()) {
+            symbols.push(op.symbol());
+        }
+    }
+    symbols.push(COND_OP);
+    symbols
+}
+
+/// Weight of `key`, where an empty map gives everything the weight 1.
+fn weight(weights: &IndexMap<String, u32>, key: &str) -> u32 {
+    if weights.is_empty() {
+        1
+    } else {
+        weights.get(key).copied().unwrap_or(0)
+    }
+}
+
+fn choose_weighted<'a, K, R: Rng + ?Sized>(
+    rng: &mut R,
+    weights: &'a IndexMap<K, u32>,
+) -> Option<&'a K> {
+    let entries: Vec<(&K, &u32)> = weights.iter().filter(|(_, w)| **w > 0).collect();
+    entries
+        .choose_weighted(rng, |(_, w)| **w)
+        .ok()
+        .map(|(key, _)| *key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    const BIT_LEVEL: &str = r#"
+[features]
+primitive = 1
+
+[operators]
+"&" = 2
+"|" = 1
+"<<" = 1
+">>" = 1
+
+[types]
+b1 = 1
+b3 = 1
+b12 = 2
+
+[sizes]
+max-depth = 2
+"#;
+
+    #[test]
+    fn formats() {
+        let profile = GenProfile::from_toml_str(BIT_LEVEL).unwrap();
+        assert_eq!(profile.feature_weight(Feature::Primitive), 1);
+        assert_eq!(profile.feature_weight(Feature::Repeat), 0);
+        assert_eq!(profile.operator_weight("&"), 2);
+        assert_eq!(profile.operator_weight("+"), 0);
+        assert_eq!(profile.sizes.max_depth, 2);
+        assert_eq!(profile.sizes.max_len, Sizes::default().max_len);
+
+        let yaml = "features:\n  primitive: 1\nsizes:\n  max-len: 4\n";
+        let profile = GenProfile::from_yaml_str(yaml).unwrap();
+        assert_eq!(profile.sizes.max_len, 4);
+        assert!(!profile.negatives.truncated);
+        for feature in Feature::ALL {
+            let toml = format!("[features]\n{} = 1\n", feature.name());
+            let profile = GenProfile::from_toml_str(&toml).unwrap();
+            assert_eq!(profile.feature_weight(feature), 1);
+        }
+        assert_eq!(profile.operator_weight("+"), 1);
+        assert_eq!(
+            GenProfile::from_yaml_str("{}").unwrap(),
+            GenProfile::default()
+        );
+        let profile = GenProfile::from_toml_str("[negatives]\ntruncated = true\n").unwrap();
+        assert!(profile.negatives.truncated && !profile.negatives.violations);
+    }
+
+    #[test]
+    fn expressions() {
+        let toml = "[expressions]\n\
+                    int-range = [-128, 127]\n\
+                    max-str-len = 0\n\
+                    [expressions.variants]\n\
+                    binary = 2\n";
+        let profile = GenProfile::from_toml_str(toml).unwrap();
+        assert_eq!(profile.expressions.int_range, Some((-128, 127)));
+        assert_eq!(profile.expressions.max_str_len, Some(0));
+        assert_eq!(profile.expressions.compound, None);
+        assert_eq!(profile.variant_weight("binary"), 2);
+        assert_eq!(profile.variant_weight("cond"), 0);
+        assert_eq!(GenProfile::default().variant_weight("cond"), 1);
+
+        let err = GenProfile::from_toml_str("[expressions.variants]\nlambda = 1\n").unwrap_err();
+        assert!(matches!(err, ProfileError::UnknownVariant(name) if name == "lambda"));
+        for (toml, setting) in [
+            ("compound = 1.5", "compound"),
+            ("boundary = -0.5", "boundary"),
+            ("int-range = [1, 0]", "int-range"),
+            ("float-range = [0.0, inf]", "float-range"),
+        ] {
+            let err = GenProfile::from_toml_str(&format!("[expressions]\n{}\n", toml));
+            assert!(
+                matches!(err, Err(ProfileError::InvalidSetting(name)) if name == setting),
+                "{}",
+                toml
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_profiles() {
+        let err = GenProfile::from_toml_str("[operators]\n\"**\" = 1\n").unwrap_err();
+        assert!(matches!(err, ProfileError::UnknownOperator(op) if op == "**"));
+        let err = GenProfile::from_toml_str("[types]\nb65 = 1\n").unwrap_err();
+        assert!(matches!(err, ProfileError::UnknownType(ty) if ty == "b65"));
+        let err = GenProfile::from_toml_str("[features]\nbits = 1\n").unwrap_err();
+        assert!(matches!(err, ProfileError::Toml(_)));
+        let err = GenProfile::from_yaml_str("size: {}\n").unwrap_err();
+        assert!(matches!(err, ProfileError::Yaml(_)));
+    }
+
+    #[test]
+    fn weighted_choices() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let profile = GenProfile::from_toml_str(BIT_LEVEL).unwrap();
+        for _ in 0..20 {
+            assert_eq!(profile.choose_feature(&mut rng), Some(Feature::Primitive));
+            let ty = profile.choose_type(&mut rng, &["u1", "b3", "b12", "f4"]);
+            assert!(matches!(ty, Some("b3" | "b12")));
+        }
+        assert_eq!(profile.choose_type(&mut rng, &["u1"]), None);
+        let nothing = GenProfile {
+            features: IndexMap::new(),
+            ..Default::default()
+        };
+        assert_eq!(nothing.choose_feature(&mut rng), None);
+    }
+}
@@ -0,0 +1,173 @@
+//! Self-referential types: linked lists and trees whose depth is controlled by the data.
+
+use rand::Rng;
+
+use crate::ast::utils::{attr, name};
+use crate::ast::{BinaryOp, Expr};
+use crate::eval::Value;
+use crate::gen::case::SpecCase;
+use crate::ksy::{Attribute, KsySpec, Repeat, TypeSpec};
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum RecursiveShape {
+    /// `node` reading `value`, `has_next` and, if `has_next != 0`, the `next` node
+    List,
+    /// `node` reading `value`, `num_children` and `num_children` child nodes
+    Tree,
+}
+
+impl RecursiveShape {
+    pub const ALL: [RecursiveShape; 2] = [RecursiveShape::List, RecursiveShape::Tree];
+
+    fn name(self) -> &'static str {
+        match self {
+            RecursiveShape::List => "list",
+            RecursiveShape::Tree => "tree",
+        }
+    }
+}
+
+/// A list is a tree in which every node has at most one child.
+struct Node {
+    value: u8,
+    children: Vec<Node>,
+}
+
+fn random_node<R: Rng + ?Sized>(rng: &mut R, depth: usize, max_children: usize) -> Node {
+    let count = if depth == 0 {
+        0
+    } else {
+        rng.gen_range(0..=max_children)
+    };
+    Node {
+        value: rng.gen(),
+        children: (0..count)
+            .map(|_| random_node(rng, depth - 1, max_children))
+            .collect(),
+    }
+}
+
+fn node_type(shape: RecursiveShape) -> TypeSpec {
+    let mut node = TypeSpec::default();
+    node.seq.push(Attribute::new("value", "u1"));
+    match shape {
+        RecursiveShape::List => {
+            node.seq.push(Attribute::new("has_next", "u1"));
+            node.seq.push(Attribute {
+                if_expr: Some(Expr::BinaryOp {
+                    l: Box::new(name("has_next")),
+                    op: BinaryOp::Ne,
+                    r: Box::new(Expr::Int(0)),
+                }),
+                ..Attribute::new("next", "node")
+            });
+        }
+        RecursiveShape::Tree => {
+            node.seq.push(Attribute::new("num_children", "u1"));
+            node.seq.push(Attribute {
+                repeat: Some(Repeat::Expr),
+                repeat_expr: Some(name("num_children")),
+                ..Attribute::new("children", "node")
+            });
+        }
+    }
+    node
+}
+
+/// Serializes `node` (at `path`) in pre-order, collecting the expected values of its attributes.
+fn write_node(
+    node: &Node,
+    shape: RecursiveShape,
+    path: Expr,
+    data: &mut Vec<u8>,
+    assertions: &mut Vec<(Expr, Value)>,
+) {
+    let count = node.children.len() as u8;
+    data.extend([node.value, count]);
+    let count_attr = match shape {
+        RecursiveShape::List => "has_next",
+        RecursiveShape::Tree => "num_children",
+    };
+    assertions.push((attr(path.clone(), "value"), Value::Int(node.value.into())));
+    assertions.push((attr(path.clone(), count_attr), Value::Int(count.into())));
+    for (i, child) in node.children.iter().enumerate() {
+        let child_path = match shape {
+            RecursiveShape::List => attr(path.clone(), "next"),
+            RecursiveShape::Tree => Expr::Subscript {
+                value: Box::new(attr(path.clone(), "children")),
+                idx: Box::new(Expr::Int(i as u64)),
+            },
+        };
+        write_node(child, shape, child_path, data, assertions);
+    }
+}
+
+/// Case reading a recursive `node` type as `root`, with nodes nested at most `max_depth` levels
+/// below it. Tree nodes have at most 3 children.
+pub fn recursive_case<R: Rng + ?Sized>(
+    rng: &mut R,
+    id: &str,
+    shape: RecursiveShape,
+    max_depth: usize,
+) -> SpecCase {
+    let max_children = match shape {
+        RecursiveShape::List => 1,
+        RecursiveShape::Tree => 3,
+    };
+    let root = random_node(rng, max_depth, max_children);
+
+    let mut spec = KsySpec::top_level(id);
+    spec.seq.push(Attribute::new("root", "node"));
+    spec.types.insert("node".to_string(), node_type(shape));
+    let mut data = Vec::new();
+    let mut assertions = Vec::new();
+    write_node(&root, shape, name("root"), &mut data, &mut assertions);
+    SpecCase {
+        spec,
+        data,
+        assertions,
+    }
+}
+
+/// Cases for every [`RecursiveShape`], with ids like `recursive_tree`.
+pub fn recursive_cases<R: Rng + ?Sized>(rng: &mut R, max_depth: usize) -> Vec<SpecCase> {
+    RecursiveShape::ALL
+        .into_iter()
+        .map(|shape| {
+            let id = format!("recursive_{}", shape.name());
+            recursive_case(rng, &id, shape, max_depth)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::typing::{infer, KsType, TypeEnv};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn paths_are_typed() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for case in recursive_cases(&mut rng, 4) {
+            let env = TypeEnv::from_spec(&case.spec, &[]).unwrap();
+            // every node reads 2 bytes
+            assert_eq!(case.data.len(), case.assertions.len());
+            for (expr, _) in &case.assertions {
+                let ty = infer(expr, &env).map(|ty| ty.widened());
+                assert_eq!(ty, Ok(KsType::Int), "{:?}", expr);
+            }
+        }
+    }
+
+    #[test]
+    fn depth_is_bounded() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..20 {
+            let case = recursive_case(&mut rng, "list", RecursiveShape::List, 3);
+            assert!(case.data.len() <= 2 * 4);
+            assert_eq!(case.data.last(), Some(&0));
+        }
+    }
+}
@@ -0,0 +1,185 @@
+//! Repeated attributes in all three repeat modes, with data making the repetition stop where
+//! intended.
+
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+use crate::ast::utils::binary;
+use crate::ast::{BinaryOp, Expr};
+use crate::eval::{eval, Env, Value};
+use crate::gen::expr::int_literal;
+use crate::ksy::{Attribute, KsySpec, Repeat};
+
+/// Names available in `repeat-until`
+const ITEM_NAME: &str = "_";
+const INDEX_NAME: &str = "_index";
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum RepeatMode {
+    Expr,
+    Until,
+    Eos,
+}
+
+impl RepeatMode {
+    pub const ALL: [RepeatMode; 3] = [RepeatMode::Expr, RepeatMode::Until, RepeatMode::Eos];
+
+    fn name(self) -> &'static str {
+        match self {
+            RepeatMode::Expr => "expr",
+            RepeatMode::Until => "until",
+            RepeatMode::Eos => "eos",
+        }
+    }
+}
+
+/// Spec with a repeated `u1` attribute `items` (possibly preceded by a `count` attribute), with
+/// data and the items expected to be read.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RepeatCase {
+    pub spec: KsySpec,
+    pub data: Vec<u8>,
+    pub items: Vec<u8>,
+}
+
+/// Random `repeat-until` condition, which should become true after `len` items.
+fn until_condition<R: Rng + ?Sized>(rng: &mut R, len: usize) -> Expr {
+    let item = || Expr::Name(ITEM_NAME.to_string());
+    let index = || Expr::Name(INDEX_NAME.to_string());
+    let byte = Expr::Int(rng.gen::<u8>().into());
+    let last_index = int_literal(len as i128 - 1);
+    match rng.gen_range(0..4) {
+        0 => binary(item(), BinaryOp::Eq, byte),
+        1 => binary(index(), BinaryOp::Eq, last_index),
+        2 => binary(
+            binary(item(), BinaryOp::Ge, byte),
+            BinaryOp::And,
+            binary(
+                index(),
+                BinaryOp::Ge,
+                int_literal(rng.gen_range(0..len) as i128),
+            ),
+        ),
+        _ => binary(
+            binary(item(), BinaryOp::Eq, byte),
+            BinaryOp::Or,
+            binary(index(), BinaryOp::Eq, last_index),
+        ),
+    }
+}
+
+/// Items for which `cond` first becomes true at the last of `len` items, or `None` if there are
+/// none.
+fn items_until<R: Rng + ?Sized>(rng: &mut R, cond: &Expr, len: usize) -> Option<Vec<u8>> {
+    let mut items = Vec::new();
+    for i in 0..len {
+        let mut env = Env::new();
+        env.set(INDEX_NAME, Value::Int(i as i128));
+        let wanted = Value::Bool(i == len - 1);
+        let candidates: Vec<u8> = (0..=u8::MAX)
+            .filter(|&x| {
+                env.set(ITEM_NAME, Value::Int(x.into()));
+                eval(cond, &env).as_ref() == Ok(&wanted)
+            })
+            .collect();
+        items.push(*candidates.choose(rng)?);
+    }
+    Some(items)
+}
+
+/// Repeated attribute of 1 to `max_len` (at most 255) items in the given mode.
+pub fn repeat_case<R: Rng + ?Sized>(
+    rng: &mut R,
+    id: &str,
+    mode: RepeatMode,
+    max_len: usize,
+) -> RepeatCase {
+    let mut spec = KsySpec::top_level(id);
+    let mut data = Vec::new();
+    let len = rng.gen_range(1..=max_len);
+    let mut items: Vec<u8> = (0..len).map(|_| rng.gen()).collect();
+    let attr = match mode {
+        RepeatMode::Expr => {
+            let count = if rng.gen() {
+                spec.seq.push(Attribute::new("count", "u1"));
+                data.push(len as u8);
+                Expr::Name("count".to_string())
+            } else {
+                Expr::Int(len as u64)
+            };
+            Attribute {
+                repeat: Some(Repeat::Expr),
+                repeat_expr: Some(count),
+                ..Attribute::new("items", "u1")
+            }
+        }
+        RepeatMode::Until => {
+            let (cond, until_items) = loop {
+                let cond = until_condition(rng, len);
+                if let Some(until_items) = items_until(rng, &cond, len) {
+                    break (cond, until_items);
+                }
+            };
+            items = until_items;
+            Attribute {
+                repeat: Some(Repeat::Until),
+                repeat_until: Some(cond),
+                ..Attribute::new("items", "u1")
+            }
+        }
+        RepeatMode::Eos => Attribute {
+            repeat: Some(Repeat::Eos),
+            ..Attribute::new("items", "u1")
+        },
+    };
+    spec.seq.push(attr);
+    data.extend(&items);
+    RepeatCase { spec, data, items }
+}
+
+/// One case of each [`RepeatMode`], with ids like `repeat_until`.
+pub fn repeat_cases<R: Rng + ?Sized>(rng: &mut R, max_len: usize) -> Vec<RepeatCase> {
+    RepeatMode::ALL
+        .into_iter()
+        .map(|mode| repeat_case(rng, &format!("repeat_{}", mode.name()), mode, max_len))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn until_stops_at_last_item() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..50 {
+            let case = repeat_case(&mut rng, "r", RepeatMode::Until, 6);
+            let cond = case.spec.seq[0].repeat_until.as_ref().unwrap();
+            assert_eq!(case.data, case.items);
+            for (i, item) in case.items.iter().enumerate() {
+                let mut env = Env::new();
+                env.set(INDEX_NAME, Value::Int(i as i128));
+                env.set(ITEM_NAME, Value::Int((*item).into()));
+                let last = i == case.items.len() - 1;
+                assert_eq!(eval(cond, &env), Ok(Value::Bool(last)));
+            }
+        }
+    }
+
+    #[test]
+    fn expr_count() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..10 {
+            let case = repeat_case(&mut rng, "r", RepeatMode::Expr, 6);
+            let items = case.spec.seq.last().unwrap();
+            let count = match items.repeat_expr.as_ref().unwrap() {
+                Expr::Int(count) => *count as usize,
+                _ => case.data[0].into(),
+            };
+            assert_eq!(count, case.items.len());
+            assert!(case.data.ends_with(&case.items));
+        }
+    }
+}
@@ -0,0 +1,432 @@
+//! Shrinking of failing test cases into minimal reproducers. The shrinker repeatedly tries
+//! smaller variants of the spec (without a type, an attribute or a key, with a simpler
+//! expression) and of the data (without a chunk of bytes, with a byte zeroed), and keeps each
+//! variant for which the caller's check says the failure persists.
+//!
+//! Expressions shrink on their own too, for failures of the expression generators that don't
+//! need a whole spec to reproduce.
+//!
+//! The check should recognize the specific failure (e.g. the KSC error message or the failing
+//! assertion), otherwise the shrinker happily turns it into a different one.
+
+use crate::ast::utils::PositiveFiniteF64;
+use crate::ast::Expr;
+use crate::ksy::{Attribute, KsySpec, TypeRef, TypeSpec, Valid};
+
+/// Smallest spec and data found, with the number of checks it took.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Shrunk {
+    pub spec: KsySpec,
+    pub data: Vec<u8>,
+    pub checks: usize,
+}
+
+impl Shrunk {
+    /// The spec followed by a hex dump of the data in comments, to paste into a bug report.
+    pub fn report(&self) -> String {
+        let mut report = self.spec.to_yaml();
+        report.push_str(&format!("# data ({} bytes):\n", self.data.len()));
+        for line in self.data.chunks(16) {
+            let bytes: Vec<String> = line.iter().map(|b| format!("{:02x}", b)).collect();
+            report.push_str(&format!("#   {}\n", bytes.join(" ")));
+        }
+        report
+    }
+}
+
+/// Shrinks `spec` and `data` for which `still_fails` holds, calling it at most `max_checks`
+/// times.
+pub fn shrink<F>(spec: &KsySpec, data: &[u8], max_checks: usize, mut still_fails: F) -> Shrunk
+where
+    F: FnMut(&KsySpec, &[u8]) -> bool,
+{
+    let mut shrunk = Shrunk {
+        spec: spec.clone(),
+        data: data.to_vec(),
+        checks: 0,
+    };
+    'shrinking: loop {
+        for candidate in type_candidates(&shrunk.spec) {
+            if shrunk.checks == max_checks {
+                break 'shrinking;
+            }
+            shrunk.checks += 1;
+            if still_fails(&candidate, &shrunk.data) {
+                shrunk.spec = candidate;
+                continue 'shrinking;
+            }
+        }
+        for candidate in data_candidates(&shrunk.data) {
+            if shrunk.checks == max_checks {
+                break 'shrinking;
+            }
+            shrunk.checks += 1;
+            if still_fails(&shrunk.spec, &candidate) {
+                shrunk.data = candidate;
+                continue 'shrinking;
+            }
+        }
+        break;
+    }
+    shrunk
+}
+
+/// Shrinks an expression for which `still_fails` holds, calling it at most `max_checks` times.
+/// Returns the smallest expression found with the number of checks it took.
+pub fn shrink_expr<F>(expr: &Expr, max_checks: usize, mut still_fails: F) -> (Expr, usize)
+where
+    F: FnMut(&Expr) -> bool,
+{
+    let mut shrunk = expr.clone();
+    let mut checks = 0;
+    'shrinking: loop {
+        for candidate in expr_candidates(&shrunk) {
+            if checks == max_checks {
+                break 'shrinking;
+            }
+            checks += 1;
+            if still_fails(&candidate) {
+                shrunk = candidate;
+                continue 'shrinking;
+            }
+        }
+        break;
+    }
+    (shrunk, checks)
+}
+
+/// Variants of the type with one thing removed or simplified, largest removals first.
+fn type_candidates(ty: &TypeSpec) -> Vec<TypeSpec> {
+    let mut candidates = Vec::new();
+    let mut with = |change: &dyn Fn(&mut TypeSpec)| {
+        let mut candidate = ty.clone();
+        change(&mut candidate);
+        candidates.push(candidate);
+    };
+    for name in ty.types.keys() {
+        with(&|c| {
+            c.types.shift_remove(name);
+        });
+    }
+    for name in ty.instances.keys() {
+        with(&|c| {
+            c.instances.shift_remove(name);
+        });
+    }
+    for i in 0..ty.seq.len() {
+        with(&|c| {
+            c.seq.remove(i);
+        });
+    }
+    for i in 0..ty.params.len() {
+        with(&|c| {
+            c.params.remove(i);
+        });
+    }
+    for (name, members) in &ty.enums {
+        with(&|c| {
+            c.enums.shift_remove(name);
+        });
+        if members.len() > 1 {
+            for value in members.keys() {
+                with(&|c| {
+                    c.enums[name].shift_remove(value);
+                });
+            }
+        }
+    }
+    for (i, attr) in ty.seq.iter().enumerate() {
+        for simpler in attr_candidates(attr) {
+            with(&|c| c.seq[i] = simpler.clone());
+        }
+    }
+    for (name, attr) in &ty.instances {
+        for simpler in attr_candidates(attr) {
+            with(&|c| c.instances[name] = simpler.clone());
+        }
+    }
+    for (name, sub) in &ty.types {
+        for simpler in type_candidates(sub) {
+            with(&|c| c.types[name] = simpler.clone());
+        }
+    }
+    candidates
+}
+
+/// Variants of the attribute without an optional key, with fewer switch cases or with a simpler
+/// expression.
+fn attr_candidates(attr: &Attribute) -> Vec<Attribute> {
+    let mut candidates = Vec::new();
+    let mut with = |change: &dyn Fn(&mut Attribute)| {
+        let mut candidate = attr.clone();
+        change(&mut candidate);
+        if candidate != *attr {
+            candidates.push(candidate);
+        }
+    };
+    with(&|c| c.doc = None);
+    with(&|c| c.if_expr = None);
+    with(&|c| c.valid = None);
+    with(&|c| {
+        c.repeat = None;
+        c.repeat_expr = None;
+        c.repeat_until = None;
+    });
+    with(&|c| c.process = None);
+    with(&|c| c.enum_name = None);
+    with(&|c| c.pos = None);
+    with(&|c| c.io = None);
+    with(&|c| c.pad_right = None);
+    with(&|c| c.consume = None);
+    with(&|c| c.include = None);
+    with(&|c| c.eos_error = None);
+    if let Some(TypeRef::Switch { cases, .. }) = &attr.type_ref {
+        for (key, type_name) in cases {
+            with(&|c| c.type_ref = Some(TypeRef::Named(type_name.clone())));
+            if cases.len() > 1 {
+                with(&|c| {
+                    if let Some(TypeRef::Switch { cases, .. }) = &mut c.type_ref {
+                        cases.shift_remove(key);
+                    }
+                });
+            }
+        }
+    }
+
+    let exprs: Vec<Expr> = exprs_mut(&mut attr.clone())
+        .into_iter()
+        .map(|expr| expr.clone())
+        .collect();
+    for (i, expr) in exprs.iter().enumerate() {
+        for simpler in expr_candidates(expr) {
+            with(&|c| *exprs_mut(c).swap_remove(i) = simpler.clone());
+        }
+    }
+    candidates
+}
+
+/// Every expression of the attribute, in a fixed order.
+fn exprs_mut(attr: &mut Attribute) -> Vec<&mut Expr> {
+    let mut exprs: Vec<&mut Expr> = [
+        &mut attr.pos,
+        &mut attr.io,
+        &mut attr.value,
+        &mut attr.size,
+        &mut attr.repeat_expr,
+        &mut attr.repeat_until,
+        &mut attr.if_expr,
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+    if let Some(TypeRef::Switch { switch_on, .. }) = &mut attr.type_ref {
+        exprs.push(switch_on);
+    }
+    match &mut attr.valid {
+        Some(Valid::Eq(expr)) => exprs.push(expr),
+        Some(Valid::Checks(checks)) => {
+            exprs.extend(
+                [&mut checks.eq, &mut checks.min, &mut checks.max]
+                    .into_iter()
+                    .flatten(),
+            );
+            exprs.extend(&mut checks.any_of);
+            exprs.extend(&mut checks.expr);
+        }
+        None => {}
+    }
+    exprs
+}
+
+/// Variants of the expression with one node simpler: a list without an item, a node replaced by
+/// one of its children or by a literal, or a literal replaced by a smaller one. Literals only get
+/// smaller, so that shrinking can't go around in circles.
+fn expr_candidates(expr: &Expr) -> Vec<Expr> {
+    let mut paths = Vec::new();
+    collect_paths(expr, &mut Vec::new(), &mut paths);
+    let mut candidates = Vec::new();
+    for path in paths {
+        let node = expr.node_at(&path).expect("paths must exist");
+        let mut replacements = Vec::new();
+        if let Expr::List(items) = node {
+            for i in 0..items.len() {
+                let mut fewer = items.clone();
+                fewer.remove(i);
+                replacements.push(Expr::List(fewer));
+            }
+        }
+        match node {
+            Expr::Int(x) => replacements.extend([Expr::Int(0), Expr::Int(x / 2)]),
+            Expr::Float(x) => replacements.extend(
+                [0.0, x.value().trunc()]
+                    .into_iter()
+                    .filter_map(|x| PositiveFiniteF64::try_from(x).ok())
+                    .map(Expr::Float),
+            ),
+            Expr::Str(s) => {
+                let chars: Vec<char> = s.chars().collect();
+                replacements.extend(
+                    [0, chars.len() / 2, chars.len().saturating_sub(1)]
+                        .map(|len| Expr::Str(chars[..len].iter().collect())),
+                );
+            }
+            Expr::Bool(_) => replacements.push(Expr::Bool(false)),
+            _ => {
+                replacements.extend(node.children().into_iter().cloned());
+                replacements.extend([Expr::Int(0), Expr::Bool(false), Expr::Str(String::new())]);
+            }
+        }
+        let mut seen = Vec::new();
+        for replacement in replacements {
+            if replacement == *node || seen.contains(&replacement) {
+                continue;
+            }
+            let mut candidate = expr.clone();
+            *candidate.node_at_mut(&path).expect("paths must exist") = replacement.clone();
+            candidates.push(candidate);
+            seen.push(replacement);
+        }
+    }
+    candidates
+}
+
+fn collect_paths(expr: &Expr, path: &mut Vec<usize>, paths: &mut Vec<Vec<usize>>) {
+    paths.push(path.clone());
+    for (i, child) in expr.children().into_iter().enumerate() {
+        path.push(i);
+        collect_paths(child, path, paths);
+        path.pop();
+    }
+}
+
+/// Variants of the data without a chunk of bytes (halves first, down to single bytes), then
+/// with a nonzero byte zeroed.
+fn data_candidates(data: &[u8]) -> Vec<Vec<u8>> {
+    let mut candidates = Vec::new();
+    let mut chunk = data.len().div_ceil(2);
+    while chunk > 0 {
+        for start in (0..data.len()).step_by(chunk) {
+            let end = (start + chunk).min(data.len());
+            candidates.push([&data[..start], &data[end..]].concat());
+        }
+        chunk /= 2;
+    }
+    for (i, byte) in data.iter().enumerate() {
+        if *byte != 0 {
+            let mut candidate = data.to_vec();
+            candidate[i] = 0;
+            candidates.push(candidate);
+        }
+    }
+    candidates
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::parser::parse_expr;
+    use crate::ast::BinaryOp;
+    use crate::gen::switch::{switch_case, SwitchOn};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn has_div(expr: &Expr) -> bool {
+        matches!(
+            expr,
+            Expr::BinaryOp {
+                op: BinaryOp::Div,
+                ..
+            }
+        ) || expr.children().into_iter().any(has_div)
+    }
+
+    /// Stand-in for a compiler bug: division in the `bad` instance with the second byte 0xff.
+    fn fails(spec: &KsySpec, data: &[u8]) -> bool {
+        let div = spec
+            .instances
+            .get("bad")
+            .and_then(|attr| attr.value.as_ref())
+            .is_some_and(has_div);
+        div && data.get(1) == Some(&0xff)
+    }
+
+    #[test]
+    fn minimal_reproducer() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut spec = switch_case(&mut rng, "shrink", SwitchOn::Enum, true).spec;
+        let expr = Expr::BinaryOp {
+            l: Box::new(Expr::BinaryOp {
+                l: Box::new(Expr::Name("selector".to_string())),
+                op: BinaryOp::Add,
+                r: Box::new(Expr::Int(3)),
+            }),
+            op: BinaryOp::Div,
+            r: Box::new(Expr::Int(2)),
+        };
+        spec.instances
+            .insert("bad".to_string(), Attribute::value_instance(expr));
+        let data = [7, 0xff, 1, 2, 3, 4, 5, 6, 7, 8];
+        assert!(fails(&spec, &data));
+
+        let shrunk = shrink(&spec, &data, 10_000, fails);
+        assert!(fails(&shrunk.spec, &shrunk.data));
+        assert!(shrunk.spec.seq.is_empty());
+        assert!(shrunk.spec.types.is_empty());
+        assert!(shrunk.spec.enums.is_empty());
+        assert_eq!(shrunk.spec.instances.len(), 1);
+        let zero = || Box::new(Expr::Int(0));
+        assert_eq!(
+            shrunk.spec.instances["bad"].value,
+            Some(Expr::BinaryOp {
+                l: zero(),
+                op: BinaryOp::Div,
+                r: zero(),
+            })
+        );
+        assert_eq!(shrunk.data, [0, 0xff]);
+        assert_eq!(shrunk.spec.id(), Some("shrink"));
+        assert!(shrunk.report().ends_with("# data (2 bytes):\n#   00 ff\n"));
+    }
+
+    #[test]
+    fn expressions() {
+        let parse = |text: &str| parse_expr(text).unwrap();
+        let expr = parse("(len + [1, 2, 3].size) / (flag ? \"xyz\".length : 2.5)");
+        let (shrunk, _) = shrink_expr(&expr, 1000, has_div);
+        assert_eq!(shrunk, parse("0 / 0"));
+
+        // stand-in for a bug with lists of more than one item and long strings
+        let fails = |expr: &Expr| {
+            let mut paths = Vec::new();
+            collect_paths(expr, &mut Vec::new(), &mut paths);
+            let nodes = paths.iter().filter_map(|path| expr.node_at(path));
+            nodes
+                .clone()
+                .any(|node| matches!(node, Expr::List(items) if items.len() > 1))
+                && nodes
+                    .into_iter()
+                    .any(|node| matches!(node, Expr::Str(s) if s.len() > 1))
+        };
+        let expr = parse("[a, b + 1, c] == [\"some\", \"text\"] and true");
+        let (shrunk, checks) = shrink_expr(&expr, 1000, fails);
+        assert!(checks < 1000);
+        assert_eq!(shrunk, parse("[\"\", \"te\"]"));
+    }
+
+    #[test]
+    fn check_budget() {
+        let spec = KsySpec::top_level("budget");
+        let data = vec![1; 64];
+        let mut calls = 0;
+        let shrunk = shrink(&spec, &data, 5, |_, _| {
+            calls += 1;
+            false
+        });
+        assert_eq!((calls, shrunk.checks), (5, 5));
+        assert_eq!(shrunk.data, data);
+
+        // everything fails: shrinks to nothing
+        let shrunk = shrink(&spec, &data, 1000, |_, _| true);
+        assert!(shrunk.data.is_empty());
+    }
+}
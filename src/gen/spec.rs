@@ -0,0 +1,283 @@
+//! Generation of complete ksy specs around expressions.
+
+use std::collections::BTreeSet;
+
+use thiserror::Error;
+
+use crate::ast::Expr;
+use crate::eval::{Env, Value};
+use crate::ksy::{Attribute, Endian, KsySpec, Repeat, TypeRef, TypeSpec};
+use crate::numeric::IntType;
+
+/// Names that every type has, so they must not be declared as attributes
+const BUILTIN_NAMES: [&str; 3] = ["_io", "_root", "_parent"];
+
+#[derive(Clone, Debug, Error, PartialEq)]
+pub enum SpecGenError {
+    #[error("unknown name `{0}`")]
+    UnknownName(String),
+    #[error("unknown enum `{}`", .0.join("::"))]
+    UnknownEnum(Vec<String>),
+    #[error("attribute `{name}` can't hold the value {value:?}")]
+    UnsupportedValue { name: String, value: Value },
+}
+
+/// Builds a spec with one value instance `expr_N` for each expression (numbered by position in
+/// `exprs`). The names and enums that the expressions refer to are declared as seq attributes
+/// and enums in a way that allows them to hold their values in `env`.
+pub fn value_instance_spec(id: &str, exprs: &[Expr], env: &Env) -> Result<KsySpec, SpecGenError> {
+    let mut spec = KsySpec::top_level(id);
+    spec.meta.as_mut().unwrap().endian = Some(Endian::Le.into());
+
+    let mut names = Vec::new();
+    let mut enums = BTreeSet::new();
+    for expr in exprs {
+        collect_refs(expr, &mut names, &mut enums);
+    }
+    for name in names {
+        let value = env
+            .get(&name)
+            .ok_or_else(|| SpecGenError::UnknownName(name.clone()))?;
+        let attr = declare(&mut spec, &name, value, &mut enums)?;
+        spec.seq.push(attr);
+    }
+    for enum_path in enums {
+        let members = env
+            .enum_members(&enum_path)
+            .ok_or_else(|| SpecGenError::UnknownEnum(enum_path.clone()))?;
+        let (enum_name, type_path) = enum_path.split_last().unwrap();
+        let mut members: Vec<(i128, String)> = members
+            .iter()
+            .map(|(label, value)| (*value, label.clone()))
+            .collect();
+        members.sort();
+        nested_type(&mut spec, type_path)
+            .enums
+            .insert(enum_name.clone(), members.into_iter().collect());
+    }
+
+    for (i, expr) in exprs.iter().enumerate() {
+        spec.instances.insert(
+            format!("expr_{}", i),
+            Attribute::value_instance(expr.clone()),
+        );
+    }
+    Ok(spec)
+}
+
+/// Collects names in `expr` that refer to attributes of the current type (in order of first
+/// occurrence) and the enums used.
+fn collect_refs(expr: &Expr, names: &mut Vec<String>, enums: &mut BTreeSet<Vec<String>>) {
+    match expr {
+        Expr::Name(name) if !BUILTIN_NAMES.contains(&name.as_str()) && !names.contains(name) => {
+            names.push(name.clone())
+        }
+        Expr::EnumMember { enum_path, .. } => {
+            enums.insert(enum_path.clone());
+        }
+        _ => {}
+    }
+    for child in expr.children() {
+        collect_refs(child, names, enums);
+    }
+}
+
+/// Attribute `name` able to hold `value`, declaring the user types (in `spec`) and enums (in
+/// `enums`) it needs.
+fn declare(
+    spec: &mut TypeSpec,
+    name: &str,
+    value: &Value,
+    enums: &mut BTreeSet<Vec<String>>,
+) -> Result<Attribute, SpecGenError> {
+    let unsupported = || SpecGenError::UnsupportedValue {
+        name: name.to_string(),
+        value: value.clone(),
+    };
+    Ok(match value {
+        Value::Int(x) => Attribute::new(name, int_type_for(*x).ok_or_else(unsupported)?.name()),
+        Value::Float(_) => Attribute::new(name, "f8"),
+        Value::Bool(_) => Attribute::new(name, "b1"),
+        Value::Str(s) => Attribute {
+            size: Some(Expr::Int(s.len() as u64)),
+            encoding: Some("UTF-8".to_string()),
+            ..Attribute::new(name, "str")
+        },
+        Value::Bytes(bytes) => Attribute {
+            id: Some(name.to_string()),
+            size: Some(Expr::Int(bytes.len() as u64)),
+            ..Default::default()
+        },
+        Value::Enum { enum_path, value } => {
+            enums.insert(enum_path.clone());
+            Attribute {
+                enum_name: Some(enum_path.join("::")),
+                ..Attribute::new(name, int_type_for(*value).ok_or_else(unsupported)?.name())
+            }
+        }
+        Value::Array(items) => {
+            // all items share the attribute, so it must be able to hold any of them
+            let first = items.first().ok_or_else(unsupported)?;
+            let mut attr = declare(spec, name, first, enums)?;
+            if let Some((min, max)) = int_range(items) {
+                let int_type = int_types_by_width()
+                    .find(|ty| ty.contains(min) && ty.contains(max))
+                    .ok_or_else(unsupported)?;
+                attr.type_ref = Some(TypeRef::Named(int_type.name()));
+            } else {
+                for item in &items[1..] {
+                    if declare(spec, name, item, enums)? != attr {
+                        return Err(unsupported());
+                    }
+                }
+            }
+            Attribute {
+                repeat: Some(Repeat::Expr),
+                repeat_expr: Some(Expr::Int(items.len() as u64)),
+                ..attr
+            }
+        }
+        Value::Struct(fields) => {
+            let mut user_type = TypeSpec::default();
+            for (field, field_value) in fields {
+                let attr = declare(&mut user_type, field, field_value, enums)?;
+                user_type.seq.push(attr);
+            }
+            let type_name = format!("{}_type", name);
+            spec.types.insert(type_name.clone(), user_type);
+            Attribute::new(name, type_name)
+        }
+    })
+}
+
+/// Smallest and largest of `items` if they are all integers (or members of the same enum).
+fn int_range(items: &[Value]) -> Option<(i128, i128)> {
+    let int_value = |item: &Value| match (item, &items[0]) {
+        (Value::Int(x), Value::Int(_)) => Some(*x),
+        (
+            Value::Enum { enum_path, value },
+            Value::Enum {
+                enum_path: first, ..
+            },
+        ) if enum_path == first => Some(*value),
+        _ => None,
+    };
+    let values = items.iter().map(int_value).collect::<Option<Vec<_>>>()?;
+    Some((*values.iter().min()?, *values.iter().max()?))
+}
+
+/// Narrowest integer type containing `value`, preferring unsigned types.
+fn int_type_for(value: i128) -> Option<IntType> {
+    int_types_by_width().find(|ty| ty.contains(value))
+}
+
+fn int_types_by_width() -> impl Iterator<Item = IntType> {
+    let mut types = IntType::ALL;
+    types.sort_by_key(|ty| (ty.width, ty.signed));
+    types.into_iter()
+}
+
+/// Type at `path` (relative to `spec`), created if it doesn't exist.
+fn nested_type<'a>(spec: &'a mut TypeSpec, path: &[String]) -> &'a mut TypeSpec {
+    match path.split_first() {
+        None => spec,
+        Some((name, rest)) => nested_type(spec.types.entry(name.clone()).or_default(), rest),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::BinaryOp;
+    use crate::typing::{infer, TypeEnv};
+    use std::collections::BTreeMap;
+
+    fn name(name: &str) -> Box<Expr> {
+        Box::new(Expr::Name(name.to_string()))
+    }
+
+    #[test]
+    fn declares_referenced_names() {
+        let mut env = Env::new();
+        env.set("len", Value::Int(300));
+        env.set("delta", Value::Int(-1));
+        env.set("unused", Value::Int(1));
+        env.set(
+            "hdr",
+            Value::Struct(BTreeMap::from([("flag".to_string(), Value::Bool(true))])),
+        );
+        env.define_enum(vec!["animal".to_string()], [("dog", 4), ("cat", 7)]);
+        let exprs = [
+            Expr::BinaryOp {
+                l: name("len"),
+                op: BinaryOp::Add,
+                r: name("delta"),
+            },
+            Expr::Attribute {
+                value: name("hdr"),
+                attr_name: "flag".to_string(),
+            },
+            Expr::EnumMember {
+                enum_path: vec!["animal".to_string()],
+                label: "cat".to_string(),
+            },
+        ];
+        let spec = value_instance_spec("corpus", &exprs, &env).unwrap();
+        assert_eq!(
+            spec.to_yaml(),
+            r#"meta:
+  id: corpus
+  endian: le
+seq:
+- id: len
+  type: u2
+- id: delta
+  type: s1
+- id: hdr
+  type: hdr_type
+instances:
+  expr_0:
+    value: (len + delta)
+  expr_1:
+    value: hdr.flag
+  expr_2:
+    value: animal::cat
+types:
+  hdr_type:
+    seq:
+    - id: flag
+      type: b1
+enums:
+  animal:
+    4: dog
+    7: cat
+"#
+        );
+
+        // the declared attributes give the expressions the types they have in `env`
+        let type_env = TypeEnv::from_spec(&spec, &[]).unwrap();
+        assert_eq!(infer(&exprs[0], &type_env), Ok(crate::typing::KsType::Int));
+        assert_eq!(infer(&exprs[1], &type_env), Ok(crate::typing::KsType::Bool));
+    }
+
+    #[test]
+    fn array_of_mixed_widths() {
+        let mut env = Env::new();
+        env.set(
+            "items",
+            Value::Array(vec![Value::Int(1), Value::Int(-200), Value::Int(70000)]),
+        );
+        let spec = value_instance_spec("arr", &[*name("items")], &env).unwrap();
+        let attr = &spec.seq[0];
+        assert_eq!(attr.type_ref, Some(TypeRef::Named("s4".to_string())));
+        assert_eq!(attr.repeat_expr, Some(Expr::Int(3)));
+    }
+
+    #[test]
+    fn unknown_name() {
+        assert_eq!(
+            value_instance_spec("x", &[*name("missing")], &Env::new()),
+            Err(SpecGenError::UnknownName("missing".to_string()))
+        );
+    }
+}
@@ -0,0 +1,307 @@
+//! String attributes (`type: str` and `strz`) in a matrix of encodings, together with the bytes
+//! to put in the stream and the string they are expected to decode to.
+
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+use crate::ast::Expr;
+use crate::ksy::Attribute;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Encoding {
+    Ascii,
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    Iso8859_1,
+    ShiftJis,
+}
+
+impl Encoding {
+    pub const ALL: [Encoding; 6] = [
+        Encoding::Ascii,
+        Encoding::Utf8,
+        Encoding::Utf16Le,
+        Encoding::Utf16Be,
+        Encoding::Iso8859_1,
+        Encoding::ShiftJis,
+    ];
+
+    /// Name in the `encoding` key, as accepted by KSC and all target runtimes.
+    pub fn name(self) -> &'static str {
+        match self {
+            Encoding::Ascii => "ASCII",
+            Encoding::Utf8 => "UTF-8",
+            Encoding::Utf16Le => "UTF-16LE",
+            Encoding::Utf16Be => "UTF-16BE",
+            Encoding::Iso8859_1 => "ISO-8859-1",
+            Encoding::ShiftJis => "SJIS",
+        }
+    }
+
+    /// Encoding named `name` in an `encoding` key, in any case and with the common aliases.
+    pub fn from_name(name: &str) -> Option<Encoding> {
+        match name.to_ascii_uppercase().as_str() {
+            "ASCII" | "US-ASCII" => Some(Encoding::Ascii),
+            "UTF-8" | "UTF8" => Some(Encoding::Utf8),
+            "UTF-16LE" => Some(Encoding::Utf16Le),
+            "UTF-16BE" => Some(Encoding::Utf16Be),
+            "ISO-8859-1" | "ISO8859-1" | "LATIN1" => Some(Encoding::Iso8859_1),
+            "SJIS" | "SHIFT_JIS" | "SHIFT-JIS" => Some(Encoding::ShiftJis),
+            _ => None,
+        }
+    }
+
+    /// Encodes `s`, or returns `None` if some character can't be represented.
+    pub fn encode(self, s: &str) -> Option<Vec<u8>> {
+        match self {
+            Encoding::Ascii => s.is_ascii().then(|| s.as_bytes().to_vec()),
+            Encoding::Utf8 => Some(s.as_bytes().to_vec()),
+            Encoding::Utf16Le => Some(s.encode_utf16().flat_map(u16::to_le_bytes).collect()),
+            Encoding::Utf16Be => Some(s.encode_utf16().flat_map(u16::to_be_bytes).collect()),
+            Encoding::Iso8859_1 => s.chars().map(|c| u8::try_from(c).ok()).collect(),
+            Encoding::ShiftJis => {
+                let (bytes, _, had_errors) = encoding_rs::SHIFT_JIS.encode(s);
+                (!had_errors).then(|| bytes.into_owned())
+            }
+        }
+    }
+
+    /// Decodes `bytes`, or returns `None` if they aren't valid in the encoding.
+    pub fn decode(self, bytes: &[u8]) -> Option<String> {
+        let utf16 = |unit: fn([u8; 2]) -> u16| {
+            if !bytes.len().is_multiple_of(2) {
+                return None;
+            }
+            let units: Vec<u16> = bytes.chunks(2).map(|b| unit([b[0], b[1]])).collect();
+            String::from_utf16(&units).ok()
+        };
+        match self {
+            Encoding::Ascii => bytes
+                .is_ascii()
+                .then(|| bytes.iter().map(|b| char::from(*b)).collect()),
+            Encoding::Utf8 => String::from_utf8(bytes.to_vec()).ok(),
+            Encoding::Utf16Le => utf16(u16::from_le_bytes),
+            Encoding::Utf16Be => utf16(u16::from_be_bytes),
+            Encoding::Iso8859_1 => Some(bytes.iter().map(|b| char::from(*b)).collect()),
+            Encoding::ShiftJis => encoding_rs::SHIFT_JIS
+                .decode_without_bom_handling_and_without_replacement(bytes)
+                .map(|s| s.into_owned()),
+        }
+    }
+
+    /// Whether a string can be terminated by a single byte. In UTF-16, zero bytes (and any other
+    /// terminator byte) appear as halves of ordinary code units.
+    pub fn supports_byte_terminator(self) -> bool {
+        !matches!(self, Encoding::Utf16Le | Encoding::Utf16Be)
+    }
+
+    /// Characters that strings in this encoding are made of. Besides the ASCII range, each
+    /// encoding gets characters that need its multi-byte or high-byte forms.
+    ///
+    /// `\` and `~` are left out of Shift_JIS: some runtimes decode them as `¥` and `‾`.
+    pub fn alphabet(self) -> Vec<char> {
+        let mut chars: Vec<char> = (' '..='~').collect();
+        let extra = match self {
+            Encoding::Ascii => "",
+            Encoding::Utf8 | Encoding::Utf16Le | Encoding::Utf16Be => "éЖ€中😀",
+            Encoding::Iso8859_1 => "äéßñ©°±ÿ",
+            Encoding::ShiftJis => {
+                chars.retain(|c| !matches!(c, '\\' | '~'));
+                "あア漢字ｱ"
+            }
+        };
+        chars.extend(extra.chars());
+        chars
+    }
+
+    /// Characters at the edges of the ranges that encode to the same number of bytes (or of
+    /// the encoding itself), where decoders tend to go wrong.
+    pub fn boundary_chars(self) -> &'static [char] {
+        match self {
+            Encoding::Ascii => &['\u{1}', '\u{7f}'],
+            Encoding::Utf8 | Encoding::Utf16Le | Encoding::Utf16Be => &[
+                '\u{7f}',
+                '\u{80}',
+                '\u{7ff}',
+                '\u{800}',
+                '\u{d7ff}',
+                '\u{e000}',
+                '\u{ffff}',
+                '\u{10000}',
+                '\u{10ffff}',
+            ],
+            Encoding::Iso8859_1 => &['\u{7f}', '\u{80}', '\u{ff}'],
+            // the first and the last half-width katakana, single bytes 0xa1 and 0xdf
+            Encoding::ShiftJis => &['｡', 'ﾟ'],
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum StrForm {
+    /// `type: str` with a fixed `size`
+    Size,
+    /// `type: str` ending at the given `terminator`
+    Terminator(u8),
+    /// `type: strz`, i.e. terminated by a zero byte
+    Strz,
+}
+
+impl StrForm {
+    pub const ALL: [StrForm; 3] = [StrForm::Size, StrForm::Terminator(b'|'), StrForm::Strz];
+
+    fn terminator(self) -> Option<u8> {
+        match self {
+            StrForm::Size => None,
+            StrForm::Terminator(terminator) => Some(terminator),
+            StrForm::Strz => Some(0),
+        }
+    }
+}
+
+/// String attribute with the bytes it reads and the value it is expected to have.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StrField {
+    pub attr: Attribute,
+    /// Bytes consumed from the stream, including the terminator
+    pub bytes: Vec<u8>,
+    pub expected: String,
+}
+
+/// Random string of up to `max_len` characters of `encoding`. If `terminator` is given, no
+/// character encodes to a sequence containing it.
+pub fn sample_string<R: Rng + ?Sized>(
+    rng: &mut R,
+    encoding: Encoding,
+    terminator: Option<u8>,
+    max_len: usize,
+) -> String {
+    let alphabet: Vec<char> = encoding
+        .alphabet()
+        .into_iter()
+        .filter(|c| {
+            let bytes = encoding.encode(&c.to_string()).unwrap();
+            terminator.is_none_or(|t| !bytes.contains(&t))
+        })
+        .collect();
+    let len = rng.gen_range(0..=max_len);
+    (0..len).map(|_| *alphabet.choose(rng).unwrap()).collect()
+}
+
+/// String attribute `id` in the given encoding and form, or `None` if the encoding doesn't
+/// support the form.
+pub fn str_field<R: Rng + ?Sized>(
+    rng: &mut R,
+    id: &str,
+    encoding: Encoding,
+    form: StrForm,
+    max_len: usize,
+) -> Option<StrField> {
+    let terminator = form.terminator();
+    if terminator.is_some() && !encoding.supports_byte_terminator() {
+        return None;
+    }
+    let expected = sample_string(rng, encoding, terminator, max_len);
+    let mut bytes = encoding.encode(&expected).unwrap();
+    let attr = match form {
+        StrForm::Size => Attribute {
+            size: Some(Expr::Int(bytes.len() as u64)),
+            ..Attribute::new(id, "str")
+        },
+        StrForm::Terminator(terminator) => Attribute {
+            terminator: Some(terminator),
+            ..Attribute::new(id, "str")
+        },
+        StrForm::Strz => Attribute::new(id, "strz"),
+    };
+    bytes.extend(terminator);
+    Some(StrField {
+        attr: Attribute {
+            encoding: Some(encoding.name().to_string()),
+            ..attr
+        },
+        bytes,
+        expected,
+    })
+}
+
+/// One field for every supported combination of [`Encoding::ALL`] and [`StrForm::ALL`], named
+/// like `utf_16le_size`.
+pub fn str_fields<R: Rng + ?Sized>(rng: &mut R, max_len: usize) -> Vec<StrField> {
+    let mut fields = Vec::new();
+    for encoding in Encoding::ALL {
+        for form in StrForm::ALL {
+            let form_name = match form {
+                StrForm::Size => "size",
+                StrForm::Terminator(_) => "term",
+                StrForm::Strz => "strz",
+            };
+            let encoding_name = encoding.name().to_lowercase().replace('-', "_");
+            let id = format!("{}_{}", encoding_name, form_name);
+            fields.extend(str_field(rng, &id, encoding, form, max_len));
+        }
+    }
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn encodings() {
+        assert_eq!(Encoding::Ascii.encode("é"), None);
+        assert_eq!(Encoding::Iso8859_1.encode("é"), Some(vec![0xe9]));
+        assert_eq!(Encoding::Utf16Be.encode("€"), Some(vec![0x20, 0xac]));
+        assert_eq!(
+            Encoding::Utf16Le.encode("😀"),
+            Some(vec![0x3d, 0xd8, 0x00, 0xde])
+        );
+        assert_eq!(
+            Encoding::ShiftJis.encode("あｱ"),
+            Some(vec![0x82, 0xa0, 0xb1])
+        );
+        assert_eq!(Encoding::ShiftJis.encode("｡ﾟ"), Some(vec![0xa1, 0xdf]));
+        for encoding in Encoding::ALL {
+            assert_eq!(Encoding::from_name(encoding.name()), Some(encoding));
+            let boundary = encoding.boundary_chars().iter().copied();
+            for c in encoding.alphabet().into_iter().chain(boundary) {
+                let bytes = encoding.encode(&c.to_string());
+                assert!(bytes.is_some(), "{:?}", c);
+                assert_eq!(encoding.decode(&bytes.unwrap()), Some(c.to_string()));
+            }
+        }
+        assert_eq!(Encoding::from_name("utf-16le"), Some(Encoding::Utf16Le));
+        assert_eq!(Encoding::from_name("KOI8-R"), None);
+        assert_eq!(Encoding::Utf16Le.decode(&[0x41]), None);
+        assert_eq!(Encoding::Utf8.decode(&[0xc3]), None);
+        assert_eq!(Encoding::Ascii.decode(&[0x80]), None);
+    }
+
+    #[test]
+    fn fields_decode_to_expected() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let fields = str_fields(&mut rng, 8);
+        assert_eq!(fields.len(), 4 * 3 + 2);
+        for field in fields {
+            let encoding = Encoding::ALL
+                .into_iter()
+                .find(|e| Some(e.name()) == field.attr.encoding.as_deref())
+                .unwrap();
+            let mut content = field.bytes.clone();
+            match (&field.attr.size, field.attr.terminator) {
+                (Some(Expr::Int(size)), None) => assert_eq!(content.len() as u64, *size),
+                (None, terminator) => {
+                    let terminator = terminator.unwrap_or(0);
+                    assert_eq!(content.pop(), Some(terminator));
+                    assert!(!content.contains(&terminator));
+                }
+                _ => unreachable!(),
+            }
+            assert_eq!(encoding.encode(&field.expected), Some(content));
+        }
+    }
+}
@@ -0,0 +1,202 @@
+//! User types read from substreams (attributes with an explicit `size`), with expressions over
+//! `_io.size`, `_io.pos` and `_io.eof` of the substreams and of the root stream.
+
+use rand::Rng;
+
+use crate::ast::utils::{attr, name};
+use crate::ast::{BinaryOp, Expr, UnaryOp};
+use crate::eval::Value;
+use crate::ksy::{Attribute, KsySpec, TypeSpec};
+
+/// Spec reading a substream `block` of `len` bytes, with a `tail` substream nested in it, and
+/// data of several lengths.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SubstreamCase {
+    pub spec: KsySpec,
+    pub variants: Vec<SubstreamData>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SubstreamData {
+    pub data: Vec<u8>,
+    pub assertions: Vec<(Expr, Value)>,
+    /// Attributes not read because their `if` (testing `_io.eof`) is false
+    pub absent: Vec<Expr>,
+}
+
+fn io(value: Expr, attr_name: &str) -> Expr {
+    attr(attr(value, "_io"), attr_name)
+}
+
+fn not_eof() -> Expr {
+    Expr::UnaryOp {
+        op: UnaryOp::Not,
+        value: Box::new(attr(name("_io"), "eof")),
+    }
+}
+
+/// The spec:
+///
+/// - the root reads `len: u1`, `block` of size `len` and `trailer: u1` if not at the end of the
+///   root stream
+/// - `block` reads `head: u1` if not at the end of its substream, then `rest` of type `tail` with
+///   the size `_io.size - _io.pos`, i.e. the rest of the substream
+/// - `tail` reads all of its substream
+///
+/// Each type has value instances with the sizes of its own stream and of the streams around it.
+fn substream_spec(id: &str) -> KsySpec {
+    let mut spec = KsySpec::top_level(id);
+    spec.seq.push(Attribute::new("len", "u1"));
+    spec.seq.push(Attribute {
+        size: Some(name("len")),
+        ..Attribute::new("block", "block")
+    });
+    spec.seq.push(Attribute {
+        if_expr: Some(not_eof()),
+        ..Attribute::new("trailer", "u1")
+    });
+    let root_instances = [
+        ("block_size", io(name("block"), "size")),
+        ("root_size", attr(name("_io"), "size")),
+    ];
+
+    let mut block = TypeSpec::default();
+    block.seq.push(Attribute {
+        if_expr: Some(not_eof()),
+        ..Attribute::new("head", "u1")
+    });
+    block.seq.push(Attribute {
+        size: Some(Expr::BinaryOp {
+            l: Box::new(attr(name("_io"), "size")),
+            op: BinaryOp::Sub,
+            r: Box::new(attr(name("_io"), "pos")),
+        }),
+        ..Attribute::new("rest", "tail")
+    });
+    let block_instances = [
+        ("sub_size", attr(name("_io"), "size")),
+        ("root_size", io(name("_root"), "size")),
+        // `rest` reads up to the end, so this holds once `block` is parsed
+        ("consumed", attr(name("_io"), "eof")),
+    ];
+
+    let mut tail = TypeSpec::default();
+    tail.seq.push(Attribute {
+        id: Some("raw".to_string()),
+        size_eos: Some(true),
+        ..Default::default()
+    });
+    let tail_instances = [
+        ("own_size", attr(name("_io"), "size")),
+        ("parent_size", io(name("_parent"), "size")),
+    ];
+
+    for (instances, type_spec) in [
+        (&tail_instances[..], &mut tail),
+        (&block_instances[..], &mut block),
+        (&root_instances[..], &mut spec),
+    ] {
+        for (instance_name, value) in instances {
+            type_spec.instances.insert(
+                instance_name.to_string(),
+                Attribute::value_instance(value.clone()),
+            );
+        }
+    }
+    spec.types.insert("block".to_string(), block);
+    spec.types.insert("tail".to_string(), tail);
+    spec
+}
+
+fn substream_data(block: &[u8], trailer: Option<u8>) -> SubstreamData {
+    let mut data = vec![block.len() as u8];
+    data.extend(block);
+    data.extend(trailer);
+
+    let int = |x: usize| Value::Int(x as i128);
+    let block_expr = name("block");
+    let rest = attr(block_expr.clone(), "rest");
+    let rest_bytes = block.get(1..).unwrap_or_default();
+    let mut assertions = vec![
+        (name("len"), int(block.len())),
+        (name("block_size"), int(block.len())),
+        (name("root_size"), int(data.len())),
+        (attr(block_expr.clone(), "sub_size"), int(block.len())),
+        (attr(block_expr.clone(), "root_size"), int(data.len())),
+        (attr(block_expr.clone(), "consumed"), Value::Bool(true)),
+        (attr(rest.clone(), "raw"), Value::Bytes(rest_bytes.to_vec())),
+        (attr(rest.clone(), "own_size"), int(rest_bytes.len())),
+        (attr(rest, "parent_size"), int(block.len())),
+    ];
+    let mut absent = Vec::new();
+    let head = attr(block_expr, "head");
+    match block.first() {
+        Some(head_value) => assertions.push((head, Value::Int((*head_value).into()))),
+        None => absent.push(head),
+    }
+    match trailer {
+        Some(trailer) => assertions.push((name("trailer"), Value::Int(trailer.into()))),
+        None => absent.push(name("trailer")),
+    }
+    SubstreamData {
+        data,
+        assertions,
+        absent,
+    }
+}
+
+/// Case with an empty `block` and one of 1 to `max_len` bytes (at most 255), each with and
+/// without a trailing byte after it.
+pub fn substream_case<R: Rng + ?Sized>(rng: &mut R, id: &str, max_len: usize) -> SubstreamCase {
+    let len = rng.gen_range(1..=max_len.min(255));
+    let mut variants = Vec::new();
+    for len in [0, len] {
+        let block: Vec<u8> = (0..len).map(|_| rng.gen()).collect();
+        variants.push(substream_data(&block, None));
+        variants.push(substream_data(&block, Some(rng.gen())));
+    }
+    SubstreamCase {
+        spec: substream_spec(id),
+        variants,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::typing::{infer, KsType, TypeEnv};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn stream_expressions_are_typed() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let case = substream_case(&mut rng, "substream", 8);
+        for type_name in ["block", "tail"] {
+            let type_path = [type_name.to_string()];
+            let env = TypeEnv::from_spec(&case.spec, &type_path).unwrap();
+            for (instance_name, instance) in &case.spec.types[type_name].instances {
+                let ty = infer(instance.value.as_ref().unwrap(), &env).unwrap();
+                let expected = match instance_name.as_str() {
+                    "consumed" => KsType::Bool,
+                    _ => KsType::Int,
+                };
+                assert_eq!(ty.widened(), expected, "{}", instance_name);
+            }
+        }
+    }
+
+    #[test]
+    fn variants() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let case = substream_case(&mut rng, "substream", 8);
+        assert_eq!(case.variants.len(), 4);
+        let empty = &case.variants[0];
+        assert_eq!(empty.data, [0]);
+        assert_eq!(empty.absent.len(), 2);
+        let full = &case.variants[3];
+        assert!(full.absent.is_empty());
+        let root_size = (name("root_size"), Value::Int(full.data.len() as i128));
+        assert!(full.assertions.contains(&root_size));
+    }
+}
@@ -0,0 +1,786 @@
+//! Whole test suites generated from a single seed. Every case records the seed it was generated
+//! from, and [`generate_case`] regenerates exactly the same case (spec, id and data) from it, so
+//! a failing test can be reproduced without regenerating the suite.
+//!
+//! The random numbers come from ChaCha, whose output is fixed for a seed across platforms and
+//! versions of `rand`, unlike [`rand::rngs::StdRng`].
+
+use std::collections::BTreeMap;
+
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::ast::utils::{attr, name};
+use crate::ast::Expr;
+use crate::datagen::interpret::read;
+use crate::datagen::trace::TraceSpan;
+use crate::datagen::{
+    synthesize, synthesize_variants, violation, DataGenError, DataOptions, MAX_VIOLATIONS,
+};
+use crate::eval::{Env, Value};
+use crate::gen::bytes::{bytes_field, BytesForm};
+use crate::gen::cast::cast_case;
+use crate::gen::cond::if_case;
+use crate::gen::contents::{contents_field, ContentsForm};
+use crate::gen::endian::{endian_case, EndianOn};
+use crate::gen::enums::edge_enum_case;
+use crate::gen::eos::{eos_case, TrailingForm};
+use crate::gen::idents::ident_case;
+use crate::gen::imports::imports_case;
+use crate::gen::instances::instances_case;
+use crate::gen::nested::nested_case;
+use crate::gen::opaque::opaque_case;
+use crate::gen::pad::{pad_case, PadForm};
+use crate::gen::pairwise::{combined_case, pairs};
+use crate::gen::params::params_case;
+use crate::gen::pos::pos_case;
+use crate::gen::primitive::primitive_spec;
+use crate::gen::process::{process_case, ProcessKind};
+use crate::gen::profile::{Feature, GenProfile};
+use crate::gen::recursive::{recursive_case, RecursiveShape};
+use crate::gen::repeat::{repeat_case, RepeatMode};
+use crate::gen::string::{str_field, Encoding, StrForm};
+use crate::gen::substream::substream_case;
+use crate::gen::switch::{switch_case, SwitchOn};
+use crate::gen::terminator::{term_case, TermForm, TermOptions};
+use crate::gen::valid::{valid_case, ValidForm};
+use crate::ksy::{Endian, KsySpec};
+use crate::oracle::{check, Verdict};
+use crate::target::Target;
+
+/// Attempts at generating a case of the chosen feature before giving up on the seed
+const ATTEMPTS: usize = 16;
+
+/// Error that parsing the data is expected to fail with.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum ParseError {
+    /// Reading past the end of the stream
+    EndOfStream,
+    /// A `contents` or `valid` check failing
+    Validation,
+    /// A switched `meta/endian` matching no case
+    UndecidedEndianness,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Outcome {
+    /// Parsing succeeds, with the expected values of the expressions
+    Values(Vec<(Expr, Value)>),
+    Error(ParseError),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct GenInput {
+    pub data: Vec<u8>,
+    pub outcome: Outcome,
+    /// Where synthesized data comes from, `None` for data made together with the spec
+    pub source: Option<DataSource>,
+    /// Fields that the bytes of valid synthesized data come from, if the profile asks for it
+    pub trace: Vec<TraceSpan>,
+    /// Whether each target is expected to read the asserted values, by the oracle; empty if
+    /// nothing is asserted
+    pub verdicts: BTreeMap<Target, Verdict>,
+}
+
+/// Provenance of synthesized data: [`regenerate_input`] makes it again from the spec and the
+/// seed of its case alone.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct DataSource {
+    /// Index of the random numbers of the data, see [`data_rng`]
+    pub variant: u64,
+    pub kind: DataKind,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DataKind {
+    /// Data parsing successfully
+    Valid,
+    /// Valid data cut to the length
+    Truncated(usize),
+    /// Data failing the check with the index, see [`violation`]
+    Violation(usize),
+}
+
+/// Test case of one feature, with the inputs to parse with its spec.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GenCase {
+    /// `meta/id` of the spec, `<feature>_<seed in hex>`
+    pub id: String,
+    /// Seed that [`generate_case`] regenerates the case from
+    pub seed: u64,
+    pub feature: Feature,
+    pub spec: KsySpec,
+    /// Specs that `spec` imports or reads as opaque types
+    pub extra_specs: Vec<KsySpec>,
+    pub inputs: Vec<GenInput>,
+}
+
+/// Random numbers for the data of the variant of the case with the seed: a ChaCha stream of its
+/// own, independent of the spec (which the first stream makes) and of the other variants.
+pub fn data_rng(seed: u64, variant: u64) -> ChaCha8Rng {
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+    rng.set_stream(variant + 1);
+    rng
+}
+
+/// Synthesized data of an input of the case, from its spec and seed alone. `None` if the data
+/// can't be made with the profile, which happens only if it isn't the one of the case.
+pub fn regenerate_input(
+    case: &GenCase,
+    source: DataSource,
+    profile: &GenProfile,
+) -> Option<Vec<u8>> {
+    let mut rng = data_rng(case.seed, source.variant);
+    let options = data_options(profile);
+    match source.kind {
+        DataKind::Valid => synthesize(&mut rng, &case.spec, &options)
+            .ok()
+            .map(|s| s.data),
+        DataKind::Truncated(len) => {
+            let data = synthesize(&mut rng, &case.spec, &options).ok()?.data;
+            data.get(..len).map(<[u8]>::to_vec)
+        }
+        DataKind::Violation(check) => violation(&mut rng, &case.spec, &options, check).ok()?,
+    }
+}
+
+fn data_options(profile: &GenProfile) -> DataOptions {
+    DataOptions {
+        max_len: profile.sizes.max_len.max(1),
+        max_items: profile.sizes.max_items.max(1),
+        max_size: profile.sizes.max_data,
+        trace: profile.trace,
+        ..Default::default()
+    }
+}
+
+/// Seeds of the cases of the suite generated from `seed`, in the order of the suite.
+pub fn case_seeds(seed: u64, count: usize) -> Vec<u64> {
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+    let mut seeds: Vec<u64> = Vec::with_capacity(count);
+    while seeds.len() < count {
+        // the ids contain the seeds, so they must be distinct
+        let case_seed = rng.gen();
+        if !seeds.contains(&case_seed) {
+            seeds.push(case_seed);
+        }
+    }
+    seeds
+}
+
+/// Suite of `count` cases (fewer if the profile allows no feature, or the generators give up on
+/// some seeds), the same for the same seed and profile.
+pub fn generate_suite(seed: u64, count: usize, profile: &GenProfile) -> Vec<GenCase> {
+    case_seeds(seed, count)
+        .into_iter()
+        .filter_map(|case_seed| generate_case(case_seed, profile))
+        .collect()
+}
+
+/// Case of a feature chosen by the profile, determined by `seed` alone, or `None` if the profile
+/// allows no feature or the generator fails to produce a case.
+pub fn generate_case(seed: u64, profile: &GenProfile) -> Option<GenCase> {
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+    let feature = profile.choose_feature(&mut rng)?;
+    let id = format!("{}_{:016x}", feature.name(), seed);
+    let (spec, extra_specs, mut inputs) =
+        (0..ATTEMPTS).find_map(|_| feature_case(&mut rng, seed, &id, feature, profile))?;
+    for input in &mut inputs {
+        input.verdicts = verdicts(&spec, input);
+    }
+    Some(GenCase {
+        id,
+        seed,
+        feature,
+        spec,
+        extra_specs,
+        inputs,
+    })
+}
+
+/// Verdicts of the oracle on the expressions asserted for the input, over the fields it reads as:
+/// a target may diverge if it may on any of them.
+fn verdicts(spec: &KsySpec, input: &GenInput) -> BTreeMap<Target, Verdict> {
+    let Outcome::Values(values) = &input.outcome else {
+        return BTreeMap::new();
+    };
+    let mut env = Env::new();
+    // specs that the interpreter doesn't support leave the names unknown
+    for (name, value) in read(spec, &input.data).unwrap_or_default() {
+        env.set(name, value);
+    }
+    let mut verdicts: BTreeMap<Target, Verdict> = Target::ALL
+        .into_iter()
+        .map(|target| (target, Verdict::Match))
+        .collect();
+    for (expr, _) in values {
+        for (target, verdict) in check(expr, &env, &Target::ALL).verdicts {
+            let Verdict::MayDiverge(quirks) = verdict else {
+                continue;
+            };
+            match verdicts.get_mut(&target) {
+                Some(Verdict::MayDiverge(known)) => {
+                    known.extend(quirks);
+                    known.sort();
+                    known.dedup();
+                }
+                Some(known) => *known = Verdict::MayDiverge(quirks),
+                None => {}
+            }
+        }
+    }
+    verdicts
+}
+
+fn values(data: Vec<u8>, values: Vec<(Expr, Value)>) -> GenInput {
+    GenInput {
+        data,
+        outcome: Outcome::Values(values),
+        source: None,
+        trace: Vec::new(),
+        verdicts: BTreeMap::new(),
+    }
+}
+
+fn error(data: Vec<u8>, error: ParseError) -> GenInput {
+    GenInput {
+        data,
+        outcome: Outcome::Error(error),
+        source: None,
+        trace: Vec::new(),
+        verdicts: BTreeMap::new(),
+    }
+}
+
+fn synthesized(input: GenInput, variant: usize, kind: DataKind) -> GenInput {
+    GenInput {
+        source: Some(DataSource {
+            variant: variant as u64,
+            kind,
+        }),
+        ..input
+    }
+}
+
+/// Valid inputs synthesized for the spec, as many as the profile asks for and taking different
+/// branches where possible.
+fn variant_inputs(
+    spec: &KsySpec,
+    seed: u64,
+    profile: &GenProfile,
+) -> Result<Vec<GenInput>, DataGenError> {
+    let options = data_options(profile);
+    let count = profile.sizes.inputs.max(1);
+    let variants = synthesize_variants(spec, &options, count, |i| data_rng(seed, i as u64))?;
+    let inputs = variants
+        .into_iter()
+        .map(|(i, variant)| {
+            let input = GenInput {
+                trace: variant.trace.clone(),
+                ..values(variant.data.clone(), variant.assertions())
+            };
+            synthesized(input, i, DataKind::Valid)
+        })
+        .collect();
+    Ok(inputs)
+}
+
+fn choose<T: Copy, R: Rng + ?Sized>(rng: &mut R, items: &[T]) -> T {
+    *items.choose(rng).expect("choices must not be empty")
+}
+
+type FeatureCase = (KsySpec, Vec<KsySpec>, Vec<GenInput>);
+
+fn feature_case<R: Rng + ?Sized>(
+    rng: &mut R,
+    seed: u64,
+    id: &str,
+    feature: Feature,
+    profile: &GenProfile,
+) -> Option<FeatureCase> {
+    let sizes = profile.sizes;
+    let max_len = sizes.max_len.max(1);
+    let max_depth = sizes.max_depth.max(1);
+    let max_items = sizes.max_items.max(1);
+    let mut case = match feature {
+        Feature::Bytes => {
+            let form = choose(rng, &BytesForm::ALL);
+            let field = bytes_field(rng, "value", form, max_len.min(255) as u8);
+            let mut spec = KsySpec::top_level(id);
+            spec.seq = field.attrs;
+            let input = values(
+                field.bytes,
+                vec![(name("value"), Value::Bytes(field.expected))],
+            );
+            (spec, vec![], vec![input])
+        }
+        Feature::Cast => {
+            let case = cast_case(rng, id, max_items, max_depth);
+            (case.spec, vec![], vec![values(case.data, case.assertions)])
+        }
+        Feature::Cond => {
+            let case = if_case(rng, id, max_depth)?;
+            let mut inputs: Vec<GenInput> = case
+                .variants
+                .into_iter()
+                .map(|variant| values(variant.data, variant.assertions))
+                .collect();
+            inputs.extend(variant_inputs(&case.spec, seed, profile).unwrap_or_default());
+            (case.spec, vec![], inputs)
+        }
+        Feature::Contents => {
+            let form = choose(rng, &ContentsForm::ALL);
+            let field = contents_field(rng, "magic", form, max_len.max(2), false);
+            let mut mismatch = field.bytes.clone();
+            let at = rng.gen_range(0..mismatch.len());
+            mismatch[at] ^= rng.gen_range(1..=u8::MAX);
+            let mut spec = KsySpec::top_level(id);
+            spec.seq = vec![field.attr];
+            let expected = vec![(name("magic"), Value::Bytes(field.bytes.clone()))];
+            let inputs = vec![
+                values(field.bytes, expected),
+                error(mismatch, ParseError::Validation),
+            ];
+            (spec, vec![], inputs)
+        }
+        Feature::Endian => {
+            let on = choose(rng, &EndianOn::ALL);
+            let with_default = rng.gen();
+            let case = endian_case(rng, id, on, with_default);
+            let inputs = case
+                .variants
+                .into_iter()
+                .map(|variant| match variant.endian {
+                    Some(_) => values(variant.data, variant.assertions),
+                    None => error(variant.data, ParseError::UndecidedEndianness),
+                })
+                .collect();
+            (case.spec, vec![], inputs)
+        }
+        Feature::Enums => {
+            let case = edge_enum_case(id);
+            (case.spec, vec![], vec![values(case.data, case.assertions)])
+        }
+        Feature::Eos => {
+            let form = choose(rng, &TrailingForm::ALL);
+            let case = eos_case(rng, id, form, max_items);
+            let inputs = case
+                .variants
+                .into_iter()
+                .map(|variant| match variant.expected {
+                    Some(tail) => values(variant.data, vec![(name("tail"), tail)]),
+                    None => error(variant.data, ParseError::EndOfStream),
+                })
+                .collect();
+            (case.spec, vec![], inputs)
+        }
+        Feature::Idents => {
+            let count = rng.gen_range(2..=max_items.max(2));
+            let case = ident_case(rng, id, &Target::ALL, count)?;
+            (case.spec, vec![], vec![values(case.data, case.assertions)])
+        }
+        Feature::Imports => {
+            let case = imports_case(rng, id);
+            let main = case.main;
+            (
+                main.spec,
+                case.imports,
+                vec![values(main.data, main.assertions)],
+            )
+        }
+        Feature::Instances => {
+            let case = instances_case(rng, id);
+            (case.spec, vec![], vec![values(case.data, case.assertions)])
+        }
+        Feature::Nested => {
+            let depth = rng.gen_range(1..=max_depth);
+            let case = nested_case(rng, id, depth);
+            (case.spec, vec![], vec![values(case.data, case.assertions)])
+        }
+        Feature::Opaque => {
+            let case = opaque_case(rng, id, max_len);
+            let stubs = case.stubs.iter().map(|stub| stub.spec()).collect();
+            let main = case.main;
+            (main.spec, stubs, vec![values(main.data, main.assertions)])
+        }
+        Feature::Pad => {
+            let form = choose(rng, &PadForm::ALL);
+            let with_terminator = rng.gen();
+            let case = pad_case(rng, id, form, with_terminator, max_len);
+            let inputs = case
+                .variants
+                .into_iter()
+                .map(|variant| values(variant.data, variant.assertions))
+                .collect();
+            (case.spec, vec![], inputs)
+        }
+        Feature::Pairwise => {
+            let (a, b) = choose(rng, &pairs());
+            let case = combined_case(rng, id, &[a, b], max_items)?.main;
+            (case.spec, vec![], vec![values(case.data, case.assertions)])
+        }
+        Feature::Params => {
+            let case = params_case(rng, id);
+            (case.spec, vec![], vec![values(case.data, case.assertions)])
+        }
+        Feature::Pos => {
+            let case = pos_case(rng, id, max_items, max_depth)?;
+            (case.spec, vec![], vec![values(case.data, case.assertions)])
+        }
+        Feature::Primitive => {
+            let endian = choose(rng, &[Endian::Le, Endian::Be]);
+            let spec = primitive_spec(id, endian);
+            let inputs = variant_inputs(&spec, seed, profile).ok()?;
+            (spec, vec![], inputs)
+        }
+        Feature::Process => {
+            let kind = choose(rng, &ProcessKind::ALL);
+            let key_from_expr = rng.gen();
+            let case = process_case(rng, id, kind, key_from_expr, max_len);
+            let expected = vec![(name("buf"), Value::Bytes(case.expected))];
+            (case.spec, vec![], vec![values(case.data, expected)])
+        }
+        Feature::Recursive => {
+            let shape = choose(rng, &RecursiveShape::ALL);
+            let case = recursive_case(rng, id, shape, max_depth);
+            (case.spec, vec![], vec![values(case.data, case.assertions)])
+        }
+        Feature::Repeat => {
+            let mode = choose(rng, &RepeatMode::ALL);
+            let case = repeat_case(rng, id, mode, max_len);
+            let items = case
+                .items
+                .iter()
+                .map(|item| Value::Int((*item).into()))
+                .collect();
+            let expected = vec![(name("items"), Value::Array(items))];
+            let mut inputs = vec![values(case.data, expected)];
+            inputs.extend(variant_inputs(&case.spec, seed, profile).unwrap_or_default());
+            (case.spec, vec![], inputs)
+        }
+        Feature::String => {
+            let encoding = choose(rng, &Encoding::ALL);
+            let form = choose(rng, &StrForm::ALL);
+            let field = str_field(rng, "value", encoding, form, max_len)?;
+            let mut spec = KsySpec::top_level(id);
+            spec.seq = vec![field.attr];
+            let input = values(
+                field.bytes,
+                vec![(name("value"), Value::Str(field.expected))],
+            );
+            (spec, vec![], vec![input])
+        }
+        Feature::Substream => {
+            let case = substream_case(rng, id, max_len);
+            let inputs = case
+                .variants
+                .into_iter()
+                .map(|variant| values(variant.data, variant.assertions))
+                .collect();
+            (case.spec, vec![], inputs)
+        }
+        Feature::Switch => {
+            let on = choose(rng, &SwitchOn::ALL);
+            let with_default = rng.gen();
+            let case = switch_case(rng, id, on, with_default);
+            let body_value = attr(name("body"), "value");
+            let mut inputs: Vec<GenInput> = case
+                .variants
+                .into_iter()
+                .map(|variant| {
+                    let expected = variant
+                        .value
+                        .map(|value| (body_value.clone(), Value::Int(value)));
+                    values(variant.data, expected.into_iter().collect())
+                })
+                .collect();
+            inputs.extend(variant_inputs(&case.spec, seed, profile).unwrap_or_default());
+            (case.spec, vec![], inputs)
+        }
+        Feature::Terminator => {
+            let form = choose(rng, &TermForm::ALL);
+            let options = choose(rng, &TermOptions::all());
+            let case = term_case(rng, id, form, options, max_len);
+            let inputs = [case.hit, case.miss]
+                .into_iter()
+                .map(|term| match term.expected {
+                    Some((value, after)) => values(
+                        term.data,
+                        vec![(name("value"), value), (name("after"), Value::Bytes(after))],
+                    ),
+                    None => error(term.data, ParseError::EndOfStream),
+                })
+                .collect();
+            (case.spec, vec![], inputs)
+        }
+        Feature::Valid => {
+            let form = choose(rng, &ValidForm::ALL);
+            let case = valid_case(rng, id, form)?;
+            let pass = values(
+                case.pass.clone(),
+                vec![(name("value"), Value::Int(case.pass[1].into()))],
+            );
+            let fail = error(case.fail, ParseError::Validation);
+            (case.spec, vec![], vec![pass, fail])
+        }
+    };
+    if profile.negatives.truncated {
+        let options = data_options(profile);
+        // the variants of the valid synthesized inputs, or a new one for data made with the spec
+        let mut variants: Vec<u64> = case
+            .2
+            .iter()
+            .filter_map(|input| match input.source {
+                Some(DataSource {
+                    variant,
+                    kind: DataKind::Valid,
+                }) => Some(variant),
+                _ => None,
+            })
+            .collect();
+        if variants.is_empty() {
+            variants.push(0);
+        }
+        for variant in variants {
+            let mut rng = data_rng(seed, variant);
+            // specs that the data generator doesn't support get none
+            let Ok(valid) = synthesize(&mut rng, &case.0, &options) else {
+                break;
+            };
+            for truncated in valid.truncations() {
+                let kind = DataKind::Truncated(truncated.len());
+                let input = error(truncated, ParseError::EndOfStream);
+                case.2.push(synthesized(input, variant as usize, kind));
+            }
+        }
+    }
+    if profile.negatives.violations {
+        let options = data_options(profile);
+        for check in 0..MAX_VIOLATIONS {
+            let mut rng = data_rng(seed, check as u64);
+            match violation(&mut rng, &case.0, &options, check) {
+                Ok(Some(data)) => {
+                    let input = error(data, ParseError::Validation);
+                    case.2
+                        .push(synthesized(input, check, DataKind::Violation(check)));
+                }
+                // no value fails the check
+                Err(DataGenError::Unsatisfiable(_)) => {}
+                // specs that the data generator doesn't support get none
+                Ok(None) | Err(_) => break,
+            }
+        }
+    }
+    Some(case)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::divergence::Quirk;
+    use crate::gen::check::check_case;
+    use crate::gen::naming::Namer;
+    use crate::schema::Schema;
+    use std::collections::HashSet;
+
+    #[test]
+    fn same_seed_same_suite() {
+        let profile = GenProfile::default();
+        let suite = generate_suite(7, 100, &profile);
+        assert_eq!(suite.len(), 100);
+        assert_eq!(suite, generate_suite(7, 100, &profile));
+        let yaml: Vec<String> = suite.iter().map(|case| case.spec.to_yaml()).collect();
+        let again: Vec<String> = generate_suite(7, 100, &profile)
+            .iter()
+            .map(|case| case.spec.to_yaml())
+            .collect();
+        assert_eq!(yaml, again);
+        assert_ne!(suite, generate_suite(8, 100, &profile));
+        // every feature shows up in a large enough suite
+        for feature in Feature::ALL {
+            assert!(
+                suite.iter().any(|case| case.feature == feature),
+                "{:?}",
+                feature
+            );
+        }
+    }
+
+    #[test]
+    fn reproduce_from_case_seed() {
+        let profile = GenProfile::default();
+        for case in generate_suite(1, 30, &profile) {
+            assert_eq!(
+                case.id,
+                format!("{}_{:016x}", case.feature.name(), case.seed)
+            );
+            assert_eq!(case.spec.id(), Some(case.id.as_str()));
+            assert_eq!(generate_case(case.seed, &profile), Some(case));
+        }
+    }
+
+    #[test]
+    fn ids_are_safe() {
+        let mut namer = Namer::new();
+        for case in generate_suite(2, 200, &GenProfile::default()) {
+            namer.claim(&case.id).unwrap();
+        }
+    }
+
+    #[test]
+    fn stable_seeds() {
+        // ChaCha output is fixed, so recorded seeds stay valid across builds
+        assert_eq!(case_seeds(0, 2), case_seeds(0, 3)[..2]);
+        let seeds = case_seeds(0, 2);
+        assert_eq!(seeds, [13080132717333068652, 8594738769458413623]);
+    }
+
+    #[test]
+    fn profile_restricts_features() {
+        let profile = GenProfile::from_toml_str("[features]\nvalid = 1\nprimitive = 2\n").unwrap();
+        for case in generate_suite(3, 20, &profile) {
+            assert!(matches!(case.feature, Feature::Valid | Feature::Primitive));
+            assert!(!case.inputs.is_empty());
+        }
+        let nothing = GenProfile::from_toml_str("[features]\n").unwrap();
+        assert!(generate_suite(3, 5, &nothing).is_empty());
+    }
+
+    #[test]
+    fn violation_negatives() {
+        let toml = "[features]\nvalid = 1\ncontents = 1\n[negatives]\nviolations = true\n";
+        let profile = GenProfile::from_toml_str(toml).unwrap();
+        for case in generate_suite(5, 10, &profile) {
+            let errors = case
+                .inputs
+                .iter()
+                .filter(|input| input.outcome == Outcome::Error(ParseError::Validation))
+                .count();
+            // the hand-made violation and at least one synthesized
+            assert!(errors >= 2, "{}", case.id);
+        }
+    }
+
+    #[test]
+    fn several_inputs() {
+        let toml = "[features]\nprimitive = 1\n[sizes]\ninputs = 3\n";
+        let profile = GenProfile::from_toml_str(toml).unwrap();
+        for case in generate_suite(9, 3, &profile) {
+            assert_eq!(case.inputs.len(), 3);
+            assert_ne!(case.inputs[0].data, case.inputs[1].data);
+            assert_ne!(case.inputs[1].data, case.inputs[2].data);
+            assert!(case
+                .inputs
+                .iter()
+                .all(|input| matches!(input.outcome, Outcome::Values(_))));
+        }
+    }
+
+    #[test]
+    fn synthesized_variants_of_branching_features() {
+        let toml = "[features]\ncond = 1\nrepeat = 1\nswitch = 1\n[sizes]\ninputs = 3\n";
+        let profile = GenProfile::from_toml_str(toml).unwrap();
+        let schema = Schema::bundled();
+        let mut features = HashSet::new();
+        for case in generate_suite(17, 30, &profile) {
+            let variants: Vec<&GenInput> = case
+                .inputs
+                .iter()
+                .filter(|input| input.source.is_some())
+                .collect();
+            assert!(variants.len() <= 3);
+            if variants.len() > 1 {
+                assert_ne!(variants[0].data, variants[1].data);
+                features.insert(case.feature);
+            }
+            for input in variants {
+                let data = regenerate_input(&case, input.source.unwrap(), &profile);
+                assert_eq!(data.as_ref(), Some(&input.data));
+            }
+            assert_eq!(check_case(&case, &profile, &schema), Ok(()), "{}", case.id);
+        }
+        assert_eq!(features.len(), 3, "{:?}", features);
+    }
+
+    #[test]
+    fn oracle_verdicts() {
+        let profile = GenProfile::from_toml_str("[features]\nprimitive = 1\n").unwrap();
+        let mut tagged = 0;
+        for case in generate_suite(21, 5, &profile) {
+            let input = &case.inputs[0];
+            let Outcome::Values(values) = &input.outcome else {
+                unreachable!()
+            };
+            // only doubles hold the integers of JavaScript
+            let unsafe_int = values
+                .iter()
+                .any(|(_, value)| matches!(value, Value::Int(x) if x.unsigned_abs() >= 1 << 53));
+            let javascript = &input.verdicts[&Target::JavaScript];
+            assert_eq!(javascript.tag() == "may-diverge", unsafe_int);
+            if unsafe_int {
+                let Verdict::MayDiverge(quirks) = javascript else {
+                    unreachable!()
+                };
+                assert!(quirks.contains(&Quirk::JsFloatOnlyInts));
+                tagged += 1;
+            }
+            assert_eq!(input.verdicts[&Target::Python], Verdict::Match);
+        }
+        assert!(tagged > 0);
+    }
+
+    #[test]
+    fn regenerated_inputs() {
+        let toml = "[features]\nprimitive = 1\n[sizes]\ninputs = 3\n\
+                    [negatives]\ntruncated = true\nviolations = true\n";
+        let profile = GenProfile::from_toml_str(toml).unwrap();
+        for case in generate_suite(11, 3, &profile) {
+            for input in &case.inputs {
+                let source = input.source.unwrap();
+                let data = regenerate_input(&case, source, &profile);
+                assert_eq!(data.as_ref(), Some(&input.data), "{:?}", source);
+            }
+        }
+    }
+
+    #[test]
+    fn truncated_negatives_of_every_feature() {
+        let toml = "[negatives]\ntruncated = true\n";
+        let profile = GenProfile::from_toml_str(toml).unwrap();
+        let schema = Schema::bundled();
+        let mut features = HashSet::new();
+        for case in generate_suite(13, 60, &profile) {
+            for input in &case.inputs {
+                if let Some(DataSource {
+                    kind: DataKind::Truncated(_),
+                    ..
+                }) = input.source
+                {
+                    assert_eq!(input.outcome, Outcome::Error(ParseError::EndOfStream));
+                    features.insert(case.feature);
+                }
+            }
+            assert_eq!(check_case(&case, &profile, &schema), Ok(()), "{}", case.id);
+        }
+        assert!(features.len() > 5, "{:?}", features);
+    }
+
+    #[test]
+    fn truncated_negatives() {
+        let toml = "[features]\nprimitive = 1\n[negatives]\ntruncated = true\n";
+        let profile = GenProfile::from_toml_str(toml).unwrap();
+        for case in generate_suite(5, 3, &profile) {
+            let (valid, truncated) = case.inputs.split_first().unwrap();
+            assert!(matches!(valid.outcome, Outcome::Values(_)));
+            assert!(!truncated.is_empty());
+            for input in truncated {
+                assert_eq!(input.outcome, Outcome::Error(ParseError::EndOfStream));
+                assert!(valid.data.starts_with(&input.data));
+                assert!(input.data.len() < valid.data.len());
+            }
+        }
+    }
+}
@@ -0,0 +1,247 @@
+//! Attributes of type `switch-on` over integers, enums and strings, with data taking each branch
+//! and data matching no case.
+
+use indexmap::IndexMap;
+use rand::seq::IteratorRandom;
+use rand::Rng;
+
+use crate::ast::Expr;
+use crate::ksy::{Attribute, Endian, KsySpec, TypeRef, TypeSpec};
+use crate::numeric::IntType;
+
+/// Key of the default case
+const DEFAULT_CASE: &str = "_";
+const ENUM_NAME: &str = "selector_kind";
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum SwitchOn {
+    /// `u1` selector
+    Int,
+    /// `u1` selector with an enum
+    Enum,
+    /// ASCII `strz` selector
+    Str,
+}
+
+impl SwitchOn {
+    pub const ALL: [SwitchOn; 3] = [SwitchOn::Int, SwitchOn::Enum, SwitchOn::Str];
+
+    fn name(self) -> &'static str {
+        match self {
+            SwitchOn::Int => "int",
+            SwitchOn::Enum => "enum",
+            SwitchOn::Str => "str",
+        }
+    }
+}
+
+/// Spec whose `body` attribute switches on the `selector` attribute before it, with data for each
+/// of its branches.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SwitchCase {
+    pub spec: KsySpec,
+    pub variants: Vec<SwitchData>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SwitchData {
+    pub data: Vec<u8>,
+    /// Type that `body` gets parsed as, `None` if no case matches and there's no default case
+    pub branch: Option<String>,
+    /// `body.value` if `body` is parsed
+    pub value: Option<i128>,
+}
+
+/// Selector value in the data and its case key.
+fn selector_value<R: Rng + ?Sized>(rng: &mut R, on: SwitchOn, i: usize) -> (Vec<u8>, Expr) {
+    match on {
+        SwitchOn::Int => {
+            let x: u8 = rng.gen();
+            (vec![x], Expr::Int(x.into()))
+        }
+        // enum values are assigned in the order of the cases
+        SwitchOn::Enum => (
+            vec![i as u8],
+            Expr::EnumMember {
+                enum_path: vec![ENUM_NAME.to_string()],
+                label: format!("kind_{}", i),
+            },
+        ),
+        SwitchOn::Str => {
+            let len = rng.gen_range(1..=4);
+            let s: String = (0..len).map(|_| ('a'..='z').choose(rng).unwrap()).collect();
+            let mut data = s.clone().into_bytes();
+            data.push(0);
+            (data, Expr::Str(s))
+        }
+    }
+}
+
+/// Switch over a selector of the given kind, with 1 to 4 cases besides the default one.
+pub fn switch_case<R: Rng + ?Sized>(
+    rng: &mut R,
+    id: &str,
+    on: SwitchOn,
+    with_default: bool,
+) -> SwitchCase {
+    let mut spec = KsySpec::top_level(id);
+    spec.meta.as_mut().unwrap().endian = Some(Endian::Le.into());
+    spec.seq.push(match on {
+        SwitchOn::Int => Attribute::new("selector", "u1"),
+        SwitchOn::Enum => Attribute {
+            enum_name: Some(ENUM_NAME.to_string()),
+            ..Attribute::new("selector", "u1")
+        },
+        SwitchOn::Str => Attribute {
+            encoding: Some("ASCII".to_string()),
+            ..Attribute::new("selector", "strz")
+        },
+    });
+
+    // each branch reads an integer of a different width, so a wrong branch shows in the result
+    let mut branch_types = IntType::ALL.iter().filter(|ty| !ty.signed).cycle();
+    let mut cases = IndexMap::new();
+    let mut selectors = Vec::new();
+    let count = rng.gen_range(1..=4);
+    while cases.len() < count {
+        let (data, key) = selector_value(rng, on, cases.len());
+        if cases.contains_key(&key) {
+            continue;
+        }
+        let type_name = format!("branch_{}", cases.len());
+        spec.types.insert(
+            type_name.clone(),
+            branch_type(*branch_types.next().unwrap()),
+        );
+        cases.insert(key, type_name.clone());
+        selectors.push((data, Some(type_name)));
+    }
+    if on == SwitchOn::Enum {
+        // one more member that no case mentions
+        let members = (0..=count).map(|i| (i as i128, format!("kind_{}", i)));
+        spec.enums.insert(ENUM_NAME.to_string(), members.collect());
+    }
+    let default = with_default.then(|| {
+        spec.types
+            .insert("branch_default".to_string(), branch_type(IntType::ALL[0]));
+        "branch_default".to_string()
+    });
+    if let Some(default) = &default {
+        cases.insert(Expr::Name(DEFAULT_CASE.to_string()), default.clone());
+    }
+    loop {
+        let (data, key) = selector_value(rng, on, count);
+        if !cases.contains_key(&key) {
+            selectors.push((data, default));
+            break;
+        }
+    }
+
+    spec.seq.push(Attribute {
+        id: Some("body".to_string()),
+        type_ref: Some(TypeRef::Switch {
+            switch_on: Expr::Name("selector".to_string()),
+            cases,
+        }),
+        ..Default::default()
+    });
+
+    let variants = selectors
+        .into_iter()
+        .map(|(mut data, branch)| {
+            let value = branch.as_ref().map(|branch| {
+                let value_type = spec.types[branch].seq[0].type_ref.clone();
+                let Some(TypeRef::Named(value_type)) = value_type else {
+                    unreachable!()
+                };
+                let value_type = IntType::from_name(&value_type).unwrap();
+                let value = rng.gen_range(0..=value_type.max_value());
+                data.extend(&(value as u64).to_le_bytes()[..usize::from(value_type.width)]);
+                value
+            });
+            SwitchData {
+                data,
+                branch,
+                value,
+            }
+        })
+        .collect();
+    SwitchCase { spec, variants }
+}
+
+fn branch_type(value_type: IntType) -> TypeSpec {
+    TypeSpec {
+        seq: vec![Attribute::new("value", value_type.name())],
+        ..Default::default()
+    }
+}
+
+/// Cases for every [`SwitchOn`] kind, with and without a default case, with ids like
+/// `switch_enum_default`.
+pub fn switch_cases<R: Rng + ?Sized>(rng: &mut R) -> Vec<SwitchCase> {
+    let mut cases = Vec::new();
+    for on in SwitchOn::ALL {
+        for with_default in [false, true] {
+            let suffix = if with_default { "_default" } else { "" };
+            let id = format!("switch_{}{}", on.name(), suffix);
+            cases.push(switch_case(rng, &id, on, with_default));
+        }
+    }
+    cases
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    /// Finds the branch for the selector at the start of `data` by looking the selector up in the
+    /// cases, like a runtime would.
+    fn select(spec: &KsySpec, data: &[u8]) -> Option<String> {
+        let Some(TypeRef::Switch { cases, .. }) = &spec.seq[1].type_ref else {
+            unreachable!()
+        };
+        let key = match (&spec.seq[0].type_ref, &spec.seq[0].enum_name) {
+            (Some(TypeRef::Named(name)), None) if name == "strz" => {
+                let end = data.iter().position(|&b| b == 0).unwrap();
+                Expr::Str(String::from_utf8(data[..end].to_vec()).unwrap())
+            }
+            (_, None) => Expr::Int(data[0].into()),
+            (_, Some(enum_name)) => match spec.enums[enum_name].get(&i128::from(data[0])) {
+                Some(label) => Expr::EnumMember {
+                    enum_path: vec![enum_name.clone()],
+                    label: label.clone(),
+                },
+                None => Expr::Int(data[0].into()),
+            },
+        };
+        cases
+            .get(&key)
+            .or_else(|| cases.get(&Expr::Name(DEFAULT_CASE.to_string())))
+            .cloned()
+    }
+
+    #[test]
+    fn variants_take_every_branch() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for case in switch_cases(&mut rng) {
+            let Some(TypeRef::Switch { cases, .. }) = &case.spec.seq[1].type_ref else {
+                unreachable!()
+            };
+            let default_key = Expr::Name(DEFAULT_CASE.to_string());
+            let explicit = cases.keys().filter(|key| **key != default_key).count();
+            assert_eq!(case.variants.len(), explicit + 1);
+            for variant in &case.variants {
+                assert_eq!(select(&case.spec, &variant.data), variant.branch);
+                assert_eq!(variant.branch.is_some(), variant.value.is_some());
+            }
+            // the last variant matches no case
+            let last = case.variants.last().unwrap();
+            assert_eq!(
+                last.branch.as_deref(),
+                cases.get(&default_key).map(String::as_str)
+            );
+        }
+    }
+}
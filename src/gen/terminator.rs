@@ -0,0 +1,253 @@
+//! The matrix of `terminator`, `consume`, `include` and `eos-error` on byte arrays, `str` and
+//! `strz`, with data in which the terminator is found and data in which it's missing.
+
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+use crate::eval::Value;
+use crate::ksy::{Attribute, KsySpec};
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum TermForm {
+    Bytes,
+    /// ASCII `str` with a printable terminator
+    Str,
+    /// ASCII `strz`, terminated by a zero byte
+    Strz,
+}
+
+impl TermForm {
+    pub const ALL: [TermForm; 3] = [TermForm::Bytes, TermForm::Str, TermForm::Strz];
+
+    fn name(self) -> &'static str {
+        match self {
+            TermForm::Bytes => "bytes",
+            TermForm::Str => "str",
+            TermForm::Strz => "strz",
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct TermOptions {
+    pub consume: bool,
+    pub include: bool,
+    pub eos_error: bool,
+}
+
+impl TermOptions {
+    /// All 8 combinations.
+    pub fn all() -> Vec<TermOptions> {
+        let mut all = Vec::new();
+        for consume in [true, false] {
+            for include in [false, true] {
+                for eos_error in [true, false] {
+                    all.push(TermOptions {
+                        consume,
+                        include,
+                        eos_error,
+                    });
+                }
+            }
+        }
+        all
+    }
+}
+
+/// Spec reading the terminated attribute `value` and then the rest of the stream as `after`,
+/// with data in which the terminator is found (`hit`) and in which it isn't (`miss`).
+#[derive(Clone, Debug, PartialEq)]
+pub struct TermCase {
+    pub spec: KsySpec,
+    pub hit: TermData,
+    pub miss: TermData,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TermData {
+    pub data: Vec<u8>,
+    /// Expected values of `value` and `after`, `None` if reading `value` fails at the end of the
+    /// stream
+    pub expected: Option<(Value, Vec<u8>)>,
+}
+
+/// Expected value of `value` and `after` if reading stops at a terminator at `end` (or at the
+/// end of the data).
+fn expected(form: TermForm, options: TermOptions, data: &[u8], end: Option<usize>) -> TermData {
+    let (value, after) = match end {
+        Some(end) => {
+            let value_end = if options.include { end + 1 } else { end };
+            let after_start = if options.consume { end + 1 } else { end };
+            (&data[..value_end], &data[after_start..])
+        }
+        None if options.eos_error => {
+            return TermData {
+                data: data.to_vec(),
+                expected: None,
+            }
+        }
+        None => (data, &data[data.len()..]),
+    };
+    let value = match form {
+        TermForm::Bytes => Value::Bytes(value.to_vec()),
+        TermForm::Str | TermForm::Strz => {
+            Value::Str(String::from_utf8(value.to_vec()).expect("terminated strings must be ASCII"))
+        }
+    };
+    TermData {
+        data: data.to_vec(),
+        expected: Some((value, after.to_vec())),
+    }
+}
+
+/// Case for one cell of the matrix, with up to `max_len` bytes before the terminator and after
+/// it. Every option is spelled out, including the ones with default values.
+pub fn term_case<R: Rng + ?Sized>(
+    rng: &mut R,
+    id: &str,
+    form: TermForm,
+    options: TermOptions,
+    max_len: usize,
+) -> TermCase {
+    let printable: Vec<u8> = (b' '..=b'~').collect();
+    let terminator = match form {
+        TermForm::Bytes => rng.gen(),
+        TermForm::Str => *printable.choose(rng).unwrap(),
+        TermForm::Strz => 0,
+    };
+    let alphabet: Vec<u8> = match form {
+        TermForm::Bytes => (0..=255).filter(|b| *b != terminator).collect(),
+        TermForm::Str | TermForm::Strz => {
+            printable.into_iter().filter(|b| *b != terminator).collect()
+        }
+    };
+    let (before_len, after_len) = (rng.gen_range(0..=max_len), rng.gen_range(0..=max_len));
+    let mut random_bytes =
+        |len: usize| -> Vec<u8> { (0..len).map(|_| *alphabet.choose(rng).unwrap()).collect() };
+    let before = random_bytes(before_len);
+    let after = random_bytes(after_len);
+
+    let mut hit = before.clone();
+    hit.push(terminator);
+    // a second terminator shows whether the reading stops at the first one
+    if rng.gen() {
+        hit.push(terminator);
+    }
+    hit.extend(&after);
+    let mut miss = before;
+    miss.extend(after);
+
+    let mut value = match form {
+        TermForm::Bytes => Attribute {
+            id: Some("value".to_string()),
+            terminator: Some(terminator),
+            ..Default::default()
+        },
+        TermForm::Str => Attribute {
+            terminator: Some(terminator),
+            ..Attribute::new("value", "str")
+        },
+        TermForm::Strz => Attribute::new("value", "strz"),
+    };
+    if form != TermForm::Bytes {
+        value.encoding = Some("ASCII".to_string());
+    }
+    value.consume = Some(options.consume);
+    value.include = Some(options.include);
+    value.eos_error = Some(options.eos_error);
+
+    let mut spec = KsySpec::top_level(id);
+    spec.seq.push(value);
+    spec.seq.push(Attribute {
+        id: Some("after".to_string()),
+        size_eos: Some(true),
+        ..Default::default()
+    });
+    TermCase {
+        spec,
+        hit: expected(form, options, &hit, Some(before_len)),
+        miss: expected(form, options, &miss, None),
+    }
+}
+
+/// Cases for the whole matrix, with ids like `term_strz_consume_include_eos_error` (listing the
+/// options that are on).
+pub fn term_cases<R: Rng + ?Sized>(rng: &mut R, max_len: usize) -> Vec<TermCase> {
+    let mut cases = Vec::new();
+    for form in TermForm::ALL {
+        for options in TermOptions::all() {
+            let mut id = format!("term_{}", form.name());
+            for (on, option) in [
+                (options.consume, "consume"),
+                (options.include, "include"),
+                (options.eos_error, "eos_error"),
+            ] {
+                if on {
+                    id.push('_');
+                    id.push_str(option);
+                }
+            }
+            cases.push(term_case(rng, &id, form, options, max_len));
+        }
+    }
+    cases
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn options() {
+        let data = b"ab|c";
+        let term = |consume, include, eos_error| {
+            let options = TermOptions {
+                consume,
+                include,
+                eos_error,
+            };
+            expected(TermForm::Bytes, options, data, Some(2)).expected
+        };
+        let bytes = |b: &[u8]| Value::Bytes(b.to_vec());
+        assert_eq!(term(true, false, true), Some((bytes(b"ab"), b"c".to_vec())));
+        assert_eq!(term(true, true, true), Some((bytes(b"ab|"), b"c".to_vec())));
+        assert_eq!(
+            term(false, false, true),
+            Some((bytes(b"ab"), b"|c".to_vec()))
+        );
+        assert_eq!(
+            term(false, true, false),
+            Some((bytes(b"ab|"), b"|c".to_vec()))
+        );
+    }
+
+    #[test]
+    fn matrix() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let cases = term_cases(&mut rng, 8);
+        assert_eq!(cases.len(), 3 * 8);
+        for case in &cases {
+            let id = case.spec.id().unwrap();
+            assert!(case.hit.expected.is_some(), "{}", id);
+            assert_eq!(
+                case.miss.expected.is_none(),
+                id.ends_with("_eos_error"),
+                "{}",
+                id
+            );
+            if let Some((value, after)) = &case.miss.expected {
+                assert!(after.is_empty());
+                let len = match value {
+                    Value::Bytes(bytes) => bytes.len(),
+                    Value::Str(s) => s.len(),
+                    _ => unreachable!(),
+                };
+                assert_eq!(len, case.miss.data.len());
+            }
+        }
+        let yaml = cases[0].spec.to_yaml();
+        assert!(yaml.contains("  consume: true\n  include: false\n  eos-error: true\n"));
+    }
+}
@@ -0,0 +1,293 @@
+//! Specs exercising every form of the `valid` key, each with one data file that passes the
+//! validation and one that fails it.
+
+use rand::seq::{IteratorRandom, SliceRandom};
+use rand::Rng;
+
+use crate::ast::{BinaryOp, Expr};
+use crate::eval::{eval, Env, EvalError, Value};
+use crate::ksy::{Attribute, KsySpec, Valid, ValidChecks};
+
+/// Name of the value being validated in `valid/expr`
+const SELF_NAME: &str = "_";
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum ValidForm {
+    /// `valid: <expr>`
+    Short,
+    Eq,
+    Min,
+    Max,
+    /// Both `min` and `max`
+    Range,
+    AnyOf,
+    /// `valid/expr` referring to an attribute read before the validated one
+    Expr,
+}
+
+impl ValidForm {
+    pub const ALL: [ValidForm; 7] = [
+        ValidForm::Short,
+        ValidForm::Eq,
+        ValidForm::Min,
+        ValidForm::Max,
+        ValidForm::Range,
+        ValidForm::AnyOf,
+        ValidForm::Expr,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            ValidForm::Short => "short",
+            ValidForm::Eq => "eq",
+            ValidForm::Min => "min",
+            ValidForm::Max => "max",
+            ValidForm::Range => "range",
+            ValidForm::AnyOf => "any_of",
+            ValidForm::Expr => "expr",
+        }
+    }
+}
+
+/// Spec with a validated `u1` attribute `value`, preceded by a `u1` attribute `base` that the
+/// validation may refer to, and data for both outcomes.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ValidCase {
+    pub spec: KsySpec,
+    pub pass: Vec<u8>,
+    pub fail: Vec<u8>,
+}
+
+/// Check of a `valid` key, in the order runtimes make them.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum ValidCheck {
+    Eq,
+    Min,
+    Max,
+    AnyOf,
+    Expr,
+}
+
+impl ValidCheck {
+    /// Name of the exception that runtimes throw when the check fails.
+    pub fn exception_name(self) -> &'static str {
+        match self {
+            ValidCheck::Eq => "ValidationNotEqualError",
+            ValidCheck::Min => "ValidationLessThanError",
+            ValidCheck::Max => "ValidationGreaterThanError",
+            ValidCheck::AnyOf => "ValidationNotAnyOfError",
+            ValidCheck::Expr => "ValidationExprError",
+        }
+    }
+}
+
+/// Whether `value` passes `valid`, with the attributes read so far in `env`.
+pub fn is_valid(valid: &Valid, value: &Value, env: &Env) -> Result<bool, EvalError> {
+    Ok(failed_check(valid, value, env)?.is_none())
+}
+
+/// First check of `valid` that `value` fails, like the one a runtime reports, or `None` if it
+/// passes them all.
+pub fn failed_check(
+    valid: &Valid,
+    value: &Value,
+    env: &Env,
+) -> Result<Option<ValidCheck>, EvalError> {
+    let mut env = env.clone();
+    env.set(SELF_NAME, value.clone());
+    let holds = |op, expected: &Expr| -> Result<bool, EvalError> {
+        let check = Expr::BinaryOp {
+            l: Box::new(Expr::Name(SELF_NAME.to_string())),
+            op,
+            r: Box::new(expected.clone()),
+        };
+        Ok(eval(&check, &env)? == Value::Bool(true))
+    };
+    let checks = match valid {
+        Valid::Eq(expected) => {
+            return Ok((!holds(BinaryOp::Eq, expected)?).then_some(ValidCheck::Eq));
+        }
+        Valid::Checks(checks) => checks,
+    };
+    if let Some(eq) = &checks.eq {
+        if !holds(BinaryOp::Eq, eq)? {
+            return Ok(Some(ValidCheck::Eq));
+        }
+    }
+    if let Some(min) = &checks.min {
+        if !holds(BinaryOp::Ge, min)? {
+            return Ok(Some(ValidCheck::Min));
+        }
+    }
+    if let Some(max) = &checks.max {
+        if !holds(BinaryOp::Le, max)? {
+            return Ok(Some(ValidCheck::Max));
+        }
+    }
+    if !checks.any_of.is_empty() {
+        let mut any = false;
+        for option in &checks.any_of {
+            any |= holds(BinaryOp::Eq, option)?;
+        }
+        if !any {
+            return Ok(Some(ValidCheck::AnyOf));
+        }
+    }
+    if let Some(expr) = &checks.expr {
+        if eval(expr, &env)? != Value::Bool(true) {
+            return Ok(Some(ValidCheck::Expr));
+        }
+    }
+    Ok(None)
+}
+
+fn literal<R: Rng + ?Sized>(rng: &mut R) -> Expr {
+    Expr::Int(rng.gen::<u8>().into())
+}
+
+fn sample_valid<R: Rng + ?Sized>(rng: &mut R, form: ValidForm) -> Valid {
+    let checks = match form {
+        ValidForm::Short => return Valid::Eq(literal(rng)),
+        ValidForm::Eq => ValidChecks {
+            eq: Some(literal(rng)),
+            ..Default::default()
+        },
+        ValidForm::Min => ValidChecks {
+            min: Some(literal(rng)),
+            ..Default::default()
+        },
+        ValidForm::Max => ValidChecks {
+            max: Some(literal(rng)),
+            ..Default::default()
+        },
+        ValidForm::Range => {
+            let (a, b) = (rng.gen::<u8>(), rng.gen::<u8>());
+            ValidChecks {
+                min: Some(Expr::Int(a.min(b).into())),
+                max: Some(Expr::Int(a.max(b).into())),
+                ..Default::default()
+            }
+        }
+        ValidForm::AnyOf => {
+            let count = rng.gen_range(2..=4);
+            let options = (0..=u8::MAX).choose_multiple(rng, count);
+            ValidChecks {
+                any_of: options.into_iter().map(|x| Expr::Int(x.into())).collect(),
+                ..Default::default()
+            }
+        }
+        ValidForm::Expr => {
+            let ops = [
+                BinaryOp::Eq,
+                BinaryOp::Ne,
+                BinaryOp::Lt,
+                BinaryOp::Le,
+                BinaryOp::Gt,
+                BinaryOp::Ge,
+            ];
+            ValidChecks {
+                expr: Some(Expr::BinaryOp {
+                    l: Box::new(Expr::Name(SELF_NAME.to_string())),
+                    op: *ops.choose(rng).unwrap(),
+                    r: Box::new(Expr::Name("base".to_string())),
+                }),
+                ..Default::default()
+            }
+        }
+    };
+    Valid::Checks(checks)
+}
+
+/// Case for the given form, or `None` if it took too many attempts to find a validation that
+/// both passes and fails for some data.
+pub fn valid_case<R: Rng + ?Sized>(rng: &mut R, id: &str, form: ValidForm) -> Option<ValidCase> {
+    for _ in 0..100 {
+        let valid = sample_valid(rng, form);
+        let base: u8 = rng.gen();
+        let mut env = Env::new();
+        env.set("base", Value::Int(base.into()));
+        let (mut pass, mut fail) = (Vec::new(), Vec::new());
+        for value in 0..=u8::MAX {
+            match is_valid(&valid, &Value::Int(value.into()), &env) {
+                Ok(true) => pass.push(value),
+                Ok(false) => fail.push(value),
+                Err(_) => return None,
+            }
+        }
+        let (Some(pass), Some(fail)) = (pass.choose(rng), fail.choose(rng)) else {
+            continue;
+        };
+        let mut spec = KsySpec::top_level(id);
+        spec.seq.push(Attribute::new("base", "u1"));
+        spec.seq.push(Attribute {
+            valid: Some(valid),
+            ..Attribute::new("value", "u1")
+        });
+        return Some(ValidCase {
+            spec,
+            pass: vec![base, *pass],
+            fail: vec![base, *fail],
+        });
+    }
+    None
+}
+
+/// One case of each [`ValidForm`], with ids like `valid_any_of`.
+pub fn valid_cases<R: Rng + ?Sized>(rng: &mut R) -> Vec<ValidCase> {
+    ValidForm::ALL
+        .into_iter()
+        .filter_map(|form| valid_case(rng, &format!("valid_{}", form.name()), form))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn checks() {
+        let env = Env::new();
+        let range = Valid::Checks(ValidChecks {
+            min: Some(Expr::Int(3)),
+            max: Some(Expr::Int(5)),
+            ..Default::default()
+        });
+        assert_eq!(is_valid(&range, &Value::Int(5), &env), Ok(true));
+        assert_eq!(is_valid(&range, &Value::Int(6), &env), Ok(false));
+        assert_eq!(
+            failed_check(&range, &Value::Int(2), &env),
+            Ok(Some(ValidCheck::Min))
+        );
+        assert_eq!(
+            failed_check(&range, &Value::Int(6), &env),
+            Ok(Some(ValidCheck::Max))
+        );
+        assert_eq!(
+            is_valid(&Valid::Eq(Expr::Int(2)), &Value::Int(2), &env),
+            Ok(true)
+        );
+    }
+
+    #[test]
+    fn pass_and_fail_data() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let cases = valid_cases(&mut rng);
+        assert_eq!(cases.len(), ValidForm::ALL.len());
+        for case in cases {
+            let valid = case.spec.seq[1].valid.as_ref().unwrap();
+            for (data, expected) in [(&case.pass, true), (&case.fail, false)] {
+                let mut env = Env::new();
+                env.set("base", Value::Int(data[0].into()));
+                let value = Value::Int(data[1].into());
+                assert_eq!(is_valid(valid, &value, &env), Ok(expected));
+            }
+        }
+        let yaml = valid_case(&mut rng, "v", ValidForm::Short)
+            .unwrap()
+            .spec
+            .to_yaml();
+        assert!(yaml.contains("  type: u1\n  valid: "), "{}", yaml);
+    }
+}
@@ -0,0 +1,255 @@
+//! Targeting a `meta/ks-version`: detection of the spec features that need a newer compiler, so
+//! that generated specs using them can be left out of suites for older versions.
+
+use std::collections::BTreeSet;
+use std::fmt;
+use std::str::FromStr;
+
+use thiserror::Error;
+
+use crate::ast::Expr;
+use crate::ksy::{Attribute, KsySpec, MetaEndian, TypeRef, TypeSpec, Valid};
+
+#[derive(Clone, Debug, Error, PartialEq)]
+#[error("invalid KS version `{0}`")]
+pub struct VersionError(String);
+
+/// Version of KSC, as in `meta/ks-version`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct KsVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+
+impl KsVersion {
+    pub const fn new(major: u32, minor: u32, patch: u32) -> Self {
+        KsVersion {
+            major,
+            minor,
+            patch,
+        }
+    }
+}
+
+impl FromStr for KsVersion {
+    type Err = VersionError;
+
+    /// Parses versions like `0.9` or `0.10.1`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<u32> = s
+            .split('.')
+            .map(|part| part.parse().map_err(|_| VersionError(s.to_string())))
+            .collect::<Result<_, _>>()?;
+        match parts[..] {
+            [major, minor] => Ok(KsVersion::new(major, minor, 0)),
+            [major, minor, patch] => Ok(KsVersion::new(major, minor, patch)),
+            _ => Err(VersionError(s.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for KsVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)?;
+        if self.patch != 0 {
+            write!(f, ".{}", self.patch)?;
+        }
+        Ok(())
+    }
+}
+
+/// Spec features that not all versions of KSC support.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum SpecFeature {
+    /// `meta/ks-opaque-types`
+    OpaqueTypes,
+    /// `eos-error` key
+    EosError,
+    /// `meta/imports`
+    Imports,
+    /// `params` of types
+    Params,
+    /// `meta/endian` with `switch-on`
+    CalculatedEndian,
+    /// `valid` key
+    Valid,
+    /// `meta/bit-endian`
+    BitEndian,
+    /// `sizeof<>` and `bitsizeof<>`
+    SizeOf,
+    /// `_index` in `repeat-expr` and `repeat-until` attributes
+    RepeatIndex,
+}
+
+impl SpecFeature {
+    /// Version of KSC that introduced the feature.
+    pub fn introduced_in(self) -> KsVersion {
+        match self {
+            SpecFeature::OpaqueTypes | SpecFeature::EosError => KsVersion::new(0, 7, 0),
+            SpecFeature::Imports | SpecFeature::Params | SpecFeature::CalculatedEndian => {
+                KsVersion::new(0, 8, 0)
+            }
+            SpecFeature::Valid
+            | SpecFeature::BitEndian
+            | SpecFeature::SizeOf
+            | SpecFeature::RepeatIndex => KsVersion::new(0, 9, 0),
+        }
+    }
+}
+
+/// Features of the spec (including its nested types) that not all versions of KSC support.
+pub fn spec_features(spec: &KsySpec) -> BTreeSet<SpecFeature> {
+    let mut features = BTreeSet::new();
+    collect_type(spec, &mut features);
+    features
+}
+
+/// Oldest version of KSC that supports every feature of the spec, if any of them needs a
+/// specific version.
+pub fn min_version(spec: &KsySpec) -> Option<KsVersion> {
+    spec_features(spec)
+        .into_iter()
+        .map(SpecFeature::introduced_in)
+        .max()
+}
+
+/// Keeps the specs that `version` supports and sets their `meta/ks-version` to it.
+pub fn for_version<'a>(
+    specs: impl IntoIterator<Item = &'a KsySpec>,
+    version: KsVersion,
+) -> Vec<KsySpec> {
+    specs
+        .into_iter()
+        .filter(|spec| min_version(spec).is_none_or(|min| min <= version))
+        .map(|spec| {
+            let mut spec = spec.clone();
+            spec.meta.get_or_insert_with(Default::default).ks_version = Some(version.to_string());
+            spec
+        })
+        .collect()
+}
+
+fn collect_type(spec: &TypeSpec, features: &mut BTreeSet<SpecFeature>) {
+    if let Some(meta) = &spec.meta {
+        if meta.ks_opaque_types == Some(true) {
+            features.insert(SpecFeature::OpaqueTypes);
+        }
+        if !meta.imports.is_empty() {
+            features.insert(SpecFeature::Imports);
+        }
+        if let Some(MetaEndian::Switch { .. }) = meta.endian {
+            features.insert(SpecFeature::CalculatedEndian);
+        }
+        if meta.bit_endian.is_some() {
+            features.insert(SpecFeature::BitEndian);
+        }
+    }
+    if !spec.params.is_empty() {
+        features.insert(SpecFeature::Params);
+    }
+    for attr in spec.seq.iter().chain(spec.instances.values()) {
+        collect_attr(attr, features);
+    }
+    for nested in spec.types.values() {
+        collect_type(nested, features);
+    }
+}
+
+fn collect_attr(attr: &Attribute, features: &mut BTreeSet<SpecFeature>) {
+    if attr.eos_error.is_some() {
+        features.insert(SpecFeature::EosError);
+    }
+    if attr.valid.is_some() {
+        features.insert(SpecFeature::Valid);
+    }
+    let mut exprs: Vec<&Expr> = [
+        &attr.pos,
+        &attr.io,
+        &attr.value,
+        &attr.size,
+        &attr.repeat_expr,
+        &attr.repeat_until,
+        &attr.if_expr,
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+    if let Some(TypeRef::Switch { switch_on, cases }) = &attr.type_ref {
+        exprs.push(switch_on);
+        exprs.extend(cases.keys());
+    }
+    match &attr.valid {
+        Some(Valid::Eq(expr)) => exprs.push(expr),
+        Some(Valid::Checks(checks)) => {
+            exprs.extend(
+                [&checks.eq, &checks.min, &checks.max, &checks.expr]
+                    .into_iter()
+                    .flatten(),
+            );
+            exprs.extend(&checks.any_of);
+        }
+        None => {}
+    }
+    for expr in exprs {
+        collect_expr(expr, features);
+    }
+}
+
+fn collect_expr(expr: &Expr, features: &mut BTreeSet<SpecFeature>) {
+    match expr {
+        Expr::SizeOf { .. } => {
+            features.insert(SpecFeature::SizeOf);
+        }
+        Expr::Name(name) if name == "_index" => {
+            features.insert(SpecFeature::RepeatIndex);
+        }
+        _ => {}
+    }
+    for child in expr.children() {
+        collect_expr(child, features);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gen::{opaque, params, valid};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn versions() {
+        let version: KsVersion = "0.9".parse().unwrap();
+        assert_eq!(version, KsVersion::new(0, 9, 0));
+        assert_eq!(version.to_string(), "0.9");
+        assert_eq!("0.10.1".parse(), Ok(KsVersion::new(0, 10, 1)));
+        assert!("0.10.x".parse::<KsVersion>().is_err());
+        assert!("1".parse::<KsVersion>().is_err());
+        assert!(KsVersion::new(0, 10, 0) > version);
+    }
+
+    #[test]
+    fn newer_features_are_excluded() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let plain = KsySpec::top_level("plain");
+        let opaque = opaque::opaque_case(&mut rng, "opaque", 4).main.spec;
+        let params = params::params_case(&mut rng, "params").spec;
+        let valid = valid::valid_cases(&mut rng).remove(0).spec;
+        assert_eq!(min_version(&plain), None);
+        assert_eq!(
+            spec_features(&params),
+            BTreeSet::from([SpecFeature::Params])
+        );
+        assert_eq!(min_version(&valid), Some(KsVersion::new(0, 9, 0)));
+
+        let specs = [plain, opaque, params, valid];
+        let kept = for_version(&specs, KsVersion::new(0, 8, 0));
+        let ids: Vec<_> = kept.iter().map(|spec| spec.id().unwrap()).collect();
+        assert_eq!(ids, ["plain", "opaque", "params"]);
+        assert_eq!(
+            kept[0].meta.as_ref().unwrap().ks_version.as_deref(),
+            Some("0.8")
+        );
+    }
+}
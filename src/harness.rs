@@ -0,0 +1,305 @@
+//! Native test files of the target languages, laid out like the ones that the upstream test
+//! suite (kaitai_struct_tests) generates from its `.kst` files, e.g. `spec/python/test_foo.py`.
+//! A [`HarnessEmitter`] per language writes the file; what is shared (parsing and translating
+//! the asserts, naming the test) happens before, with the language translators.
+
+use std::path::Path;
+
+use thiserror::Error;
+
+use crate::ast::parser::{parse_expr, SyntaxError};
+use crate::gen::suite::GenCase;
+use crate::kst::{case_specs, KstSpec, LiteralError};
+use crate::target::Target;
+use crate::translator::native::{byte_list, class_name, NativeError, NativeTranslator};
+use cpp::CppEmitter;
+use csharp::CSharpEmitter;
+use go::GoEmitter;
+use java::JavaEmitter;
+use javascript::JavaScriptEmitter;
+use lua::LuaEmitter;
+use nim::NimEmitter;
+use perl::PerlEmitter;
+use php::PhpEmitter;
+use python::PythonEmitter;
+use ruby::RubyEmitter;
+use rust::RustEmitter;
+use swift::SwiftEmitter;
+
+pub mod cpp;
+pub mod csharp;
+pub mod go;
+pub mod java;
+pub mod javascript;
+pub mod lua;
+pub mod nim;
+pub mod perl;
+pub mod php;
+pub mod python;
+pub mod ruby;
+pub mod rust;
+pub mod swift;
+
+const HEADER: &str = "Autogenerated from KST: please remove this line if doing any edits by hand!";
+
+#[derive(Clone, Debug, Error, PartialEq)]
+pub enum HarnessError {
+    #[error(transparent)]
+    Literal(#[from] LiteralError),
+    #[error("can't parse `{text}`: {error}")]
+    Syntax { text: String, error: SyntaxError },
+    #[error(transparent)]
+    Translate(#[from] NativeError),
+}
+
+/// Writer of the test files of one target language.
+pub trait HarnessEmitter {
+    fn target(&self) -> Target;
+
+    /// Variable that holds the parsed object in tests
+    fn root(&self) -> &'static str {
+        "r"
+    }
+
+    /// Path of the test file, relative to the root of the test suite
+    fn path(&self, test: &HarnessTest) -> String;
+
+    fn source(&self, test: &HarnessTest) -> String;
+}
+
+/// Test of parsing a data file with a spec, with everything in the syntax of the language: the
+/// data parses into the values of the asserts, or fails with the exception.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HarnessTest<'a> {
+    /// Name of the data file without its extension, which the test is named after
+    pub name: String,
+    /// Class (or module) of the top-level type
+    pub class: String,
+    /// `meta/id` of the spec
+    pub id: &'a str,
+    /// Data file, relative to the directory of data files
+    pub data: &'a str,
+    /// Exception as in KST files, like `ValidationNotEqualError<u1>`
+    pub exception: Option<&'a str>,
+    pub asserts: Vec<HarnessAssert>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct HarnessAssert {
+    pub actual: String,
+    pub expected: String,
+    /// Largest difference from the expected float value that passes, if not exact
+    pub epsilon: Option<f64>,
+    /// Whether the values are byte arrays, which some languages compare differently
+    pub bytes: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct NativeTest {
+    /// Path of the file relative to the root of the test suite
+    pub path: String,
+    pub source: String,
+}
+
+pub fn emitter(target: Target) -> Box<dyn HarnessEmitter> {
+    match target {
+        Target::Cpp => Box::new(CppEmitter),
+        Target::CSharp => Box::new(CSharpEmitter),
+        Target::Go => Box::new(GoEmitter),
+        Target::Java => Box::new(JavaEmitter::default()),
+        Target::JavaScript => Box::new(JavaScriptEmitter::default()),
+        Target::Lua => Box::new(LuaEmitter),
+        Target::Nim => Box::new(NimEmitter),
+        Target::Perl => Box::new(PerlEmitter::default()),
+        Target::Php => Box::new(PhpEmitter),
+        Target::Python => Box::new(PythonEmitter::default()),
+        Target::Ruby => Box::new(RubyEmitter::default()),
+        Target::Rust => Box::new(RustEmitter),
+        Target::Swift => Box::new(SwiftEmitter),
+    }
+}
+
+/// Test of the KST spec, with the asserts and the expected values translated to the language of
+/// the emitter.
+pub fn emit(emitter: &dyn HarnessEmitter, spec: &KstSpec) -> Result<NativeTest, HarnessError> {
+    let target = emitter.target();
+    let translator = NativeTranslator {
+        target,
+        spec_id: &spec.id,
+        root: emitter.root(),
+    };
+    let parse = |text: &str| {
+        parse_expr(text).map_err(|error| HarnessError::Syntax {
+            text: text.to_string(),
+            error,
+        })
+    };
+    let asserts = spec
+        .asserts
+        .iter()
+        .map(|assert| {
+            let actual = parse(&assert.actual)?;
+            let expected = parse(&assert.expected)?;
+            Ok(HarnessAssert {
+                actual: translator.translate(&actual)?,
+                expected: translator.translate(&expected)?,
+                epsilon: assert.epsilon,
+                bytes: byte_list(&expected).is_some(),
+            })
+        })
+        .collect::<Result<_, HarnessError>>()?;
+    let test = HarnessTest {
+        name: Path::new(&spec.data).file_stem().map_or_else(
+            || spec.id.clone(),
+            |stem| stem.to_string_lossy().into_owned(),
+        ),
+        class: class_name(target, &spec.id),
+        id: &spec.id,
+        data: &spec.data,
+        exception: spec.exception.as_deref(),
+        asserts,
+    };
+    Ok(NativeTest {
+        path: emitter.path(&test),
+        source: emitter.source(&test),
+    })
+}
+
+/// Test of the KST spec in the language of the target.
+pub fn native_test(spec: &KstSpec, target: Target) -> Result<NativeTest, HarnessError> {
+    emit(emitter(target).as_ref(), spec)
+}
+
+/// Tests of the inputs of the case, in order, one file each.
+pub fn case_tests(
+    case: &GenCase,
+    emitter: &dyn HarnessEmitter,
+) -> Result<Vec<NativeTest>, HarnessError> {
+    case_specs(case)?
+        .iter()
+        .map(|spec| emit(emitter, spec))
+        .collect()
+}
+
+/// Name of a runtime exception (as in KST files, like `ValidationNotEqualError<u1>`) without
+/// the type of the value that failed validation, and with it.
+fn split_exception(exception: &str) -> (&str, Option<&str>) {
+    match exception.split_once('<') {
+        Some((name, type_arg)) => (name, Some(type_arg.trim_end_matches('>'))),
+        None => (exception, None),
+    }
+}
+
+/// `FooBar` as `foo_bar`
+fn snake_case(name: &str) -> String {
+    let mut out = String::new();
+    for (i, ch) in name.chars().enumerate() {
+        if ch.is_ascii_uppercase() && i > 0 {
+            out.push('_');
+        }
+        out.push(ch.to_ascii_lowercase());
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kst::KstAssert;
+
+    fn spec(exception: Option<&str>) -> KstSpec {
+        let assert = |actual: &str, expected: &str, epsilon| KstAssert {
+            actual: actual.to_string(),
+            expected: expected.to_string(),
+            epsilon,
+        };
+        KstSpec {
+            id: "enum_case".to_string(),
+            data: "enum_case_1.bin".to_string(),
+            exception: exception.map(str::to_string),
+            asserts: if exception.is_some() {
+                Vec::new()
+            } else {
+                vec![
+                    assert("header.kind", "header::kind::small", None),
+                    assert("items[0]", "[0x50, 0x4b]", None),
+                    assert("ratio", "0.1", Some(1e-6)),
+                ]
+            },
+        }
+    }
+
+    #[test]
+    fn exceptions() {
+        let eof = spec(Some("EndOfStreamError"));
+        let expected = [
+            (Target::Cpp, "std::ifstream::failure"),
+            (Target::CSharp, "Assert.Throws<EndOfStreamException>"),
+            (Target::Go, "errors.Is(err, io.ErrUnexpectedEOF)"),
+            (Target::JavaScript, "KaitaiStream.EOFError"),
+            (Target::Python, "self.assertRaises(EOFError)"),
+            (Target::Ruby, "raise_error(EOFError)"),
+        ];
+        for (target, snippet) in expected {
+            let source = native_test(&eof, target).unwrap().source;
+            assert!(source.contains(snippet), "{}:\n{}", target, source);
+        }
+        let validation = spec(Some("ValidationNotEqualError<u1>"));
+        let source = native_test(&validation, Target::Cpp).unwrap().source;
+        assert!(source.contains("kaitai::validation_not_equal_error<uint8_t>"));
+    }
+
+    #[test]
+    fn every_target() {
+        let mut paths = Vec::new();
+        for target in Target::ALL {
+            for spec in [spec(None), spec(Some("EndOfStreamError"))] {
+                let test = native_test(&spec, target).unwrap();
+                assert!(test.source.contains(HEADER), "{}", target);
+                paths.push(test.path);
+            }
+        }
+        paths.dedup();
+        assert_eq!(paths.len(), Target::ALL.len());
+    }
+
+    #[test]
+    fn generated_cases() {
+        use crate::gen::profile::GenProfile;
+        use crate::gen::suite::generate_suite;
+
+        let mut emitted = 0;
+        for case in generate_suite(0, 20, &GenProfile::default()) {
+            for target in Target::ALL {
+                match case_tests(&case, emitter(target).as_ref()) {
+                    Ok(tests) => {
+                        assert_eq!(tests.len(), case.inputs.len());
+                        emitted += 1;
+                    }
+                    Err(error) => assert!(
+                        matches!(error, HarnessError::Translate(_)),
+                        "{}: {}",
+                        case.id,
+                        error
+                    ),
+                }
+            }
+        }
+        assert!(emitted > 0);
+    }
+
+    #[test]
+    fn untranslatable_asserts() {
+        let mut spec = spec(None);
+        spec.asserts[0].actual = "header.kind.to_i".to_string();
+        assert!(matches!(
+            native_test(&spec, Target::Python),
+            Err(HarnessError::Translate(_))
+        ));
+        spec.asserts[0].actual = "header.".to_string();
+        assert!(matches!(
+            native_test(&spec, Target::Python),
+            Err(HarnessError::Syntax { .. })
+        ));
+    }
+}
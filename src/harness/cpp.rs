@@ -0,0 +1,213 @@
+//! C++ tests with Boost.Test, for the STL runtime (`cpp_stl_11`): one translation unit per test,
+//! and a CMake fragment that lists them with the sources of the compiled specs.
+
+use std::fmt::Write;
+
+use crate::target::Target;
+
+use super::{snake_case, split_exception, HarnessEmitter, HarnessTest, NativeTest, HEADER};
+
+#[derive(Clone, Debug, Default)]
+pub struct CppEmitter;
+
+impl HarnessEmitter for CppEmitter {
+    fn target(&self) -> Target {
+        Target::Cpp
+    }
+
+    fn path(&self, test: &HarnessTest) -> String {
+        format!("spec/cpp_stl_11/test_{}.cpp", test.name)
+    }
+
+    fn source(&self, test: &HarnessTest) -> String {
+        let mut out = String::new();
+        writeln!(out, "// {}\n", HEADER).unwrap();
+        writeln!(out, "#include <boost/test/unit_test.hpp>").unwrap();
+        writeln!(out, "#include \"{}.h\"", test.id).unwrap();
+        writeln!(out, "#include <iostream>").unwrap();
+        writeln!(out, "#include <fstream>").unwrap();
+        writeln!(out, "#include <vector>\n").unwrap();
+        writeln!(out, "BOOST_AUTO_TEST_CASE(test_{}) {{", test.name).unwrap();
+        writeln!(
+            out,
+            "    std::ifstream ifs(\"src/{}\", std::ifstream::binary);",
+            test.data
+        )
+        .unwrap();
+        writeln!(out, "    kaitai::kstream ks(&ifs);").unwrap();
+        if let Some(exception) = test.exception {
+            let exception = match split_exception(exception) {
+                ("EndOfStreamError", _) => "std::ifstream::failure".to_string(),
+                (name, None) => format!("kaitai::{}", snake_case(name)),
+                (name, Some(type_arg)) => {
+                    let value_type = match type_arg {
+                        "u1" => "uint8_t",
+                        "u2" => "uint16_t",
+                        "u4" => "uint32_t",
+                        "u8" => "uint64_t",
+                        "s1" => "int8_t",
+                        "s2" => "int16_t",
+                        "s4" => "int32_t",
+                        "s8" => "int64_t",
+                        "f4" => "float",
+                        "f8" => "double",
+                        "bool" | "b1" => "bool",
+                        t if t.starts_with('b') => "uint64_t",
+                        _ => "std::string",
+                    };
+                    format!("kaitai::{}<{}>", snake_case(name), value_type)
+                }
+            };
+            writeln!(
+                out,
+                "    BOOST_CHECK_THROW({class}* r = new {class}(&ks); delete r, {exception});",
+                class = test.class,
+                exception = exception
+            )
+            .unwrap();
+        } else {
+            writeln!(
+                out,
+                "    {class}* r = new {class}(&ks);\n",
+                class = test.class
+            )
+            .unwrap();
+            for assert in &test.asserts {
+                match assert.epsilon {
+                    Some(epsilon) => writeln!(
+                        out,
+                        "    BOOST_CHECK_SMALL({} - {}, {:?});",
+                        assert.actual, assert.expected, epsilon
+                    ),
+                    None => writeln!(
+                        out,
+                        "    BOOST_CHECK_EQUAL({}, {});",
+                        assert.actual, assert.expected
+                    ),
+                }
+                .unwrap();
+            }
+            writeln!(out, "\n    delete r;").unwrap();
+        }
+        writeln!(out, "}}").unwrap();
+        out
+    }
+}
+
+/// Directory of the C++ suite, relative to the root of the test suite
+const SPEC_DIR: &str = "spec/cpp_stl_11";
+
+/// CMake fragment, for `include()` in the build of the C++ suite, that lists the C++ tests among
+/// `tests` in `GENERATED_TEST_SOURCES` and the sources that KSC compiles the specs into in
+/// `GENERATED_FORMAT_SOURCES`.
+pub fn cmake_fragment(tests: &[NativeTest], spec_ids: &[&str]) -> NativeTest {
+    let mut spec_ids = spec_ids.to_vec();
+    spec_ids.sort_unstable();
+    spec_ids.dedup();
+    let mut out = String::new();
+    writeln!(out, "# {}\n", HEADER).unwrap();
+    writeln!(out, "set(GENERATED_TEST_SOURCES").unwrap();
+    for test in tests {
+        if let Some(file) = test
+            .path
+            .strip_prefix(SPEC_DIR)
+            .and_then(|rest| rest.strip_prefix('/'))
+        {
+            writeln!(out, "    ${{CMAKE_CURRENT_LIST_DIR}}/{}", file).unwrap();
+        }
+    }
+    writeln!(out, ")\n\nset(GENERATED_FORMAT_SOURCES").unwrap();
+    for id in spec_ids {
+        writeln!(
+            out,
+            "    ${{CMAKE_CURRENT_LIST_DIR}}/../../compiled/cpp_stl_11/{}.cpp",
+            id
+        )
+        .unwrap();
+    }
+    writeln!(out, ")").unwrap();
+    NativeTest {
+        path: format!("{}/generated_tests.cmake", SPEC_DIR),
+        source: out,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::harness::emit;
+    use crate::kst::KstSpec;
+
+    const KST: &str = "\
+id: enum_case
+data: enum_case_1.bin
+asserts:
+  - actual: header.kind
+    expected: header::kind::small
+  - actual: items[0]
+    expected: '[0x50, 0x4b]'
+  - actual: ratio
+    expected: 0.1
+    epsilon: 1e-6
+";
+
+    #[test]
+    fn translation_unit() {
+        let spec = KstSpec::from_yaml_str(KST).unwrap();
+        let test = emit(&CppEmitter, &spec).unwrap();
+        assert_eq!(test.path, "spec/cpp_stl_11/test_enum_case_1.cpp");
+        assert_eq!(
+            test.source,
+            format!(
+                "// {}\n\n\
+                 #include <boost/test/unit_test.hpp>\n\
+                 #include \"enum_case.h\"\n\
+                 #include <iostream>\n\
+                 #include <fstream>\n\
+                 #include <vector>\n\n\
+                 BOOST_AUTO_TEST_CASE(test_enum_case_1) {{\n    \
+                 std::ifstream ifs(\"src/enum_case_1.bin\", std::ifstream::binary);\n    \
+                 kaitai::kstream ks(&ifs);\n    \
+                 enum_case_t* r = new enum_case_t(&ks);\n\n    \
+                 BOOST_CHECK_EQUAL(r->header()->kind(), enum_case_t::header_t::KIND_SMALL);\n    \
+                 BOOST_CHECK_EQUAL(r->items()->at(0), std::string(\"\\x50\\x4b\", 2));\n    \
+                 BOOST_CHECK_SMALL(r->ratio() - 0.1, 1e-6);\n\n    \
+                 delete r;\n\
+                 }}\n",
+                HEADER
+            )
+        );
+    }
+
+    #[test]
+    fn cmake() {
+        let mut spec = KstSpec::from_yaml_str(KST).unwrap();
+        let first = emit(&CppEmitter, &spec).unwrap();
+        spec.data = "enum_case_2.bin".to_string();
+        let second = emit(&CppEmitter, &spec).unwrap();
+        let java = NativeTest {
+            path: "spec/java/src/io/kaitai/struct/spec/TestEnumCase1.java".to_string(),
+            source: String::new(),
+        };
+        let fragment = cmake_fragment(
+            &[first, java, second],
+            &["enum_case", "colors", "enum_case"],
+        );
+        assert_eq!(fragment.path, "spec/cpp_stl_11/generated_tests.cmake");
+        assert_eq!(
+            fragment.source,
+            format!(
+                "# {}\n\n\
+                 set(GENERATED_TEST_SOURCES\n    \
+                 ${{CMAKE_CURRENT_LIST_DIR}}/test_enum_case_1.cpp\n    \
+                 ${{CMAKE_CURRENT_LIST_DIR}}/test_enum_case_2.cpp\n\
+                 )\n\n\
+                 set(GENERATED_FORMAT_SOURCES\n    \
+                 ${{CMAKE_CURRENT_LIST_DIR}}/../../compiled/cpp_stl_11/colors.cpp\n    \
+                 ${{CMAKE_CURRENT_LIST_DIR}}/../../compiled/cpp_stl_11/enum_case.cpp\n\
+                 )\n",
+                HEADER
+            )
+        );
+    }
+}
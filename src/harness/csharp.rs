@@ -0,0 +1,156 @@
+//! C# tests with NUnit, in the `Kaitai` namespace with the `CommonSpec` base class of the
+//! upstream suite. Values compare with `Assert.AreEqual`, which NUnit compares numerically
+//! across integer types, and byte arrays item by item with `CollectionAssert.AreEqual`.
+
+use std::fmt::Write;
+
+use crate::target::Target;
+use crate::translator::native::upper_camel;
+
+use super::{split_exception, HarnessAssert, HarnessEmitter, HarnessTest, HEADER};
+
+#[derive(Clone, Debug, Default)]
+pub struct CSharpEmitter;
+
+fn assert_statement(assert: &HarnessAssert) -> String {
+    match assert.epsilon {
+        Some(epsilon) => format!(
+            "Assert.AreEqual({}, {}, {:?});",
+            assert.expected, assert.actual, epsilon
+        ),
+        None if assert.bytes => format!(
+            "CollectionAssert.AreEqual({}, {});",
+            assert.expected, assert.actual
+        ),
+        None => format!("Assert.AreEqual({}, {});", assert.expected, assert.actual),
+    }
+}
+
+impl HarnessEmitter for CSharpEmitter {
+    fn target(&self) -> Target {
+        Target::CSharp
+    }
+
+    fn path(&self, test: &HarnessTest) -> String {
+        format!(
+            "spec/csharp/kaitai_struct_csharp_tests/tests/Spec{}.cs",
+            upper_camel(&test.name)
+        )
+    }
+
+    fn source(&self, test: &HarnessTest) -> String {
+        let mut out = String::new();
+        writeln!(out, "// {}\n", HEADER).unwrap();
+        writeln!(out, "using NUnit.Framework;").unwrap();
+        let exception = test
+            .exception
+            .map(|exception| match split_exception(exception).0 {
+                "EndOfStreamError" => "EndOfStreamException",
+                name => name,
+            });
+        if exception == Some("EndOfStreamException") {
+            writeln!(out, "using System.IO;").unwrap();
+        }
+        writeln!(out, "\nnamespace Kaitai\n{{").unwrap();
+        writeln!(out, "    [TestFixture]").unwrap();
+        writeln!(
+            out,
+            "    public class Spec{} : CommonSpec\n    {{",
+            upper_camel(&test.name)
+        )
+        .unwrap();
+        writeln!(out, "        [Test]").unwrap();
+        writeln!(
+            out,
+            "        public void Test{}()\n        {{",
+            upper_camel(&test.name)
+        )
+        .unwrap();
+        let parse = format!("{}.FromFile(SourceFile(\"{}\"))", test.class, test.data);
+        if let Some(exception) = exception {
+            writeln!(
+                out,
+                "            Assert.Throws<{}>(delegate {{ {}; }});",
+                exception, parse
+            )
+            .unwrap();
+        } else {
+            writeln!(out, "            var r = {};", parse).unwrap();
+            if !test.asserts.is_empty() {
+                writeln!(out).unwrap();
+            }
+            for assert in &test.asserts {
+                writeln!(out, "            {}", assert_statement(assert)).unwrap();
+            }
+        }
+        writeln!(out, "        }}\n    }}\n}}").unwrap();
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::harness::emit;
+    use crate::kst::KstSpec;
+
+    const KST: &str = "\
+id: enum_case
+data: enum_case_1.bin
+asserts:
+  - actual: header.kind
+    expected: header::kind::small
+  - actual: items[0]
+    expected: '[0x50, 0x4b]'
+  - actual: big
+    expected: 18446744073709551615
+  - actual: wide
+    expected: -2147483649
+  - actual: ratio
+    expected: 0.1
+    epsilon: 1e-6
+";
+
+    #[test]
+    fn nunit() {
+        let mut spec = KstSpec::from_yaml_str(KST).unwrap();
+        let test = emit(&CSharpEmitter, &spec).unwrap();
+        assert_eq!(
+            test.path,
+            "spec/csharp/kaitai_struct_csharp_tests/tests/SpecEnumCase1.cs"
+        );
+        assert_eq!(
+            test.source,
+            format!(
+                "// {}\n\n\
+                 using NUnit.Framework;\n\n\
+                 namespace Kaitai\n\
+                 {{\n    \
+                 [TestFixture]\n    \
+                 public class SpecEnumCase1 : CommonSpec\n    \
+                 {{\n        \
+                 [Test]\n        \
+                 public void TestEnumCase1()\n        \
+                 {{\n            \
+                 var r = EnumCase.FromFile(SourceFile(\"enum_case_1.bin\"));\n\n            \
+                 Assert.AreEqual(EnumCase.Header.Kind.Small, r.Header.Kind);\n            \
+                 CollectionAssert.AreEqual(new byte[] {{ 80, 75 }}, r.Items[0]);\n            \
+                 Assert.AreEqual(18446744073709551615UL, r.Big);\n            \
+                 Assert.AreEqual((-2147483649L), r.Wide);\n            \
+                 Assert.AreEqual(0.1, r.Ratio, 1e-6);\n        \
+                 }}\n    \
+                 }}\n\
+                 }}\n",
+                HEADER
+            )
+        );
+
+        spec.exception = Some("ValidationNotEqualError<u1>".to_string());
+        let source = emit(&CSharpEmitter, &spec).unwrap().source;
+        assert!(!source.contains("using System.IO;"));
+        assert!(source.contains(
+            "            Assert.Throws<ValidationNotEqualError>(delegate { \
+             EnumCase.FromFile(SourceFile(\"enum_case_1.bin\")); });\n"
+        ));
+    }
+}
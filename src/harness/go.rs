@@ -0,0 +1,193 @@
+//! Go tests with the `testing` package alone. Each spec gets a directory and an external test
+//! package of its own, named after the `meta/id`, and the data parses with the error-returning
+//! `Read` of the runtime. Slices compare with `reflect.DeepEqual`, anything else with `!=`.
+
+use std::fmt::Write;
+
+use crate::target::Target;
+use crate::translator::native::upper_camel;
+
+use super::{split_exception, HarnessAssert, HarnessEmitter, HarnessTest, HEADER};
+
+#[derive(Clone, Debug, Default)]
+pub struct GoEmitter;
+
+/// Package of the tests of the spec: the `meta/id` without underscores, which Go package names
+/// don't use, as an external test package (which no keyword is named like).
+fn package_name(id: &str) -> String {
+    format!("{}_test", id.replace('_', ""))
+}
+
+fn check(assert: &HarnessAssert) -> String {
+    let failed = match assert.epsilon {
+        Some(epsilon) => format!(
+            "math.Abs(float64({}) - {}) > {:?}",
+            assert.actual, assert.expected, epsilon
+        ),
+        None if assert.bytes => {
+            format!("!reflect.DeepEqual({}, {})", assert.actual, assert.expected)
+        }
+        None => format!("{} != {}", assert.actual, assert.expected),
+    };
+    format!(
+        "\tif {} {{\n\t\tt.Errorf(\"got %v, want %v\", {}, {})\n\t}}",
+        failed, assert.actual, assert.expected
+    )
+}
+
+impl HarnessEmitter for GoEmitter {
+    fn target(&self) -> Target {
+        Target::Go
+    }
+
+    fn path(&self, test: &HarnessTest) -> String {
+        format!("spec/go/{}/{}_test.go", test.id, test.name)
+    }
+
+    fn source(&self, test: &HarnessTest) -> String {
+        let eof = test
+            .exception
+            .is_some_and(|exception| split_exception(exception).0 == "EndOfStreamError");
+        let mut imports = Vec::new();
+        if test.exception.is_some() {
+            imports.push("errors");
+        }
+        if eof {
+            imports.push("io");
+        }
+        // asserts are only checked if the data parses
+        let asserts = if test.exception.is_none() {
+            &test.asserts[..]
+        } else {
+            &[]
+        };
+        if asserts.iter().any(|assert| assert.epsilon.is_some()) {
+            imports.push("math");
+        }
+        imports.push("os");
+        if asserts.iter().any(|assert| assert.bytes) {
+            imports.push("reflect");
+        }
+        imports.push("testing");
+        let mut out = String::new();
+        writeln!(out, "// {}\n", HEADER).unwrap();
+        writeln!(out, "package {}\n\nimport (", package_name(test.id)).unwrap();
+        for import in imports {
+            writeln!(out, "\t\"{}\"", import).unwrap();
+        }
+        writeln!(
+            out,
+            "\n\t\"github.com/kaitai-io/kaitai_struct_go_runtime/kaitai\""
+        )
+        .unwrap();
+        writeln!(out, "\t. \"test_formats\"\n)\n").unwrap();
+        writeln!(out, "func Test{}(t *testing.T) {{", upper_camel(&test.name)).unwrap();
+        writeln!(out, "\tf, err := os.Open(\"../../../src/{}\")", test.data).unwrap();
+        writeln!(out, "\tif err != nil {{\n\t\tt.Fatal(err)\n\t}}").unwrap();
+        writeln!(out, "\tdefer f.Close()").unwrap();
+        writeln!(out, "\ts := kaitai.NewStream(f)").unwrap();
+        writeln!(out, "\tvar r {}", test.class).unwrap();
+        writeln!(out, "\terr = r.Read(s, &r, &r)").unwrap();
+        if let Some(exception) = test.exception {
+            if eof {
+                writeln!(out, "\tif !errors.Is(err, io.ErrUnexpectedEOF) {{").unwrap();
+                writeln!(
+                    out,
+                    "\t\tt.Fatalf(\"got %v, want io.ErrUnexpectedEOF\", err)"
+                )
+                .unwrap();
+            } else {
+                let name = split_exception(exception).0;
+                writeln!(out, "\tvar want kaitai.{}", name).unwrap();
+                writeln!(out, "\tif !errors.As(err, &want) {{").unwrap();
+                writeln!(out, "\t\tt.Fatalf(\"got %v, want kaitai.{}\", err)", name).unwrap();
+            }
+            writeln!(out, "\t}}").unwrap();
+        } else {
+            writeln!(out, "\tif err != nil {{\n\t\tt.Fatal(err)\n\t}}").unwrap();
+            if !asserts.is_empty() {
+                writeln!(out).unwrap();
+            }
+            for assert in asserts {
+                writeln!(out, "{}", check(assert)).unwrap();
+            }
+        }
+        writeln!(out, "}}").unwrap();
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::harness::emit;
+    use crate::kst::KstSpec;
+
+    const KST: &str = "\
+id: enum_case
+data: enum_case_1.bin
+asserts:
+  - actual: header.kind
+    expected: header::kind::small
+  - actual: items[0]
+    expected: '[0x50, 0x4b]'
+  - actual: ratio
+    expected: 0.1
+    epsilon: 1e-6
+";
+
+    #[test]
+    fn testing() {
+        let mut spec = KstSpec::from_yaml_str(KST).unwrap();
+        let test = emit(&GoEmitter, &spec).unwrap();
+        assert_eq!(test.path, "spec/go/enum_case/enum_case_1_test.go");
+        assert_eq!(
+            test.source,
+            format!(
+                "// {}\n\n\
+                 package enumcase_test\n\n\
+                 import (\n\
+                 \t\"math\"\n\
+                 \t\"os\"\n\
+                 \t\"reflect\"\n\
+                 \t\"testing\"\n\n\
+                 \t\"github.com/kaitai-io/kaitai_struct_go_runtime/kaitai\"\n\
+                 \t. \"test_formats\"\n\
+                 )\n\n\
+                 func TestEnumCase1(t *testing.T) {{\n\
+                 \tf, err := os.Open(\"../../../src/enum_case_1.bin\")\n\
+                 \tif err != nil {{\n\t\tt.Fatal(err)\n\t}}\n\
+                 \tdefer f.Close()\n\
+                 \ts := kaitai.NewStream(f)\n\
+                 \tvar r EnumCase\n\
+                 \terr = r.Read(s, &r, &r)\n\
+                 \tif err != nil {{\n\t\tt.Fatal(err)\n\t}}\n\n\
+                 \tif r.Header.Kind != EnumCase_Header_Kind__Small {{\n\
+                 \t\tt.Errorf(\"got %v, want %v\", r.Header.Kind, EnumCase_Header_Kind__Small)\n\
+                 \t}}\n\
+                 \tif !reflect.DeepEqual(r.Items[0], []uint8{{80, 75}}) {{\n\
+                 \t\tt.Errorf(\"got %v, want %v\", r.Items[0], []uint8{{80, 75}})\n\
+                 \t}}\n\
+                 \tif math.Abs(float64(r.Ratio) - 0.1) > 1e-6 {{\n\
+                 \t\tt.Errorf(\"got %v, want %v\", r.Ratio, 0.1)\n\
+                 \t}}\n\
+                 }}\n",
+                HEADER
+            )
+        );
+
+        spec.exception = Some("ValidationNotEqualError<u1>".to_string());
+        let source = emit(&GoEmitter, &spec).unwrap().source;
+        assert!(source.contains("import (\n\t\"errors\"\n\t\"os\"\n\t\"testing\"\n\n"));
+        assert!(source.contains(
+            "\tvar want kaitai.ValidationNotEqualError\n\
+             \tif !errors.As(err, &want) {\n"
+        ));
+    }
+
+    #[test]
+    fn package_names() {
+        assert_eq!(package_name("enum_case"), "enumcase_test");
+        assert_eq!(package_name("type"), "type_test");
+    }
+}
@@ -0,0 +1,205 @@
+//! Java tests with TestNG, like the ones of the upstream suite, or with JUnit 5. Tests are
+//! classes `io.kaitai.struct.spec.Test<Name>` that parse the data with `fromFile` of the class
+//! compiled into `io.kaitai.struct.testformats`.
+
+use std::fmt::Write;
+
+use crate::target::Target;
+use crate::translator::native::upper_camel;
+
+use super::{split_exception, HarnessAssert, HarnessEmitter, HarnessTest, HEADER};
+
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum JavaFramework {
+    /// TestNG, with the `CommonSpec` base class of the upstream suite
+    #[default]
+    TestNg,
+    JUnit,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct JavaEmitter {
+    pub framework: JavaFramework,
+}
+
+impl JavaEmitter {
+    /// Assert statement. TestNG takes the actual value first, JUnit the expected one.
+    fn assert(&self, assert: &HarnessAssert) -> String {
+        let (first, second) = match self.framework {
+            JavaFramework::TestNg => (&assert.actual, &assert.expected),
+            JavaFramework::JUnit => (&assert.expected, &assert.actual),
+        };
+        let method = match self.framework {
+            JavaFramework::JUnit if assert.bytes => "assertArrayEquals",
+            _ => "assertEquals",
+        };
+        match assert.epsilon {
+            Some(epsilon) => format!("{}({}, {}, {:?});", method, first, second, epsilon),
+            None => format!("{}({}, {});", method, first, second),
+        }
+    }
+}
+
+impl HarnessEmitter for JavaEmitter {
+    fn target(&self) -> Target {
+        Target::Java
+    }
+
+    fn path(&self, test: &HarnessTest) -> String {
+        format!(
+            "spec/java/src/io/kaitai/struct/spec/Test{}.java",
+            upper_camel(&test.name)
+        )
+    }
+
+    fn source(&self, test: &HarnessTest) -> String {
+        let class_name = format!("Test{}", upper_camel(&test.name));
+        let mut out = String::new();
+        writeln!(out, "// {}\n", HEADER).unwrap();
+        writeln!(out, "package io.kaitai.struct.spec;\n").unwrap();
+        writeln!(out, "import io.kaitai.struct.testformats.{};", test.class).unwrap();
+        let exception = test
+            .exception
+            .map(|exception| match split_exception(exception).0 {
+                "EndOfStreamError" => "java.nio.BufferUnderflowException".to_string(),
+                name => format!("KaitaiStream.{}", name),
+            });
+        if exception
+            .as_ref()
+            .is_some_and(|exception| exception.starts_with("KaitaiStream."))
+        {
+            writeln!(out, "import io.kaitai.struct.KaitaiStream;").unwrap();
+        }
+        let parse = format!("{}.fromFile(SRC_DIR + \"{}\")", test.class, test.data);
+        match self.framework {
+            JavaFramework::TestNg => {
+                writeln!(out, "import org.testng.annotations.Test;").unwrap();
+                writeln!(out, "import static org.testng.Assert.*;\n").unwrap();
+                writeln!(out, "public class {} extends CommonSpec {{", class_name).unwrap();
+                match &exception {
+                    Some(exception) => {
+                        writeln!(out, "    @Test(expectedExceptions = {}.class)", exception)
+                    }
+                    None => writeln!(out, "    @Test"),
+                }
+                .unwrap();
+            }
+            JavaFramework::JUnit => {
+                writeln!(out, "import org.junit.jupiter.api.Test;").unwrap();
+                writeln!(out, "import static org.junit.jupiter.api.Assertions.*;\n").unwrap();
+                writeln!(out, "public class {} {{", class_name).unwrap();
+                writeln!(
+                    out,
+                    "    private static final String SRC_DIR = \"../../src/\";\n"
+                )
+                .unwrap();
+                writeln!(out, "    @Test").unwrap();
+            }
+        }
+        writeln!(
+            out,
+            "    public void test{}() throws Exception {{",
+            upper_camel(&test.name)
+        )
+        .unwrap();
+        match (&exception, self.framework) {
+            (Some(exception), JavaFramework::JUnit) => {
+                writeln!(
+                    out,
+                    "        assertThrows({}.class, () -> {});",
+                    exception, parse
+                )
+                .unwrap();
+            }
+            _ => {
+                writeln!(out, "        {} r = {};", test.class, parse).unwrap();
+                if exception.is_none() && !test.asserts.is_empty() {
+                    writeln!(out).unwrap();
+                    for assert in &test.asserts {
+                        writeln!(out, "        {}", self.assert(assert)).unwrap();
+                    }
+                }
+            }
+        }
+        writeln!(out, "    }}\n}}").unwrap();
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::harness::emit;
+    use crate::kst::KstSpec;
+
+    const KST: &str = "\
+id: enum_case
+data: enum_case_1.bin
+asserts:
+  - actual: header.kind
+    expected: header::kind::small
+  - actual: items[0]
+    expected: '[0x50, 0x4b]'
+  - actual: ratio
+    expected: 0.1
+    epsilon: 1e-6
+";
+
+    #[test]
+    fn testng() {
+        let spec = KstSpec::from_yaml_str(KST).unwrap();
+        let test = emit(&JavaEmitter::default(), &spec).unwrap();
+        assert_eq!(
+            test.path,
+            "spec/java/src/io/kaitai/struct/spec/TestEnumCase1.java"
+        );
+        for line in [
+            "import org.testng.annotations.Test;",
+            "public class TestEnumCase1 extends CommonSpec {",
+            "    public void testEnumCase1() throws Exception {",
+            "        EnumCase r = EnumCase.fromFile(SRC_DIR + \"enum_case_1.bin\");",
+            "        assertEquals(r.header().kind(), EnumCase.Header.Kind.SMALL);",
+            "        assertEquals(r.items().get(0), new byte[] { 80, 75 });",
+            "        assertEquals(r.ratio(), 0.1, 1e-6);",
+        ] {
+            assert!(test.source.contains(line), "{}", line);
+        }
+
+        let mut failing = spec;
+        failing.exception = Some("ValidationNotEqualError<u1>".to_string());
+        let source = emit(&JavaEmitter::default(), &failing).unwrap().source;
+        assert!(source.contains("import io.kaitai.struct.KaitaiStream;"));
+        assert!(source.contains(
+            "    @Test(expectedExceptions = KaitaiStream.ValidationNotEqualError.class)"
+        ));
+        // asserts of failing tests are never reached
+        assert!(!source.contains("assertEquals"));
+    }
+
+    #[test]
+    fn junit() {
+        let emitter = JavaEmitter {
+            framework: JavaFramework::JUnit,
+        };
+        let mut spec = KstSpec::from_yaml_str(KST).unwrap();
+        let source = emit(&emitter, &spec).unwrap().source;
+        for line in [
+            "import static org.junit.jupiter.api.Assertions.*;",
+            "public class TestEnumCase1 {",
+            "    private static final String SRC_DIR = \"../../src/\";",
+            "        assertEquals(EnumCase.Header.Kind.SMALL, r.header().kind());",
+            "        assertArrayEquals(new byte[] { 80, 75 }, r.items().get(0));",
+            "        assertEquals(0.1, r.ratio(), 1e-6);",
+        ] {
+            assert!(source.contains(line), "{}", line);
+        }
+
+        spec.exception = Some("EndOfStreamError".to_string());
+        let source = emit(&emitter, &spec).unwrap().source;
+        assert!(source.contains(
+            "        assertThrows(java.nio.BufferUnderflowException.class, \
+             () -> EnumCase.fromFile(SRC_DIR + \"enum_case_1.bin\"));"
+        ));
+        assert!(!source.contains("KaitaiStream"));
+    }
+}
@@ -0,0 +1,260 @@
+//! JavaScript tests run by mocha: CommonJS files with the test helpers of the upstream suite, or
+//! ES modules that import the compiled (UMD) class and the runtime themselves.
+//!
+//! The runtime reads 64-bit integers into numbers, which only hold integers up to 2^53 exactly,
+//! so expected integers beyond are BigInt literals, compared to the value converted to BigInt.
+
+use std::fmt::Write;
+
+use crate::target::Target;
+use crate::translator::native::upper_camel;
+
+use super::{split_exception, HarnessAssert, HarnessEmitter, HarnessTest, HEADER};
+
+/// Largest integer that a JS number holds exactly (`Number.MAX_SAFE_INTEGER`)
+const MAX_SAFE_INTEGER: u64 = (1 << 53) - 1;
+
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum JsModule {
+    /// CommonJS, with `testHelper` and `testHelperThrows` of the upstream suite
+    #[default]
+    CommonJs,
+    EsModule,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct JavaScriptEmitter {
+    pub module: JsModule,
+}
+
+/// BigInt literal of an integer literal (possibly negated, in parentheses) beyond the ones a
+/// number holds exactly.
+fn bigint_literal(literal: &str) -> Option<String> {
+    let literal = literal.trim_start_matches('(').trim_end_matches(')');
+    let digits = literal.strip_prefix('-').unwrap_or(literal);
+    match digits.parse::<u64>() {
+        Ok(x) if x > MAX_SAFE_INTEGER => Some(format!("{}n", literal)),
+        _ => None,
+    }
+}
+
+fn assert_statement(assert: &HarnessAssert) -> String {
+    if let Some(epsilon) = assert.epsilon {
+        return format!(
+            "assert(Math.abs({} - {}) <= {:?});",
+            assert.actual, assert.expected, epsilon
+        );
+    }
+    if assert.bytes {
+        return format!(
+            "assert.deepStrictEqual(Array.from({}), {});",
+            assert.actual, assert.expected
+        );
+    }
+    match bigint_literal(&assert.expected) {
+        Some(expected) => format!(
+            "assert.strictEqual(BigInt({}), {});",
+            assert.actual, expected
+        ),
+        None => format!(
+            "assert.strictEqual({}, {});",
+            assert.actual, assert.expected
+        ),
+    }
+}
+
+impl HarnessEmitter for JavaScriptEmitter {
+    fn target(&self) -> Target {
+        Target::JavaScript
+    }
+
+    fn path(&self, test: &HarnessTest) -> String {
+        match self.module {
+            JsModule::CommonJs => format!("spec/javascript/test_{}.js", test.name),
+            JsModule::EsModule => format!("spec/javascript/test_{}.mjs", test.name),
+        }
+    }
+
+    fn source(&self, test: &HarnessTest) -> String {
+        let exception = test
+            .exception
+            .map(|exception| match split_exception(exception).0 {
+                "EndOfStreamError" => "EOFError",
+                name => name,
+            });
+        let mut out = String::new();
+        writeln!(out, "// {}\n", HEADER).unwrap();
+        match self.module {
+            JsModule::CommonJs => {
+                if let Some(exception) = exception {
+                    writeln!(out, "var testHelperThrows = require('testHelperThrows');").unwrap();
+                    writeln!(
+                        out,
+                        "var KaitaiStream = require('kaitai-struct/KaitaiStream');\n"
+                    )
+                    .unwrap();
+                    writeln!(
+                        out,
+                        "testHelperThrows('{}', 'src/{}', KaitaiStream.{});",
+                        test.class, test.data, exception
+                    )
+                    .unwrap();
+                    return out;
+                }
+                writeln!(out, "var assert = require('assert');").unwrap();
+                writeln!(out, "var testHelper = require('testHelper');\n").unwrap();
+                writeln!(
+                    out,
+                    "testHelper('{class}', 'src/{data}', function(r, {class}) {{",
+                    class = test.class,
+                    data = test.data
+                )
+                .unwrap();
+                for assert in &test.asserts {
+                    writeln!(out, "  {}", assert_statement(assert)).unwrap();
+                }
+                writeln!(out, "}});").unwrap();
+            }
+            JsModule::EsModule => {
+                writeln!(out, "import assert from 'node:assert';").unwrap();
+                writeln!(out, "import {{ readFileSync }} from 'node:fs';").unwrap();
+                writeln!(
+                    out,
+                    "import KaitaiStream from 'kaitai-struct/KaitaiStream.js';"
+                )
+                .unwrap();
+                writeln!(
+                    out,
+                    "import {class}Module from '../../compiled/javascript/{class}.js';\n",
+                    class = test.class
+                )
+                .unwrap();
+                writeln!(
+                    out,
+                    "const {{ {class} }} = {class}Module;\n",
+                    class = test.class
+                )
+                .unwrap();
+                writeln!(out, "describe('{}', function() {{", upper_camel(&test.name)).unwrap();
+                writeln!(out, "  it('parses test properly', function() {{").unwrap();
+                let parse = format!(
+                    "new {}(new KaitaiStream(readFileSync('src/{}')))",
+                    test.class, test.data
+                );
+                match exception {
+                    Some(exception) => writeln!(
+                        out,
+                        "    assert.throws(() => {}, KaitaiStream.{});",
+                        parse, exception
+                    )
+                    .unwrap(),
+                    None => {
+                        writeln!(out, "    const r = {};\n", parse).unwrap();
+                        for assert in &test.asserts {
+                            writeln!(out, "    {}", assert_statement(assert)).unwrap();
+                        }
+                    }
+                }
+                writeln!(out, "  }});\n}});").unwrap();
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::harness::emit;
+    use crate::kst::KstSpec;
+
+    const KST: &str = "\
+id: enum_case
+data: enum_case_1.bin
+asserts:
+  - actual: header.kind
+    expected: header::kind::small
+  - actual: items[0]
+    expected: '[0x50, 0x4b]'
+  - actual: big
+    expected: 18446744073709551615
+  - actual: small
+    expected: -9007199254740991
+  - actual: ratio
+    expected: 0.1
+    epsilon: 1e-6
+";
+
+    #[test]
+    fn common_js() {
+        let spec = KstSpec::from_yaml_str(KST).unwrap();
+        let test = emit(&JavaScriptEmitter::default(), &spec).unwrap();
+        assert_eq!(test.path, "spec/javascript/test_enum_case_1.js");
+        assert_eq!(
+            test.source,
+            format!(
+                "// {}\n\n\
+                 var assert = require('assert');\n\
+                 var testHelper = require('testHelper');\n\n\
+                 testHelper('EnumCase', 'src/enum_case_1.bin', function(r, EnumCase) {{\n  \
+                 assert.strictEqual(r.header.kind, EnumCase.Header.Kind.SMALL);\n  \
+                 assert.deepStrictEqual(Array.from(r.items[0]), [80, 75]);\n  \
+                 assert.strictEqual(BigInt(r.big), 18446744073709551615n);\n  \
+                 assert.strictEqual(r.small, (-9007199254740991));\n  \
+                 assert(Math.abs(r.ratio - 0.1) <= 1e-6);\n\
+                 }});\n",
+                HEADER
+            )
+        );
+    }
+
+    #[test]
+    fn es_module() {
+        let emitter = JavaScriptEmitter {
+            module: JsModule::EsModule,
+        };
+        let mut spec = KstSpec::from_yaml_str(KST).unwrap();
+        spec.asserts.truncate(1);
+        let test = emit(&emitter, &spec).unwrap();
+        assert_eq!(test.path, "spec/javascript/test_enum_case_1.mjs");
+        assert_eq!(
+            test.source,
+            format!(
+                "// {}\n\n\
+                 import assert from 'node:assert';\n\
+                 import {{ readFileSync }} from 'node:fs';\n\
+                 import KaitaiStream from 'kaitai-struct/KaitaiStream.js';\n\
+                 import EnumCaseModule from '../../compiled/javascript/EnumCase.js';\n\n\
+                 const {{ EnumCase }} = EnumCaseModule;\n\n\
+                 describe('EnumCase1', function() {{\n  \
+                 it('parses test properly', function() {{\n    \
+                 const r = new EnumCase(new KaitaiStream(readFileSync('src/enum_case_1.bin')));\n\n    \
+                 assert.strictEqual(r.header.kind, EnumCase.Header.Kind.SMALL);\n  \
+                 }});\n\
+                 }});\n",
+                HEADER
+            )
+        );
+
+        spec.exception = Some("ValidationNotEqualError<u1>".to_string());
+        let source = emit(&emitter, &spec).unwrap().source;
+        assert!(source.contains(
+            "    assert.throws(() => new EnumCase(new KaitaiStream(\
+             readFileSync('src/enum_case_1.bin'))), KaitaiStream.ValidationNotEqualError);\n"
+        ));
+    }
+
+    #[test]
+    fn bigint_literals() {
+        assert_eq!(bigint_literal("9007199254740991"), None);
+        assert_eq!(
+            bigint_literal("9007199254740992"),
+            Some("9007199254740992n".to_string())
+        );
+        assert_eq!(
+            bigint_literal("(-9223372036854775808)"),
+            Some("-9223372036854775808n".to_string())
+        );
+        assert_eq!(bigint_literal("1e300"), None);
+    }
+}
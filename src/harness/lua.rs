@@ -0,0 +1,135 @@
+//! Lua tests with LuaUnit, like the ones of the upstream suite: a test class per data file,
+//! with the parsing function passed to the error asserts rather than called in a closure.
+//! Arrays are tables indexed from 1, which the translator accounts for in the asserted paths.
+
+use std::fmt::Write;
+
+use crate::target::Target;
+use crate::translator::native::upper_camel;
+
+use super::{split_exception, HarnessEmitter, HarnessTest, HEADER};
+
+/// Pattern (of `string.find`) of the message of the error that the runtime raises when reading
+/// beyond the end of the stream
+const EOF_PATTERN: &str = ".+: requested %d+ bytes, but only %d+ bytes available";
+
+#[derive(Clone, Debug, Default)]
+pub struct LuaEmitter;
+
+impl HarnessEmitter for LuaEmitter {
+    fn target(&self) -> Target {
+        Target::Lua
+    }
+
+    fn path(&self, test: &HarnessTest) -> String {
+        format!("spec/lua/test_{}.lua", test.name)
+    }
+
+    fn source(&self, test: &HarnessTest) -> String {
+        let mut out = String::new();
+        writeln!(out, "-- {}\n", HEADER).unwrap();
+        writeln!(out, "local luaunit = require(\"luaunit\")\n").unwrap();
+        writeln!(out, "require(\"{}\")\n", test.id).unwrap();
+        let test_class = format!("Test{}", upper_camel(&test.name));
+        writeln!(out, "{} = {{}}\n", test_class).unwrap();
+        writeln!(out, "function {}:test_{}()", test_class, test.name).unwrap();
+        let src = format!("\"src/{}\"", test.data);
+        if let Some(exception) = test.exception {
+            let parse = format!("{class}.from_file, {class}, {src}", class = test.class);
+            if split_exception(exception).0 == "EndOfStreamError" {
+                writeln!(
+                    out,
+                    "    luaunit.assertErrorMsgMatches(\"{}\", {})",
+                    EOF_PATTERN, parse
+                )
+                .unwrap();
+            } else {
+                writeln!(out, "    -- {}", exception).unwrap();
+                writeln!(out, "    luaunit.assertError({})", parse).unwrap();
+            }
+        } else {
+            writeln!(out, "    local r = {}:from_file({})", test.class, src).unwrap();
+            if !test.asserts.is_empty() {
+                writeln!(out).unwrap();
+            }
+            for assert in &test.asserts {
+                match assert.epsilon {
+                    Some(epsilon) => writeln!(
+                        out,
+                        "    luaunit.assertAlmostEquals({}, {}, {:?})",
+                        assert.actual, assert.expected, epsilon
+                    ),
+                    None => writeln!(
+                        out,
+                        "    luaunit.assertEquals({}, {})",
+                        assert.actual, assert.expected
+                    ),
+                }
+                .unwrap();
+            }
+        }
+        writeln!(out, "end").unwrap();
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::harness::emit;
+    use crate::kst::KstSpec;
+
+    const KST: &str = "\
+id: enum_case
+data: enum_case_1.bin
+asserts:
+  - actual: header.kind
+    expected: header::kind::small
+  - actual: blocks[0].items[2]
+    expected: 7
+  - actual: magic
+    expected: '[0x50, 0x4b]'
+  - actual: ratio
+    expected: 0.1
+    epsilon: 1e-6
+";
+
+    #[test]
+    fn luaunit() {
+        let mut spec = KstSpec::from_yaml_str(KST).unwrap();
+        let test = emit(&LuaEmitter, &spec).unwrap();
+        assert_eq!(test.path, "spec/lua/test_enum_case_1.lua");
+        assert_eq!(
+            test.source,
+            format!(
+                "-- {}\n\n\
+                 local luaunit = require(\"luaunit\")\n\n\
+                 require(\"enum_case\")\n\n\
+                 TestEnumCase1 = {{}}\n\n\
+                 function TestEnumCase1:test_enum_case_1()\n    \
+                 local r = EnumCase:from_file(\"src/enum_case_1.bin\")\n\n    \
+                 luaunit.assertEquals(r.header.kind, EnumCase.Header.Kind.small)\n    \
+                 luaunit.assertEquals(r.blocks[1].items[3], 7)\n    \
+                 luaunit.assertEquals(r.magic, \"\\x50\\x4b\")\n    \
+                 luaunit.assertAlmostEquals(r.ratio, 0.1, 1e-6)\n\
+                 end\n",
+                HEADER
+            )
+        );
+
+        spec.exception = Some("EndOfStreamError".to_string());
+        let source = emit(&LuaEmitter, &spec).unwrap().source;
+        assert!(source.contains(&format!(
+            "    luaunit.assertErrorMsgMatches(\"{}\", \
+             EnumCase.from_file, EnumCase, \"src/enum_case_1.bin\")\n",
+            EOF_PATTERN
+        )));
+
+        spec.exception = Some("ValidationNotEqualError<u1>".to_string());
+        let source = emit(&LuaEmitter, &spec).unwrap().source;
+        assert!(source.contains(
+            "    -- ValidationNotEqualError<u1>\n    \
+             luaunit.assertError(EnumCase.from_file, EnumCase, \"src/enum_case_1.bin\")\n"
+        ));
+    }
+}
@@ -0,0 +1,107 @@
+//! Nim tests with the `unittest` module: a suite per data file, with `check`s that report every
+//! value that differs rather than stopping at the first one.
+
+use std::fmt::Write;
+
+use crate::target::Target;
+use crate::translator::native::upper_camel;
+
+use super::{split_exception, HarnessEmitter, HarnessTest, HEADER};
+
+#[derive(Clone, Debug, Default)]
+pub struct NimEmitter;
+
+impl HarnessEmitter for NimEmitter {
+    fn target(&self) -> Target {
+        Target::Nim
+    }
+
+    fn path(&self, test: &HarnessTest) -> String {
+        format!("spec/nim/tests/t_{}.nim", test.name)
+    }
+
+    fn source(&self, test: &HarnessTest) -> String {
+        let mut out = String::new();
+        writeln!(out, "# {}\n", HEADER).unwrap();
+        writeln!(out, "import unittest, os, streams, options, sequtils").unwrap();
+        writeln!(out, "import ../../compiled/nim/{}", test.id).unwrap();
+        writeln!(out, "import auxiliary/test_utils\n").unwrap();
+        writeln!(out, "suite \"{}\":", upper_camel(&test.name)).unwrap();
+        writeln!(out, "  test \"parses test properly\":").unwrap();
+        let parse = format!("{}.fromFile(\"../../src/{}\")", test.class, test.data);
+        if let Some(exception) = test.exception {
+            let exception = match split_exception(exception).0 {
+                "EndOfStreamError" => "IOError",
+                _ => "KaitaiError",
+            };
+            writeln!(out, "    expect({}):\n      discard {}", exception, parse).unwrap();
+        } else {
+            writeln!(out, "    let r = {}", parse).unwrap();
+            if !test.asserts.is_empty() {
+                writeln!(out).unwrap();
+            }
+            for assert in &test.asserts {
+                match assert.epsilon {
+                    Some(epsilon) => writeln!(
+                        out,
+                        "    check abs({} - {}) <= {:?}",
+                        assert.actual, assert.expected, epsilon
+                    ),
+                    None => writeln!(out, "    check {} == {}", assert.actual, assert.expected),
+                }
+                .unwrap();
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::harness::emit;
+    use crate::kst::KstSpec;
+
+    const KST: &str = "\
+id: enum_case
+data: enum_case_1.bin
+asserts:
+  - actual: header.kind
+    expected: header::kind::small
+  - actual: magic
+    expected: '[0x50, 0x4b]'
+  - actual: ratio
+    expected: 0.1
+    epsilon: 1e-6
+";
+
+    #[test]
+    fn unittest() {
+        let mut spec = KstSpec::from_yaml_str(KST).unwrap();
+        let test = emit(&NimEmitter, &spec).unwrap();
+        assert_eq!(test.path, "spec/nim/tests/t_enum_case_1.nim");
+        assert_eq!(
+            test.source,
+            format!(
+                "# {}\n\n\
+                 import unittest, os, streams, options, sequtils\n\
+                 import ../../compiled/nim/enum_case\n\
+                 import auxiliary/test_utils\n\n\
+                 suite \"EnumCase1\":\n  \
+                 test \"parses test properly\":\n    \
+                 let r = EnumCase.fromFile(\"../../src/enum_case_1.bin\")\n\n    \
+                 check r.header.kind == EnumCase_Header_Kind.small\n    \
+                 check r.magic == @[80'u8, 75'u8]\n    \
+                 check abs(r.ratio - 0.1) <= 1e-6\n",
+                HEADER
+            )
+        );
+
+        spec.exception = Some("EndOfStreamError".to_string());
+        let source = emit(&NimEmitter, &spec).unwrap().source;
+        assert!(source.contains(
+            "    expect(IOError):\n      \
+             discard EnumCase.fromFile(\"../../src/enum_case_1.bin\")\n"
+        ));
+    }
+}
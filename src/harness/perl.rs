@@ -0,0 +1,198 @@
+//! Perl tests, as Test::Class classes like the ones of the upstream suite or as plain Test::More
+//! scripts that find the compiled module themselves.
+//!
+//! The runtime reads byte arrays into byte strings and decodes strings into text strings, so the
+//! sources `use utf8` for their non-ASCII string literals to be text strings too, and byte arrays
+//! compare as hex dumps, which tells them apart from text and shows the bytes when they differ.
+
+use std::fmt::Write;
+
+use crate::target::Target;
+use crate::translator::native::upper_camel;
+
+use super::{HarnessAssert, HarnessEmitter, HarnessTest, HEADER};
+
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum PerlFramework {
+    #[default]
+    TestClass,
+    TestMore,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct PerlEmitter {
+    pub framework: PerlFramework,
+}
+
+fn assert_statement(assert: &HarnessAssert) -> String {
+    match assert.epsilon {
+        Some(epsilon) => format!(
+            "cmp_ok(abs({} - {}), '<=', {:?}, 'Approximately equals');",
+            assert.actual, assert.expected, epsilon
+        ),
+        None if assert.bytes => format!(
+            "is(unpack('H*', {}), unpack('H*', {}), 'Equals');",
+            assert.actual, assert.expected
+        ),
+        None => format!("is({}, {}, 'Equals');", assert.actual, assert.expected),
+    }
+}
+
+impl HarnessEmitter for PerlEmitter {
+    fn target(&self) -> Target {
+        Target::Perl
+    }
+
+    fn root(&self) -> &'static str {
+        "$r"
+    }
+
+    fn path(&self, test: &HarnessTest) -> String {
+        match self.framework {
+            PerlFramework::TestClass => format!("spec/perl/Test{}.t", upper_camel(&test.name)),
+            PerlFramework::TestMore => format!("spec/perl/test_{}.t", test.name),
+        }
+    }
+
+    fn source(&self, test: &HarnessTest) -> String {
+        let asserts = if test.exception.is_none() {
+            &test.asserts[..]
+        } else {
+            &[]
+        };
+        let utf8 = asserts.iter().any(|assert| !assert.expected.is_ascii());
+        let mut out = String::new();
+        writeln!(out, "# {}\n", HEADER).unwrap();
+        if self.framework == PerlFramework::TestClass {
+            writeln!(
+                out,
+                "package spec::perl::Test{};\n",
+                upper_camel(&test.name)
+            )
+            .unwrap();
+        }
+        writeln!(out, "use strict;\nuse warnings;").unwrap();
+        if utf8 {
+            writeln!(out, "use utf8;").unwrap();
+        }
+        let indent = match self.framework {
+            PerlFramework::TestClass => {
+                writeln!(out, "use base qw(Test::Class);\nuse Test::More;").unwrap();
+                writeln!(out, "use {};\n", test.class).unwrap();
+                let count = if test.exception.is_some() {
+                    1
+                } else {
+                    asserts.len()
+                };
+                writeln!(out, "sub test_{}: Test({}) {{", test.name, count).unwrap();
+                "    "
+            }
+            PerlFramework::TestMore => {
+                writeln!(out, "use FindBin;").unwrap();
+                writeln!(out, "use lib \"$FindBin::Bin/../../compiled/perl\";").unwrap();
+                writeln!(out, "use Test::More;").unwrap();
+                writeln!(out, "use {};\n", test.class).unwrap();
+                ""
+            }
+        };
+        let parse = format!("{}->from_file('src/{}')", test.class, test.data);
+        if let Some(exception) = test.exception {
+            // the runtime dies with messages, not exception objects
+            writeln!(out, "{}eval {{ {}; }};", indent, parse).unwrap();
+            writeln!(out, "{}ok($@, '{}');", indent, exception).unwrap();
+        } else {
+            writeln!(out, "{}my $r = {};", indent, parse).unwrap();
+            if !asserts.is_empty() {
+                writeln!(out).unwrap();
+            }
+            for assert in asserts {
+                writeln!(out, "{}{}", indent, assert_statement(assert)).unwrap();
+            }
+        }
+        match self.framework {
+            PerlFramework::TestClass => writeln!(out, "}}\n\nTest::Class->runtests;"),
+            PerlFramework::TestMore => writeln!(out, "\ndone_testing();"),
+        }
+        .unwrap();
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::harness::emit;
+    use crate::kst::KstSpec;
+
+    const KST: &str = "\
+id: enum_case
+data: enum_case_1.bin
+asserts:
+  - actual: magic
+    expected: '[0x50, 0x4b]'
+  - actual: name
+    expected: '\"été\"'
+  - actual: ratio
+    expected: 0.1
+    epsilon: 1e-6
+";
+
+    #[test]
+    fn test_class() {
+        let mut spec = KstSpec::from_yaml_str(KST).unwrap();
+        let test = emit(&PerlEmitter::default(), &spec).unwrap();
+        assert_eq!(test.path, "spec/perl/TestEnumCase1.t");
+        assert_eq!(
+            test.source,
+            format!(
+                "# {}\n\n\
+                 package spec::perl::TestEnumCase1;\n\n\
+                 use strict;\n\
+                 use warnings;\n\
+                 use utf8;\n\
+                 use base qw(Test::Class);\n\
+                 use Test::More;\n\
+                 use EnumCase;\n\n\
+                 sub test_enum_case_1: Test(3) {{\n    \
+                 my $r = EnumCase->from_file('src/enum_case_1.bin');\n\n    \
+                 is(unpack('H*', $r->magic()), unpack('H*', pack('C*', (80, 75))), 'Equals');\n    \
+                 is($r->name(), \"été\", 'Equals');\n    \
+                 cmp_ok(abs($r->ratio() - 0.1), '<=', 1e-6, 'Approximately equals');\n\
+                 }}\n\n\
+                 Test::Class->runtests;\n",
+                HEADER
+            )
+        );
+
+        spec.asserts.truncate(1);
+        let source = emit(&PerlEmitter::default(), &spec).unwrap().source;
+        assert!(!source.contains("use utf8;"));
+    }
+
+    #[test]
+    fn test_more() {
+        let emitter = PerlEmitter {
+            framework: PerlFramework::TestMore,
+        };
+        let mut spec = KstSpec::from_yaml_str(KST).unwrap();
+        spec.exception = Some("EndOfStreamError".to_string());
+        let test = emit(&emitter, &spec).unwrap();
+        assert_eq!(test.path, "spec/perl/test_enum_case_1.t");
+        assert_eq!(
+            test.source,
+            format!(
+                "# {}\n\n\
+                 use strict;\n\
+                 use warnings;\n\
+                 use FindBin;\n\
+                 use lib \"$FindBin::Bin/../../compiled/perl\";\n\
+                 use Test::More;\n\
+                 use EnumCase;\n\n\
+                 eval {{ EnumCase->from_file('src/enum_case_1.bin'); }};\n\
+                 ok($@, 'EndOfStreamError');\n\n\
+                 done_testing();\n",
+                HEADER
+            )
+        );
+    }
+}
@@ -0,0 +1,163 @@
+//! PHP tests with PHPUnit, in the namespace that the upstream suite compiles the formats into.
+//!
+//! Values compare with `assertSame`, which doesn't coerce, as integers and byte arrays (strings)
+//! are read into values of the same type as their literals. Floats are the exception: an
+//! expression that PHP evaluates with integers only yields an integer even if KS types it as a
+//! float, so expected floats compare with `assertEquals`, which compares numbers by value.
+
+use std::fmt::Write;
+
+use crate::target::Target;
+use crate::translator::native::upper_camel;
+
+use super::{split_exception, HarnessAssert, HarnessEmitter, HarnessTest, HEADER};
+
+#[derive(Clone, Debug, Default)]
+pub struct PhpEmitter;
+
+/// Whether the (possibly negated, in parentheses) literal is a float literal.
+fn is_float_literal(literal: &str) -> bool {
+    let literal = literal.trim_start_matches('(').trim_end_matches(')');
+    literal.parse::<i64>().is_err() && literal.parse::<f64>().is_ok()
+}
+
+fn assert_statement(assert: &HarnessAssert) -> String {
+    let method = match assert.epsilon {
+        Some(epsilon) => {
+            return format!(
+                "$this->assertEqualsWithDelta({}, {}, {:?});",
+                assert.expected, assert.actual, epsilon
+            )
+        }
+        None if is_float_literal(&assert.expected) => "assertEquals",
+        None => "assertSame",
+    };
+    format!("$this->{}({}, {});", method, assert.expected, assert.actual)
+}
+
+impl HarnessEmitter for PhpEmitter {
+    fn target(&self) -> Target {
+        Target::Php
+    }
+
+    fn root(&self) -> &'static str {
+        "$r"
+    }
+
+    fn path(&self, test: &HarnessTest) -> String {
+        format!("spec/php/{}Test.php", upper_camel(&test.name))
+    }
+
+    fn source(&self, test: &HarnessTest) -> String {
+        let mut out = String::new();
+        writeln!(out, "<?php\n// {}\n", HEADER).unwrap();
+        writeln!(out, "namespace Kaitai\\Struct\\Tests;\n").unwrap();
+        // the compiled class, for runs without the autoloader of the suite
+        writeln!(
+            out,
+            "require_once __DIR__ . '/../../compiled/php/{}.php';\n",
+            test.class
+        )
+        .unwrap();
+        writeln!(
+            out,
+            "class {}Test extends TestCase {{",
+            upper_camel(&test.name)
+        )
+        .unwrap();
+        writeln!(
+            out,
+            "    public function test{}() {{",
+            upper_camel(&test.name)
+        )
+        .unwrap();
+        if let Some(exception) = test.exception {
+            writeln!(
+                out,
+                "        $this->expectException(\\Kaitai\\Struct\\Error\\{}::class);",
+                split_exception(exception).0
+            )
+            .unwrap();
+        }
+        writeln!(
+            out,
+            "        $r = {}::fromFile(self::SRC_DIR_PATH . '/{}');",
+            test.class, test.data
+        )
+        .unwrap();
+        if test.exception.is_none() && !test.asserts.is_empty() {
+            writeln!(out).unwrap();
+            for assert in &test.asserts {
+                writeln!(out, "        {}", assert_statement(assert)).unwrap();
+            }
+        }
+        writeln!(out, "    }}\n}}").unwrap();
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::harness::emit;
+    use crate::kst::KstSpec;
+
+    const KST: &str = "\
+id: enum_case
+data: enum_case_1.bin
+asserts:
+  - actual: header.kind
+    expected: header::kind::small
+  - actual: magic
+    expected: '[0x50, 0x4b]'
+  - actual: big
+    expected: -9223372036854775808
+  - actual: half
+    expected: 2.0
+  - actual: ratio
+    expected: 0.1
+    epsilon: 1e-6
+";
+
+    #[test]
+    fn phpunit() {
+        let mut spec = KstSpec::from_yaml_str(KST).unwrap();
+        let test = emit(&PhpEmitter, &spec).unwrap();
+        assert_eq!(test.path, "spec/php/EnumCase1Test.php");
+        assert_eq!(
+            test.source,
+            format!(
+                "<?php\n// {}\n\n\
+                 namespace Kaitai\\Struct\\Tests;\n\n\
+                 require_once __DIR__ . '/../../compiled/php/EnumCase.php';\n\n\
+                 class EnumCase1Test extends TestCase {{\n    \
+                 public function testEnumCase1() {{\n        \
+                 $r = EnumCase::fromFile(self::SRC_DIR_PATH . '/enum_case_1.bin');\n\n        \
+                 $this->assertSame(EnumCase\\Header\\Kind::SMALL, $r->header()->kind());\n        \
+                 $this->assertSame(\"\\x50\\x4b\", $r->magic());\n        \
+                 $this->assertSame(PHP_INT_MIN, $r->big());\n        \
+                 $this->assertEquals(2.0, $r->half());\n        \
+                 $this->assertEqualsWithDelta(0.1, $r->ratio(), 1e-6);\n    \
+                 }}\n\
+                 }}\n",
+                HEADER
+            )
+        );
+
+        spec.exception = Some("ValidationNotEqualError<u1>".to_string());
+        let source = emit(&PhpEmitter, &spec).unwrap().source;
+        assert!(source.contains(
+            "        $this->expectException(\
+             \\Kaitai\\Struct\\Error\\ValidationNotEqualError::class);\n"
+        ));
+        assert!(!source.contains("assertSame"));
+    }
+
+    #[test]
+    fn float_literals() {
+        assert!(is_float_literal("(-4.5)"));
+        assert!(is_float_literal("1e300"));
+        assert!(!is_float_literal("(-4)"));
+        assert!(!is_float_literal("\"4.5\""));
+    }
+}
@@ -0,0 +1,197 @@
+//! Python tests, as unittest classes like the ones of the upstream suite or as plain pytest
+//! functions. Either imports the compiled module (named after the `meta/id`) and parses the data
+//! with `from_file`.
+
+use std::fmt::Write;
+
+use crate::target::Target;
+use crate::translator::native::upper_camel;
+
+use super::{split_exception, HarnessAssert, HarnessEmitter, HarnessTest, HEADER};
+
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum PythonStyle {
+    /// `unittest.TestCase` classes
+    #[default]
+    Unittest,
+    /// Test functions with plain asserts, for pytest
+    Pytest,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct PythonEmitter {
+    pub style: PythonStyle,
+}
+
+impl PythonEmitter {
+    fn assert(&self, assert: &HarnessAssert) -> String {
+        match (self.style, assert.epsilon) {
+            (PythonStyle::Unittest, Some(epsilon)) => format!(
+                "self.assertAlmostEqual({}, {}, delta={:?})",
+                assert.actual, assert.expected, epsilon
+            ),
+            (PythonStyle::Unittest, None) => {
+                format!("self.assertEqual({}, {})", assert.actual, assert.expected)
+            }
+            (PythonStyle::Pytest, Some(epsilon)) => format!(
+                "assert {} == pytest.approx({}, abs={:?})",
+                assert.actual, assert.expected, epsilon
+            ),
+            (PythonStyle::Pytest, None) => {
+                format!("assert {} == {}", assert.actual, assert.expected)
+            }
+        }
+    }
+}
+
+impl HarnessEmitter for PythonEmitter {
+    fn target(&self) -> Target {
+        Target::Python
+    }
+
+    fn path(&self, test: &HarnessTest) -> String {
+        format!("spec/python/test_{}.py", test.name)
+    }
+
+    fn source(&self, test: &HarnessTest) -> String {
+        let exception = test
+            .exception
+            .map(|exception| match split_exception(exception).0 {
+                "EndOfStreamError" => "EOFError".to_string(),
+                name => format!("kaitaistruct.{}", name),
+            });
+        let mut imports = Vec::new();
+        let (indent, raises) = match self.style {
+            PythonStyle::Unittest => {
+                imports.push("unittest");
+                ("        ", "self.assertRaises")
+            }
+            PythonStyle::Pytest => {
+                let approx = test.asserts.iter().any(|assert| assert.epsilon.is_some());
+                if exception.is_some() || approx {
+                    imports.push("pytest");
+                }
+                ("    ", "pytest.raises")
+            }
+        };
+        if exception
+            .as_ref()
+            .is_some_and(|e| e.starts_with("kaitaistruct."))
+        {
+            imports.push("kaitaistruct");
+        }
+        let mut out = String::new();
+        writeln!(out, "# {}\n", HEADER).unwrap();
+        if !imports.is_empty() {
+            for module in imports {
+                writeln!(out, "import {}", module).unwrap();
+            }
+            writeln!(out).unwrap();
+        }
+        writeln!(out, "from {} import {}\n", test.id, test.class).unwrap();
+        match self.style {
+            PythonStyle::Unittest => {
+                writeln!(
+                    out,
+                    "class Test{}(unittest.TestCase):",
+                    upper_camel(&test.name)
+                )
+                .unwrap();
+                writeln!(out, "    def test_{}(self):", test.name).unwrap();
+            }
+            PythonStyle::Pytest => writeln!(out, "def test_{}():", test.name).unwrap(),
+        }
+        let parse = format!("{}.from_file('src/{}')", test.class, test.data);
+        if let Some(exception) = exception {
+            writeln!(out, "{}with {}({}):", indent, raises, exception).unwrap();
+            writeln!(out, "{}    with {} as r:", indent, parse).unwrap();
+            writeln!(out, "{}        pass", indent).unwrap();
+        } else {
+            writeln!(out, "{}with {} as r:", indent, parse).unwrap();
+            if test.asserts.is_empty() {
+                writeln!(out, "{}    pass", indent).unwrap();
+            }
+            for assert in &test.asserts {
+                writeln!(out, "{}    {}", indent, self.assert(assert)).unwrap();
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::harness::emit;
+    use crate::kst::KstSpec;
+
+    const KST: &str = "\
+id: enum_case
+data: enum_case_1.bin
+asserts:
+  - actual: header.kind
+    expected: header::kind::small
+  - actual: items[0]
+    expected: '[0x50, 0x4b]'
+  - actual: ratio
+    expected: 0.1
+    epsilon: 1e-6
+";
+
+    #[test]
+    fn unittest() {
+        let spec = KstSpec::from_yaml_str(KST).unwrap();
+        let test = emit(&PythonEmitter::default(), &spec).unwrap();
+        assert_eq!(test.path, "spec/python/test_enum_case_1.py");
+        assert_eq!(
+            test.source,
+            format!(
+                "# {}\n\n\
+                 import unittest\n\n\
+                 from enum_case import EnumCase\n\n\
+                 class TestEnumCase1(unittest.TestCase):\n    \
+                 def test_enum_case_1(self):\n        \
+                 with EnumCase.from_file('src/enum_case_1.bin') as r:\n            \
+                 self.assertEqual(r.header.kind, EnumCase.Header.Kind.small)\n            \
+                 self.assertEqual(r.items[0], b\"\\x50\\x4b\")\n            \
+                 self.assertAlmostEqual(r.ratio, 0.1, delta=1e-6)\n",
+                HEADER
+            )
+        );
+    }
+
+    #[test]
+    fn pytest() {
+        let emitter = PythonEmitter {
+            style: PythonStyle::Pytest,
+        };
+        let mut spec = KstSpec::from_yaml_str(KST).unwrap();
+        assert_eq!(
+            emit(&emitter, &spec).unwrap().source,
+            format!(
+                "# {}\n\n\
+                 import pytest\n\n\
+                 from enum_case import EnumCase\n\n\
+                 def test_enum_case_1():\n    \
+                 with EnumCase.from_file('src/enum_case_1.bin') as r:\n        \
+                 assert r.header.kind == EnumCase.Header.Kind.small\n        \
+                 assert r.items[0] == b\"\\x50\\x4b\"\n        \
+                 assert r.ratio == pytest.approx(0.1, abs=1e-6)\n",
+                HEADER
+            )
+        );
+
+        spec.asserts.truncate(1);
+        let source = emit(&emitter, &spec).unwrap().source;
+        assert!(source.starts_with(&format!("# {}\n\nfrom enum_case", HEADER)));
+
+        spec.exception = Some("ValidationNotEqualError<u1>".to_string());
+        let source = emit(&emitter, &spec).unwrap().source;
+        assert!(source.contains("import pytest\nimport kaitaistruct\n\nfrom enum_case"));
+        assert!(source.contains(
+            "    with pytest.raises(kaitaistruct.ValidationNotEqualError):\n        \
+             with EnumCase.from_file('src/enum_case_1.bin') as r:\n            \
+             pass\n"
+        ));
+    }
+}
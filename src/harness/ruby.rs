@@ -0,0 +1,190 @@
+//! Ruby tests, as RSpec specs like the ones of the upstream suite or as Minitest classes. Both
+//! require the compiled file (named after the `meta/id`) and parse the data with `from_file`.
+//! Enum values are symbols prefixed with the enum name, like `:animal_cat`.
+
+use std::fmt::Write;
+
+use crate::target::Target;
+use crate::translator::native::upper_camel;
+
+use super::{split_exception, HarnessAssert, HarnessEmitter, HarnessTest, HEADER};
+
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum RubyFramework {
+    #[default]
+    RSpec,
+    Minitest,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct RubyEmitter {
+    pub framework: RubyFramework,
+}
+
+impl RubyEmitter {
+    fn assert(&self, assert: &HarnessAssert) -> String {
+        match (self.framework, assert.epsilon) {
+            (RubyFramework::RSpec, Some(epsilon)) => format!(
+                "expect({}).to be_within({:?}).of({})",
+                assert.actual, epsilon, assert.expected
+            ),
+            (RubyFramework::RSpec, None) => {
+                format!("expect({}).to eq {}", assert.actual, assert.expected)
+            }
+            (RubyFramework::Minitest, Some(epsilon)) => format!(
+                "assert_in_delta {}, {}, {:?}",
+                assert.expected, assert.actual, epsilon
+            ),
+            (RubyFramework::Minitest, None) => {
+                format!("assert_equal {}, {}", assert.expected, assert.actual)
+            }
+        }
+    }
+}
+
+impl HarnessEmitter for RubyEmitter {
+    fn target(&self) -> Target {
+        Target::Ruby
+    }
+
+    fn path(&self, test: &HarnessTest) -> String {
+        match self.framework {
+            RubyFramework::RSpec => format!("spec/ruby/{}_spec.rb", test.name),
+            RubyFramework::Minitest => format!("spec/ruby/test_{}.rb", test.name),
+        }
+    }
+
+    fn source(&self, test: &HarnessTest) -> String {
+        let mut out = String::new();
+        writeln!(out, "# {}\n", HEADER).unwrap();
+        match self.framework {
+            RubyFramework::RSpec => {
+                writeln!(out, "RSpec.describe '{}' do", test.class).unwrap();
+                writeln!(out, "  it 'parses test properly' do").unwrap();
+                writeln!(out, "    require '{}'", test.id).unwrap();
+            }
+            RubyFramework::Minitest => {
+                writeln!(out, "require 'minitest/autorun'").unwrap();
+                writeln!(out, "require '{}'\n", test.id).unwrap();
+                writeln!(
+                    out,
+                    "class Test{} < Minitest::Test",
+                    upper_camel(&test.name)
+                )
+                .unwrap();
+                writeln!(out, "  def test_{}", test.name).unwrap();
+            }
+        }
+        let parse = format!("{}.from_file('src/{}')", test.class, test.data);
+        if let Some(exception) = test.exception {
+            let exception = match split_exception(exception).0 {
+                "EndOfStreamError" => "EOFError".to_string(),
+                name => format!("Kaitai::Struct::{}", name),
+            };
+            match self.framework {
+                RubyFramework::RSpec => writeln!(
+                    out,
+                    "    expect {{ {} }}.to raise_error({})",
+                    parse, exception
+                ),
+                RubyFramework::Minitest => {
+                    writeln!(out, "    assert_raises({}) {{ {} }}", exception, parse)
+                }
+            }
+            .unwrap();
+        } else {
+            writeln!(out, "    r = {}", parse).unwrap();
+            if !test.asserts.is_empty() {
+                writeln!(out).unwrap();
+            }
+            for assert in &test.asserts {
+                writeln!(out, "    {}", self.assert(assert)).unwrap();
+            }
+        }
+        writeln!(out, "  end\nend").unwrap();
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::harness::emit;
+    use crate::kst::KstSpec;
+
+    const KST: &str = "\
+id: enum_case
+data: enum_case_1.bin
+asserts:
+  - actual: header.kind
+    expected: header::kind::small
+  - actual: items[0]
+    expected: '[0x50, 0x4b]'
+  - actual: ratio
+    expected: 0.1
+    epsilon: 1e-6
+";
+
+    #[test]
+    fn rspec() {
+        let mut spec = KstSpec::from_yaml_str(KST).unwrap();
+        let test = emit(&RubyEmitter::default(), &spec).unwrap();
+        assert_eq!(test.path, "spec/ruby/enum_case_1_spec.rb");
+        assert_eq!(
+            test.source,
+            format!(
+                "# {}\n\n\
+                 RSpec.describe 'EnumCase' do\n  \
+                 it 'parses test properly' do\n    \
+                 require 'enum_case'\n    \
+                 r = EnumCase.from_file('src/enum_case_1.bin')\n\n    \
+                 expect(r.header.kind).to eq :kind_small\n    \
+                 expect(r.items[0]).to eq [80, 75].pack('C*')\n    \
+                 expect(r.ratio).to be_within(1e-6).of(0.1)\n  \
+                 end\n\
+                 end\n",
+                HEADER
+            )
+        );
+
+        spec.exception = Some("ValidationNotEqualError<u1>".to_string());
+        let source = emit(&RubyEmitter::default(), &spec).unwrap().source;
+        assert!(source.contains(
+            "    expect { EnumCase.from_file('src/enum_case_1.bin') }\
+             .to raise_error(Kaitai::Struct::ValidationNotEqualError)\n"
+        ));
+    }
+
+    #[test]
+    fn minitest() {
+        let emitter = RubyEmitter {
+            framework: RubyFramework::Minitest,
+        };
+        let mut spec = KstSpec::from_yaml_str(KST).unwrap();
+        let test = emit(&emitter, &spec).unwrap();
+        assert_eq!(test.path, "spec/ruby/test_enum_case_1.rb");
+        assert_eq!(
+            test.source,
+            format!(
+                "# {}\n\n\
+                 require 'minitest/autorun'\n\
+                 require 'enum_case'\n\n\
+                 class TestEnumCase1 < Minitest::Test\n  \
+                 def test_enum_case_1\n    \
+                 r = EnumCase.from_file('src/enum_case_1.bin')\n\n    \
+                 assert_equal :kind_small, r.header.kind\n    \
+                 assert_equal [80, 75].pack('C*'), r.items[0]\n    \
+                 assert_in_delta 0.1, r.ratio, 1e-6\n  \
+                 end\n\
+                 end\n",
+                HEADER
+            )
+        );
+
+        spec.exception = Some("EndOfStreamError".to_string());
+        let source = emit(&emitter, &spec).unwrap().source;
+        assert!(source.contains(
+            "    assert_raises(EOFError) { EnumCase.from_file('src/enum_case_1.bin') }\n"
+        ));
+    }
+}
@@ -0,0 +1,176 @@
+//! Rust tests, run by `cargo test`. Parsing returns a `KResult`, which either holds the parsed
+//! object or, for tests of exceptions, the `KError` that matches the one of the KST file.
+
+use std::fmt::Write;
+
+use crate::target::Target;
+
+use super::{split_exception, HarnessAssert, HarnessEmitter, HarnessTest, HEADER};
+
+#[derive(Clone, Debug, Default)]
+pub struct RustEmitter;
+
+/// Pattern of the `KError` of a runtime exception, if the runtime has one for it.
+fn error_pattern(exception: &str) -> Option<String> {
+    let kind = match split_exception(exception).0 {
+        "EndOfStreamError" => return Some("KError::Eof { .. }".to_string()),
+        "ValidationNotEqualError" => "NotEqual",
+        "ValidationLessThanError" => "LessThan",
+        "ValidationGreaterThanError" => "GreaterThan",
+        "ValidationNotAnyOfError" => "NotAnyOf",
+        "ValidationNotInEnumError" => "NotInEnum",
+        "ValidationExprError" => "Expr",
+        _ => return None,
+    };
+    Some(format!(
+        "KError::ValidationFailed(ValidationFailedError {{ kind: ValidationKind::{}, .. }})",
+        kind
+    ))
+}
+
+fn assert_statement(assert: &HarnessAssert) -> String {
+    // accessors return references to the values, which a subscript already goes through
+    let value = if assert.actual.ends_with(')') {
+        format!("*{}", assert.actual)
+    } else {
+        assert.actual.clone()
+    };
+    match assert.epsilon {
+        Some(epsilon) => format!(
+            "assert!(({} - {}).abs() <= {:?});",
+            value, assert.expected, epsilon
+        ),
+        None if assert.bytes => format!(
+            "assert_eq!(&{}[..], &{}[..]);",
+            assert.actual, assert.expected
+        ),
+        None => format!("assert_eq!({}, {});", value, assert.expected),
+    }
+}
+
+impl HarnessEmitter for RustEmitter {
+    fn target(&self) -> Target {
+        Target::Rust
+    }
+
+    fn path(&self, test: &HarnessTest) -> String {
+        format!("spec/rust/tests/test_{}.rs", test.name)
+    }
+
+    fn source(&self, test: &HarnessTest) -> String {
+        let mut out = String::new();
+        writeln!(out, "// {}\n", HEADER).unwrap();
+        writeln!(out, "use std::fs;\n").unwrap();
+        writeln!(out, "extern crate kaitai;\nuse self::kaitai::*;").unwrap();
+        writeln!(out, "mod formats;\nuse formats::{}::*;\n", test.id).unwrap();
+        writeln!(out, "#[test]\nfn test_{}() {{", test.name).unwrap();
+        writeln!(
+            out,
+            "    let bytes = fs::read(\"../../src/{}\").unwrap();",
+            test.data
+        )
+        .unwrap();
+        writeln!(out, "    let _io = BytesReader::from(bytes);").unwrap();
+        writeln!(
+            out,
+            "    let res: KResult<OptRc<{class}>> = {class}::read_into(&_io, None, None);",
+            class = test.class
+        )
+        .unwrap();
+        if let Some(exception) = test.exception {
+            writeln!(
+                out,
+                "    let err = res.expect_err(\"expected {}\");",
+                exception
+            )
+            .unwrap();
+            if let Some(pattern) = error_pattern(exception) {
+                writeln!(
+                    out,
+                    "    assert!(matches!(err, {}), \"{{:?}}\", err);",
+                    pattern
+                )
+                .unwrap();
+            }
+        } else {
+            writeln!(out, "    let r = res.unwrap();").unwrap();
+            if !test.asserts.is_empty() {
+                writeln!(out).unwrap();
+            }
+            for assert in &test.asserts {
+                writeln!(out, "    {}", assert_statement(assert)).unwrap();
+            }
+        }
+        writeln!(out, "}}").unwrap();
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::harness::emit;
+    use crate::kst::KstSpec;
+
+    const KST: &str = "\
+id: enum_case
+data: enum_case_1.bin
+asserts:
+  - actual: header.kind
+    expected: header::kind::small
+  - actual: magic
+    expected: '[0x50, 0x4b]'
+  - actual: items[1]
+    expected: 7
+  - actual: ratio
+    expected: 0.1
+    epsilon: 1e-6
+";
+
+    #[test]
+    fn cargo_test() {
+        let mut spec = KstSpec::from_yaml_str(KST).unwrap();
+        let test = emit(&RustEmitter, &spec).unwrap();
+        assert_eq!(test.path, "spec/rust/tests/test_enum_case_1.rs");
+        assert_eq!(
+            test.source,
+            format!(
+                "// {}\n\n\
+                 use std::fs;\n\n\
+                 extern crate kaitai;\n\
+                 use self::kaitai::*;\n\
+                 mod formats;\n\
+                 use formats::enum_case::*;\n\n\
+                 #[test]\n\
+                 fn test_enum_case_1() {{\n    \
+                 let bytes = fs::read(\"../../src/enum_case_1.bin\").unwrap();\n    \
+                 let _io = BytesReader::from(bytes);\n    \
+                 let res: KResult<OptRc<EnumCase>> = EnumCase::read_into(&_io, None, None);\n    \
+                 let r = res.unwrap();\n\n    \
+                 assert_eq!(*r.header().kind(), EnumCase_Header_Kind::Small);\n    \
+                 assert_eq!(&r.magic()[..], &vec![80u8, 75u8][..]);\n    \
+                 assert_eq!(r.items()[1], 7);\n    \
+                 assert!((*r.ratio() - 0.1).abs() <= 1e-6);\n\
+                 }}\n",
+                HEADER
+            )
+        );
+
+        spec.exception = Some("ValidationNotEqualError<u1>".to_string());
+        let source = emit(&RustEmitter, &spec).unwrap().source;
+        assert!(source.contains(
+            "    let err = res.expect_err(\"expected ValidationNotEqualError<u1>\");\n    \
+             assert!(matches!(err, KError::ValidationFailed(ValidationFailedError \
+             { kind: ValidationKind::NotEqual, .. })), \"{:?}\", err);\n"
+        ));
+    }
+
+    #[test]
+    fn error_patterns() {
+        assert_eq!(
+            error_pattern("EndOfStreamError").as_deref(),
+            Some("KError::Eof { .. }")
+        );
+        assert_eq!(error_pattern("UndecidedEndiannessError"), None);
+    }
+}
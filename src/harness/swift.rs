@@ -0,0 +1,123 @@
+//! Swift tests with XCTest, in the test target of a Swift package whose `TestFormats` target holds
+//! the compiled formats.
+
+use std::fmt::Write;
+
+use crate::target::Target;
+use crate::translator::native::upper_camel;
+
+use super::{HarnessEmitter, HarnessTest, HEADER};
+
+/// Directory of the test target, where SwiftPM finds the tests
+const TESTS_DIR: &str = "spec/swift/Tests/TestFormatsTests";
+
+#[derive(Clone, Debug, Default)]
+pub struct SwiftEmitter;
+
+impl HarnessEmitter for SwiftEmitter {
+    fn target(&self) -> Target {
+        Target::Swift
+    }
+
+    fn path(&self, test: &HarnessTest) -> String {
+        format!("{}/{}Tests.swift", TESTS_DIR, upper_camel(&test.name))
+    }
+
+    fn source(&self, test: &HarnessTest) -> String {
+        let mut out = String::new();
+        writeln!(out, "// {}\n", HEADER).unwrap();
+        writeln!(out, "import XCTest\n@testable import TestFormats\n").unwrap();
+        writeln!(
+            out,
+            "final class {}Tests: XCTestCase {{",
+            upper_camel(&test.name)
+        )
+        .unwrap();
+        writeln!(out, "    func test{}() throws {{", upper_camel(&test.name)).unwrap();
+        let parse = format!("{}.fromFile(path: \"src/{}\")", test.class, test.data);
+        if let Some(exception) = test.exception {
+            writeln!(
+                out,
+                "        XCTAssertThrowsError(try {}, \"expected {}\")",
+                parse, exception
+            )
+            .unwrap();
+        } else {
+            writeln!(out, "        let r = try {}", parse).unwrap();
+            if !test.asserts.is_empty() {
+                writeln!(out).unwrap();
+            }
+            for assert in &test.asserts {
+                match assert.epsilon {
+                    Some(epsilon) => writeln!(
+                        out,
+                        "        XCTAssertEqual({}, {}, accuracy: {:?})",
+                        assert.actual, assert.expected, epsilon
+                    ),
+                    None => writeln!(
+                        out,
+                        "        XCTAssertEqual({}, {})",
+                        assert.actual, assert.expected
+                    ),
+                }
+                .unwrap();
+            }
+        }
+        writeln!(out, "    }}\n}}").unwrap();
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::harness::emit;
+    use crate::kst::KstSpec;
+
+    const KST: &str = "\
+id: enum_case
+data: enum_case_1.bin
+asserts:
+  - actual: header.kind
+    expected: header::kind::small
+  - actual: magic
+    expected: '[0x50, 0x4b]'
+  - actual: ratio
+    expected: 0.1
+    epsilon: 1e-6
+";
+
+    #[test]
+    fn xctest() {
+        let mut spec = KstSpec::from_yaml_str(KST).unwrap();
+        let test = emit(&SwiftEmitter, &spec).unwrap();
+        assert_eq!(
+            test.path,
+            "spec/swift/Tests/TestFormatsTests/EnumCase1Tests.swift"
+        );
+        assert_eq!(
+            test.source,
+            format!(
+                "// {}\n\n\
+                 import XCTest\n\
+                 @testable import TestFormats\n\n\
+                 final class EnumCase1Tests: XCTestCase {{\n    \
+                 func testEnumCase1() throws {{\n        \
+                 let r = try EnumCase.fromFile(path: \"src/enum_case_1.bin\")\n\n        \
+                 XCTAssertEqual(r.header.kind, EnumCase.Header.Kind.small)\n        \
+                 XCTAssertEqual(r.magic, [UInt8]([80, 75]))\n        \
+                 XCTAssertEqual(r.ratio, 0.1, accuracy: 1e-6)\n    \
+                 }}\n\
+                 }}\n",
+                HEADER
+            )
+        );
+
+        spec.exception = Some("ValidationNotEqualError<u1>".to_string());
+        let source = emit(&SwiftEmitter, &spec).unwrap().source;
+        assert!(source.contains(
+            "        XCTAssertThrowsError(try EnumCase.fromFile(path: \"src/enum_case_1.bin\"), \
+             \"expected ValidationNotEqualError<u1>\")\n"
+        ));
+    }
+}
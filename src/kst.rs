@@ -0,0 +1,662 @@
+//! Support for Kaitai Struct Test specs (KST), the language-neutral test format used by
+//! https://github.com/kaitai-io/kaitai_struct_tests.
+
+use std::collections::VecDeque;
+use std::path::Path;
+use std::{fs, io};
+
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use thiserror::Error;
+
+use crate::ast::utils::PositiveFiniteF64;
+use crate::ast::Expr;
+use crate::datagen::interpret::{read, ReadError};
+use crate::datagen::{field_assertions, value_assertions};
+use crate::eval::{eval, Env, EvalError, Value};
+use crate::gen::suite::{GenCase, GenInput, Outcome, ParseError};
+use crate::ksy::TypeSpec;
+use crate::tolerance::{comparison_for, FloatComparison};
+use crate::translator::translate;
+use path::paths_exist;
+
+pub mod diff;
+pub mod path;
+
+#[derive(Clone, Debug, Error, PartialEq)]
+pub enum LiteralError {
+    #[error(transparent)]
+    Eval(#[from] EvalError),
+    #[error("non-finite float {0} has no literal")]
+    NonFiniteFloat(f64),
+    #[error("enum `{}` has no member with value {value}", enum_path.join("::"))]
+    UnknownEnumValue { enum_path: Vec<String>, value: i128 },
+    #[error("struct values have no literal")]
+    Struct,
+}
+
+#[derive(Debug, Error)]
+pub enum KstError {
+    #[error("can't read the KST spec: {0}")]
+    Io(#[from] io::Error),
+    #[error("invalid KST spec: {0}")]
+    Yaml(#[from] serde_yaml::Error),
+}
+
+/// KST spec of one input of a generated case (or one read from a `.kst` file). Its data file
+/// holds the bytes of the input.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct KstSpec {
+    /// `meta/id` of the ksy spec to parse the data with
+    pub id: String,
+    /// Name of the data file, relative to the directory of data files
+    pub data: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exception: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub asserts: Vec<KstAssert>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct KstAssert {
+    pub actual: String,
+    #[serde(serialize_with = "scalar", deserialize_with = "scalar_literal")]
+    pub expected: String,
+    /// Largest difference from a float `expected` value that passes, if the comparison isn't
+    /// exact
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub epsilon: Option<f64>,
+}
+
+impl KstSpec {
+    pub fn to_yaml(&self) -> String {
+        serde_yaml::to_string(self).expect("KST model must be serializable to YAML")
+    }
+
+    pub fn from_yaml_str(s: &str) -> Result<Self, KstError> {
+        Ok(serde_yaml::from_str(s)?)
+    }
+
+    /// Loads a `.kst` file, like the ones of the upstream test suite.
+    pub fn load(path: &Path) -> Result<Self, KstError> {
+        Self::from_yaml_str(&fs::read_to_string(path)?)
+    }
+}
+
+/// Serializes a literal that YAML would read as a number or a boolean as one, the way
+/// hand-written KST files have them, and other literals as strings.
+fn scalar<S: Serializer>(literal: &str, serializer: S) -> Result<S::Ok, S::Error> {
+    if let Ok(x) = literal.parse::<i64>() {
+        return serializer.serialize_i64(x);
+    }
+    if let Ok(x) = literal.parse::<u64>() {
+        return serializer.serialize_u64(x);
+    }
+    if let Ok(x) = literal.parse::<bool>() {
+        return serializer.serialize_bool(x);
+    }
+    match literal.parse::<f64>() {
+        // only if the number reads back the same, so that the literal stays as it is
+        Ok(x) if x.is_finite() && format!("{:?}", x) == literal => serializer.serialize_f64(x),
+        _ => serializer.serialize_str(literal),
+    }
+}
+
+/// Literal of an `expected` value, which YAML may read as a number or a boolean instead of a
+/// string.
+fn scalar_literal<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    match serde_yaml::Value::deserialize(deserializer)? {
+        serde_yaml::Value::String(literal) => Ok(literal),
+        serde_yaml::Value::Number(x) => Ok(x.to_string()),
+        serde_yaml::Value::Bool(x) => Ok(x.to_string()),
+        value => Err(D::Error::custom(format!(
+            "expected value must be a scalar, not {:?}",
+            value
+        ))),
+    }
+}
+
+/// KST specs of the inputs of the case, in order. The data of the first one is `<id>.bin`, of
+/// the others `<id>_<index>.bin`.
+pub fn case_specs(case: &GenCase) -> Result<Vec<KstSpec>, LiteralError> {
+    let env = spec_env(&case.spec);
+    case.inputs
+        .iter()
+        .enumerate()
+        .map(|(index, input)| {
+            let exception = match &input.outcome {
+                Outcome::Values(_) => None,
+                Outcome::Error(error) => Some(exception(case, input, *error)),
+            };
+            let asserts = expected_values(case, input)
+                .into_iter()
+                .map(|expectation| {
+                    Ok(KstAssert {
+                        actual: translate(&expectation.actual),
+                        expected: literal(&expectation.expected, &env)?,
+                        epsilon: match expectation.comparison {
+                            FloatComparison::Exact => None,
+                            FloatComparison::Approx { epsilon } => Some(epsilon),
+                        },
+                    })
+                })
+                .collect::<Result<_, LiteralError>>()?;
+            Ok(KstSpec {
+                id: case.id.clone(),
+                data: data_file(case, index),
+                exception,
+                asserts,
+            })
+        })
+        .collect()
+}
+
+/// Value that an expression is expected to have after parsing.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Expectation {
+    pub actual: Expr,
+    pub expected: Value,
+    /// How to compare a float value, as chosen by [`comparison_for`]; exact for other values
+    pub comparison: FloatComparison,
+}
+
+/// Expected values of parsing the input, for its asserts: the values of its outcome, then those
+/// of the fields that the built-in interpreter reads from the data and the outcome doesn't
+/// cover. Values without a literal are checked by conditions expected to be true.
+pub fn expected_values(case: &GenCase, input: &GenInput) -> Vec<Expectation> {
+    let Outcome::Values(values) = &input.outcome else {
+        return Vec::new();
+    };
+    let mut env = spec_env(&case.spec);
+    let mut expected: Vec<(Expr, Value)> = values
+        .iter()
+        .flat_map(|(expr, value)| value_assertions(expr.clone(), value))
+        .collect();
+    // specs reading other specs are beyond the interpreter
+    if case.extra_specs.is_empty() {
+        if let Ok(fields) = read(&case.spec, &input.data) {
+            for (expr, value) in field_assertions(&fields) {
+                let covered = expected.iter().any(|(covered, _)| *covered == expr);
+                // fields of types that a switch doesn't have in common can't be reached
+                if !covered && paths_exist(&case.spec, &case.extra_specs, &expr) {
+                    expected.push((expr, value));
+                }
+            }
+            for (name, value) in fields {
+                env.set(name, value);
+            }
+        }
+    }
+    expected
+        .into_iter()
+        .map(|(actual, expected)| {
+            let comparison = match expected {
+                Value::Float(_) => comparison_for(&actual, &env),
+                _ => FloatComparison::Exact,
+            };
+            Expectation {
+                actual,
+                expected,
+                comparison,
+            }
+        })
+        .collect()
+}
+
+/// Name of the data file of the input of the case with the index.
+pub fn data_file(case: &GenCase, index: usize) -> String {
+    if index == 0 {
+        format!("{}.bin", case.id)
+    } else {
+        format!("{}_{}.bin", case.id, index)
+    }
+}
+
+/// Exception that parsing the input fails with. The kind of check that fails (and the type of
+/// the checked value) comes from the built-in interpreter, as outcomes don't tell.
+fn exception(case: &GenCase, input: &GenInput, error: ParseError) -> String {
+    if error == ParseError::Validation && case.extra_specs.is_empty() {
+        if let Err(ReadError::Validation(_, exception)) = read(&case.spec, &input.data) {
+            return exception;
+        }
+    }
+    exception_name(error).to_string()
+}
+
+/// Name of the exception in KST `exception` keys. Validation errors have a common name here,
+/// runtimes throw one of the kind of check failing, like `ValidationNotEqualError<u1>`.
+pub fn exception_name(error: ParseError) -> &'static str {
+    match error {
+        ParseError::EndOfStream => "EndOfStreamError",
+        ParseError::Validation => "ValidationError",
+        ParseError::UndecidedEndianness => "UndecidedEndiannessError",
+    }
+}
+
+/// Environment with the enums of the spec and its nested types. Values name enums the way the
+/// type they come from does, so nested enums are there by their name too unless a type closer to
+/// the top level has one with the same name.
+fn spec_env(spec: &TypeSpec) -> Env {
+    let mut env = Env::new();
+    let mut types = VecDeque::from([(Vec::new(), spec)]);
+    let mut names = Vec::new();
+    while let Some((path, ty)) = types.pop_front() {
+        for (name, members) in &ty.enums {
+            let mut enum_path: Vec<String> = path.clone();
+            enum_path.push(name.clone());
+            let members: Vec<_> = members
+                .iter()
+                .map(|(value, label)| (label.clone(), *value))
+                .collect();
+            if !names.contains(name) {
+                names.push(name.clone());
+                env.define_enum(vec![name.clone()], members.clone());
+            }
+            env.define_enum(enum_path, members);
+        }
+        for (name, nested) in &ty.types {
+            let mut nested_path = path.clone();
+            nested_path.push(name.clone());
+            types.push_back((nested_path, nested));
+        }
+    }
+    env
+}
+
+/// Evaluates the expression and renders the result as a KST `expected` value.
+pub fn eval_to_kst(expr: &Expr, env: &Env) -> Result<String, LiteralError> {
+    let value = eval(expr, env)?;
+    literal(&value, env)
+}
+
+/// Renders the value in the syntax of the KS expression language, as expected by the `expected`
+/// keys of KST asserts.
+pub fn literal(value: &Value, env: &Env) -> Result<String, LiteralError> {
+    match value {
+        Value::Int(x) => Ok(x.to_string()),
+        Value::Float(x) => {
+            let abs = PositiveFiniteF64::try_from(x.abs())
+                .map_err(|_| LiteralError::NonFiniteFloat(*x))?;
+            let sign = if x.is_sign_negative() && *x != 0.0 {
+                "-"
+            } else {
+                ""
+            };
+            Ok(format!("{}{}", sign, translate(&Expr::Float(abs))))
+        }
+        Value::Str(x) => Ok(str_literal(x)),
+        Value::Bool(x) => Ok(x.to_string()),
+        Value::Bytes(x) => Ok(bytes_literal(x)),
+        Value::Array(items) => Ok(format!(
+            "[{}]",
+            items
+                .iter()
+                .map(|item| literal(item, env))
+                .collect::<Result<Vec<_>, _>>()?
+                .join(", ")
+        )),
+        Value::Enum { enum_path, value } => match env.enum_label(enum_path, *value) {
+            Some(label) => Ok(format!("{}::{}", enum_path.join("::"), label)),
+            None => Err(LiteralError::UnknownEnumValue {
+                enum_path: enum_path.clone(),
+                value: *value,
+            }),
+        },
+        Value::Struct(_) => Err(LiteralError::Struct),
+    }
+}
+
+/// Double-quoted string literal. Unlike single-quoted ones, they can represent any string (see
+/// https://doc.kaitai.io/user_guide.html#_basic_data_types).
+fn str_literal(s: &str) -> String {
+    let mut result = String::with_capacity(s.len() + 2);
+    result.push('"');
+    for ch in s.chars() {
+        match ch {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            ch if ch.is_control() => result.push_str(&format!("\\u{:04x}", ch as u32)),
+            ch => result.push(ch),
+        }
+    }
+    result.push('"');
+    result
+}
+
+fn bytes_literal(bytes: &[u8]) -> String {
+    if bytes.is_empty() {
+        // an empty list literal has no element type, so it has to be cast explicitly
+        return "[].as<bytes>".to_string();
+    }
+    format!(
+        "[{}]",
+        bytes
+            .iter()
+            .map(|b| format!("0x{:02x}", b))
+            .collect::<Vec<_>>()
+            .join(", ")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::{BinaryOp, UnaryOp};
+    use indexmap::IndexMap;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn neg_int() {
+        let expr = Expr::UnaryOp {
+            op: UnaryOp::Neg,
+            value: Box::new(Expr::Int(5)),
+        };
+        assert_eq!(eval_to_kst(&expr, &Env::new()).unwrap(), "-5");
+    }
+
+    #[test]
+    fn float_division() {
+        let expr = Expr::BinaryOp {
+            l: Box::new(Expr::Int(7)),
+            op: BinaryOp::Div,
+            r: Box::new(Expr::Float(PositiveFiniteF64::try_from(2.0).unwrap())),
+        };
+        assert_eq!(eval_to_kst(&expr, &Env::new()).unwrap(), "3.5");
+    }
+
+    #[test]
+    fn whole_float_keeps_fraction() {
+        assert_eq!(literal(&Value::Float(-4.0), &Env::new()).unwrap(), "-4.0");
+    }
+
+    #[test]
+    fn str_with_quotes() {
+        let expr = Expr::Str(r#"a"b\c"#.to_string());
+        assert_eq!(eval_to_kst(&expr, &Env::new()).unwrap(), r#""a\"b\\c""#);
+    }
+
+    #[test]
+    fn bytes() {
+        let expr = Expr::List(vec![Expr::Int(0x41), Expr::Int(0)]);
+        assert_eq!(eval_to_kst(&expr, &Env::new()).unwrap(), "[0x41, 0x00]");
+        assert_eq!(
+            literal(&Value::Bytes(vec![]), &Env::new()).unwrap(),
+            "[].as<bytes>"
+        );
+    }
+
+    #[test]
+    fn enum_member() {
+        let mut env = Env::new();
+        env.define_enum(vec!["animal".to_string()], [("cat", 7), ("dog", 4)]);
+        let value = Value::Enum {
+            enum_path: vec!["animal".to_string()],
+            value: 4,
+        };
+        assert_eq!(literal(&value, &env).unwrap(), "animal::dog");
+    }
+
+    #[test]
+    fn nan() {
+        assert!(matches!(
+            literal(&Value::Float(f64::NAN), &Env::new()),
+            Err(LiteralError::NonFiniteFloat(_))
+        ));
+    }
+
+    #[test]
+    fn case_specs_of_inputs() {
+        use crate::gen::profile::{Feature, GenProfile};
+        use crate::gen::suite::generate_suite;
+
+        let mut spec = TypeSpec::top_level("enum_case");
+        let mut header = TypeSpec::default();
+        header.enums.insert(
+            "kind".to_string(),
+            IndexMap::from([(1, "small".to_string())]),
+        );
+        spec.types.insert("header".to_string(), header);
+        let input = |outcome| GenInput {
+            data: vec![1],
+            outcome,
+            source: None,
+            trace: Vec::new(),
+            verdicts: BTreeMap::new(),
+        };
+        let kind = Value::Enum {
+            enum_path: vec!["kind".to_string()],
+            value: 1,
+        };
+        let case = GenCase {
+            id: "enum_case".to_string(),
+            seed: 0,
+            feature: Feature::Enums,
+            spec,
+            extra_specs: Vec::new(),
+            inputs: vec![
+                input(Outcome::Values(vec![(Expr::Name("k".to_string()), kind)])),
+                input(Outcome::Error(ParseError::EndOfStream)),
+            ],
+        };
+        let specs = case_specs(&case).unwrap();
+        assert_eq!(
+            specs[0].to_yaml(),
+            "id: enum_case\n\
+             data: enum_case.bin\n\
+             asserts:\n\
+             - actual: k\n  \
+               expected: kind::small\n"
+        );
+        assert_eq!(
+            specs[1].to_yaml(),
+            "id: enum_case\ndata: enum_case_1.bin\nexception: EndOfStreamError\n"
+        );
+
+        for case in generate_suite(0, 20, &GenProfile::default()) {
+            let specs = case_specs(&case).unwrap();
+            assert_eq!(specs.len(), case.inputs.len());
+        }
+    }
+
+    #[test]
+    fn expected_values_of_data() {
+        use crate::gen::profile::Feature;
+        use crate::ksy::Attribute;
+
+        let mut spec = TypeSpec::top_level("floats");
+        spec.seq = vec![Attribute::new("a", "u1"), Attribute::new("b", "u1")];
+        let third = Expr::BinaryOp {
+            l: Box::new(Expr::Name("a".to_string())),
+            op: BinaryOp::Div,
+            r: Box::new(Expr::Float(PositiveFiniteF64::try_from(3.0).unwrap())),
+        };
+        let outcome = Outcome::Values(vec![
+            (Expr::Name("a".to_string()), Value::Int(1)),
+            (third, Value::Float(1.0 / 3.0)),
+        ]);
+        let case = GenCase {
+            id: "floats".to_string(),
+            seed: 0,
+            feature: Feature::Cast,
+            spec,
+            extra_specs: Vec::new(),
+            inputs: vec![GenInput {
+                data: vec![1, 2],
+                outcome,
+                source: None,
+                trace: Vec::new(),
+                verdicts: BTreeMap::new(),
+            }],
+        };
+        let spec = &case_specs(&case).unwrap()[0];
+        let asserts: Vec<(String, String)> = spec
+            .asserts
+            .iter()
+            .map(|assert| (assert.actual.clone(), assert.expected.clone()))
+            .collect();
+        assert_eq!(asserts[0], ("a".to_string(), "1".to_string()));
+        assert_eq!(asserts[1].1, "0.3333333333333333");
+        // `b` comes from reading the data
+        assert_eq!(asserts[2..], [("b".to_string(), "2".to_string())]);
+        // the division rounds, so the value is compared with a tolerance
+        let epsilons: Vec<bool> = spec.asserts.iter().map(|a| a.epsilon.is_some()).collect();
+        assert_eq!(epsilons, [false, true, false]);
+        assert!(
+            spec.to_yaml().contains("  epsilon: 2."),
+            "{}",
+            spec.to_yaml()
+        );
+    }
+
+    #[test]
+    fn validation_exceptions() {
+        use crate::gen::profile::Feature;
+        use crate::gen::valid::{valid_cases, ValidForm};
+        use rand::rngs::StdRng;
+        use rand::SeedableRng;
+
+        let mut rng = StdRng::seed_from_u64(0);
+        let names: Vec<String> = valid_cases(&mut rng)
+            .into_iter()
+            .map(|valid| {
+                let case = GenCase {
+                    id: valid.spec.id().unwrap().to_string(),
+                    seed: 0,
+                    feature: Feature::Valid,
+                    spec: valid.spec,
+                    extra_specs: Vec::new(),
+                    inputs: vec![GenInput {
+                        data: valid.fail,
+                        outcome: Outcome::Error(ParseError::Validation),
+                        source: None,
+                        trace: Vec::new(),
+                        verdicts: BTreeMap::new(),
+                    }],
+                };
+                case_specs(&case).unwrap()[0].exception.clone().unwrap()
+            })
+            .collect();
+        assert_eq!(names.len(), ValidForm::ALL.len());
+        assert_eq!(names[0], "ValidationNotEqualError<u1>");
+        assert!(names.contains(&"ValidationNotAnyOfError<u1>".to_string()));
+        assert!(names.contains(&"ValidationExprError<u1>".to_string()));
+    }
+
+    #[test]
+    fn golden_kst() {
+        use crate::gen::profile::Feature;
+
+        let mut spec = TypeSpec::top_level("literals");
+        spec.enums.insert(
+            "animal".to_string(),
+            IndexMap::from([(4, "dog".to_string()), (7, "cat".to_string())]),
+        );
+        let name = |name: &str| Expr::Name(name.to_string());
+        let values = vec![
+            (name("magic"), Value::Bytes(b"PACK-1".to_vec())),
+            (name("empty"), Value::Bytes(Vec::new())),
+            (
+                name("greeting"),
+                Value::Str("こんにちは, \"мир\"".to_string()),
+            ),
+            (
+                name("pet"),
+                Value::Enum {
+                    enum_path: vec!["animal".to_string()],
+                    value: 7,
+                },
+            ),
+            (name("neg"), Value::Int(-3)),
+            (name("big"), Value::Int(u64::MAX.into())),
+            (name("flag"), Value::Bool(false)),
+            (name("half"), Value::Float(-0.5)),
+            (name("tiny"), Value::Float(f64::MIN_POSITIVE)),
+        ];
+        let case = GenCase {
+            id: "literals".to_string(),
+            seed: 0,
+            feature: Feature::Enums,
+            spec,
+            extra_specs: Vec::new(),
+            inputs: vec![GenInput {
+                data: Vec::new(),
+                outcome: Outcome::Values(values),
+                source: None,
+                trace: Vec::new(),
+                verdicts: BTreeMap::new(),
+            }],
+        };
+        assert_eq!(
+            case_specs(&case).unwrap()[0].to_yaml(),
+            r#"id: literals
+data: literals.bin
+asserts:
+- actual: magic
+  expected: '[0x50, 0x41, 0x43, 0x4b, 0x2d, 0x31]'
+- actual: empty
+  expected: '[].as<bytes>'
+- actual: greeting
+  expected: '"こんにちは, \"мир\""'
+- actual: pet
+  expected: animal::cat
+- actual: neg
+  expected: -3
+- actual: big
+  expected: 18446744073709551615
+- actual: flag
+  expected: false
+- actual: half
+  expected: -0.5
+- actual: tiny
+  expected: 2.2250738585072014e-308
+"#
+        );
+    }
+
+    #[test]
+    fn read_kst() {
+        let text = "\
+id: str_encodings
+data: str_encodings.bin
+asserts:
+  - actual: str1
+    expected: '\"Some ASCII\"'
+  - actual: len_of_1
+    expected: 0x0a
+  - actual: ratio
+    expected: 0.5
+  - actual: flag
+    expected: true
+";
+        let mut spec = KstSpec::from_yaml_str(text).unwrap();
+        let expected: Vec<&str> = spec.asserts.iter().map(|a| a.expected.as_str()).collect();
+        assert_eq!(expected, ["\"Some ASCII\"", "10", "0.5", "true"]);
+        assert_eq!(spec.exception, None);
+
+        spec.asserts.truncate(1);
+        spec.exception = Some("EndOfStreamError".to_string());
+        assert_eq!(KstSpec::from_yaml_str(&spec.to_yaml()).unwrap(), spec);
+
+        let unknown_key = "id: a\ndata: a.bin\nimports: []\n";
+        assert!(matches!(
+            KstSpec::from_yaml_str(unknown_key),
+            Err(KstError::Yaml(_))
+        ));
+    }
+
+    #[test]
+    fn eval_error() {
+        let expr = Expr::Name("missing".to_string());
+        assert_eq!(
+            eval_to_kst(&expr, &Env::new()),
+            Err(LiteralError::Eval(EvalError::UnknownName(
+                "missing".to_string()
+            )))
+        );
+    }
+}
@@ -0,0 +1,308 @@
+//! Structural diff of KST specs, and merging generated asserts into hand-written specs. Asserts
+//! are matched by their `actual` expression, and expressions are compared parsed, so that
+//! `0x10` and `16` are the same expected value.
+
+use crate::ast::parser::parse_expr;
+use crate::translator::translate;
+
+use super::{KstAssert, KstError, KstSpec};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum AssertChange {
+    Added(KstAssert),
+    Removed(KstAssert),
+    /// Assert of the same `actual` expression with another expected value or epsilon
+    Changed {
+        old: KstAssert,
+        new: KstAssert,
+    },
+}
+
+/// Differences between two KST specs. Changed keys are `(old, new)` pairs.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct KstDiff {
+    pub id: Option<(String, String)>,
+    pub data: Option<(String, String)>,
+    pub exception: Option<(Option<String>, Option<String>)>,
+    /// Changes of the asserts, removed and changed ones in the order of the old spec, then
+    /// added ones in the order of the new spec
+    pub asserts: Vec<AssertChange>,
+}
+
+impl KstDiff {
+    pub fn is_empty(&self) -> bool {
+        *self == KstDiff::default()
+    }
+}
+
+/// Expression in a canonical form, or as it is if it doesn't parse.
+fn normalized(text: &str) -> String {
+    parse_expr(text).map_or_else(|_| text.to_string(), |expr| translate(&expr))
+}
+
+fn find<'a>(asserts: &'a [KstAssert], actual: &str) -> Option<&'a KstAssert> {
+    asserts
+        .iter()
+        .find(|assert| normalized(&assert.actual) == actual)
+}
+
+pub fn diff(old: &KstSpec, new: &KstSpec) -> KstDiff {
+    let changed = |old: &String, new: &String| (old != new).then(|| (old.clone(), new.clone()));
+    let mut asserts = Vec::new();
+    for old_assert in &old.asserts {
+        match find(&new.asserts, &normalized(&old_assert.actual)) {
+            None => asserts.push(AssertChange::Removed(old_assert.clone())),
+            Some(new_assert)
+                if normalized(&old_assert.expected) != normalized(&new_assert.expected)
+                    || old_assert.epsilon != new_assert.epsilon =>
+            {
+                asserts.push(AssertChange::Changed {
+                    old: old_assert.clone(),
+                    new: new_assert.clone(),
+                })
+            }
+            Some(_) => {}
+        }
+    }
+    for new_assert in &new.asserts {
+        if find(&old.asserts, &normalized(&new_assert.actual)).is_none() {
+            asserts.push(AssertChange::Added(new_assert.clone()));
+        }
+    }
+    KstDiff {
+        id: changed(&old.id, &new.id),
+        data: changed(&old.data, &new.data),
+        exception: (old.exception != new.exception)
+            .then(|| (old.exception.clone(), new.exception.clone())),
+        asserts,
+    }
+}
+
+/// Asserts of `generated` whose `actual` expression `existing` has no assert of.
+fn missing_asserts(existing: &KstSpec, generated: &KstSpec) -> Vec<KstAssert> {
+    generated
+        .asserts
+        .iter()
+        .filter(|assert| find(&existing.asserts, &normalized(&assert.actual)).is_none())
+        .cloned()
+        .collect()
+}
+
+/// `existing` with the asserts of `generated` that it has no assert of the same `actual`
+/// expression of appended. Everything `existing` has, including expected values that differ
+/// from the generated ones, stays as it is.
+pub fn merge(existing: &KstSpec, generated: &KstSpec) -> KstSpec {
+    let mut merged = existing.clone();
+    merged.asserts.extend(missing_asserts(existing, generated));
+    merged
+}
+
+/// The line without its indentation, unless it is blank or a comment.
+fn content(line: &str) -> Option<&str> {
+    let trimmed = line.trim_start();
+    (!trimmed.is_empty() && !trimmed.starts_with('#')).then_some(trimmed)
+}
+
+/// [`merge`] on the text of a KST file, which keeps its comments and formatting: the missing
+/// asserts are inserted at the end of its `asserts` list (which is added if there is none),
+/// indented like the items already in it. A list in flow style (`asserts: [...]`) is rewritten
+/// in block style, without the comments inside it.
+pub fn merge_yaml(existing: &str, generated: &KstSpec) -> Result<String, KstError> {
+    let spec = KstSpec::from_yaml_str(existing)?;
+    let missing = missing_asserts(&spec, generated);
+    if missing.is_empty() {
+        return Ok(existing.to_string());
+    }
+    let lines: Vec<&str> = existing.lines().collect();
+    let top_level =
+        |line: &str| !line.is_empty() && !line.starts_with([' ', '\t', '-', '#']) && line != "...";
+    let asserts_key = lines.iter().position(|line| line.starts_with("asserts:"));
+    let (replaced, indent, asserts, with_key) = match asserts_key {
+        Some(key) => {
+            let end = lines[key + 1..]
+                .iter()
+                .position(|line| top_level(line))
+                .map_or(lines.len(), |offset| key + 1 + offset);
+            // after the last item, not after the blank lines and comments that follow it
+            let end = (key + 1..end)
+                .rev()
+                .find(|&i| content(lines[i]).is_some())
+                .map_or(key + 1, |last| last + 1);
+            let value = content(&lines[key]["asserts:".len()..])
+                .or_else(|| lines[key + 1..end].iter().copied().find_map(content));
+            if value.is_some_and(|value| !value.starts_with('-')) {
+                let mut asserts = spec.asserts.clone();
+                asserts.extend(missing);
+                (key..end, "  ", asserts, true)
+            } else {
+                let indent = lines[key + 1..end]
+                    .iter()
+                    .find_map(|line| {
+                        let trimmed = line.trim_start();
+                        trimmed
+                            .starts_with('-')
+                            .then(|| &line[..line.len() - trimmed.len()])
+                    })
+                    .unwrap_or("  ");
+                (end..end, indent, missing, false)
+            }
+        }
+        None => (lines.len()..lines.len(), "  ", missing, true),
+    };
+
+    let mut inserted = Vec::new();
+    if with_key {
+        inserted.push("asserts:".to_string());
+    }
+    for assert in &asserts {
+        let yaml = serde_yaml::to_string(assert).expect("KST model must be serializable to YAML");
+        for (i, line) in yaml.lines().enumerate() {
+            let marker = if i == 0 { "- " } else { "  " };
+            inserted.push(format!("{}{}{}", indent, marker, line));
+        }
+    }
+    let mut merged: Vec<String> = lines.iter().map(|line| line.to_string()).collect();
+    merged.splice(replaced, inserted);
+    let mut out = merged.join("\n");
+    out.push('\n');
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert(actual: &str, expected: &str) -> KstAssert {
+        KstAssert {
+            actual: actual.to_string(),
+            expected: expected.to_string(),
+            epsilon: None,
+        }
+    }
+
+    fn spec(asserts: Vec<KstAssert>) -> KstSpec {
+        KstSpec {
+            id: "fixed".to_string(),
+            data: "fixed.bin".to_string(),
+            exception: None,
+            asserts,
+        }
+    }
+
+    #[test]
+    fn diff_of_asserts() {
+        let old = spec(vec![
+            assert("len", "0x10"),
+            assert("header . magic", "[0x50]"),
+            assert("gone", "1"),
+        ]);
+        let mut new = spec(vec![
+            assert("new_one", "true"),
+            assert("header.magic", "[0x51]"),
+            assert("len", "16"),
+        ]);
+        new.exception = Some("EndOfStreamError".to_string());
+        let diff = diff(&old, &new);
+        assert_eq!(
+            diff.asserts,
+            [
+                AssertChange::Changed {
+                    old: assert("header . magic", "[0x50]"),
+                    new: assert("header.magic", "[0x51]"),
+                },
+                AssertChange::Removed(assert("gone", "1")),
+                AssertChange::Added(assert("new_one", "true")),
+            ]
+        );
+        assert_eq!(
+            diff.exception,
+            Some((None, Some("EndOfStreamError".to_string())))
+        );
+        assert_eq!(diff.id, None);
+        assert!(super::diff(&old, &old).is_empty());
+    }
+
+    #[test]
+    fn merged_yaml() {
+        let existing = "\
+# hand-written
+id: fixed
+data: fixed.bin
+asserts:
+    - actual: len
+      expected: 0x10 # header says so
+
+    # more to come
+exception: EndOfStreamError
+";
+        let generated = spec(vec![assert("len", "17"), assert("items[0]", "[0x50]")]);
+        let merged = merge_yaml(existing, &generated).unwrap();
+        assert_eq!(
+            merged,
+            "\
+# hand-written
+id: fixed
+data: fixed.bin
+asserts:
+    - actual: len
+      expected: 0x10 # header says so
+    - actual: items[0]
+      expected: '[0x50]'
+
+    # more to come
+exception: EndOfStreamError
+"
+        );
+        let merged_spec = KstSpec::from_yaml_str(&merged).unwrap();
+        let expected = merge(&KstSpec::from_yaml_str(existing).unwrap(), &generated);
+        assert_eq!(merged_spec, expected);
+        assert_eq!(merge_yaml(&merged, &generated).unwrap(), merged);
+
+        let without_asserts = "id: fixed\ndata: fixed.bin\n";
+        assert_eq!(
+            merge_yaml(without_asserts, &generated).unwrap(),
+            "id: fixed\ndata: fixed.bin\nasserts:\n  - actual: len\n    expected: 17\n  \
+             - actual: items[0]\n    expected: '[0x50]'\n"
+        );
+    }
+
+    #[test]
+    fn merged_flow_yaml() {
+        let generated = spec(vec![assert("len", "17"), assert("items[0]", "[0x50]")]);
+        let empty = "id: fixed\nasserts: [] # none yet\ndata: fixed.bin\n";
+        assert_eq!(
+            merge_yaml(empty, &generated).unwrap(),
+            "id: fixed\nasserts:\n  - actual: len\n    expected: 17\n  \
+             - actual: items[0]\n    expected: '[0x50]'\ndata: fixed.bin\n"
+        );
+
+        let existing = "\
+id: fixed
+data: fixed.bin
+asserts: [{actual: len, expected: 16},
+  {actual: items.size, expected: 2}]
+
+exception: EndOfStreamError
+";
+        let merged = merge_yaml(existing, &generated).unwrap();
+        assert_eq!(
+            merged,
+            "\
+id: fixed
+data: fixed.bin
+asserts:
+  - actual: len
+    expected: 16
+  - actual: items.size
+    expected: 2
+  - actual: items[0]
+    expected: '[0x50]'
+
+exception: EndOfStreamError
+"
+        );
+        let merged_spec = KstSpec::from_yaml_str(&merged).unwrap();
+        let expected = merge(&KstSpec::from_yaml_str(existing).unwrap(), &generated);
+        assert_eq!(merged_spec, expected);
+    }
+}
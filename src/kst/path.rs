@@ -0,0 +1,297 @@
+//! `actual` paths of KST asserts, reaching attributes through nested types, instances and array
+//! items, like `blocks[2].header.magic`. Paths are built step by step against the spec, so a
+//! finished one always names an attribute that parsing with the spec yields.
+
+use thiserror::Error;
+
+use crate::ast::Expr;
+use crate::ksy::{KsySpec, TypeRef, TypeSpec};
+use crate::translator::translate;
+
+#[derive(Clone, Debug, Error, PartialEq)]
+pub enum PathError {
+    #[error("`{path}` has no attribute `{name}`")]
+    UnknownAttribute { path: String, name: String },
+    #[error("`{0}` is an array, its items need a subscript")]
+    Array(String),
+    #[error("`{0}` is not an array")]
+    NotArray(String),
+    #[error("`{0}` is not a path of attributes")]
+    NotPath(String),
+}
+
+/// Path from the top-level type, with the types of the value it reaches.
+#[derive(Clone, Debug)]
+pub struct FieldPath<'s> {
+    imports: &'s [KsySpec],
+    expr: Option<Expr>,
+    /// User types the value may have (more than one for a switch), each with the types it's
+    /// nested in before it. Empty for values of other types.
+    types: Vec<Vec<&'s TypeSpec>>,
+    /// Whether the value is an array whose items the types are of
+    array: bool,
+}
+
+impl<'s> FieldPath<'s> {
+    /// Empty path, at the top-level type of `spec`. Types named like the `meta/id` of one of
+    /// `imports` are its top-level type.
+    pub fn new(spec: &'s KsySpec, imports: &'s [KsySpec]) -> Self {
+        FieldPath {
+            imports,
+            expr: None,
+            types: vec![vec![spec]],
+            array: false,
+        }
+    }
+
+    /// Path of the expression, made of names, attributes and subscripts with literal indices.
+    pub fn from_expr(
+        spec: &'s KsySpec,
+        imports: &'s [KsySpec],
+        expr: &Expr,
+    ) -> Result<Self, PathError> {
+        match expr {
+            Expr::Name(name) => FieldPath::new(spec, imports).field(name),
+            Expr::Attribute { value, attr_name } => {
+                FieldPath::from_expr(spec, imports, value)?.field(attr_name)
+            }
+            Expr::Subscript { value, idx } => match **idx {
+                Expr::Int(index) => FieldPath::from_expr(spec, imports, value)?.index(index),
+                _ => Err(PathError::NotPath(translate(expr))),
+            },
+            _ => Err(PathError::NotPath(translate(expr))),
+        }
+    }
+
+    /// Path to the attribute (a `seq` one or an instance) of the value the path reaches. For a
+    /// switch, every type it may have must have the attribute.
+    pub fn field(&self, name: &str) -> Result<Self, PathError> {
+        if self.array {
+            return Err(PathError::Array(self.to_string()));
+        }
+        let unknown = || PathError::UnknownAttribute {
+            path: self.to_string(),
+            name: name.to_string(),
+        };
+        if self.types.is_empty() {
+            return Err(unknown());
+        }
+        let mut types = Vec::new();
+        let mut array = false;
+        for scopes in &self.types {
+            let ty = *scopes.last().expect("scopes must not be empty");
+            let attr = ty
+                .seq
+                .iter()
+                .find(|attr| attr.id.as_deref() == Some(name))
+                .or_else(|| ty.instances.get(name))
+                .ok_or_else(unknown)?;
+            array = attr.repeat.is_some();
+            let type_names: Vec<&String> = match &attr.type_ref {
+                Some(TypeRef::Named(type_name)) => vec![type_name],
+                Some(TypeRef::Switch { cases, .. }) => cases.values().collect(),
+                None => Vec::new(),
+            };
+            types.extend(
+                type_names
+                    .into_iter()
+                    .filter_map(|type_name| self.resolve(type_name, scopes)),
+            );
+        }
+        let expr = match &self.expr {
+            None => Expr::Name(name.to_string()),
+            Some(value) => Expr::Attribute {
+                value: Box::new(value.clone()),
+                attr_name: name.to_string(),
+            },
+        };
+        Ok(FieldPath {
+            imports: self.imports,
+            expr: Some(expr),
+            types,
+            array,
+        })
+    }
+
+    /// Path to the item of the array the path reaches.
+    pub fn index(&self, index: u64) -> Result<Self, PathError> {
+        let (Some(value), true) = (&self.expr, self.array) else {
+            return Err(PathError::NotArray(self.to_string()));
+        };
+        Ok(FieldPath {
+            expr: Some(Expr::Subscript {
+                value: Box::new(value.clone()),
+                idx: Box::new(Expr::Int(index)),
+            }),
+            array: false,
+            ..self.clone()
+        })
+    }
+
+    /// Expression of the path, `None` for the empty one.
+    pub fn expr(&self) -> Option<&Expr> {
+        self.expr.as_ref()
+    }
+
+    /// User type the name (possibly with arguments, or a path like `a::b`) refers to in the
+    /// innermost of the scopes, with the types it's nested in. `None` for other types.
+    fn resolve(&self, type_name: &str, scopes: &[&'s TypeSpec]) -> Option<Vec<&'s TypeSpec>> {
+        let type_name = type_name.split('(').next().unwrap_or(type_name).trim();
+        let mut parts = type_name.split("::");
+        let first = parts.next()?;
+        let mut resolved = (0..scopes.len())
+            .rev()
+            .find_map(|depth| {
+                let ty = scopes[depth].types.get(first)?;
+                let mut resolved = scopes[..=depth].to_vec();
+                resolved.push(ty);
+                Some(resolved)
+            })
+            .or_else(|| {
+                let import = self.imports.iter().find(|spec| spec.id() == Some(first))?;
+                Some(vec![import])
+            })?;
+        for part in parts {
+            let ty = resolved.last()?.types.get(part)?;
+            resolved.push(ty);
+        }
+        Some(resolved)
+    }
+}
+
+impl std::fmt::Display for FieldPath<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.expr {
+            Some(expr) => f.write_str(&translate(expr)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Whether every path in the expression (and not just a part of a longer one) reaches an
+/// attribute of the spec.
+pub fn paths_exist(spec: &KsySpec, imports: &[KsySpec], expr: &Expr) -> bool {
+    match FieldPath::from_expr(spec, imports, expr) {
+        Ok(_) => true,
+        Err(PathError::NotPath(_)) => expr
+            .children()
+            .into_iter()
+            .all(|child| paths_exist(spec, imports, child)),
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::BinaryOp;
+    use crate::ksy::{Attribute, Repeat};
+    use indexmap::IndexMap;
+
+    fn nested_spec() -> KsySpec {
+        let mut header = TypeSpec::default();
+        header.seq.push(Attribute::new("magic", "u4"));
+        let mut block = TypeSpec::default();
+        block.seq.push(Attribute::new("header", "header"));
+        block
+            .instances
+            .insert("twice".to_string(), Attribute::value_instance(Expr::Int(2)));
+        let mut other = TypeSpec::default();
+        other.seq.push(Attribute::new("header", "block::header"));
+
+        let mut spec = KsySpec::top_level("nested");
+        block.types.insert("header".to_string(), header);
+        spec.types.insert("block".to_string(), block);
+        spec.types.insert("other".to_string(), other);
+        spec.seq = vec![
+            Attribute {
+                repeat: Some(Repeat::Eos),
+                ..Attribute::new("blocks", "block")
+            },
+            Attribute {
+                id: Some("body".to_string()),
+                type_ref: Some(TypeRef::Switch {
+                    switch_on: Expr::Int(1),
+                    cases: IndexMap::from([
+                        (Expr::Int(1), "block".to_string()),
+                        (Expr::Int(2), "other".to_string()),
+                    ]),
+                }),
+                ..Default::default()
+            },
+        ];
+        spec
+    }
+
+    #[test]
+    fn built_paths() {
+        let spec = nested_spec();
+        let root = FieldPath::new(&spec, &[]);
+        let magic = root
+            .field("blocks")
+            .and_then(|path| path.index(2))
+            .and_then(|path| path.field("header"))
+            .and_then(|path| path.field("magic"))
+            .unwrap();
+        assert_eq!(magic.to_string(), "blocks[2].header.magic");
+        let parsed = FieldPath::from_expr(&spec, &[], magic.expr().unwrap()).unwrap();
+        assert_eq!(parsed.expr(), magic.expr());
+        // both types of the switch have a header
+        assert!(root
+            .field("body")
+            .and_then(|path| path.field("header"))
+            .is_ok());
+        assert!(root
+            .field("blocks")
+            .unwrap()
+            .index(0)
+            .unwrap()
+            .field("twice")
+            .is_ok());
+
+        assert_eq!(
+            root.field("blocks")
+                .and_then(|path| path.field("header"))
+                .unwrap_err(),
+            PathError::Array("blocks".to_string())
+        );
+        assert_eq!(
+            root.field("body")
+                .and_then(|path| path.field("twice"))
+                .unwrap_err(),
+            PathError::UnknownAttribute {
+                path: "body".to_string(),
+                name: "twice".to_string()
+            }
+        );
+        assert_eq!(
+            root.field("body")
+                .and_then(|path| path.index(0))
+                .unwrap_err(),
+            PathError::NotArray("body".to_string())
+        );
+    }
+
+    #[test]
+    fn paths_in_expressions() {
+        let spec = nested_spec();
+        let path = |s: &str| {
+            s.split('.').fold(None, |value: Option<Expr>, part| {
+                Some(match value {
+                    None => Expr::Name(part.to_string()),
+                    Some(value) => Expr::Attribute {
+                        value: Box::new(value),
+                        attr_name: part.to_string(),
+                    },
+                })
+            })
+        };
+        let check = Expr::BinaryOp {
+            l: Box::new(path("body.header.magic").unwrap()),
+            op: BinaryOp::Ne,
+            r: Box::new(Expr::Int(0)),
+        };
+        assert!(paths_exist(&spec, &[], &check));
+        assert!(!paths_exist(&spec, &[], &path("body.magic").unwrap()));
+    }
+}
@@ -0,0 +1,332 @@
+//! Model of a ksy spec document, serialized to YAML with serde.
+//!
+//! Only the parts of the format that the generators need are modeled. Keys appear in the output
+//! in the order of the struct fields (and in insertion order for maps), which follows the usual
+//! order in hand-written specs. Expressions are stored as [`Expr`] and emitted in the KS
+//! expression syntax.
+
+use indexmap::IndexMap;
+use serde::Serialize;
+
+use crate::ast::Expr;
+use crate::translator::translate;
+
+/// Top-level ksy document, i.e. a type spec with `meta/id` set.
+pub type KsySpec = TypeSpec;
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct TypeSpec {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub meta: Option<Meta>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub doc: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub params: Vec<Param>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub seq: Vec<Attribute>,
+    #[serde(skip_serializing_if = "IndexMap::is_empty")]
+    pub instances: IndexMap<String, Attribute>,
+    #[serde(skip_serializing_if = "IndexMap::is_empty")]
+    pub types: IndexMap<String, TypeSpec>,
+    #[serde(skip_serializing_if = "IndexMap::is_empty")]
+    pub enums: IndexMap<String, IndexMap<i128, String>>,
+}
+
+impl TypeSpec {
+    /// Empty top-level spec of the format `id`.
+    pub fn top_level(id: impl Into<String>) -> KsySpec {
+        TypeSpec {
+            meta: Some(Meta {
+                id: Some(id.into()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    /// `meta/id` of the spec.
+    pub fn id(&self) -> Option<&str> {
+        self.meta.as_ref()?.id.as_deref()
+    }
+
+    pub fn to_yaml(&self) -> String {
+        serde_yaml::to_string(self).expect("spec model must be serializable to YAML")
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Meta {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ks_version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ks_opaque_types: Option<bool>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub imports: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub endian: Option<MetaEndian>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bit_endian: Option<Endian>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub encoding: Option<String>,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Endian {
+    Le,
+    Be,
+}
+
+/// Value of `meta/endian`: either fixed, or calculated when the type gets parsed (which is only
+/// allowed in nested types).
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum MetaEndian {
+    Fixed(Endian),
+    Switch {
+        #[serde(rename = "switch-on")]
+        switch_on: Expr,
+        cases: IndexMap<Expr, Endian>,
+    },
+}
+
+impl From<Endian> for MetaEndian {
+    fn from(endian: Endian) -> Self {
+        MetaEndian::Fixed(endian)
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Param {
+    pub id: String,
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub type_name: Option<String>,
+    #[serde(rename = "enum", skip_serializing_if = "Option::is_none")]
+    pub enum_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub doc: Option<String>,
+}
+
+/// Attribute of a `seq` or an instance. Instances have no `id` (it's the key in the `instances`
+/// map instead), but may have `pos`, `io` and `value`.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Attribute {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub doc: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pos: Option<Expr>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub io: Option<Expr>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<Expr>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub contents: Option<Contents>,
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub type_ref: Option<TypeRef>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<Expr>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size_eos: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub terminator: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub consume: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub include: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub eos_error: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pad_right: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub encoding: Option<String>,
+    #[serde(rename = "enum", skip_serializing_if = "Option::is_none")]
+    pub enum_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub process: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub repeat: Option<Repeat>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub repeat_expr: Option<Expr>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub repeat_until: Option<Expr>,
+    #[serde(rename = "if", skip_serializing_if = "Option::is_none")]
+    pub if_expr: Option<Expr>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub valid: Option<Valid>,
+}
+
+impl Attribute {
+    /// `seq` attribute `id` of the given type.
+    pub fn new(id: impl Into<String>, type_name: impl Into<String>) -> Self {
+        Attribute {
+            id: Some(id.into()),
+            type_ref: Some(TypeRef::Named(type_name.into())),
+            ..Default::default()
+        }
+    }
+
+    /// Value instance.
+    pub fn value_instance(value: Expr) -> Self {
+        Attribute {
+            value: Some(value),
+            ..Default::default()
+        }
+    }
+}
+
+/// Expected fixed content of an attribute. Strings are encoded in UTF-8.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum Contents {
+    Str(String),
+    Bytes(Vec<u8>),
+    /// List of bytes and strings, concatenated
+    Mixed(Vec<ContentsItem>),
+}
+
+impl Contents {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            Contents::Str(s) => s.as_bytes().to_vec(),
+            Contents::Bytes(bytes) => bytes.clone(),
+            Contents::Mixed(items) => items
+                .iter()
+                .flat_map(|item| match item {
+                    ContentsItem::Byte(byte) => vec![*byte],
+                    ContentsItem::Str(s) => s.as_bytes().to_vec(),
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum ContentsItem {
+    Byte(u8),
+    Str(String),
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(untagged, rename_all = "kebab-case")]
+pub enum TypeRef {
+    Named(String),
+    Switch {
+        #[serde(rename = "switch-on")]
+        switch_on: Expr,
+        cases: IndexMap<Expr, String>,
+    },
+}
+
+impl TypeRef {
+    /// Reference to the parametric type `name` with the given arguments, like `entry(len, true)`.
+    pub fn call(name: &str, args: &[Expr]) -> Self {
+        let args: Vec<String> = args.iter().map(translate).collect();
+        TypeRef::Named(format!("{}({})", name, args.join(", ")))
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Repeat {
+    Eos,
+    Expr,
+    Until,
+}
+
+/// Value of the `valid` key: either the shorthand for `eq` or a map of checks.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum Valid {
+    Eq(Expr),
+    Checks(ValidChecks),
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ValidChecks {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub eq: Option<Expr>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min: Option<Expr>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max: Option<Expr>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub any_of: Vec<Expr>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expr: Option<Expr>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::BinaryOp;
+
+    #[test]
+    fn yaml() {
+        let mut spec = TypeSpec::top_level("example");
+        spec.meta.as_mut().unwrap().endian = Some(Endian::Le.into());
+        spec.seq.push(Attribute {
+            contents: Some(Contents::Str("EX".to_string())),
+            ..Attribute::new("magic", "u1")
+        });
+        spec.seq.push(Attribute {
+            id: Some("kind".to_string()),
+            type_ref: Some(TypeRef::Switch {
+                switch_on: Expr::Name("magic".to_string()),
+                cases: IndexMap::from([(Expr::Int(1), "u2".to_string())]),
+            }),
+            repeat: Some(Repeat::Expr),
+            repeat_expr: Some(Expr::Int(2)),
+            ..Default::default()
+        });
+        spec.instances.insert(
+            "twice".to_string(),
+            Attribute::value_instance(Expr::BinaryOp {
+                l: Box::new(Expr::Name("magic".to_string())),
+                op: BinaryOp::Mul,
+                r: Box::new(Expr::Int(2)),
+            }),
+        );
+        spec.enums.insert(
+            "animal".to_string(),
+            IndexMap::from([(-1, "none".to_string()), (7, "cat".to_string())]),
+        );
+        assert_eq!(spec.id(), Some("example"));
+        assert_eq!(
+            spec.to_yaml(),
+            r#"meta:
+  id: example
+  endian: le
+seq:
+- id: magic
+  contents: EX
+  type: u1
+- id: kind
+  type:
+    switch-on: magic
+    cases:
+      1: u2
+  repeat: expr
+  repeat-expr: 2
+instances:
+  twice:
+    value: (magic * 2)
+enums:
+  animal:
+    -1: none
+    7: cat
+"#
+        );
+    }
+}
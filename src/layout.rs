@@ -0,0 +1,324 @@
+//! Generated suites written out in the layout of the upstream test suite
+//! (kaitai_struct_tests): specs in `formats/<id>.ksy`, data in `src/<id>.bin` (with the trace of
+//! synthesized data in `src/<id>.trace.txt`) and KST specs in `spec/ks/<id>.kst`, so that the
+//! output can be dropped into it.
+//!
+//! Ids are best made free of collisions when generating, with a [`Namer`] that knows the
+//! corpus. What still collides, with the files already in the directory or with other cases of
+//! the batch, is handled as [`OnCollision`] says. The suite is indexed in a [`Manifest`], which
+//! each write adds its cases to.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use thiserror::Error;
+
+use crate::datagen::trace;
+use crate::gen::naming::{file_name, NameError, Namer};
+use crate::gen::suite::GenCase;
+use crate::kst::{case_specs, LiteralError};
+use manifest::{manifest_spec, Manifest, MANIFEST_FILE};
+
+pub mod manifest;
+
+pub const FORMATS_DIR: &str = "formats";
+pub const DATA_DIR: &str = "src";
+pub const KST_DIR: &str = "spec/ks";
+
+#[derive(Debug, Error)]
+pub enum LayoutError {
+    #[error("can't write the suite: {0}")]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Corpus(#[from] NameError),
+    #[error("no KST spec for `{id}`: {error}")]
+    Literal { id: String, error: LiteralError },
+    #[error("`{}` already exists with other contents", .0.display())]
+    Exists(PathBuf),
+    #[error("`{}` is written by two cases with other contents", .0.display())]
+    Duplicate(PathBuf),
+    #[error("spec `{id}` collides with the existing `{existing}`")]
+    Id { id: String, existing: String },
+    #[error("can't read the manifest: {0}")]
+    Manifest(#[from] serde_yaml::Error),
+}
+
+/// What to do with a case whose files collide. Files that exist with the same contents don't
+/// collide, so writing a suite again changes nothing.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum OnCollision {
+    /// Fail before anything is written
+    Fail,
+    /// Leave the case out
+    Skip,
+    /// Replace the existing files. Cases of the batch that write the same file, and ids that
+    /// only differ in case or underscores from existing ones, still fail.
+    Overwrite,
+}
+
+/// File of a case, with its path relative to the root of the suite.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SuiteFile {
+    pub path: PathBuf,
+    pub contents: Vec<u8>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct WriteReport {
+    /// Files created or replaced
+    pub written: Vec<PathBuf>,
+    /// Files that already existed with the same contents
+    pub unchanged: Vec<PathBuf>,
+    /// Ids of the cases left out because of collisions
+    pub skipped: Vec<String>,
+}
+
+/// Files of the case: its specs (the case's own and the extra ones), the data of its inputs, their
+/// traces if they have any and their KST specs.
+pub fn case_files(case: &GenCase) -> Result<Vec<SuiteFile>, LiteralError> {
+    let mut files = Vec::new();
+    for spec in std::iter::once(&case.spec).chain(&case.extra_specs) {
+        let id = spec.id().expect("generated specs must have ids");
+        files.push(SuiteFile {
+            path: Path::new(FORMATS_DIR).join(file_name(id)),
+            contents: spec.to_yaml().into_bytes(),
+        });
+    }
+    for (input, kst) in case.inputs.iter().zip(case_specs(case)?) {
+        let kst_name = Path::new(&kst.data).with_extension("kst");
+        files.push(SuiteFile {
+            path: Path::new(DATA_DIR).join(&kst.data),
+            contents: input.data.clone(),
+        });
+        if !input.trace.is_empty() {
+            files.push(SuiteFile {
+                path: Path::new(DATA_DIR).join(Path::new(&kst.data).with_extension("trace.txt")),
+                contents: trace::report(&input.trace).into_bytes(),
+            });
+        }
+        files.push(SuiteFile {
+            path: Path::new(KST_DIR).join(kst_name),
+            contents: kst.to_yaml().into_bytes(),
+        });
+    }
+    Ok(files)
+}
+
+/// Writes the files of the cases under `root`, creating the directories they go to.
+pub fn write_suite(
+    root: &Path,
+    cases: &[GenCase],
+    on_collision: OnCollision,
+) -> Result<WriteReport, LayoutError> {
+    let formats = root.join(FORMATS_DIR);
+    let mut namer = if formats.is_dir() {
+        Namer::from_corpus(&formats)?
+    } else {
+        Namer::new()
+    };
+    let mut index = match fs::read_to_string(root.join(MANIFEST_FILE)) {
+        Ok(text) => Manifest::from_yaml_str(&text)?,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => Manifest::default(),
+        Err(error) => return Err(error.into()),
+    };
+    let mut report = WriteReport::default();
+    let mut planned: HashMap<PathBuf, Vec<u8>> = HashMap::new();
+    let mut accepted = Vec::new();
+    for case in cases {
+        let literal = |error| LayoutError::Literal {
+            id: case.id.clone(),
+            error,
+        };
+        let files = case_files(case).map_err(literal)?;
+        let ids: Vec<&str> = std::iter::once(&case.spec)
+            .chain(&case.extra_specs)
+            .filter_map(|spec| spec.id())
+            .collect();
+        let overwrite = on_collision == OnCollision::Overwrite;
+        match (
+            collision(root, &namer, &planned, &ids, &files, overwrite)?,
+            on_collision,
+        ) {
+            (None, _) => {}
+            (Some(_), OnCollision::Skip) => {
+                report.skipped.push(case.id.clone());
+                continue;
+            }
+            (Some(error), _) => return Err(error),
+        }
+        for id in ids {
+            namer.reserve(id);
+        }
+        index.insert(manifest_spec(case).map_err(literal)?);
+        for file in files {
+            if planned
+                .insert(file.path.clone(), file.contents.clone())
+                .is_none()
+            {
+                accepted.push(file);
+            }
+        }
+    }
+
+    accepted.push(SuiteFile {
+        path: PathBuf::from(MANIFEST_FILE),
+        contents: index.to_yaml().into_bytes(),
+    });
+    for file in accepted {
+        let path = root.join(&file.path);
+        if fs::read(&path).is_ok_and(|contents| contents == file.contents) {
+            report.unchanged.push(file.path);
+            continue;
+        }
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&path, &file.contents)?;
+        report.written.push(file.path);
+    }
+    Ok(report)
+}
+
+/// First collision of the files (and spec ids) of a case, with existing files (unless they are
+/// to be overwritten) and with the files planned for the cases before it.
+fn collision(
+    root: &Path,
+    namer: &Namer,
+    planned: &HashMap<PathBuf, Vec<u8>>,
+    ids: &[&str],
+    files: &[SuiteFile],
+    overwrite: bool,
+) -> Result<Option<LayoutError>, io::Error> {
+    for id in ids {
+        // the same id is the same file, whose contents decide
+        if let Some(existing) = namer.collision(id).filter(|existing| existing != id) {
+            return Ok(Some(LayoutError::Id {
+                id: id.to_string(),
+                existing: existing.to_string(),
+            }));
+        }
+    }
+    for file in files {
+        if let Some(contents) = planned.get(&file.path) {
+            if *contents != file.contents {
+                return Ok(Some(LayoutError::Duplicate(file.path.clone())));
+            }
+            continue;
+        }
+        if overwrite {
+            continue;
+        }
+        match fs::read(root.join(&file.path)) {
+            Ok(contents) if contents != file.contents => {
+                return Ok(Some(LayoutError::Exists(file.path.clone())));
+            }
+            Ok(_) => {}
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {}
+            Err(error) => return Err(error),
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gen::profile::GenProfile;
+    use crate::gen::suite::generate_suite;
+
+    fn temp_root(name: &str) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("ks_suite_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        root
+    }
+
+    fn file_names(paths: &[PathBuf]) -> Vec<String> {
+        paths
+            .iter()
+            .map(|path| path.to_string_lossy().into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn suite_layout() {
+        let profile = GenProfile::from_toml_str("trace = true\n").unwrap();
+        let cases = generate_suite(0, 5, &profile);
+        let root = temp_root("layout");
+        let report = write_suite(&root, &cases, OnCollision::Fail);
+        let again = write_suite(&root, &cases, OnCollision::Fail);
+        let kst = fs::read_to_string(root.join(KST_DIR).join(format!("{}.kst", cases[0].id)));
+        // the first traced input and the name of its data
+        let (traced, data) = cases
+            .iter()
+            .flat_map(|case| case.inputs.iter().zip(case_specs(case).unwrap()))
+            .find(|(input, _)| !input.trace.is_empty())
+            .expect("some input must be traced");
+        let trace_name = Path::new(&data.data).with_extension("trace.txt");
+        let traced_report = fs::read_to_string(root.join(DATA_DIR).join(&trace_name));
+        fs::remove_dir_all(&root).unwrap();
+
+        let report = report.unwrap();
+        let first = &cases[0].id;
+        let names = file_names(&report.written);
+        for name in [
+            MANIFEST_FILE.to_string(),
+            format!("formats/{}.ksy", first),
+            format!("src/{}.bin", first),
+            format!("spec/ks/{}.kst", first),
+        ] {
+            assert!(names.contains(&name), "{} not in {:?}", name, names);
+        }
+        assert!(kst.unwrap().starts_with(&format!("id: {}\n", first)));
+        assert_eq!(traced_report.unwrap(), trace::report(&traced.trace));
+        // only the traced inputs have a trace
+        let traces = names.iter().filter(|name| name.ends_with(".trace.txt"));
+        let traced_inputs = cases.iter().flat_map(|case| &case.inputs);
+        assert_eq!(
+            traces.count(),
+            traced_inputs
+                .filter(|input| !input.trace.is_empty())
+                .count()
+        );
+        let again = again.unwrap();
+        assert!(again.written.is_empty());
+        assert_eq!(again.unchanged, report.written);
+    }
+
+    #[test]
+    fn collisions() {
+        let cases = generate_suite(1, 2, &GenProfile::default());
+        let root = temp_root("collisions");
+        let ksy = root.join(FORMATS_DIR).join(file_name(&cases[0].id));
+        fs::create_dir_all(ksy.parent().unwrap()).unwrap();
+        fs::write(&ksy, "meta:\n  id: hand_written\n").unwrap();
+        let failed = write_suite(&root, &cases, OnCollision::Fail);
+        let nothing_written = !root.join(DATA_DIR).exists();
+        let skipped = write_suite(&root, &cases, OnCollision::Skip);
+        let overwritten = write_suite(&root, &cases, OnCollision::Overwrite);
+
+        let near_id = cases[1].id.replacen('_', "", 1);
+        let mut renamed = cases[1].clone();
+        renamed.id = near_id.clone();
+        renamed.spec.meta.as_mut().unwrap().id = Some(near_id);
+        let near = write_suite(&root, &[renamed], OnCollision::Overwrite);
+        fs::remove_dir_all(&root).unwrap();
+
+        assert!(matches!(failed, Err(LayoutError::Exists(path)) if root.join(&path) == ksy));
+        assert!(nothing_written);
+        assert_eq!(skipped.unwrap().skipped, [cases[0].id.clone()]);
+        let overwritten = overwritten.unwrap();
+        assert!(overwritten
+            .written
+            .contains(&ksy.strip_prefix(&root).unwrap().to_path_buf()));
+        assert!(matches!(near, Err(LayoutError::Id { existing, .. }) if existing == cases[1].id));
+
+        let mut twice = cases[0].clone();
+        twice.inputs[0].data.push(0);
+        let root = temp_root("duplicate");
+        let duplicate = write_suite(&root, &[cases[0].clone(), twice], OnCollision::Overwrite);
+        let _ = fs::remove_dir_all(&root);
+        assert!(matches!(duplicate, Err(LayoutError::Duplicate(_))));
+    }
+}
@@ -0,0 +1,224 @@
+//! Index of a generated suite, `manifest.yaml` at its root: every generated spec with its files,
+//! feature, seed and the target languages its tests translate to, so that runners can pick and
+//! schedule tests without globbing the directories. Synthesized data files also record what
+//! regenerates them.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::gen::naming::file_name;
+use crate::gen::suite::{DataKind, GenCase};
+use crate::harness::{case_tests, emitter};
+use crate::kst::{case_specs, LiteralError};
+use crate::oracle::Verdict;
+use crate::target::Target;
+
+use super::{DATA_DIR, FORMATS_DIR, KST_DIR};
+
+pub const MANIFEST_FILE: &str = "manifest.yaml";
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Manifest {
+    /// Specs in the order of their ids
+    pub specs: Vec<ManifestSpec>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ManifestSpec {
+    pub id: String,
+    pub feature: String,
+    /// Seed that the case regenerates from
+    pub seed: u64,
+    /// Path of the `.ksy` file, like all the paths relative to the root of the suite
+    pub ksy: String,
+    /// Specs that the spec imports or reads as opaque types
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub imports: Vec<String>,
+    pub inputs: Vec<ManifestInput>,
+    /// Targets (as `ksc` names them) whose languages all the asserts translate to and which the
+    /// oracle expects to read the asserted values
+    pub targets: Vec<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ManifestInput {
+    pub data: String,
+    pub kst: String,
+    /// Exception that parsing the data fails with
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exception: Option<String>,
+    /// Where synthesized data comes from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<InputSource>,
+    /// Tags of the oracle's verdicts (see [`Verdict::tag`]) by target, for the targets that may
+    /// not read the asserted values
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub verdicts: BTreeMap<String, String>,
+}
+
+/// What [`regenerate_input`](crate::gen::suite::regenerate_input) makes the data again from.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct InputSource {
+    /// Seed of the case
+    pub seed: u64,
+    pub variant: u64,
+    #[serde(with = "serde_yaml::with::singleton_map")]
+    pub kind: DataKind,
+}
+
+impl Manifest {
+    pub fn to_yaml(&self) -> String {
+        serde_yaml::to_string(self).expect("manifest must be serializable to YAML")
+    }
+
+    pub fn from_yaml_str(s: &str) -> Result<Self, serde_yaml::Error> {
+        serde_yaml::from_str(s)
+    }
+
+    /// Adds the spec, replacing the one with the same id.
+    pub fn insert(&mut self, spec: ManifestSpec) {
+        match self
+            .specs
+            .binary_search_by(|existing| existing.id.as_str().cmp(&spec.id))
+        {
+            Ok(index) => self.specs[index] = spec,
+            Err(index) => self.specs.insert(index, spec),
+        }
+    }
+}
+
+pub fn manifest_spec(case: &GenCase) -> Result<ManifestSpec, LiteralError> {
+    let ksy_path = |id: &str| format!("{}/{}", FORMATS_DIR, file_name(id));
+    let inputs = case_specs(case)?
+        .into_iter()
+        .zip(&case.inputs)
+        .map(|(kst, input)| ManifestInput {
+            kst: format!(
+                "{}/{}",
+                KST_DIR,
+                Path::new(&kst.data).with_extension("kst").display()
+            ),
+            data: format!("{}/{}", DATA_DIR, kst.data),
+            exception: kst.exception,
+            source: input.source.map(|source| InputSource {
+                seed: case.seed,
+                variant: source.variant,
+                kind: source.kind,
+            }),
+            verdicts: input
+                .verdicts
+                .iter()
+                .filter(|(_, verdict)| **verdict != Verdict::Match)
+                .map(|(target, verdict)| (target.ksc_name().to_string(), verdict.tag().to_string()))
+                .collect(),
+        })
+        .collect();
+    let targets = Target::ALL
+        .into_iter()
+        .filter(|target| {
+            case.inputs.iter().all(|input| {
+                input
+                    .verdicts
+                    .get(target)
+                    .is_none_or(|verdict| *verdict == Verdict::Match)
+            })
+        })
+        .filter(|target| case_tests(case, emitter(*target).as_ref()).is_ok())
+        .map(|target| target.ksc_name().to_string())
+        .collect();
+    Ok(ManifestSpec {
+        id: case.id.clone(),
+        feature: case.feature.name().to_string(),
+        seed: case.seed,
+        ksy: ksy_path(&case.id),
+        imports: case
+            .extra_specs
+            .iter()
+            .filter_map(|spec| spec.id())
+            .map(ksy_path)
+            .collect(),
+        inputs,
+        targets,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gen::profile::GenProfile;
+    use crate::gen::suite::{generate_case, generate_suite, regenerate_input, DataSource};
+    use crate::layout::case_files;
+
+    #[test]
+    fn specs() {
+        let cases = generate_suite(0, 3, &GenProfile::default());
+        let mut manifest = Manifest::default();
+        for case in cases.iter().rev() {
+            manifest.insert(manifest_spec(case).unwrap());
+        }
+        manifest.insert(manifest_spec(&cases[0]).unwrap());
+        assert_eq!(manifest.specs.len(), 3);
+        assert!(manifest.specs.windows(2).all(|w| w[0].id < w[1].id));
+
+        let spec = manifest.specs.iter().find(|s| s.id == cases[0].id).unwrap();
+        assert_eq!(spec.seed, cases[0].seed);
+        assert_eq!(spec.feature, cases[0].feature.name());
+        assert_eq!(spec.ksy, format!("formats/{}.ksy", cases[0].id));
+        assert_eq!(spec.inputs.len(), cases[0].inputs.len());
+        assert_eq!(spec.inputs[0].data, format!("src/{}.bin", cases[0].id));
+        assert_eq!(spec.inputs[0].kst, format!("spec/ks/{}.kst", cases[0].id));
+
+        let yaml = manifest.to_yaml();
+        assert!(yaml.starts_with("specs:\n- id: "));
+        assert_eq!(Manifest::from_yaml_str(&yaml).unwrap(), manifest);
+    }
+
+    #[test]
+    fn regenerated_from_the_manifest() {
+        let toml = "[features]\nprimitive = 1\n[negatives]\ntruncated = true\n";
+        let profile = GenProfile::from_toml_str(toml).unwrap();
+        let case = &generate_suite(3, 1, &profile)[0];
+        let mut manifest = Manifest::default();
+        manifest.insert(manifest_spec(case).unwrap());
+        let yaml = manifest.to_yaml();
+        assert!(yaml.contains("kind: valid\n"), "{}", yaml);
+        assert!(yaml.contains("truncated: "), "{}", yaml);
+
+        let manifest = Manifest::from_yaml_str(&yaml).unwrap();
+        let input = manifest.specs[0].inputs.last().unwrap();
+        let source = input.source.unwrap();
+        assert!(matches!(source.kind, DataKind::Truncated(_)));
+        let regenerated = generate_case(source.seed, &profile).unwrap();
+        let data = regenerate_input(
+            &regenerated,
+            DataSource {
+                variant: source.variant,
+                kind: source.kind,
+            },
+            &profile,
+        );
+        let files = case_files(case).unwrap();
+        let bin = files
+            .iter()
+            .find(|file| file.path == Path::new(&input.data))
+            .unwrap();
+        assert_eq!(data, Some(bin.contents.clone()));
+    }
+
+    #[test]
+    fn divergent_targets() {
+        let profile = GenProfile::from_toml_str("[features]\nprimitive = 1\n").unwrap();
+        let case = generate_suite(21, 5, &profile)
+            .into_iter()
+            .find(|case| case.inputs[0].verdicts[&Target::JavaScript] != Verdict::Match)
+            .unwrap();
+        let spec = manifest_spec(&case).unwrap();
+        assert!(!spec.targets.contains(&"javascript".to_string()));
+        assert!(spec.targets.contains(&"python".to_string()));
+        let verdicts = &spec.inputs[0].verdicts;
+        assert_eq!(verdicts["javascript"], "may-diverge");
+        assert!(!verdicts.contains_key("python"));
+    }
+}
@@ -1,4 +1,19 @@
 #![forbid(unsafe_code)]
 
-mod ast;
-mod translator;
+pub mod ast;
+pub mod datagen;
+pub mod differential;
+pub mod divergence;
+pub mod eval;
+pub mod gen;
+pub mod harness;
+pub mod kst;
+pub mod ksy;
+pub mod layout;
+pub mod numeric;
+pub mod oracle;
+pub mod schema;
+pub mod target;
+pub mod tolerance;
+pub mod translator;
+pub mod typing;
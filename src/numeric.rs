@@ -0,0 +1,239 @@
+//! Numeric types and the implicit numeric promotion rules of KSC, shared by type inference
+//! ([`crate::typing`]) and the reference evaluator ([`crate::eval`]) so that the two cannot drift
+//! apart.
+//!
+//! The KS expression language has no separate integer division operator: `/` on two integers is
+//! an integer (floor) division, while as soon as one operand is a float, the other one is
+//! converted and the operation is carried out in floating point.
+
+use crate::ast::BinaryOp;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum NumKind {
+    Int,
+    Float,
+}
+
+/// Fixed-width integer type of an attribute, like `u2` or `s4`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct IntType {
+    pub signed: bool,
+    /// Width in bytes (1, 2, 4 or 8)
+    pub width: u8,
+}
+
+impl IntType {
+    pub const ALL: [IntType; 8] = [
+        IntType::new(false, 1),
+        IntType::new(false, 2),
+        IntType::new(false, 4),
+        IntType::new(false, 8),
+        IntType::new(true, 1),
+        IntType::new(true, 2),
+        IntType::new(true, 4),
+        IntType::new(true, 8),
+    ];
+
+    const fn new(signed: bool, width: u8) -> Self {
+        Self { signed, width }
+    }
+
+    /// Parses a type name like `u2` or `s8` (without an endianness suffix).
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|ty| ty.name() == name)
+    }
+
+    pub fn name(self) -> String {
+        format!("{}{}", if self.signed { 's' } else { 'u' }, self.width)
+    }
+
+    pub fn bits(self) -> u32 {
+        u32::from(self.width) * 8
+    }
+
+    pub fn min_value(self) -> i128 {
+        if self.signed {
+            -(1 << (self.bits() - 1))
+        } else {
+            0
+        }
+    }
+
+    pub fn max_value(self) -> i128 {
+        if self.signed {
+            (1 << (self.bits() - 1)) - 1
+        } else {
+            (1 << self.bits()) - 1
+        }
+    }
+
+    pub fn contains(self, value: i128) -> bool {
+        (self.min_value()..=self.max_value()).contains(&value)
+    }
+
+    /// Reinterprets the lowest [`bits`](Self::bits) of `value` as this type, like a narrowing
+    /// conversion in a language with fixed-width integers.
+    pub fn wrap(self, value: i128) -> i128 {
+        let shift = 128 - self.bits();
+        if self.signed {
+            (value << shift) >> shift
+        } else {
+            ((value as u128) << shift >> shift) as i128
+        }
+    }
+}
+
+/// Size in bytes of a fixed-size built-in type (`u1`..`u8`, `s1`..`s8`, `f4`, `f8`).
+pub fn builtin_byte_size(name: &str) -> Option<u32> {
+    match name {
+        "f4" => Some(4),
+        "f8" => Some(8),
+        _ => IntType::from_name(name).map(|ty| u32::from(ty.width)),
+    }
+}
+
+/// How a binary operator treats a pair of numeric operands.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct NumericOp {
+    /// Kind in which the operation is carried out. If it's [`NumKind::Float`], integer operands
+    /// are converted to floats first.
+    pub domain: NumKind,
+    /// Kind of the result, or `None` if the operator yields a boolean
+    pub result: Option<NumKind>,
+}
+
+impl NumericOp {
+    /// Whether one of the operands of kinds `l` and `r` gets converted from an integer to a float.
+    pub fn promotes(&self, l: NumKind, r: NumKind) -> bool {
+        self.domain == NumKind::Float && (l == NumKind::Int || r == NumKind::Int)
+    }
+}
+
+/// Kind both operands get converted to when they meet in an arithmetic operation, a comparison, or
+/// as alternatives (branches of `?:`, items of a list).
+pub fn common_kind(a: NumKind, b: NumKind) -> NumKind {
+    if a == NumKind::Int && b == NumKind::Int {
+        NumKind::Int
+    } else {
+        NumKind::Float
+    }
+}
+
+/// Promotion rule for `l op r`, or `None` if the operator doesn't accept these numeric operands.
+pub fn numeric_op(op: BinaryOp, l: NumKind, r: NumKind) -> Option<NumericOp> {
+    let domain = common_kind(l, r);
+    match op {
+        BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div | BinaryOp::Rem => {
+            Some(NumericOp {
+                domain,
+                result: Some(domain),
+            })
+        }
+        BinaryOp::Eq | BinaryOp::Ne | BinaryOp::Lt | BinaryOp::Le | BinaryOp::Gt | BinaryOp::Ge => {
+            Some(NumericOp {
+                domain,
+                result: None,
+            })
+        }
+        BinaryOp::BitOr | BinaryOp::BitXor | BinaryOp::BitAnd | BinaryOp::Shl | BinaryOp::Shr => {
+            (domain == NumKind::Int).then_some(NumericOp {
+                domain,
+                result: Some(NumKind::Int),
+            })
+        }
+        BinaryOp::And | BinaryOp::Or => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::utils::PositiveFiniteF64;
+    use crate::ast::Expr;
+    use crate::eval::{eval, Env, Value};
+    use crate::typing::{infer, KsType, TypeEnv};
+
+    const ALL_OPS: [BinaryOp; 18] = [
+        BinaryOp::Add,
+        BinaryOp::Sub,
+        BinaryOp::Mul,
+        BinaryOp::Div,
+        BinaryOp::Rem,
+        BinaryOp::Eq,
+        BinaryOp::Ne,
+        BinaryOp::Lt,
+        BinaryOp::Le,
+        BinaryOp::Gt,
+        BinaryOp::Ge,
+        BinaryOp::And,
+        BinaryOp::Or,
+        BinaryOp::BitOr,
+        BinaryOp::BitXor,
+        BinaryOp::BitAnd,
+        BinaryOp::Shl,
+        BinaryOp::Shr,
+    ];
+
+    #[test]
+    fn int_type_ranges() {
+        let u2 = IntType::from_name("u2").unwrap();
+        assert_eq!((u2.min_value(), u2.max_value()), (0, 65535));
+        let s8 = IntType::from_name("s8").unwrap();
+        assert_eq!(
+            (s8.min_value(), s8.max_value()),
+            (i64::MIN.into(), i64::MAX.into())
+        );
+        assert_eq!(IntType::from_name("u3"), None);
+        assert_eq!(IntType::from_name("s1").unwrap().wrap(0xff), -1);
+        assert_eq!(u2.wrap(-1), 65535);
+        assert_eq!(builtin_byte_size("f4"), Some(4));
+    }
+
+    #[test]
+    fn int_division_stays_int() {
+        let rule = numeric_op(BinaryOp::Div, NumKind::Int, NumKind::Int).unwrap();
+        assert_eq!(rule.result, Some(NumKind::Int));
+        assert!(!rule.promotes(NumKind::Int, NumKind::Int));
+    }
+
+    #[test]
+    fn mixed_operands_promote() {
+        let rule = numeric_op(BinaryOp::Lt, NumKind::Int, NumKind::Float).unwrap();
+        assert_eq!(rule.domain, NumKind::Float);
+        assert_eq!(rule.result, None);
+        assert!(rule.promotes(NumKind::Int, NumKind::Float));
+        assert_eq!(
+            numeric_op(BinaryOp::BitAnd, NumKind::Float, NumKind::Int),
+            None
+        );
+    }
+
+    /// Type inference and evaluation must agree on the type of every numeric operation.
+    #[test]
+    fn typing_agrees_with_eval() {
+        let operand = |kind| match kind {
+            NumKind::Int => Expr::Int(7),
+            NumKind::Float => Expr::Float(PositiveFiniteF64::try_from(2.0).unwrap()),
+        };
+        for op in ALL_OPS {
+            for l in [NumKind::Int, NumKind::Float] {
+                for r in [NumKind::Int, NumKind::Float] {
+                    let expr = Expr::BinaryOp {
+                        l: Box::new(operand(l)),
+                        op,
+                        r: Box::new(operand(r)),
+                    };
+                    let inferred = infer(&expr, &TypeEnv::new()).ok();
+                    let evaluated = match eval(&expr, &Env::new()) {
+                        Ok(Value::Int(_)) => Some(KsType::Int),
+                        Ok(Value::Float(_)) => Some(KsType::Float),
+                        Ok(Value::Bool(_)) => Some(KsType::Bool),
+                        Ok(value) => panic!("unexpected value {:?}", value),
+                        Err(_) => None,
+                    };
+                    assert_eq!(inferred, evaluated, "{:?} {:?} {:?}", l, op, r);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,109 @@
+//! In-process differential oracle: decides for each target language whether its native evaluation
+//! of an expression is expected to agree with the reference evaluator.
+
+use std::collections::BTreeMap;
+
+use crate::ast::Expr;
+use crate::divergence::{predict, Quirk};
+use crate::eval::{eval, Env, EvalError, Value};
+use crate::target::Target;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Verdict {
+    /// Native evaluation is expected to give the same result as the reference evaluator
+    Match,
+    /// Native evaluation may differ because of the listed quirks of the target
+    MayDiverge(Vec<Quirk>),
+}
+
+impl Verdict {
+    /// Short tag to attach to the generated tests.
+    pub fn tag(&self) -> &'static str {
+        match self {
+            Verdict::Match => "match",
+            Verdict::MayDiverge(_) => "may-diverge",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct OracleReport {
+    /// Result of the reference evaluator
+    pub reference: Result<Value, EvalError>,
+    pub verdicts: BTreeMap<Target, Verdict>,
+}
+
+impl OracleReport {
+    /// Targets for which a test of this expression is expected to pass.
+    pub fn matching_targets(&self) -> impl Iterator<Item = Target> + '_ {
+        self.verdicts
+            .iter()
+            .filter(|(_, verdict)| **verdict == Verdict::Match)
+            .map(|(target, _)| *target)
+    }
+}
+
+pub fn check(expr: &Expr, env: &Env, targets: &[Target]) -> OracleReport {
+    let reference = eval(expr, env);
+    let divergences = predict(expr, env);
+    let verdicts = targets
+        .iter()
+        .map(|&target| {
+            let mut quirks: Vec<Quirk> = divergences
+                .iter()
+                .filter(|divergence| divergence.targets().contains(&target))
+                .map(|divergence| divergence.quirk)
+                .collect();
+            quirks.sort();
+            quirks.dedup();
+            let verdict = if quirks.is_empty() {
+                Verdict::Match
+            } else {
+                Verdict::MayDiverge(quirks)
+            };
+            (target, verdict)
+        })
+        .collect();
+    OracleReport {
+        reference,
+        verdicts,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::{BinaryOp, UnaryOp};
+
+    #[test]
+    fn plain_arithmetic_matches_everywhere() {
+        let expr = Expr::BinaryOp {
+            l: Box::new(Expr::Int(2)),
+            op: BinaryOp::Mul,
+            r: Box::new(Expr::Int(3)),
+        };
+        let report = check(&expr, &Env::new(), &Target::ALL);
+        assert_eq!(report.reference, Ok(Value::Int(6)));
+        assert_eq!(report.matching_targets().count(), Target::ALL.len());
+    }
+
+    #[test]
+    fn floor_division() {
+        let expr = Expr::BinaryOp {
+            l: Box::new(Expr::UnaryOp {
+                op: UnaryOp::Neg,
+                value: Box::new(Expr::Int(7)),
+            }),
+            op: BinaryOp::Div,
+            r: Box::new(Expr::Int(2)),
+        };
+        let report = check(&expr, &Env::new(), &[Target::Python, Target::Java]);
+        assert_eq!(report.reference, Ok(Value::Int(-4)));
+        assert_eq!(report.verdicts[&Target::Python], Verdict::Match);
+        assert_eq!(
+            report.verdicts[&Target::Java],
+            Verdict::MayDiverge(vec![Quirk::TruncatingDivision])
+        );
+        assert_eq!(report.verdicts[&Target::Java].tag(), "may-diverge");
+    }
+}
@@ -0,0 +1,416 @@
+//! Validation of generated specs against a JSON schema of the ksy format, so that a generator
+//! emitting a malformed document gets reported as a bug of the generator instead of showing up
+//! as a compiler failure.
+//!
+//! A schema of the keys that the generators emit is bundled. The official schema of the
+//! `kaitai_struct` project can be loaded instead. Only the keywords that these schemas use are
+//! supported (`$ref` to local definitions, `allOf`/`anyOf`/`oneOf`/`not`, `type`, `enum`,
+//! `pattern`, `minimum`/`maximum`, `properties`, `patternProperties`, `additionalProperties`,
+//! `required`, `dependencies` and `items`); other keywords are ignored.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use regex::Regex;
+use serde_yaml::{Mapping, Value};
+use thiserror::Error;
+
+use crate::ksy::KsySpec;
+
+const BUNDLED: &str = include_str!("schema/ksy_schema.json");
+
+#[derive(Debug, Error)]
+pub enum SchemaError {
+    #[error("invalid schema: {0}")]
+    Parse(#[from] serde_yaml::Error),
+    #[error("invalid pattern in the schema: {0}")]
+    Pattern(#[from] regex::Error),
+    #[error("unresolvable `$ref` `{0}`")]
+    Ref(String),
+}
+
+/// Part of a document that doesn't match the schema.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Violation {
+    /// JSON pointer to the offending value, like `/seq/0/type`
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let path = if self.path.is_empty() {
+            "/"
+        } else {
+            &self.path
+        };
+        write!(f, "{}: {}", path, self.message)
+    }
+}
+
+/// Generated spec that doesn't match the schema.
+#[derive(Clone, Debug, Error, PartialEq)]
+#[error(
+    "generator bug: spec `{id}` doesn't match the ksy schema: {}",
+    violations.iter().map(ToString::to_string).collect::<Vec<_>>().join("; ")
+)]
+pub struct InvalidSpec {
+    pub id: String,
+    pub violations: Vec<Violation>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Schema {
+    root: Value,
+    patterns: HashMap<String, Regex>,
+}
+
+impl Schema {
+    /// The schema bundled with the generator.
+    pub fn bundled() -> Self {
+        Self::parse(BUNDLED).expect("bundled schema must be valid")
+    }
+
+    /// Schema in JSON (or YAML).
+    pub fn parse(s: &str) -> Result<Self, SchemaError> {
+        let mut schema = Schema {
+            root: serde_yaml::from_str(s)?,
+            patterns: HashMap::new(),
+        };
+        schema.prepare(&schema.root.clone())?;
+        Ok(schema)
+    }
+
+    /// Compiles the patterns and checks that the references resolve.
+    fn prepare(&mut self, node: &Value) -> Result<(), SchemaError> {
+        match node {
+            Value::Mapping(map) => {
+                for (key, value) in map {
+                    match (key.as_str(), value) {
+                        (Some("pattern"), Value::String(pattern)) => self.compile(pattern)?,
+                        (Some("patternProperties"), Value::Mapping(props)) => {
+                            for pattern in props.keys().filter_map(Value::as_str) {
+                                self.compile(pattern)?;
+                            }
+                        }
+                        (Some("$ref"), Value::String(reference)) => {
+                            self.resolve(reference)?;
+                        }
+                        _ => {}
+                    }
+                    self.prepare(value)?;
+                }
+            }
+            Value::Sequence(items) => {
+                for item in items {
+                    self.prepare(item)?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn compile(&mut self, pattern: &str) -> Result<(), SchemaError> {
+        if !self.patterns.contains_key(pattern) {
+            self.patterns
+                .insert(pattern.to_string(), Regex::new(pattern)?);
+        }
+        Ok(())
+    }
+
+    /// Subschema at a local reference like `#/definitions/TypeSpec`.
+    fn resolve(&self, reference: &str) -> Result<&Value, SchemaError> {
+        let pointer = reference
+            .strip_prefix('#')
+            .ok_or_else(|| SchemaError::Ref(reference.to_string()))?;
+        pointer
+            .split('/')
+            .skip(1)
+            .try_fold(&self.root, |node, part| node.get(part))
+            .ok_or_else(|| SchemaError::Ref(reference.to_string()))
+    }
+
+    /// Parts of the spec that don't match the schema, in document order.
+    pub fn validate(&self, spec: &KsySpec) -> Vec<Violation> {
+        let doc = serde_yaml::to_value(spec).expect("spec model must be serializable to YAML");
+        self.validate_value(&doc)
+    }
+
+    /// Parts of a document that don't match the schema, for documents that the spec model can't
+    /// hold, like hand-written specs.
+    pub fn validate_value(&self, doc: &Value) -> Vec<Violation> {
+        let mut violations = Vec::new();
+        self.check(&self.root, doc, "", &mut violations);
+        violations
+    }
+
+    /// Fails with all violations if the spec doesn't match the schema.
+    pub fn check_spec(&self, spec: &KsySpec) -> Result<(), InvalidSpec> {
+        let violations = self.validate(spec);
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(InvalidSpec {
+                id: spec.id().unwrap_or_default().to_string(),
+                violations,
+            })
+        }
+    }
+
+    fn matches(&self, schema: &Value, value: &Value, path: &str) -> bool {
+        let mut violations = Vec::new();
+        self.check(schema, value, path, &mut violations);
+        violations.is_empty()
+    }
+
+    fn check(&self, schema: &Value, value: &Value, path: &str, out: &mut Vec<Violation>) {
+        let Value::Mapping(schema) = schema else {
+            // `true` accepts everything and `false` nothing
+            if schema.as_bool() == Some(false) {
+                out.push(violation(path, "no value is allowed here"));
+            }
+            return;
+        };
+        let keyword = |name: &str| schema.get(name);
+
+        if let Some(reference) = keyword("$ref").and_then(Value::as_str) {
+            let target = self.resolve(reference).expect("references were checked");
+            self.check(target, value, path, out);
+        }
+        for sub in keyword("allOf")
+            .and_then(Value::as_sequence)
+            .into_iter()
+            .flatten()
+        {
+            self.check(sub, value, path, out);
+        }
+        if let Some(subs) = keyword("anyOf").and_then(Value::as_sequence) {
+            if !subs.iter().any(|sub| self.matches(sub, value, path)) {
+                out.push(violation(path, "doesn't match any of the alternatives"));
+            }
+        }
+        if let Some(subs) = keyword("oneOf").and_then(Value::as_sequence) {
+            let count = subs
+                .iter()
+                .filter(|sub| self.matches(sub, value, path))
+                .count();
+            if count != 1 {
+                let message = format!("matches {} of the alternatives instead of one", count);
+                out.push(violation(path, &message));
+            }
+        }
+        if let Some(sub) = keyword("not") {
+            if self.matches(sub, value, path) {
+                out.push(violation(path, "matches a schema that it must not"));
+            }
+        }
+
+        if let Some(types) = keyword("type") {
+            let types: Vec<&str> = match types {
+                Value::Sequence(types) => types.iter().filter_map(Value::as_str).collect(),
+                ty => ty.as_str().into_iter().collect(),
+            };
+            if !types.iter().any(|ty| has_type(value, ty)) {
+                let message = format!("expected {}", types.join(" or "));
+                out.push(violation(path, &message));
+                return;
+            }
+        }
+        if let Some(allowed) = keyword("enum").and_then(Value::as_sequence) {
+            if !allowed.contains(value) {
+                out.push(violation(path, "not one of the allowed values"));
+            }
+        }
+        if let (Some(pattern), Value::String(s)) = (keyword("pattern"), value) {
+            let pattern = pattern.as_str().unwrap_or_default();
+            if !self.patterns[pattern].is_match(s) {
+                let message = format!("`{}` doesn't match `{}`", s, pattern);
+                out.push(violation(path, &message));
+            }
+        }
+        if let Some(x) = value.as_f64() {
+            let min = keyword("minimum").and_then(Value::as_f64);
+            let max = keyword("maximum").and_then(Value::as_f64);
+            if min.is_some_and(|min| x < min) || max.is_some_and(|max| x > max) {
+                out.push(violation(path, "out of range"));
+            }
+        }
+        if let Value::Sequence(items) = value {
+            if let Some(item_schema) = keyword("items") {
+                for (i, item) in items.iter().enumerate() {
+                    self.check(item_schema, item, &format!("{}/{}", path, i), out);
+                }
+            }
+        }
+        if let Value::Mapping(map) = value {
+            self.check_object(schema, map, path, out);
+        }
+    }
+
+    fn check_object(&self, schema: &Mapping, map: &Mapping, path: &str, out: &mut Vec<Violation>) {
+        let keys: Vec<String> = map.keys().map(key_string).collect();
+        for required in schema
+            .get("required")
+            .and_then(Value::as_sequence)
+            .into_iter()
+            .flatten()
+            .filter_map(Value::as_str)
+        {
+            if !keys.iter().any(|key| key == required) {
+                let message = format!("missing `{}`", required);
+                out.push(violation(path, &message));
+            }
+        }
+        if let Some(Value::Mapping(dependencies)) = schema.get("dependencies") {
+            for (key, needed) in dependencies {
+                let key = key_string(key);
+                if !keys.contains(&key) {
+                    continue;
+                }
+                for needed in needed.as_sequence().into_iter().flatten() {
+                    let needed = key_string(needed);
+                    if !keys.contains(&needed) {
+                        let message = format!("`{}` needs `{}`", key, needed);
+                        out.push(violation(path, &message));
+                    }
+                }
+            }
+        }
+
+        let properties = schema.get("properties").and_then(Value::as_mapping);
+        let pattern_properties = schema.get("patternProperties").and_then(Value::as_mapping);
+        for (key, value) in keys.iter().zip(map.values()) {
+            let value_path = format!("{}/{}", path, key);
+            let mut known = false;
+            if let Some(sub) = properties.and_then(|props| props.get(key.as_str())) {
+                known = true;
+                self.check(sub, value, &value_path, out);
+            }
+            for (pattern, sub) in pattern_properties.into_iter().flatten() {
+                let pattern = pattern.as_str().unwrap_or_default();
+                if self.patterns[pattern].is_match(key) {
+                    known = true;
+                    self.check(sub, value, &value_path, out);
+                }
+            }
+            if known {
+                continue;
+            }
+            match schema.get("additionalProperties") {
+                Some(Value::Bool(false)) => {
+                    let message = format!("unexpected key `{}`", key);
+                    out.push(violation(path, &message));
+                }
+                Some(sub @ Value::Mapping(_)) => self.check(sub, value, &value_path, out),
+                _ => {}
+            }
+        }
+    }
+}
+
+fn violation(path: &str, message: &str) -> Violation {
+    Violation {
+        path: path.to_string(),
+        message: message.to_string(),
+    }
+}
+
+/// Key of a mapping as it would be in JSON, where all keys are strings.
+fn key_string(key: &Value) -> String {
+    match key {
+        Value::String(s) => s.clone(),
+        Value::Number(x) => x.to_string(),
+        Value::Bool(x) => x.to_string(),
+        key => serde_yaml::to_string(key)
+            .unwrap_or_default()
+            .trim()
+            .to_string(),
+    }
+}
+
+fn has_type(value: &Value, ty: &str) -> bool {
+    match ty {
+        "object" => value.is_mapping(),
+        "array" => value.is_sequence(),
+        "string" => value.is_string(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "number" => value.is_number(),
+        "boolean" => value.is_bool(),
+        "null" => value.is_null(),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::Expr;
+    use crate::gen::{endian, imports, pairwise, params, valid};
+    use crate::ksy::{Attribute, Repeat};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn generated_specs_are_valid() {
+        let schema = Schema::bundled();
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut specs = vec![params::params_case(&mut rng, "params").spec];
+        let case = imports::imports_case(&mut rng, "imports");
+        specs.push(case.main.spec);
+        specs.extend(case.imports);
+        specs.extend(endian::endian_cases(&mut rng).into_iter().map(|c| c.spec));
+        specs.extend(valid::valid_cases(&mut rng).into_iter().map(|c| c.spec));
+        specs.extend(
+            pairwise::pairwise_cases(&mut rng, 4)
+                .into_iter()
+                .map(|c| c.main.spec),
+        );
+        for spec in &specs {
+            assert_eq!(schema.check_spec(spec), Ok(()));
+        }
+    }
+
+    #[test]
+    fn violations() {
+        let schema = Schema::bundled();
+        let mut spec = KsySpec::top_level("Bad");
+        spec.seq.push(Attribute {
+            repeat_expr: Some(Expr::Int(2)),
+            ..Attribute::new("x", "u1")
+        });
+        spec.instances.insert(
+            "y".to_string(),
+            Attribute {
+                repeat: Some(Repeat::Eos),
+                ..Attribute::new("y", "u1")
+            },
+        );
+        let violations: Vec<String> = schema
+            .validate(&spec)
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            violations,
+            [
+                "/meta/id: `Bad` doesn't match `^[a-z][a-z0-9_]*$`",
+                "/seq/0: `repeat-expr` needs `repeat`",
+                "/instances/y: matches a schema that it must not",
+            ]
+        );
+        let err = schema.check_spec(&spec).unwrap_err();
+        assert!(err.to_string().starts_with("generator bug: spec `Bad` "));
+    }
+
+    #[test]
+    fn custom_schemas() {
+        let schema = Schema::parse(r#"{"properties": {"meta": {"type": "array"}}}"#).unwrap();
+        let violations = schema.validate(&KsySpec::top_level("x"));
+        assert_eq!(violations, [violation("/meta", "expected array")]);
+        let err = Schema::parse(r##"{"$ref": "#/definitions/missing"}"##).unwrap_err();
+        assert!(matches!(err, SchemaError::Ref(_)));
+        let err = Schema::parse(r#"{"pattern": "("}"#).unwrap_err();
+        assert!(matches!(err, SchemaError::Pattern(_)));
+    }
+}
@@ -0,0 +1,408 @@
+use std::fmt;
+
+/// Target languages of KSC that can parse data (i.e. excluding `graphviz` and `html`).
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum Target {
+    Cpp,
+    CSharp,
+    Go,
+    Java,
+    JavaScript,
+    Lua,
+    Nim,
+    Perl,
+    Php,
+    Python,
+    Ruby,
+    Rust,
+    Swift,
+}
+
+impl Target {
+    pub const ALL: [Target; 13] = [
+        Target::Cpp,
+        Target::CSharp,
+        Target::Go,
+        Target::Java,
+        Target::JavaScript,
+        Target::Lua,
+        Target::Nim,
+        Target::Perl,
+        Target::Php,
+        Target::Python,
+        Target::Ruby,
+        Target::Rust,
+        Target::Swift,
+    ];
+
+    /// Name of the target as accepted by the `-t` option of KSC.
+    pub fn ksc_name(self) -> &'static str {
+        match self {
+            Target::Cpp => "cpp_stl",
+            Target::CSharp => "csharp",
+            Target::Go => "go",
+            Target::Java => "java",
+            Target::JavaScript => "javascript",
+            Target::Lua => "lua",
+            Target::Nim => "nim",
+            Target::Perl => "perl",
+            Target::Php => "php",
+            Target::Python => "python",
+            Target::Ruby => "ruby",
+            Target::Rust => "rust",
+            Target::Swift => "swift",
+        }
+    }
+
+    /// Keywords of the language and members of the runtime's base classes and streams, which the
+    /// names of attributes, types and enums may collide with. Only the words that are valid KS
+    /// identifiers are listed.
+    pub fn reserved_words(self) -> &'static [&'static str] {
+        match self {
+            Target::Cpp => &[
+                "auto",
+                "bool",
+                "break",
+                "case",
+                "catch",
+                "char",
+                "class",
+                "const",
+                "default",
+                "delete",
+                "do",
+                "double",
+                "else",
+                "enum",
+                "explicit",
+                "extern",
+                "float",
+                "for",
+                "friend",
+                "goto",
+                "if",
+                "inline",
+                "int",
+                "long",
+                "namespace",
+                "new",
+                "operator",
+                "private",
+                "protected",
+                "public",
+                "register",
+                "return",
+                "short",
+                "signed",
+                "sizeof",
+                "static",
+                "struct",
+                "switch",
+                "template",
+                "this",
+                "throw",
+                "try",
+                "typedef",
+                "typename",
+                "union",
+                "unsigned",
+                "using",
+                "virtual",
+                "void",
+                "volatile",
+                "while",
+                "std",
+                "kaitai",
+                "kstream",
+                "kstruct",
+                "read",
+                "clean_up",
+            ],
+            Target::CSharp => &[
+                "abstract",
+                "base",
+                "byte",
+                "checked",
+                "decimal",
+                "delegate",
+                "event",
+                "fixed",
+                "foreach",
+                "implicit",
+                "in",
+                "interface",
+                "internal",
+                "is",
+                "lock",
+                "object",
+                "operator",
+                "out",
+                "override",
+                "params",
+                "readonly",
+                "ref",
+                "sbyte",
+                "sealed",
+                "stackalloc",
+                "string",
+                "typeof",
+                "uint",
+                "ulong",
+                "unchecked",
+                "unsafe",
+                "ushort",
+                "virtual",
+                "m_io",
+                "m_root",
+                "m_parent",
+                "read",
+                "m_read",
+            ],
+            Target::Go => &[
+                "chan",
+                "defer",
+                "fallthrough",
+                "func",
+                "go",
+                "import",
+                "interface",
+                "map",
+                "package",
+                "range",
+                "select",
+                "type",
+                "var",
+                "error",
+                "string",
+                "len",
+                "cap",
+                "append",
+                "copy",
+                "make",
+                "panic",
+                "recover",
+                "io",
+                "kaitai",
+                "read",
+            ],
+            Target::Java => &[
+                "abstract",
+                "assert",
+                "boolean",
+                "byte",
+                "class",
+                "extends",
+                "final",
+                "finally",
+                "implements",
+                "import",
+                "instanceof",
+                "interface",
+                "native",
+                "package",
+                "strictfp",
+                "super",
+                "synchronized",
+                "throws",
+                "transient",
+                "get_class",
+                "hash_code",
+                "to_string",
+                "equals",
+                "notify",
+                "wait",
+                "read",
+                "from_file",
+            ],
+            Target::JavaScript => &[
+                "arguments",
+                "await",
+                "debugger",
+                "delete",
+                "eval",
+                "export",
+                "function",
+                "instanceof",
+                "let",
+                "typeof",
+                "var",
+                "with",
+                "yield",
+                "constructor",
+                "prototype",
+                "read",
+                "undefined",
+                "null",
+            ],
+            Target::Lua => &[
+                "elseif", "end", "function", "local", "nil", "repeat", "then", "until", "self",
+                "class", "read", "new",
+            ],
+            Target::Nim => &[
+                "addr",
+                "asm",
+                "bind",
+                "block",
+                "concept",
+                "converter",
+                "discard",
+                "distinct",
+                "div",
+                "end",
+                "except",
+                "export",
+                "from",
+                "func",
+                "include",
+                "iterator",
+                "macro",
+                "method",
+                "mixin",
+                "mod",
+                "nil",
+                "proc",
+                "ptr",
+                "raise",
+                "ref",
+                "result",
+                "shl",
+                "shr",
+                "static",
+                "template",
+                "tuple",
+                "type",
+                "var",
+                "when",
+                "xor",
+                "yield",
+                "read",
+                "from_file",
+            ],
+            Target::Perl => &[
+                "elsif",
+                "foreach",
+                "last",
+                "local",
+                "my",
+                "next",
+                "our",
+                "package",
+                "print",
+                "redo",
+                "sub",
+                "unless",
+                "until",
+                "new",
+                "read",
+                "from_file",
+                "bless",
+            ],
+            Target::Php => &[
+                "array",
+                "callable",
+                "clone",
+                "declare",
+                "echo",
+                "empty",
+                "endif",
+                "global",
+                "include",
+                "isset",
+                "list",
+                "print",
+                "require",
+                "trait",
+                "unset",
+                "var",
+                "read",
+                "from_file",
+            ],
+            Target::Python => &[
+                "def",
+                "del",
+                "elif",
+                "except",
+                "exec",
+                "finally",
+                "from",
+                "global",
+                "import",
+                "in",
+                "is",
+                "lambda",
+                "async",
+                "nonlocal",
+                "pass",
+                "print",
+                "raise",
+                "with",
+                "yield",
+                "self",
+                "close",
+                "from_file",
+                "from_bytes",
+                "from_io",
+                "kaitaistruct",
+            ],
+            Target::Ruby => &[
+                "begin",
+                "def",
+                "defined",
+                "elsif",
+                "end",
+                "ensure",
+                "module",
+                "next",
+                "nil",
+                "redo",
+                "rescue",
+                "retry",
+                "self",
+                "then",
+                "undef",
+                "unless",
+                "until",
+                "yield",
+                "class",
+                "initialize",
+                "read",
+                "from_file",
+                "send",
+                "object_id",
+            ],
+            Target::Rust => &[
+                "as", "crate", "dyn", "extern", "fn", "impl", "in", "let", "loop", "match", "mod",
+                "move", "mut", "pub", "ref", "self", "super", "trait", "type", "unsafe", "use",
+                "where", "box", "read", "result",
+            ],
+            Target::Swift => &[
+                "associatedtype",
+                "deinit",
+                "extension",
+                "fileprivate",
+                "func",
+                "guard",
+                "init",
+                "inout",
+                "internal",
+                "let",
+                "open",
+                "operator",
+                "protocol",
+                "repeat",
+                "rethrows",
+                "subscript",
+                "typealias",
+                "var",
+                "where",
+                "read",
+            ],
+        }
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.ksc_name())
+    }
+}
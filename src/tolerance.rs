@@ -0,0 +1,277 @@
+//! Choosing how to compare a floating-point result of an expression with the expected value.
+//!
+//! A single IEEE 754 operation is correctly rounded in every target language, but as soon as a
+//! result had to be rounded, the outcome depends on things like the evaluation order chosen by the
+//! translator, use of extended precision or fused multiply-add contractions. An assertion
+//! checking such a value for exact equality would be brittle.
+
+use crate::ast::utils::binary;
+use crate::ast::utils::PositiveFiniteF64;
+use crate::ast::{BinaryOp, Expr, UnaryOp};
+use crate::eval::{eval, Env, Value};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum FloatComparison {
+    /// No rounding occurred anywhere, the result must match bit for bit
+    Exact,
+    /// The result must be within `epsilon` of the expected value
+    Approx { epsilon: f64 },
+}
+
+/// Number of float operations in `expr` whose result could not be represented exactly and had
+/// to be rounded.
+pub fn rounded_ops(expr: &Expr, env: &Env) -> usize {
+    let own = match expr {
+        Expr::BinaryOp { l, op, r } => match (eval(l, env), eval(r, env)) {
+            (Ok(l), Ok(r)) => usize::from(!is_exact_op(*op, &l, &r)),
+            _ => 0,
+        },
+        _ => 0,
+    };
+    own + expr
+        .children()
+        .into_iter()
+        .map(|child| rounded_ops(child, env))
+        .sum::<usize>()
+}
+
+/// Chooses how the float value of `expr` should be compared in an assertion.
+pub fn comparison_for(expr: &Expr, env: &Env) -> FloatComparison {
+    let rounded = rounded_ops(expr, env);
+    if rounded == 0 {
+        return FloatComparison::Exact;
+    }
+    let magnitude = match eval(expr, env) {
+        Ok(Value::Float(x)) if x.is_finite() => x.abs().max(f64::MIN_POSITIVE),
+        _ => f64::MIN_POSITIVE,
+    };
+    // Each rounding contributes an error of at most half an ulp, but the errors of
+    // intermediate results may get magnified by later operations, so leave a generous margin
+    FloatComparison::Approx {
+        epsilon: magnitude * f64::EPSILON * 4.0 * rounded as f64,
+    }
+}
+
+/// Builds a KS expression checking that `actual` evaluates to `expected` in the given way.
+///
+/// Returns `None` if `expected` is not finite, since such a value has no literal in the KS
+/// expression language.
+pub fn float_assertion(actual: Expr, expected: f64, comparison: FloatComparison) -> Option<Expr> {
+    let expected_lit = float_literal(expected)?;
+    Some(match comparison {
+        FloatComparison::Exact => binary(actual, BinaryOp::Eq, expected_lit),
+        FloatComparison::Approx { epsilon } => {
+            let diff = binary(actual, BinaryOp::Sub, expected_lit);
+            binary(
+                binary(diff.clone(), BinaryOp::Lt, float_literal(epsilon)?),
+                BinaryOp::And,
+                binary(diff, BinaryOp::Gt, float_literal(-epsilon)?),
+            )
+        }
+    })
+}
+
+/// Builds a KS expression checking that `actual` evaluates to the non-finite `expected`
+/// value, which has no literal: NaN is the only value not equal to itself, and infinities are
+/// beyond the largest finite double.
+///
+/// Returns `None` if `expected` is finite.
+pub fn non_finite_assertion(actual: Expr, expected: f64) -> Option<Expr> {
+    let max = float_literal(f64::MAX)?;
+    Some(if expected.is_nan() {
+        binary(actual.clone(), BinaryOp::Ne, actual)
+    } else if expected == f64::INFINITY {
+        binary(actual, BinaryOp::Gt, max)
+    } else if expected == f64::NEG_INFINITY {
+        let min = Expr::UnaryOp {
+            op: UnaryOp::Neg,
+            value: Box::new(max),
+        };
+        binary(actual, BinaryOp::Lt, min)
+    } else {
+        return None;
+    })
+}
+
+/// KS literal for a finite float, wrapping it in a negation if it's negative, `-0.0` included
+/// (there are no negative number literals in the KS expression language).
+pub fn float_literal(value: f64) -> Option<Expr> {
+    let lit = Expr::Float(PositiveFiniteF64::try_from(value.abs()).ok()?);
+    Some(if value.is_sign_negative() {
+        Expr::UnaryOp {
+            op: UnaryOp::Neg,
+            value: Box::new(lit),
+        }
+    } else {
+        lit
+    })
+}
+
+fn as_exact_f64(value: &Value) -> Option<f64> {
+    match value {
+        Value::Float(x) => Some(*x),
+        // integers beyond 2^53 get rounded when they're converted to a float
+        Value::Int(x) if (*x as f64) as i128 == *x => Some(*x as f64),
+        _ => None,
+    }
+}
+
+/// Whether the float operation `l op r` gives an exactly representable result. Operations not
+/// involving floats are always considered exact.
+fn is_exact_op(op: BinaryOp, l: &Value, r: &Value) -> bool {
+    if !matches!(l, Value::Float(_)) && !matches!(r, Value::Float(_)) {
+        return true;
+    }
+    let (a, b) = match (as_exact_f64(l), as_exact_f64(r)) {
+        (Some(a), Some(b)) => (a, b),
+        _ => return false,
+    };
+    match op {
+        BinaryOp::Add | BinaryOp::Sub => {
+            let b = if op == BinaryOp::Sub { -b } else { b };
+            let s = a + b;
+            if !s.is_finite() {
+                return false;
+            }
+            // TwoSum error-free transformation (Knuth)
+            let bb = s - a;
+            let err = (a - (s - bb)) + (b - bb);
+            err == 0.0
+        }
+        BinaryOp::Mul => {
+            let p = a * b;
+            p.is_finite() && a.mul_add(b, -p) == 0.0
+        }
+        BinaryOp::Div => {
+            if b == 0.0 {
+                return true;
+            }
+            let q = a / b;
+            q.is_finite() && q.mul_add(b, -a) == 0.0
+        }
+        // the remainder is only guaranteed to be exact for integral operands
+        BinaryOp::Rem => a.fract() == 0.0 && b.fract() == 0.0,
+        // comparisons don't produce a float
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::translator::translate;
+
+    fn float(x: f64) -> Expr {
+        Expr::Float(PositiveFiniteF64::try_from(x).unwrap())
+    }
+
+    #[test]
+    fn exact_sum() {
+        let expr = binary(float(0.5), BinaryOp::Add, float(0.25));
+        assert_eq!(rounded_ops(&expr, &Env::new()), 0);
+        assert_eq!(comparison_for(&expr, &Env::new()), FloatComparison::Exact);
+    }
+
+    #[test]
+    fn inexact_sum() {
+        let expr = binary(float(0.1), BinaryOp::Add, float(0.2));
+        assert_eq!(rounded_ops(&expr, &Env::new()), 1);
+        assert!(matches!(
+            comparison_for(&expr, &Env::new()),
+            FloatComparison::Approx { .. }
+        ));
+    }
+
+    #[test]
+    fn inexact_div() {
+        let expr = binary(Expr::Int(1), BinaryOp::Div, float(3.0));
+        assert_eq!(rounded_ops(&expr, &Env::new()), 1);
+        let expr = binary(Expr::Int(1), BinaryOp::Div, float(4.0));
+        assert_eq!(rounded_ops(&expr, &Env::new()), 0);
+    }
+
+    #[test]
+    fn int_ops_are_exact() {
+        let expr = binary(Expr::Int(1), BinaryOp::Div, Expr::Int(3));
+        assert_eq!(rounded_ops(&expr, &Env::new()), 0);
+    }
+
+    #[test]
+    fn large_int_promoted_to_float() {
+        let expr = binary(Expr::Int((1 << 53) + 1), BinaryOp::Mul, float(1.0));
+        assert_eq!(rounded_ops(&expr, &Env::new()), 1);
+    }
+
+    #[test]
+    fn exact_assertion() {
+        let assertion =
+            float_assertion(Expr::Name("x".to_string()), -1.5, FloatComparison::Exact).unwrap();
+        assert_eq!(translate(&assertion), "(x == (-1.5))");
+    }
+
+    #[test]
+    fn approx_assertion() {
+        let assertion = float_assertion(
+            Expr::Name("x".to_string()),
+            0.3,
+            FloatComparison::Approx { epsilon: 0.001 },
+        )
+        .unwrap();
+        assert_eq!(
+            translate(&assertion),
+            "(((x - 0.3) < 0.001) and ((x - 0.3) > (-0.001)))"
+        );
+    }
+
+    #[test]
+    fn approx_assertion_holds() {
+        let expr = binary(float(0.1), BinaryOp::Add, float(0.2));
+        let env = Env::new();
+        let comparison = comparison_for(&expr, &env);
+        let assertion = float_assertion(expr, 0.3, comparison).unwrap();
+        assert_eq!(eval(&assertion, &env), Ok(Value::Bool(true)));
+    }
+
+    #[test]
+    fn non_finite_assertions() {
+        let x = Expr::Name("x".to_string());
+        let nan = non_finite_assertion(x.clone(), f64::NAN).unwrap();
+        assert_eq!(translate(&nan), "(x != x)");
+        for value in [f64::NAN, f64::INFINITY, f64::NEG_INFINITY] {
+            let mut env = Env::new();
+            env.set("x", Value::Float(value));
+            for expected in [f64::NAN, f64::INFINITY, f64::NEG_INFINITY] {
+                // the evaluator doesn't order NaN
+                if value.is_nan() && !expected.is_nan() {
+                    continue;
+                }
+                let assertion = non_finite_assertion(x.clone(), expected).unwrap();
+                let holds = value.to_bits() == expected.to_bits();
+                assert_eq!(eval(&assertion, &env), Ok(Value::Bool(holds)));
+            }
+        }
+        assert_eq!(non_finite_assertion(x, 1.0), None);
+    }
+
+    #[test]
+    fn non_finite_has_no_assertion() {
+        let assertion = float_assertion(
+            Expr::Name("x".to_string()),
+            f64::INFINITY,
+            FloatComparison::Exact,
+        );
+        assert_eq!(assertion, None);
+    }
+
+    #[test]
+    fn signed_literals() {
+        for value in [1.5, -1.5, 0.0, -0.0] {
+            let Ok(Value::Float(evaluated)) = eval(&float_literal(value).unwrap(), &Env::new())
+            else {
+                panic!("{}", value);
+            };
+            assert_eq!(evaluated.to_bits(), value.to_bits(), "{}", value);
+        }
+        assert_eq!(translate(&float_literal(-0.0).unwrap()), "(-0.0)");
+    }
+}
@@ -1,108 +1,178 @@
-use crate::ast::{BinaryOp, Expr, UnaryOp};
+use std::ops::Range;
+
+use serde::{Serialize, Serializer};
+
+use crate::ast::{Expr, UnaryOp};
+
+pub mod native;
 
 pub fn translate(expr: &Expr) -> String {
-    match expr {
-        Expr::Int(x) => x.to_string(),
-        Expr::Float(x) => {
-            let value = x.value();
-            let formatted = if should_format_float_with_exponent(value) {
-                format!("{:e}", value)
-            } else {
-                value.to_string()
-            };
-            if formatted.chars().all(|ch| ch.is_ascii_digit()) {
-                // The float has been formatted as a valid integer, which means that KSC would
-                // interpret it as an integer if we leave it as is. But we don't want that - this
-                // AST node represents a float and it must remain this way.
-                formatted + ".0"
-            } else {
-                formatted
-            }
+    let mut writer = Writer::new(&[]);
+    writer.write(expr, &mut Vec::new());
+    writer.out
+}
+
+/// Expressions are serialized in the KS expression syntax, which is how they are written in ksy and
+/// KST files. Integer and boolean literals become YAML scalars of the corresponding type.
+impl Serialize for Expr {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Expr::Int(x) => serializer.serialize_u64(*x),
+            Expr::Bool(x) => serializer.serialize_bool(*x),
+            expr => serializer.serialize_str(&translate(expr)),
         }
-        Expr::Str(x) => {
-            // See https://doc.kaitai.io/user_guide.html#_basic_data_types:
-            // > Everything between single quotes is interpreted literally, i.e. there is no way one
-            // > can include a single quote inside a single quoted string.
-            assert!(
-                !x.contains('\''),
-                "strings containing a single quote (') not supported yet (got {})",
-                x
-            );
-            format!("'{}'", x)
+    }
+}
+
+/// Byte range occupied by the node at `path` (a sequence of indices into [`Expr::children`])
+/// in the result of [`translate`]. Returns `None` if there is no such node.
+pub fn node_span(expr: &Expr, path: &[usize]) -> Option<Range<usize>> {
+    let mut writer = Writer::new(path);
+    writer.write(expr, &mut Vec::new());
+    writer.span
+}
+
+struct Writer<'a> {
+    out: String,
+    /// Path of the node whose span should be recorded
+    target: &'a [usize],
+    span: Option<Range<usize>>,
+}
+
+impl<'a> Writer<'a> {
+    fn new(target: &'a [usize]) -> Self {
+        Self {
+            out: String::new(),
+            target,
+            span: None,
         }
-        Expr::Bool(x) => x.to_string(),
-        Expr::EnumMember { enum_path, label } => {
-            let mut parts: Vec<&str> = enum_path.iter().map(|s| s.as_str()).collect();
-            parts.push(label);
-            parts.join("::")
+    }
+
+    fn write(&mut self, expr: &Expr, path: &mut Vec<usize>) {
+        let start = self.out.len();
+        match expr {
+            Expr::Int(x) => self.out.push_str(&x.to_string()),
+            Expr::Float(x) => {
+                let value = x.value();
+                let formatted = if should_format_float_with_exponent(value) {
+                    format!("{:e}", value)
+                } else {
+                    value.to_string()
+                };
+                self.out.push_str(&formatted);
+                if formatted.chars().all(|ch| ch.is_ascii_digit()) {
+                    // The float has been formatted as a valid integer, which means that KSC would
+                    // interpret it as an integer if we leave it as is. But we don't want that -
+                    // this AST node represents a float and it must remain this way.
+                    self.out.push_str(".0");
+                }
+            }
+            Expr::Str(x) => {
+                // See https://doc.kaitai.io/user_guide.html#_basic_data_types:
+                // > Everything between single quotes is interpreted literally, i.e. there is no
+                // > way one can include a single quote inside a single quoted string.
+                assert!(
+                    !x.contains('\''),
+                    "strings containing a single quote (') not supported yet (got {})",
+                    x
+                );
+                self.out.push_str(&format!("'{}'", x));
+            }
+            Expr::Bool(x) => self.out.push_str(&x.to_string()),
+            Expr::EnumMember { enum_path, label } => {
+                let mut parts: Vec<&str> = enum_path.iter().map(|s| s.as_str()).collect();
+                parts.push(label);
+                self.out.push_str(&parts.join("::"));
+            }
+            Expr::List(items) => {
+                self.out.push('[');
+                self.write_separated(items, 0, path);
+                self.out.push(']');
+            }
+
+            Expr::Name(name) => self.out.push_str(name),
+            Expr::Attribute { value, attr_name } => {
+                self.write_child(0, value, path);
+                self.out.push('.');
+                self.out.push_str(attr_name);
+            }
+            Expr::MethodCall {
+                value,
+                method_name,
+                args,
+            } => {
+                self.write_child(0, value, path);
+                self.out.push('.');
+                self.out.push_str(method_name);
+                self.out.push('(');
+                self.write_separated(args, 1, path);
+                self.out.push(')');
+            }
+
+            Expr::UnaryOp { op, value } => {
+                self.out.push('(');
+                self.out.push_str(op.symbol());
+                if *op == UnaryOp::Not {
+                    self.out.push(' ');
+                }
+                self.write_child(0, value, path);
+                self.out.push(')');
+            }
+            Expr::BinaryOp { l, op, r } => {
+                self.out.push('(');
+                self.write_child(0, l, path);
+                self.out.push(' ');
+                self.out.push_str(op.symbol());
+                self.out.push(' ');
+                self.write_child(1, r, path);
+                self.out.push(')');
+            }
+            Expr::CondOp {
+                cond,
+                if_true,
+                if_false,
+            } => {
+                self.out.push('(');
+                self.write_child(0, cond, path);
+                self.out.push_str(" ? ");
+                self.write_child(1, if_true, path);
+                self.out.push_str(" : ");
+                self.write_child(2, if_false, path);
+                self.out.push(')');
+            }
+            Expr::Subscript { value, idx } => {
+                self.write_child(0, value, path);
+                self.out.push('[');
+                self.write_child(1, idx, path);
+                self.out.push(']');
+            }
+            Expr::CastTo { value, type_name } => {
+                self.write_child(0, value, path);
+                self.out.push_str(&format!(".as<{}>", type_name));
+            }
+            Expr::SizeOf { type_name, bits } => {
+                let keyword = if *bits { "bitsizeof" } else { "sizeof" };
+                self.out.push_str(&format!("{}<{}>", keyword, type_name));
+            }
+        }
+        if path.as_slice() == self.target {
+            self.span = Some(start..self.out.len());
         }
-        Expr::List(items) => format!(
-            "[{}]",
-            items.iter().map(translate).collect::<Vec<_>>().join(", ")
-        ),
-
-        Expr::Name(name) => name.clone(),
-        Expr::Attribute { value, attr_name } => format!("{}.{}", translate(value), attr_name),
-        Expr::MethodCall {
-            value,
-            method_name,
-            args,
-        } => format!(
-            "{}.{}({})",
-            translate(value),
-            method_name,
-            args.iter().map(translate).collect::<Vec<_>>().join(", ")
-        ),
-
-        Expr::UnaryOp { op, value } => format!("({}{})", translate_unary_op(op), translate(value)),
-        Expr::BinaryOp { l, op, r } => format!(
-            "({} {} {})",
-            translate(l),
-            translate_binary_op(op),
-            translate(r)
-        ),
-        Expr::CondOp {
-            cond,
-            if_true,
-            if_false,
-        } => format!(
-            "({} ? {} : {})",
-            translate(cond),
-            translate(if_true),
-            translate(if_false)
-        ),
-        Expr::Subscript { value, idx } => format!("{}[{}]", translate(value), translate(idx)),
     }
-}
 
-fn translate_unary_op(op: &UnaryOp) -> &'static str {
-    match op {
-        UnaryOp::Neg => "-",
-        UnaryOp::Not => "not ",
-        UnaryOp::Inv => "~",
+    fn write_child(&mut self, idx: usize, child: &Expr, path: &mut Vec<usize>) {
+        path.push(idx);
+        self.write(child, path);
+        path.pop();
     }
-}
 
-fn translate_binary_op(op: &BinaryOp) -> &'static str {
-    match op {
-        BinaryOp::Add => "+",
-        BinaryOp::Sub => "-",
-        BinaryOp::Mul => "*",
-        BinaryOp::Div => "/",
-        BinaryOp::Rem => "%",
-        BinaryOp::Eq => "==",
-        BinaryOp::Ne => "!=",
-        BinaryOp::Lt => "<",
-        BinaryOp::Le => "<=",
-        BinaryOp::Gt => ">",
-        BinaryOp::Ge => ">=",
-        BinaryOp::And => "and",
-        BinaryOp::Or => "or",
-        BinaryOp::BitOr => "|",
-        BinaryOp::BitXor => "^",
-        BinaryOp::BitAnd => "&",
-        BinaryOp::Shl => "<<",
-        BinaryOp::Shr => ">>",
+    fn write_separated(&mut self, items: &[Expr], first_idx: usize, path: &mut Vec<usize>) {
+        for (i, item) in items.iter().enumerate() {
+            if i > 0 {
+                self.out.push_str(", ");
+            }
+            self.write_child(first_idx + i, item, path);
+        }
     }
 }
 
@@ -118,6 +188,8 @@ fn should_format_float_with_exponent(value: f64) -> bool {
 mod tests {
     use super::*;
     use crate::ast::utils::PositiveFiniteF64;
+    use crate::ast::BinaryOp;
+    use crate::ast::TypeName;
 
     #[test]
     fn int() {
@@ -260,6 +332,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::useless_vec)]
     fn enum_member() {
         let expr = Expr::EnumMember {
             enum_path: vec!["some_type", "port"]
@@ -702,4 +775,43 @@ mod tests {
         };
         assert_eq!(translate(&expr), "[[1, 300], [(-1), 1]]['1'.to_i][0]");
     }
+
+    #[test]
+    fn node_span_nested() {
+        let expr = Expr::BinaryOp {
+            l: Box::new(Expr::Name("a".to_string())),
+            op: BinaryOp::Add,
+            r: Box::new(Expr::MethodCall {
+                value: Box::new(Expr::Str("xyz".to_string())),
+                method_name: "substring".to_string(),
+                args: vec![Expr::Int(0), Expr::Int(12)],
+            }),
+        };
+        let translated = translate(&expr);
+        assert_eq!(translated, "(a + 'xyz'.substring(0, 12))");
+        assert_eq!(node_span(&expr, &[]), Some(0..translated.len()));
+        assert_eq!(node_span(&expr, &[0]), Some(1..2));
+        let span = node_span(&expr, &[1, 2]).unwrap();
+        assert_eq!(&translated[span], "12");
+        assert_eq!(node_span(&expr, &[1, 3]), None);
+    }
+
+    #[test]
+    fn cast_and_sizeof() {
+        let expr = Expr::BinaryOp {
+            l: Box::new(Expr::CastTo {
+                value: Box::new(Expr::Name("x".to_string())),
+                type_name: TypeName::new("u2"),
+            }),
+            op: BinaryOp::Add,
+            r: Box::new(Expr::SizeOf {
+                type_name: TypeName {
+                    path: vec!["hdr".to_string(), "entry".to_string()],
+                    is_array: false,
+                },
+                bits: true,
+            }),
+        };
+        assert_eq!(translate(&expr), "(x.as<u2> + bitsizeof<hdr::entry>)");
+    }
 }
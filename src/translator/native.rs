@@ -0,0 +1,439 @@
+//! KS expressions of test asserts in the syntax of the target languages: attributes of the
+//! parsed object, enum members and literals, with the names that KSC gives them in each
+//! language. Only what asserts on parsed values need is covered; operators whose meaning
+//! depends on the types of the operands (like `/` or `+`) aren't.
+
+use thiserror::Error;
+
+use crate::ast::{BinaryOp, Expr, UnaryOp};
+use crate::target::Target;
+
+use super::translate;
+
+#[derive(Clone, Debug, Error, PartialEq)]
+#[error("`{expr}` has no translation to {target}")]
+pub struct NativeError {
+    pub expr: String,
+    pub target: Target,
+}
+
+/// Methods of KS values that are written like attributes, whose names differ per language.
+const BUILT_IN_METHODS: [&str; 9] = [
+    "first", "last", "length", "max", "min", "reverse", "size", "to_i", "to_s",
+];
+
+/// Translator of expressions on the object parsed with the spec `spec_id`, which the variable
+/// `root` holds.
+#[derive(Clone, Debug)]
+pub struct NativeTranslator<'a> {
+    pub target: Target,
+    pub spec_id: &'a str,
+    pub root: &'a str,
+}
+
+impl NativeTranslator<'_> {
+    pub fn translate(&self, expr: &Expr) -> Result<String, NativeError> {
+        let target = self.target;
+        let unsupported = || NativeError {
+            expr: translate(expr),
+            target,
+        };
+        Ok(match expr {
+            Expr::Int(x) => int_literal(target, *x),
+            Expr::Float(x) => float_literal(x.value()),
+            Expr::Str(s) => str_literal(target, s),
+            Expr::Bool(x) => bool_literal(target, *x),
+            Expr::EnumMember { enum_path, label } => self.enum_member(enum_path, label),
+            Expr::List(_) | Expr::CastTo { .. } => {
+                let bytes = byte_list(expr).ok_or_else(unsupported)?;
+                bytes_literal(target, &bytes)
+            }
+            Expr::Name(name) if name == "_root" => self.root.to_string(),
+            Expr::Name(name) if !name.starts_with('_') => attribute(target, self.root, name),
+            Expr::Attribute { value, attr_name }
+                if !attr_name.starts_with('_')
+                    && !BUILT_IN_METHODS.contains(&attr_name.as_str()) =>
+            {
+                attribute(target, &self.translate(value)?, attr_name)
+            }
+            Expr::Subscript { value, idx } => match **idx {
+                Expr::Int(index) => subscript(target, &self.translate(value)?, index),
+                _ => return Err(unsupported()),
+            },
+            // the literal of its magnitude would be an unsigned (or, in PHP, a float) one
+            Expr::UnaryOp {
+                op: UnaryOp::Neg,
+                value,
+            } if matches!(target, Target::CSharp | Target::Php)
+                && **value == Expr::Int(1 << 63) =>
+            {
+                match target {
+                    Target::CSharp => "long.MinValue",
+                    _ => "PHP_INT_MIN",
+                }
+                .to_string()
+            }
+            Expr::UnaryOp { op, value } => {
+                let value = self.translate(value)?;
+                match (op, target) {
+                    (UnaryOp::Neg, _) => format!("(-{})", value),
+                    (UnaryOp::Not, Target::Python | Target::Lua | Target::Nim) => {
+                        format!("(not {})", value)
+                    }
+                    (UnaryOp::Not, _) => format!("(!{})", value),
+                    (UnaryOp::Inv, _) => return Err(unsupported()),
+                }
+            }
+            Expr::BinaryOp { l, op, r } => {
+                let op = binary_op(target, *op).ok_or_else(unsupported)?;
+                format!("({} {} {})", self.translate(l)?, op, self.translate(r)?)
+            }
+            _ => return Err(unsupported()),
+        })
+    }
+
+    /// Member of an enum, whose path has the names of the types it's nested in (below the
+    /// top-level one) before its own.
+    fn enum_member(&self, enum_path: &[String], label: &str) -> String {
+        let (enum_name, types) = enum_path
+            .split_last()
+            .expect("enum paths must not be empty");
+        let class = class_name(self.target, self.spec_id);
+        let nested = |separator: &str| {
+            let mut path = vec![class.clone()];
+            path.extend(types.iter().map(|ty| upper_camel(ty)));
+            path.push(upper_camel(enum_name));
+            path.join(separator)
+        };
+        match self.target {
+            Target::Cpp => {
+                let mut path = vec![class.clone()];
+                path.extend(types.iter().map(|ty| format!("{}_t", ty)));
+                path.push(format!("{}_{}", enum_name, label).to_uppercase());
+                path.join("::")
+            }
+            Target::CSharp => format!("{}.{}", nested("."), upper_camel(label)),
+            Target::Go => format!("{}__{}", nested("_"), upper_camel(label)),
+            Target::Java | Target::JavaScript => {
+                format!("{}.{}", nested("."), label.to_uppercase())
+            }
+            Target::Lua | Target::Python => format!("{}.{}", nested("."), label),
+            Target::Nim => format!("{}.{}", nested("_"), label),
+            Target::Perl => {
+                let mut path = vec![class.clone()];
+                path.extend(types.iter().map(|ty| upper_camel(ty)));
+                let constant = format!("{}_{}", enum_name, label).to_uppercase();
+                format!("${}::{}", path.join("::"), constant)
+            }
+            Target::Php => format!("{}::{}", nested("\\"), label.to_uppercase()),
+            Target::Ruby => format!(":{}_{}", enum_name, label),
+            Target::Rust => format!("{}::{}", nested("_"), upper_camel(label)),
+            Target::Swift => format!("{}.{}", nested("."), lower_camel(label)),
+        }
+    }
+}
+
+/// Name of the class (or module) that KSC generates for the spec with the `meta/id`.
+pub fn class_name(target: Target, id: &str) -> String {
+    match target {
+        Target::Cpp => format!("{}_t", id),
+        _ => upper_camel(id),
+    }
+}
+
+/// `foo_bar` as `FooBar`
+pub fn upper_camel(name: &str) -> String {
+    name.split('_')
+        .map(|part| {
+            let mut chars = part.chars();
+            match chars.next() {
+                Some(first) => first.to_ascii_uppercase().to_string() + chars.as_str(),
+                None => String::new(),
+            }
+        })
+        .collect()
+}
+
+/// `foo_bar` as `fooBar`
+pub fn lower_camel(name: &str) -> String {
+    let upper = upper_camel(name);
+    let mut chars = upper.chars();
+    match chars.next() {
+        Some(first) => first.to_ascii_lowercase().to_string() + chars.as_str(),
+        None => upper,
+    }
+}
+
+/// Attribute (or instance) of the object `obj`.
+fn attribute(target: Target, obj: &str, name: &str) -> String {
+    match target {
+        Target::Cpp => format!("{}->{}()", obj, name),
+        Target::CSharp | Target::Go => format!("{}.{}", obj, upper_camel(name)),
+        Target::Java => format!("{}.{}()", obj, lower_camel(name)),
+        Target::JavaScript | Target::Nim | Target::Swift => {
+            format!("{}.{}", obj, lower_camel(name))
+        }
+        Target::Lua | Target::Python | Target::Ruby => format!("{}.{}", obj, name),
+        Target::Perl => format!("{}->{}()", obj, name),
+        Target::Php => format!("{}->{}()", obj, lower_camel(name)),
+        Target::Rust => format!("{}.{}()", obj, name),
+    }
+}
+
+fn subscript(target: Target, array: &str, index: u64) -> String {
+    match target {
+        Target::Cpp => format!("{}->at({})", array, index),
+        Target::Java => format!("{}.get({})", array, index),
+        // Lua tables are indexed from 1
+        Target::Lua => format!("{}[{}]", array, index + 1),
+        Target::Perl => format!("{}->[{}]", array, index),
+        _ => format!("{}[{}]", array, index),
+    }
+}
+
+fn binary_op(target: Target, op: BinaryOp) -> Option<&'static str> {
+    let word_logic = matches!(target, Target::Python | Target::Lua | Target::Nim);
+    Some(match op {
+        BinaryOp::Sub => "-",
+        BinaryOp::Mul => "*",
+        BinaryOp::Eq => "==",
+        BinaryOp::Ne if target == Target::Lua => "~=",
+        BinaryOp::Ne => "!=",
+        BinaryOp::Lt => "<",
+        BinaryOp::Le => "<=",
+        BinaryOp::Gt => ">",
+        BinaryOp::Ge => ">=",
+        BinaryOp::And if word_logic => "and",
+        BinaryOp::And => "&&",
+        BinaryOp::Or if word_logic => "or",
+        BinaryOp::Or => "||",
+        _ => return None,
+    })
+}
+
+/// Integer literal with the suffix that the language needs for it to have a 64-bit type.
+pub fn int_literal(target: Target, x: u64) -> String {
+    let beyond_i32 = x > i32::MAX as u64;
+    let beyond_i64 = x > i64::MAX as u64;
+    match target {
+        Target::Cpp if beyond_i64 => format!("{}ULL", x),
+        Target::Cpp if beyond_i32 => format!("{}LL", x),
+        Target::CSharp if beyond_i64 => format!("{}UL", x),
+        Target::CSharp | Target::Java if beyond_i32 && !beyond_i64 => format!("{}L", x),
+        // Java has no unsigned longs, and `u8` values are read into signed ones
+        Target::Java if beyond_i64 => format!("0x{:x}L", x),
+        Target::Nim if beyond_i64 => format!("{}'u64", x),
+        // the bits of the value as a signed integer, which is how the runtimes read `u8`
+        Target::Lua if beyond_i64 => format!("0x{:x}", x),
+        Target::Php if beyond_i64 => format!("{}", x as i64),
+        _ => x.to_string(),
+    }
+}
+
+/// Float literal that no language reads as an integer.
+pub fn float_literal(x: f64) -> String {
+    format!("{:?}", x)
+}
+
+pub fn bool_literal(target: Target, x: bool) -> String {
+    match (target, x) {
+        (Target::Python, true) => "True".to_string(),
+        (Target::Python, false) => "False".to_string(),
+        (Target::Perl, x) => u8::from(x).to_string(),
+        (_, x) => x.to_string(),
+    }
+}
+
+/// Double-quoted string literal, with non-ASCII characters as they are and the characters the
+/// language treats specially escaped.
+pub fn str_literal(target: Target, s: &str) -> String {
+    let mut out = String::from("\"");
+    for ch in s.chars() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            '$' if matches!(target, Target::Perl | Target::Php) => out.push_str("\\$"),
+            '@' if target == Target::Perl => out.push_str("\\@"),
+            '#' if target == Target::Ruby => out.push_str("\\#"),
+            ch if ch.is_control() => {
+                let code = ch as u32;
+                out.push_str(&match target {
+                    Target::Cpp => format!("\\{:03o}", code),
+                    Target::Perl => format!("\\x{{{:x}}}", code),
+                    Target::CSharp
+                    | Target::Go
+                    | Target::Java
+                    | Target::JavaScript
+                    | Target::Python => format!("\\u{:04x}", code),
+                    _ => format!("\\u{{{:x}}}", code),
+                });
+            }
+            ch => out.push(ch),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Literal of a byte array, of the type that the runtime reads them into.
+pub fn bytes_literal(target: Target, bytes: &[u8]) -> String {
+    let join = |format: &dyn Fn(u8) -> String| {
+        bytes
+            .iter()
+            .map(|b| format(*b))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let escaped = || {
+        bytes
+            .iter()
+            .map(|b| format!("\\x{:02x}", b))
+            .collect::<String>()
+    };
+    match target {
+        Target::Cpp => format!("std::string(\"{}\", {})", escaped(), bytes.len()),
+        Target::CSharp => format!("new byte[] {{ {} }}", join(&|b| b.to_string())),
+        Target::Go => format!("[]uint8{{{}}}", join(&|b| b.to_string())),
+        Target::Java => format!("new byte[] {{ {} }}", join(&|b| (b as i8).to_string())),
+        Target::JavaScript => format!("[{}]", join(&|b| b.to_string())),
+        Target::Lua | Target::Php => format!("\"{}\"", escaped()),
+        Target::Nim => format!("@[{}]", join(&|b| format!("{}'u8", b))),
+        Target::Perl => format!("pack('C*', ({}))", join(&|b| b.to_string())),
+        Target::Python => format!("b\"{}\"", escaped()),
+        Target::Ruby => format!("[{}].pack('C*')", join(&|b| b.to_string())),
+        Target::Rust if bytes.is_empty() => "Vec::<u8>::new()".to_string(),
+        Target::Rust => format!("vec![{}]", join(&|b| format!("{}u8", b))),
+        Target::Swift => format!("[UInt8]([{}])", join(&|b| b.to_string())),
+    }
+}
+
+/// Bytes of a list of byte literals (possibly cast to `bytes`, as an empty one must be).
+pub fn byte_list(expr: &Expr) -> Option<Vec<u8>> {
+    match expr {
+        Expr::List(items) => items
+            .iter()
+            .map(|item| match item {
+                Expr::Int(x) => u8::try_from(*x).ok(),
+                _ => None,
+            })
+            .collect(),
+        Expr::CastTo { value, type_name } if type_name.simple_name() == Some("bytes") => {
+            byte_list(value)
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::parser::parse_expr;
+
+    fn native(target: Target, source: &str) -> String {
+        let translator = NativeTranslator {
+            target,
+            spec_id: "enum_test",
+            root: if matches!(target, Target::Perl | Target::Php) {
+                "$r"
+            } else {
+                "r"
+            },
+        };
+        translator.translate(&parse_expr(source).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn paths() {
+        let path = "blocks[2].header_info.magic";
+        let expected = [
+            (Target::Cpp, "r->blocks()->at(2)->header_info()->magic()"),
+            (Target::CSharp, "r.Blocks[2].HeaderInfo.Magic"),
+            (Target::Go, "r.Blocks[2].HeaderInfo.Magic"),
+            (Target::Java, "r.blocks().get(2).headerInfo().magic()"),
+            (Target::JavaScript, "r.blocks[2].headerInfo.magic"),
+            (Target::Lua, "r.blocks[3].header_info.magic"),
+            (Target::Perl, "$r->blocks()->[2]->header_info()->magic()"),
+            (Target::Php, "$r->blocks()[2]->headerInfo()->magic()"),
+            (Target::Python, "r.blocks[2].header_info.magic"),
+            (Target::Rust, "r.blocks()[2].header_info().magic()"),
+        ];
+        for (target, translated) in expected {
+            assert_eq!(native(target, path), translated, "{}", target);
+        }
+    }
+
+    #[test]
+    fn enum_members() {
+        let expected = [
+            (Target::Cpp, "enum_test_t::ANIMAL_CAT"),
+            (Target::CSharp, "EnumTest.Animal.Cat"),
+            (Target::Go, "EnumTest_Animal__Cat"),
+            (Target::Java, "EnumTest.Animal.CAT"),
+            (Target::Perl, "$EnumTest::ANIMAL_CAT"),
+            (Target::Php, "EnumTest\\Animal::CAT"),
+            (Target::Python, "EnumTest.Animal.cat"),
+            (Target::Ruby, ":animal_cat"),
+            (Target::Rust, "EnumTest_Animal::Cat"),
+        ];
+        for (target, translated) in expected {
+            assert_eq!(native(target, "animal::cat"), translated, "{}", target);
+        }
+        assert_eq!(
+            native(Target::Java, "header::kind::small_one"),
+            "EnumTest.Header.Kind.SMALL_ONE"
+        );
+        assert_eq!(
+            native(Target::Cpp, "header::kind::a"),
+            "enum_test_t::header_t::KIND_A"
+        );
+    }
+
+    #[test]
+    fn literals() {
+        assert_eq!(
+            native(Target::Java, "[0x50, 0xff]"),
+            "new byte[] { 80, -1 }"
+        );
+        assert_eq!(native(Target::Python, "[].as<bytes>"), "b\"\"");
+        assert_eq!(
+            native(Target::Cpp, "[0x50, 0x41]"),
+            "std::string(\"\\x50\\x41\", 2)"
+        );
+        assert_eq!(native(Target::Java, "4294967296"), "4294967296L");
+        assert_eq!(
+            native(Target::Java, "18446744073709551615"),
+            "0xffffffffffffffffL"
+        );
+        assert_eq!(
+            native(Target::CSharp, "18446744073709551615"),
+            "18446744073709551615UL"
+        );
+        assert_eq!(native(Target::Python, "-4.0"), "(-4.0)");
+        assert_eq!(
+            native(Target::CSharp, "-9223372036854775808"),
+            "long.MinValue"
+        );
+        assert_eq!(native(Target::Php, "-9223372036854775808"), "PHP_INT_MIN");
+        assert_eq!(native(Target::Python, "true"), "True");
+        assert_eq!(native(Target::Perl, "\"a$b\\u0001\""), "\"a\\$b\\x{1}\"");
+        assert_eq!(native(Target::Rust, "\"é\\n\""), "\"é\\n\"");
+        assert_eq!(native(Target::Lua, "x != x"), "(r.x ~= r.x)");
+        assert_eq!(native(Target::Python, "not (x > 1.5)"), "(not (r.x > 1.5))");
+    }
+
+    #[test]
+    fn unsupported() {
+        let translator = NativeTranslator {
+            target: Target::Python,
+            spec_id: "t",
+            root: "r",
+        };
+        for source in ["a / 2", "a.to_s", "items[i]", "_io.pos", "[1, 300]", "~a"] {
+            let expr = parse_expr(source).unwrap();
+            assert!(translator.translate(&expr).is_err(), "{}", source);
+        }
+    }
+}
@@ -0,0 +1,951 @@
+//! Type inference for KS expressions, mirroring `TypeDetector` of KSC:
+//! https://github.com/kaitai-io/kaitai_struct_compiler/blob/master/shared/src/main/scala/io/kaitai/struct/translators/TypeDetector.scala
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::ops::Range;
+use std::str::FromStr;
+
+use serde::de::{self, Deserialize, Deserializer};
+use serde::ser::{Serialize, Serializer};
+
+use thiserror::Error;
+
+use crate::ast::TypeName;
+use crate::ast::{BinaryOp, Expr, UnaryOp};
+use crate::numeric::{builtin_byte_size, common_kind, numeric_op, IntType, NumKind};
+use crate::translator::{node_span, translate};
+
+pub mod spec;
+
+/// Type of a KS expression.
+///
+/// Its string form (see the [`Display`](fmt::Display) and [`FromStr`] implementations, also used
+/// for serialization) matches the type names of ksy where there is one (`u2`, `str`, `bytes`,
+/// `io`, `struct`, `header::entry[]`, ...). The remaining types are written as `int` and `f8`
+/// (calculated integers and floats) and `enum:path::to::enum`.
+#[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum KsType {
+    /// `CalcIntType`
+    Int,
+    /// `Int1Type` or `IntMultiType`, the type of integer attributes like `u2`
+    SizedInt(IntType),
+    /// `CalcFloatType`
+    Float,
+    /// `CalcStrType`
+    Str,
+    Bool,
+    Bytes,
+    Array(Box<KsType>),
+    Enum(Vec<String>),
+    User(Vec<String>),
+    /// `KaitaiStructType`, the common supertype of all user types
+    AnyStruct,
+    /// `KaitaiStreamType`, i.e. the type of `_io`
+    Stream,
+}
+
+impl KsType {
+    pub fn is_numeric(&self) -> bool {
+        self.num_kind().is_some()
+    }
+
+    pub fn num_kind(&self) -> Option<NumKind> {
+        match self {
+            KsType::Int | KsType::SizedInt(_) => Some(NumKind::Int),
+            KsType::Float => Some(NumKind::Float),
+            _ => None,
+        }
+    }
+
+    /// Type of the value once it takes part in a computation, where fixed-width integers
+    /// behave like any other integer.
+    pub fn widened(&self) -> KsType {
+        match self {
+            KsType::SizedInt(_) => KsType::Int,
+            ty => ty.clone(),
+        }
+    }
+
+    fn from_num_kind(kind: NumKind) -> Self {
+        match kind {
+            NumKind::Int => KsType::Int,
+            NumKind::Float => KsType::Float,
+        }
+    }
+}
+
+impl fmt::Display for KsType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KsType::Int => f.write_str("int"),
+            KsType::SizedInt(int_type) => f.write_str(&int_type.name()),
+            KsType::Float => f.write_str("f8"),
+            KsType::Str => f.write_str("str"),
+            KsType::Bool => f.write_str("bool"),
+            KsType::Bytes => f.write_str("bytes"),
+            KsType::Array(item) => write!(f, "{}[]", item),
+            KsType::Enum(enum_path) => write!(f, "enum:{}", enum_path.join("::")),
+            KsType::User(type_path) => f.write_str(&type_path.join("::")),
+            KsType::AnyStruct => f.write_str("struct"),
+            KsType::Stream => f.write_str("io"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Error, PartialEq)]
+#[error("invalid type `{0}`")]
+pub struct ParseTypeError(String);
+
+impl FromStr for KsType {
+    type Err = ParseTypeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(item) = s.strip_suffix("[]") {
+            return Ok(KsType::Array(Box::new(item.parse()?)));
+        }
+        let path = |s: &str| -> Result<Vec<String>, ParseTypeError> {
+            let path: Vec<String> = s.split("::").map(str::to_string).collect();
+            let is_identifier = |part: &String| {
+                part.starts_with(|ch: char| ch.is_ascii_lowercase() || ch == '_')
+                    && part
+                        .chars()
+                        .all(|ch| ch.is_ascii_lowercase() || ch.is_ascii_digit() || ch == '_')
+            };
+            if path.iter().all(is_identifier) {
+                Ok(path)
+            } else {
+                Err(ParseTypeError(s.to_string()))
+            }
+        };
+        let without_endian = s.strip_suffix("le").or_else(|| s.strip_suffix("be"));
+        Ok(match s {
+            "int" => KsType::Int,
+            "str" => KsType::Str,
+            "bool" => KsType::Bool,
+            "bytes" => KsType::Bytes,
+            "struct" => KsType::AnyStruct,
+            "io" => KsType::Stream,
+            _ => match (
+                primitive_type(s).or_else(|| without_endian.and_then(primitive_type)),
+                s.strip_prefix("enum:"),
+            ) {
+                (Some(ty), _) => ty,
+                (None, Some(enum_path)) => KsType::Enum(path(enum_path)?),
+                (None, None) => KsType::User(path(s)?),
+            },
+        })
+    }
+}
+
+/// Type of a value read as a primitive of ksy (without its endian suffix): `u4`, `f4`, `b1` or
+/// `b12`.
+fn primitive_type(name: &str) -> Option<KsType> {
+    let bits = name
+        .strip_prefix('b')
+        .filter(|bits| bits.chars().all(|ch| ch.is_ascii_digit()))
+        .and_then(|bits| bits.parse::<u32>().ok());
+    match (name, bits) {
+        ("f4" | "f8", _) => Some(KsType::Float),
+        (_, Some(1)) => Some(KsType::Bool),
+        (_, Some(2..=64)) => Some(KsType::Int),
+        _ => IntType::from_name(name).map(KsType::SizedInt),
+    }
+}
+
+impl Serialize for KsType {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for KsType {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(de::Error::custom)
+    }
+}
+
+/// Names, user types and enums visible to an expression.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TypeEnv {
+    names: BTreeMap<String, KsType>,
+    types: BTreeMap<Vec<String>, BTreeMap<String, KsType>>,
+    enums: BTreeMap<Vec<String>, BTreeSet<String>>,
+}
+
+impl TypeEnv {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set(&mut self, name: impl Into<String>, ty: KsType) {
+        self.names.insert(name.into(), ty);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&KsType> {
+        self.names.get(name)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = (&String, &KsType)> {
+        self.names.iter()
+    }
+
+    pub fn define_type<I, S>(&mut self, type_path: Vec<String>, fields: I)
+    where
+        I: IntoIterator<Item = (S, KsType)>,
+        S: Into<String>,
+    {
+        self.types.insert(
+            type_path,
+            fields
+                .into_iter()
+                .map(|(name, ty)| (name.into(), ty))
+                .collect(),
+        );
+    }
+
+    pub fn user_types(&self) -> impl Iterator<Item = &Vec<String>> {
+        self.types.keys()
+    }
+
+    pub fn type_fields(&self, type_path: &[String]) -> Option<&BTreeMap<String, KsType>> {
+        self.types.get(type_path)
+    }
+
+    pub fn define_enum<I, S>(&mut self, enum_path: Vec<String>, labels: I)
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.enums
+            .insert(enum_path, labels.into_iter().map(Into::into).collect());
+    }
+
+    pub fn enum_labels(&self, enum_path: &[String]) -> Option<&BTreeSet<String>> {
+        self.enums.get(enum_path)
+    }
+
+    pub fn enums(&self) -> impl Iterator<Item = (&Vec<String>, &BTreeSet<String>)> {
+        self.enums.iter()
+    }
+}
+
+/// Reason why the type of an expression could not be inferred.
+#[derive(Clone, Debug, Error, PartialEq)]
+pub enum TypeErrorKind {
+    #[error("unknown name `{0}`")]
+    UnknownName(String),
+    #[error("unknown enum member `{}::{label}`", enum_path.join("::"))]
+    UnknownEnumMember {
+        enum_path: Vec<String>,
+        label: String,
+    },
+    #[error("`{op}` expects {expected}, but got {}", describe(actual))]
+    Mismatch {
+        op: String,
+        expected: String,
+        actual: Vec<KsType>,
+    },
+    #[error("{receiver} has no attribute or method `{name}`")]
+    UnknownMember { receiver: KsType, name: String },
+    #[error("unknown type `{0}`")]
+    UnknownType(String),
+    #[error("cannot infer the type of an empty list")]
+    EmptyList,
+}
+
+#[derive(Clone, Debug, Error, PartialEq)]
+#[error("{kind} (in `{}` at {}..{})", translate(node), span.start, span.end)]
+pub struct TypeError {
+    pub kind: TypeErrorKind,
+    /// The subexpression that could not be typed
+    pub node: Box<Expr>,
+    /// Location of [`node`](Self::node) in the whole expression as a sequence of indices into
+    /// [`Expr::children`]
+    pub path: Vec<usize>,
+    /// Byte range of [`node`](Self::node) in the whole expression as rendered by [`translate`]
+    pub span: Range<usize>,
+}
+
+pub fn infer(expr: &Expr, env: &TypeEnv) -> Result<KsType, TypeError> {
+    let mut path = Vec::new();
+    infer_at(expr, env, &mut path).map_err(|(path, kind)| {
+        let node = expr
+            .node_at(&path)
+            .expect("error path must point to an existing node")
+            .clone()
+            .into();
+        let span = node_span(expr, &path).expect("error path must point to an existing node");
+        TypeError {
+            kind,
+            node,
+            path,
+            span,
+        }
+    })
+}
+
+type InferResult = Result<KsType, (Vec<usize>, TypeErrorKind)>;
+
+fn infer_child(idx: usize, expr: &Expr, env: &TypeEnv, path: &mut Vec<usize>) -> InferResult {
+    path.push(idx);
+    let result = infer_at(expr, env, path);
+    path.pop();
+    result
+}
+
+fn fail(path: &[usize], kind: TypeErrorKind) -> InferResult {
+    Err((path.to_vec(), kind))
+}
+
+fn infer_at(expr: &Expr, env: &TypeEnv, path: &mut Vec<usize>) -> InferResult {
+    let mismatch = |op: &str, expected: &str, actual: Vec<KsType>| TypeErrorKind::Mismatch {
+        op: op.to_string(),
+        expected: expected.to_string(),
+        actual,
+    };
+    match expr {
+        Expr::Int(_) => Ok(KsType::Int),
+        Expr::Float(_) => Ok(KsType::Float),
+        Expr::Str(_) => Ok(KsType::Str),
+        Expr::Bool(_) => Ok(KsType::Bool),
+        Expr::EnumMember { enum_path, label } => match env.enum_labels(enum_path) {
+            Some(labels) if labels.contains(label) => Ok(KsType::Enum(enum_path.clone())),
+            _ => fail(
+                path,
+                TypeErrorKind::UnknownEnumMember {
+                    enum_path: enum_path.clone(),
+                    label: label.clone(),
+                },
+            ),
+        },
+        Expr::List(items) => {
+            if items.is_empty() {
+                return fail(path, TypeErrorKind::EmptyList);
+            }
+            let is_byte_array = items
+                .iter()
+                .all(|item| matches!(item, Expr::Int(x) if *x <= u64::from(u8::MAX)));
+            if is_byte_array {
+                return Ok(KsType::Bytes);
+            }
+            let mut item_type = infer_child(0, &items[0], env, path)?;
+            for (i, item) in items.iter().enumerate().skip(1) {
+                let ty = infer_child(i, item, env, path)?;
+                item_type = match combine_types(&item_type, &ty) {
+                    Some(combined) => combined,
+                    None => {
+                        return fail(
+                            path,
+                            mismatch("[]", "items of compatible types", vec![item_type, ty]),
+                        )
+                    }
+                };
+            }
+            Ok(KsType::Array(Box::new(item_type)))
+        }
+
+        Expr::Name(name) => match env.get(name) {
+            Some(ty) => Ok(ty.clone()),
+            None => fail(path, TypeErrorKind::UnknownName(name.clone())),
+        },
+        Expr::Attribute { value, attr_name } => {
+            let receiver = infer_child(0, value, env, path)?;
+            match infer_attribute(&receiver, attr_name, env) {
+                Some(ty) => Ok(ty),
+                None => fail(
+                    path,
+                    TypeErrorKind::UnknownMember {
+                        receiver,
+                        name: attr_name.clone(),
+                    },
+                ),
+            }
+        }
+        Expr::MethodCall {
+            value,
+            method_name,
+            args,
+        } => {
+            let receiver = infer_child(0, value, env, path)?;
+            let args = args
+                .iter()
+                .enumerate()
+                .map(|(i, arg)| infer_child(i + 1, arg, env, path))
+                .collect::<Result<Vec<_>, _>>()?;
+            let (expected, result) = match method_signature(&receiver, method_name) {
+                Some(signature) => signature,
+                None => {
+                    return fail(
+                        path,
+                        TypeErrorKind::UnknownMember {
+                            receiver,
+                            name: method_name.clone(),
+                        },
+                    )
+                }
+            };
+            if args
+                .iter()
+                .map(KsType::widened)
+                .ne(expected.iter().cloned())
+            {
+                let expected = format!("arguments ({})", describe(&expected));
+                return fail(path, mismatch(method_name, &expected, args));
+            }
+            Ok(result)
+        }
+
+        Expr::UnaryOp { op, value } => {
+            let operand = infer_child(0, value, env, path)?;
+            match (op, operand.widened()) {
+                (UnaryOp::Neg, ty @ (KsType::Int | KsType::Float))
+                | (UnaryOp::Inv, ty @ KsType::Int) => Ok(ty),
+                (UnaryOp::Not, KsType::Bool) => Ok(KsType::Bool),
+                _ => {
+                    let expected = match op {
+                        UnaryOp::Neg => "a number",
+                        UnaryOp::Not => "a boolean",
+                        UnaryOp::Inv => "an integer",
+                    };
+                    fail(path, mismatch(op.symbol(), expected, vec![operand]))
+                }
+            }
+        }
+        Expr::BinaryOp { l, op, r } => {
+            let l = infer_child(0, l, env, path)?;
+            let r = infer_child(1, r, env, path)?;
+            match infer_binary_op(*op, &l, &r) {
+                Some(ty) => Ok(ty),
+                None => fail(
+                    path,
+                    mismatch(op.symbol(), binary_op_expectation(*op), vec![l, r]),
+                ),
+            }
+        }
+        Expr::CondOp {
+            cond,
+            if_true,
+            if_false,
+        } => {
+            let cond = infer_child(0, cond, env, path)?;
+            if cond != KsType::Bool {
+                return fail(path, mismatch("?:", "a boolean condition", vec![cond]));
+            }
+            let if_true = infer_child(1, if_true, env, path)?;
+            let if_false = infer_child(2, if_false, env, path)?;
+            match combine_types(&if_true, &if_false) {
+                Some(ty) => Ok(ty),
+                None => fail(
+                    path,
+                    mismatch(
+                        "?:",
+                        "branches of compatible types",
+                        vec![if_true, if_false],
+                    ),
+                ),
+            }
+        }
+        Expr::Subscript { value, idx } => {
+            let container = infer_child(0, value, env, path)?;
+            let idx = infer_child(1, idx, env, path)?;
+            match (&container, idx.widened()) {
+                (KsType::Array(item), KsType::Int) => Ok(*item.clone()),
+                (KsType::Bytes, KsType::Int) => Ok(KsType::Int),
+                _ => fail(
+                    path,
+                    mismatch(
+                        "[]",
+                        "an array or byte array and an integer index",
+                        vec![container, idx],
+                    ),
+                ),
+            }
+        }
+        // Apart from integers becoming enum members, casts are purely static in KSC, they don't
+        // check or convert the value
+        Expr::CastTo { value, type_name } => {
+            let source = infer_child(0, value, env, path)?;
+            match resolve_type_name(type_name, env) {
+                Some(KsType::Enum(_)) if source.widened() != KsType::Int => fail(
+                    path,
+                    mismatch(&format!(".as<{}>", type_name), "an integer", vec![source]),
+                ),
+                Some(ty) => Ok(ty),
+                None => fail(path, TypeErrorKind::UnknownType(type_name.to_string())),
+            }
+        }
+        Expr::SizeOf { type_name, .. } => {
+            let ty = match resolve_type_name(type_name, env) {
+                Some(ty) => ty,
+                None => return fail(path, TypeErrorKind::UnknownType(type_name.to_string())),
+            };
+            let is_fixed_size = match &ty {
+                KsType::User(_) => true,
+                _ => type_name
+                    .simple_name()
+                    .and_then(builtin_byte_size)
+                    .is_some(),
+            };
+            if !is_fixed_size {
+                return fail(path, mismatch("sizeof", "a fixed-size type", vec![ty]));
+            }
+            Ok(KsType::Int)
+        }
+    }
+}
+
+/// Type referred to by a type name in a cast or `sizeof`.
+fn resolve_type_name(type_name: &TypeName, env: &TypeEnv) -> Option<KsType> {
+    let ty = match type_name.path.as_slice() {
+        [name] => match name.as_str() {
+            "f4" | "f8" => Some(KsType::Float),
+            "str" => Some(KsType::Str),
+            "bytes" => Some(KsType::Bytes),
+            "b1" => Some(KsType::Bool),
+            name => IntType::from_name(name).map(KsType::SizedInt),
+        },
+        _ => None,
+    };
+    let ty = match ty {
+        Some(ty) => ty,
+        None if env.enum_labels(&type_name.path).is_some() => KsType::Enum(type_name.path.clone()),
+        None => {
+            env.type_fields(&type_name.path)?;
+            KsType::User(type_name.path.clone())
+        }
+    };
+    Some(if type_name.is_array {
+        KsType::Array(Box::new(ty))
+    } else {
+        ty
+    })
+}
+
+/// Common type of two values that can appear in the same position (branches of `?:`, items of a
+/// list, cases of a switch), or `None` if KSC rejects the combination. Mirrors
+/// `TypeDetector.combineTypes` of KSC.
+pub fn combine_types(a: &KsType, b: &KsType) -> Option<KsType> {
+    if a == b {
+        return Some(a.clone());
+    }
+    if let (Some(a), Some(b)) = (a.num_kind(), b.num_kind()) {
+        return Some(KsType::from_num_kind(common_kind(a, b)));
+    }
+    match (a, b) {
+        (KsType::User(_) | KsType::AnyStruct, KsType::User(_) | KsType::AnyStruct) => {
+            Some(KsType::AnyStruct)
+        }
+        (KsType::Array(a), KsType::Array(b)) => Some(KsType::Array(Box::new(combine_types(a, b)?))),
+        _ => None,
+    }
+}
+
+/// Whether a `cases` key of type `case` can be matched against a `switch-on` value of type `on`.
+///
+/// KSC compiles a switch into a chain of `==` comparisons (or a native switch statement with the
+/// same semantics), so a case is valid exactly if the two values can be compared for equality:
+/// integers of any width with each other, members of the same enum, strings, etc. In particular,
+/// an integer case doesn't match an enum value, even if it's the value of one of its members.
+pub fn is_valid_switch_case(on: &KsType, case: &KsType) -> bool {
+    infer_binary_op(BinaryOp::Eq, on, case).is_some()
+}
+
+fn infer_attribute(receiver: &KsType, attr_name: &str, env: &TypeEnv) -> Option<KsType> {
+    match (&receiver.widened(), attr_name) {
+        (KsType::Int, "to_s") => Some(KsType::Str),
+        (KsType::Float | KsType::Bool | KsType::Enum(_), "to_i") => Some(KsType::Int),
+        (KsType::Str, "length" | "to_i") => Some(KsType::Int),
+        (KsType::Str, "reverse") => Some(KsType::Str),
+        (KsType::Bytes, "length" | "size" | "first" | "last" | "min" | "max") => Some(KsType::Int),
+        (KsType::Array(_), "size") => Some(KsType::Int),
+        (KsType::Array(item), "first" | "last" | "min" | "max") => Some(*item.clone()),
+        (KsType::Stream, "size" | "pos") => Some(KsType::Int),
+        (KsType::Stream, "eof") => Some(KsType::Bool),
+        (KsType::User(path), _) => env
+            .type_fields(path)
+            .and_then(|fields| fields.get(attr_name))
+            .cloned(),
+        _ => None,
+    }
+}
+
+/// Argument types and the result type of a built-in method.
+fn method_signature(receiver: &KsType, method_name: &str) -> Option<(Vec<KsType>, KsType)> {
+    match (receiver, method_name) {
+        (KsType::Str, "substring") => Some((vec![KsType::Int, KsType::Int], KsType::Str)),
+        (KsType::Str, "to_i") => Some((vec![KsType::Int], KsType::Int)),
+        (KsType::Bytes, "to_s") => Some((vec![KsType::Str], KsType::Str)),
+        _ => None,
+    }
+}
+
+fn infer_binary_op(op: BinaryOp, l: &KsType, r: &KsType) -> Option<KsType> {
+    if let (Some(l), Some(r)) = (l.num_kind(), r.num_kind()) {
+        let rule = numeric_op(op, l, r)?;
+        return Some(rule.result.map_or(KsType::Bool, KsType::from_num_kind));
+    }
+    match op {
+        BinaryOp::Add => (*l == KsType::Str && *r == KsType::Str).then_some(KsType::Str),
+        BinaryOp::Eq | BinaryOp::Ne => {
+            let comparable = l == r
+                && matches!(
+                    l,
+                    KsType::Str | KsType::Bool | KsType::Bytes | KsType::Enum(_)
+                );
+            comparable.then_some(KsType::Bool)
+        }
+        BinaryOp::Lt | BinaryOp::Le | BinaryOp::Gt | BinaryOp::Ge => {
+            let comparable = l == r && matches!(l, KsType::Str | KsType::Bytes);
+            comparable.then_some(KsType::Bool)
+        }
+        BinaryOp::And | BinaryOp::Or => {
+            (*l == KsType::Bool && *r == KsType::Bool).then_some(KsType::Bool)
+        }
+        _ => None,
+    }
+}
+
+fn binary_op_expectation(op: BinaryOp) -> &'static str {
+    match op {
+        BinaryOp::Add => "two numbers or two strings",
+        BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div | BinaryOp::Rem => "two numbers",
+        BinaryOp::Eq | BinaryOp::Ne => "two values of the same comparable type",
+        BinaryOp::Lt | BinaryOp::Le | BinaryOp::Gt | BinaryOp::Ge => {
+            "two numbers, strings or byte arrays"
+        }
+        BinaryOp::And | BinaryOp::Or => "two booleans",
+        BinaryOp::BitOr | BinaryOp::BitXor | BinaryOp::BitAnd | BinaryOp::Shl | BinaryOp::Shr => {
+            "two integers"
+        }
+    }
+}
+
+fn describe(types: &[KsType]) -> String {
+    types
+        .iter()
+        .map(KsType::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::utils::PositiveFiniteF64;
+
+    fn binary(l: Expr, op: BinaryOp, r: Expr) -> Expr {
+        Expr::BinaryOp {
+            l: Box::new(l),
+            op,
+            r: Box::new(r),
+        }
+    }
+
+    fn name(name: &str) -> Expr {
+        Expr::Name(name.to_string())
+    }
+
+    fn attr(value: Expr, attr_name: &str) -> Expr {
+        Expr::Attribute {
+            value: Box::new(value),
+            attr_name: attr_name.to_string(),
+        }
+    }
+
+    #[test]
+    fn int_plus_float() {
+        let expr = binary(
+            Expr::Int(1),
+            BinaryOp::Add,
+            Expr::Float(PositiveFiniteF64::try_from(0.5).unwrap()),
+        );
+        assert_eq!(infer(&expr, &TypeEnv::new()), Ok(KsType::Float));
+    }
+
+    #[test]
+    fn int_div_int() {
+        let expr = binary(Expr::Int(7), BinaryOp::Div, Expr::Int(2));
+        assert_eq!(infer(&expr, &TypeEnv::new()), Ok(KsType::Int));
+    }
+
+    #[test]
+    fn str_plus_int() {
+        let expr = binary(Expr::Str("a".to_string()), BinaryOp::Add, Expr::Int(3));
+        let error = infer(&expr, &TypeEnv::new()).unwrap_err();
+        assert_eq!(
+            error.kind,
+            TypeErrorKind::Mismatch {
+                op: "+".to_string(),
+                expected: "two numbers or two strings".to_string(),
+                actual: vec![KsType::Str, KsType::Int],
+            }
+        );
+        assert_eq!(*error.node, expr);
+        assert_eq!(error.path, vec![]);
+        assert_eq!(error.span, 0..9);
+    }
+
+    #[test]
+    fn byte_array_literal() {
+        let expr = Expr::List(vec![Expr::Int(1), Expr::Int(255)]);
+        assert_eq!(infer(&expr, &TypeEnv::new()), Ok(KsType::Bytes));
+        let expr = Expr::List(vec![Expr::Int(1), Expr::Int(256)]);
+        assert_eq!(
+            infer(&expr, &TypeEnv::new()),
+            Ok(KsType::Array(Box::new(KsType::Int)))
+        );
+    }
+
+    #[test]
+    fn user_type_field() {
+        let mut env = TypeEnv::new();
+        env.set("hdr", KsType::User(vec!["header".to_string()]));
+        env.define_type(
+            vec!["header".to_string()],
+            [("magic", KsType::Bytes), ("version", KsType::Int)],
+        );
+        assert_eq!(infer(&attr(name("hdr"), "magic"), &env), Ok(KsType::Bytes));
+        assert!(matches!(
+            infer(&attr(name("hdr"), "missing"), &env).unwrap_err().kind,
+            TypeErrorKind::UnknownMember { .. }
+        ));
+    }
+
+    #[test]
+    fn io_eof() {
+        let mut env = TypeEnv::new();
+        env.set("_io", KsType::Stream);
+        assert_eq!(infer(&attr(name("_io"), "eof"), &env), Ok(KsType::Bool));
+    }
+
+    #[test]
+    fn array_max() {
+        let mut env = TypeEnv::new();
+        env.set("items", KsType::Array(Box::new(KsType::Str)));
+        assert_eq!(infer(&attr(name("items"), "max"), &env), Ok(KsType::Str));
+    }
+
+    #[test]
+    fn enum_to_i() {
+        let mut env = TypeEnv::new();
+        env.define_enum(vec!["animal".to_string()], ["cat", "dog"]);
+        let member = Expr::EnumMember {
+            enum_path: vec!["animal".to_string()],
+            label: "cat".to_string(),
+        };
+        assert_eq!(
+            infer(&member, &env),
+            Ok(KsType::Enum(vec!["animal".to_string()]))
+        );
+        assert_eq!(infer(&attr(member, "to_i"), &env), Ok(KsType::Int));
+    }
+
+    #[test]
+    fn substring_args() {
+        let expr = Expr::MethodCall {
+            value: Box::new(Expr::Str("abc".to_string())),
+            method_name: "substring".to_string(),
+            args: vec![Expr::Int(0), Expr::Str("x".to_string())],
+        };
+        let error = infer(&expr, &TypeEnv::new()).unwrap_err();
+        assert_eq!(
+            error.kind,
+            TypeErrorKind::Mismatch {
+                op: "substring".to_string(),
+                expected: "arguments (int, int)".to_string(),
+                actual: vec![KsType::Int, KsType::Str],
+            }
+        );
+    }
+
+    #[test]
+    fn cond_op_numeric_branches() {
+        let expr = Expr::CondOp {
+            cond: Box::new(Expr::Bool(true)),
+            if_true: Box::new(Expr::Int(1)),
+            if_false: Box::new(Expr::Float(PositiveFiniteF64::try_from(2.5).unwrap())),
+        };
+        assert_eq!(infer(&expr, &TypeEnv::new()), Ok(KsType::Float));
+    }
+
+    #[test]
+    fn error_in_nested_node() {
+        // (1 + (not 2))
+        let expr = binary(
+            Expr::Int(1),
+            BinaryOp::Add,
+            Expr::UnaryOp {
+                op: UnaryOp::Not,
+                value: Box::new(Expr::Int(2)),
+            },
+        );
+        let error = infer(&expr, &TypeEnv::new()).unwrap_err();
+        assert_eq!(error.path, vec![1]);
+        assert_eq!(&translate(&expr)[error.span.clone()], "(not 2)");
+        assert_eq!(
+            error.to_string(),
+            "`not` expects a boolean, but got int (in `(not 2)` at 5..12)"
+        );
+    }
+
+    #[test]
+    fn unknown_name_span() {
+        let expr = attr(name("foo"), "size");
+        let error = infer(&expr, &TypeEnv::new()).unwrap_err();
+        assert_eq!(error.kind, TypeErrorKind::UnknownName("foo".to_string()));
+        assert_eq!(error.span, 0..3);
+    }
+
+    #[test]
+    fn casts_and_sizeof() {
+        let mut env = TypeEnv::new();
+        env.set("x", KsType::Int);
+        let u2 = IntType::from_name("u2").unwrap();
+        let cast = Expr::CastTo {
+            value: Box::new(name("x")),
+            type_name: TypeName::new("u2"),
+        };
+        assert_eq!(infer(&cast, &env), Ok(KsType::SizedInt(u2)));
+        assert_eq!(
+            infer(&binary(cast, BinaryOp::Add, Expr::Int(1)), &env),
+            Ok(KsType::Int)
+        );
+        let size_of = |name: &str| Expr::SizeOf {
+            type_name: TypeName::new(name),
+            bits: false,
+        };
+        assert_eq!(infer(&size_of("f8"), &env), Ok(KsType::Int));
+        assert!(matches!(
+            infer(&size_of("str"), &env).unwrap_err().kind,
+            TypeErrorKind::Mismatch { .. }
+        ));
+        assert_eq!(
+            infer(&size_of("foo"), &env).unwrap_err().kind,
+            TypeErrorKind::UnknownType("foo".to_string())
+        );
+    }
+
+    #[test]
+    fn enum_casts() {
+        let mut env = TypeEnv::new();
+        env.define_enum(vec!["animal".to_string()], ["cat", "dog"]);
+        let animal = KsType::Enum(vec!["animal".to_string()]);
+        let cast = |value| Expr::CastTo {
+            value: Box::new(value),
+            type_name: TypeName::new("animal"),
+        };
+        let to_enum = cast(Expr::Int(4));
+        assert_eq!(infer(&to_enum, &env), Ok(animal.clone()));
+        assert_eq!(infer(&attr(to_enum, "to_i"), &env), Ok(KsType::Int));
+        let error = infer(&cast(Expr::Str("4".to_string())), &env).unwrap_err();
+        assert_eq!(
+            error.kind,
+            TypeErrorKind::Mismatch {
+                op: ".as<animal>".to_string(),
+                expected: "an integer".to_string(),
+                actual: vec![KsType::Str],
+            }
+        );
+    }
+
+    #[test]
+    fn ternary_unification() {
+        let user = |name: &str| KsType::User(vec![name.to_string()]);
+        let u1 = KsType::SizedInt(IntType::from_name("u1").unwrap());
+        assert_eq!(combine_types(&u1, &u1), Some(u1.clone()));
+        assert_eq!(combine_types(&u1, &KsType::Float), Some(KsType::Float));
+        assert_eq!(
+            combine_types(&user("a"), &user("b")),
+            Some(KsType::AnyStruct)
+        );
+        assert_eq!(
+            combine_types(
+                &KsType::Array(Box::new(KsType::Int)),
+                &KsType::Array(Box::new(u1))
+            ),
+            Some(KsType::Array(Box::new(KsType::Int)))
+        );
+        assert_eq!(
+            combine_types(
+                &KsType::Enum(vec!["a".to_string()]),
+                &KsType::Enum(vec!["b".to_string()])
+            ),
+            None
+        );
+        assert_eq!(combine_types(&KsType::Str, &KsType::Bytes), None);
+    }
+
+    #[test]
+    fn switch_cases() {
+        let u1 = KsType::SizedInt(IntType::from_name("u1").unwrap());
+        let animal = KsType::Enum(vec!["animal".to_string()]);
+        assert!(is_valid_switch_case(&u1, &KsType::Int));
+        assert!(is_valid_switch_case(&animal, &animal));
+        assert!(is_valid_switch_case(&KsType::Str, &KsType::Str));
+        assert!(!is_valid_switch_case(&animal, &KsType::Int));
+        assert!(!is_valid_switch_case(&u1, &KsType::Str));
+        assert!(!is_valid_switch_case(
+            &animal,
+            &KsType::Enum(vec!["color".to_string()])
+        ));
+    }
+
+    #[test]
+    fn string_form() {
+        let types = [
+            KsType::Int,
+            KsType::SizedInt(IntType::from_name("s4").unwrap()),
+            KsType::Float,
+            KsType::Bytes,
+            KsType::Array(Box::new(KsType::Array(Box::new(KsType::Str)))),
+            KsType::Enum(vec!["header".to_string(), "kind".to_string()]),
+            KsType::User(vec!["header".to_string(), "entry".to_string()]),
+            KsType::AnyStruct,
+            KsType::Stream,
+        ];
+        let strings: Vec<String> = types.iter().map(KsType::to_string).collect();
+        assert_eq!(
+            strings,
+            [
+                "int",
+                "s4",
+                "f8",
+                "bytes",
+                "str[][]",
+                "enum:header::kind",
+                "header::entry",
+                "struct",
+                "io"
+            ]
+        );
+        for (ty, s) in types.iter().zip(&strings) {
+            assert_eq!(&s.parse::<KsType>().unwrap(), ty);
+        }
+        assert!("Header".parse::<KsType>().is_err());
+        assert!("enum:".parse::<KsType>().is_err());
+        for (s, ty) in [
+            ("f4", KsType::Float),
+            ("f4be", KsType::Float),
+            ("f8le", KsType::Float),
+            ("u2le", KsType::SizedInt(IntType::from_name("u2").unwrap())),
+            ("b1", KsType::Bool),
+            ("b1be", KsType::Bool),
+            ("b12le", KsType::Int),
+            ("b64", KsType::Int),
+            ("b65", KsType::User(vec!["b65".to_string()])),
+            ("table", KsType::User(vec!["table".to_string()])),
+        ] {
+            assert_eq!(s.parse::<KsType>(), Ok(ty), "{}", s);
+        }
+    }
+
+    #[test]
+    fn serde() {
+        let ty = KsType::Array(Box::new(KsType::Enum(vec!["kind".to_string()])));
+        let yaml = serde_yaml::to_string(&ty).unwrap();
+        assert_eq!(yaml, "enum:kind[]\n");
+        assert_eq!(serde_yaml::from_str::<KsType>(&yaml).unwrap(), ty);
+    }
+}
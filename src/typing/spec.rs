@@ -0,0 +1,472 @@
+//! Building a [`TypeEnv`] from a ksy spec, so that the expressions generated for the spec only
+//! refer to attributes that exist and use them according to their types.
+//!
+//! User types are identified by their path from the top-level type (e.g. `["header", "entry"]`
+//! for a type `entry` nested in `header`), except for the top-level type itself, which is
+//! identified by its `meta/id`. Enums are identified by the path of the type that defines them
+//! followed by the enum name. These are also the qualified names by which the expression
+//! language can refer to them from anywhere in the spec.
+//!
+//! Value instances are not included, because their type would have to be inferred from the
+//! expression.
+
+use std::collections::BTreeMap;
+
+use serde_yaml::{Mapping, Value};
+use thiserror::Error;
+
+use super::{combine_types, KsType, TypeEnv};
+use crate::ksy::KsySpec;
+use crate::numeric::IntType;
+
+#[derive(Clone, Debug, Error, PartialEq)]
+pub enum SpecError {
+    #[error("spec has no `meta/id`")]
+    MissingId,
+    #[error("type `{}` is not defined in the spec", .0.join("::"))]
+    UnknownType(Vec<String>),
+}
+
+impl TypeEnv {
+    /// Type environment of expressions in the type at `type_path` (see the module documentation
+    /// for how types are identified; the top-level type can also be referred to by an empty
+    /// path).
+    pub fn from_ksy(doc: &Value, type_path: &[String]) -> Result<Self, SpecError> {
+        Self::from_ksy_with_imports(doc, &[], type_path)
+    }
+
+    /// Same as [`TypeEnv::from_ksy`], with the specs that `doc` imports (directly or indirectly).
+    /// The top-level type of an imported spec is identified by its `meta/id`, its nested types by
+    /// paths starting with it.
+    pub fn from_ksy_with_imports(
+        doc: &Value,
+        imports: &[Value],
+        type_path: &[String],
+    ) -> Result<Self, SpecError> {
+        let mut spec = Spec::new(spec_id(doc)?, doc);
+        for import in imports {
+            spec.add_type(vec![spec_id(import)?.to_string()], import);
+        }
+        let context = spec
+            .canonical_key(type_path)
+            .ok_or_else(|| SpecError::UnknownType(type_path.to_vec()))?;
+
+        let mut env = TypeEnv::new();
+        for (key, enum_names) in &spec.enums {
+            for (enum_name, labels) in enum_names {
+                let mut enum_path = key.clone();
+                enum_path.push(enum_name.clone());
+                env.define_enum(enum_path, labels.iter().cloned());
+            }
+        }
+        for key in spec.types.keys() {
+            let mut fields = spec.fields(key);
+            fields.extend(spec.params(key));
+            fields.extend(spec.special_names(key));
+            env.define_type(spec.user_path(key), fields);
+        }
+
+        for (name, ty) in spec.fields(&context) {
+            env.set(name, ty);
+        }
+        for (name, ty) in spec.params(&context) {
+            env.set(name, ty);
+        }
+        for (name, ty) in spec.special_names(&context) {
+            env.set(name, ty);
+        }
+        Ok(env)
+    }
+
+    /// Same as [`TypeEnv::from_ksy`], for a spec built with the [`crate::ksy`] model.
+    pub fn from_spec(spec: &KsySpec, type_path: &[String]) -> Result<Self, SpecError> {
+        Self::from_spec_with_imports(spec, &[], type_path)
+    }
+
+    /// Same as [`TypeEnv::from_ksy_with_imports`], for specs built with the [`crate::ksy`] model.
+    pub fn from_spec_with_imports(
+        spec: &KsySpec,
+        imports: &[KsySpec],
+        type_path: &[String],
+    ) -> Result<Self, SpecError> {
+        let to_value =
+            |spec| serde_yaml::to_value(spec).expect("spec model must be serializable to YAML");
+        let imports: Vec<Value> = imports.iter().map(to_value).collect();
+        Self::from_ksy_with_imports(&to_value(spec), &imports, type_path)
+    }
+}
+
+fn spec_id(doc: &Value) -> Result<&str, SpecError> {
+    doc.get("meta")
+        .and_then(|meta| meta.get("id"))
+        .and_then(Value::as_str)
+        .ok_or(SpecError::MissingId)
+}
+
+/// Index of the types and enums defined in a spec. Types are keyed by their path from the
+/// top-level type, which has an empty key.
+struct Spec<'a> {
+    root_id: &'a str,
+    types: BTreeMap<Vec<String>, &'a Value>,
+    enums: BTreeMap<Vec<String>, BTreeMap<String, Vec<String>>>,
+}
+
+impl<'a> Spec<'a> {
+    fn new(root_id: &'a str, doc: &'a Value) -> Self {
+        let mut spec = Spec {
+            root_id,
+            types: BTreeMap::new(),
+            enums: BTreeMap::new(),
+        };
+        spec.add_type(Vec::new(), doc);
+        spec
+    }
+
+    fn add_type(&mut self, key: Vec<String>, spec: &'a Value) {
+        if let Some(enums) = spec.get("enums").and_then(Value::as_mapping) {
+            let enums = enums
+                .iter()
+                .filter_map(|(name, members)| {
+                    Some((name.as_str()?.to_string(), enum_labels(members)))
+                })
+                .collect();
+            self.enums.insert(key.clone(), enums);
+        }
+        if let Some(types) = spec.get("types").and_then(Value::as_mapping) {
+            for (name, nested) in types {
+                if let Some(name) = name.as_str() {
+                    let mut nested_key = key.clone();
+                    nested_key.push(name.to_string());
+                    self.add_type(nested_key, nested);
+                }
+            }
+        }
+        self.types.insert(key, spec);
+    }
+
+    fn user_path(&self, key: &[String]) -> Vec<String> {
+        if key.is_empty() {
+            vec![self.root_id.to_string()]
+        } else {
+            key.to_vec()
+        }
+    }
+
+    /// Key of the type identified by `path` (the inverse of [`Spec::user_path`]).
+    fn canonical_key(&self, path: &[String]) -> Option<Vec<String>> {
+        if path.is_empty() || path == [self.root_id] {
+            return Some(Vec::new());
+        }
+        self.types.contains_key(path).then(|| path.to_vec())
+    }
+
+    /// Resolves a type name used in the type at `scope` the way KSC does: by looking for it
+    /// among the nested types of `scope` and then of its enclosing types.
+    fn resolve_type(&self, scope: &[String], name: &str) -> Option<Vec<String>> {
+        let name: Vec<String> = name.split("::").map(str::to_string).collect();
+        if name == [self.root_id] {
+            return Some(Vec::new());
+        }
+        (0..=scope.len()).rev().find_map(|len| {
+            let mut key = scope[..len].to_vec();
+            key.extend(name.iter().cloned());
+            self.types.contains_key(&key).then_some(key)
+        })
+    }
+
+    fn resolve_enum(&self, scope: &[String], name: &str) -> Option<Vec<String>> {
+        let mut name: Vec<String> = name.split("::").map(str::to_string).collect();
+        let enum_name = name.pop()?;
+        (0..=scope.len()).rev().find_map(|len| {
+            let mut key = scope[..len].to_vec();
+            key.extend(name.iter().cloned());
+            let enums = self.enums.get(&key)?;
+            enums.contains_key(&enum_name).then(|| {
+                key.push(enum_name.clone());
+                key
+            })
+        })
+    }
+
+    /// Types of the attributes and (non-value) instances of the type at `key`.
+    fn fields(&self, key: &[String]) -> Vec<(String, KsType)> {
+        let spec = self.types[key];
+        let seq = spec
+            .get("seq")
+            .and_then(Value::as_sequence)
+            .into_iter()
+            .flatten()
+            .filter_map(|attr| Some((attr.get("id")?.as_str()?.to_string(), attr)));
+        let instances = spec
+            .get("instances")
+            .and_then(Value::as_mapping)
+            .into_iter()
+            .flatten()
+            .filter_map(|(id, attr)| Some((id.as_str()?.to_string(), attr)));
+        seq.chain(instances)
+            .filter_map(|(id, attr)| Some((id, self.attr_type(key, attr)?)))
+            .collect()
+    }
+
+    fn params(&self, key: &[String]) -> Vec<(String, KsType)> {
+        self.types[key]
+            .get("params")
+            .and_then(Value::as_sequence)
+            .into_iter()
+            .flatten()
+            .filter_map(|param| {
+                let id = param.get("id")?.as_str()?.to_string();
+                let type_name = param.get("type")?.as_str()?;
+                let (type_name, is_array) = match type_name.strip_suffix("[]") {
+                    Some(item) => (item, true),
+                    None => (type_name, false),
+                };
+                let ty = match type_name {
+                    "bool" => KsType::Bool,
+                    "bytes" => KsType::Bytes,
+                    "io" => KsType::Stream,
+                    _ => match param.get("enum").and_then(Value::as_str) {
+                        Some(enum_name) => KsType::Enum(self.resolve_enum(key, enum_name)?),
+                        None => self.named_type(key, type_name)?,
+                    },
+                };
+                Some((id, wrap_array(ty, is_array)))
+            })
+            .collect()
+    }
+
+    fn attr_type(&self, scope: &[String], attr: &Value) -> Option<KsType> {
+        if attr.get("value").is_some() {
+            return None;
+        }
+        let ty = match attr.get("type") {
+            None => {
+                let is_raw = ["size", "size-eos", "contents", "terminator"]
+                    .iter()
+                    .any(|key| attr.get(key).is_some());
+                is_raw.then_some(KsType::Bytes)?
+            }
+            Some(Value::String(type_name)) => match attr.get("enum").and_then(Value::as_str) {
+                Some(enum_name) => KsType::Enum(self.resolve_enum(scope, enum_name)?),
+                None => self.named_type(scope, type_name)?,
+            },
+            Some(switch @ Value::Mapping(_)) => self.switch_type(scope, switch)?,
+            Some(_) => return None,
+        };
+        Some(wrap_array(ty, attr.get("repeat").is_some()))
+    }
+
+    /// Type of a switch, if all cases have a common type.
+    fn switch_type(&self, scope: &[String], switch: &Value) -> Option<KsType> {
+        let cases = switch.get("cases")?.as_mapping()?;
+        let mut types = cases
+            .values()
+            .map(|type_name| self.named_type(scope, type_name.as_str()?));
+        let first = types.next()??;
+        types.try_fold(first, |acc, ty| combine_types(&acc, &ty?))
+    }
+
+    /// Built-in or user type referred to by a `type` key, ignoring the arguments of a
+    /// parametric type.
+    fn named_type(&self, scope: &[String], type_name: &str) -> Option<KsType> {
+        let type_name = type_name
+            .split_once('(')
+            .map_or(type_name, |(name, _)| name.trim_end());
+        builtin_type(type_name).or_else(|| {
+            let key = self.resolve_type(scope, type_name)?;
+            Some(KsType::User(self.user_path(&key)))
+        })
+    }
+
+    /// `_io`, `_root` and `_parent` (if it has a known type) in the type at `key`. They are also
+    /// members of every user type, which allows chains like `_parent._parent.x`.
+    fn special_names(&self, key: &[String]) -> Vec<(String, KsType)> {
+        let mut names = vec![
+            ("_io".to_string(), KsType::Stream),
+            ("_root".to_string(), KsType::User(self.user_path(&[]))),
+        ];
+        if let Some(parent) = self.parent(key) {
+            names.push(("_parent".to_string(), KsType::User(self.user_path(&parent))));
+        }
+        names
+    }
+
+    /// Type of `_parent` in the type at `key`: the type that uses it as the type of an attribute,
+    /// or the type in which it's defined if it's not used anywhere. If several types use it,
+    /// `_parent` has no common type.
+    fn parent(&self, key: &[String]) -> Option<Vec<String>> {
+        if key.is_empty() {
+            return None;
+        }
+        let user_path = KsType::User(self.user_path(key));
+        let mut users = self.types.keys().filter(|other| {
+            self.fields(other).iter().any(|(_, ty)| match ty {
+                KsType::Array(item) => **item == user_path,
+                ty => *ty == user_path,
+            })
+        });
+        match (users.next(), users.next()) {
+            (Some(user), None) => Some(user.clone()),
+            (None, _) => Some(key[..key.len() - 1].to_vec()),
+            (Some(_), Some(_)) => None,
+        }
+    }
+}
+
+fn enum_labels(members: &Value) -> Vec<String> {
+    members
+        .as_mapping()
+        .map(Mapping::values)
+        .into_iter()
+        .flatten()
+        .filter_map(|member| match member {
+            Value::String(label) => Some(label.clone()),
+            // verbose enum definitions: `1: { id: label, doc: ... }`
+            member => Some(member.get("id")?.as_str()?.to_string()),
+        })
+        .collect()
+}
+
+/// Type of a built-in type name, ignoring the endianness suffix (`u2le`, `f8be`, `b12le`, ...).
+pub fn builtin_type(type_name: &str) -> Option<KsType> {
+    let base = type_name
+        .strip_suffix("le")
+        .or_else(|| type_name.strip_suffix("be"))
+        .unwrap_or(type_name);
+    match base {
+        "str" | "strz" => return Some(KsType::Str),
+        "f4" | "f8" => return Some(KsType::Float),
+        "b1" => return Some(KsType::Bool),
+        _ => {}
+    }
+    if let Some(int_type) = IntType::from_name(base) {
+        return Some(KsType::SizedInt(int_type));
+    }
+    let bits: u32 = base.strip_prefix('b')?.parse().ok()?;
+    (2..=64).contains(&bits).then_some(KsType::Int)
+}
+
+fn wrap_array(ty: KsType, is_array: bool) -> KsType {
+    if is_array {
+        KsType::Array(Box::new(ty))
+    } else {
+        ty
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SPEC: &str = r#"
+meta:
+  id: archive
+seq:
+  - id: magic
+    contents: ARC
+  - id: header
+    type: header
+  - id: entries
+    type: entry
+    repeat: expr
+    repeat-expr: header.num_entries
+instances:
+  total:
+    value: header.num_entries * 2
+types:
+  header:
+    seq:
+      - id: num_entries
+        type: u4le
+      - id: kind
+        type: u1
+        enum: kind
+    enums:
+      kind:
+        1: plain
+        2:
+          id: compressed
+  entry:
+    params:
+      - id: idx
+        type: u2
+    seq:
+      - id: name
+        type: strz
+        encoding: UTF-8
+      - id: body
+        type:
+          switch-on: _parent.header.kind
+          cases:
+            'kind::plain': u1
+            'kind::compressed': u4
+"#;
+
+    fn path(parts: &[&str]) -> Vec<String> {
+        parts.iter().map(|part| part.to_string()).collect()
+    }
+
+    #[test]
+    fn top_level() {
+        let doc = serde_yaml::from_str(SPEC).unwrap();
+        let env = TypeEnv::from_ksy(&doc, &[]).unwrap();
+        assert_eq!(env.get("magic"), Some(&KsType::Bytes));
+        assert_eq!(env.get("header"), Some(&KsType::User(path(&["header"]))));
+        assert_eq!(
+            env.get("entries"),
+            Some(&KsType::Array(Box::new(KsType::User(path(&["entry"])))))
+        );
+        assert_eq!(env.get("total"), None);
+        assert_eq!(env.get("_parent"), None);
+        assert_eq!(
+            env.type_fields(&path(&["header"])).unwrap()["kind"],
+            KsType::Enum(path(&["header", "kind"]))
+        );
+        assert!(env
+            .enum_labels(&path(&["header", "kind"]))
+            .unwrap()
+            .contains("compressed"));
+    }
+
+    #[test]
+    fn nested_type() {
+        let doc = serde_yaml::from_str(SPEC).unwrap();
+        let env = TypeEnv::from_ksy(&doc, &path(&["entry"])).unwrap();
+        assert_eq!(env.get("name"), Some(&KsType::Str));
+        assert_eq!(env.get("body"), Some(&KsType::Int));
+        assert_eq!(
+            env.get("idx"),
+            Some(&KsType::SizedInt(IntType::from_name("u2").unwrap()))
+        );
+        assert_eq!(env.get("_parent"), Some(&KsType::User(path(&["archive"]))));
+        assert_eq!(env.get("_root"), Some(&KsType::User(path(&["archive"]))));
+    }
+
+    #[test]
+    fn unknown_context() {
+        let doc = serde_yaml::from_str(SPEC).unwrap();
+        assert_eq!(
+            TypeEnv::from_ksy(&doc, &path(&["missing"])),
+            Err(SpecError::UnknownType(path(&["missing"])))
+        );
+    }
+
+    #[test]
+    fn from_model() {
+        let mut spec = KsySpec::top_level("example");
+        spec.seq.push(crate::ksy::Attribute::new("len", "u2le"));
+        let env = TypeEnv::from_spec(&spec, &[]).unwrap();
+        assert_eq!(
+            env.get("len"),
+            Some(&KsType::SizedInt(IntType::from_name("u2").unwrap()))
+        );
+    }
+
+    #[test]
+    fn builtin_types() {
+        assert_eq!(builtin_type("b1"), Some(KsType::Bool));
+        assert_eq!(builtin_type("b12le"), Some(KsType::Int));
+        assert_eq!(builtin_type("f8be"), Some(KsType::Float));
+        assert_eq!(builtin_type("header"), None);
+    }
+}